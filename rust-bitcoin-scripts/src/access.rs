@@ -0,0 +1,102 @@
+//! Role-based access control for coordinator operations.
+//!
+//! Callers are identified by an API key or an mTLS client-certificate fingerprint. Only hashes of
+//! API keys are stored. Each role grants a fixed set of actions: signers submit partial signatures,
+//! operators initiate peg-out batches, auditors read.
+
+use bitcoin::hashes::{sha256, Hash};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Operator,
+    Signer,
+    Auditor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    InitiatePegOut,
+    SubmitPartialSignature,
+    ReadState,
+}
+
+impl Role {
+    pub fn allows(&self, action: Action) -> bool {
+        matches!(
+            (self, action),
+            (Role::Operator, Action::InitiatePegOut)
+                | (Role::Signer, Action::SubmitPartialSignature)
+                | (_, Action::ReadState)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identity {
+    ApiKey(String),
+    /// Hex sha256 fingerprint of the client certificate presented over mTLS
+    CertFingerprint(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessError {
+    Unauthenticated,
+    Forbidden { principal: String, action: Action },
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessError::Unauthenticated => write!(f, "unknown API key or certificate"),
+            AccessError::Forbidden { principal, action } => write!(f, "{} may not perform {:?}", principal, action),
+        }
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub name: String,
+    pub role: Role,
+}
+
+#[derive(Default)]
+pub struct AccessControl {
+    api_keys: HashMap<sha256::Hash, Principal>,
+    certificates: HashMap<String, Principal>,
+}
+
+impl AccessControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_api_key(&mut self, api_key: &str, name: &str, role: Role) {
+        self.api_keys.insert(sha256::Hash::hash(api_key.as_bytes()), Principal { name: name.to_string(), role });
+    }
+
+    pub fn add_certificate(&mut self, fingerprint: &str, name: &str, role: Role) {
+        self.certificates.insert(fingerprint.to_lowercase(), Principal { name: name.to_string(), role });
+    }
+
+    pub fn authenticate(&self, identity: &Identity) -> Result<&Principal, AccessError> {
+        match identity {
+            Identity::ApiKey(key) => self.api_keys.get(&sha256::Hash::hash(key.as_bytes())),
+            Identity::CertFingerprint(fp) => self.certificates.get(&fp.to_lowercase()),
+        }
+        .ok_or(AccessError::Unauthenticated)
+    }
+
+    /// Authenticate `identity` and check its role grants `action`
+    pub fn authorize(&self, identity: &Identity, action: Action) -> Result<&Principal, AccessError> {
+        let principal = self.authenticate(identity)?;
+        if principal.role.allows(action) {
+            Ok(principal)
+        } else {
+            Err(AccessError::Forbidden { principal: principal.name.clone(), action })
+        }
+    }
+}
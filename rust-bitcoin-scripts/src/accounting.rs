@@ -0,0 +1,168 @@
+//! Fee accounting for the operator's books, derived from the spend history.
+//!
+//! `FeeLedger::from_history` turns every `SpendReceipt` broadcast in a period into a `FeeEntry`
+//! booked to its `Job` (deposit sweep, peg-out batch number, clawback, ...); receipts nobody
+//! tagged are booked to `UNATTRIBUTED`, so the product totals always add up to the fees in the
+//! history. Fees of confirmed transactions are booked; fees of unconfirmed ones are shown as
+//! pending, since a replaced or dropped transaction never pays its fee. `to_csv` and `to_json`
+//! export the ledger, amounts in integer sats and BTC strings (`report::format_btc`).
+
+use crate::receipt::{SpendHistory, SpendReceipt};
+use crate::report::format_btc;
+use bitcoin::{Amount, Txid};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Product of receipts without a `Job`
+pub const UNATTRIBUTED: &str = "unattributed";
+
+const CSV_HEADER: &str = "txid,product,reference,path_used,fee_sats,fee_btc,feerate_sat_per_kwu,broadcast_time,block_height,status";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// The fee of one transaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeEntry {
+    pub txid: Txid,
+    pub product: String,
+    /// E.g. the peg-out batch number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    pub path_used: String,
+    pub fee_sats: u64,
+    pub fee_btc: String,
+    pub feerate_sat_per_kwu: u64,
+    /// Unix time of the broadcast
+    pub broadcast_time: u64,
+    /// `None` while the fee is pending
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_height: Option<u64>,
+}
+
+impl FeeEntry {
+    pub fn new(receipt: &SpendReceipt) -> Self {
+        Self {
+            txid: receipt.txid,
+            product: receipt.job.as_ref().map(|job| job.product().to_string()).unwrap_or_else(|| UNATTRIBUTED.to_string()),
+            reference: receipt.job.as_ref().and_then(|job| job.reference()),
+            path_used: receipt.path_used.clone(),
+            fee_sats: receipt.fee.to_sat(),
+            fee_btc: format_btc(receipt.fee),
+            feerate_sat_per_kwu: receipt.feerate.to_sat_per_kwu(),
+            broadcast_time: receipt.broadcast_time,
+            block_height: receipt.block.as_ref().map(|block| block.height),
+        }
+    }
+
+    pub fn booked(&self) -> bool {
+        self.block_height.is_some()
+    }
+}
+
+/// Fees of one product
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProductTotal {
+    pub product: String,
+    pub transactions: usize,
+    /// Fees of confirmed transactions
+    pub booked_sats: u64,
+    /// Fees of transactions not confirmed yet
+    pub pending_sats: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeLedger {
+    /// Unix times the ledger covers, `from` inclusive and `to` exclusive
+    pub from: u64,
+    pub to: u64,
+    /// In broadcast order
+    pub entries: Vec<FeeEntry>,
+    /// By product name
+    pub totals: Vec<ProductTotal>,
+}
+
+impl FeeLedger {
+    /// Book the receipts in `history` broadcast from unix time `from` up to `to`
+    pub fn from_history(history: &SpendHistory, from: u64, to: u64) -> Self {
+        let mut entries: Vec<FeeEntry> = history.receipts.iter()
+            .filter(|r| r.broadcast_time >= from && r.broadcast_time < to)
+            .map(FeeEntry::new)
+            .collect();
+        entries.sort_by_key(|e| e.broadcast_time);
+
+        let mut totals: BTreeMap<&str, ProductTotal> = BTreeMap::new();
+        for entry in &entries {
+            let total = totals.entry(&entry.product).or_insert_with(|| ProductTotal { product: entry.product.clone(), transactions: 0, booked_sats: 0, pending_sats: 0 });
+            total.transactions += 1;
+            if entry.booked() {
+                total.booked_sats += entry.fee_sats;
+            } else {
+                total.pending_sats += entry.fee_sats;
+            }
+        }
+        let totals = totals.into_values().collect();
+        Self { from, to, entries, totals }
+    }
+
+    /// Every receipt in the history file at `path`; an empty ledger if there is none yet
+    pub fn load_history(path: &Path, from: u64, to: u64) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::from_history(&SpendHistory::load(path)?.unwrap_or_default(), from, to))
+    }
+
+    pub fn booked(&self) -> Amount {
+        Amount::from_sat(self.totals.iter().map(|t| t.booked_sats).sum())
+    }
+
+    pub fn pending(&self) -> Amount {
+        Amount::from_sat(self.totals.iter().map(|t| t.pending_sats).sum())
+    }
+
+    /// One row per transaction under a header row; `status` is `booked` or `pending`
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", CSV_HEADER);
+        for e in &self.entries {
+            let row = [
+                e.txid.to_string(),
+                csv_field(&e.product),
+                csv_field(e.reference.as_deref().unwrap_or_default()),
+                csv_field(&e.path_used),
+                e.fee_sats.to_string(),
+                e.fee_btc.clone(),
+                e.feerate_sat_per_kwu.to_string(),
+                e.broadcast_time.to_string(),
+                e.block_height.map(|h| h.to_string()).unwrap_or_default(),
+                if e.booked() { "booked" } else { "pending" }.to_string(),
+            ];
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn export(&self, path: &Path, format: ExportFormat) -> Result<(), Box<dyn std::error::Error>> {
+        let contents = match format {
+            ExportFormat::Csv => self.to_csv(),
+            ExportFormat::Json => self.to_json()?,
+        };
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+/// Quote `value` if it would otherwise break the row
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
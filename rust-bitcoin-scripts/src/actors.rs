@@ -0,0 +1,349 @@
+//! Message-passing actors for the long-running service.
+//!
+//! Each actor is a tokio task that exclusively owns its state (scanner, holding queue, signing
+//! rounds) and is driven through a cloneable handle over an mpsc channel; requests that need an
+//! answer carry a oneshot reply. Nothing is shared behind locks, so many vaults and descriptors can
+//! be served concurrently without lock-ordering bugs. Errors cross the channel as strings.
+//! The `spawn_with` variants stop on a `CancellationToken`, bound each operation by a deadline,
+//! save their state (scanner checkpoint, waiting transactions, collecting rounds) on the way out
+//! and return the task's `JoinHandle` so shutdown can wait for that to finish.
+
+use crate::broadcast::{HoldStatus, HoldingQueue};
+use crate::cancel::{guarded, CancellationToken, OperationTimeouts};
+use crate::reservation::UtxoReservations;
+use crate::scanner::{BlockScanner, ScanEvent, ScannerCheckpoint};
+use crate::signing_round::{Escalation, RoundState, SigningRound, SigningRounds};
+use crate::test_setup::BitcoinRPC;
+use crate::webhooks::LifecycleEvent;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{ScriptBuf, Transaction, Txid};
+use log::{error, warn};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+const MAILBOX: usize = 256;
+
+type Reply<T> = oneshot::Sender<Result<T, String>>;
+
+async fn request<M, T>(tx: &mpsc::Sender<M>, make: impl FnOnce(Reply<T>) -> M) -> Result<T, Box<dyn std::error::Error>> {
+    let (reply, rx) = oneshot::channel();
+    tx.send(make(reply)).await.map_err(|_| "actor stopped")?;
+    Ok(rx.await.map_err(|_| "actor dropped the request")??)
+}
+
+enum WatcherMsg {
+    Watch(ScriptBuf),
+    ScanRange { from: u64, to: u64, reply: Reply<Vec<ScanEvent>> },
+    CatchUp { reply: Reply<Vec<ScanEvent>> },
+}
+
+pub struct WatcherOptions {
+    pub shutdown: CancellationToken,
+    pub timeouts: OperationTimeouts,
+    /// Scanner checkpoint loaded on start and saved on shutdown
+    pub checkpoint_path: Option<PathBuf>,
+}
+
+impl Default for WatcherOptions {
+    fn default() -> Self {
+        Self { shutdown: CancellationToken::new(), timeouts: OperationTimeouts::default(), checkpoint_path: None }
+    }
+}
+
+/// Owns a `BlockScanner`; scan events are also published on the events channel
+#[derive(Clone)]
+pub struct WatcherHandle {
+    tx: mpsc::Sender<WatcherMsg>,
+}
+
+impl WatcherHandle {
+    pub fn spawn(rpc: BitcoinRPC, scanner: BlockScanner, events: mpsc::UnboundedSender<ScanEvent>) -> Self {
+        Self::spawn_with(rpc, scanner, events, WatcherOptions::default()).expect("no checkpoint to load").0
+    }
+
+    /// Scripts and outputs from a saved checkpoint are added to `scanner`. A scan interrupted by
+    /// shutdown or a block deadline still publishes the events of the blocks it finished; the reply
+    /// is then an error naming the last scanned height.
+    pub fn spawn_with(rpc: BitcoinRPC, scanner: BlockScanner, events: mpsc::UnboundedSender<ScanEvent>, options: WatcherOptions) -> Result<(Self, JoinHandle<()>), Box<dyn std::error::Error>> {
+        let WatcherOptions { shutdown, timeouts, checkpoint_path } = options;
+        let mut scanner = scanner;
+        let mut last_scanned = None;
+        if let Some(checkpoint) = checkpoint_path.as_deref().map(ScannerCheckpoint::load).transpose()?.flatten() {
+            let restored = BlockScanner::from_checkpoint(&checkpoint)?;
+            scanner.watched_scripts.extend(restored.watched_scripts);
+            scanner.tracked.extend(restored.tracked);
+            last_scanned = checkpoint.last_scanned;
+        }
+        let (tx, mut rx) = mpsc::channel(MAILBOX);
+        let task = tokio::spawn(async move {
+            loop {
+                let msg = tokio::select! {
+                    biased;
+                    _ = shutdown.cancelled() => break,
+                    msg = rx.recv() => match msg {
+                        Some(msg) => msg,
+                        None => break,
+                    },
+                };
+                let (range, reply) = match msg {
+                    WatcherMsg::Watch(script) => {
+                        scanner.watch_script(script);
+                        continue;
+                    }
+                    WatcherMsg::ScanRange { from, to, reply } => (Ok((from, to)), reply),
+                    WatcherMsg::CatchUp { reply } => {
+                        let tip = guarded(&shutdown, "tip lookup", timeouts.scan_block, rpc.get_block_count()).await;
+                        let range = match (tip, last_scanned) {
+                            (Ok(tip), Some(last)) => Ok((last + 1, tip)),
+                            // First run: start watching from the current tip
+                            (Ok(tip), None) => {
+                                last_scanned = Some(tip);
+                                Ok((tip + 1, tip))
+                            }
+                            (Err(e), _) => Err(e.to_string()),
+                        };
+                        (range, reply)
+                    }
+                };
+                let result = match range {
+                    Ok((from, to)) if from > to => Ok(Vec::new()),
+                    Ok((from, to)) => match scanner.scan_range_cancellable(&rpc, from, to, &shutdown, timeouts.scan_block).await {
+                        Ok(progress) => {
+                            for event in &progress.events {
+                                let _ = events.send(event.clone());
+                            }
+                            last_scanned = last_scanned.max(progress.last_scanned);
+                            match progress.interrupted {
+                                None => Ok(progress.events),
+                                Some(interrupted) => Err(format!("{} (last scanned height: {:?})", interrupted, progress.last_scanned)),
+                            }
+                        }
+                        Err(e) => Err(e.to_string()),
+                    },
+                    Err(e) => Err(e),
+                };
+                let _ = reply.send(result);
+            }
+            if let Some(path) = &checkpoint_path {
+                if let Err(e) = scanner.checkpoint(last_scanned).save(path) {
+                    error!("failed to save scanner checkpoint to {}: {}", path.display(), e);
+                }
+            }
+        });
+        Ok((Self { tx }, task))
+    }
+
+    pub async fn watch(&self, script_pubkey: ScriptBuf) -> Result<(), Box<dyn std::error::Error>> {
+        self.tx.send(WatcherMsg::Watch(script_pubkey)).await.map_err(|_| "watcher stopped")?;
+        Ok(())
+    }
+
+    pub async fn scan_range(&self, from: u64, to: u64) -> Result<Vec<ScanEvent>, Box<dyn std::error::Error>> {
+        request(&self.tx, |reply| WatcherMsg::ScanRange { from, to, reply }).await
+    }
+
+    /// Scan every block after the last scanned one up to the node's tip. Without a checkpoint the
+    /// first call only records the current tip.
+    pub async fn catch_up(&self) -> Result<Vec<ScanEvent>, Box<dyn std::error::Error>> {
+        request(&self.tx, |reply| WatcherMsg::CatchUp { reply }).await
+    }
+}
+
+enum BroadcasterMsg {
+    Hold { tx: Transaction, reply: Reply<Txid> },
+    Status { txid: Txid, reply: Reply<Option<HoldStatus>> },
+    Poll { reply: Reply<Vec<Txid>> },
+}
+
+pub struct BroadcasterOptions {
+    /// How often held transactions are re-checked
+    pub interval: Duration,
+    pub shutdown: CancellationToken,
+    pub timeouts: OperationTimeouts,
+    /// Transactions still waiting are loaded from here on start and flushed here on shutdown
+    pub state_path: Option<PathBuf>,
+}
+
+/// Owns a `HoldingQueue` and polls it every `interval`
+#[derive(Clone)]
+pub struct BroadcasterHandle {
+    tx: mpsc::Sender<BroadcasterMsg>,
+}
+
+impl BroadcasterHandle {
+    pub fn spawn(rpc: BitcoinRPC, interval: Duration) -> Self {
+        let options = BroadcasterOptions { interval, shutdown: CancellationToken::new(), timeouts: OperationTimeouts::default(), state_path: None };
+        Self::spawn_with(rpc, options).expect("no state to load").0
+    }
+
+    pub fn spawn_with(rpc: BitcoinRPC, options: BroadcasterOptions) -> Result<(Self, JoinHandle<()>), Box<dyn std::error::Error>> {
+        let BroadcasterOptions { interval, shutdown, timeouts, state_path } = options;
+        let mut queue = match &state_path {
+            Some(path) => HoldingQueue::load(path)?,
+            None => HoldingQueue::new(),
+        };
+        let (tx, mut rx) = mpsc::channel(MAILBOX);
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = shutdown.cancelled() => break,
+                    msg = rx.recv() => match msg {
+                        Some(BroadcasterMsg::Hold { tx, reply }) => {
+                            let _ = reply.send(Ok(queue.hold(tx)));
+                        }
+                        Some(BroadcasterMsg::Status { txid, reply }) => {
+                            let _ = reply.send(Ok(queue.status(&txid).cloned()));
+                        }
+                        Some(BroadcasterMsg::Poll { reply }) => {
+                            let result = guarded(&shutdown, "broadcast poll", timeouts.broadcast_poll, queue.poll(&rpc)).await;
+                            let _ = reply.send(result.map_err(|e| e.to_string()));
+                        }
+                        None => break,
+                    },
+                    _ = ticker.tick() => {
+                        if queue.pending() > 0 {
+                            if let Err(e) = guarded(&shutdown, "broadcast poll", timeouts.broadcast_poll, queue.poll(&rpc)).await {
+                                warn!("broadcaster poll failed: {}", e);
+                            }
+                        }
+                    }
+                }
+            }
+            if let Some(path) = &state_path {
+                if let Err(e) = queue.save(path) {
+                    error!("failed to flush holding queue to {}: {}", path.display(), e);
+                }
+            }
+        });
+        Ok((Self { tx }, task))
+    }
+
+    pub async fn hold(&self, tx: Transaction) -> Result<Txid, Box<dyn std::error::Error>> {
+        request(&self.tx, |reply| BroadcasterMsg::Hold { tx, reply }).await
+    }
+
+    pub async fn status(&self, txid: Txid) -> Result<Option<HoldStatus>, Box<dyn std::error::Error>> {
+        request(&self.tx, |reply| BroadcasterMsg::Status { txid, reply }).await
+    }
+
+    /// Poll now instead of waiting for the next tick
+    pub async fn poll(&self) -> Result<Vec<Txid>, Box<dyn std::error::Error>> {
+        request(&self.tx, |reply| BroadcasterMsg::Poll { reply }).await
+    }
+}
+
+enum CoordinatorMsg {
+    Open { round: SigningRound, reply: Reply<()> },
+    Submit { round_id: String, signer: String, partial: Psbt, reply: Reply<bool> },
+    Take { round_id: String, reply: Reply<Psbt> },
+    State { round_id: String, reply: Reply<Option<RoundState>> },
+    Tick { now: Instant, reply: Reply<Vec<Escalation>> },
+}
+
+pub struct CoordinatorOptions {
+    /// How often round timeouts are evaluated
+    pub interval: Duration,
+    pub shutdown: CancellationToken,
+    /// Where collecting rounds are saved on shutdown and resumed from on start
+    pub state_path: Option<PathBuf>,
+}
+
+/// Owns the open `SigningRounds`. Stalled rounds release their UTXOs from `reservations` and are
+/// published as `SigningRoundStalled` lifecycle events.
+#[derive(Clone)]
+pub struct CoordinatorHandle {
+    tx: mpsc::Sender<CoordinatorMsg>,
+}
+
+impl CoordinatorHandle {
+    pub fn spawn(reservations: UtxoReservations, events: mpsc::UnboundedSender<LifecycleEvent>, interval: Duration) -> Self {
+        let options = CoordinatorOptions { interval, shutdown: CancellationToken::new(), state_path: None };
+        Self::spawn_with(reservations, events, options).expect("no state to resume").0
+    }
+
+    /// Resumes the rounds saved at `options.state_path` (their UTXOs must be re-reserved by the
+    /// caller) and, on shutdown, saves every round still collecting signatures there.
+    pub fn spawn_with(reservations: UtxoReservations, events: mpsc::UnboundedSender<LifecycleEvent>, options: CoordinatorOptions) -> Result<(Self, JoinHandle<()>), Box<dyn std::error::Error>> {
+        let CoordinatorOptions { interval, shutdown, state_path } = options;
+        let mut rounds = match &state_path {
+            Some(path) => SigningRounds::resume(path)?,
+            None => SigningRounds::new(),
+        };
+        let (tx, mut rx) = mpsc::channel(MAILBOX);
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            let escalate = |escalations: &[Escalation]| {
+                for escalation in escalations {
+                    reservations.release_all(&escalation.released_utxos);
+                    let _ = events.send(escalation.to_event());
+                }
+            };
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = shutdown.cancelled() => {
+                        if let Some(path) = &state_path {
+                            if let Err(e) = rounds.persist(path, Instant::now()) {
+                                error!("failed to persist signing rounds to {}: {}", path.display(), e);
+                            }
+                        }
+                        break;
+                    }
+                    msg = rx.recv() => match msg {
+                        Some(CoordinatorMsg::Open { round, reply }) => {
+                            rounds.open(round);
+                            let _ = reply.send(Ok(()));
+                        }
+                        Some(CoordinatorMsg::Submit { round_id, signer, partial, reply }) => {
+                            let _ = reply.send(rounds.submit(&round_id, &signer, partial).map_err(|e| e.to_string()));
+                        }
+                        Some(CoordinatorMsg::Take { round_id, reply }) => {
+                            let _ = reply.send(rounds.rounds.remove(&round_id).map(|r| r.psbt).ok_or_else(|| format!("unknown round {}", round_id)));
+                        }
+                        Some(CoordinatorMsg::State { round_id, reply }) => {
+                            let _ = reply.send(Ok(rounds.rounds.get(&round_id).map(|r| r.state)));
+                        }
+                        Some(CoordinatorMsg::Tick { now, reply }) => {
+                            let escalations = rounds.tick(now);
+                            escalate(&escalations);
+                            let _ = reply.send(Ok(escalations));
+                        }
+                        None => break,
+                    },
+                    _ = ticker.tick() => escalate(&rounds.tick(Instant::now())),
+                }
+            }
+        });
+        Ok((Self { tx }, task))
+    }
+
+    pub async fn open(&self, round: SigningRound) -> Result<(), Box<dyn std::error::Error>> {
+        request(&self.tx, |reply| CoordinatorMsg::Open { round, reply }).await
+    }
+
+    pub async fn submit(&self, round_id: &str, signer: &str, partial: Psbt) -> Result<bool, Box<dyn std::error::Error>> {
+        let (round_id, signer) = (round_id.to_string(), signer.to_string());
+        request(&self.tx, |reply| CoordinatorMsg::Submit { round_id, signer, partial, reply }).await
+    }
+
+    /// Remove a round and return its merged PSBT
+    pub async fn take(&self, round_id: &str) -> Result<Psbt, Box<dyn std::error::Error>> {
+        let round_id = round_id.to_string();
+        request(&self.tx, |reply| CoordinatorMsg::Take { round_id, reply }).await
+    }
+
+    /// State of round `round_id`; `None` once taken or if it was never opened
+    pub async fn state(&self, round_id: &str) -> Result<Option<RoundState>, Box<dyn std::error::Error>> {
+        let round_id = round_id.to_string();
+        request(&self.tx, |reply| CoordinatorMsg::State { round_id, reply }).await
+    }
+
+    /// Evaluate timeouts as of `now` (the actor also does this on its own interval)
+    pub async fn tick(&self, now: Instant) -> Result<Vec<Escalation>, Box<dyn std::error::Error>> {
+        request(&self.tx, |reply| CoordinatorMsg::Tick { now, reply }).await
+    }
+}
@@ -0,0 +1,224 @@
+//! Fresh deposit addresses from ranged descriptors.
+//!
+//! `AddressManager` derives address #i of a `wsh(...)`/`tr(...)` descriptor with wildcard keys,
+//! hands out the next unused index and recovers which indices were used from the chain: from the
+//! UTXO set (fast, but misses outputs already spent) or by scanning blocks
+//! (complete). Both look `gap_limit` indices past the highest used one, like wallets do.
+//! A frozen manager (`freeze`, see `incident`) refuses to issue addresses until unfrozen.
+
+use crate::backend::ChainBackend;
+use crate::scanner::{BlockScanner, ScanEvent};
+use crate::state_file;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, Network, PublicKey, ScriptBuf};
+use miniscript::descriptor::DescriptorPublicKey;
+use miniscript::Descriptor;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// Persistent state of an `AddressManager`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddressState {
+    pub descriptor: String,
+    pub network: Network,
+    pub gap_limit: u32,
+    pub next_index: u32,
+    pub used: BTreeSet<u32>,
+    /// Why issuance is frozen, if it is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frozen: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AddressManager {
+    pub descriptor: Descriptor<DescriptorPublicKey>,
+    pub network: Network,
+    /// Unused indices looked at past the highest used one when scanning
+    pub gap_limit: u32,
+    next_index: u32,
+    used: BTreeSet<u32>,
+    /// scriptPubKeys of indices `0..scripts.len()`
+    scripts: HashMap<ScriptBuf, u32>,
+    frozen: Option<String>,
+}
+
+impl AddressManager {
+    /// Manager for a ranged, single-path descriptor
+    pub fn new(descriptor: Descriptor<DescriptorPublicKey>, network: Network) -> Result<Self, Box<dyn std::error::Error>> {
+        if !descriptor.has_wildcard() {
+            return Err(format!("{} has no wildcard key", descriptor).into());
+        }
+        if descriptor.is_multipath() {
+            return Err("multipath descriptors must be split into one manager per path".into());
+        }
+        Ok(Self { descriptor, network, gap_limit: DEFAULT_GAP_LIMIT, next_index: 0, used: BTreeSet::new(), scripts: HashMap::new(), frozen: None })
+    }
+
+    pub fn with_gap_limit(self, gap_limit: u32) -> Self {
+        Self { gap_limit, ..self }
+    }
+
+    /// The descriptor at `index`
+    pub fn derive(&self, index: u32) -> Result<Descriptor<PublicKey>, Box<dyn std::error::Error>> {
+        Ok(self.descriptor.derived_descriptor(&Secp256k1::verification_only(), index)?)
+    }
+
+    pub fn address(&self, index: u32) -> Result<Address, Box<dyn std::error::Error>> {
+        Ok(self.derive(index)?.address(self.network)?)
+    }
+
+    /// Issue a fresh address: the next index never handed out or seen used
+    pub fn next_address(&mut self) -> Result<(u32, Address), Box<dyn std::error::Error>> {
+        if let Some(reason) = &self.frozen {
+            return Err(format!("address issuance is frozen: {}", reason).into());
+        }
+        let index = self.next_index;
+        let address = self.address(index)?;
+        self.next_index = index.checked_add(1).ok_or("derivation index space exhausted")?;
+        Ok((index, address))
+    }
+
+    /// Index the next call to `next_address` returns
+    pub fn next_index(&self) -> u32 {
+        self.next_index
+    }
+
+    /// Stop `next_address` until `unfreeze`; deriving and scanning keep working
+    pub fn freeze(&mut self, reason: &str) {
+        self.frozen = Some(reason.to_string());
+    }
+
+    pub fn unfreeze(&mut self) {
+        self.frozen = None;
+    }
+
+    /// Why issuance is frozen, if it is
+    pub fn frozen(&self) -> Option<&str> {
+        self.frozen.as_deref()
+    }
+
+    pub fn used(&self) -> &BTreeSet<u32> {
+        &self.used
+    }
+
+    /// Record `index` as used; addresses up to it are never handed out again
+    pub fn mark_used(&mut self, index: u32) {
+        self.used.insert(index);
+        self.next_index = self.next_index.max(index.saturating_add(1));
+    }
+
+    /// Indices to look at: everything handed out plus `gap_limit` more
+    fn lookahead_end(&self) -> u32 {
+        self.next_index.saturating_add(self.gap_limit)
+    }
+
+    /// Derive scripts up to `end` (exclusive) that are not cached yet
+    fn derive_until(&mut self, end: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let start = self.scripts.len() as u32;
+        for index in start..end {
+            let script = self.derive(index)?.script_pubkey();
+            self.scripts.insert(script, index);
+        }
+        Ok(())
+    }
+
+    /// Index of the address paying `script_pubkey`, within the lookahead window
+    pub fn index_of(&mut self, script_pubkey: &ScriptBuf) -> Result<Option<u32>, Box<dyn std::error::Error>> {
+        self.derive_until(self.lookahead_end())?;
+        Ok(self.scripts.get(script_pubkey).copied())
+    }
+
+    /// Mark the indices of deposits among `events`; returns the newly used ones
+    pub fn observe(&mut self, events: &[ScanEvent]) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        let mut found = Vec::new();
+        for event in events {
+            if let ScanEvent::Deposit { script_pubkey, .. } = event {
+                if let Some(index) = self.index_of(script_pubkey)? {
+                    if !self.used.contains(&index) {
+                        self.mark_used(index);
+                        found.push(index);
+                    }
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Mark indices holding unspent outputs, per `ChainBackend::utxos_for_scripts` (one
+    /// `scantxoutset` on Core); repeats while the window moves. Returns the newly used indices.
+    pub async fn scan_utxo_set(&mut self, backend: &impl ChainBackend) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        let mut found = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = self.lookahead_end();
+            if start >= end {
+                return Ok(found);
+            }
+            let scripts = (start..end).map(|index| Ok(self.derive(index)?.script_pubkey())).collect::<Result<Vec<ScriptBuf>, Box<dyn std::error::Error>>>()?;
+            let utxos = backend.utxos_for_scripts(&scripts).await?;
+            start = end;
+            for utxo in utxos {
+                if let Some(index) = self.index_of(&utxo.script_pubkey)? {
+                    if !self.used.contains(&index) {
+                        self.mark_used(index);
+                        found.push(index);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Mark indices that received anything in blocks `from..=to`, spent or not. The block range
+    /// is scanned again whenever a hit moves the lookahead window. Returns the newly used indices.
+    pub async fn scan_blocks(&mut self, backend: &impl ChainBackend, from: u64, to: u64) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        let mut found = Vec::new();
+        let mut watched = 0;
+        loop {
+            let end = self.lookahead_end();
+            if watched >= end {
+                return Ok(found);
+            }
+            let mut scanner = BlockScanner::new();
+            for index in watched..end {
+                scanner.watch_script(self.derive(index)?.script_pubkey());
+            }
+            watched = end;
+            found.extend(self.observe(&scanner.scan_range(backend, from, to).await?)?);
+        }
+    }
+
+    pub fn state(&self) -> AddressState {
+        AddressState {
+            descriptor: self.descriptor.to_string(),
+            network: self.network,
+            gap_limit: self.gap_limit,
+            next_index: self.next_index,
+            used: self.used.clone(),
+            frozen: self.frozen.clone(),
+        }
+    }
+
+    pub fn from_state(state: &AddressState) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut manager = Self::new(Descriptor::from_str(&state.descriptor)?, state.network)?.with_gap_limit(state.gap_limit);
+        manager.next_index = state.next_index;
+        manager.used = state.used.clone();
+        manager.frozen = state.frozen.clone();
+        Ok(manager)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        state_file::save_json(path, &self.state())
+    }
+
+    /// `None` if nothing was saved yet
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match state_file::load_json::<AddressState>(path)? {
+            Some(state) => Ok(Some(Self::from_state(&state)?)),
+            None => Ok(None),
+        }
+    }
+}
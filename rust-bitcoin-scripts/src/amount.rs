@@ -0,0 +1,59 @@
+//! `Amount` at the RPC boundary and in transaction builders.
+//!
+//! Amounts go to Core as exact 8-decimal BTC strings (Core accepts strings wherever it takes an
+//! amount) and come back through `Amount::from_btc`, which parses the shortest decimal form of
+//! the JSON number and so round-trips every value Core can print. Fees are deducted with checked
+//! arithmetic: a fee larger than the input or a dust result is an error, not a wrapped `u64`.
+
+use crate::outputs::dust_limit;
+use crate::report::format_btc;
+use bitcoin::{Amount, Script};
+use serde_json::Value;
+use std::fmt;
+
+/// RPC parameter for `amount`
+pub fn to_rpc(amount: Amount) -> Value {
+    Value::String(format_btc(amount))
+}
+
+/// Parse a BTC amount from an RPC result, given as a JSON number or string
+pub fn from_rpc(value: &Value) -> Result<Amount, Box<dyn std::error::Error>> {
+    match value {
+        Value::Number(n) => Ok(Amount::from_btc(n.as_f64().ok_or("amount out of range")?)?),
+        Value::String(s) => Ok(Amount::from_str_in(s, bitcoin::Denomination::Bitcoin)?),
+        other => Err(format!("expected a BTC amount, got {}", other).into()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeeError {
+    FeeExceedsAmount { amount: Amount, fee: Amount },
+    /// What remains after the fee is below the dust limit of the output script
+    BelowDust { value: Amount, dust: Amount },
+}
+
+impl fmt::Display for FeeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeeError::FeeExceedsAmount { amount, fee } => write!(f, "fee of {} sats exceeds the {} sats being spent", fee.to_sat(), amount.to_sat()),
+            FeeError::BelowDust { value, dust } => write!(f, "{} sats left after the fee is below the {} sat dust limit", value.to_sat(), dust.to_sat()),
+        }
+    }
+}
+
+impl std::error::Error for FeeError {}
+
+/// `amount - fee`, failing instead of underflowing
+pub fn deduct_fee(amount: Amount, fee: Amount) -> Result<Amount, FeeError> {
+    amount.checked_sub(fee).ok_or(FeeError::FeeExceedsAmount { amount, fee })
+}
+
+/// `amount - fee` for an output paying `script_pubkey`, which must stay above its dust limit
+pub fn deduct_fee_for(amount: Amount, fee: Amount, script_pubkey: &Script) -> Result<Amount, FeeError> {
+    let value = deduct_fee(amount, fee)?;
+    let dust = dust_limit(script_pubkey);
+    if value < dust {
+        return Err(FeeError::BelowDust { value, dust });
+    }
+    Ok(value)
+}
@@ -0,0 +1,172 @@
+//! Vault parameter analyzer: timelock races between spend paths.
+//!
+//! Spend paths are the top-level branches of the normalized semantic policy (for `tr` the key path
+//! and the leaves), as in `lint`. Paths without a timelock are the clawback side. Every timelocked
+//! path must leave a watchtower time to notice it and get a clawback confirmed, assuming blocks
+//! arrive at `worst_case_block_interval`; consecutive timelocked paths must be that far apart too,
+//! so the earlier one can answer the later. Each warning carries the delay that would fix it.
+
+use miniscript::bitcoin::PublicKey;
+use miniscript::policy::{Liftable, Semantic};
+use miniscript::Descriptor;
+use bitcoin::relative;
+use bitcoin::Sequence;
+use std::fmt;
+use std::time::Duration;
+
+/// Relative time locks count in units of 512 seconds
+const TIME_UNIT_SECS: u32 = 512;
+/// Expected block interval, for how long getting a clawback confirmed takes in wall time
+const TARGET_BLOCK_SECS: u32 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaceParams {
+    /// Time for a watchtower to notice a spend and broadcast the clawback
+    pub watchtower_reaction: Duration,
+    /// Shortest sustained block interval to plan for (a hashrate surge)
+    pub worst_case_block_interval: Duration,
+    /// Blocks to allow for the clawback to confirm under congestion
+    pub confirmation_blocks: u32,
+    /// Current height, to turn absolute height locks into a delay; without it they are skipped
+    pub tip_height: Option<u32>,
+}
+
+impl Default for RaceParams {
+    fn default() -> Self {
+        Self {
+            watchtower_reaction: Duration::from_secs(30 * 60),
+            worst_case_block_interval: Duration::from_secs(120),
+            confirmation_blocks: 6,
+            tip_height: None,
+        }
+    }
+}
+
+impl RaceParams {
+    /// Blocks that must pass before a timelocked path opens
+    pub fn required_blocks(&self) -> u32 {
+        let interval = self.worst_case_block_interval.as_secs().max(1);
+        let reaction = (self.watchtower_reaction.as_secs() + interval - 1) / interval;
+        reaction as u32 + self.confirmation_blocks
+    }
+
+    /// Seconds that must pass before a time-based path opens, rounded up to whole 512 s units
+    pub fn required_secs(&self) -> u32 {
+        let secs = self.watchtower_reaction.as_secs() as u32 + self.confirmation_blocks * TARGET_BLOCK_SECS;
+        (secs + TIME_UNIT_SECS - 1) / TIME_UNIT_SECS * TIME_UNIT_SECS
+    }
+}
+
+/// How long after funding a path becomes spendable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delay {
+    Immediate,
+    Blocks(u32),
+    Seconds(u32),
+}
+
+impl fmt::Display for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Delay::Immediate => write!(f, "immediately"),
+            Delay::Blocks(n) => write!(f, "{} blocks", n),
+            Delay::Seconds(s) => write!(f, "{} seconds", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RaceWarning {
+    /// Every path is timelocked, so nothing can claw a theft back
+    NoClawbackPath,
+    /// Path opens before a watchtower can get a clawback confirmed
+    DelayTooShort { path: usize, delay: Delay, suggested: Delay },
+    /// `later` opens too soon after `earlier` for `earlier` to answer it
+    TimelockRace { earlier: usize, later: usize, gap: Delay, suggested_later: Delay },
+    /// Absolute time locks, absolute heights without a tip, or blocks mixed with seconds
+    Unanalyzed { path: usize, reason: &'static str },
+}
+
+impl fmt::Display for RaceWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RaceWarning::NoClawbackPath => write!(f, "no spend path without a timelock is left for a clawback"),
+            RaceWarning::DelayTooShort { path, delay, suggested } => write!(f, "path {} opens after {}, too soon for a watchtower; use at least {}", path, delay, suggested),
+            RaceWarning::TimelockRace { earlier, later, gap, suggested_later } => write!(f, "path {} opens only {} after path {}; delay it to at least {}", later, gap, earlier, suggested_later),
+            RaceWarning::Unanalyzed { path, reason } => write!(f, "path {} not analyzed: {}", path, reason),
+        }
+    }
+}
+
+/// Delay of one spend path, or why it cannot be given one
+fn path_delay(policy: &Semantic<PublicKey>, params: &RaceParams) -> Result<Delay, &'static str> {
+    let mut blocks = None;
+    let mut secs = None;
+    for value in policy.relative_timelocks() {
+        match Sequence::from_consensus(value).to_relative_lock_time() {
+            Some(relative::LockTime::Blocks(h)) => blocks = blocks.max(Some(h.value() as u32)),
+            Some(relative::LockTime::Time(t)) => secs = secs.max(Some(t.value() as u32 * TIME_UNIT_SECS)),
+            None => return Err("relative lock disabled"),
+        }
+    }
+    for value in policy.absolute_timelocks() {
+        if value >= bitcoin::absolute::LOCK_TIME_THRESHOLD {
+            return Err("absolute time lock");
+        }
+        let tip = params.tip_height.ok_or("absolute height lock without a tip height")?;
+        blocks = blocks.max(Some(value.saturating_sub(tip)));
+    }
+    match (blocks, secs) {
+        (Some(_), Some(_)) => Err("mixes block and time locks"),
+        (Some(b), None) => Ok(Delay::Blocks(b)),
+        (None, Some(s)) => Ok(Delay::Seconds(s)),
+        (None, None) => Ok(Delay::Immediate),
+    }
+}
+
+/// Delay of each spend path, in path order
+pub fn path_delays(descriptor: &Descriptor<PublicKey>, params: &RaceParams) -> Result<Vec<Result<Delay, &'static str>>, Box<dyn std::error::Error>> {
+    Ok(match descriptor.lift()?.normalized() {
+        Semantic::Threshold(1, branches) => branches.iter().map(|b| path_delay(b, params)).collect(),
+        policy => vec![path_delay(&policy, params)],
+    })
+}
+
+/// Check `descriptor` for timelock races
+pub fn analyze(descriptor: &Descriptor<PublicKey>, params: &RaceParams) -> Result<Vec<RaceWarning>, Box<dyn std::error::Error>> {
+    let mut warnings = Vec::new();
+    let delays = path_delays(descriptor, params)?;
+    if !delays.iter().any(|d| *d == Ok(Delay::Immediate)) {
+        warnings.push(RaceWarning::NoClawbackPath);
+    }
+
+    let mut blocks: Vec<(usize, u32)> = Vec::new();
+    let mut secs: Vec<(usize, u32)> = Vec::new();
+    for (path, delay) in delays.into_iter().enumerate() {
+        match delay {
+            Ok(Delay::Immediate) => {}
+            Ok(Delay::Blocks(b)) => blocks.push((path, b)),
+            Ok(Delay::Seconds(s)) => secs.push((path, s)),
+            Err(reason) => warnings.push(RaceWarning::Unanalyzed { path, reason }),
+        }
+    }
+    check_races(&mut warnings, blocks, params.required_blocks(), Delay::Blocks);
+    check_races(&mut warnings, secs, params.required_secs(), Delay::Seconds);
+    Ok(warnings)
+}
+
+/// Minimum delay and gaps for paths locked in one unit
+fn check_races(warnings: &mut Vec<RaceWarning>, mut paths: Vec<(usize, u32)>, required: u32, unit: fn(u32) -> Delay) {
+    paths.sort_by_key(|(path, delay)| (*delay, *path));
+    for (path, delay) in &paths {
+        if *delay < required {
+            warnings.push(RaceWarning::DelayTooShort { path: *path, delay: unit(*delay), suggested: unit(required) });
+        }
+    }
+    for pair in paths.windows(2) {
+        let ((earlier, first), (later, second)) = (pair[0], pair[1]);
+        if second - first < required {
+            warnings.push(RaceWarning::TimelockRace { earlier, later, gap: unit(second - first), suggested_later: unit(first + required) });
+        }
+    }
+}
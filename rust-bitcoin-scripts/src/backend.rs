@@ -0,0 +1,633 @@
+//! Chain access behind one trait, so the crate can run against a Core node, an Esplora HTTP API
+//! or an Electrum server.
+//!
+//! `ChainBackend` covers what the library needs without a wallet: UTXOs by script or descriptor,
+//! broadcast, fee estimates, the relay floor, the tip, blocks, transactions and confirmation
+//! status. `WalletBackend` adds the funding wallet the regtest flows and hybrid spends pay from.
+//! `BitcoinRPC` implements both over RPC; `EsploraBackend` implements `ChainBackend` over the
+//! Esplora REST API (`blockstream.info/api`, `mempool.space/api` or a self-hosted electrs);
+//! `ElectrumBackend` over the Electrum protocol, newline-delimited JSON-RPC on a plain TCP
+//! connection (no TLS), one connection per request.
+//!
+//! Esplora addresses scripts by `sha256(script_pubkey)` in hex, Electrum by the same hash with
+//! its bytes reversed (`esplora_script_hash` / `electrum_script_hash`). Neither reports a
+//! mempool minimum, so their relay floor is the relay fee alone. Blocks come back in the layout of
+//! Core's `getblock` at verbosity 2, so the scanner reads every backend alike; Electrum serves no
+//! blocks and cannot drive a scan.
+
+use crate::broadcast::ChainTip;
+use crate::fees::{self, RelayFloor, DEFAULT_MIN_RELAY_RATE};
+use crate::psbt;
+use crate::rpc_types::{ListUnspentEntry, MempoolEntry, MempoolEntryFees, Utxo};
+use crate::test_setup::{BitcoinRPC, CoreError};
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::{deserialize, serialize_hex};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{Address, Amount, Block, BlockHash, FeeRate, Network, OutPoint, PublicKey, Script, ScriptBuf, Transaction, Txid};
+use miniscript::Descriptor;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// Where a transaction is
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxStatus {
+    /// 0 while in the mempool
+    pub confirmations: u32,
+    /// Hash and height of the confirming block
+    pub block: Option<(BlockHash, u64)>,
+}
+
+// The futures are not required to be `Send`: like the rest of the crate, callers await them on
+// the task that made them.
+#[allow(async_fn_in_trait)]
+pub trait ChainBackend {
+    fn network(&self) -> Network;
+    /// URL or address of the server, to tell backends apart in reports
+    fn endpoint(&self) -> &str;
+    /// Parse `address`, rejecting addresses of another network
+    fn parse_address(&self, address: &str) -> Result<Address, Box<dyn std::error::Error>> {
+        Ok(Address::from_str(address)?.require_network(self.network())?)
+    }
+    /// Unspent outputs paying `script_pubkey`; unconfirmed ones only if the backend sees them
+    async fn utxos(&self, script_pubkey: &Script) -> Result<Vec<Utxo>, Box<dyn std::error::Error>>;
+    /// `utxos` of every script in `scripts`
+    async fn utxos_for_scripts(&self, scripts: &[ScriptBuf]) -> Result<Vec<Utxo>, Box<dyn std::error::Error>> {
+        let mut utxos = Vec::new();
+        for script_pubkey in scripts {
+            utxos.extend(self.utxos(script_pubkey).await?);
+        }
+        Ok(utxos)
+    }
+    /// Unspent outputs paying `descriptor`
+    async fn descriptor_utxos(&self, descriptor: &Descriptor<PublicKey>) -> Result<Vec<Utxo>, Box<dyn std::error::Error>> {
+        self.utxos(&descriptor.script_pubkey()).await
+    }
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid, Box<dyn std::error::Error>>;
+    /// Rate for confirmation within `conf_target` blocks; `None` when the backend has no data
+    async fn fee_rate(&self, conf_target: u16) -> Result<Option<FeeRate>, Box<dyn std::error::Error>>;
+    /// Lowest rate the backend relays, with `configured` as our own minimum
+    async fn relay_floor(&self, configured: FeeRate) -> Result<RelayFloor, Box<dyn std::error::Error>>;
+    async fn tip_height(&self) -> Result<u64, Box<dyn std::error::Error>>;
+    /// Height and median time past of the tip
+    async fn chain_tip(&self) -> Result<ChainTip, Box<dyn std::error::Error>>;
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn std::error::Error>>;
+    /// `block_hash` of every height in `heights`
+    async fn block_hashes(&self, heights: RangeInclusive<u64>) -> Result<Vec<BlockHash>, Box<dyn std::error::Error>> {
+        let mut hashes = Vec::new();
+        for height in heights {
+            hashes.push(self.block_hash(height).await?);
+        }
+        Ok(hashes)
+    }
+    /// Block `hash` as `getblock` returns it at verbosity 2, or at verbosity 3 (every input with
+    /// its `prevout`) when `prevouts` is set and the backend can supply them
+    async fn block(&self, hash: &BlockHash, prevouts: bool) -> Result<Value, Box<dyn std::error::Error>>;
+    /// `None` if the backend does not know `txid`
+    async fn tx_status(&self, txid: &Txid) -> Result<Option<TxStatus>, Box<dyn std::error::Error>>;
+    async fn transaction(&self, txid: &Txid) -> Result<Transaction, Box<dyn std::error::Error>>;
+    /// Size and fees of the unconfirmed `txid`. Without package data from the backend the entry
+    /// covers `txid` alone, as if it had no unconfirmed ancestors.
+    async fn mempool_entry(&self, txid: &Txid) -> Result<MempoolEntry, Box<dyn std::error::Error>> {
+        match self.tx_status(txid).await? {
+            Some(status) if status.confirmations == 0 => {}
+            Some(_) => return Err(format!("{} is already confirmed", txid).into()),
+            None => return Err(format!("{} is not in the mempool", txid).into()),
+        }
+        let tx = self.transaction(txid).await?;
+        let mut input_value = Amount::ZERO;
+        for input in &tx.input {
+            let prev_tx = self.transaction(&input.previous_output.txid).await?;
+            let prevout = prev_tx.output.get(input.previous_output.vout as usize).ok_or_else(|| format!("{} has no output {}", input.previous_output.txid, input.previous_output.vout))?;
+            input_value += Amount::from_sat(prevout.value);
+        }
+        let fee = input_value.checked_sub(tx.output.iter().map(|o| Amount::from_sat(o.value)).sum()).ok_or_else(|| format!("{} spends more than its inputs", txid))?;
+        Ok(lone_entry(tx.weight().to_wu(), fee))
+    }
+}
+
+/// Funding wallet on top of a `ChainBackend`, for the flows that pay from, mine to or sign with it
+#[allow(async_fn_in_trait)]
+pub trait WalletBackend: ChainBackend {
+    /// Fresh receive address, tagged with `label` ("" for none)
+    async fn new_address(&self, label: &str) -> Result<Address, Box<dyn std::error::Error>>;
+    async fn change_address(&self) -> Result<Address, Box<dyn std::error::Error>>;
+    async fn balance(&self) -> Result<Amount, Box<dyn std::error::Error>>;
+    /// Pay `amount` to `address` from the wallet
+    async fn send_to(&self, address: &Address, amount: Amount) -> Result<Txid, Box<dyn std::error::Error>>;
+    /// Wallet UTXOs with at least `min_conf` confirmations
+    async fn unspent(&self, min_conf: u32) -> Result<Vec<ListUnspentEntry>, Box<dyn std::error::Error>>;
+    /// `psbt` with the wallet's signatures on the inputs it controls, not finalized
+    async fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt, Box<dyn std::error::Error>>;
+    /// Mine `blocks` to a fresh wallet address, empty ones when `empty` is set, and return the
+    /// new tip height. Regtest only.
+    async fn generate(&self, blocks: u32, empty: bool) -> Result<u64, Box<dyn std::error::Error>>;
+}
+
+/// Script hash as Esplora's `/scripthash/:hash` endpoints take it
+pub fn esplora_script_hash(script_pubkey: &Script) -> String {
+    sha256::Hash::hash(script_pubkey.as_bytes()).to_string()
+}
+
+/// Script hash as Electrum's `blockchain.scripthash.*` methods take it
+pub fn electrum_script_hash(script_pubkey: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script_pubkey.as_bytes()).to_byte_array();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+/// Mempool entry of a transaction of `weight` paying `fee`, without unconfirmed ancestors
+fn lone_entry(weight: u64, fee: Amount) -> MempoolEntry {
+    let vsize = weight.div_ceil(4);
+    MempoolEntry {
+        vsize,
+        weight,
+        ancestor_count: 1,
+        ancestor_size: vsize,
+        fees: MempoolEntryFees { base: fee, modified: fee, ancestor: fee, descendant: fee },
+    }
+}
+
+/// `block` at `height` in the layout of `getblock` verbosity 2, for backends serving raw blocks
+fn block_json(block: &Block, height: u64) -> Value {
+    let txs: Vec<Value> = block.txdata.iter().map(|tx| {
+        let vin: Vec<Value> = tx.input.iter().map(|input| match tx.is_coin_base() {
+            true => json!({ "coinbase": hex::encode(input.script_sig.as_bytes()) }),
+            false => json!({ "txid": input.previous_output.txid.to_string(), "vout": input.previous_output.vout }),
+        }).collect();
+        let vout: Vec<Value> = tx.output.iter().enumerate().map(|(n, output)| json!({
+            "value": Amount::from_sat(output.value).to_btc(),
+            "n": n,
+            "scriptPubKey": { "hex": hex::encode(output.script_pubkey.as_bytes()) },
+        })).collect();
+        json!({ "txid": tx.txid().to_string(), "vin": vin, "vout": vout })
+    }).collect();
+    json!({ "hash": block.block_hash().to_string(), "height": height, "tx": txs })
+}
+
+/// The last result of a mining batch ending in `getblockcount`, failing on any earlier call
+fn tip_after(results: Vec<Result<Value, Box<dyn std::error::Error>>>) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut tip = None;
+    for result in results {
+        tip = Some(result?);
+    }
+    tip.and_then(|t| t.as_u64()).ok_or_else(|| "getblockcount returned no height".into())
+}
+
+/// Height of the block holding `txid` from its confirmation count; an error if the count runs
+/// past the tip, which a node only reports when its answers straddle a reorg or it misbehaves
+pub(crate) fn confirmed_height(txid: &Txid, confirmations: u32, tip: u64) -> Result<u64, Box<dyn std::error::Error>> {
+    (tip + 1).checked_sub(confirmations as u64).ok_or_else(|| format!("{} has {} confirmations but the tip is at height {}", txid, confirmations, tip).into())
+}
+
+/// Esplora and Electrum report the block height; 0 or less means unconfirmed
+fn confirmations_at(height: Option<u64>, tip: u64) -> u32 {
+    match height {
+        Some(height) if height > 0 && height <= tip => (tip + 1 - height) as u32,
+        _ => 0,
+    }
+}
+
+impl ChainBackend for BitcoinRPC {
+    fn network(&self) -> Network {
+        self.network
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.url
+    }
+
+    async fn utxos(&self, script_pubkey: &Script) -> Result<Vec<Utxo>, Box<dyn std::error::Error>> {
+        match Address::from_script(script_pubkey, self.network) {
+            Ok(address) => self.find_utxos_for_address(&address.to_string()).await,
+            Err(_) => Ok(self.scan_tx_out_set(&[format!("raw({})", script_pubkey.to_hex_string())]).await?.utxos()),
+        }
+    }
+
+    /// One `scantxoutset` for all of `scripts`, so confirmed outputs only
+    async fn utxos_for_scripts(&self, scripts: &[ScriptBuf]) -> Result<Vec<Utxo>, Box<dyn std::error::Error>> {
+        let objects: Vec<String> = scripts.iter().map(|s| format!("raw({})", s.to_hex_string())).collect();
+        Ok(self.scan_tx_out_set(&objects).await?.utxos())
+    }
+
+    /// Confirmed only; the UTXO set has no mempool outputs
+    async fn descriptor_utxos(&self, descriptor: &Descriptor<PublicKey>) -> Result<Vec<Utxo>, Box<dyn std::error::Error>> {
+        self.find_utxos_for_descriptor(descriptor).await
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid, Box<dyn std::error::Error>> {
+        Ok(Txid::from_str(&self.send_raw_transaction(&serialize_hex(tx)).await?)?)
+    }
+
+    async fn fee_rate(&self, conf_target: u16) -> Result<Option<FeeRate>, Box<dyn std::error::Error>> {
+        Ok(self.estimate_smart_fee(conf_target).await?.fee_rate.map(fees::from_btc_per_kvb))
+    }
+
+    async fn relay_floor(&self, configured: FeeRate) -> Result<RelayFloor, Box<dyn std::error::Error>> {
+        let info = self.get_mempool_info().await?;
+        Ok(RelayFloor {
+            min_relay: fees::from_btc_per_kvb(info.min_relay_tx_fee),
+            mempool_min: fees::from_btc_per_kvb(info.mempool_min_fee),
+            configured,
+        })
+    }
+
+    async fn tip_height(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        self.get_block_count().await
+    }
+
+    async fn chain_tip(&self) -> Result<ChainTip, Box<dyn std::error::Error>> {
+        let info = self.get_blockchain_info().await?;
+        Ok(ChainTip { height: info.blocks, median_time_past: info.median_time })
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn std::error::Error>> {
+        self.get_block_hash(height).await
+    }
+
+    /// In one round trip
+    async fn block_hashes(&self, heights: RangeInclusive<u64>) -> Result<Vec<BlockHash>, Box<dyn std::error::Error>> {
+        self.get_block_hashes(heights).await
+    }
+
+    /// Verbosity 3 needs Core 23 or later; older nodes reject it or leave `prevout` out
+    async fn block(&self, hash: &BlockHash, prevouts: bool) -> Result<Value, Box<dyn std::error::Error>> {
+        self.call_rpc("getblock", json!([hash.to_string(), if prevouts { 3 } else { 2 }])).await
+    }
+
+    async fn tx_status(&self, txid: &Txid) -> Result<Option<TxStatus>, Box<dyn std::error::Error>> {
+        let raw = match self.get_raw_transaction_verbose(txid).await {
+            Ok(raw) => raw,
+            Err(e) if CoreError::of(&*e) == Some(CoreError::NotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let confirmations = raw.confirmations.unwrap_or(0);
+        let block = match raw.block_hash {
+            Some(hash) if confirmations > 0 => Some((hash, confirmed_height(txid, confirmations, self.get_block_count().await?)?)),
+            _ => None,
+        };
+        Ok(Some(TxStatus { confirmations, block }))
+    }
+
+    async fn transaction(&self, txid: &Txid) -> Result<Transaction, Box<dyn std::error::Error>> {
+        self.get_raw_transaction_verbose(txid).await?.transaction()
+    }
+
+    /// `getmempoolentry`, with the package of unconfirmed ancestors
+    async fn mempool_entry(&self, txid: &Txid) -> Result<MempoolEntry, Box<dyn std::error::Error>> {
+        self.get_mempool_entry(txid).await
+    }
+}
+
+impl WalletBackend for BitcoinRPC {
+    async fn new_address(&self, label: &str) -> Result<Address, Box<dyn std::error::Error>> {
+        self.parse_address(&self.new_labeled_address(label).await?)
+    }
+
+    async fn change_address(&self) -> Result<Address, Box<dyn std::error::Error>> {
+        let address: String = self.call_typed("getrawchangeaddress", json!([])).await?;
+        self.parse_address(&address)
+    }
+
+    async fn balance(&self) -> Result<Amount, Box<dyn std::error::Error>> {
+        self.get_balance().await
+    }
+
+    async fn send_to(&self, address: &Address, amount: Amount) -> Result<Txid, Box<dyn std::error::Error>> {
+        Ok(Txid::from_str(&self.send_to_address(&address.to_string(), amount).await?)?)
+    }
+
+    async fn unspent(&self, min_conf: u32) -> Result<Vec<ListUnspentEntry>, Box<dyn std::error::Error>> {
+        self.list_unspent(min_conf).await
+    }
+
+    async fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt, Box<dyn std::error::Error>> {
+        let processed = self.wallet_process_psbt(&psbt::to_base64(psbt), true, false).await?;
+        psbt::from_base64(&processed.psbt)
+    }
+
+    /// The blocks and the new height come back in one batched round trip
+    async fn generate(&self, blocks: u32, empty: bool) -> Result<u64, Box<dyn std::error::Error>> {
+        let address = self.get_new_address().await?;
+        let mut calls = match empty {
+            true => vec![("generateblock", json!([address, []])); blocks as usize],
+            false => vec![("generatetoaddress", json!([blocks, address]))],
+        };
+        calls.push(("getblockcount", json!([])));
+        tip_after(self.call_batch(calls).await?)
+    }
+}
+
+/// Esplora REST API at `base_url`, e.g. `https://blockstream.info/testnet/api`
+#[derive(Debug, Clone)]
+pub struct EsploraBackend {
+    pub base_url: String,
+    pub network: Network,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraStatus {
+    confirmed: bool,
+    block_height: Option<u64>,
+    block_hash: Option<BlockHash>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraBlock {
+    height: u64,
+    mediantime: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraTx {
+    weight: u64,
+    fee: u64,
+    status: EsploraStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraUtxo {
+    txid: Txid,
+    vout: u32,
+    value: u64,
+    status: EsploraStatus,
+}
+
+impl EsploraBackend {
+    pub fn new(base_url: &str, network: Network) -> Self {
+        Self { base_url: base_url.trim_end_matches('/').to_string(), network, client: reqwest::Client::new() }
+    }
+
+    async fn get(&self, path: &str) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+        let response = self.client.get(format!("{}{}", self.base_url, path)).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(format!("GET {}: {} {}", path, status, response.text().await.unwrap_or_default()).into());
+        }
+        Ok(response)
+    }
+}
+
+impl ChainBackend for EsploraBackend {
+    fn network(&self) -> Network {
+        self.network
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn utxos(&self, script_pubkey: &Script) -> Result<Vec<Utxo>, Box<dyn std::error::Error>> {
+        let entries: Vec<EsploraUtxo> = self.get(&format!("/scripthash/{}/utxo", esplora_script_hash(script_pubkey))).await?.json().await?;
+        let tip = self.tip_height().await?;
+        Ok(entries.into_iter().map(|e| Utxo {
+            outpoint: OutPoint::new(e.txid, e.vout),
+            amount: Amount::from_sat(e.value),
+            script_pubkey: script_pubkey.to_owned(),
+            confirmations: if e.status.confirmed { confirmations_at(e.status.block_height, tip) } else { 0 },
+        }).collect())
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid, Box<dyn std::error::Error>> {
+        let response = self.client.post(format!("{}/tx", self.base_url)).body(serialize_hex(tx)).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(format!("broadcast of {} rejected: {} {}", tx.txid(), status, body).into());
+        }
+        Ok(Txid::from_str(body.trim())?)
+    }
+
+    /// `/fee-estimates` maps targets to sat/vB; the estimate for the largest target not above
+    /// `conf_target` is used
+    async fn fee_rate(&self, conf_target: u16) -> Result<Option<FeeRate>, Box<dyn std::error::Error>> {
+        let estimates: BTreeMap<String, f64> = self.get("/fee-estimates").await?.json().await?;
+        let best = estimates.iter()
+            .filter_map(|(target, rate)| Some((target.parse::<u16>().ok()?, *rate)))
+            .filter(|(target, _)| *target <= conf_target)
+            .max_by_key(|(target, _)| *target);
+        Ok(best.map(|(_, sat_per_vb)| FeeRate::from_sat_per_kwu((sat_per_vb * 250.0).ceil() as u64)))
+    }
+
+    async fn relay_floor(&self, configured: FeeRate) -> Result<RelayFloor, Box<dyn std::error::Error>> {
+        Ok(RelayFloor { min_relay: DEFAULT_MIN_RELAY_RATE, mempool_min: DEFAULT_MIN_RELAY_RATE, configured })
+    }
+
+    async fn tip_height(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(self.get("/blocks/tip/height").await?.text().await?.trim().parse()?)
+    }
+
+    async fn chain_tip(&self) -> Result<ChainTip, Box<dyn std::error::Error>> {
+        let hash = self.get("/blocks/tip/hash").await?.text().await?;
+        let block: EsploraBlock = self.get(&format!("/block/{}", hash.trim())).await?.json().await?;
+        Ok(ChainTip { height: block.height, median_time_past: block.mediantime })
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn std::error::Error>> {
+        Ok(BlockHash::from_str(self.get(&format!("/block-height/{}", height)).await?.text().await?.trim())?)
+    }
+
+    /// Built from the raw block; Esplora has no prevouts in it, so `prevouts` is ignored
+    async fn block(&self, hash: &BlockHash, _prevouts: bool) -> Result<Value, Box<dyn std::error::Error>> {
+        let info: EsploraBlock = self.get(&format!("/block/{}", hash)).await?.json().await?;
+        let block: Block = deserialize(&self.get(&format!("/block/{}/raw", hash)).await?.bytes().await?)?;
+        Ok(block_json(&block, info.height))
+    }
+
+    async fn tx_status(&self, txid: &Txid) -> Result<Option<TxStatus>, Box<dyn std::error::Error>> {
+        let response = self.client.get(format!("{}/tx/{}/status", self.base_url, txid)).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let status: EsploraStatus = response.error_for_status()?.json().await?;
+        if !status.confirmed {
+            return Ok(Some(TxStatus { confirmations: 0, block: None }));
+        }
+        let confirmations = confirmations_at(status.block_height, self.tip_height().await?);
+        let block = status.block_hash.zip(status.block_height);
+        Ok(Some(TxStatus { confirmations, block }))
+    }
+
+    async fn transaction(&self, txid: &Txid) -> Result<Transaction, Box<dyn std::error::Error>> {
+        let hex = self.get(&format!("/tx/{}/hex", txid)).await?.text().await?;
+        Ok(deserialize(&hex::decode(hex.trim())?)?)
+    }
+
+    /// Esplora reports the fee but not the unconfirmed ancestors
+    async fn mempool_entry(&self, txid: &Txid) -> Result<MempoolEntry, Box<dyn std::error::Error>> {
+        let tx: EsploraTx = self.get(&format!("/tx/{}", txid)).await?.json().await?;
+        if tx.status.confirmed {
+            return Err(format!("{} is already confirmed", txid).into());
+        }
+        Ok(lone_entry(tx.weight, Amount::from_sat(tx.fee)))
+    }
+}
+
+/// Electrum server at `address` (`host:port`), plain TCP
+#[derive(Debug)]
+pub struct ElectrumBackend {
+    pub address: String,
+    pub network: Network,
+    next_id: AtomicU64,
+}
+
+/// The request was malformed or asked for something the server does not support
+pub const ELECTRUM_BAD_REQUEST: i64 = 1;
+/// The server's bitcoind refused the request; for lookups, an unknown transaction
+pub const ELECTRUM_DAEMON_ERROR: i64 = 2;
+
+/// Error object of an Electrum response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElectrumError {
+    pub method: String,
+    pub code: i64,
+    pub message: String,
+}
+
+impl ElectrumError {
+    fn from_json(method: &str, error: &Value) -> Self {
+        Self {
+            method: method.to_string(),
+            code: error["code"].as_i64().unwrap_or_default(),
+            message: error["message"].as_str().map(str::to_string).unwrap_or_else(|| error.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ElectrumError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: error {}: {}", self.method, self.code, self.message)
+    }
+}
+
+impl std::error::Error for ElectrumError {}
+
+#[derive(Debug, Deserialize)]
+struct ElectrumUnspent {
+    tx_hash: Txid,
+    tx_pos: u32,
+    /// 0 (or -1 with unconfirmed parents) while in the mempool
+    height: i64,
+    value: u64,
+}
+
+impl ElectrumBackend {
+    pub fn new(address: &str, network: Network) -> Self {
+        Self { address: address.to_string(), network, next_id: AtomicU64::new(0) }
+    }
+
+    /// Send one request and read its response line
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut stream = TcpStream::connect(&self.address).await?;
+        let mut request = serde_json::to_vec(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))?;
+        request.push(b'\n');
+        stream.write_all(&request).await?;
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).await?;
+        let mut response: Value = serde_json::from_str(&line).map_err(|e| format!("{}: bad response {:?}: {}", method, line, e))?;
+        match response.get("error") {
+            Some(error) if !error.is_null() => Err(ElectrumError::from_json(method, error).into()),
+            _ => Ok(response["result"].take()),
+        }
+    }
+}
+
+impl ChainBackend for ElectrumBackend {
+    fn network(&self) -> Network {
+        self.network
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.address
+    }
+
+    async fn utxos(&self, script_pubkey: &Script) -> Result<Vec<Utxo>, Box<dyn std::error::Error>> {
+        let entries: Vec<ElectrumUnspent> = serde_json::from_value(self.call("blockchain.scripthash.listunspent", json!([electrum_script_hash(script_pubkey)])).await?)?;
+        let tip = self.tip_height().await?;
+        Ok(entries.into_iter().map(|e| Utxo {
+            outpoint: OutPoint::new(e.tx_hash, e.tx_pos),
+            amount: Amount::from_sat(e.value),
+            script_pubkey: script_pubkey.to_owned(),
+            confirmations: confirmations_at(u64::try_from(e.height).ok(), tip),
+        }).collect())
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid, Box<dyn std::error::Error>> {
+        let txid = self.call("blockchain.transaction.broadcast", json!([serialize_hex(tx)])).await?;
+        Ok(Txid::from_str(txid.as_str().ok_or("broadcast returned no txid")?)?)
+    }
+
+    /// `blockchain.estimatefee` answers in BTC/kvB, -1 without an estimate
+    async fn fee_rate(&self, conf_target: u16) -> Result<Option<FeeRate>, Box<dyn std::error::Error>> {
+        let rate = self.call("blockchain.estimatefee", json!([conf_target])).await?.as_f64().ok_or("estimatefee returned no number")?;
+        if rate <= 0.0 {
+            return Ok(None);
+        }
+        Ok(Some(fees::from_btc_per_kvb(Amount::from_btc(rate)?)))
+    }
+
+    async fn relay_floor(&self, configured: FeeRate) -> Result<RelayFloor, Box<dyn std::error::Error>> {
+        let rate = self.call("blockchain.relayfee", json!([])).await?.as_f64().ok_or("relayfee returned no number")?;
+        let min_relay = fees::from_btc_per_kvb(Amount::from_btc(rate)?);
+        Ok(RelayFloor { min_relay, mempool_min: min_relay, configured })
+    }
+
+    async fn tip_height(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        self.call("blockchain.headers.subscribe", json!([])).await?["height"].as_u64().ok_or_else(|| "headers.subscribe returned no height".into())
+    }
+
+    /// Median time past from the last 11 headers, as Core computes it
+    async fn chain_tip(&self) -> Result<ChainTip, Box<dyn std::error::Error>> {
+        let height = self.tip_height().await?;
+        let first = height.saturating_sub(10);
+        let headers = self.call("blockchain.block.headers", json!([first, height + 1 - first])).await?;
+        let bytes = hex::decode(headers["hex"].as_str().ok_or("block.headers returned no hex")?)?;
+        let mut times = bytes.chunks(80).map(|chunk| Ok(deserialize::<Header>(chunk)?.time)).collect::<Result<Vec<u32>, Box<dyn std::error::Error>>>()?;
+        times.sort_unstable();
+        let median_time_past = *times.get(times.len() / 2).ok_or("block.headers returned no headers")?;
+        Ok(ChainTip { height, median_time_past: median_time_past as u64 })
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn std::error::Error>> {
+        let header = self.call("blockchain.block.header", json!([height])).await?;
+        let header: Header = deserialize(&hex::decode(header.as_str().ok_or("block.header returned no hex")?)?)?;
+        Ok(header.block_hash())
+    }
+
+    /// The Electrum protocol serves headers only
+    async fn block(&self, hash: &BlockHash, _prevouts: bool) -> Result<Value, Box<dyn std::error::Error>> {
+        Err(format!("{} serves no blocks, so block {} cannot be fetched; scan through Core or Esplora", self.address, hash).into())
+    }
+
+    /// Needs a server that answers verbose `blockchain.transaction.get` (ElectrumX, Fulcrum);
+    /// electrs rejects verbose requests
+    async fn tx_status(&self, txid: &Txid) -> Result<Option<TxStatus>, Box<dyn std::error::Error>> {
+        let raw = match self.call("blockchain.transaction.get", json!([txid.to_string(), true])).await {
+            Ok(raw) => raw,
+            Err(e) => return match e.downcast_ref::<ElectrumError>().map(|error| error.code) {
+                Some(ELECTRUM_DAEMON_ERROR) => Ok(None),
+                Some(ELECTRUM_BAD_REQUEST) => Err(format!("{} rejected verbose blockchain.transaction.get ({}); tx_status needs ElectrumX or Fulcrum", self.address, e).into()),
+                _ => Err(e),
+            },
+        };
+        let confirmations = raw["confirmations"].as_u64().unwrap_or(0) as u32;
+        let block = match raw["blockhash"].as_str() {
+            Some(hash) if confirmations > 0 => Some((BlockHash::from_str(hash)?, confirmed_height(txid, confirmations, self.tip_height().await?)?)),
+            _ => None,
+        };
+        Ok(Some(TxStatus { confirmations, block }))
+    }
+
+    async fn transaction(&self, txid: &Txid) -> Result<Transaction, Box<dyn std::error::Error>> {
+        let hex = self.call("blockchain.transaction.get", json!([txid.to_string()])).await?;
+        Ok(deserialize(&hex::decode(hex.as_str().ok_or("transaction.get returned no hex")?)?)?)
+    }
+}
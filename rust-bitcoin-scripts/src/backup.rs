@@ -0,0 +1,175 @@
+//! Encrypted backup archives of service state for disaster recovery.
+//!
+//! An archive bundles files and directories (the service `state_dir` with scanner checkpoints,
+//! queues and rounds, descriptor files, keystores, ...) into one file:
+//! `MAGIC || salt(16) || nonce(24) || XChaCha20-Poly1305(JSON contents)`, keyed by Argon2id over a
+//! passphrase. Every entry carries its SHA-256, checked on restore in addition to the AEAD tag.
+
+use base64::Engine;
+use bitcoin::hashes::{sha256, Hash};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use zeroize::Zeroizing;
+
+pub const MAGIC: &[u8; 6] = b"WYBAK1";
+pub const FORMAT_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupEntry {
+    /// Relative path, `/`-separated
+    pub path: String,
+    pub sha256: String,
+    /// Base64 file contents
+    pub data: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupContents {
+    pub version: u32,
+    pub crate_version: String,
+    pub created_at: u64,
+    pub entries: Vec<BackupEntry>,
+}
+
+impl BackupContents {
+    /// Read `sources`; a file is stored under its name, a directory under its name plus the
+    /// relative path of every file below it
+    pub fn collect(sources: &[PathBuf]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut entries = Vec::new();
+        for source in sources {
+            let name = source.file_name().ok_or_else(|| format!("{} has no file name", source.display()))?;
+            collect_into(source, PathBuf::from(name), &mut entries)?;
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        if let Some(pair) = entries.windows(2).find(|w| w[0].path == w[1].path) {
+            return Err(format!("two sources both provide {}", pair[0].path).into());
+        }
+        let created_at = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+        Ok(Self { version: FORMAT_VERSION, crate_version: env!("CARGO_PKG_VERSION").to_string(), created_at, entries })
+    }
+
+    /// Check every entry's hash and path
+    pub fn verify(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.version != FORMAT_VERSION {
+            return Err(format!("unsupported backup version {}", self.version).into());
+        }
+        for entry in &self.entries {
+            safe_relative_path(&entry.path)?;
+            let data = base64::engine::general_purpose::STANDARD.decode(&entry.data)?;
+            if sha256::Hash::hash(&data).to_string() != entry.sha256 {
+                return Err(format!("integrity check failed for {}", entry.path).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn collect_into(path: &Path, relative: PathBuf, entries: &mut Vec<BackupEntry>) -> Result<(), Box<dyn std::error::Error>> {
+    if path.is_dir() {
+        for child in std::fs::read_dir(path)? {
+            let child = child?;
+            collect_into(&child.path(), relative.join(child.file_name()), entries)?;
+        }
+        return Ok(());
+    }
+    let data = std::fs::read(path)?;
+    let components: Vec<String> = relative.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+    entries.push(BackupEntry {
+        path: components.join("/"),
+        sha256: sha256::Hash::hash(&data).to_string(),
+        data: base64::engine::general_purpose::STANDARD.encode(&data),
+    });
+    Ok(())
+}
+
+/// Reject absolute paths and `..` so a crafted archive cannot write outside the target directory
+fn safe_relative_path(path: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let relative = PathBuf::from(path);
+    if path.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!("unsafe path in backup: {}", path).into());
+    }
+    Ok(relative)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, Box<dyn std::error::Error>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| format!("key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+pub fn encrypt(contents: &BackupContents, passphrase: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let plaintext = Zeroizing::new(serde_json::to_vec(contents)?);
+    seal(MAGIC, &plaintext, passphrase)
+}
+
+/// Decrypt and verify an archive; a wrong passphrase and any modification both fail here
+pub fn decrypt(archive: &[u8], passphrase: &str) -> Result<BackupContents, Box<dyn std::error::Error>> {
+    let plaintext = open(MAGIC, "backup archive", archive, passphrase)?;
+    let contents: BackupContents = serde_json::from_slice(&plaintext)?;
+    contents.verify()?;
+    Ok(contents)
+}
+
+/// `magic || salt || nonce || XChaCha20-Poly1305(plaintext)` keyed by Argon2id over `passphrase`
+pub(crate) fn seal(magic: &[u8], plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let key = derive_key(passphrase, &salt)?;
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+        .encrypt(XNonce::from_slice(&nonce), plaintext)
+        .map_err(|_| "encryption failed")?;
+    Ok([magic, salt.as_slice(), nonce.as_slice(), ciphertext.as_slice()].concat())
+}
+
+/// Inverse of `seal`; `kind` names the file in errors
+pub(crate) fn open(magic: &[u8], kind: &str, sealed: &[u8], passphrase: &str) -> Result<Zeroizing<Vec<u8>>, Box<dyn std::error::Error>> {
+    let header = magic.len() + SALT_LEN + NONCE_LEN;
+    if sealed.len() < header || &sealed[..magic.len()] != magic {
+        return Err(format!("not a wrapYield {}", kind).into());
+    }
+    let salt = &sealed[magic.len()..magic.len() + SALT_LEN];
+    let nonce = &sealed[magic.len() + SALT_LEN..header];
+    let key = derive_key(passphrase, salt)?;
+    Ok(Zeroizing::new(
+        XChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .decrypt(XNonce::from_slice(nonce), &sealed[header..])
+            .map_err(|_| format!("wrong passphrase or corrupted {}", kind))?,
+    ))
+}
+
+/// Write an encrypted archive of `sources` to `archive`
+pub fn create(archive: &Path, sources: &[PathBuf], passphrase: &str) -> Result<BackupContents, Box<dyn std::error::Error>> {
+    let contents = BackupContents::collect(sources)?;
+    std::fs::write(archive, encrypt(&contents, passphrase)?)?;
+    Ok(contents)
+}
+
+/// Restore `archive` into `target_dir`. Existing files are only replaced with `overwrite`; nothing
+/// is written unless the whole archive verifies and no conflict is found.
+pub fn restore(archive: &Path, target_dir: &Path, passphrase: &str, overwrite: bool) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let contents = decrypt(&std::fs::read(archive)?, passphrase)?;
+    let targets: Vec<PathBuf> = contents.entries.iter()
+        .map(|e| safe_relative_path(&e.path).map(|p| target_dir.join(p)))
+        .collect::<Result<_, _>>()?;
+    if !overwrite {
+        if let Some(existing) = targets.iter().find(|t| t.exists()) {
+            return Err(format!("{} already exists", existing.display()).into());
+        }
+    }
+    for (entry, target) in contents.entries.iter().zip(&targets) {
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(target, base64::engine::general_purpose::STANDARD.decode(&entry.data)?)?;
+    }
+    Ok(targets)
+}
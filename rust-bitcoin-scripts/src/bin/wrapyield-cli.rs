@@ -0,0 +1,182 @@
+//! Descriptor tooling, a regtest vault demo and incident response.
+//!
+//! - `descriptor inspect <descriptor> [--index N]`: addresses on every network, scripts, spend
+//!   paths with their timelock requirements and the max satisfaction weight
+//! - `descriptor address <descriptor> [--index N] [--network NET] [--record FILE [--template NAME]]`:
+//!   one address (default regtest), optionally recorded in the provenance index `FILE`
+//! - `address provenance <address> --provenance FILE`: template, parameters, index and spend
+//!   paths of an address recorded in `FILE` (see `provenance`)
+//!
+//! - `vault demo --path backup|multisig|taproot-leaf [--wallet NAME] [--amount SATS]`: fund a
+//!   fresh vault from the wallet (default `wrapyield_demo`, created if missing), spend it along
+//!   the path and print every txid (see `demo`)
+//!
+//! - `incident freeze --reason TEXT [--addresses FILE]... [--vault FILE]...`: stop issuing
+//!   addresses from the `AddressManager` state files and building peg-outs from the vault state
+//!   files; `incident unfreeze` with the same files lifts it
+//! - `incident trace <txid> [--to HEIGHT]`: every descendant of a suspicious transaction and the
+//!   outputs the coins sit in now
+//! - `incident recover --keystore FILE --destination ADDRESS [--entry NAME]...`: signed, not
+//!   broadcast, recovery transactions for every UTXO of the keystore entries (default all); the
+//!   passphrase is read from `WRAPYIELD_KEYSTORE_PASSPHRASE` (see `incident`)
+//!
+//! `--index` picks the child of a ranged (`/*`) descriptor and defaults to 0; `--network` takes
+//! `bitcoin`, `testnet`, `testnet4`, `signet` or `regtest`. The `descriptor` and `address`
+//! commands and `incident freeze`/`unfreeze` contact no node; the others use the
+//! `WRAPYIELD_NETWORK` / `WRAPYIELD_RPC_*` variables (see `RpcConfig::from_env`), and `vault demo`
+//! refuses anything but regtest.
+
+use bitcoin_scripts::demo::{self, DemoPath};
+use bitcoin_scripts::incident::{self, RecoveryOutcome};
+use bitcoin_scripts::inspect::{self, DescriptorReport};
+use bitcoin_scripts::keystore::Keystore;
+use bitcoin_scripts::provenance::{AddressOrigin, ProvenanceIndex};
+use bitcoin_scripts::test_setup::{parse_chain, BitcoinRPC};
+use miniscript::bitcoin::address::NetworkUnchecked;
+use miniscript::bitcoin::{Address, Amount, Network, Txid};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const USAGE: &str = "usage: descriptor inspect <descriptor> [--index N] | descriptor address <descriptor> [--index N] [--network NET] [--record FILE [--template NAME]] | address provenance <address> --provenance FILE | vault demo --path backup|multisig|taproot-leaf [--wallet NAME] [--amount SATS] | incident freeze --reason TEXT [--addresses FILE]... [--vault FILE]... | incident unfreeze [--addresses FILE]... [--vault FILE]... | incident trace <txid> [--to HEIGHT] | incident recover --keystore FILE --destination ADDRESS [--entry NAME]...";
+
+const DEMO_WALLET: &str = "wrapyield_demo";
+const DEMO_AMOUNT: Amount = Amount::from_sat(1_000_000);
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("descriptor") => descriptor_command(&args[1..]),
+        Some("address") => address_command(&args[1..]),
+        Some("vault") => vault_command(&args[1..]).await,
+        Some("incident") => incident_command(&args[1..]).await,
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn descriptor_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (command, descriptor) = match args {
+        [command, descriptor, ..] => (command.as_str(), inspect::parse(descriptor)?),
+        _ => return Err(USAGE.into()),
+    };
+    let index = match flag(args, "--index") {
+        Some(index) => index.parse()?,
+        None => 0,
+    };
+    match command {
+        "inspect" => {
+            print!("{}", DescriptorReport::new(&descriptor, index)?);
+            Ok(())
+        }
+        "address" => {
+            let network = match flag(args, "--network") {
+                Some(network) => parse_chain(network)?.0,
+                None => Network::Regtest,
+            };
+            if let Some(file) = flag(args, "--record") {
+                let template = flag(args, "--template").unwrap_or("descriptor");
+                let origin = AddressOrigin::derived(template, BTreeMap::new(), &descriptor, index, network)?;
+                ProvenanceIndex::append(Path::new(file), origin)?;
+            }
+            println!("{}", inspect::address(&descriptor, index, network)?);
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn address_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let address: Address<NetworkUnchecked> = match args {
+        [command, address, ..] if command == "provenance" => address.parse()?,
+        _ => return Err(USAGE.into()),
+    };
+    let file = flag(args, "--provenance").ok_or(USAGE)?;
+    let index = ProvenanceIndex::load(Path::new(file))?.ok_or_else(|| format!("no provenance index at {}", file))?;
+    let origins = index.lookup(&address);
+    if origins.is_empty() {
+        return Err(format!("{} was never recorded in {}", args[1], file).into());
+    }
+    for origin in origins {
+        print!("{}", origin);
+        println!("spend paths:");
+        for (i, case) in origin.report()?.paths.iter().enumerate() {
+            let needs = inspect::requirements(case);
+            if needs.is_empty() {
+                println!("  {}. {}", i + 1, case);
+            } else {
+                println!("  {}. {}; needs {}", i + 1, case, needs.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn vault_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.first().map(String::as_str) != Some("demo") {
+        return Err(USAGE.into());
+    }
+    let path = DemoPath::from_str(flag(args, "--path").ok_or(USAGE)?)?;
+    let wallet = flag(args, "--wallet").unwrap_or(DEMO_WALLET);
+    let amount = match flag(args, "--amount") {
+        Some(sats) => Amount::from_sat(sats.parse()?),
+        None => DEMO_AMOUNT,
+    };
+    let rpc = BitcoinRPC::from_env()?;
+    // Either may fail because the wallet already exists or is loaded
+    let _ = rpc.create_wallet(wallet).await;
+    let _ = rpc.load_wallet(wallet).await;
+    print!("{}", demo::run(&rpc.with_wallet(wallet), path, amount).await?);
+    Ok(())
+}
+
+async fn incident_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let addresses: Vec<PathBuf> = flags(args, "--addresses").into_iter().map(PathBuf::from).collect();
+    let vaults: Vec<PathBuf> = flags(args, "--vault").into_iter().map(PathBuf::from).collect();
+    match args.first().map(String::as_str) {
+        Some("freeze") => {
+            let reason = flag(args, "--reason").ok_or(USAGE)?;
+            print!("{}", incident::freeze(&addresses, &vaults, reason)?);
+            Ok(())
+        }
+        Some("unfreeze") => {
+            print!("{}", incident::unfreeze(&addresses, &vaults)?);
+            Ok(())
+        }
+        Some("trace") => {
+            let txid = Txid::from_str(args.get(1).ok_or(USAGE)?)?;
+            let to = match flag(args, "--to") {
+                Some(height) => Some(height.parse()?),
+                None => None,
+            };
+            print!("{}", incident::trace_descendants(&BitcoinRPC::from_env()?, &txid, to).await?);
+            Ok(())
+        }
+        Some("recover") => {
+            let file = flag(args, "--keystore").ok_or(USAGE)?;
+            let destination = flag(args, "--destination").ok_or(USAGE)?;
+            let passphrase = std::env::var("WRAPYIELD_KEYSTORE_PASSPHRASE").map_err(|_| "WRAPYIELD_KEYSTORE_PASSPHRASE is not set")?;
+            let keystore = Keystore::load(Path::new(file), &passphrase)?.ok_or_else(|| format!("no keystore at {}", file))?;
+            let names: Vec<String> = flags(args, "--entry").into_iter().map(String::from).collect();
+            let prepared = incident::prepare_recovery(&BitcoinRPC::from_env()?, &keystore, &names, destination).await?;
+            for recovery in &prepared {
+                println!("{}", recovery);
+            }
+            let blocked = prepared.iter().filter(|r| matches!(r.outcome, RecoveryOutcome::Blocked { .. })).count();
+            println!("{} ready, {} blocked", prepared.len() - blocked, blocked);
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// Value following `name`
+fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).map(String::as_str)
+}
+
+/// Values following every occurrence of `name`
+fn flags<'a>(args: &'a [String], name: &str) -> Vec<&'a str> {
+    args.windows(2).filter(|w| w[0] == name).map(|w| w[1].as_str()).collect()
+}
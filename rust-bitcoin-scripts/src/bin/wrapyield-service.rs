@@ -0,0 +1,48 @@
+//! Service daemon: scans new blocks, broadcasts held transactions and runs signing rounds until
+//! SIGINT/SIGTERM, then flushes its state to `WRAPYIELD_STATE_DIR` (default `./wrapyield-state`).
+//! `WRAPYIELD_WALLETS` lists wallets (comma separated) to unload on shutdown. The node is chosen
+//! with the `WRAPYIELD_NETWORK` / `WRAPYIELD_RPC_*` variables (see `RpcConfig::from_env`);
+//! `WRAPYIELD_READ_ONLY=1` simulates every broadcast and signature instead of executing it.
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` (plus `OTEL_EXPORTER_OTLP_HEADERS`, `OTEL_SERVICE_NAME`) turns on
+//! trace and metric export to an OpenTelemetry collector (see `telemetry`). Log output goes to
+//! stderr at `info` unless `RUST_LOG` says otherwise.
+
+use bitcoin_scripts::read_only;
+use bitcoin_scripts::scanner::BlockScanner;
+use bitcoin_scripts::service::{Service, ServiceConfig};
+use bitcoin_scripts::telemetry::OtlpConfig;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use std::path::PathBuf;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let state_dir = std::env::var("WRAPYIELD_STATE_DIR").map(PathBuf::from).unwrap_or_else(|_| PathBuf::from("wrapyield-state"));
+    let mut config = ServiceConfig::new(state_dir);
+    config.wallets = std::env::var("WRAPYIELD_WALLETS")
+        .map(|w| w.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    config.read_only = read_only::enable_from_env();
+    config.telemetry = OtlpConfig::from_env()?;
+
+    let (service, mut events) = Service::start(BitcoinRPC::from_env()?, BlockScanner::new(), config)?;
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(event) = events.scans.recv() => println!("scan: {:?}", event),
+                Some(event) = events.lifecycle.recv() => println!("lifecycle: {}", event.name()),
+                else => break,
+            }
+        }
+    });
+
+    let report = service.run_until_signal().await;
+    println!("stopped: {:?}", report.stopped);
+    if !report.wallets_closed.is_empty() {
+        println!("wallets unloaded: {:?}", report.wallets_closed);
+    }
+    if !report.timed_out.is_empty() || !report.errors.is_empty() {
+        return Err(format!("unclean shutdown: timed out {:?}, errors {:?}", report.timed_out, report.errors).into());
+    }
+    Ok(())
+}
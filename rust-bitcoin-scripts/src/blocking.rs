@@ -0,0 +1,231 @@
+//! Blocking counterpart of `test_setup::BitcoinRPC` for simple CLI paths and non-async consumers.
+//! Enabled with the `blocking` feature; must not be called from inside a tokio runtime.
+//!
+//! `BlockingChainBackend` mirrors `backend::ChainBackend` without futures, so code written for
+//! non-async consumers reads the chain through the same operations.
+
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use crate::amount;
+use crate::backend::{confirmed_height, TxStatus};
+use crate::broadcast::ChainTip;
+use crate::fees::{self, RelayFloor};
+use crate::read_only;
+use crate::rpc_types::{BlockchainInfo, EstimateSmartFeeResult, GetRawTransactionResult, MempoolInfo, ScanTxOutSetResult, Utxo};
+use crate::test_setup::{CoreError, RpcConfig, RpcError};
+use miniscript::bitcoin::consensus::encode::serialize_hex;
+use miniscript::bitcoin::{Amount, BlockHash, FeeRate, Network, Script, Transaction, Txid};
+
+/// `backend::ChainBackend` for blocking callers
+pub trait BlockingChainBackend {
+    fn network(&self) -> Network;
+    /// URL or address of the server, to tell backends apart in reports
+    fn endpoint(&self) -> &str;
+    /// Unspent outputs paying `script_pubkey`; unconfirmed ones only if the backend sees them
+    fn utxos(&self, script_pubkey: &Script) -> Result<Vec<Utxo>, Box<dyn std::error::Error>>;
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid, Box<dyn std::error::Error>>;
+    /// Rate for confirmation within `conf_target` blocks; `None` when the backend has no data
+    fn fee_rate(&self, conf_target: u16) -> Result<Option<FeeRate>, Box<dyn std::error::Error>>;
+    /// Lowest rate the backend relays, with `configured` as our own minimum
+    fn relay_floor(&self, configured: FeeRate) -> Result<RelayFloor, Box<dyn std::error::Error>>;
+    fn tip_height(&self) -> Result<u64, Box<dyn std::error::Error>>;
+    /// Height and median time past of the tip
+    fn chain_tip(&self) -> Result<ChainTip, Box<dyn std::error::Error>>;
+    fn block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn std::error::Error>>;
+    /// Block `hash` as `getblock` returns it at verbosity 2, or at verbosity 3 (every input with
+    /// its `prevout`) when `prevouts` is set and the backend can supply them
+    fn block(&self, hash: &BlockHash, prevouts: bool) -> Result<Value, Box<dyn std::error::Error>>;
+    /// `None` if the backend does not know `txid`
+    fn tx_status(&self, txid: &Txid) -> Result<Option<TxStatus>, Box<dyn std::error::Error>>;
+    fn transaction(&self, txid: &Txid) -> Result<Transaction, Box<dyn std::error::Error>>;
+}
+
+pub struct BitcoinRpcBlocking {
+    pub url: String,
+    pub client: reqwest::blocking::Client,
+    pub auth: String,
+    pub network: Network,
+}
+
+impl BitcoinRpcBlocking {
+    /// Node from the `WRAPYIELD_*` environment, like `BitcoinRPC::new`; panics on an invalid one
+    pub fn new() -> Self {
+        Self::with_config(RpcConfig::from_env().expect("invalid RPC configuration in environment"))
+            .expect("invalid RPC configuration in environment")
+    }
+
+    pub fn with_config(config: RpcConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            auth: config.auth.basic_credentials()?,
+            url: config.url,
+            client: reqwest::blocking::Client::new(),
+            network: config.network,
+        })
+    }
+
+    pub fn with_wallet(&self, wallet: &str) -> Self {
+        let url = format!("{}/wallet/{}", self.url.trim_end_matches('/'), wallet);
+        Self {
+            url,
+            client: self.client.clone(),
+            auth: self.auth.clone(),
+            network: self.network,
+        }
+    }
+    pub fn call_rpc(&self, method: &str, params: serde_json::Value) -> Result<Value, Box<dyn std::error::Error>> {
+        if read_only::is_enabled() && read_only::intercepts(method, &params) {
+            let mempool_check = match method {
+                "sendrawtransaction" => Some(self.request("testmempoolaccept", json!([[params[0].clone()]]))?),
+                _ => None,
+            };
+            return read_only::simulate(method, &params, mempool_check.as_ref());
+        }
+        self.request(method, params)
+    }
+
+    /// `call_rpc` with the result deserialized into `T`
+    pub fn call_typed<T: DeserializeOwned>(&self, method: &str, params: serde_json::Value) -> Result<T, Box<dyn std::error::Error>> {
+        let result = self.call_rpc(method, params)?;
+        serde_json::from_value(result).map_err(|e| format!("unexpected {} result: {}", method, e).into())
+    }
+
+    fn request(&self, method: &str, params: serde_json::Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let req = json!({
+            "jsonrpc": "1.0",
+            "id": "rust",
+            "method": method,
+            "params": params,
+        });
+        let resp = self.client.post(&self.url)
+            .header("Authorization", format!("Basic {}", self.auth))
+            .json(&req)
+            .send()?;
+        let resp_json: Value = resp.json()?;
+        if resp_json["error"].is_null() {
+            Ok(resp_json["result"].clone())
+        } else {
+            Err(RpcError::from_json(&resp_json["error"]).into())
+        }
+    }
+    pub fn get_new_address(&self) -> Result<String, Box<dyn std::error::Error>> {
+        self.call_typed("getnewaddress", json!([]))
+    }
+    pub fn send_to_address(&self, address: &str, amount: Amount) -> Result<String, Box<dyn std::error::Error>> {
+        self.call_typed("sendtoaddress", json!([address, amount::to_rpc(amount)]))
+    }
+    pub fn generate_to_address(&self, blocks: u32, address: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.call_typed("generatetoaddress", json!([blocks, address]))
+    }
+    pub fn get_balance(&self) -> Result<Amount, Box<dyn std::error::Error>> {
+        amount::from_rpc(&self.call_rpc("getbalance", json!([]))?)
+    }
+    pub fn create_raw_transaction(&self, inputs: Vec<Value>, outputs: HashMap<String, Amount>) -> Result<String, Box<dyn std::error::Error>> {
+        let outputs: HashMap<String, Value> = outputs.into_iter().map(|(address, value)| (address, amount::to_rpc(value))).collect();
+        self.call_typed("createrawtransaction", json!([inputs, outputs]))
+    }
+    pub fn send_raw_transaction(&self, hex: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.call_typed("sendrawtransaction", json!([hex]))
+    }
+    pub fn create_wallet(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let result = self.call_rpc("createwallet", json!([name, false, false, "", false, true, true]));
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) if CoreError::of(&*e) == Some(CoreError::WalletExists) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+    /// Top the wallet's keypool up to at least `size` keys; returns the resulting keypool size
+    pub fn ensure_keypool(&self, size: u64) -> Result<u64, Box<dyn std::error::Error>> {
+        let keypool_size = |info: Value| info["keypoolsize"].as_u64().ok_or("getwalletinfo returned no keypoolsize");
+        let current = keypool_size(self.call_rpc("getwalletinfo", json!([]))?)?;
+        if current >= size {
+            return Ok(current);
+        }
+        self.call_rpc("keypoolrefill", json!([size]))?;
+        Ok(keypool_size(self.call_rpc("getwalletinfo", json!([]))?)?)
+    }
+    pub fn new_labeled_address(&self, label: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let addr = self.call_rpc("getnewaddress", json!([label]))?;
+        Ok(addr.as_str().ok_or("getnewaddress returned no address")?.to_string())
+    }
+    /// Load wallet `name`; a wallet that is already loaded is fine
+    pub fn load_wallet(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self.call_rpc("loadwallet", json!([name])) {
+            Ok(_) => Ok(()),
+            Err(e) if CoreError::of(&*e) == Some(CoreError::WalletAlreadyLoaded) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl BlockingChainBackend for BitcoinRpcBlocking {
+    fn network(&self) -> Network {
+        self.network
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.url
+    }
+
+    /// From the UTXO set, so confirmed outputs only
+    fn utxos(&self, script_pubkey: &Script) -> Result<Vec<Utxo>, Box<dyn std::error::Error>> {
+        let scan: ScanTxOutSetResult = self.call_typed("scantxoutset", json!(["start", [format!("raw({})", script_pubkey.to_hex_string())]]))?;
+        Ok(scan.utxos())
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid, Box<dyn std::error::Error>> {
+        Ok(Txid::from_str(&self.send_raw_transaction(&serialize_hex(tx))?)?)
+    }
+
+    fn fee_rate(&self, conf_target: u16) -> Result<Option<FeeRate>, Box<dyn std::error::Error>> {
+        let estimate: EstimateSmartFeeResult = self.call_typed("estimatesmartfee", json!([conf_target]))?;
+        Ok(estimate.fee_rate.map(fees::from_btc_per_kvb))
+    }
+
+    fn relay_floor(&self, configured: FeeRate) -> Result<RelayFloor, Box<dyn std::error::Error>> {
+        let info: MempoolInfo = self.call_typed("getmempoolinfo", json!([]))?;
+        Ok(RelayFloor {
+            min_relay: fees::from_btc_per_kvb(info.min_relay_tx_fee),
+            mempool_min: fees::from_btc_per_kvb(info.mempool_min_fee),
+            configured,
+        })
+    }
+
+    fn tip_height(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        self.call_typed("getblockcount", json!([]))
+    }
+
+    fn chain_tip(&self) -> Result<ChainTip, Box<dyn std::error::Error>> {
+        let info: BlockchainInfo = self.call_typed("getblockchaininfo", json!([]))?;
+        Ok(ChainTip { height: info.blocks, median_time_past: info.median_time })
+    }
+
+    fn block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn std::error::Error>> {
+        self.call_typed("getblockhash", json!([height]))
+    }
+
+    fn block(&self, hash: &BlockHash, prevouts: bool) -> Result<Value, Box<dyn std::error::Error>> {
+        self.call_rpc("getblock", json!([hash.to_string(), if prevouts { 3 } else { 2 }]))
+    }
+
+    fn tx_status(&self, txid: &Txid) -> Result<Option<TxStatus>, Box<dyn std::error::Error>> {
+        let raw: GetRawTransactionResult = match self.call_typed("getrawtransaction", json!([txid.to_string(), true])) {
+            Ok(raw) => raw,
+            Err(e) if CoreError::of(&*e) == Some(CoreError::NotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let confirmations = raw.confirmations.unwrap_or(0);
+        let block = match raw.block_hash {
+            Some(hash) if confirmations > 0 => Some((hash, confirmed_height(txid, confirmations, self.tip_height()?)?)),
+            _ => None,
+        };
+        Ok(Some(TxStatus { confirmations, block }))
+    }
+
+    fn transaction(&self, txid: &Txid) -> Result<Transaction, Box<dyn std::error::Error>> {
+        let raw: GetRawTransactionResult = self.call_typed("getrawtransaction", json!([txid.to_string(), true]))?;
+        raw.transaction()
+    }
+}
@@ -0,0 +1,237 @@
+//! Broadcaster with a holding area for transactions that are not final yet.
+//!
+//! Transactions whose nLockTime is still in the future (CLTV spends) are held locally and submitted
+//! the moment they can enter the mempool, i.e. when the next block's height (or the tip's
+//! median-time-past, for time locks) passes the locktime. Relative (BIP68) locks are detected from
+//! the node's `non-BIP68-final` rejection and retried on later polls.
+//!
+//! Security-critical transactions (clawbacks, justice transactions) that must confirm before a
+//! CSV window closes skip the queue: `broadcast_priority` re-signs them at an aggressive fee rate,
+//! up to `PriorityPolicy::cap`, and submits them to every given node at once.
+
+use crate::backend::ChainBackend;
+use crate::fees::{self, RelayFloor};
+use crate::locktime::LOCKTIME_THRESHOLD;
+use crate::state_file;
+use crate::test_setup::CoreError;
+use bitcoin::consensus::encode::{deserialize, serialize_hex};
+use bitcoin::{Amount, FeeRate, Transaction, Txid};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HoldStatus {
+    /// Not final yet; `reason` describes what is being waited on
+    Waiting { reason: String },
+    Broadcast { txid: String },
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct HeldTx {
+    pub tx: Transaction,
+    pub status: HoldStatus,
+}
+
+/// Chain state the finality rules are evaluated against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChainTip {
+    pub height: u64,
+    pub median_time_past: u64,
+}
+
+impl ChainTip {
+    pub async fn fetch(backend: &impl ChainBackend) -> Result<Self, Box<dyn std::error::Error>> {
+        backend.chain_tip().await
+    }
+}
+
+/// Consensus `IsFinalTx` evaluated for inclusion in the next block, as the mempool does
+pub fn is_final(tx: &Transaction, tip: ChainTip) -> bool {
+    let lock_time = tx.lock_time.to_consensus_u32();
+    if lock_time == 0 {
+        return true;
+    }
+    let limit = if lock_time < LOCKTIME_THRESHOLD { tip.height + 1 } else { tip.median_time_past };
+    if (lock_time as u64) < limit {
+        return true;
+    }
+    tx.input.iter().all(|i| i.sequence.is_final())
+}
+
+#[derive(Default)]
+pub struct HoldingQueue {
+    pub entries: BTreeMap<Txid, HeldTx>,
+}
+
+impl HoldingQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a fully signed transaction; it is submitted on the first poll where it is final
+    pub fn hold(&mut self, tx: Transaction) -> Txid {
+        let txid = tx.txid();
+        let reason = format!("nLockTime {}", tx.lock_time.to_consensus_u32());
+        self.entries.insert(txid, HeldTx { tx, status: HoldStatus::Waiting { reason } });
+        txid
+    }
+
+    pub fn status(&self, txid: &Txid) -> Option<&HoldStatus> {
+        self.entries.get(txid).map(|e| &e.status)
+    }
+
+    /// Write the transactions still waiting to `path` (JSON array of raw hex) and return how many
+    pub fn save(&self, path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+        let waiting: Vec<String> = self.entries.values()
+            .filter(|e| matches!(e.status, HoldStatus::Waiting { .. }))
+            .map(|e| serialize_hex(&e.tx))
+            .collect();
+        state_file::save_json(path, &waiting)?;
+        Ok(waiting.len())
+    }
+
+    /// Queue restored from `save`; empty if the file does not exist
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut queue = Self::new();
+        let waiting: Vec<String> = state_file::load_json(path)?.unwrap_or_default();
+        for raw in waiting {
+            queue.hold(deserialize(&hex::decode(raw)?)?);
+        }
+        Ok(queue)
+    }
+
+    pub fn pending(&self) -> usize {
+        self.entries.values().filter(|e| matches!(e.status, HoldStatus::Waiting { .. })).count()
+    }
+
+    /// Check every waiting transaction against the current tip and broadcast the ones that became final.
+    /// Returns the txids broadcast on this poll.
+    pub async fn poll(&mut self, backend: &impl ChainBackend) -> Result<Vec<Txid>, Box<dyn std::error::Error>> {
+        let tip = ChainTip::fetch(backend).await?;
+        let mut broadcast = Vec::new();
+        for (txid, entry) in self.entries.iter_mut() {
+            if !matches!(entry.status, HoldStatus::Waiting { .. }) {
+                continue;
+            }
+            if !is_final(&entry.tx, tip) {
+                entry.status = HoldStatus::Waiting {
+                    reason: format!("nLockTime {} not reached at height {} / MTP {}", entry.tx.lock_time.to_consensus_u32(), tip.height, tip.median_time_past),
+                };
+                continue;
+            }
+            match backend.broadcast(&entry.tx).await {
+                Ok(sent) => {
+                    entry.status = HoldStatus::Broadcast { txid: sent.to_string() };
+                    broadcast.push(*txid);
+                }
+                Err(e) if CoreError::of(&*e) == Some(CoreError::NonFinal) => {
+                    entry.status = HoldStatus::Waiting { reason: "relative locktime (BIP68) not reached".to_string() };
+                }
+                Err(e) => entry.status = HoldStatus::Failed { error: e.to_string() },
+            }
+        }
+        Ok(broadcast)
+    }
+
+    /// Poll every `interval` until nothing is left waiting
+    pub async fn run(&mut self, backend: &impl ChainBackend, interval: Duration) -> Result<(), Box<dyn std::error::Error>> {
+        while self.pending() > 0 {
+            self.poll(backend).await?;
+            if self.pending() > 0 {
+                tokio::time::sleep(interval).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fee policy of the priority lane
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriorityPolicy {
+    /// Percentage applied to the estimate for the blocks left before the deadline
+    pub boost_percent: u64,
+    /// Never pay more than this rate
+    pub cap: FeeRate,
+}
+
+impl Default for PriorityPolicy {
+    fn default() -> Self {
+        Self { boost_percent: 200, cap: FeeRate::from_sat_per_vb_unchecked(500) }
+    }
+}
+
+impl PriorityPolicy {
+    /// Boosted `estimate`, at least `floor` and at most the cap. Errors if the floor itself is
+    /// above the cap, since such a transaction would not even be relayed.
+    pub fn rate(&self, estimate: FeeRate, floor: FeeRate) -> Result<FeeRate, Box<dyn std::error::Error>> {
+        if floor > self.cap {
+            return Err(format!("relay floor {} is above the priority cap {}", floor, self.cap).into());
+        }
+        let boosted = FeeRate::from_sat_per_kwu(estimate.to_sat_per_kwu().saturating_mul(self.boost_percent) / 100);
+        Ok(boosted.max(floor).min(self.cap))
+    }
+}
+
+/// Result of a priority broadcast
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriorityOutcome {
+    pub txid: Txid,
+    pub fee_rate: FeeRate,
+    pub fee: Amount,
+    /// Blocks left before `deadline_height` when it was sent
+    pub blocks_left: u64,
+    /// Endpoints of the backends that accepted (or already had) the transaction
+    pub accepted_by: Vec<String>,
+    /// Endpoint and error of every backend that rejected it
+    pub rejected_by: Vec<(String, String)>,
+}
+
+/// Sign with `build` at the priority rate and submit to all of `nodes` concurrently, bypassing
+/// the holding queue. `build` returns the signed transaction and the fee it pays at the given
+/// rate; `deadline_height` is the last height the transaction can confirm at (the end of the CSV
+/// window). Succeeds if at least one node accepts it.
+pub async fn broadcast_priority<B: ChainBackend, F>(nodes: &[B], deadline_height: u64, policy: &PriorityPolicy, build: F) -> Result<PriorityOutcome, Box<dyn std::error::Error>>
+where
+    F: Fn(FeeRate) -> Result<(Transaction, Amount), Box<dyn std::error::Error>>,
+{
+    let primary = nodes.first().ok_or("no node to broadcast to")?;
+    let tip = primary.tip_height().await?;
+    let blocks_left = deadline_height.saturating_sub(tip);
+    if blocks_left == 0 {
+        return Err(format!("deadline height {} already reached at tip {}", deadline_height, tip).into());
+    }
+    let conf_target = blocks_left.clamp(1, 1008) as u16;
+    let estimate = fees::estimate_fee_rate(primary, conf_target).await?;
+    let floor = RelayFloor::fetch(primary, fees::min_relay_rate_from_env()?).await?;
+    let fee_rate = policy.rate(estimate, floor.rate())?;
+    broadcast_everywhere(nodes, fee_rate, blocks_left, build).await
+}
+
+/// Sign with `build` at exactly `fee_rate` and submit to all of `nodes` concurrently; used by
+/// `broadcast_priority` and to re-submit a replacement at an escalated rate
+pub async fn broadcast_everywhere<B: ChainBackend, F>(nodes: &[B], fee_rate: FeeRate, blocks_left: u64, build: F) -> Result<PriorityOutcome, Box<dyn std::error::Error>>
+where
+    F: Fn(FeeRate) -> Result<(Transaction, Amount), Box<dyn std::error::Error>>,
+{
+    let primary = nodes.first().ok_or("no node to broadcast to")?;
+    let floor = RelayFloor::fetch(primary, fees::min_relay_rate_from_env()?).await?;
+    let (tx, fee) = build(fee_rate)?;
+    floor.check(&tx, fee)?;
+    let results = futures::future::join_all(nodes.iter().map(|node| node.broadcast(&tx))).await;
+
+    let mut outcome = PriorityOutcome { txid: tx.txid(), fee_rate, fee, blocks_left, accepted_by: Vec::new(), rejected_by: Vec::new() };
+    for (node, result) in nodes.iter().zip(results) {
+        match result {
+            Ok(_) => outcome.accepted_by.push(node.endpoint().to_string()),
+            Err(e) if CoreError::of(&*e) == Some(CoreError::AlreadyKnown) => outcome.accepted_by.push(node.endpoint().to_string()),
+            Err(e) => outcome.rejected_by.push((node.endpoint().to_string(), e.to_string())),
+        }
+    }
+    if outcome.accepted_by.is_empty() {
+        let errors: Vec<String> = outcome.rejected_by.iter().map(|(url, e)| format!("{}: {}", url, e)).collect();
+        return Err(format!("no node accepted {}: {}", outcome.txid, errors.join("; ")).into());
+    }
+    Ok(outcome)
+}
@@ -0,0 +1,62 @@
+//! Cancellation and per-operation deadlines for the async flows.
+//!
+//! Long-running loops (scanner, broadcaster, coordinator) take a `CancellationToken` and wrap each
+//! unit of work in `guarded`, so a shutdown request or a stuck RPC interrupts the current operation
+//! instead of waiting for the whole loop to drain.
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+pub use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Interrupted {
+    Cancelled { operation: String },
+    TimedOut { operation: String, after: Duration },
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interrupted::Cancelled { operation } => write!(f, "{} cancelled", operation),
+            Interrupted::TimedOut { operation, after } => write!(f, "{} timed out after {:?}", operation, after),
+        }
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Deadlines for the individual operations of each actor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OperationTimeouts {
+    /// Fetching and classifying one block
+    pub scan_block: Duration,
+    /// One poll of the holding queue (tip lookup plus broadcasts)
+    pub broadcast_poll: Duration,
+}
+
+impl Default for OperationTimeouts {
+    fn default() -> Self {
+        Self { scan_block: Duration::from_secs(30), broadcast_poll: Duration::from_secs(30) }
+    }
+}
+
+/// Run `fut` unless `token` is cancelled first or `deadline` passes
+pub async fn guarded<T, F>(token: &CancellationToken, operation: &str, deadline: Duration, fut: F) -> Result<T, Box<dyn std::error::Error>>
+where
+    F: Future<Output = Result<T, Box<dyn std::error::Error>>>,
+{
+    tokio::select! {
+        biased;
+        _ = token.cancelled() => Err(Interrupted::Cancelled { operation: operation.to_string() }.into()),
+        result = tokio::time::timeout(deadline, fut) => match result {
+            Ok(inner) => inner,
+            Err(_) => Err(Interrupted::TimedOut { operation: operation.to_string(), after: deadline }.into()),
+        },
+    }
+}
+
+/// The `Interrupted` cause of an error returned by `guarded`, if that is why it failed
+pub fn interruption<'a>(error: &'a (dyn std::error::Error + 'static)) -> Option<&'a Interrupted> {
+    error.downcast_ref::<Interrupted>()
+}
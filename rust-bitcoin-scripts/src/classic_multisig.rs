@@ -1,51 +1,119 @@
-use miniscript::{Descriptor, bitcoin::{Network, PrivateKey, secp256k1, PublicKey}};
-use rand::RngCore;
+use crate::migration::NUMS_INTERNAL_KEY;
+use crate::secret::SigningKey;
+use crate::verify::verify_input;
+use miniscript::{Descriptor, Legacy, Miniscript, MiniscriptKey, Terminal, bitcoin::{Network, PublicKey}};
+use bitcoin::ecdsa::Signature;
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::secp256k1::{Message, Secp256k1, Signing};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{PrivateKey, Script, ScriptBuf, Transaction, TxOut};
+use std::collections::HashMap;
 use std::str::FromStr;
 
-fn random_secret_key() -> secp256k1::SecretKey {
-    let mut data = [0u8; 32]; //bytearray of length 32
-    rand::thread_rng().fill_bytes(&mut data);
-    secp256k1::SecretKey::from_slice(&data).unwrap()
-}
-
+#[derive(Debug)]
 pub struct MultisigInfo {
     pub address: String,
     pub descriptor: String,
-    pub private_keys: Vec<PrivateKey>, //for testing only
+    pub private_keys: Vec<SigningKey>, //for testing only
     pub public_keys: Vec<PublicKey>,
 }
 
-pub fn run() -> Result<(), Box<dyn std::error::Error>> {
-    let info = create_multisig()?;
+pub fn run(network: Network) -> Result<(), Box<dyn std::error::Error>> {
+    let info = create_multisig_on(network)?;
     println!("Classic 2-of-3 P2SH multisig example");
     println!("Descriptor: {}", info.descriptor);
-    println!("Regtest Address: {}", info.address);
+    println!("{} Address: {}", network, info.address);
     Ok(())
 }
 
 pub fn create_multisig() -> Result<MultisigInfo, Box<dyn std::error::Error>> {
-    let secp = secp256k1::Secp256k1::new();
-    let key1 = random_secret_key();
-    let key2 = random_secret_key();
-    let key3 = random_secret_key();
-    let privkey1 = PrivateKey::new(key1, Network::Regtest);
-    let privkey2 = PrivateKey::new(key2, Network::Regtest);
-    let privkey3 = PrivateKey::new(key3, Network::Regtest);
-    let pubkey1 = PublicKey::from_private_key(&secp, &privkey1);
-    let pubkey2 = PublicKey::from_private_key(&secp, &privkey2);
-    let pubkey3 = PublicKey::from_private_key(&secp, &privkey3);
-    let descriptor_str = format!("sh(multi(2,{},{},{}))", pubkey1, pubkey2, pubkey3);
-    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&descriptor_str)?;
-    let address = descriptor.address(Network::Regtest)?;
+    create_multisig_on(Network::Regtest)
+}
+
+/// Random 2-of-3 P2SH multisig with keys and address for `network`
+pub fn create_multisig_on(network: Network) -> Result<MultisigInfo, Box<dyn std::error::Error>> {
+    let privkey1 = SigningKey::random(network);
+    let privkey2 = SigningKey::random(network);
+    let privkey3 = SigningKey::random(network);
+    let pubkey1 = privkey1.public_key;
+    let pubkey2 = privkey2.public_key;
+    let pubkey3 = privkey3.public_key;
+    let multisig = create_multisig_with_keys_on(2, &[pubkey1, pubkey2, pubkey3], MultisigKind::Sh, network)?;
     
     Ok(MultisigInfo {
-        address: address.to_string(),
-        descriptor: descriptor_str,
+        address: multisig.address,
+        descriptor: multisig.descriptor.to_string().split('#').next().unwrap_or_default().to_string(),
         private_keys: vec![privkey1, privkey2, privkey3],
         public_keys: vec![pubkey1, pubkey2, pubkey3],
     })
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultisigKind {
+    /// `sh(multi(...))`, at most 15 keys
+    Sh,
+    /// `wsh(multi(...))`, at most 20 keys
+    Wsh,
+    /// `sh(wsh(multi(...)))`, P2WSH nested in P2SH for senders without native segwit
+    ShWsh,
+    /// `tr(NUMS,multi_a(...))`: a single script leaf under an unspendable internal key
+    TrMultiA,
+}
+
+/// An m-of-n multisig for given keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultisigScript {
+    pub kind: MultisigKind,
+    pub address: String,
+    pub descriptor: Descriptor<PublicKey>,
+    /// P2SH redeemScript (`Sh`, `ShWsh`)
+    pub redeem_script: Option<ScriptBuf>,
+    /// P2WSH witnessScript (`Wsh`, `ShWsh`), or the leaf script for `TrMultiA`
+    pub witness_script: Option<ScriptBuf>,
+}
+
+/// `m`-of-`keys` multisig of the given kind, with a regtest address
+pub fn create_multisig_with_keys(m: usize, keys: &[PublicKey], kind: MultisigKind) -> Result<MultisigScript, Box<dyn std::error::Error>> {
+    create_multisig_with_keys_on(m, keys, kind, Network::Regtest)
+}
+
+/// `create_multisig_with_keys` with the address for `network`
+pub fn create_multisig_with_keys_on(m: usize, keys: &[PublicKey], kind: MultisigKind, network: Network) -> Result<MultisigScript, Box<dyn std::error::Error>> {
+    let descriptor = multisig_descriptor(m, keys, kind)?;
+    let (redeem_script, witness_script) = match (&kind, &descriptor) {
+        (MultisigKind::Sh, _) => (Some(descriptor.explicit_script()?), None),
+        (MultisigKind::Wsh, _) => (None, Some(descriptor.explicit_script()?)),
+        (MultisigKind::ShWsh, _) => {
+            let witness_script = descriptor.explicit_script()?;
+            (Some(witness_script.to_v0_p2wsh()), Some(witness_script))
+        }
+        (MultisigKind::TrMultiA, Descriptor::Tr(tr)) => (None, tr.iter_scripts().next().map(|(_, ms)| ms.encode())),
+        (MultisigKind::TrMultiA, _) => unreachable!("tr(...) parses to a taproot descriptor"),
+    };
+    Ok(MultisigScript { kind, address: descriptor.address(network)?.to_string(), descriptor, redeem_script, witness_script })
+}
+
+/// `m`-of-`keys` descriptor of the given kind, over bare keys or descriptor keys with origin
+/// (`hd::HdWallet::descriptor_key`)
+pub fn multisig_descriptor<Pk>(m: usize, keys: &[Pk], kind: MultisigKind) -> Result<Descriptor<Pk>, Box<dyn std::error::Error>>
+where
+    Pk: MiniscriptKey,
+    Descriptor<Pk>: FromStr<Err = miniscript::Error>,
+{
+    if m == 0 || m > keys.len() {
+        return Err(format!("{}-of-{} multisig", m, keys.len()).into());
+    }
+    let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+    let multi = format!("{},{}", m, keys.join(","));
+    let descriptor_str = match kind {
+        MultisigKind::Sh => format!("sh(multi({}))", multi),
+        MultisigKind::Wsh => format!("wsh(multi({}))", multi),
+        MultisigKind::ShWsh => format!("sh(wsh(multi({})))", multi),
+        MultisigKind::TrMultiA => format!("tr({},multi_a({}))", NUMS_INTERNAL_KEY, multi),
+    };
+    Ok(Descriptor::from_str(&descriptor_str)?)
+}
+
 pub fn create_redeem_script(public_keys: &[PublicKey]) -> bitcoin::ScriptBuf {
     let mut builder = bitcoin::script::Builder::new();
     builder = builder.push_int(2);
@@ -55,4 +123,60 @@ pub fn create_redeem_script(public_keys: &[PublicKey]) -> bitcoin::ScriptBuf {
     builder = builder.push_int(3);
     builder = builder.push_opcode(bitcoin::opcodes::all::OP_CHECKMULTISIG);
     builder.into_script()
-}
\ No newline at end of file
+}
+
+/// `m` and the keys of a bare `multi` redeem script, in script order
+pub fn parse_multisig_redeem_script(redeem_script: &Script) -> Result<(usize, Vec<PublicKey>), Box<dyn std::error::Error>> {
+    let ms = Miniscript::<PublicKey, Legacy>::parse(redeem_script)?;
+    match &ms.node {
+        Terminal::Multi(m, keys) => Ok((*m, keys.clone())),
+        _ => Err(format!("not a multisig redeem script: {}", ms).into()),
+    }
+}
+
+/// Pre-segwit SIGHASH_ALL signature of input `input_index` of `tx`, which spends a P2SH output
+/// with `redeem_script`. The legacy sighash commits to the redeem script, not the amount.
+pub fn sign_legacy_p2sh_input<C: Signing>(secp: &Secp256k1<C>, tx: &Transaction, input_index: usize, redeem_script: &Script, key: &PrivateKey) -> Result<Signature, Box<dyn std::error::Error>> {
+    let sighash = SighashCache::new(tx).legacy_signature_hash(input_index, redeem_script, EcdsaSighashType::All.to_u32())?;
+    let sig = secp.sign_ecdsa(&Message::from_slice(&sighash[..])?, &key.inner);
+    Ok(Signature::sighash_all(sig))
+}
+
+/// `OP_0 <sig_1> .. <sig_m> <redeem_script>`: the leading `OP_0` is the CHECKMULTISIG dummy and
+/// signatures follow the order of their keys in the redeem script. Exactly `m` signatures from
+/// the script's keys are required; extras are ignored in key order.
+pub fn build_p2sh_multisig_script_sig(redeem_script: &Script, sigs: &HashMap<PublicKey, Signature>) -> Result<ScriptBuf, Box<dyn std::error::Error>> {
+    let (m, keys) = parse_multisig_redeem_script(redeem_script)?;
+    let ordered: Vec<&Signature> = keys.iter().filter_map(|key| sigs.get(key)).take(m).collect();
+    if ordered.len() < m {
+        return Err(format!("need {} multisig signatures, have {}", m, ordered.len()).into());
+    }
+    let mut builder = Builder::new().push_int(0);
+    for sig in ordered {
+        builder = builder.push_slice(PushBytesBuf::try_from(sig.to_vec())?);
+    }
+    Ok(builder.push_slice(PushBytesBuf::try_from(redeem_script.to_bytes())?).into_script())
+}
+
+/// Sign input `input_index` of `tx` with `keys` and set its scriptSig, all locally: no wallet
+/// or `signrawtransactionwithkey` is involved. Keys not in the redeem script are an error, and
+/// the scriptSig is only set once `verify::verify_input` accepts it.
+pub fn sign_p2sh_multisig<C: Signing>(secp: &Secp256k1<C>, tx: &mut Transaction, input_index: usize, redeem_script: &Script, keys: &[PrivateKey]) -> Result<(), Box<dyn std::error::Error>> {
+    let (_, script_keys) = parse_multisig_redeem_script(redeem_script)?;
+    let mut sigs = HashMap::new();
+    for key in keys {
+        let public = key.public_key(secp);
+        if !script_keys.contains(&public) {
+            return Err(format!("{} is not a key of the redeem script", public).into());
+        }
+        sigs.insert(public, sign_legacy_p2sh_input(secp, tx, input_index, redeem_script, key)?);
+    }
+    let mut signed = tx.clone();
+    signed.input.get_mut(input_index).ok_or_else(|| format!("no input {}", input_index))?.script_sig = build_p2sh_multisig_script_sig(redeem_script, &sigs)?;
+    // Legacy signatures do not commit to the amount, so the spent output's script is all the
+    // interpreter needs
+    let prevout = TxOut { value: 0, script_pubkey: ScriptBuf::new_p2sh(&redeem_script.script_hash()) };
+    verify_input(&signed, input_index, &prevout)?;
+    *tx = signed;
+    Ok(())
+}
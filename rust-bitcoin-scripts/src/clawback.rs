@@ -0,0 +1,145 @@
+//! Fee escalation and alerting for a broadcast clawback until it confirms.
+//!
+//! A clawback must confirm before the adversary's timelocked path matures (`deadline_height`). If
+//! blocks keep arriving without it (a congested mempool, or miners censoring it), `ClawbackWatch`
+//! replaces it every `escalate_every` blocks at a higher rate, jumps straight to the cap once only
+//! `alert_margin` blocks are left, and from then on raises a `ClawbackStuck` alert on every poll.
+//! Replacements rely on the inputs signalling RBF, as `psbt::SpendableUtxo` does by default.
+
+use crate::broadcast::{self, PriorityOutcome, PriorityPolicy};
+use crate::test_setup::BitcoinRPC;
+use crate::webhooks::LifecycleEvent;
+use bitcoin::{Amount, FeeRate, Transaction, Txid};
+
+/// BIP125 replacements must pay at least the incremental relay fee (1 sat/vB by default) more
+const MIN_BUMP: FeeRate = FeeRate::from_sat_per_vb_unchecked(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EscalationPolicy {
+    /// Rate of the first broadcast, boosted from the estimate, and the cap for every replacement
+    pub priority: PriorityPolicy,
+    /// Replace after this many blocks without confirmation
+    pub escalate_every: u64,
+    /// Rate increase per replacement
+    pub step_percent: u64,
+    /// With this many blocks or fewer left, go to the cap and alert
+    pub alert_margin: u64,
+}
+
+impl Default for EscalationPolicy {
+    fn default() -> Self {
+        Self { priority: PriorityPolicy::default(), escalate_every: 1, step_percent: 50, alert_margin: 3 }
+    }
+}
+
+/// What to do at a tip while the clawback is unconfirmed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Escalation {
+    /// Rate to replace the clawback at, if it should be replaced now
+    pub bump_to: Option<FeeRate>,
+    pub alert: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClawbackStatus {
+    Confirmed { txid: Txid, confirmations: u32 },
+    Pending { txid: Txid, blocks_left: u64 },
+    /// Replaced at a higher rate on this poll
+    Bumped { replaced: Txid, txid: Txid, fee_rate: FeeRate },
+}
+
+/// A broadcast clawback being watched until it confirms
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClawbackWatch {
+    pub deadline_height: u64,
+    pub policy: EscalationPolicy,
+    /// Current version of the clawback
+    pub txid: Txid,
+    pub fee_rate: FeeRate,
+    pub fee: Amount,
+    /// Tip when the clawback was first broadcast
+    pub broadcast_height: u64,
+    /// Tip when the current version was broadcast
+    pub last_bump_height: u64,
+    /// Earlier versions, any of which may still be the one that confirms
+    pub replaced: Vec<Txid>,
+}
+
+impl ClawbackWatch {
+    /// Broadcast the clawback through the priority lane and start watching it
+    pub async fn start<F>(nodes: &[BitcoinRPC], deadline_height: u64, policy: EscalationPolicy, build: F) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        F: Fn(FeeRate) -> Result<(Transaction, Amount), Box<dyn std::error::Error>>,
+    {
+        let outcome = broadcast::broadcast_priority(nodes, deadline_height, &policy.priority, build).await?;
+        let tip = deadline_height - outcome.blocks_left;
+        Ok(Self::from_outcome(&outcome, deadline_height, policy, tip))
+    }
+
+    pub fn from_outcome(outcome: &PriorityOutcome, deadline_height: u64, policy: EscalationPolicy, tip: u64) -> Self {
+        Self {
+            deadline_height,
+            policy,
+            txid: outcome.txid,
+            fee_rate: outcome.fee_rate,
+            fee: outcome.fee,
+            broadcast_height: tip,
+            last_bump_height: tip,
+            replaced: Vec::new(),
+        }
+    }
+
+    /// Decision at `tip` for a clawback that is not confirmed yet
+    pub fn escalation(&self, tip: u64) -> Escalation {
+        let blocks_left = self.deadline_height.saturating_sub(tip);
+        let cap = self.policy.priority.cap;
+        let alert = blocks_left <= self.policy.alert_margin;
+        let due = alert || tip >= self.last_bump_height + self.policy.escalate_every;
+        if !due || self.fee_rate >= cap {
+            return Escalation { bump_to: None, alert };
+        }
+        let stepped = FeeRate::from_sat_per_kwu(self.fee_rate.to_sat_per_kwu().saturating_mul(100 + self.policy.step_percent) / 100);
+        let bumped = FeeRate::from_sat_per_kwu(self.fee_rate.to_sat_per_kwu() + MIN_BUMP.to_sat_per_kwu()).max(stepped);
+        Escalation { bump_to: Some(if alert { cap } else { bumped.min(cap) }), alert }
+    }
+
+    /// Check for confirmation of any version, and replace or alert as `escalation` says.
+    /// `build` must produce the same spend as the one originally broadcast.
+    pub async fn poll<F>(&mut self, nodes: &[BitcoinRPC], build: F) -> Result<(ClawbackStatus, Vec<LifecycleEvent>), Box<dyn std::error::Error>>
+    where
+        F: Fn(FeeRate) -> Result<(Transaction, Amount), Box<dyn std::error::Error>>,
+    {
+        let primary = nodes.first().ok_or("no node to poll")?;
+        for txid in std::iter::once(&self.txid).chain(&self.replaced) {
+            if let Ok(raw) = primary.get_raw_transaction_verbose(txid).await {
+                if let Some(confirmations) = raw.confirmations.filter(|c| *c > 0) {
+                    return Ok((ClawbackStatus::Confirmed { txid: *txid, confirmations }, Vec::new()));
+                }
+            }
+        }
+
+        let tip = primary.get_block_count().await?;
+        let blocks_left = self.deadline_height.saturating_sub(tip);
+        let escalation = self.escalation(tip);
+        let mut status = ClawbackStatus::Pending { txid: self.txid, blocks_left };
+        if let Some(rate) = escalation.bump_to {
+            let outcome = broadcast::broadcast_everywhere(nodes, rate, blocks_left, build).await?;
+            status = ClawbackStatus::Bumped { replaced: self.txid, txid: outcome.txid, fee_rate: rate };
+            self.replaced.push(self.txid);
+            self.txid = outcome.txid;
+            self.fee_rate = rate;
+            self.fee = outcome.fee;
+            self.last_bump_height = tip;
+        }
+        let mut alerts = Vec::new();
+        if escalation.alert {
+            alerts.push(LifecycleEvent::ClawbackStuck {
+                txid: self.txid.to_string(),
+                blocks_pending: tip - self.broadcast_height,
+                blocks_left,
+                fee_rate_sat_vb: self.fee_rate.to_sat_per_vb_ceil(),
+            });
+        }
+        Ok((status, alerts))
+    }
+}
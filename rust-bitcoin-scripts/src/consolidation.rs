@@ -0,0 +1,236 @@
+//! Consolidation of vault UTXOs, so large peg-outs find a few big inputs instead of many small
+//! ones.
+//!
+//! `ConsolidationPolicy` says when: at least `min_utxos` spendable outputs, a fee rate at or
+//! below `max_fee_rate`, and at most `max_inputs` inputs per transaction, smallest first. Outputs
+//! worth less than the fee of spending them are left alone. `plan` applies the policy to the
+//! vault's UTXOs and builds the unsigned PSBT sweeping them back into the vault descriptor.
+//!
+//! `Consolidator` is the background job. Each `tick` either opens a consolidation or follows the
+//! one in flight, through the same path as any federation spend: the inputs are reserved for
+//! `CONSOLIDATION_OWNER`, a `SigningRound` is opened with the coordinator, signers submit to the
+//! coordinator as usual, and the completed round is finalized and handed to the broadcaster.
+//! Nothing new is planned until that transaction confirms, since the UTXO set still lists its
+//! inputs until then. A stalled round has its inputs released by the coordinator and is dropped;
+//! the next tick plans afresh. `run` ticks every interval until the shutdown token is cancelled.
+
+use crate::actors::{BroadcasterHandle, CoordinatorHandle};
+use crate::backend::ChainBackend;
+use crate::broadcast::HoldStatus;
+use crate::cancel::CancellationToken;
+use crate::fees::{self, FeePlan};
+use crate::locktime::{self, LockTimePolicy};
+use crate::psbt::{self, SpendableUtxo};
+use crate::reservation::UtxoReservations;
+use crate::rpc_types::Utxo;
+use crate::signing_round::{RoundState, SigningRound};
+use bitcoin::absolute::LockTime;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{Amount, FeeRate, OutPoint, PublicKey, TxOut, Txid};
+use log::warn;
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Reservation owner of the inputs of a consolidation in flight
+pub const CONSOLIDATION_OWNER: &str = "consolidation";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConsolidationPolicy {
+    /// Consolidate once the vault holds at least this many spendable outputs
+    pub min_utxos: usize,
+    /// Never consolidate above this rate; consolidations can always wait
+    pub max_fee_rate: FeeRate,
+    /// Inputs per consolidation transaction
+    pub max_inputs: usize,
+    /// Confirmations an output needs before it is consolidated
+    pub min_confirmations: u32,
+    /// Target the fee is estimated for
+    pub conf_target: u16,
+}
+
+impl Default for ConsolidationPolicy {
+    fn default() -> Self {
+        Self {
+            min_utxos: 20,
+            max_fee_rate: FeeRate::from_sat_per_vb_unchecked(5),
+            max_inputs: 50,
+            min_confirmations: 6,
+            conf_target: 144,
+        }
+    }
+}
+
+/// An unsigned consolidation, ready for a signing round
+#[derive(Debug, Clone, PartialEq)]
+pub struct Consolidation {
+    pub psbt: Psbt,
+    pub inputs: Vec<OutPoint>,
+    pub input_value: Amount,
+    pub fee: Amount,
+    pub fee_rate: FeeRate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// Nothing to do yet, and why
+    Wait(String),
+    Consolidate(Consolidation),
+}
+
+impl ConsolidationPolicy {
+    /// Consolidate the outputs of `utxos` locked by `descriptor` back into it at `fee_rate`, or
+    /// say why not. `utxos` should already exclude outputs reserved by other builders.
+    pub fn plan(&self, descriptor: &Descriptor<PublicKey>, utxos: &[Utxo], fee_rate: FeeRate, lock_time: LockTime) -> Result<Decision, Box<dyn std::error::Error>> {
+        if self.max_inputs < 2 {
+            return Err(format!("max_inputs {} cannot consolidate anything", self.max_inputs).into());
+        }
+        let script_pubkey = descriptor.script_pubkey();
+        let spendable: Vec<&Utxo> = utxos.iter().filter(|u| u.script_pubkey == script_pubkey && u.confirmations >= self.min_confirmations).collect();
+        if spendable.len() < self.min_utxos.max(2) {
+            return Ok(Decision::Wait(format!("{} spendable outputs, consolidating from {}", spendable.len(), self.min_utxos.max(2))));
+        }
+        if fee_rate > self.max_fee_rate {
+            return Ok(Decision::Wait(format!("fee rate {} is above the ceiling {}", fee_rate, self.max_fee_rate)));
+        }
+
+        // Spending an output costs this much; cheaper outputs would only shrink the vault
+        let input_fee = fees::fee_for(&[descriptor, descriptor], &[], fee_rate)? - fees::fee_for(&[descriptor], &[], fee_rate)?;
+        let mut candidates: Vec<&Utxo> = spendable.into_iter().filter(|u| u.amount > input_fee).collect();
+        candidates.sort_by_key(|u| (u.amount, u.outpoint));
+        candidates.truncate(self.max_inputs);
+        if candidates.len() < 2 {
+            return Ok(Decision::Wait(format!("fewer than 2 outputs are worth more than the {} sats it costs to spend them", input_fee.to_sat())));
+        }
+
+        let input_value: Amount = candidates.iter().map(|u| u.amount).sum();
+        let inputs: Vec<&Descriptor<PublicKey>> = vec![descriptor; candidates.len()];
+        let fee_plan = FeePlan::sweep(&inputs, input_value, script_pubkey.clone(), fee_rate)?;
+        let utxos: Vec<SpendableUtxo> = candidates.iter()
+            .map(|u| SpendableUtxo::new(u.outpoint, TxOut { value: u.amount.to_sat(), script_pubkey: u.script_pubkey.clone() }))
+            .collect();
+        let definite = Descriptor::<DefiniteDescriptorKey>::from_str(&descriptor.to_string())?;
+        Ok(Decision::Consolidate(Consolidation {
+            psbt: psbt::create(&definite, &utxos, fee_plan.outputs, lock_time)?,
+            inputs: candidates.iter().map(|u| u.outpoint).collect(),
+            input_value,
+            fee: fee_plan.fee,
+            fee_rate,
+        }))
+    }
+}
+
+/// What one `Consolidator::tick` did
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsolidationStep {
+    /// No consolidation in flight and none due
+    Idle { reason: String },
+    /// Opened signing round `round_id`; `psbt` is what the signers sign
+    Opened { round_id: String, psbt: Psbt, fee: Amount },
+    /// Round `round_id` is still collecting signatures
+    Signing { round_id: String },
+    /// Round `round_id` completed; the transaction is with the broadcaster
+    Broadcast { round_id: String, txid: Txid },
+    /// The last consolidation is not confirmed yet
+    Confirming { txid: Txid },
+    /// Round `round_id` stalled and was dropped; its inputs are free again
+    Stalled { round_id: String },
+}
+
+/// The consolidation job of one vault descriptor
+pub struct Consolidator {
+    pub policy: ConsolidationPolicy,
+    pub descriptor: Descriptor<PublicKey>,
+    /// Federation members asked to sign, and how many must
+    pub signers: Vec<String>,
+    pub quorum: usize,
+    pub round_timeout: Duration,
+    /// Signing round of the consolidation in flight
+    pub in_flight: Option<String>,
+    /// The last consolidation, until it confirms
+    pub confirming: Option<Txid>,
+}
+
+impl Consolidator {
+    pub fn new(policy: ConsolidationPolicy, descriptor: Descriptor<PublicKey>, signers: Vec<String>, quorum: usize, round_timeout: Duration) -> Self {
+        Self { policy, descriptor, signers, quorum, round_timeout, in_flight: None, confirming: None }
+    }
+
+    /// Follow the consolidation in flight, or open one if the policy says so
+    pub async fn tick(&mut self, backend: &impl ChainBackend, reservations: &UtxoReservations, coordinator: &CoordinatorHandle, broadcaster: &BroadcasterHandle) -> Result<ConsolidationStep, Box<dyn std::error::Error>> {
+        if let Some(round_id) = self.in_flight.clone() {
+            let state = coordinator.state(&round_id).await?;
+            return match state {
+                Some(RoundState::Collecting) => Ok(ConsolidationStep::Signing { round_id }),
+                Some(RoundState::Complete) => {
+                    let tx = psbt::finalize(coordinator.take(&round_id).await?)?;
+                    self.in_flight = None;
+                    let txid = broadcaster.hold(tx).await?;
+                    self.confirming = Some(txid);
+                    Ok(ConsolidationStep::Broadcast { round_id, txid })
+                }
+                Some(RoundState::Stalled) => {
+                    coordinator.take(&round_id).await?;
+                    self.in_flight = None;
+                    Ok(ConsolidationStep::Stalled { round_id })
+                }
+                None => {
+                    self.in_flight = None;
+                    Ok(ConsolidationStep::Idle { reason: format!("round {} is gone", round_id) })
+                }
+            };
+        }
+        if let Some(txid) = self.confirming {
+            let status = broadcaster.status(txid).await?;
+            match status {
+                Some(HoldStatus::Failed { error }) => {
+                    self.confirming = None;
+                    return Ok(ConsolidationStep::Idle { reason: format!("consolidation {} failed: {}", txid, error) });
+                }
+                Some(HoldStatus::Broadcast { .. }) => {
+                    let confirmations = backend.tx_status(&txid).await.ok().flatten().map_or(0, |status| status.confirmations);
+                    if confirmations == 0 {
+                        return Ok(ConsolidationStep::Confirming { txid });
+                    }
+                    self.confirming = None;
+                }
+                _ => return Ok(ConsolidationStep::Confirming { txid }),
+            }
+        }
+
+        let utxos: Vec<Utxo> = backend.descriptor_utxos(&self.descriptor).await?.into_iter()
+            .filter(|u| reservations.reserved_by(&u.outpoint).is_none())
+            .collect();
+        let fee_rate = fees::estimate_fee_rate(backend, self.policy.conf_target).await?;
+        let lock_time = locktime::reconcile(LockTime::ZERO, backend.tip_height().await? as u32, LockTimePolicy::default());
+        let consolidation = match self.policy.plan(&self.descriptor, &utxos, fee_rate, lock_time)? {
+            Decision::Wait(reason) => return Ok(ConsolidationStep::Idle { reason }),
+            Decision::Consolidate(consolidation) => consolidation,
+        };
+        reservations.reserve(CONSOLIDATION_OWNER, &consolidation.inputs, Some(self.round_timeout))?;
+        let round_id = format!("consolidation-{}", consolidation.psbt.unsigned_tx.txid());
+        let round = SigningRound::new(&round_id, consolidation.psbt.clone(), self.signers.clone(), self.quorum, self.round_timeout);
+        if let Err(e) = coordinator.open(round).await {
+            reservations.release(CONSOLIDATION_OWNER, &consolidation.inputs);
+            return Err(e);
+        }
+        self.in_flight = Some(round_id.clone());
+        Ok(ConsolidationStep::Opened { round_id, psbt: consolidation.psbt, fee: consolidation.fee })
+    }
+
+    /// Tick every `interval` until `shutdown` is cancelled
+    pub async fn run(mut self, backend: impl ChainBackend, reservations: UtxoReservations, coordinator: CoordinatorHandle, broadcaster: BroadcasterHandle, interval: Duration, shutdown: CancellationToken) {
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep(interval) => {}
+            }
+            let failed = self.tick(&backend, &reservations, &coordinator, &broadcaster).await.err().map(|e| e.to_string());
+            if let Some(e) = failed {
+                warn!("consolidation of {} failed: {}", self.descriptor, e);
+            }
+        }
+    }
+}
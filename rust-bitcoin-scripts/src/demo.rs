@@ -0,0 +1,131 @@
+//! End-to-end vault demo behind `wrapyield-cli vault demo`: create a vault with fresh keys, fund
+//! it from the node wallet, mine past its timelock if the chosen path has one, spend it and
+//! confirm the spend.
+//!
+//! The wsh paths use `timelock_csv::csv_vault_descriptor`: `backup` signs with the backup key
+//! right away, `multisig` with two of the three signers after `DEMO_CSV_DELAY` blocks. The
+//! `taproot-leaf` path uses the same policy as two leaves under `NUMS_INTERNAL_KEY` and spends
+//! the `multi_a` leaf. Planning, signing and broadcasting go through `flows`, so blocks are only
+//! mined on regtest.
+
+use crate::backend::WalletBackend;
+use crate::flows::{self, FundedUtxo, SpendResult};
+use crate::migration::NUMS_INTERNAL_KEY;
+use crate::secret::SigningKey;
+use crate::spend::SpendPlan;
+use crate::test_setup::DESTINATION_LABEL;
+use crate::timelock_csv::csv_vault_descriptor;
+use bitcoin::{Amount, Network, PrivateKey, PublicKey};
+use miniscript::Descriptor;
+use std::fmt;
+use std::str::FromStr;
+
+/// Relative delay of the multisig branch, in blocks
+pub const DEMO_CSV_DELAY: u16 = 10;
+/// Flat fee of the spend
+pub const DEMO_FEE: Amount = Amount::from_sat(1_000);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemoPath {
+    Backup,
+    Multisig,
+    TaprootLeaf,
+}
+
+impl FromStr for DemoPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "backup" => Ok(Self::Backup),
+            "multisig" => Ok(Self::Multisig),
+            "taproot-leaf" => Ok(Self::TaprootLeaf),
+            _ => Err(format!("unknown demo path {:?}, expected backup, multisig or taproot-leaf", s)),
+        }
+    }
+}
+
+impl fmt::Display for DemoPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Backup => write!(f, "backup"),
+            Self::Multisig => write!(f, "multisig"),
+            Self::TaprootLeaf => write!(f, "taproot-leaf"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemoReport {
+    pub path: DemoPath,
+    pub funded: FundedUtxo,
+    /// Blocks mined after funding so the path's timelock is satisfied
+    pub blocks_waited: u32,
+    pub spend: SpendResult,
+    /// Height of the block that confirmed the spend
+    pub spend_height: u64,
+}
+
+impl fmt::Display for DemoReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "path: {}", self.path)?;
+        writeln!(f, "descriptor: {}", self.funded.descriptor)?;
+        writeln!(f, "address: {}", self.funded.address)?;
+        writeln!(f, "funding txid: {} (vout {}, height {})", self.funded.outpoint().txid, self.funded.outpoint().vout, self.funded.height)?;
+        if self.blocks_waited > 0 {
+            writeln!(f, "mined {} block(s) for the timelock", self.blocks_waited)?;
+        }
+        writeln!(f, "spend txid: {} ({}, fee {} sat, height {})", self.spend.txid, self.spend.plan.path, self.spend.fee.to_sat(), self.spend_height)
+    }
+}
+
+/// Vault descriptor for `path` over three signers and the backup key, in that order
+pub fn demo_descriptor(path: DemoPath, keys: &[PublicKey; 4]) -> Result<Descriptor<PublicKey>, Box<dyn std::error::Error>> {
+    match path {
+        DemoPath::Backup | DemoPath::Multisig => csv_vault_descriptor(keys[3], &keys[..3], 2, DEMO_CSV_DELAY),
+        DemoPath::TaprootLeaf => Ok(Descriptor::from_str(&format!(
+            "tr({},{{pk({}),and_v(v:multi_a(2,{},{},{}),older({}))}})",
+            NUMS_INTERNAL_KEY, keys[3], keys[0], keys[1], keys[2], DEMO_CSV_DELAY
+        ))?),
+    }
+}
+
+/// Run the demo for `path` with `amount` from `wallet`
+pub async fn run(wallet: &impl WalletBackend, path: DemoPath, amount: Amount) -> Result<DemoReport, Box<dyn std::error::Error>> {
+    if wallet.network() != Network::Regtest {
+        return Err(format!("the vault demo mines blocks and runs on regtest only, not {}", wallet.network()).into());
+    }
+    if wallet.balance().await? < amount + DEMO_FEE {
+        flows::mine(wallet, 101).await?;
+    }
+    let keys: Vec<PrivateKey> = (0..4).map(|_| SigningKey::random(Network::Regtest).expose()).collect();
+    let secp = bitcoin::secp256k1::Secp256k1::new();
+    let public: Vec<PublicKey> = keys.iter().map(|k| k.public_key(&secp)).collect();
+    let descriptor = demo_descriptor(path, &[public[0], public[1], public[2], public[3]])?;
+    let signers = match path {
+        DemoPath::Backup => &keys[3..],
+        DemoPath::Multisig | DemoPath::TaprootLeaf => &keys[..2],
+    };
+
+    let funded = flows::fund_descriptor(wallet, &descriptor, amount).await?;
+    let (plan, blocks_waited) = wait_for_plan(wallet, &funded, signers).await?;
+    let destination = wallet.new_address(DESTINATION_LABEL).await?.to_string();
+    let spend = flows::spend_utxo(wallet, &funded, &plan, signers, &destination, DEMO_FEE).await?;
+    let spend_height = flows::mine(wallet, 1).await?;
+    Ok(DemoReport { path, funded, blocks_waited, spend, spend_height })
+}
+
+/// Mine one block at a time until `signers` can spend `funded`, for at most `DEMO_CSV_DELAY` blocks
+async fn wait_for_plan(wallet: &impl WalletBackend, funded: &FundedUtxo, signers: &[PrivateKey]) -> Result<(SpendPlan, u32), Box<dyn std::error::Error>> {
+    let mut mined = 0;
+    loop {
+        match flows::plan_spend(wallet, funded, signers).await {
+            Ok(plan) => return Ok((plan, mined)),
+            Err(e) if mined >= DEMO_CSV_DELAY as u32 => return Err(e),
+            Err(_) => {
+                flows::mine(wallet, 1).await?;
+                mined += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Differential validation of constructed spends.
+//!
+//! A spend is checked by the local miniscript interpreter and by `testmempoolaccept` on one or more
+//! external Core nodes (typically a second regtest node that has the prevouts but did not build
+//! the transaction). Disagreements point at witness construction bugs before a broadcast fails.
+//! Tests enable this mode with `WRAPYIELD_DIFFERENTIAL=1` and `WRAPYIELD_DIFF_RPC_URL`.
+
+use crate::test_setup::BitcoinRPC;
+use crate::verify::{verify_spend, ExecutionTrace, VerifyError};
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::{Transaction, TxOut};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict {
+    pub validator: String,
+    /// `None` when accepted, otherwise the reject reason
+    pub rejection: Option<String>,
+    /// Interpreter trace of the rejected input, when the local validator could replay it
+    pub trace: Option<ExecutionTrace>,
+}
+
+impl Verdict {
+    pub fn accepted(&self) -> bool {
+        self.rejection.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffReport {
+    pub verdicts: Vec<Verdict>,
+}
+
+impl DiffReport {
+    pub fn agree(&self) -> bool {
+        self.verdicts.windows(2).all(|w| w[0].accepted() == w[1].accepted())
+    }
+
+    pub fn all_accept(&self) -> bool {
+        self.verdicts.iter().all(Verdict::accepted)
+    }
+}
+
+/// Evaluate every input's witness against its prevout with the miniscript interpreter
+/// (`verify::verify_spend`); the verdict carries the first failure
+pub fn local_verdict(tx: &Transaction, prevouts: &[TxOut]) -> Verdict {
+    let (rejection, trace) = match verify_spend(tx, prevouts) {
+        Ok(()) => (None, None),
+        Err(VerifyError::Inputs(mut failures)) => {
+            let first = failures.remove(0);
+            (Some(format!("input {}: {}", first.input_index, first.reason)), first.trace)
+        }
+        Err(e) => (Some(e.to_string()), None),
+    };
+    Verdict { validator: "local".to_string(), rejection, trace }
+}
+
+/// `testmempoolaccept` on an external node
+pub async fn core_verdict(name: &str, rpc: &BitcoinRPC, tx: &Transaction) -> Result<Verdict, Box<dyn std::error::Error>> {
+    let result = rpc.test_mempool_accept(&[serialize_hex(tx)]).await?;
+    let entry = result.first().ok_or("testmempoolaccept returned no result")?;
+    let rejection = if entry.allowed {
+        None
+    } else {
+        Some(entry.reject_reason.clone().unwrap_or_else(|| "rejected".to_string()))
+    };
+    Ok(Verdict { validator: name.to_string(), rejection, trace: None })
+}
+
+/// Run the local interpreter and every external node on `tx`
+pub async fn compare(tx: &Transaction, prevouts: &[TxOut], nodes: &[(String, BitcoinRPC)]) -> Result<DiffReport, Box<dyn std::error::Error>> {
+    let mut verdicts = vec![local_verdict(tx, prevouts)];
+    for (name, rpc) in nodes {
+        verdicts.push(core_verdict(name, rpc, tx).await?);
+    }
+    Ok(DiffReport { verdicts })
+}
+
+/// External nodes configured through the environment, if differential mode is on
+pub fn nodes_from_env() -> Option<Vec<(String, BitcoinRPC)>> {
+    if std::env::var("WRAPYIELD_DIFFERENTIAL").ok().as_deref() != Some("1") {
+        return None;
+    }
+    let urls = std::env::var("WRAPYIELD_DIFF_RPC_URL").ok()?;
+    Some(urls.split(',').enumerate().map(|(i, url)| {
+        let mut rpc = BitcoinRPC::new();
+        rpc.url = url.trim().to_string();
+        (format!("core-{}", i), rpc)
+    }).collect())
+}
@@ -0,0 +1,220 @@
+//! Fee market history, so peg-out batches that are not urgent can wait for cheaper blocks.
+//!
+//! `FeeMarket::poll` records a `FeeSample`: the backend's estimate for each of `SAMPLE_TARGETS`
+//! (`estimatesmartfee` on a Core node) and the relay floor (`mempoolminfee` rises with a full
+//! mempool). `percentile` summarises the recorded rates over a window and `forecast` estimates
+//! what a sender who can wait gets: the history is cut into stretches as long as the wait and
+//! the median of each stretch's lowest rate is the expected low. The history is a JSON file
+//! (`save` / `load`), pruned to `max_age` seconds.
+//!
+//! `PegOutSla` decides per batch: urgent batches and batches at their deadline go out now,
+//! others go out once the current rate is within `tolerance_percent` of the expected low for
+//! the time they have left, and wait otherwise.
+
+use crate::backend::ChainBackend;
+use crate::fees;
+use crate::state_file;
+use bitcoin::FeeRate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Confirmation targets sampled by `FeeMarket::poll`: next blocks, about an hour, about a day
+pub const SAMPLE_TARGETS: [u16; 3] = [2, 6, 144];
+/// Seconds of history `FeeMarket::new` keeps by default: one week
+pub const DEFAULT_MAX_AGE: u64 = 7 * 24 * 3600;
+
+/// The market at one moment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeSample {
+    /// Unix time
+    pub time: u64,
+    pub height: u64,
+    /// Estimate per confirmation target; targets the backend had no estimate for are missing
+    pub estimates: BTreeMap<u16, FeeRate>,
+    /// Lowest rate the backend would relay
+    pub floor: FeeRate,
+}
+
+impl FeeSample {
+    /// Rate for `conf_target`, at least the floor; `None` if it was not estimated
+    pub fn rate(&self, conf_target: u16) -> Option<FeeRate> {
+        self.estimates.get(&conf_target).map(|rate| (*rate).max(self.floor))
+    }
+}
+
+/// What the history says about paying for `conf_target` within a horizon
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Forecast {
+    /// Latest recorded rate
+    pub current: FeeRate,
+    /// Median of the lowest rate in each horizon-long stretch of history; `current` when the
+    /// history is shorter than the horizon
+    pub expected_low: FeeRate,
+    /// Stretches `expected_low` is based on; few means little confidence
+    pub windows: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeMarket {
+    /// Oldest first
+    pub samples: Vec<FeeSample>,
+    /// Samples older than this many seconds before the latest one are dropped
+    pub max_age: u64,
+}
+
+impl Default for FeeMarket {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_AGE)
+    }
+}
+
+impl FeeMarket {
+    pub fn new(max_age: u64) -> Self {
+        Self { samples: Vec::new(), max_age }
+    }
+
+    /// Add `sample` in time order and prune what is older than `max_age`
+    pub fn record(&mut self, sample: FeeSample) {
+        let at = self.samples.partition_point(|s| s.time <= sample.time);
+        self.samples.insert(at, sample);
+        let latest = self.samples.last().map(|s| s.time).unwrap_or_default();
+        self.samples.retain(|s| s.time + self.max_age >= latest);
+    }
+
+    /// Sample the market through `backend` now
+    pub async fn sample(backend: &impl ChainBackend) -> Result<FeeSample, Box<dyn std::error::Error>> {
+        let mut estimates = BTreeMap::new();
+        for target in SAMPLE_TARGETS {
+            if let Some(rate) = backend.fee_rate(target).await? {
+                estimates.insert(target, rate);
+            }
+        }
+        let floor = backend.relay_floor(fees::min_relay_rate_from_env()?).await?.rate();
+        Ok(FeeSample {
+            time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            height: backend.tip_height().await?,
+            estimates,
+            floor,
+        })
+    }
+
+    /// `sample` and `record`
+    pub async fn poll(&mut self, backend: &impl ChainBackend) -> Result<&FeeSample, Box<dyn std::error::Error>> {
+        let sample = Self::sample(backend).await?;
+        let time = sample.time;
+        self.record(sample);
+        Ok(self.samples.iter().rev().find(|s| s.time == time).expect("just recorded"))
+    }
+
+    pub fn latest(&self) -> Option<&FeeSample> {
+        self.samples.last()
+    }
+
+    /// Time and rate of every sample estimating `conf_target` no earlier than `since`
+    fn rates(&self, conf_target: u16, since: u64) -> Vec<(u64, FeeRate)> {
+        self.samples.iter().filter(|s| s.time >= since).filter_map(|s| Some((s.time, s.rate(conf_target)?))).collect()
+    }
+
+    /// `percent`th percentile (nearest rank) of the rates for `conf_target` over the last
+    /// `window` seconds; `None` without samples
+    pub fn percentile(&self, conf_target: u16, percent: u8, window: u64) -> Option<FeeRate> {
+        let latest = self.latest()?.time;
+        let mut rates: Vec<FeeRate> = self.rates(conf_target, latest.saturating_sub(window)).into_iter().map(|(_, rate)| rate).collect();
+        rates.sort();
+        nearest_rank(&rates, percent)
+    }
+
+    /// Expected rates for `conf_target` to a sender who can wait `horizon` seconds; `None` if
+    /// no sample estimates the target
+    pub fn forecast(&self, conf_target: u16, horizon: u64) -> Option<Forecast> {
+        let rates = self.rates(conf_target, 0);
+        let (latest, current) = *rates.last()?;
+        let oldest = rates[0].0;
+        let mut lows = Vec::new();
+        if horizon > 0 {
+            let mut end = latest;
+            while end >= oldest + horizon {
+                let start = end - horizon;
+                if let Some(low) = rates.iter().filter(|(time, _)| *time > start && *time <= end).map(|(_, rate)| *rate).min() {
+                    lows.push(low);
+                }
+                end = start;
+            }
+        }
+        lows.sort();
+        Some(Forecast { current, expected_low: nearest_rank(&lows, 50).unwrap_or(current), windows: lows.len() })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        state_file::save_json(path, self)
+    }
+
+    /// `None` if nothing was saved yet
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        state_file::load_json(path)
+    }
+}
+
+fn nearest_rank(sorted: &[FeeRate], percent: u8) -> Option<FeeRate> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (sorted.len() * percent.min(100) as usize).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+/// How long peg-out batches may wait for cheaper fees
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PegOutSla {
+    /// Seconds a non-urgent batch may wait from when it was queued
+    pub max_delay: u64,
+    /// Target the batch is priced for once it goes out
+    pub conf_target: u16,
+    /// Send once the current rate is at most this many percent above the expected low
+    pub tolerance_percent: u64,
+}
+
+impl Default for PegOutSla {
+    fn default() -> Self {
+        Self { max_delay: 24 * 3600, conf_target: 6, tolerance_percent: 10 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendReason {
+    Urgent,
+    /// `max_delay` is used up
+    Deadline,
+    /// The current rate is close enough to the expected low
+    Cheap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// Build and broadcast the batch at `rate`
+    Now { rate: FeeRate, reason: SendReason },
+    /// Ask again after the next poll; the batch goes out once the rate nears `target` and at
+    /// `deadline` (unix time) at the latest
+    Defer { current: FeeRate, target: FeeRate, deadline: u64 },
+}
+
+impl PegOutSla {
+    /// When the batch queued at `queued_at` should go out, as of unix time `now`
+    pub fn schedule(&self, market: &FeeMarket, queued_at: u64, urgent: bool, now: u64) -> Result<Schedule, Box<dyn std::error::Error>> {
+        let deadline = queued_at.saturating_add(self.max_delay);
+        let forecast = market.forecast(self.conf_target, deadline.saturating_sub(now))
+            .ok_or_else(|| format!("no fee history for a {}-block target", self.conf_target))?;
+        let reason = if urgent {
+            SendReason::Urgent
+        } else if now >= deadline {
+            SendReason::Deadline
+        } else if forecast.current.to_sat_per_kwu() * 100 <= forecast.expected_low.to_sat_per_kwu() * (100 + self.tolerance_percent) {
+            SendReason::Cheap
+        } else {
+            return Ok(Schedule::Defer { current: forecast.current, target: forecast.expected_low, deadline });
+        };
+        Ok(Schedule::Now { rate: forecast.current, reason })
+    }
+}
@@ -0,0 +1,195 @@
+//! Fee estimation for the transactions we build.
+//!
+//! Input weight comes from the descriptor (`max_weight_to_satisfy`, the most expensive
+//! satisfaction, so an estimate never undershoots) and the rate from the `backend::ChainBackend`
+//! (`estimatesmartfee` on a Core node). A fresh regtest node has no fee data, so there
+//! `REGTEST_FALLBACK_RATE` is used; other networks get an error instead of a guess. `FeePlan`
+//! then sets the fee and, if it is worth it, a change output.
+//!
+//! Whatever the estimate, a transaction must also clear the node's relay floor: `minrelaytxfee`,
+//! `mempoolminfee` (which rises while the mempool is full) and our own configured minimum, see
+//! `RelayFloor`. The floor is fetched again right before broadcasting, since a time-critical
+//! clawback may be signed well before the mempool gets congested.
+
+use crate::amount::{deduct_fee, deduct_fee_for};
+use crate::backend::ChainBackend;
+use bitcoin::consensus::encode::{serialize, VarInt};
+use bitcoin::{Amount, FeeRate, Network, PublicKey, ScriptBuf, Transaction, TxOut, Txid, Weight};
+use miniscript::descriptor::DescriptorType;
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+pub const REGTEST_FALLBACK_RATE: FeeRate = FeeRate::from_sat_per_vb_unchecked(2);
+/// Core's default `-minrelaytxfee`
+pub const DEFAULT_MIN_RELAY_RATE: FeeRate = FeeRate::from_sat_per_vb_unchecked(1);
+
+/// Outpoint, sequence and an empty scriptSig; the satisfaction weight covers the rest
+const UNSIGNED_INPUT_WEIGHT: u64 = (32 + 4 + 1 + 4) * 4;
+
+/// Upper bound on the weight of a transaction spending one output per descriptor in `inputs`
+pub fn spend_weight(inputs: &[&Descriptor<PublicKey>], outputs: &[TxOut]) -> Result<Weight, Box<dyn std::error::Error>> {
+    let mut weight = (4 + 4 + VarInt(inputs.len() as u64).len() + VarInt(outputs.len() as u64).len()) as u64 * 4;
+    weight += outputs.iter().map(|o| serialize(o).len() as u64 * 4).sum::<u64>();
+    for descriptor in inputs {
+        weight += UNSIGNED_INPUT_WEIGHT + descriptor.max_weight_to_satisfy()? as u64;
+    }
+    let legacy = |d: &&Descriptor<PublicKey>| matches!(d.desc_type(), DescriptorType::Bare | DescriptorType::Pkh | DescriptorType::Sh);
+    if !inputs.iter().all(legacy) {
+        // segwit marker and flag, plus the item count of every input's witness: the
+        // satisfaction weight is counted on top of an input with an empty witness
+        weight += 2 + inputs.len() as u64;
+    }
+    Ok(Weight::from_wu(weight))
+}
+
+pub fn vsize(inputs: &[&Descriptor<PublicKey>], outputs: &[TxOut]) -> Result<u64, Box<dyn std::error::Error>> {
+    Ok(spend_weight(inputs, outputs)?.to_vbytes_ceil())
+}
+
+pub fn fee_for(inputs: &[&Descriptor<PublicKey>], outputs: &[TxOut], rate: FeeRate) -> Result<Amount, Box<dyn std::error::Error>> {
+    fee_at(rate, spend_weight(inputs, outputs)?).ok_or_else(|| "fee overflows".into())
+}
+
+/// Fee `weight` pays at `rate`; `None` on overflow
+pub fn fee_at(rate: FeeRate, weight: Weight) -> Option<Amount> {
+    rate.to_sat_per_kwu().checked_mul(weight.to_wu()).map(|sat_wu_per_kwu| Amount::from_sat(sat_wu_per_kwu / 1_000))
+}
+
+/// Fee `vsize` virtual bytes pay at `rate`; `None` on overflow
+pub fn fee_at_vsize(rate: FeeRate, vsize: u64) -> Option<Amount> {
+    fee_at(rate, Weight::from_vb(vsize)?)
+}
+
+/// `estimatesmartfee` reports BTC/kvB; round up to sat/kwu
+pub fn from_btc_per_kvb(rate: Amount) -> FeeRate {
+    FeeRate::from_sat_per_kwu((rate.to_sat() + 3) / 4)
+}
+
+/// Fee rate for confirmation within `conf_target` blocks, raised to the relay floor if needed
+pub async fn estimate_fee_rate(backend: &impl ChainBackend, conf_target: u16) -> Result<FeeRate, Box<dyn std::error::Error>> {
+    let rate = match backend.fee_rate(conf_target).await? {
+        Some(rate) => rate,
+        None if backend.network() == Network::Regtest => REGTEST_FALLBACK_RATE,
+        None => return Err(format!("no fee estimate for {} blocks on {}", conf_target, backend.network()).into()),
+    };
+    let configured = min_relay_rate_from_env()?;
+    let floor = RelayFloor::fetch(backend, configured).await?;
+    Ok(rate.max(floor.rate()))
+}
+
+/// `WRAPYIELD_MIN_RELAY_FEERATE` in sat/vB, or `DEFAULT_MIN_RELAY_RATE` when unset
+pub fn min_relay_rate_from_env() -> Result<FeeRate, Box<dyn std::error::Error>> {
+    min_relay_rate_from_vars(|name| std::env::var(name).ok())
+}
+
+/// Like `min_relay_rate_from_env`, reading variables through `var`
+pub fn min_relay_rate_from_vars(var: impl Fn(&str) -> Option<String>) -> Result<FeeRate, Box<dyn std::error::Error>> {
+    match var("WRAPYIELD_MIN_RELAY_FEERATE") {
+        Some(rate) => {
+            let sat_per_vb = u64::from_str(rate.trim()).map_err(|e| format!("WRAPYIELD_MIN_RELAY_FEERATE: {}", e))?;
+            FeeRate::from_sat_per_vb(sat_per_vb).ok_or_else(|| "WRAPYIELD_MIN_RELAY_FEERATE overflows".into())
+        }
+        None => Ok(DEFAULT_MIN_RELAY_RATE),
+    }
+}
+
+/// Lowest fee rate a transaction can pay and still be relayed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelayFloor {
+    /// Node's `minrelaytxfee`
+    pub min_relay: FeeRate,
+    /// Node's `mempoolminfee`
+    pub mempool_min: FeeRate,
+    /// Our own minimum, applied even if the node would accept less
+    pub configured: FeeRate,
+}
+
+/// A transaction paying less than the relay floor
+#[derive(Debug, Clone, PartialEq)]
+pub struct BelowRelayFloor {
+    pub txid: Txid,
+    pub vsize: u64,
+    pub fee: Amount,
+    pub required: Amount,
+    pub floor: RelayFloor,
+}
+
+impl std::fmt::Display for BelowRelayFloor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} pays {} sats for {} vB, the relay floor of {} requires {}", self.txid, self.fee.to_sat(), self.vsize, self.floor.rate(), self.required.to_sat())
+    }
+}
+
+impl std::error::Error for BelowRelayFloor {}
+
+impl RelayFloor {
+    pub async fn fetch(backend: &impl ChainBackend, configured: FeeRate) -> Result<Self, Box<dyn std::error::Error>> {
+        backend.relay_floor(configured).await
+    }
+
+    pub fn rate(&self) -> FeeRate {
+        self.min_relay.max(self.mempool_min).max(self.configured)
+    }
+
+    /// Fee `vsize` virtual bytes need to clear the floor
+    pub fn required_fee(&self, vsize: u64) -> Amount {
+        fee_at_vsize(self.rate(), vsize).unwrap_or(Amount::MAX_MONEY)
+    }
+
+    /// Whether `tx`, paying `fee`, clears the floor
+    pub fn check(&self, tx: &Transaction, fee: Amount) -> Result<(), BelowRelayFloor> {
+        let vsize = tx.vsize() as u64;
+        let required = self.required_fee(vsize);
+        if fee < required {
+            return Err(BelowRelayFloor { txid: tx.txid(), vsize, fee, required, floor: *self });
+        }
+        Ok(())
+    }
+}
+
+/// Fetch the relay floor again and broadcast `tx` only if it still clears it
+pub async fn broadcast_above_floor(backend: &impl ChainBackend, tx: &Transaction, fee: Amount) -> Result<Txid, Box<dyn std::error::Error>> {
+    RelayFloor::fetch(backend, min_relay_rate_from_env()?).await?.check(tx, fee)?;
+    backend.broadcast(tx).await
+}
+
+/// Outputs and fee of a spend
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeePlan {
+    pub outputs: Vec<TxOut>,
+    pub fee: Amount,
+    /// Estimated weight the fee was computed for
+    pub weight: Weight,
+    /// Index of the change output in `outputs`, if there is one
+    pub change: Option<usize>,
+}
+
+impl FeePlan {
+    /// Pay `outputs` from `inputs` (worth `input_value` together) at `rate`. What is left goes to
+    /// `change_script` if it stays above dust after paying for the extra output, else to the fee.
+    pub fn with_change(inputs: &[&Descriptor<PublicKey>], input_value: Amount, outputs: Vec<TxOut>, change_script: ScriptBuf, rate: FeeRate) -> Result<Self, Box<dyn std::error::Error>> {
+        let spent = outputs.iter().try_fold(Amount::ZERO, |total, o| total.checked_add(Amount::from_sat(o.value))).ok_or("output total overflows")?;
+        let available = input_value.checked_sub(spent).ok_or_else(|| format!("outputs total {} sats but inputs only {}", spent.to_sat(), input_value.to_sat()))?;
+
+        let mut with_change = outputs.clone();
+        with_change.push(TxOut { value: 0, script_pubkey: change_script });
+        let fee = fee_for(inputs, &with_change, rate)?;
+        let change = with_change.len() - 1;
+        if let Ok(value) = deduct_fee_for(available, fee, &with_change[change].script_pubkey) {
+            with_change[change].value = value.to_sat();
+            return Ok(FeePlan { weight: spend_weight(inputs, &with_change)?, outputs: with_change, fee, change: Some(change) });
+        }
+
+        let fee = fee_for(inputs, &outputs, rate)?;
+        deduct_fee(available, fee)?;
+        Ok(FeePlan { weight: spend_weight(inputs, &outputs)?, outputs, fee: available, change: None })
+    }
+
+    /// Send all of `inputs` to `destination`, less the fee
+    pub fn sweep(inputs: &[&Descriptor<PublicKey>], input_value: Amount, destination: ScriptBuf, rate: FeeRate) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut outputs = vec![TxOut { value: 0, script_pubkey: destination }];
+        let fee = fee_for(inputs, &outputs, rate)?;
+        outputs[0].value = deduct_fee_for(input_value, fee, &outputs[0].script_pubkey)?.to_sat();
+        Ok(FeePlan { weight: spend_weight(inputs, &outputs)?, outputs, fee, change: None })
+    }
+}
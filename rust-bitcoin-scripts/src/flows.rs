@@ -0,0 +1,120 @@
+//! End-to-end regtest flows: fund a descriptor from the node wallet, wait for it to mature, and
+//! spend it along a `spend::Planner` path with local keys.
+//!
+//! `fund_descriptor` sends, confirms and locates the output; `plan_spend` plans against the
+//! current tip; `spend_utxo` builds the PSBT with the plan's sequence and locktime, signs with
+//! the plan's signers only, finalizes and broadcasts (`spend_utxo_estimated` sets the fee from
+//! `fees`). Funding and mining go through a `WalletBackend`, everything else through any
+//! `ChainBackend`; `mine_censoring` mines empty blocks.
+
+use crate::amount::deduct_fee_for;
+use crate::backend::{ChainBackend, WalletBackend};
+use crate::fees::{self, FeePlan};
+use crate::locktime::{self, LockTimePolicy};
+use crate::psbt::{self, SpendableUtxo};
+use crate::receipt::SpendReceipt;
+use crate::spend::{Planner, SpendPlan};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Amount, OutPoint, PrivateKey, PublicKey, Transaction, TxOut, Txid};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+/// A confirmed output locked by `descriptor`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FundedUtxo {
+    pub descriptor: Descriptor<PublicKey>,
+    pub address: String,
+    /// Carries the funding transaction, so legacy descriptors can be spent too
+    pub utxo: SpendableUtxo,
+    /// Height of the block that confirmed the funding transaction
+    pub height: u64,
+}
+
+impl FundedUtxo {
+    pub fn outpoint(&self) -> OutPoint {
+        self.utxo.outpoint
+    }
+
+    pub fn amount(&self) -> Amount {
+        Amount::from_sat(self.utxo.txout.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendResult {
+    pub txid: Txid,
+    pub transaction: Transaction,
+    pub fee: Amount,
+    pub plan: SpendPlan,
+    pub receipt: SpendReceipt,
+}
+
+/// Mine `blocks` to a new wallet address and return the new tip height
+pub async fn mine(backend: &impl WalletBackend, blocks: u32) -> Result<u64, Box<dyn std::error::Error>> {
+    backend.generate(blocks, false).await
+}
+
+/// Mine `blocks` empty blocks, leaving everything in the mempool unconfirmed as censoring miners
+/// would, and return the new tip height
+pub async fn mine_censoring(backend: &impl WalletBackend, blocks: u32) -> Result<u64, Box<dyn std::error::Error>> {
+    backend.generate(blocks, true).await
+}
+
+/// Send `amount` from the node wallet to `descriptor`, confirm it in one block and locate the output
+pub async fn fund_descriptor(backend: &impl WalletBackend, descriptor: &Descriptor<PublicKey>, amount: Amount) -> Result<FundedUtxo, Box<dyn std::error::Error>> {
+    let address = descriptor.address(backend.network())?;
+    let txid = backend.send_to(&address, amount).await?;
+    let height = mine(backend, 1).await?;
+    let tx = backend.transaction(&txid).await?;
+    let vout = tx.output.iter().position(|o| o.script_pubkey == address.script_pubkey()).ok_or_else(|| format!("{} has no output to {}", txid, address))?;
+    let mut utxo = SpendableUtxo::new(OutPoint::new(txid, vout as u32), tx.output[vout].clone());
+    utxo.prev_tx = Some(tx);
+    Ok(FundedUtxo { descriptor: descriptor.clone(), address: address.to_string(), utxo, height })
+}
+
+/// Cheapest path `keys` can take on `funded` at the current tip
+pub async fn plan_spend(backend: &impl ChainBackend, funded: &FundedUtxo, keys: &[PrivateKey]) -> Result<SpendPlan, Box<dyn std::error::Error>> {
+    let secp = Secp256k1::new();
+    let tip = backend.chain_tip().await?;
+    let confirmations = (tip.height + 1).saturating_sub(funded.height) as u32;
+    let planner = Planner::new(keys.iter().map(|k| k.public_key(&secp)), tip.height as u32, tip.median_time_past as u32, confirmations);
+    planner.plan(&funded.descriptor)
+}
+
+/// Spend `funded` to `destination` along `plan`, paying `fee`, and broadcast it. The lock time
+/// is set against fee sniping; a fee below the node's current relay floor is an error.
+pub async fn spend_utxo(backend: &impl ChainBackend, funded: &FundedUtxo, plan: &SpendPlan, keys: &[PrivateKey], destination: &str, fee: Amount) -> Result<SpendResult, Box<dyn std::error::Error>> {
+    spend_utxo_with_policy(backend, funded, plan, keys, destination, fee, LockTimePolicy::default()).await
+}
+
+/// `spend_utxo` with the lock time chosen by `policy`
+pub async fn spend_utxo_with_policy(backend: &impl ChainBackend, funded: &FundedUtxo, plan: &SpendPlan, keys: &[PrivateKey], destination: &str, fee: Amount, policy: LockTimePolicy) -> Result<SpendResult, Box<dyn std::error::Error>> {
+    let secp = Secp256k1::new();
+    let destination = backend.parse_address(destination)?;
+    let value = deduct_fee_for(funded.amount(), fee, &destination.script_pubkey())?;
+    let descriptor = Descriptor::<DefiniteDescriptorKey>::from_str(&funded.descriptor.to_string())?;
+
+    let mut utxo = funded.utxo.clone();
+    utxo.sequence = plan.sequence;
+    let outputs = vec![TxOut { value: value.to_sat(), script_pubkey: destination.script_pubkey() }];
+    let lock_time = locktime::reconcile(plan.lock_time, backend.tip_height().await? as u32, policy);
+    let mut unsigned = psbt::create(&descriptor, &[utxo], outputs, lock_time)?;
+    // Only the planned signers sign, so the finalizer cannot pick a different path
+    let signers: Vec<PrivateKey> = keys.iter().filter(|k| plan.signers.contains(&k.public_key(&secp))).copied().collect();
+    if signers.len() != plan.signers.len() {
+        return Err(format!("plan needs {} signer(s), {} key(s) match", plan.signers.len(), signers.len()).into());
+    }
+    psbt::sign(&mut unsigned, &signers)?;
+    let transaction = psbt::finalize(unsigned)?;
+    let receipt = SpendReceipt::broadcast(backend, &transaction, fee, plan.path.to_string()).await?;
+    Ok(SpendResult { txid: receipt.txid, transaction, fee, plan: plan.clone(), receipt })
+}
+
+/// `spend_utxo` with the fee estimated for confirmation within `conf_target` blocks
+pub async fn spend_utxo_estimated(backend: &impl ChainBackend, funded: &FundedUtxo, plan: &SpendPlan, keys: &[PrivateKey], destination: &str, conf_target: u16) -> Result<SpendResult, Box<dyn std::error::Error>> {
+    let rate = fees::estimate_fee_rate(backend, conf_target).await?;
+    let script_pubkey = backend.parse_address(destination)?.script_pubkey();
+    let fee_plan = FeePlan::sweep(&[&funded.descriptor], funded.amount(), script_pubkey, rate)?;
+    spend_utxo(backend, funded, plan, keys, destination, fee_plan.fee).await
+}
@@ -0,0 +1,159 @@
+//! BIP39 mnemonics and BIP32 account keys for descriptors.
+//!
+//! `HdWallet` derives account keys at the standard paths (`m/purpose'/coin'/account'`, coin type
+//! 1 off mainnet) and hands them out as descriptor keys with origin,
+//! `[fingerprint/86'/1'/0']xpub.../<chain>/*`, which the vault builders accept in place of bare
+//! public keys. `derive` turns a ranged descriptor into the one for a single index, and
+//! `signing_key` gives the matching private key for `psbt::sign`.
+//!
+//! Like `secret::SigningKey`, the wallet keeps its master key zeroized on drop and compares it
+//! in constant time; the `ExtendedPrivKey`s it hands out are `Copy` and the caller's to keep
+//! short-lived. Generated mnemonics come back zeroizing too.
+
+use bip39::Mnemonic;
+use bitcoin::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint};
+use crate::secret::ct_eq;
+use bitcoin::secp256k1::{Secp256k1, Signing};
+use miniscript::bitcoin::{Network, PrivateKey, PublicKey};
+use miniscript::descriptor::{DescriptorPublicKey, DescriptorSecretKey, DescriptorXKey, Wildcard};
+use miniscript::Descriptor;
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use std::fmt;
+use zeroize::Zeroizing;
+
+/// BIP43 purposes of the account paths we derive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    /// `44'`: P2PKH
+    Bip44,
+    /// `49'`: P2WPKH nested in P2SH
+    Bip49,
+    /// `84'`: P2WPKH
+    Bip84,
+    /// `86'`: single-key P2TR
+    Bip86,
+    /// `48'/.../2'`: P2WSH multisig cosigner
+    Bip48Wsh,
+}
+
+impl Purpose {
+    fn index(self) -> u32 {
+        match self {
+            Purpose::Bip44 => 44,
+            Purpose::Bip49 => 49,
+            Purpose::Bip84 => 84,
+            Purpose::Bip86 => 86,
+            Purpose::Bip48Wsh => 48,
+        }
+    }
+}
+
+/// New mnemonic of 12, 15, 18, 21 or 24 words
+pub fn generate_mnemonic(words: usize) -> Result<Zeroizing<Mnemonic>, Box<dyn std::error::Error>> {
+    if !(12..=24).contains(&words) || words % 3 != 0 {
+        return Err(format!("{} words is not a BIP39 mnemonic length", words).into());
+    }
+    let mut entropy = Zeroizing::new(vec![0u8; words * 4 / 3]);
+    rand::thread_rng().fill_bytes(entropy.as_mut());
+    Ok(Zeroizing::new(Mnemonic::from_entropy(&entropy)?))
+}
+
+pub fn parse_mnemonic(words: &str) -> Result<Mnemonic, Box<dyn std::error::Error>> {
+    Ok(Mnemonic::parse(words)?)
+}
+
+/// `m/purpose'/coin'/account'`, with `/2'` appended for BIP48 P2WSH
+pub fn account_path(purpose: Purpose, network: Network, account: u32) -> Result<DerivationPath, Box<dyn std::error::Error>> {
+    let coin = if network == Network::Bitcoin { 0 } else { 1 };
+    let mut path = vec![ChildNumber::from_hardened_idx(purpose.index())?, ChildNumber::from_hardened_idx(coin)?, ChildNumber::from_hardened_idx(account)?];
+    if purpose == Purpose::Bip48Wsh {
+        path.push(ChildNumber::from_hardened_idx(2)?);
+    }
+    Ok(DerivationPath::from(path))
+}
+
+pub struct HdWallet {
+    /// `ExtendedPrivKey::encode` of the master key
+    master: Secret<[u8; 78]>,
+    pub network: Network,
+}
+
+impl Clone for HdWallet {
+    fn clone(&self) -> Self {
+        Self { master: Secret::new(*self.master.expose_secret()), network: self.network }
+    }
+}
+
+impl PartialEq for HdWallet {
+    fn eq(&self, other: &Self) -> bool {
+        self.network == other.network && ct_eq(self.master.expose_secret(), other.master.expose_secret())
+    }
+}
+
+impl fmt::Debug for HdWallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HdWallet({}, [REDACTED])", self.fingerprint(&Secp256k1::signing_only()))
+    }
+}
+
+impl HdWallet {
+    pub fn from_mnemonic(mnemonic: &Mnemonic, passphrase: &str, network: Network) -> Result<Self, Box<dyn std::error::Error>> {
+        let seed = Zeroizing::new(mnemonic.to_seed(passphrase));
+        let master = Zeroizing::new(ExtendedPrivKey::new_master(network, seed.as_ref())?.encode());
+        Ok(Self { master: Secret::new(*master), network })
+    }
+
+    /// A temporary master key; do not store it
+    fn master(&self) -> ExtendedPrivKey {
+        let mut master = ExtendedPrivKey::decode(self.master.expose_secret()).expect("encoded on construction");
+        // The encoding only tells mainnet from the test networks
+        master.network = self.network;
+        master
+    }
+
+    pub fn fingerprint<C: Signing>(&self, secp: &Secp256k1<C>) -> Fingerprint {
+        self.master().fingerprint(secp)
+    }
+
+    pub fn account_xprv(&self, path: &DerivationPath) -> Result<ExtendedPrivKey, Box<dyn std::error::Error>> {
+        Ok(self.master().derive_priv(&Secp256k1::new(), path)?)
+    }
+
+    pub fn account_xpub(&self, path: &DerivationPath) -> Result<ExtendedPubKey, Box<dyn std::error::Error>> {
+        Ok(ExtendedPubKey::from_priv(&Secp256k1::new(), &self.account_xprv(path)?))
+    }
+
+    /// `[fingerprint/path]xpub/<chain>/*` for the account at `path`
+    pub fn descriptor_key(&self, path: &DerivationPath, chain: u32) -> Result<DescriptorPublicKey, Box<dyn std::error::Error>> {
+        let secp = Secp256k1::new();
+        Ok(DescriptorPublicKey::XPub(DescriptorXKey {
+            origin: Some((self.fingerprint(&secp), path.clone())),
+            xkey: self.account_xpub(path)?,
+            derivation_path: DerivationPath::from(vec![ChildNumber::from_normal_idx(chain)?]),
+            wildcard: Wildcard::Unhardened,
+        }))
+    }
+
+    /// `descriptor_key` with the account xprv, for signing descriptors (`tprv.../0/*`)
+    pub fn descriptor_secret_key(&self, path: &DerivationPath, chain: u32) -> Result<DescriptorSecretKey, Box<dyn std::error::Error>> {
+        let secp = Secp256k1::new();
+        Ok(DescriptorSecretKey::XPrv(DescriptorXKey {
+            origin: Some((self.fingerprint(&secp), path.clone())),
+            xkey: self.account_xprv(path)?,
+            derivation_path: DerivationPath::from(vec![ChildNumber::from_normal_idx(chain)?]),
+            wildcard: Wildcard::Unhardened,
+        }))
+    }
+
+    /// Private key at `path/chain/index`
+    pub fn signing_key(&self, path: &DerivationPath, chain: u32, index: u32) -> Result<PrivateKey, Box<dyn std::error::Error>> {
+        let full = path.extend([ChildNumber::from_normal_idx(chain)?, ChildNumber::from_normal_idx(index)?]);
+        Ok(self.account_xprv(&full)?.to_priv())
+    }
+}
+
+/// The descriptor for `index` of a ranged descriptor
+pub fn derive(descriptor: &Descriptor<DescriptorPublicKey>, index: u32) -> Result<Descriptor<PublicKey>, Box<dyn std::error::Error>> {
+    Ok(descriptor.derived_descriptor(&Secp256k1::verification_only(), index)?)
+}
@@ -0,0 +1,168 @@
+//! Header tracking for median-time-past (BIP113).
+//!
+//! `HeaderTracker` keeps the most recent headers of the active chain (at least the 11 that MTP
+//! is the median of), follows reorgs by rewinding to the last header still in the chain, and
+//! answers time-lock questions the way consensus does: an absolute time lock against the tip's
+//! MTP, a BIP68 time-based relative lock against the MTP of the block before the one that
+//! confirmed the output. Headers come from `getblockheader`, polled by `sync` or `run`.
+
+use crate::broadcast::ChainTip;
+use crate::locktime::LOCKTIME_THRESHOLD;
+use crate::test_setup::BitcoinRPC;
+use bitcoin::block::Header;
+use bitcoin::BlockHash;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Number of blocks median-time-past is taken over
+pub const MTP_WINDOW: usize = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackedHeader {
+    pub height: u64,
+    pub hash: BlockHash,
+    pub prev_hash: BlockHash,
+    pub time: u32,
+}
+
+/// Median of up to 11 block timestamps, as `GetMedianTimePast` computes it
+pub fn median_time_past(times: &[u32]) -> Option<u32> {
+    let mut sorted = times.to_vec();
+    sorted.sort_unstable();
+    sorted.get(sorted.len() / 2).copied()
+}
+
+#[derive(Debug, Clone)]
+pub struct HeaderTracker {
+    headers: VecDeque<TrackedHeader>,
+    capacity: usize,
+}
+
+impl Default for HeaderTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeaderTracker {
+    pub fn new() -> Self {
+        Self::with_capacity(MTP_WINDOW)
+    }
+
+    /// Keep `capacity` headers (at least `MTP_WINDOW`), so the MTP of older blocks is known too
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { headers: VecDeque::new(), capacity: capacity.max(MTP_WINDOW) }
+    }
+
+    pub fn tip(&self) -> Option<&TrackedHeader> {
+        self.headers.back()
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = &TrackedHeader> {
+        self.headers.iter()
+    }
+
+    /// Add the header at `height`. A header at or below the tip replaces the headers it reorgs
+    /// out; it must build on what is left.
+    pub fn connect(&mut self, height: u64, header: &Header) -> Result<(), Box<dyn std::error::Error>> {
+        while self.tip().map_or(false, |tip| tip.height >= height) {
+            self.headers.pop_back();
+        }
+        if let Some(tip) = self.tip() {
+            if tip.height + 1 != height {
+                return Err(format!("header at height {} does not follow tip {}", height, tip.height).into());
+            }
+            if tip.hash != header.prev_blockhash {
+                return Err(format!("header {} at height {} does not build on {}", header.block_hash(), height, tip.hash).into());
+            }
+        }
+        self.headers.push_back(TrackedHeader { height, hash: header.block_hash(), prev_hash: header.prev_blockhash, time: header.time });
+        if self.headers.len() > self.capacity {
+            self.headers.pop_front();
+        }
+        Ok(())
+    }
+
+    /// MTP of the block at `height`; `None` unless it and its 10 ancestors are tracked (fewer
+    /// ancestors only near genesis)
+    pub fn median_time_past_at(&self, height: u64) -> Option<u32> {
+        let first = self.headers.front()?.height;
+        let window_start = height.saturating_sub(MTP_WINDOW as u64 - 1);
+        if height > self.tip()?.height || window_start < first {
+            return None;
+        }
+        let times: Vec<u32> = self.headers.iter().filter(|h| h.height >= window_start && h.height <= height).map(|h| h.time).collect();
+        median_time_past(&times)
+    }
+
+    /// MTP of the tip, the time a transaction in the next block is judged against
+    pub fn current_median_time_past(&self) -> Option<u32> {
+        self.median_time_past_at(self.tip()?.height)
+    }
+
+    pub fn chain_tip(&self) -> Option<ChainTip> {
+        Some(ChainTip { height: self.tip()?.height, median_time_past: self.current_median_time_past()? as u64 })
+    }
+
+    /// Whether a time-based nLockTime / `after` is satisfied in the next block; `None` for height locks
+    pub fn absolute_time_mature(&self, lock_time: u32) -> Option<bool> {
+        if lock_time < LOCKTIME_THRESHOLD {
+            return None;
+        }
+        Some(lock_time < self.current_median_time_past()?)
+    }
+
+    /// Whether a BIP68 time-based relative lock of `units` * 512 seconds on an output confirmed
+    /// at `confirmed_height` is satisfied in the next block. `None` if the MTP of the block before
+    /// `confirmed_height` is not tracked (use `with_capacity` to look further back).
+    pub fn relative_time_mature(&self, confirmed_height: u64, units: u16) -> Option<bool> {
+        let start = self.median_time_past_at(confirmed_height.checked_sub(1)?)?;
+        let now = self.current_median_time_past()?;
+        Some(now.saturating_sub(start) >= units as u32 * 512)
+    }
+
+    /// Catch up with the node: rewind past reorged headers, then fetch the missing ones (only
+    /// the most recent `capacity` if far behind). Returns whether the tip changed.
+    pub async fn sync(&mut self, rpc: &BitcoinRPC) -> Result<bool, Box<dyn std::error::Error>> {
+        let before = self.tip().map(|tip| tip.hash);
+        let best = rpc.get_block_count().await?;
+        while let Some(tip) = self.tip().copied() {
+            if tip.height <= best && rpc.get_block_hash(tip.height).await? == tip.hash {
+                break;
+            }
+            self.headers.pop_back();
+        }
+        let oldest_needed = (best + 1).saturating_sub(self.capacity as u64);
+        let from = match self.tip() {
+            Some(tip) if tip.height + 1 >= oldest_needed => tip.height + 1,
+            _ => {
+                self.headers.clear();
+                oldest_needed
+            }
+        };
+        for height in from..=best {
+            let hash = rpc.get_block_hash(height).await?;
+            self.connect(height, &rpc.get_block_header(&hash).await?)?;
+        }
+        Ok(self.tip().map(|tip| tip.hash) != before)
+    }
+
+    /// Sync every `interval` and send the new `ChainTip` whenever the tip changes, until
+    /// `updates` is closed
+    pub async fn run(&mut self, rpc: &BitcoinRPC, interval: Duration, updates: mpsc::UnboundedSender<ChainTip>) -> Result<(), Box<dyn std::error::Error>> {
+        loop {
+            if self.sync(rpc).await? {
+                if let Some(tip) = self.chain_tip() {
+                    if updates.send(tip).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            if updates.is_closed() {
+                return Ok(());
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
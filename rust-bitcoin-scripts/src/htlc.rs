@@ -0,0 +1,246 @@
+//! Hash-time-locked contracts for atomic swaps: the receiver claims with the preimage of a hash,
+//! the sender takes the coins back once an absolute timelock (`after`) has passed.
+//!
+//! Both output types are built from miniscript:
+//! - wsh: `andor(pk(R),sha256(H),and_v(v:pk(S),after(T)))`
+//! - taproot: a NUMS internal key tagged with the hash (so HTLCs are not linkable through a
+//!   shared internal key) and two leaves, `and_v(v:pk(R),sha256(H))` and `and_v(v:pk(S),after(T))`
+//!
+//! `hash160` locks replace `sha256(H)` with `hash160(H)`. Preimages are 32 bytes, the only size
+//! miniscript hash fragments accept.
+//!
+//! Stack layouts (bottom to top):
+//! - wsh claim: `<preimage> <sig_R> <witness_script>`
+//! - wsh refund: `<sig_S> <> <witness_script>`; the empty element fails `pk(R)` so `andor` takes
+//!   the refund branch
+//! - taproot claim: `<preimage> <sig_R> <leaf_script> <control_block>`
+//! - taproot refund: `<sig_S> <leaf_script> <control_block>`
+//!
+//! A refund needs the spending transaction's nLockTime at `timeout` or later and a non-final
+//! sequence on the input.
+
+use crate::nums;
+use crate::verify::verify_witness;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::{hash160, sha256, Hash};
+use bitcoin::key::KeyPair;
+use bitcoin::secp256k1::{Message, Secp256k1, Signing};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, ScriptPath, SighashCache, TapSighashType};
+use bitcoin::taproot::{self, LeafVersion};
+use bitcoin::{ecdsa, Address, Network, PrivateKey, ScriptBuf, Transaction, TxOut, Witness};
+use miniscript::bitcoin::PublicKey;
+use miniscript::{Descriptor, Miniscript, Tap};
+use std::str::FromStr;
+
+/// The hash a claim must reveal the preimage of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashLock {
+    Sha256(sha256::Hash),
+    Hash160(hash160::Hash),
+}
+
+impl HashLock {
+    pub fn sha256(preimage: &[u8; 32]) -> Self {
+        Self::Sha256(sha256::Hash::hash(preimage))
+    }
+
+    pub fn hash160(preimage: &[u8; 32]) -> Self {
+        Self::Hash160(hash160::Hash::hash(preimage))
+    }
+
+    pub fn matches(&self, preimage: &[u8; 32]) -> bool {
+        match self {
+            Self::Sha256(hash) => *hash == sha256::Hash::hash(preimage),
+            Self::Hash160(hash) => *hash == hash160::Hash::hash(preimage),
+        }
+    }
+
+    fn fragment(&self) -> String {
+        match self {
+            Self::Sha256(hash) => format!("sha256({})", hash),
+            Self::Hash160(hash) => format!("hash160({})", hash),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HtlcKind {
+    Wsh,
+    Tr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HtlcPath {
+    /// Receiver's signature and the preimage
+    Claim,
+    /// Sender's signature after the timeout
+    Refund,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtlcParams {
+    pub receiver: PublicKey,
+    pub sender: PublicKey,
+    pub hash_lock: HashLock,
+    /// Block height (or timestamp, from 500 000 000) the refund path unlocks at
+    pub timeout: u32,
+}
+
+/// A signature for one HTLC path: ECDSA for wsh, BIP340 for taproot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HtlcSignature {
+    Ecdsa(ecdsa::Signature),
+    Schnorr(taproot::Signature),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Htlc {
+    pub params: HtlcParams,
+    pub descriptor: Descriptor<PublicKey>,
+}
+
+impl Htlc {
+    pub fn new(kind: HtlcKind, params: HtlcParams) -> Result<Self, Box<dyn std::error::Error>> {
+        match kind {
+            HtlcKind::Wsh => Self::wsh(params),
+            HtlcKind::Tr => Self::tr(params),
+        }
+    }
+
+    pub fn wsh(params: HtlcParams) -> Result<Self, Box<dyn std::error::Error>> {
+        let descriptor = Descriptor::from_str(&format!(
+            "wsh(andor(pk({}),{},and_v(v:pk({}),after({}))))",
+            params.receiver,
+            params.hash_lock.fragment(),
+            params.sender,
+            params.timeout
+        ))?;
+        Ok(Self { params, descriptor })
+    }
+
+    pub fn tr(params: HtlcParams) -> Result<Self, Box<dyn std::error::Error>> {
+        let (internal_key, _) = nums::unspendable_internal_key(&format!("htlc/{}", params.hash_lock.fragment()))?;
+        let descriptor = Descriptor::from_str(&format!("tr({},{{{},{}}})", internal_key, claim_leaf(&params), refund_leaf(&params)))?;
+        Ok(Self { params, descriptor })
+    }
+
+    pub fn address(&self, network: Network) -> Result<Address, Box<dyn std::error::Error>> {
+        Ok(self.descriptor.address(network)?)
+    }
+
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        self.descriptor.script_pubkey()
+    }
+
+    /// nLockTime a refund transaction needs
+    pub fn refund_lock_time(&self) -> LockTime {
+        LockTime::from_consensus(self.params.timeout)
+    }
+
+    /// Key that signs for `path`
+    pub fn signing_key(&self, path: HtlcPath) -> PublicKey {
+        match path {
+            HtlcPath::Claim => self.params.receiver,
+            HtlcPath::Refund => self.params.sender,
+        }
+    }
+
+    /// Script executed for `path`: the witness script for wsh, the path's leaf for taproot
+    pub fn script(&self, path: HtlcPath) -> Result<ScriptBuf, Box<dyn std::error::Error>> {
+        match &self.descriptor {
+            Descriptor::Wsh(_) => Ok(self.descriptor.explicit_script()?),
+            Descriptor::Tr(_) => {
+                let leaf = match path {
+                    HtlcPath::Claim => claim_leaf(&self.params),
+                    HtlcPath::Refund => refund_leaf(&self.params),
+                };
+                Ok(Miniscript::<PublicKey, Tap>::from_str(&leaf)?.encode())
+            }
+            _ => Err("HTLCs are wsh or tr descriptors".into()),
+        }
+    }
+
+    /// Sign input `input_index` of `tx` for `path` with `key`: `SIGHASH_ALL` for wsh,
+    /// `SIGHASH_DEFAULT` for taproot. `prevouts` are the outputs spent by every input of `tx`,
+    /// in input order.
+    pub fn sign<C: Signing>(&self, secp: &Secp256k1<C>, tx: &Transaction, input_index: usize, prevouts: &[TxOut], path: HtlcPath, key: &PrivateKey) -> Result<HtlcSignature, Box<dyn std::error::Error>> {
+        if key.public_key(secp) != self.signing_key(path) {
+            return Err(format!("key does not sign the {:?} path", path).into());
+        }
+        let spent = prevouts.get(input_index).ok_or_else(|| format!("no prevout for input {}", input_index))?;
+        if spent.script_pubkey != self.script_pubkey() {
+            return Err(format!("input {} does not spend this HTLC", input_index).into());
+        }
+        let script = self.script(path)?;
+        let mut cache = SighashCache::new(tx);
+        match &self.descriptor {
+            Descriptor::Tr(_) => {
+                let sighash = cache.taproot_script_spend_signature_hash(input_index, &Prevouts::All(prevouts), ScriptPath::new(&script, LeafVersion::TapScript), TapSighashType::Default)?;
+                let keypair = KeyPair::from_secret_key(secp, &key.inner);
+                let sig = secp.sign_schnorr_no_aux_rand(&Message::from_slice(sighash.as_ref())?, &keypair);
+                Ok(HtlcSignature::Schnorr(taproot::Signature { sig, hash_ty: TapSighashType::Default }))
+            }
+            _ => {
+                let sighash = cache.segwit_signature_hash(input_index, &script, spent.value, EcdsaSighashType::All)?;
+                let sig = secp.sign_ecdsa(&Message::from_slice(sighash.as_ref())?, &key.inner);
+                Ok(HtlcSignature::Ecdsa(ecdsa::Signature::sighash_all(sig)))
+            }
+        }
+    }
+
+    /// Witness claiming input `input_index` of `tx` with the receiver's signature and `preimage`,
+    /// checked with `verify::verify_witness` against `prevouts` as in `sign`
+    pub fn claim_witness(&self, tx: &Transaction, input_index: usize, prevouts: &[TxOut], receiver_sig: &HtlcSignature, preimage: &[u8; 32]) -> Result<Witness, Box<dyn std::error::Error>> {
+        if !self.params.hash_lock.matches(preimage) {
+            return Err("preimage does not match the hash lock".into());
+        }
+        let mut witness = Witness::new();
+        witness.push(preimage);
+        witness.push(self.signature_bytes(receiver_sig)?);
+        self.push_script(&mut witness, HtlcPath::Claim)?;
+        verify_witness(tx, input_index, prevouts, &witness)?;
+        Ok(witness)
+    }
+
+    /// Witness refunding input `input_index` of `tx` with the sender's signature, checked like
+    /// `claim_witness`'s; `tx` must carry `refund_lock_time`
+    pub fn refund_witness(&self, tx: &Transaction, input_index: usize, prevouts: &[TxOut], sender_sig: &HtlcSignature) -> Result<Witness, Box<dyn std::error::Error>> {
+        let mut witness = Witness::new();
+        witness.push(self.signature_bytes(sender_sig)?);
+        if let Descriptor::Wsh(_) = self.descriptor {
+            witness.push(Vec::<u8>::new());
+        }
+        self.push_script(&mut witness, HtlcPath::Refund)?;
+        verify_witness(tx, input_index, prevouts, &witness)?;
+        Ok(witness)
+    }
+
+    fn signature_bytes(&self, sig: &HtlcSignature) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match (&self.descriptor, sig) {
+            (Descriptor::Tr(_), HtlcSignature::Schnorr(sig)) => Ok(sig.to_vec()),
+            (Descriptor::Wsh(_), HtlcSignature::Ecdsa(sig)) => Ok(sig.to_vec()),
+            _ => Err("signature type does not match the HTLC output type".into()),
+        }
+    }
+
+    fn push_script(&self, witness: &mut Witness, path: HtlcPath) -> Result<(), Box<dyn std::error::Error>> {
+        let script = self.script(path)?;
+        witness.push(script.as_bytes());
+        if let Descriptor::Tr(tr) = &self.descriptor {
+            let control_block = tr
+                .spend_info()
+                .control_block(&(script, LeafVersion::TapScript))
+                .ok_or("leaf is not part of the taproot tree")?;
+            witness.push(control_block.serialize());
+        }
+        Ok(())
+    }
+}
+
+fn claim_leaf(params: &HtlcParams) -> String {
+    format!("and_v(v:pk({}),{})", params.receiver, params.hash_lock.fragment())
+}
+
+fn refund_leaf(params: &HtlcParams) -> String {
+    format!("and_v(v:pk({}),after({}))", params.sender, params.timeout)
+}
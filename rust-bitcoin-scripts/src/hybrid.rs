@@ -0,0 +1,80 @@
+//! Hybrid spends: one transaction spending a vault UTXO (signed locally through its descriptor)
+//! together with a wallet UTXO that pays the fee (signed by the `WalletBackend`, `walletprocesspsbt` on Core)
+
+use crate::amount::deduct_fee;
+use crate::backend::WalletBackend;
+use crate::locktime::{self, LockTimePolicy};
+use crate::psbt;
+use miniscript::bitcoin::{PrivateKey, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::sighash::{SighashCache, EcdsaSighashType};
+use bitcoin::secp256k1::Message;
+use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness, absolute::LockTime};
+
+pub struct VaultInput {
+    pub outpoint: OutPoint,
+    pub amount: Amount,
+    pub descriptor: Descriptor<PublicKey>,
+    pub sequence: Sequence,
+}
+
+/// Build the unsigned hybrid PSBT: input 0 is the vault UTXO, input 1 a wallet UTXO large enough
+/// to cover `fee`. The whole vault amount goes to `destination`; wallet change returns to the wallet.
+/// `lock_time` is what the vault path requires; `policy` decides whether it is raised against fee sniping.
+pub async fn build_hybrid_psbt(wallet: &impl WalletBackend, vault: &VaultInput, destination: &str, fee: Amount, lock_time: LockTime, policy: LockTimePolicy) -> Result<Psbt, Box<dyn std::error::Error>> {
+    let lock_time = locktime::reconcile(lock_time, wallet.tip_height().await? as u32, policy);
+    let unspent = wallet.unspent(1).await?;
+    let wallet_utxo = unspent.iter()
+        .find(|u| u.spendable && u.amount > fee + Amount::from_sat(10_000))
+        .ok_or("no wallet UTXO large enough to pay the fee")?;
+    let wallet_outpoint = wallet_utxo.outpoint();
+    let wallet_amount = wallet_utxo.amount;
+    let wallet_script = wallet_utxo.script_pubkey.clone();
+    let change_address = wallet.change_address().await?;
+
+    let tx = Transaction {
+        version: 2,
+        lock_time,
+        input: vec![
+            TxIn { previous_output: vault.outpoint, script_sig: ScriptBuf::new(), sequence: vault.sequence, witness: Witness::default() },
+            TxIn { previous_output: wallet_outpoint, script_sig: ScriptBuf::new(), sequence: Sequence(0xfffffffd), witness: Witness::default() },
+        ],
+        output: vec![
+            TxOut { value: vault.amount.to_sat(), script_pubkey: wallet.parse_address(destination)?.script_pubkey() },
+            TxOut { value: deduct_fee(wallet_amount, fee)?.to_sat(), script_pubkey: change_address.script_pubkey() },
+        ],
+    };
+    let mut psbt = Psbt::from_unsigned_tx(tx)?;
+    psbt.inputs[0].witness_utxo = Some(TxOut { value: vault.amount.to_sat(), script_pubkey: vault.descriptor.script_pubkey() });
+    psbt.inputs[0].witness_script = Some(vault.descriptor.explicit_script()?);
+    psbt.inputs[1].witness_utxo = Some(TxOut { value: wallet_amount.to_sat(), script_pubkey: wallet_script });
+    Ok(psbt)
+}
+
+/// Add ECDSA partial signatures for a wsh input from `keys`
+pub fn sign_wsh_input(psbt: &mut Psbt, input_index: usize, keys: &[PrivateKey]) -> Result<(), Box<dyn std::error::Error>> {
+    let secp = secp256k1::Secp256k1::new();
+    let input = &psbt.inputs[input_index];
+    let witness_script = input.witness_script.clone().ok_or("input has no witness_script")?;
+    let value = input.witness_utxo.as_ref().ok_or("input has no witness_utxo")?.value;
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    let sighash = cache.segwit_signature_hash(input_index, &witness_script, value, EcdsaSighashType::All)?;
+    let msg = Message::from_slice(&sighash[..])?;
+    for key in keys {
+        let sig = secp.sign_ecdsa(&msg, &key.inner);
+        psbt.inputs[input_index].partial_sigs.insert(PublicKey::from_private_key(&secp, key), bitcoin::ecdsa::Signature::sighash_all(sig));
+    }
+    Ok(())
+}
+
+/// Coordinate both signing domains and return the fully signed transaction.
+/// `psbt::finalize` fails if either input is left without a final witness.
+pub async fn sign_hybrid(wallet: &impl WalletBackend, psbt: Psbt, vault_keys: &[PrivateKey]) -> Result<Transaction, Box<dyn std::error::Error>> {
+    let mut psbt = psbt::process_with_wallet(wallet, psbt).await?;
+    if psbt.inputs[1].partial_sigs.is_empty() && psbt.inputs[1].final_script_witness.is_none() {
+        return Err("node wallet did not sign its fee input".into());
+    }
+    sign_wsh_input(&mut psbt, 0, vault_keys)?;
+    psbt::finalize(psbt)
+}
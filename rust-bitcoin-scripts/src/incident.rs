@@ -0,0 +1,302 @@
+//! Incident response: freeze, trace and recover. Behind `wrapyield-cli incident`.
+//!
+//! - `freeze` / `unfreeze`: flag `AddressManager` and vault `Protocol` state files so no new
+//!   deposit address is handed out and no unbonding or withdrawal is built until the flag is
+//!   lifted. Every file is loaded before any is written, so a bad path changes nothing.
+//! - `trace_descendants`: follow the outputs of a suspicious transaction block by block with a
+//!   `BlockScanner`, which reports each tracked output it sees spent; the spending transactions'
+//!   outputs are tracked in turn, within the same block too. What is still unspent at the end is where
+//!   the coins sit now.
+//! - `prepare_recovery`: for every UTXO of every keystore entry, plan a spend with the keys we
+//!   hold and sign it to a safe destination, one transaction per UTXO so each can go out on its
+//!   own. Nothing is broadcast; UTXOs whose paths are not open to us yet (an immature timelock,
+//!   too few keys) are reported with the reason.
+
+use crate::addresses::AddressManager;
+use crate::backend::ChainBackend;
+use crate::fees::{self, FeePlan};
+use crate::keystore::{KeyEntry, Keystore};
+use crate::locktime::{self, LockTimePolicy};
+use crate::psbt::{self, SpendableUtxo};
+use crate::rpc_types::Utxo;
+use crate::scanner::{BlockScanner, ScanEvent, TrackedOutput};
+use crate::spend::{Planner, SpendPath};
+use crate::vault::Protocol;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Amount, FeeRate, OutPoint, PrivateKey, ScriptBuf, Transaction, TxOut, Txid};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Confirmation target of the recovery transactions' fee estimate
+pub const RECOVERY_CONF_TARGET: u16 = 1;
+
+/// State files changed by `freeze` or `unfreeze`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreezeReport {
+    pub address_files: Vec<PathBuf>,
+    /// Vault files with the coins each still holds, the candidates for recovery
+    pub vaults: Vec<(PathBuf, Option<(OutPoint, Amount)>)>,
+}
+
+impl fmt::Display for FreezeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for path in &self.address_files {
+            writeln!(f, "addresses: {}", path.display())?;
+        }
+        for (path, utxo) in &self.vaults {
+            match utxo {
+                Some((outpoint, amount)) => writeln!(f, "vault: {} holds {} at {}", path.display(), amount, outpoint)?,
+                None => writeln!(f, "vault: {} (withdrawn)", path.display())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Freeze address issuance in every `addresses` file and peg-outs in every `vaults` file
+pub fn freeze(addresses: &[PathBuf], vaults: &[PathBuf], reason: &str) -> Result<FreezeReport, Box<dyn std::error::Error>> {
+    set_frozen(addresses, vaults, Some(reason))
+}
+
+/// Lift a `freeze` from the same files
+pub fn unfreeze(addresses: &[PathBuf], vaults: &[PathBuf]) -> Result<FreezeReport, Box<dyn std::error::Error>> {
+    set_frozen(addresses, vaults, None)
+}
+
+fn set_frozen(addresses: &[PathBuf], vaults: &[PathBuf], reason: Option<&str>) -> Result<FreezeReport, Box<dyn std::error::Error>> {
+    let mut managers = Vec::new();
+    for path in addresses {
+        let manager = AddressManager::load(path)?.ok_or_else(|| format!("no address state at {}", path.display()))?;
+        managers.push((path, manager));
+    }
+    let mut protocols = Vec::new();
+    for path in vaults {
+        let protocol = Protocol::load(path)?.ok_or_else(|| format!("no vault state at {}", path.display()))?;
+        protocols.push((path, protocol));
+    }
+
+    let mut report = FreezeReport { address_files: Vec::new(), vaults: Vec::new() };
+    for (path, mut manager) in managers {
+        match reason {
+            Some(reason) => manager.freeze(reason),
+            None => manager.unfreeze(),
+        }
+        manager.save(path)?;
+        report.address_files.push(path.clone());
+    }
+    for (path, mut protocol) in protocols {
+        match reason {
+            Some(reason) => protocol.freeze(reason),
+            None => protocol.unfreeze(),
+        }
+        protocol.save(path)?;
+        report.vaults.push((path.clone(), protocol.utxo()));
+    }
+    Ok(report)
+}
+
+/// One output of the traced set being spent
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceHop {
+    /// 1 for spends of the suspicious transaction's own outputs, 2 for their children, ...
+    pub depth: u32,
+    pub spent: OutPoint,
+    pub value_sats: u64,
+    pub spending_txid: Txid,
+    pub input_index: usize,
+    pub height: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub root: Txid,
+    pub root_height: u64,
+    /// Last height scanned
+    pub scanned_to: u64,
+    /// In chain order
+    pub hops: Vec<TraceHop>,
+    /// Descendant outputs nobody spent by `scanned_to`, with their depth
+    pub unspent: Vec<(OutPoint, TrackedOutput, u32)>,
+}
+
+impl Trace {
+    /// Every transaction the coins went through, in chain order
+    pub fn descendants(&self) -> Vec<Txid> {
+        let mut seen = HashSet::new();
+        self.hops.iter().map(|hop| hop.spending_txid).filter(|txid| seen.insert(*txid)).collect()
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "root: {} at height {}, scanned to {}", self.root, self.root_height, self.scanned_to)?;
+        for hop in &self.hops {
+            writeln!(f, "  {}{} ({} sat) -> {}:{} at height {}", "  ".repeat(hop.depth as usize - 1), hop.spent, hop.value_sats, hop.spending_txid, hop.input_index, hop.height)?;
+        }
+        writeln!(f, "unspent:")?;
+        for (outpoint, output, depth) in &self.unspent {
+            writeln!(f, "  {} ({} sat, depth {}) {}", outpoint, output.value_sats, depth, output.script_pubkey.to_hex_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Outputs of a transaction in a verbosity 2 or 3 `getblock` result
+fn block_tx_outputs(tx: &Value) -> Result<Vec<TrackedOutput>, Box<dyn std::error::Error>> {
+    let mut outputs = Vec::new();
+    for out in tx["vout"].as_array().into_iter().flatten() {
+        outputs.push(TrackedOutput {
+            value_sats: Amount::from_btc(out["value"].as_f64().unwrap_or_default())?.to_sat(),
+            script_pubkey: ScriptBuf::from_bytes(hex::decode(out["scriptPubKey"]["hex"].as_str().unwrap_or_default())?),
+        });
+    }
+    Ok(outputs)
+}
+
+/// Track the spendable outputs of `txid` at `depth`
+fn track_outputs(scanner: &mut BlockScanner, depths: &mut HashMap<OutPoint, u32>, txid: Txid, outputs: Vec<TrackedOutput>, depth: u32) {
+    for (vout, output) in outputs.into_iter().enumerate() {
+        if output.script_pubkey.is_op_return() {
+            continue;
+        }
+        let outpoint = OutPoint::new(txid, vout as u32);
+        depths.insert(outpoint, depth);
+        scanner.track_outpoint(outpoint, output);
+    }
+}
+
+/// Follow every output of the confirmed transaction `root` through the blocks from its own up
+/// to `to` (default: the tip). On Core `root` is looked up with `getrawtransaction`, so the node
+/// needs `-txindex` unless it is a wallet transaction.
+pub async fn trace_descendants(backend: &impl ChainBackend, root: &Txid, to: Option<u64>) -> Result<Trace, Box<dyn std::error::Error>> {
+    let status = backend.tx_status(root).await?.ok_or_else(|| format!("{} is unknown to {}", root, backend.endpoint()))?;
+    let root_height = match status.block {
+        Some((_, height)) if status.confirmations > 0 => height,
+        _ => return Err(format!("{} is not confirmed", root).into()),
+    };
+    let found = backend.transaction(root).await?;
+    let to = match to {
+        Some(to) => to,
+        None => backend.tip_height().await?,
+    };
+    if to < root_height {
+        return Err(format!("{} confirmed at height {}, after {}", root, root_height, to).into());
+    }
+
+    let mut scanner = BlockScanner::new();
+    let mut depths = HashMap::new();
+    let outputs = found.output.iter().map(|out| TrackedOutput { value_sats: out.value, script_pubkey: out.script_pubkey.clone() }).collect();
+    track_outputs(&mut scanner, &mut depths, *root, outputs, 0);
+
+    let mut hops = Vec::new();
+    let mut expanded = HashSet::new();
+    for height in root_height..=to {
+        let hash = backend.block_hash(height).await?;
+        let block = scanner.fetch_block(backend, &hash).await?;
+        let txs = block["tx"].as_array().ok_or("getblock result has no tx array")?;
+        // A spend tracks new outputs, which later transactions of the same block may spend
+        loop {
+            let mut spent_any = false;
+            for event in scanner.scan_block_json(backend, &block).await? {
+                let ScanEvent::Spend { outpoint, value_sats, spending_txid, input_index, height } = event else { continue };
+                spent_any = true;
+                let depth = depths[&outpoint] + 1;
+                hops.push(TraceHop { depth, spent: outpoint, value_sats, spending_txid, input_index, height });
+                if expanded.insert(spending_txid) {
+                    let tx = txs.iter().find(|tx| tx["txid"].as_str() == Some(&spending_txid.to_string())).ok_or("spending transaction missing from its block")?;
+                    track_outputs(&mut scanner, &mut depths, spending_txid, block_tx_outputs(tx)?, depth);
+                }
+            }
+            if !spent_any {
+                break;
+            }
+        }
+    }
+
+    let mut unspent: Vec<(OutPoint, TrackedOutput, u32)> = scanner.tracked.into_iter().map(|(outpoint, output)| (outpoint, output, depths[&outpoint])).collect();
+    unspent.sort_by(|a, b| (a.2, a.0).cmp(&(b.2, b.0)));
+    Ok(Trace { root: *root, root_height, scanned_to: to, hops, unspent })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecoveryOutcome {
+    /// Signed and ready to broadcast
+    Ready { transaction: Transaction, path: SpendPath, fee: Amount },
+    /// No path we hold the keys for is open yet, or the UTXO cannot pay the fee
+    Blocked { reason: String },
+}
+
+/// The recovery prepared for one UTXO of keystore entry `entry`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedRecovery {
+    pub entry: String,
+    pub utxo: Utxo,
+    pub outcome: RecoveryOutcome,
+}
+
+impl fmt::Display for PreparedRecovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({}): ", self.entry, self.utxo.outpoint, self.utxo.amount)?;
+        match &self.outcome {
+            RecoveryOutcome::Ready { transaction, path, fee } => write!(f, "ready via {} paying {} fee\n  {}", path, fee, serialize_hex(transaction)),
+            RecoveryOutcome::Blocked { reason } => write!(f, "blocked: {}", reason),
+        }
+    }
+}
+
+/// Sign a spend of `utxo` to `destination` with the keys of `entry`, along the path `planner`
+/// picks; `prev_tx` is needed for non-segwit descriptors
+fn build_recovery(entry: &KeyEntry, utxo: &Utxo, prev_tx: Option<Transaction>, planner: &Planner, destination: &ScriptBuf, rate: FeeRate, tip: u32) -> Result<RecoveryOutcome, Box<dyn std::error::Error>> {
+    let secp = Secp256k1::new();
+    let plan = planner.plan(&entry.descriptor)?;
+    let definite = Descriptor::<DefiniteDescriptorKey>::from_str(&entry.descriptor.to_string())?;
+    let mut spendable = SpendableUtxo::new(utxo.outpoint, TxOut { value: utxo.amount.to_sat(), script_pubkey: utxo.script_pubkey.clone() });
+    spendable.sequence = plan.sequence;
+    spendable.prev_tx = prev_tx;
+    let fee_plan = FeePlan::sweep(&[&entry.descriptor], utxo.amount, destination.clone(), rate)?;
+    let lock_time = locktime::reconcile(plan.lock_time, tip, LockTimePolicy::default());
+    let mut unsigned = psbt::create(&definite, &[spendable], fee_plan.outputs, lock_time)?;
+    // Only the planned signers sign, so the finalizer cannot pick a different path
+    let signers: Vec<PrivateKey> = entry.signers().into_iter().filter(|k| plan.signers.contains(&k.public_key(&secp))).collect();
+    psbt::sign(&mut unsigned, &signers)?;
+    Ok(RecoveryOutcome::Ready { transaction: psbt::finalize(unsigned)?, path: plan.path, fee: fee_plan.fee })
+}
+
+/// Prepare a recovery to `destination` for every confirmed UTXO of the keystore entries `names`
+/// (all entries if empty). Nothing is broadcast.
+pub async fn prepare_recovery(backend: &impl ChainBackend, keystore: &Keystore, names: &[String], destination: &str) -> Result<Vec<PreparedRecovery>, Box<dyn std::error::Error>> {
+    let destination = backend.parse_address(destination)?.script_pubkey();
+    for name in names {
+        if keystore.get(name).is_none() {
+            return Err(format!("keystore has no entry named {}", name).into());
+        }
+    }
+    let tip = backend.chain_tip().await?;
+    let rate = fees::estimate_fee_rate(backend, RECOVERY_CONF_TARGET).await?;
+
+    let mut prepared = Vec::new();
+    for (name, entry) in &keystore.entries {
+        if !names.is_empty() && !names.contains(name) {
+            continue;
+        }
+        let keys: Vec<_> = entry.keys.iter().map(|k| k.public_key).collect();
+        let segwit = entry.descriptor.desc_type().segwit_version().is_some();
+        for utxo in backend.descriptor_utxos(&entry.descriptor).await?.into_iter().filter(|u| u.confirmations > 0) {
+            let prev_tx = match segwit {
+                true => None,
+                false => Some(backend.transaction(&utxo.outpoint.txid).await?),
+            };
+            let planner = Planner::new(keys.iter().copied(), tip.height as u32, tip.median_time_past as u32, utxo.confirmations);
+            let outcome = build_recovery(entry, &utxo, prev_tx, &planner, &destination, rate, tip.height as u32)
+                .unwrap_or_else(|e| RecoveryOutcome::Blocked { reason: e.to_string() });
+            prepared.push(PreparedRecovery { entry: name.clone(), utxo, outcome });
+        }
+    }
+    Ok(prepared)
+}
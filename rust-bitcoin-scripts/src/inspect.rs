@@ -0,0 +1,111 @@
+//! Descriptor summary behind `wrapyield-cli descriptor inspect`: the address on every network,
+//! the scripts, each spend path with the timelocks and preimages it needs, and the worst-case
+//! satisfaction weight.
+//!
+//! Descriptors are parsed with `DescriptorPublicKey`, so xpubs with origins and wildcards are
+//! accepted; a ranged descriptor is summarized at one index (`hd::derive`). Spend paths come
+//! from `path_matrix::cases`.
+
+use crate::hd;
+use crate::locktime::{LOCKTIME_THRESHOLD, SEQUENCE_TYPE_FLAG};
+use crate::path_matrix::{self, PathCase};
+use bitcoin::{Address, Network, ScriptBuf};
+use miniscript::bitcoin::PublicKey;
+use miniscript::descriptor::DescriptorPublicKey;
+use miniscript::Descriptor;
+use std::fmt;
+use std::str::FromStr;
+
+/// Networks an inspection lists an address for
+pub const NETWORKS: [Network; 4] = [Network::Bitcoin, Network::Testnet, Network::Signet, Network::Regtest];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorReport {
+    pub descriptor: Descriptor<PublicKey>,
+    /// Index the descriptor was derived at, if it is ranged
+    pub index: Option<u32>,
+    pub addresses: Vec<(Network, Address)>,
+    pub script_pubkey: ScriptBuf,
+    /// Witness or redeem script; `None` for taproot
+    pub script: Option<ScriptBuf>,
+    pub max_satisfaction_weight: usize,
+    pub paths: Vec<PathCase>,
+}
+
+impl DescriptorReport {
+    pub fn new(descriptor: &Descriptor<DescriptorPublicKey>, index: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let derived = hd::derive(descriptor, index)?;
+        let addresses = NETWORKS.iter().map(|n| Ok((*n, derived.address(*n)?))).collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+        Ok(Self {
+            index: descriptor.has_wildcard().then_some(index),
+            addresses,
+            script_pubkey: derived.script_pubkey(),
+            script: match derived {
+                Descriptor::Tr(_) => None,
+                _ => Some(derived.explicit_script()?),
+            },
+            max_satisfaction_weight: derived.max_weight_to_satisfy()?,
+            paths: path_matrix::cases(&derived)?,
+            descriptor: derived,
+        })
+    }
+}
+
+impl fmt::Display for DescriptorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "descriptor: {}", self.descriptor)?;
+        writeln!(f, "type: {:?}", self.descriptor.desc_type())?;
+        if let Some(index) = self.index {
+            writeln!(f, "index: {}", index)?;
+        }
+        for (network, address) in &self.addresses {
+            writeln!(f, "address ({}): {}", network, address)?;
+        }
+        writeln!(f, "script_pubkey: {}", self.script_pubkey.to_hex_string())?;
+        if let Some(script) = &self.script {
+            writeln!(f, "script: {}", script.to_hex_string())?;
+        }
+        writeln!(f, "max satisfaction weight: {} WU", self.max_satisfaction_weight)?;
+        writeln!(f, "spend paths:")?;
+        for (i, case) in self.paths.iter().enumerate() {
+            let needs = requirements(case);
+            if needs.is_empty() {
+                writeln!(f, "  {}. {}", i + 1, case)?;
+            } else {
+                writeln!(f, "  {}. {}; needs {}", i + 1, case, needs.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a spend along `case` needs besides signatures
+pub fn requirements(case: &PathCase) -> Vec<String> {
+    let mut needs = Vec::new();
+    if case.older > 0 {
+        let value = case.older & 0xffff;
+        if case.older & SEQUENCE_TYPE_FLAG != 0 {
+            needs.push(format!("nSequence of {} x 512 s", value));
+        } else {
+            needs.push(format!("nSequence of {} blocks", value));
+        }
+    }
+    if case.after >= LOCKTIME_THRESHOLD {
+        needs.push(format!("nLockTime at timestamp {} or later", case.after));
+    } else if case.after > 0 {
+        needs.push(format!("nLockTime at height {} or later", case.after));
+    }
+    if case.preimage {
+        needs.push("a hash preimage".to_string());
+    }
+    needs
+}
+
+/// Address of `descriptor` at `index` on `network`
+pub fn address(descriptor: &Descriptor<DescriptorPublicKey>, index: u32, network: Network) -> Result<Address, Box<dyn std::error::Error>> {
+    Ok(hd::derive(descriptor, index)?.address(network)?)
+}
+
+pub fn parse(descriptor: &str) -> Result<Descriptor<DescriptorPublicKey>, Box<dyn std::error::Error>> {
+    Ok(Descriptor::from_str(descriptor)?)
+}
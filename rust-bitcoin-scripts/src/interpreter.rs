@@ -0,0 +1,614 @@
+//! Step-by-step script evaluator that records every op with the stack before and after it.
+//!
+//! `verify::verify_spend` decides whether an input is valid with the miniscript interpreter,
+//! which only reports the fragments it satisfied. When an input fails, `trace_input` replays it
+//! here op by op so the failing opcode and the stack it saw are visible, instead of a bare
+//! `mandatory-script-verify-flag-failed`. Legacy P2PKH and P2SH, P2WPKH, P2WSH, P2SH-P2WSH and
+//! tapscript leaves are covered; a taproot key-path spend has no script to step through.
+//!
+//! The opcode set is what miniscript emits (stack and flow control, hashes, numeric comparison,
+//! CHECKSIG/CHECKMULTISIG/CHECKSIGADD, CLTV/CSV). It is a debugging aid and makes no claim to
+//! consensus completeness.
+
+use crate::locktime::{LOCKTIME_THRESHOLD, SEQUENCE_TYPE_FLAG};
+use bitcoin::blockdata::opcodes::all::*;
+use bitcoin::blockdata::opcodes::All as Opcode;
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::{ecdsa, Message, PublicKey, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache};
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash};
+use bitcoin::{Script, ScriptBuf, Transaction, TxIn, TxOut};
+use std::borrow::Borrow;
+use std::fmt;
+
+const SEQUENCE_DISABLE_FLAG: i64 = 1 << 31;
+const SEQUENCE_MASK: i64 = 0x0000_ffff;
+/// First byte of a taproot annex
+const ANNEX_TAG: u8 = 0x50;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceStep {
+    /// Index of the instruction within the executed script
+    pub position: usize,
+    /// `OP_CHECKSIG`, `PUSH 02ab..`, ...
+    pub op: String,
+    /// False when skipped inside an untaken IF branch
+    pub executed: bool,
+    pub stack_before: Vec<Vec<u8>>,
+    pub stack_after: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionTrace {
+    /// Every op of the script up to the one that failed; empty for inputs without a script
+    /// (taproot key path)
+    pub steps: Vec<TraceStep>,
+    /// `None` if the script succeeded
+    pub error: Option<String>,
+    /// What the failing op was stopped by, if it is one of the input's own stack elements or a
+    /// timelock field
+    pub blame: Option<Blame>,
+}
+
+impl ExecutionTrace {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// The step that failed, if any: the failing op, or the last one before a final stack that
+    /// does not leave exactly one true element
+    pub fn failing_step(&self) -> Option<&TraceStep> {
+        self.error.as_ref().and(self.steps.last())
+    }
+}
+
+/// What a failing input is pinned on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Blame {
+    /// Element `index` of the input's initial stack, counted from the bottom as serialized in
+    /// the witness (or scriptSig); scripts and control blocks are never blamed
+    Element { index: usize, value: Vec<u8> },
+    /// The transaction's nLockTime does not satisfy an `OP_CHECKLOCKTIMEVERIFY`
+    LockTime,
+    /// The input's nSequence does not satisfy an `OP_CHECKSEQUENCEVERIFY`
+    Sequence,
+}
+
+impl fmt::Display for Blame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Blame::Element { index, value } if value.is_empty() => write!(f, "witness element {} (<>)", index),
+            Blame::Element { index, value } => write!(f, "witness element {} ({})", index, hex::encode(value)),
+            Blame::LockTime => write!(f, "nLockTime"),
+            Blame::Sequence => write!(f, "nSequence"),
+        }
+    }
+}
+
+fn fmt_stack(stack: &[Vec<u8>]) -> String {
+    let items: Vec<String> = stack.iter().map(|e| if e.is_empty() { "<>".to_string() } else { hex::encode(e) }).collect();
+    format!("[{}]", items.join(" "))
+}
+
+impl fmt::Display for ExecutionTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            let marker = if step.executed { " " } else { "~" };
+            writeln!(f, "{}{:>3} {:<24} {} -> {}", marker, step.position, step.op, fmt_stack(&step.stack_before), fmt_stack(&step.stack_after))?;
+        }
+        match &self.error {
+            Some(e) => write!(f, "FAILED: {}", e),
+            None => write!(f, "OK"),
+        }
+    }
+}
+
+fn cast_to_bool(v: &[u8]) -> bool {
+    for (i, b) in v.iter().enumerate() {
+        if *b != 0 {
+            return !(i == v.len() - 1 && *b == 0x80);
+        }
+    }
+    false
+}
+
+fn decode_num(v: &[u8], max_len: usize) -> Result<i64, String> {
+    if v.len() > max_len {
+        return Err(format!("script number of {} bytes exceeds {}", v.len(), max_len));
+    }
+    if v.is_empty() {
+        return Ok(0);
+    }
+    let mut result: i64 = 0;
+    for (i, b) in v.iter().enumerate() {
+        result |= (*b as i64) << (8 * i);
+    }
+    if v[v.len() - 1] & 0x80 != 0 {
+        Ok(-(result & !(0x80i64 << (8 * (v.len() - 1)))))
+    } else {
+        Ok(result)
+    }
+}
+
+fn encode_num(n: i64) -> Vec<u8> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let negative = n < 0;
+    let mut abs = n.unsigned_abs();
+    let mut out = Vec::new();
+    while abs > 0 {
+        out.push((abs & 0xff) as u8);
+        abs >>= 8;
+    }
+    if out[out.len() - 1] & 0x80 != 0 {
+        out.push(if negative { 0x80 } else { 0 });
+    } else if negative {
+        let last = out.len() - 1;
+        out[last] |= 0x80;
+    }
+    out
+}
+
+fn encode_bool(b: bool) -> Vec<u8> {
+    if b { vec![1] } else { Vec::new() }
+}
+
+/// Which signature message the script's signature checks commit to
+#[derive(Debug, Clone, Copy)]
+enum SigVersion {
+    Legacy,
+    WitnessV0 { amount_sats: u64 },
+    Tapscript { leaf_hash: TapLeafHash },
+}
+
+/// What the failing op was stopped by, before it is located on the initial stack
+#[derive(Debug, Clone)]
+enum Culprit {
+    Operand(Vec<u8>),
+    LockTime,
+    Sequence,
+}
+
+struct Machine<'a, T: Borrow<TxOut>> {
+    tx: &'a Transaction,
+    input_index: usize,
+    prevouts: &'a Prevouts<'a, T>,
+    script: &'a Script,
+    version: SigVersion,
+    stack: Vec<Vec<u8>>,
+    alt: Vec<Vec<u8>>,
+    culprit: Option<Culprit>,
+}
+
+impl<'a, T: Borrow<TxOut>> Machine<'a, T> {
+    fn pop(&mut self) -> Result<Vec<u8>, String> {
+        self.stack.pop().ok_or_else(|| "stack underflow".to_string())
+    }
+
+    fn pop_num(&mut self) -> Result<i64, String> {
+        decode_num(&self.pop()?, 4)
+    }
+
+    /// Blame `operand` for the error about to be returned
+    fn blame(&mut self, operand: &[u8], error: String) -> String {
+        self.culprit = Some(Culprit::Operand(operand.to_vec()));
+        error
+    }
+
+    fn check_sig(&mut self, sig: &[u8], pubkey: &[u8]) -> Result<bool, String> {
+        if sig.is_empty() {
+            return Ok(false);
+        }
+        let secp = Secp256k1::verification_only();
+        if let SigVersion::Tapscript { leaf_hash } = self.version {
+            match pubkey.len() {
+                0 => return Err(self.blame(pubkey, "empty public key".to_string())),
+                32 => {}
+                // Unknown key types are left to future soft forks and succeed
+                _ => return Ok(true),
+            }
+            let signature = bitcoin::taproot::Signature::from_slice(sig).map_err(|e| self.blame(sig, format!("bad signature encoding: {}", e)))?;
+            let key = XOnlyPublicKey::from_slice(pubkey).map_err(|e| self.blame(pubkey, format!("bad public key: {}", e)))?;
+            let sighash = SighashCache::new(self.tx)
+                .taproot_script_spend_signature_hash(self.input_index, self.prevouts, leaf_hash, signature.hash_ty)
+                .map_err(|e| self.blame(sig, e.to_string()))?;
+            let msg = Message::from_slice(sighash.as_ref()).map_err(|e| e.to_string())?;
+            return Ok(secp.verify_schnorr(&signature.sig, &msg, &key).is_ok());
+        }
+        let (der, hash_type) = sig.split_at(sig.len() - 1);
+        let mut signature = ecdsa::Signature::from_der(der).map_err(|e| self.blame(sig, format!("bad signature encoding: {}", e)))?;
+        signature.normalize_s();
+        let key = PublicKey::from_slice(pubkey).map_err(|e| self.blame(pubkey, format!("bad public key: {}", e)))?;
+        let msg = match self.version {
+            SigVersion::WitnessV0 { amount_sats } => {
+                let hash_type = EcdsaSighashType::from_standard(hash_type[0] as u32).map_err(|e| self.blame(sig, e.to_string()))?;
+                let sighash = SighashCache::new(self.tx)
+                    .segwit_signature_hash(self.input_index, self.script, amount_sats, hash_type)
+                    .map_err(|e| e.to_string())?;
+                Message::from_slice(&sighash[..])
+            }
+            _ => {
+                let sighash = SighashCache::new(self.tx)
+                    .legacy_signature_hash(self.input_index, self.script, hash_type[0] as u32)
+                    .map_err(|e| e.to_string())?;
+                Message::from_slice(&sighash[..])
+            }
+        }
+        .map_err(|e| e.to_string())?;
+        Ok(secp.verify_ecdsa(&msg, &signature, &key).is_ok())
+    }
+
+    fn check_multisig(&mut self) -> Result<bool, String> {
+        let n = self.pop_num()?;
+        if !(0..=20).contains(&n) {
+            return Err(format!("invalid key count {}", n));
+        }
+        let mut keys: Vec<Vec<u8>> = (0..n).map(|_| self.pop()).collect::<Result<_, _>>()?;
+        let m = self.pop_num()?;
+        if m < 0 || m > n {
+            return Err(format!("invalid signature count {}", m));
+        }
+        let mut sigs: Vec<Vec<u8>> = (0..m).map(|_| self.pop()).collect::<Result<_, _>>()?;
+        let dummy = self.pop()?;
+        if !dummy.is_empty() {
+            return Err(self.blame(&dummy, "CHECKMULTISIG dummy element must be empty".to_string()));
+        }
+        // Popped top-first; consensus matches signatures and keys in push order
+        keys.reverse();
+        sigs.reverse();
+        let mut key_iter = keys.iter();
+        for sig in &sigs {
+            loop {
+                match key_iter.next() {
+                    Some(key) if self.check_sig(sig, key)? => break,
+                    Some(_) => continue,
+                    // The first signature no remaining key accepts is the bad one
+                    None if !sig.is_empty() => {
+                        return Err(self.blame(sig, "non-empty signature failed verification (NULLFAIL)".to_string()));
+                    }
+                    None => match sigs.iter().find(|s| !s.is_empty()) {
+                        Some(other) => return Err(self.blame(other, "non-empty signature failed verification (NULLFAIL)".to_string())),
+                        None => return Ok(false),
+                    },
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn check_lock_time(&self, required: i64) -> Result<(), String> {
+        if required < 0 {
+            return Err("negative locktime".to_string());
+        }
+        let threshold = LOCKTIME_THRESHOLD as i64;
+        let lock_time = self.tx.lock_time.to_consensus_u32() as i64;
+        if (required < threshold) != (lock_time < threshold) {
+            return Err(format!("locktime type mismatch: script {} vs tx {}", required, lock_time));
+        }
+        if required > lock_time {
+            return Err(format!("nLockTime {} below required {}", lock_time, required));
+        }
+        if self.tx.input[self.input_index].sequence.is_final() {
+            return Err("input sequence is final, nLockTime is disabled".to_string());
+        }
+        Ok(())
+    }
+
+    fn check_sequence(&self, required: i64) -> Result<(), String> {
+        if required < 0 {
+            return Err("negative sequence".to_string());
+        }
+        if required & SEQUENCE_DISABLE_FLAG != 0 {
+            return Ok(());
+        }
+        if self.tx.version < 2 {
+            return Err(format!("CSV requires version 2, tx is version {}", self.tx.version));
+        }
+        let sequence = self.tx.input[self.input_index].sequence.to_consensus_u32() as i64;
+        if sequence & SEQUENCE_DISABLE_FLAG != 0 {
+            return Err("input sequence has the disable flag set".to_string());
+        }
+        let type_flag = SEQUENCE_TYPE_FLAG as i64;
+        let mask = type_flag | SEQUENCE_MASK;
+        let (required, sequence) = (required & mask, sequence & mask);
+        if (required & type_flag) != (sequence & type_flag) {
+            return Err("relative locktime type mismatch".to_string());
+        }
+        if required > sequence {
+            return Err(format!("nSequence {} below required {}", sequence, required));
+        }
+        Ok(())
+    }
+
+    fn verify(&mut self, what: &str) -> Result<(), String> {
+        if cast_to_bool(&self.pop()?) {
+            Ok(())
+        } else {
+            Err(format!("{} failed", what))
+        }
+    }
+
+    fn step(&mut self, op: Opcode) -> Result<(), String> {
+        match op {
+            OP_PUSHNUM_NEG1 => self.stack.push(encode_num(-1)),
+            o if o.to_u8() >= OP_PUSHNUM_1.to_u8() && o.to_u8() <= OP_PUSHNUM_16.to_u8() => {
+                self.stack.push(encode_num((o.to_u8() - OP_PUSHNUM_1.to_u8() + 1) as i64))
+            }
+            OP_NOP => {}
+            OP_VERIFY => self.verify("OP_VERIFY")?,
+            OP_RETURN => return Err("OP_RETURN".to_string()),
+            OP_TOALTSTACK => {
+                let v = self.pop()?;
+                self.alt.push(v);
+            }
+            OP_FROMALTSTACK => {
+                let v = self.alt.pop().ok_or("alt stack underflow")?;
+                self.stack.push(v);
+            }
+            OP_IFDUP => {
+                let top = self.stack.last().ok_or("stack underflow")?.clone();
+                if cast_to_bool(&top) {
+                    self.stack.push(top);
+                }
+            }
+            OP_DEPTH => self.stack.push(encode_num(self.stack.len() as i64)),
+            OP_DROP => {
+                self.pop()?;
+            }
+            OP_2DROP => {
+                self.pop()?;
+                self.pop()?;
+            }
+            OP_DUP => {
+                let top = self.stack.last().ok_or("stack underflow")?.clone();
+                self.stack.push(top);
+            }
+            OP_SWAP => {
+                let len = self.stack.len();
+                if len < 2 {
+                    return Err("stack underflow".to_string());
+                }
+                self.stack.swap(len - 1, len - 2);
+            }
+            OP_SIZE => {
+                let len = self.stack.last().ok_or("stack underflow")?.len();
+                self.stack.push(encode_num(len as i64));
+            }
+            OP_EQUAL | OP_EQUALVERIFY => {
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.stack.push(encode_bool(a == b));
+                if op == OP_EQUALVERIFY {
+                    self.verify("OP_EQUALVERIFY")?;
+                }
+            }
+            OP_1ADD => {
+                let n = self.pop_num()?;
+                self.stack.push(encode_num(n + 1));
+            }
+            OP_NOT => {
+                let n = self.pop_num()?;
+                self.stack.push(encode_bool(n == 0));
+            }
+            OP_0NOTEQUAL => {
+                let n = self.pop_num()?;
+                self.stack.push(encode_bool(n != 0));
+            }
+            OP_ADD | OP_SUB | OP_BOOLAND | OP_BOOLOR | OP_NUMEQUAL | OP_NUMEQUALVERIFY | OP_LESSTHAN | OP_GREATERTHAN => {
+                let (b, a) = (self.pop_num()?, self.pop_num()?);
+                let result = match op {
+                    OP_ADD => encode_num(a + b),
+                    OP_SUB => encode_num(a - b),
+                    OP_BOOLAND => encode_bool(a != 0 && b != 0),
+                    OP_BOOLOR => encode_bool(a != 0 || b != 0),
+                    OP_LESSTHAN => encode_bool(a < b),
+                    OP_GREATERTHAN => encode_bool(a > b),
+                    _ => encode_bool(a == b),
+                };
+                self.stack.push(result);
+                if op == OP_NUMEQUALVERIFY {
+                    self.verify("OP_NUMEQUALVERIFY")?;
+                }
+            }
+            OP_RIPEMD160 => {
+                let v = self.pop()?;
+                self.stack.push(ripemd160::Hash::hash(&v).to_byte_array().to_vec());
+            }
+            OP_SHA256 => {
+                let v = self.pop()?;
+                self.stack.push(sha256::Hash::hash(&v).to_byte_array().to_vec());
+            }
+            OP_HASH160 => {
+                let v = self.pop()?;
+                self.stack.push(hash160::Hash::hash(&v).to_byte_array().to_vec());
+            }
+            OP_HASH256 => {
+                let v = self.pop()?;
+                self.stack.push(sha256d::Hash::hash(&v).to_byte_array().to_vec());
+            }
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                let (pubkey, sig) = (self.pop()?, self.pop()?);
+                let ok = self.check_sig(&sig, &pubkey)?;
+                if !ok && !sig.is_empty() {
+                    return Err(self.blame(&sig, "non-empty signature failed verification (NULLFAIL)".to_string()));
+                }
+                self.stack.push(encode_bool(ok));
+                if op == OP_CHECKSIGVERIFY {
+                    self.verify("OP_CHECKSIGVERIFY")?;
+                }
+            }
+            OP_CHECKSIGADD => {
+                if !matches!(self.version, SigVersion::Tapscript { .. }) {
+                    return Err("OP_CHECKSIGADD outside tapscript".to_string());
+                }
+                let pubkey = self.pop()?;
+                let n = self.pop_num()?;
+                let sig = self.pop()?;
+                let ok = self.check_sig(&sig, &pubkey)?;
+                if !ok && !sig.is_empty() {
+                    return Err(self.blame(&sig, "non-empty signature failed verification".to_string()));
+                }
+                self.stack.push(encode_num(n + i64::from(ok)));
+            }
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                if matches!(self.version, SigVersion::Tapscript { .. }) {
+                    return Err(format!("{} is disabled in tapscript", op));
+                }
+                let ok = self.check_multisig()?;
+                self.stack.push(encode_bool(ok));
+                if op == OP_CHECKMULTISIGVERIFY {
+                    self.verify("OP_CHECKMULTISIGVERIFY")?;
+                }
+            }
+            OP_CLTV => {
+                let required = decode_num(self.stack.last().ok_or("stack underflow")?, 5)?;
+                self.check_lock_time(required).map_err(|e| {
+                    self.culprit = Some(Culprit::LockTime);
+                    e
+                })?;
+            }
+            OP_CSV => {
+                let required = decode_num(self.stack.last().ok_or("stack underflow")?, 5)?;
+                self.check_sequence(required).map_err(|e| {
+                    self.culprit = Some(Culprit::Sequence);
+                    e
+                })?;
+            }
+            other => return Err(format!("unsupported opcode {}", other)),
+        }
+        Ok(())
+    }
+}
+
+/// The pushes of a push-only scriptSig, bottom of the stack first
+fn pushes(script_sig: &Script) -> Option<Vec<Vec<u8>>> {
+    script_sig.instructions()
+        .map(|i| match i.ok()? {
+            Instruction::PushBytes(bytes) => Some(bytes.as_bytes().to_vec()),
+            Instruction::Op(op) if op == OP_PUSHNUM_NEG1 || (op.to_u8() >= OP_PUSHNUM_1.to_u8() && op.to_u8() <= OP_PUSHNUM_16.to_u8()) => {
+                Some(encode_num(op.to_u8() as i64 - OP_PUSHNUM_1.to_u8() as i64 + 1))
+            }
+            Instruction::Op(_) => None,
+        })
+        .collect()
+}
+
+/// The script `input` executes against `prevout`, the stack it starts from and how its
+/// signatures are hashed; `None` for a taproot key-path spend or an output type without one
+fn script_of(input: &TxIn, prevout: &TxOut) -> Option<(ScriptBuf, Vec<Vec<u8>>, SigVersion)> {
+    let spk = &prevout.script_pubkey;
+    let script_sig = pushes(&input.script_sig)?;
+    // The witness program: the output itself, or the redeem script of a nested segwit output
+    let program = match script_sig.last() {
+        Some(redeem) if spk.is_p2sh() && Script::from_bytes(redeem).is_witness_program() => ScriptBuf::from(redeem.clone()),
+        _ => spk.clone(),
+    };
+    let witness = input.witness.to_vec();
+    let v0 = SigVersion::WitnessV0 { amount_sats: prevout.value };
+    if program.is_v0_p2wpkh() {
+        Some((program.p2wpkh_script_code()?, witness, v0))
+    } else if program.is_v0_p2wsh() {
+        let (script, stack) = witness.split_last()?;
+        Some((ScriptBuf::from(script.clone()), stack.to_vec(), v0))
+    } else if program.is_v1_p2tr() {
+        let mut items = witness;
+        if items.len() >= 2 && items.last()?.first() == Some(&ANNEX_TAG) {
+            items.pop();
+        }
+        if items.len() < 2 {
+            return None;
+        }
+        let control = ControlBlock::decode(&items.pop()?).ok()?;
+        let script = ScriptBuf::from(items.pop()?);
+        if control.leaf_version != LeafVersion::TapScript {
+            return None;
+        }
+        let leaf_hash = TapLeafHash::from_script(&script, control.leaf_version);
+        Some((script, items, SigVersion::Tapscript { leaf_hash }))
+    } else if spk.is_p2sh() {
+        let (redeem, stack) = script_sig.split_last()?;
+        Some((ScriptBuf::from(redeem.clone()), stack.to_vec(), SigVersion::Legacy))
+    } else if spk.is_p2pkh() {
+        Some((spk.clone(), script_sig, SigVersion::Legacy))
+    } else {
+        None
+    }
+}
+
+/// Replay input `input_index` of `tx` op by op against `prevout`, the output it spends;
+/// `prevouts` are the outputs every input spends, which tapscript signatures commit to. `None`
+/// if the input has no script to step through.
+pub fn trace_input<T: Borrow<TxOut>>(tx: &Transaction, input_index: usize, prevout: &TxOut, prevouts: &Prevouts<'_, T>) -> Option<ExecutionTrace> {
+    let (script, stack, version) = script_of(tx.input.get(input_index)?, prevout)?;
+    let elements = stack.clone();
+    let mut machine = Machine { tx, input_index, prevouts, script: &script, version, stack, alt: Vec::new(), culprit: None };
+    let mut steps = Vec::new();
+    let mut exec: Vec<bool> = Vec::new();
+
+    for (position, instruction) in script.instructions().enumerate() {
+        let stack_before = machine.stack.clone();
+        let executing = exec.iter().all(|e| *e);
+        let (op_name, result) = match instruction {
+            Err(e) => (format!("<invalid: {}>", e), Err(e.to_string())),
+            Ok(Instruction::PushBytes(bytes)) => {
+                if executing {
+                    machine.stack.push(bytes.as_bytes().to_vec());
+                }
+                let name = if bytes.is_empty() { "OP_0".to_string() } else { format!("PUSH {}", hex::encode(bytes.as_bytes())) };
+                (name, Ok(()))
+            }
+            Ok(Instruction::Op(op)) => {
+                let result = match op {
+                    OP_IF | OP_NOTIF => {
+                        if executing {
+                            machine.pop().map(|v| {
+                                let truth = cast_to_bool(&v);
+                                exec.push(if op == OP_IF { truth } else { !truth });
+                            })
+                        } else {
+                            exec.push(false);
+                            Ok(())
+                        }
+                    }
+                    OP_ELSE => match exec.last_mut() {
+                        Some(top) => {
+                            *top = !*top;
+                            Ok(())
+                        }
+                        None => Err("OP_ELSE without OP_IF".to_string()),
+                    },
+                    OP_ENDIF => exec.pop().map(|_| ()).ok_or_else(|| "OP_ENDIF without OP_IF".to_string()),
+                    _ if !executing => Ok(()),
+                    _ => machine.step(op),
+                };
+                (op.to_string(), result)
+            }
+        };
+        steps.push(TraceStep { position, op: op_name, executed: executing, stack_before, stack_after: machine.stack.clone() });
+        if let Err(error) = result {
+            let blame = match machine.culprit {
+                Some(Culprit::LockTime) => Some(Blame::LockTime),
+                Some(Culprit::Sequence) => Some(Blame::Sequence),
+                // Located by value: signatures, keys and preimages do not repeat on a stack
+                Some(Culprit::Operand(value)) if !value.is_empty() => elements.iter()
+                    .rposition(|e| *e == value)
+                    .map(|index| Blame::Element { index, value }),
+                _ => None,
+            };
+            return Some(ExecutionTrace { steps, error: Some(error), blame });
+        }
+    }
+
+    let error = if !exec.is_empty() {
+        Some("unbalanced conditional".to_string())
+    } else if machine.stack.len() != 1 {
+        Some(format!("script left {} stack elements, expected exactly 1", machine.stack.len()))
+    } else if !cast_to_bool(&machine.stack[0]) {
+        Some("script evaluated to false".to_string())
+    } else {
+        None
+    };
+    Some(ExecutionTrace { steps, error, blame: None })
+}
@@ -0,0 +1,152 @@
+//! Encrypted store of generated descriptors and their private keys, so a vault created in one
+//! run can be spent in a later one.
+//!
+//! The file is `MAGIC || salt || nonce || XChaCha20-Poly1305(JSON)` with the same Argon2id
+//! passphrase scheme as `backup`. Entries are stored by name as descriptor string, network and WIF
+//! keys; loading parses them back and checks that every key appears in its descriptor.
+
+use crate::backup::{open, seal};
+use crate::classic_multisig::MultisigInfo;
+use crate::secret::SigningKey;
+use crate::state_file;
+use miniscript::bitcoin::{Address, Network, PrivateKey, PublicKey};
+use miniscript::{Descriptor, ForEachKey};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use zeroize::{Zeroize, Zeroizing};
+
+pub const MAGIC: &[u8; 6] = b"WYKEY1";
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyEntry {
+    pub descriptor: Descriptor<PublicKey>,
+    pub network: Network,
+    /// Keys held for the descriptor; may be fewer than it names
+    pub keys: Vec<SigningKey>,
+}
+
+impl KeyEntry {
+    pub fn new(descriptor: Descriptor<PublicKey>, network: Network, keys: Vec<SigningKey>) -> Result<Self, Box<dyn std::error::Error>> {
+        let entry = Self { descriptor, network, keys };
+        entry.check()?;
+        Ok(entry)
+    }
+
+    pub fn from_multisig(info: &MultisigInfo, network: Network) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(Descriptor::from_str(&info.descriptor)?, network, info.private_keys.clone())
+    }
+
+    fn check(&self) -> Result<(), Box<dyn std::error::Error>> {
+        for key in &self.keys {
+            if key.network != self.network {
+                return Err(format!("key {} is for {}, the entry for {}", key.public_key, key.network, self.network).into());
+            }
+            if !self.descriptor.for_any_key(|k| *k == key.public_key || k.inner.x_only_public_key().0 == key.public_key.inner.x_only_public_key().0) {
+                return Err(format!("key {} does not appear in {}", key.public_key, self.descriptor).into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn address(&self) -> Result<Address, Box<dyn std::error::Error>> {
+        Ok(self.descriptor.address(self.network)?)
+    }
+
+    /// Temporary signing keys, for `psbt::sign` and friends; do not store them
+    pub fn signers(&self) -> Vec<PrivateKey> {
+        self.keys.iter().map(SigningKey::expose).collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredEntry {
+    descriptor: String,
+    network: Network,
+    wif_keys: Vec<String>,
+}
+
+impl Drop for StoredEntry {
+    fn drop(&mut self) {
+        self.wif_keys.zeroize();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredKeystore {
+    version: u32,
+    entries: BTreeMap<String, StoredEntry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Keystore {
+    pub entries: BTreeMap<String, KeyEntry>,
+}
+
+impl Keystore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `entry` under `name`; an existing entry is only replaced with `overwrite`
+    pub fn insert(&mut self, name: &str, entry: KeyEntry, overwrite: bool) -> Result<(), Box<dyn std::error::Error>> {
+        if !overwrite && self.entries.contains_key(name) {
+            return Err(format!("keystore already has an entry named {}", name).into());
+        }
+        self.entries.insert(name.to_string(), entry);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&KeyEntry> {
+        self.entries.get(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<KeyEntry> {
+        self.entries.remove(name)
+    }
+
+    pub fn encrypt(&self, passphrase: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let stored = StoredKeystore {
+            version: FORMAT_VERSION,
+            entries: self.entries.iter().map(|(name, entry)| (name.clone(), StoredEntry {
+                descriptor: entry.descriptor.to_string(),
+                network: entry.network,
+                wif_keys: entry.keys.iter().map(|k| k.to_wif().to_string()).collect(),
+            })).collect(),
+        };
+        let plaintext = Zeroizing::new(serde_json::to_vec(&stored)?);
+        seal(MAGIC, &plaintext, passphrase)
+    }
+
+    /// Decrypt and rebuild every entry; a wrong passphrase, a modified file or a key that does
+    /// not belong to its descriptor all fail
+    pub fn decrypt(sealed: &[u8], passphrase: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let plaintext = open(MAGIC, "keystore", sealed, passphrase)?;
+        let stored: StoredKeystore = serde_json::from_slice(&plaintext)?;
+        if stored.version != FORMAT_VERSION {
+            return Err(format!("unsupported keystore version {}", stored.version).into());
+        }
+        let mut keystore = Self::new();
+        for (name, entry) in stored.entries {
+            let keys = entry.wif_keys.iter().map(|wif| SigningKey::from_wif_for(wif, entry.network)).collect::<Result<Vec<_>, _>>()?;
+            let descriptor = Descriptor::from_str(&entry.descriptor)?;
+            let entry = KeyEntry::new(descriptor, entry.network, keys).map_err(|e| format!("entry {}: {}", name, e))?;
+            keystore.entries.insert(name, entry);
+        }
+        Ok(keystore)
+    }
+
+    pub fn save(&self, path: &Path, passphrase: &str) -> Result<(), Box<dyn std::error::Error>> {
+        state_file::write_atomic(path, &self.encrypt(passphrase)?)
+    }
+
+    /// `None` if nothing was saved yet
+    pub fn load(path: &Path, passphrase: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match state_file::read_if_exists(path)? {
+            Some(contents) => Ok(Some(Self::decrypt(&contents, passphrase)?)),
+            None => Ok(None),
+        }
+    }
+}
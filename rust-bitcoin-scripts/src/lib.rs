@@ -2,4 +2,76 @@ pub mod classic_multisig;
 pub mod timelock_cltv;
 pub mod timelock_csv;
 pub mod test_setup;
-pub mod simple_taproot; 
\ No newline at end of file
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod simple_taproot;
+pub mod migration;
+pub mod report;
+pub mod schema;
+pub mod manifest;
+pub mod scanner;
+pub mod psbt;
+pub mod hybrid;
+pub mod broadcast;
+pub mod watch_only;
+pub mod access;
+pub mod webhooks;
+pub mod signing_round;
+pub mod reservation;
+pub mod actors;
+pub mod tenancy;
+pub mod templates;
+pub mod taproot_tree;
+pub mod lint;
+pub mod weak_keys;
+pub mod secret;
+pub mod attestation;
+pub mod differential;
+pub mod witness;
+pub mod taproot;
+pub mod cancel;
+pub mod service;
+pub mod backup;
+pub mod read_only;
+pub mod replay;
+pub mod rpc_types;
+pub mod spend;
+pub mod sighash;
+pub mod flows;
+pub mod amount;
+pub mod interpreter;
+pub mod verify;
+pub mod validate;
+pub mod fees;
+pub mod op_return;
+pub mod headers;
+pub mod locktime;
+pub mod outputs;
+pub mod keystore;
+pub mod hd;
+pub mod addresses;
+pub mod clawback;
+pub mod analyzer;
+pub mod nums;
+pub mod vault;
+pub mod recovery;
+pub mod state_file;
+pub mod sweep;
+pub mod receipt;
+pub mod txbuilder;
+pub mod path_matrix;
+pub mod htlc;
+pub mod swap;
+pub mod inspect;
+pub mod demo;
+pub mod backend;
+pub mod provenance;
+pub mod satisfier;
+pub mod incident;
+pub mod telemetry;
+pub mod watchtower;
+pub mod tower_client;
+pub mod timeout_tree;
+pub mod fee_market;
+pub mod consolidation;
+pub mod accounting;
@@ -0,0 +1,89 @@
+//! Vault linter: flags key reuse and duplicate leaves that weaken a vault template.
+//!
+//! Spend paths are the taproot leaves (plus the key path) for `tr`, and the top-level branches
+//! of the normalized semantic policy for everything else. Keys are compared by x-only
+//! serialization so a compressed key and its tapscript form count as the same key.
+
+use crate::taproot_tree::x_only;
+use miniscript::bitcoin::PublicKey;
+use miniscript::policy::{Liftable, Semantic};
+use miniscript::{Descriptor, ForEachKey};
+use bitcoin::hashes::Hash;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintFinding {
+    /// The same key appears in several otherwise unrelated spend paths
+    KeyReusedAcrossPaths { key: XOnlyPublicKey, paths: Vec<usize> },
+    /// The same leaf script appears more than once in the tree
+    DuplicateLeaf { leaf_hash: TapLeafHash, depths: Vec<u8> },
+    /// The internal (key-path) key also appears inside a leaf
+    KeyPathKeyInLeaf { key: XOnlyPublicKey, leaves: Vec<usize> },
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintFinding::KeyReusedAcrossPaths { key, paths } => write!(f, "key {} is reused across spend paths {:?}", key, paths),
+            LintFinding::DuplicateLeaf { leaf_hash, depths } => write!(f, "leaf {} appears at depths {:?}", leaf_hash, depths),
+            LintFinding::KeyPathKeyInLeaf { key, leaves } => write!(f, "internal key {} also appears in leaves {:?}", key, leaves),
+        }
+    }
+}
+
+fn keys_of<T: ForEachKey<PublicKey>>(item: &T) -> Vec<XOnlyPublicKey> {
+    let mut keys = Vec::new();
+    item.for_each_key(|pk| {
+        if !keys.contains(&x_only(pk)) {
+            keys.push(x_only(pk));
+        }
+        true
+    });
+    keys
+}
+
+/// Keys of each spend path, in path order
+fn path_keys(descriptor: &Descriptor<PublicKey>) -> Result<Vec<Vec<XOnlyPublicKey>>, Box<dyn std::error::Error>> {
+    Ok(match descriptor {
+        Descriptor::Tr(tr) => tr.iter_scripts().map(|(_, ms)| keys_of(ms)).collect(),
+        _ => match descriptor.lift()?.normalized() {
+            Semantic::Threshold(1, branches) => branches.iter().map(keys_of).collect(),
+            policy => vec![keys_of(&policy)],
+        },
+    })
+}
+
+/// Run every check against `descriptor`
+pub fn lint(descriptor: &Descriptor<PublicKey>) -> Result<Vec<LintFinding>, Box<dyn std::error::Error>> {
+    let mut findings = Vec::new();
+
+    let mut key_paths: BTreeMap<[u8; 32], (XOnlyPublicKey, Vec<usize>)> = BTreeMap::new();
+    for (index, keys) in path_keys(descriptor)?.iter().enumerate() {
+        for key in keys {
+            key_paths.entry(key.serialize()).or_insert((*key, Vec::new())).1.push(index);
+        }
+    }
+
+    if let Descriptor::Tr(tr) = descriptor {
+        let internal = x_only(tr.internal_key());
+        if let Some((_, leaves)) = key_paths.get(&internal.serialize()) {
+            findings.push(LintFinding::KeyPathKeyInLeaf { key: internal, leaves: leaves.clone() });
+        }
+        let mut leaf_depths: BTreeMap<[u8; 32], (TapLeafHash, Vec<u8>)> = BTreeMap::new();
+        for (depth, ms) in tr.iter_scripts() {
+            let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+            leaf_depths.entry(leaf_hash.to_byte_array()).or_insert((leaf_hash, Vec::new())).1.push(depth);
+        }
+        for (leaf_hash, depths) in leaf_depths.into_values().filter(|(_, d)| d.len() > 1) {
+            findings.push(LintFinding::DuplicateLeaf { leaf_hash, depths });
+        }
+    }
+
+    for (key, paths) in key_paths.into_values().filter(|(_, p)| p.len() > 1) {
+        findings.push(LintFinding::KeyReusedAcrossPaths { key, paths });
+    }
+    Ok(findings)
+}
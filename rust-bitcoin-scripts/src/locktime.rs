@@ -0,0 +1,49 @@
+//! Anti-fee-sniping nLockTime, as Bitcoin Core's wallet sets it.
+//!
+//! A transaction locked to the current tip height cannot be put into a block that re-mines the
+//! tip, which takes away some of the incentive to reorg for its fee. Core uses the tip height and,
+//! one time in ten, up to 99 blocks less so late broadcasts do not stand out; so do we. Builders
+//! know the lock time their spend path requires (a CLTV `after`, or none) and `reconcile` it with
+//! the tip: a height lock below the tip is raised to it, a time lock is kept as it is, since
+//! nLockTime is either a height or a time.
+
+use bitcoin::absolute::LockTime;
+use rand::Rng;
+
+/// Locktimes below this are block heights, above are unix timestamps
+pub(crate) const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+/// Bit 22 of an `older` value: the delay is in units of 512 seconds
+pub(crate) const SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockTimePolicy {
+    #[default]
+    AntiFeeSniping,
+    /// Exactly the lock time the spend path requires (`LockTime::ZERO` without one)
+    RequiredOnly,
+}
+
+/// Lock time for a transaction built on top of `tip_height`
+pub fn anti_fee_sniping<R: Rng>(tip_height: u32, rng: &mut R) -> LockTime {
+    let mut height = tip_height;
+    if rng.gen_range(0..10) == 0 {
+        height = height.saturating_sub(rng.gen_range(0..100));
+    }
+    LockTime::from_height(height).unwrap_or(LockTime::ZERO)
+}
+
+/// Lock time for a spend that needs at least `required`
+pub fn reconcile(required: LockTime, tip_height: u32, policy: LockTimePolicy) -> LockTime {
+    reconcile_with(required, tip_height, policy, &mut rand::thread_rng())
+}
+
+/// `reconcile` with a caller-provided source of randomness
+pub fn reconcile_with<R: Rng>(required: LockTime, tip_height: u32, policy: LockTimePolicy, rng: &mut R) -> LockTime {
+    match (policy, required) {
+        (LockTimePolicy::RequiredOnly, _) | (_, LockTime::Seconds(_)) => required,
+        (LockTimePolicy::AntiFeeSniping, LockTime::Blocks(height)) => {
+            let sniping = anti_fee_sniping(tip_height, rng);
+            if sniping.to_consensus_u32() > height.to_consensus_u32() { sniping } else { required }
+        }
+    }
+}
@@ -1,10 +1,144 @@
-mod classic_multisig;
-mod timelock_cltv;
-mod timelock_csv;
+use bitcoin_scripts::{classic_multisig, timelock_cltv, timelock_csv};
+use bitcoin_scripts::accounting::{ExportFormat, FeeLedger};
+use bitcoin_scripts::attestation;
+use bitcoin_scripts::backup;
+use bitcoin_scripts::replay;
+use bitcoin_scripts::scanner::BlockScanner;
+use bitcoin_scripts::test_setup::{parse_chain, BitcoinRPC, RpcConfig};
+use bitcoin_scripts::manifest::{SignedManifest, VaultManifest};
+use bitcoin_scripts::weak_keys::KeyPolicy;
+use miniscript::bitcoin::{Network, PrivateKey, PublicKey};
+use miniscript::Descriptor;
+use bitcoin::secp256k1::{KeyPair, Secp256k1, XOnlyPublicKey};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    classic_multisig::run()?;
-    timelock_cltv::run()?;
-    timelock_csv::run()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("manifest") => manifest_command(&args[1..]),
+        Some("attest") => attest_command(&args[1..]),
+        Some("backup") => backup_command(&args[1..]),
+        Some("replay") => replay_command(&args[1..]),
+        Some("fees") => fees_command(&args[1..]),
+        _ => {
+            let network = RpcConfig::from_env()?.network;
+            classic_multisig::run(network)?;
+            timelock_cltv::run(network)?;
+            timelock_csv::run(network)?;
+            Ok(())
+        }
+    }
+}
+
+/// `manifest create <descriptor> <signing-key-wif> [network] [provenance]` | `manifest verify <file> <signer-xonly-pubkey>...`
+fn manifest_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    match args.first().map(String::as_str) {
+        Some("create") if args.len() >= 3 => {
+            let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&args[1])?;
+            KeyPolicy::default().check_descriptor(&descriptor)?;
+            let signing_key = PrivateKey::from_wif(&args[2])?;
+            let network = match args.get(3) {
+                Some(n) => parse_chain(n)?.0,
+                None => Network::Regtest,
+            };
+            let keypair = KeyPair::from_secret_key(&Secp256k1::new(), &signing_key.inner);
+            let manifest = VaultManifest::from_descriptor(&descriptor, network, args.get(4).map(String::as_str))?;
+            println!("{}", manifest.sign(&keypair)?.to_json()?);
+            Ok(())
+        }
+        Some("verify") if args.len() >= 3 => {
+            let signed = SignedManifest::from_json(&std::fs::read_to_string(&args[1])?)?;
+            let trusted = args[2..].iter().map(|k| XOnlyPublicKey::from_str(k)).collect::<Result<Vec<_>, _>>()?;
+            signed.verify(&trusted)?;
+            println!("Manifest OK: {} signed by {}", signed.manifest.address, signed.signer);
+            Ok(())
+        }
+        _ => Err("usage: manifest create <descriptor> <signing-key-wif> [network] [provenance] | manifest verify <file> <signer-xonly-pubkey>...".into()),
+    }
+}
+
+/// `attest <git-commit> <network> <count> <descriptor>...`
+fn attest_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.len() < 4 {
+        return Err("usage: attest <git-commit> <network> <count> <descriptor>...".into());
+    }
+    let network = parse_chain(&args[1])?.0;
+    let count: u32 = args[2].parse()?;
+    let attestation = attestation::attest(&args[0], network, &args[3..], count)?;
+    println!("{}", attestation.to_json()?);
+    Ok(())
+}
+
+/// `backup create <archive> <path>...` | `backup verify <archive>` | `backup restore <archive> <target-dir> [--overwrite]`.
+/// The passphrase is read from `WRAPYIELD_BACKUP_PASSPHRASE` so it never appears in the process list.
+fn backup_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = std::env::var("WRAPYIELD_BACKUP_PASSPHRASE").map_err(|_| "WRAPYIELD_BACKUP_PASSPHRASE is not set")?;
+    match args.first().map(String::as_str) {
+        Some("create") if args.len() >= 3 => {
+            let sources: Vec<PathBuf> = args[2..].iter().map(PathBuf::from).collect();
+            let contents = backup::create(Path::new(&args[1]), &sources, &passphrase)?;
+            println!("Backed up {} files to {}", contents.entries.len(), args[1]);
+            Ok(())
+        }
+        Some("verify") if args.len() >= 2 => {
+            let contents = backup::decrypt(&std::fs::read(&args[1])?, &passphrase)?;
+            for entry in &contents.entries {
+                println!("{}  {}", entry.sha256, entry.path);
+            }
+            println!("Backup OK: {} files, created at {}", contents.entries.len(), contents.created_at);
+            Ok(())
+        }
+        Some("restore") if args.len() >= 3 => {
+            let overwrite = args.iter().any(|a| a == "--overwrite");
+            let restored = backup::restore(Path::new(&args[1]), Path::new(&args[2]), &passphrase, overwrite)?;
+            println!("Restored {} files into {}", restored.len(), args[2]);
+            Ok(())
+        }
+        _ => Err("usage: backup create <archive> <path>... | backup verify <archive> | backup restore <archive> <target-dir> [--overwrite]".into()),
+    }
+}
+
+/// `replay <from-height> <to-height> <script-pubkey-hex>...`: scan a historical range for
+/// deposits to and spends from the given scripts in read-only mode and report throughput
+fn replay_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.len() < 2 {
+        return Err("usage: replay <from-height> <to-height> <script-pubkey-hex>...".into());
+    }
+    let (from, to): (u64, u64) = (args[0].parse()?, args[1].parse()?);
+    let mut scanner = BlockScanner::new();
+    for script in &args[2..] {
+        scanner.watch_script(bitcoin::ScriptBuf::from_bytes(hex::decode(script)?));
+    }
+    let report = tokio::runtime::Runtime::new()?.block_on(async {
+        replay::replay(&BitcoinRPC::from_env()?, &mut scanner, from, to).await
+    })?;
+    for event in &report.events {
+        println!("{:?}", event);
+    }
+    println!("Replayed {} blocks / {} transactions: {} deposits, {} spends", report.blocks, report.transactions, report.deposits(), report.spends());
+    println!("Throughput: {:.1} blocks/s, {:.0} tx/s (fetch {:?}, classify {:?}, fallback lookups {})",
+        report.blocks_per_sec(), report.transactions_per_sec(), report.fetch_time, report.classify_time, report.fallback_lookups);
+    if let Some((height, took)) = report.slowest_block {
+        println!("Slowest block: {} ({:?})", height, took);
+    }
+    Ok(())
+}
+
+/// `fees <history.json> <out.csv|out.json> [from-unix-time] [to-unix-time]`: export the fees in
+/// the spend history, attributed to the job each transaction was for
+fn fees_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.len() < 2 {
+        return Err("usage: fees <history.json> <out.csv|out.json> [from-unix-time] [to-unix-time]".into());
+    }
+    let from: u64 = args.get(2).map(|a| a.parse()).transpose()?.unwrap_or(0);
+    let to: u64 = args.get(3).map(|a| a.parse()).transpose()?.unwrap_or(u64::MAX);
+    let format = if args[1].ends_with(".json") { ExportFormat::Json } else { ExportFormat::Csv };
+    let ledger = FeeLedger::load_history(Path::new(&args[0]), from, to)?;
+    ledger.export(Path::new(&args[1]), format)?;
+    for total in &ledger.totals {
+        println!("{:<16} {:>4} txs  booked {:>12} sats  pending {:>12} sats", total.product, total.transactions, total.booked_sats, total.pending_sats);
+    }
+    println!("Exported {} transactions to {}: {} sats booked, {} sats pending", ledger.entries.len(), args[1], ledger.booked().to_sat(), ledger.pending().to_sat());
     Ok(())
 }
@@ -0,0 +1,212 @@
+//! Vault manifest: a single signed file with everything a third party needs to verify a vault
+//!
+//! A vault without a key path carries a `nums::NumsProof` for its internal key; `verify` checks
+//! it along with everything else. Taproot manifests also record the `taproot::KeyPathTweak` a
+//! key-path signer has to apply.
+
+use crate::nums::NumsProof;
+use crate::schema::{self, SchemaKind};
+use crate::taproot::KeyPathTweak;
+use miniscript::bitcoin::{Network, PublicKey};
+use miniscript::{Descriptor, ForEachKey};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::{schnorr, KeyPair, Message, Secp256k1};
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptEntry {
+    /// "witness_script" for wsh, "tapleaf" for tr
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u8>,
+    pub script_hex: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leaf_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InternalKeyProvenance {
+    pub key: String,
+    /// Free-form description of how the key was chosen (e.g. "hoisted backup key", "nums")
+    pub provenance: String,
+    /// Derivation proving the key path unspendable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nums_proof: Option<NumsProof>,
+    /// Merkle root the key-path signer must tweak by, and the resulting output key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_path_tweak: Option<KeyPathTweak>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultManifest {
+    pub descriptor: String,
+    pub descriptor_checksum: String,
+    pub network: String,
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal_key: Option<InternalKeyProvenance>,
+    pub scripts: Vec<ScriptEntry>,
+    pub federation_pubkeys: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedManifest {
+    pub manifest: VaultManifest,
+    /// sha256 of the compact JSON encoding of `manifest`
+    pub digest: String,
+    pub signer: String,
+    pub signature: String,
+}
+
+impl SchemaKind for SignedManifest {
+    const KIND: &'static str = "vault_manifest";
+    fn json_schema() -> Value {
+        serde_json::json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "$id": format!("wrapyield/{}/v{}", Self::KIND, schema::SCHEMA_VERSION),
+            "type": "object",
+            "required": ["schema_version", "kind", "manifest", "digest", "signer", "signature"],
+        })
+    }
+}
+
+impl VaultManifest {
+    /// Collect descriptor, checksum, address, scripts and keys for `descriptor`
+    pub fn from_descriptor(descriptor: &Descriptor<PublicKey>, network: Network, internal_key_provenance: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let full = descriptor.to_string();
+        let (body, checksum) = full.split_once('#').unwrap_or((full.as_str(), ""));
+        let mut federation_pubkeys = Vec::new();
+        descriptor.for_each_key(|pk| {
+            let key = pk.to_string();
+            if !federation_pubkeys.contains(&key) {
+                federation_pubkeys.push(key);
+            }
+            true
+        });
+        let mut scripts = Vec::new();
+        let mut internal_key = None;
+        match descriptor {
+            Descriptor::Tr(tr) => {
+                for (depth, ms) in tr.iter_scripts() {
+                    let script = ms.encode();
+                    scripts.push(ScriptEntry {
+                        kind: "tapleaf".to_string(),
+                        depth: Some(depth),
+                        script_hex: hex::encode(script.as_bytes()),
+                        leaf_hash: Some(TapLeafHash::from_script(&script, LeafVersion::TapScript).to_string()),
+                    });
+                }
+                internal_key = Some(InternalKeyProvenance {
+                    key: tr.internal_key().to_string(),
+                    provenance: internal_key_provenance.unwrap_or("unspecified").to_string(),
+                    nums_proof: None,
+                    key_path_tweak: Some(KeyPathTweak::from_spend_info(&tr.spend_info())),
+                });
+            }
+            _ => {
+                if let Ok(script) = descriptor.explicit_script() {
+                    scripts.push(ScriptEntry {
+                        kind: "witness_script".to_string(),
+                        depth: None,
+                        script_hex: hex::encode(script.as_bytes()),
+                        leaf_hash: None,
+                    });
+                }
+            }
+        }
+        Ok(Self {
+            descriptor: body.to_string(),
+            descriptor_checksum: checksum.to_string(),
+            network: network.to_string(),
+            address: descriptor.address(network)?.to_string(),
+            internal_key,
+            scripts,
+            federation_pubkeys,
+        })
+    }
+
+    /// Attach the proof that the internal key is a NUMS point; the key is then no longer
+    /// listed among the federation keys
+    pub fn with_nums_proof(mut self, proof: NumsProof) -> Result<Self, Box<dyn std::error::Error>> {
+        let internal = self.internal_key.as_mut().ok_or("only taproot vaults have an internal key")?;
+        proof.verify(&PublicKey::from_str(&internal.key)?.inner.x_only_public_key().0)?;
+        internal.nums_proof = Some(proof);
+        let key = internal.key.clone();
+        self.federation_pubkeys.retain(|k| *k != key);
+        Ok(self)
+    }
+
+    /// For auditors: the proof that the key path cannot be spent, checked against the internal key
+    pub fn verify_key_path_unspendable(&self) -> Result<&NumsProof, Box<dyn std::error::Error>> {
+        let internal = self.internal_key.as_ref().ok_or("not a taproot vault")?;
+        let proof = internal.nums_proof.as_ref().ok_or("no proof that the key path is unspendable")?;
+        proof.verify(&PublicKey::from_str(&internal.key)?.inner.x_only_public_key().0)?;
+        Ok(proof)
+    }
+
+    pub fn digest(&self) -> Result<sha256::Hash, Box<dyn std::error::Error>> {
+        Ok(sha256::Hash::hash(&serde_json::to_vec(self)?))
+    }
+
+    /// Schnorr-sign the manifest digest with `keypair`
+    pub fn sign(self, keypair: &KeyPair) -> Result<SignedManifest, Box<dyn std::error::Error>> {
+        let secp = Secp256k1::new();
+        let digest = self.digest()?;
+        let msg = Message::from_slice(&digest[..])?;
+        let signature = secp.sign_schnorr_no_aux_rand(&msg, keypair);
+        Ok(SignedManifest {
+            manifest: self,
+            digest: digest.to_string(),
+            signer: XOnlyPublicKey::from_keypair(keypair).0.to_string(),
+            signature: hex::encode(signature.as_ref().to_vec()),
+        })
+    }
+}
+
+impl SignedManifest {
+    /// Verify that one of `trusted_signers` signed the manifest and that every derived field
+    /// matches a fresh derivation from the descriptor. A signature by any other key is rejected,
+    /// however valid: the manifest carries its signer, so anyone can re-sign a forged one.
+    pub fn verify(&self, trusted_signers: &[XOnlyPublicKey]) -> Result<(), Box<dyn std::error::Error>> {
+        let secp = Secp256k1::verification_only();
+        let digest = self.manifest.digest()?;
+        if digest.to_string() != self.digest {
+            return Err("manifest digest mismatch".into());
+        }
+        let signer = XOnlyPublicKey::from_str(&self.signer)?;
+        if !trusted_signers.contains(&signer) {
+            return Err(format!("manifest signed by {}, which is not a trusted signer", signer).into());
+        }
+        let signature = schnorr::Signature::from_slice(&hex::decode(&self.signature)?)?;
+        secp.verify_schnorr(&signature, &Message::from_slice(&digest[..])?, &signer)?;
+
+        let network = Network::from_str(&self.manifest.network)?;
+        let full = if self.manifest.descriptor_checksum.is_empty() {
+            self.manifest.descriptor.clone()
+        } else {
+            format!("{}#{}", self.manifest.descriptor, self.manifest.descriptor_checksum)
+        };
+        let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&full)?;
+        let provenance = self.manifest.internal_key.as_ref().map(|k| k.provenance.as_str());
+        let mut expected = VaultManifest::from_descriptor(&descriptor, network, provenance)?;
+        if let Some(proof) = self.manifest.internal_key.as_ref().and_then(|k| k.nums_proof.clone()) {
+            expected = expected.with_nums_proof(proof)?;
+        }
+        if expected != self.manifest {
+            return Err("manifest contents do not match the descriptor".into());
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(schema::to_json_pretty(self)?)
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(schema::from_json(s)?)
+    }
+}
@@ -0,0 +1,194 @@
+//! Migration of existing `wsh(...)` vaults to equivalent taproot `tr(...)` vaults
+
+use miniscript::bitcoin::{Network, PrivateKey, PublicKey, secp256k1};
+use miniscript::descriptor::{TapTree, WshInner};
+use miniscript::policy::{Liftable, Semantic};
+use miniscript::{Descriptor, Miniscript, ScriptContext, Segwitv0, Tap, Terminal};
+use crate::amount::deduct_fee_for;
+use bitcoin::{Amount, Transaction, TxIn, TxOut, OutPoint, ScriptBuf, Sequence, Witness, absolute::LockTime};
+use bitcoin::sighash::{SighashCache, EcdsaSighashType};
+use bitcoin::secp256k1::Message;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// BIP341 "H" point (x-only, even y): a key with no known discrete log, used when no key path is
+/// wanted; `nums::NumsProof::Bip341H` re-derives it
+pub const NUMS_INTERNAL_KEY: &str = "0250929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// How the key path of the migrated taproot output was chosen
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyPath {
+    /// A bare `pk(K)` branch of the old policy was moved onto the key path
+    Hoisted(PublicKey),
+    /// The whole old policy lives in the leaf; the internal key is not meant to be spendable
+    Unspendable(PublicKey),
+}
+
+pub struct Migration {
+    pub old_descriptor: Descriptor<PublicKey>,
+    pub new_descriptor: Descriptor<PublicKey>,
+    pub key_path: KeyPath,
+    pub new_address: String,
+}
+
+/// Map a `wsh(...)` vault onto an equivalent `tr(...)` vault.
+///
+/// A top-level `or_d`/`or_i` with a bare `pk(K)` branch has `K` hoisted onto the
+/// key path and the other branch placed in a single leaf. Otherwise the whole policy goes into the
+/// leaf under `NUMS_INTERNAL_KEY`. The leaf is rebuilt fragment by fragment as tapscript, with
+/// `multi` becoming `multi_a`, and must pass the tapscript context checks.
+pub fn wsh_to_tr(old: &Descriptor<PublicKey>, network: Network) -> Result<Migration, Box<dyn std::error::Error>> {
+    let ms = wsh_miniscript(old)?;
+    let (key_path, leaf) = match hoistable_key(&ms) {
+        Some((key, rest)) => (KeyPath::Hoisted(key), to_tapscript(&rest)?),
+        None => (KeyPath::Unspendable(PublicKey::from_str(NUMS_INTERNAL_KEY)?), to_tapscript(&ms)?),
+    };
+    Tap::check_global_validity(&leaf)?;
+    Tap::check_local_validity(&leaf)?;
+    leaf.sanity_check()?;
+    let internal_key = match &key_path {
+        KeyPath::Hoisted(k) | KeyPath::Unspendable(k) => *k,
+    };
+    let new_descriptor = Descriptor::new_tr(internal_key, Some(TapTree::Leaf(Arc::new(leaf))))?;
+    let new_address = new_descriptor.address(network)?.to_string();
+    let migration = Migration {
+        old_descriptor: old.clone(),
+        new_descriptor,
+        key_path,
+        new_address,
+    };
+    if !migration.verify()? {
+        return Err("migrated descriptor does not encode the same policy".into());
+    }
+    Ok(migration)
+}
+
+impl Migration {
+    /// Check that the new descriptor encodes the same logical (semantic) policy as the old one.
+    /// With an unspendable internal key, only the leaves are compared.
+    pub fn verify(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        let old_policy = self.old_descriptor.lift()?.normalized();
+        let new_policy = match (&self.key_path, &self.new_descriptor) {
+            (KeyPath::Hoisted(_), desc) => desc.lift()?.normalized(),
+            (KeyPath::Unspendable(_), Descriptor::Tr(tr)) => {
+                let leaves = tr.iter_scripts()
+                    .map(|(_, ms)| ms.lift())
+                    .collect::<Result<Vec<Semantic<PublicKey>>, _>>()?;
+                Semantic::Threshold(1, leaves).normalized()
+            }
+            _ => return Ok(false),
+        };
+        Ok(old_policy == new_policy)
+    }
+
+    /// Build an unsigned spend moving `amount` less `fee` from the old vault output to the new address
+    pub fn build_spend(&self, outpoint: OutPoint, amount: Amount, fee: Amount, sequence: Sequence, lock_time: LockTime) -> Result<Transaction, Box<dyn std::error::Error>> {
+        let script_pubkey = self.new_descriptor.script_pubkey();
+        let value = deduct_fee_for(amount, fee, &script_pubkey)?;
+        Ok(Transaction {
+            version: 2,
+            lock_time,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence,
+                witness: Witness::default(),
+            }],
+            output: vec![TxOut {
+                value: value.to_sat(),
+                script_pubkey,
+            }],
+        })
+    }
+
+    /// Sign input `input_index` of `tx` with `keys` and let the old descriptor's satisfier pick the witness.
+    /// The input's sequence and the tx locktime are offered to the satisfier for `older`/`after` checks.
+    pub fn sign_spend(&self, tx: &mut Transaction, input_index: usize, amount: Amount, keys: &[PrivateKey]) -> Result<(), Box<dyn std::error::Error>> {
+        let secp = secp256k1::Secp256k1::new();
+        let script_code = self.old_descriptor.explicit_script()?;
+        let mut cache = SighashCache::new(&*tx);
+        let sighash = cache.segwit_signature_hash(input_index, &script_code, amount.to_sat(), EcdsaSighashType::All)?;
+        let msg = Message::from_slice(&sighash[..])?;
+        let mut sigs: HashMap<PublicKey, bitcoin::ecdsa::Signature> = HashMap::new();
+        for key in keys {
+            let sig = secp.sign_ecdsa(&msg, &key.inner);
+            sigs.insert(PublicKey::from_private_key(&secp, key), bitcoin::ecdsa::Signature::sighash_all(sig));
+        }
+        let sequence = tx.input[input_index].sequence;
+        let lock_time = tx.lock_time;
+        self.old_descriptor.satisfy(&mut tx.input[input_index], (sigs, sequence, lock_time))?;
+        let prevout = TxOut { value: amount.to_sat(), script_pubkey: self.old_descriptor.script_pubkey() };
+        crate::verify::verify_input(tx, input_index, &prevout)?;
+        Ok(())
+    }
+}
+
+fn wsh_miniscript(desc: &Descriptor<PublicKey>) -> Result<Miniscript<PublicKey, Segwitv0>, Box<dyn std::error::Error>> {
+    match desc {
+        Descriptor::Wsh(wsh) => match wsh.as_inner() {
+            WshInner::Ms(ms) => Ok(ms.clone()),
+            WshInner::SortedMulti(_) => Err("wsh(sortedmulti(...)) has no ordered tapscript equivalent".into()),
+        },
+        _ => Err("only wsh(...) descriptors can be migrated".into()),
+    }
+}
+
+/// If the top-level fragment is a disjunction with a bare `pk(K)` arm, return `K` and the other arm
+fn hoistable_key(ms: &Miniscript<PublicKey, Segwitv0>) -> Option<(PublicKey, Arc<Miniscript<PublicKey, Segwitv0>>)> {
+    let (left, right) = match &ms.node {
+        Terminal::OrD(l, r) | Terminal::OrI(l, r) => (l, r),
+        _ => return None,
+    };
+    if let Some(key) = bare_pk(left) {
+        return Some((key, right.clone()));
+    }
+    bare_pk(right).map(|key| (key, left.clone()))
+}
+
+/// The same fragment tree in the tapscript context, `multi` mapped to `multi_a`. Each node is
+/// re-typed by `from_ast`, which also applies the tapscript consensus limits.
+fn to_tapscript(ms: &Miniscript<PublicKey, Segwitv0>) -> Result<Miniscript<PublicKey, Tap>, Box<dyn std::error::Error>> {
+    let sub = |ms: &Arc<Miniscript<PublicKey, Segwitv0>>| to_tapscript(ms).map(Arc::new);
+    let node = match &ms.node {
+        Terminal::True => Terminal::True,
+        Terminal::False => Terminal::False,
+        Terminal::PkK(pk) => Terminal::PkK(*pk),
+        Terminal::PkH(pk) => Terminal::PkH(*pk),
+        Terminal::RawPkH(hash) => Terminal::RawPkH(*hash),
+        Terminal::After(lock_time) => Terminal::After(*lock_time),
+        Terminal::Older(sequence) => Terminal::Older(*sequence),
+        Terminal::Sha256(hash) => Terminal::Sha256(*hash),
+        Terminal::Hash256(hash) => Terminal::Hash256(*hash),
+        Terminal::Ripemd160(hash) => Terminal::Ripemd160(*hash),
+        Terminal::Hash160(hash) => Terminal::Hash160(*hash),
+        Terminal::Alt(x) => Terminal::Alt(sub(x)?),
+        Terminal::Swap(x) => Terminal::Swap(sub(x)?),
+        Terminal::Check(x) => Terminal::Check(sub(x)?),
+        Terminal::DupIf(x) => Terminal::DupIf(sub(x)?),
+        Terminal::Verify(x) => Terminal::Verify(sub(x)?),
+        Terminal::NonZero(x) => Terminal::NonZero(sub(x)?),
+        Terminal::ZeroNotEqual(x) => Terminal::ZeroNotEqual(sub(x)?),
+        Terminal::AndV(x, y) => Terminal::AndV(sub(x)?, sub(y)?),
+        Terminal::AndB(x, y) => Terminal::AndB(sub(x)?, sub(y)?),
+        Terminal::AndOr(x, y, z) => Terminal::AndOr(sub(x)?, sub(y)?, sub(z)?),
+        Terminal::OrB(x, y) => Terminal::OrB(sub(x)?, sub(y)?),
+        Terminal::OrD(x, y) => Terminal::OrD(sub(x)?, sub(y)?),
+        Terminal::OrC(x, y) => Terminal::OrC(sub(x)?, sub(y)?),
+        Terminal::OrI(x, y) => Terminal::OrI(sub(x)?, sub(y)?),
+        Terminal::Thresh(k, subs) => Terminal::Thresh(*k, subs.iter().map(sub).collect::<Result<_, _>>()?),
+        Terminal::Multi(k, keys) => Terminal::MultiA(*k, keys.clone()),
+        Terminal::MultiA(..) => return Err("multi_a cannot appear in a wsh descriptor".into()),
+    };
+    Ok(Miniscript::from_ast(node)?)
+}
+
+fn bare_pk(ms: &Miniscript<PublicKey, Segwitv0>) -> Option<PublicKey> {
+    match &ms.node {
+        Terminal::Check(inner) => match &inner.node {
+            Terminal::PkK(pk) => Some(*pk),
+            _ => None,
+        },
+        _ => None,
+    }
+}
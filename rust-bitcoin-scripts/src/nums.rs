@@ -0,0 +1,85 @@
+//! Provably unspendable taproot internal keys ("nothing up my sleeve" points).
+//!
+//! A vault without a key path still needs an internal key. `NumsProof` records how one was
+//! derived so anyone can re-derive it and see that nobody can know its discrete log:
+//! - `Bip341H`: the `H` point from BIP341, `lift_x(sha256(G))` with `G` uncompressed
+//! - `Tagged`: hash-to-curve of a published tag by try-and-increment: the first `counter` for
+//!   which `tagged_hash("WrapYield/NUMS", tag || counter_be)` is a valid x coordinate
+//!
+//! Every vault can use its own tag, so outputs are not linkable through a shared internal key.
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::constants::{GENERATOR_X, GENERATOR_Y};
+use miniscript::bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+pub const HASH_TAG: &str = "WrapYield/NUMS";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "scheme", rename_all = "snake_case")]
+pub enum NumsProof {
+    Bip341H,
+    Tagged { tag: String, counter: u32 },
+}
+
+fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    engine.input(data);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn candidate(tag: &str, counter: u32) -> Option<XOnlyPublicKey> {
+    let data = [tag.as_bytes(), &counter.to_be_bytes()].concat();
+    XOnlyPublicKey::from_slice(&tagged_hash(HASH_TAG, &data)).ok()
+}
+
+impl NumsProof {
+    /// Derive the unspendable key for `tag`
+    pub fn derive(tag: &str) -> Result<(XOnlyPublicKey, Self), Box<dyn std::error::Error>> {
+        if tag.is_empty() {
+            return Err("NUMS tag must not be empty".into());
+        }
+        // Each attempt succeeds with probability about 1/2
+        for counter in 0..=u32::MAX {
+            if let Some(key) = candidate(tag, counter) {
+                return Ok((key, NumsProof::Tagged { tag: tag.to_string(), counter }));
+            }
+        }
+        Err("no curve point found for tag".into())
+    }
+
+    /// Re-derive the key this proof stands for; a `Tagged` proof must also name the first valid
+    /// counter, so a key cannot be ground out of many counters
+    pub fn key(&self) -> Result<XOnlyPublicKey, Box<dyn std::error::Error>> {
+        match self {
+            NumsProof::Bip341H => {
+                let generator = [&[0x04u8][..], &GENERATOR_X[..], &GENERATOR_Y[..]].concat();
+                Ok(XOnlyPublicKey::from_slice(sha256::Hash::hash(&generator).as_ref())?)
+            }
+            NumsProof::Tagged { tag, counter } => {
+                if let Some(earlier) = (0..*counter).find(|c| candidate(tag, *c).is_some()) {
+                    return Err(format!("counter {} is not the first valid one ({} is)", counter, earlier).into());
+                }
+                candidate(tag, *counter).ok_or_else(|| "tag and counter do not give a curve point".into())
+            }
+        }
+    }
+
+    /// Check that `key` is the key this proof derives
+    pub fn verify(&self, key: &XOnlyPublicKey) -> Result<(), Box<dyn std::error::Error>> {
+        if &self.key()? != key {
+            return Err(format!("{} is not the NUMS point of {:?}", key, self).into());
+        }
+        Ok(())
+    }
+}
+
+/// Internal key for `tag` as a descriptor key (even y), with its proof
+pub fn unspendable_internal_key(tag: &str) -> Result<(PublicKey, NumsProof), Box<dyn std::error::Error>> {
+    let (key, proof) = NumsProof::derive(tag)?;
+    Ok((PublicKey::new(key.public_key(bitcoin::secp256k1::Parity::Even)), proof))
+}
@@ -0,0 +1,156 @@
+//! Bridge metadata in OP_RETURN outputs, within relay policy.
+//!
+//! Every data output starts with `MAGIC` and a kind byte. A payload that fits in one output goes
+//! inline; a larger one is split into numbered chunks when the policy relays several data
+//! outputs per transaction, and otherwise replaced by a commitment (SHA256 and length) with the
+//! full payload kept off-chain. `decode` skips non-data outputs, so recipients can sit in between.
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::script::{Instruction, PushBytesBuf};
+use bitcoin::{Script, ScriptBuf, TxOut};
+
+pub const MAGIC: [u8; 2] = *b"WY";
+const KIND_CHUNK: u8 = 0x01;
+const KIND_COMMITMENT: u8 = 0x02;
+/// Magic, kind, chunk index and chunk count
+const CHUNK_HEADER: usize = 5;
+const COMMITMENT_LEN: usize = 3 + 32 + 4;
+
+/// What the relaying nodes accept in data outputs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DataPolicy {
+    /// Bytes of data per OP_RETURN output
+    pub max_payload: usize,
+    /// Whether more than one OP_RETURN output per transaction is standard
+    pub multiple_outputs: bool,
+}
+
+impl DataPolicy {
+    /// `-datacarriersize=83` (80 bytes of data) and one data output, the long-standing default
+    pub const CORE_DEFAULT: DataPolicy = DataPolicy { max_payload: 80, multiple_outputs: false };
+}
+
+impl Default for DataPolicy {
+    fn default() -> Self {
+        DataPolicy::CORE_DEFAULT
+    }
+}
+
+/// Hash commitment to a payload stored off-chain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Commitment {
+    pub hash: sha256::Hash,
+    pub len: u32,
+}
+
+impl Commitment {
+    pub fn to(payload: &[u8]) -> Self {
+        Commitment { hash: sha256::Hash::hash(payload), len: payload.len() as u32 }
+    }
+
+    pub fn matches(&self, payload: &[u8]) -> bool {
+        *self == Commitment::to(payload)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decoded {
+    Payload(Vec<u8>),
+    Commitment(Commitment),
+}
+
+fn data_output(data: Vec<u8>) -> Result<TxOut, Box<dyn std::error::Error>> {
+    let push = PushBytesBuf::try_from(data)?;
+    Ok(TxOut { value: 0, script_pubkey: ScriptBuf::new_op_return(&push) })
+}
+
+/// Zero-value outputs carrying `payload` under `policy`: one chunk, several chunks or a commitment
+pub fn encode(payload: &[u8], policy: &DataPolicy) -> Result<Vec<TxOut>, Box<dyn std::error::Error>> {
+    if policy.max_payload <= CHUNK_HEADER {
+        return Err(format!("a {} byte data output cannot hold any payload", policy.max_payload).into());
+    }
+    let per_chunk = policy.max_payload - CHUNK_HEADER;
+    let chunks: Vec<&[u8]> = if payload.is_empty() { vec![payload] } else { payload.chunks(per_chunk).collect() };
+    let count = chunks.len();
+    if count == 1 || (policy.multiple_outputs && count <= u8::MAX as usize) {
+        return chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let mut data = vec![MAGIC[0], MAGIC[1], KIND_CHUNK, index as u8, count as u8];
+                data.extend_from_slice(chunk);
+                data_output(data)
+            })
+            .collect();
+    }
+    if policy.max_payload < COMMITMENT_LEN {
+        return Err(format!("a {} byte data output cannot hold a commitment", policy.max_payload).into());
+    }
+    Ok(vec![commit(payload)?])
+}
+
+/// A single output committing to `payload`, whatever its size
+pub fn commit(payload: &[u8]) -> Result<TxOut, Box<dyn std::error::Error>> {
+    let commitment = Commitment::to(payload);
+    let mut data = vec![MAGIC[0], MAGIC[1], KIND_COMMITMENT];
+    data.extend_from_slice(commitment.hash.as_byte_array());
+    data.extend_from_slice(&commitment.len.to_be_bytes());
+    data_output(data)
+}
+
+/// The data pushed by an OP_RETURN script with our magic
+fn tagged_data(script: &Script) -> Option<Vec<u8>> {
+    if !script.is_op_return() {
+        return None;
+    }
+    let mut instructions = script.instructions();
+    instructions.next();
+    match instructions.next() {
+        Some(Ok(Instruction::PushBytes(push))) if push.as_bytes().starts_with(&MAGIC) && push.len() >= 3 => Some(push.as_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+/// Read the payload or commitment back from a transaction's outputs
+pub fn decode(outputs: &[TxOut]) -> Result<Decoded, Box<dyn std::error::Error>> {
+    let tagged: Vec<Vec<u8>> = outputs.iter().filter_map(|o| tagged_data(&o.script_pubkey)).collect();
+    let first = tagged.first().ok_or("no data outputs")?;
+    match first[2] {
+        KIND_COMMITMENT => {
+            if tagged.len() != 1 || first.len() != COMMITMENT_LEN {
+                return Err("malformed commitment output".into());
+            }
+            let hash = sha256::Hash::from_slice(&first[3..35])?;
+            let len = u32::from_be_bytes(first[35..39].try_into()?);
+            Ok(Decoded::Commitment(Commitment { hash, len }))
+        }
+        KIND_CHUNK => {
+            let mut payload = Vec::new();
+            for (expected, data) in tagged.iter().enumerate() {
+                if data.len() < CHUNK_HEADER || data[2] != KIND_CHUNK {
+                    return Err(format!("data output {} is not a chunk", expected).into());
+                }
+                if data[3] as usize != expected || data[4] as usize != tagged.len() {
+                    return Err(format!("chunk {} of {} found where chunk {} of {} was expected", data[3], data[4], expected, tagged.len()).into());
+                }
+                payload.extend_from_slice(&data[CHUNK_HEADER..]);
+            }
+            Ok(Decoded::Payload(payload))
+        }
+        kind => Err(format!("unknown data output kind {:#04x}", kind).into()),
+    }
+}
+
+/// Decode and, for a commitment, check it against the off-chain `payload`
+pub fn decode_with(outputs: &[TxOut], payload: Option<&[u8]>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match decode(outputs)? {
+        Decoded::Payload(data) => Ok(data),
+        Decoded::Commitment(commitment) => {
+            let payload = payload.ok_or("outputs only commit to the payload")?;
+            if !commitment.matches(payload) {
+                return Err("payload does not match its commitment".into());
+            }
+            Ok(payload.to_vec())
+        }
+    }
+}
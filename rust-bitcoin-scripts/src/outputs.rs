@@ -0,0 +1,117 @@
+//! Output script types, including the newer ones: pay-to-anchor (P2A, standard since Core 28),
+//! bare OP_RETURN and witness versions 2 to 16, which nodes relay but cannot validate yet.
+//!
+//! `classify` never fails, so anything a block contains can be scanned and reported;
+//! `dust_limit` follows Core's `GetDustThreshold` at the default 3 sat/vB dust relay fee.
+
+use bitcoin::address::WitnessVersion;
+use bitcoin::blockdata::opcodes::all::{OP_PUSHNUM_1, OP_RETURN};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::{Amount, Script, ScriptBuf, TxOut};
+use std::fmt;
+
+/// Witness program of the pay-to-anchor output, `OP_1 <0x4e73>`
+pub const P2A_PROGRAM: [u8; 2] = [0x4e, 0x73];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputType {
+    P2pk,
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    /// Pay-to-anchor: anyone can spend it with an empty witness, to bump the fee through CPFP
+    P2a,
+    OpReturn,
+    /// A witness program this crate does not know: v1 with a length other than 32 (and not P2A),
+    /// or v2 to v16
+    WitnessUnknown { version: u8, program_len: usize },
+    NonStandard,
+}
+
+impl fmt::Display for OutputType {
+    /// Core's names, as in the `type` field of a decoded scriptPubKey
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OutputType::P2pk => "pubkey",
+            OutputType::P2pkh => "pubkeyhash",
+            OutputType::P2sh => "scripthash",
+            OutputType::P2wpkh => "witness_v0_keyhash",
+            OutputType::P2wsh => "witness_v0_scripthash",
+            OutputType::P2tr => "witness_v1_taproot",
+            OutputType::P2a => "anchor",
+            OutputType::OpReturn => "nulldata",
+            OutputType::WitnessUnknown { .. } => "witness_unknown",
+            OutputType::NonStandard => "nonstandard",
+        };
+        f.write_str(name)
+    }
+}
+
+pub fn classify(script: &Script) -> OutputType {
+    if is_p2a(script) {
+        OutputType::P2a
+    } else if script.is_p2pk() {
+        OutputType::P2pk
+    } else if script.is_p2pkh() {
+        OutputType::P2pkh
+    } else if script.is_p2sh() {
+        OutputType::P2sh
+    } else if script.is_v0_p2wpkh() {
+        OutputType::P2wpkh
+    } else if script.is_v0_p2wsh() {
+        OutputType::P2wsh
+    } else if script.is_v1_p2tr() {
+        OutputType::P2tr
+    } else if script.is_op_return() {
+        OutputType::OpReturn
+    } else if let Some(version) = script.witness_version() {
+        if script.is_witness_program() && version != WitnessVersion::V0 {
+            return OutputType::WitnessUnknown { version: version.to_num(), program_len: script.len() - 2 };
+        }
+        OutputType::NonStandard
+    } else {
+        OutputType::NonStandard
+    }
+}
+
+pub fn is_p2a(script: &Script) -> bool {
+    script.as_bytes() == [OP_PUSHNUM_1.to_u8(), 0x02, P2A_PROGRAM[0], P2A_PROGRAM[1]]
+}
+
+/// Smallest value Core relays in an output paying `script`; zero for OP_RETURN
+pub fn dust_limit(script: &Script) -> Amount {
+    match classify(script) {
+        OutputType::OpReturn => Amount::ZERO,
+        _ => script.dust_value(),
+    }
+}
+
+pub fn p2a_script() -> ScriptBuf {
+    ScriptBuf::from_bytes(vec![OP_PUSHNUM_1.to_u8(), 0x02, P2A_PROGRAM[0], P2A_PROGRAM[1]])
+}
+
+/// A P2A anchor output; `value` may be zero only in a v3 transaction that pays no fee itself
+/// (ephemeral dust, Core 29), and must otherwise be at least its 240 sat dust limit
+pub fn p2a_output(value: Amount) -> TxOut {
+    TxOut { value: value.to_sat(), script_pubkey: p2a_script() }
+}
+
+/// A zero-value `OP_RETURN <data>` output, without any framing (see `op_return` for that)
+pub fn op_return_output(data: &[u8]) -> Result<TxOut, Box<dyn std::error::Error>> {
+    let push = PushBytesBuf::try_from(data.to_vec())?;
+    Ok(TxOut { value: 0, script_pubkey: Builder::new().push_opcode(OP_RETURN).push_slice(push).into_script() })
+}
+
+/// Output script for a witness program of any version, passed through as is
+pub fn witness_program_script(version: WitnessVersion, program: &[u8]) -> Result<ScriptBuf, Box<dyn std::error::Error>> {
+    if !(2..=40).contains(&program.len()) {
+        return Err(format!("witness program of {} bytes, must be 2 to 40", program.len()).into());
+    }
+    if version == WitnessVersion::V0 && program.len() != 20 && program.len() != 32 {
+        return Err(format!("v0 witness program of {} bytes, must be 20 or 32", program.len()).into());
+    }
+    let push = PushBytesBuf::try_from(program.to_vec())?;
+    Ok(Builder::new().push_opcode(version.into()).push_slice(push).into_script())
+}
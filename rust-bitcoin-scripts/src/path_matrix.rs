@@ -0,0 +1,198 @@
+//! Exhaustive spend-path coverage for descriptor templates on regtest.
+//!
+//! `cases` splits a descriptor into every way it can be satisfied: the taproot key path, and for
+//! each leaf (or the whole script of a non-taproot descriptor) every combination of keys and
+//! timelocks its lifted policy accepts, i.e. each `or_d`/`or_i` arm and each k-of-n signer
+//! subset. `run` funds the descriptor once per case, matures the case's timelocks, spends with
+//! only the case's keys and waits for the spend to confirm, so a new template gets a test for
+//! every branch from a single call.
+
+use crate::flows::{fund_descriptor, mine, spend_utxo};
+use crate::locktime::{LOCKTIME_THRESHOLD, SEQUENCE_TYPE_FLAG};
+use crate::spend::{Planner, SpendPath};
+use crate::taproot_tree::x_only;
+use crate::test_setup::BitcoinRPC;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Amount, PrivateKey, PublicKey, Txid};
+use miniscript::policy::{Liftable, Semantic};
+use miniscript::Descriptor;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::time::Duration;
+
+/// Amount each case funds and spends
+pub const CASE_AMOUNT: Amount = Amount::from_sat(100_000);
+/// Flat fee of each case's spend; covers the largest template witnesses at the regtest floor
+pub const CASE_FEE: Amount = Amount::from_sat(2_000);
+
+/// One way to satisfy a descriptor
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathCase {
+    pub path: SpendPath,
+    pub keys: BTreeSet<PublicKey>,
+    /// Largest relative timelock of the branch, 0 if none
+    pub older: u32,
+    /// Largest absolute timelock of the branch, 0 if none
+    pub after: u32,
+    /// The branch needs a hash preimage
+    pub preimage: bool,
+}
+
+impl fmt::Display for PathCase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} with {} key(s)", self.path, self.keys.len())?;
+        if self.older > 0 {
+            write!(f, ", older({})", self.older)?;
+        }
+        if self.after > 0 {
+            write!(f, ", after({})", self.after)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathOutcome {
+    Spent { txid: Txid },
+    /// Not exercised on regtest: missing key, time-based lock or hash preimage
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathResult {
+    pub case: PathCase,
+    pub outcome: PathOutcome,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Requirement {
+    keys: BTreeSet<PublicKey>,
+    older: u32,
+    after: u32,
+    preimage: bool,
+}
+
+impl Requirement {
+    fn merge(&self, other: &Requirement) -> Requirement {
+        Requirement {
+            keys: self.keys.union(&other.keys).copied().collect(),
+            older: self.older.max(other.older),
+            after: self.after.max(other.after),
+            preimage: self.preimage || other.preimage,
+        }
+    }
+}
+
+/// Every index subset of `0..n` with `k` elements
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![Vec::new()];
+    }
+    (k - 1..n).flat_map(|last| combinations(last, k - 1).into_iter().map(move |mut c| { c.push(last); c })).collect()
+}
+
+/// Minimal requirement sets of `policy`, one per branch
+fn requirements(policy: &Semantic<PublicKey>) -> Vec<Requirement> {
+    let single = |r: Requirement| vec![r];
+    match policy {
+        Semantic::Unsatisfiable => Vec::new(),
+        Semantic::Trivial => single(Requirement::default()),
+        Semantic::Key(key) => single(Requirement { keys: [*key].into(), ..Default::default() }),
+        Semantic::Older(sequence) => single(Requirement { older: sequence.to_consensus_u32(), ..Default::default() }),
+        Semantic::After(lock_time) => single(Requirement { after: lock_time.to_consensus_u32(), ..Default::default() }),
+        Semantic::Sha256(_) | Semantic::Hash256(_) | Semantic::Ripemd160(_) | Semantic::Hash160(_) => single(Requirement { preimage: true, ..Default::default() }),
+        Semantic::Threshold(k, subs) => {
+            let branches: Vec<Vec<Requirement>> = subs.iter().map(requirements).collect();
+            let mut out = BTreeSet::new();
+            for chosen in combinations(subs.len(), *k) {
+                let mut partial = vec![Requirement::default()];
+                for index in chosen {
+                    partial = partial.iter().flat_map(|p| branches[index].iter().map(move |b| p.merge(b))).collect();
+                }
+                out.extend(partial);
+            }
+            out.into_iter().collect()
+        }
+    }
+}
+
+fn case(path: SpendPath, requirement: Requirement) -> PathCase {
+    PathCase { path, keys: requirement.keys, older: requirement.older, after: requirement.after, preimage: requirement.preimage }
+}
+
+/// Every satisfiable branch of `descriptor`
+pub fn cases(descriptor: &Descriptor<PublicKey>) -> Result<Vec<PathCase>, Box<dyn std::error::Error>> {
+    let mut out = Vec::new();
+    match descriptor {
+        Descriptor::Tr(tr) => {
+            out.push(case(SpendPath::TaprootKey, Requirement { keys: [*tr.internal_key()].into(), ..Default::default() }));
+            for (depth, ms) in tr.iter_scripts() {
+                let path = SpendPath::TaprootLeaf { script: ms.encode(), depth };
+                out.extend(requirements(&ms.lift()?).into_iter().map(|r| case(path.clone(), r)));
+            }
+        }
+        _ => out.extend(requirements(&descriptor.lift()?).into_iter().map(|r| case(SpendPath::Script, r))),
+    }
+    Ok(out)
+}
+
+/// Why `case` cannot run with `keys`, if it cannot
+fn skip_reason(case: &PathCase, keys: &[PrivateKey]) -> Option<String> {
+    let secp = Secp256k1::new();
+    if case.preimage {
+        return Some("needs a hash preimage".into());
+    }
+    if case.older & SEQUENCE_TYPE_FLAG != 0 || case.after >= LOCKTIME_THRESHOLD {
+        return Some("time-based timelock".into());
+    }
+    let missing = case.keys.iter().filter(|k| !keys.iter().any(|p| x_only(&p.public_key(&secp)) == x_only(k))).count();
+    (missing > 0).then(|| format!("{} key(s) not held", missing))
+}
+
+async fn run_case(rpc: &BitcoinRPC, descriptor: &Descriptor<PublicKey>, case: &PathCase, keys: &[PrivateKey]) -> Result<Txid, Box<dyn std::error::Error>> {
+    let secp = Secp256k1::new();
+    let signers: Vec<PrivateKey> = keys.iter().filter(|p| case.keys.iter().any(|k| x_only(k) == x_only(&p.public_key(&secp)))).copied().collect();
+    let funded = fund_descriptor(rpc, descriptor, CASE_AMOUNT).await?;
+    let mut tip = funded.height;
+    if case.older > 1 {
+        tip = mine(rpc, case.older - 1).await?;
+    }
+    if tip + 1 < case.after as u64 {
+        tip = mine(rpc, (case.after as u64 - tip - 1) as u32).await?;
+    }
+    let info = rpc.get_blockchain_info().await?;
+    let planner = Planner::new(signers.iter().map(|k| k.public_key(&secp)), tip as u32, info.median_time as u32, (tip + 1 - funded.height) as u32);
+    let plan = planner.candidates(descriptor)?.into_iter().find(|p| p.path == case.path).ok_or("planner found no plan for this path")?;
+    let destination = rpc.get_new_address().await?;
+    let spent = spend_utxo(rpc, &funded, &plan, &signers, &destination, CASE_FEE).await?;
+    let witness = &spent.transaction.input[0].witness;
+    let took = match &case.path {
+        SpendPath::TaprootKey => witness.len() == 1,
+        SpendPath::TaprootLeaf { script, .. } => witness.len() >= 2 && witness.nth(witness.len() - 2) == Some(script.as_bytes()),
+        SpendPath::Script => true,
+    };
+    if !took {
+        return Err("the finalized spend took a different path".into());
+    }
+    mine(rpc, 1).await?;
+    rpc.wait_for_confirmations(&spent.txid, 1, Duration::from_secs(30)).await?;
+    Ok(spent.txid)
+}
+
+/// Fund and spend `descriptor` once for every case, using only the case's keys out of `keys`.
+/// Cases run one after the other; a failing case does not stop the others.
+pub async fn run(rpc: &BitcoinRPC, descriptor: &Descriptor<PublicKey>, keys: &[PrivateKey]) -> Result<Vec<PathResult>, Box<dyn std::error::Error>> {
+    let mut results = Vec::new();
+    for case in cases(descriptor)? {
+        let outcome = match skip_reason(&case, keys) {
+            Some(reason) => PathOutcome::Skipped(reason),
+            None => match run_case(rpc, descriptor, &case, keys).await {
+                Ok(txid) => PathOutcome::Spent { txid },
+                Err(e) => PathOutcome::Failed(e.to_string()),
+            },
+        };
+        results.push(PathResult { case, outcome });
+    }
+    Ok(results)
+}
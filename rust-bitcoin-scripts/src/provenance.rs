@@ -0,0 +1,162 @@
+//! Address provenance: which template, parameters and derivation index produced an address, and
+//! who can spend it. Behind `wrapyield-cli address provenance`, for incident response.
+//!
+//! `ProvenanceIndex` maps scriptPubKeys to the `AddressOrigin`s recorded for them, so an address
+//! is found whatever network it is encoded for. Origins are built from a descriptor and index
+//! (`AddressOrigin::derived`), from an `AddressManager` or from a vault template; the spend paths
+//! are recomputed from the stored descriptor on lookup (`AddressOrigin::report`). The index is a
+//! JSON file, extended with `append` as addresses are handed out.
+
+use crate::addresses::AddressManager;
+use crate::hd;
+use crate::inspect::{self, DescriptorReport};
+use crate::state_file;
+use crate::templates::{self, TemplateParams};
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::{Address, Network, Script, ScriptBuf};
+use miniscript::descriptor::DescriptorPublicKey;
+use miniscript::Descriptor;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// Where one address came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressOrigin {
+    pub address: String,
+    pub network: Network,
+    pub script_pubkey: ScriptBuf,
+    /// Name of the template or constructor, e.g. `csv_vault` or `template-v2`
+    pub template: String,
+    /// Parameters the template was built with, by name
+    pub params: BTreeMap<String, String>,
+    /// Descriptor as recorded, ranged if `index` is set
+    pub descriptor: String,
+    pub index: Option<u32>,
+}
+
+impl AddressOrigin {
+    /// Origin of `descriptor` at `index` (ignored unless the descriptor is ranged) on `network`
+    pub fn derived(template: &str, params: BTreeMap<String, String>, descriptor: &Descriptor<DescriptorPublicKey>, index: u32, network: Network) -> Result<Self, Box<dyn std::error::Error>> {
+        let derived = hd::derive(descriptor, index)?;
+        Ok(Self {
+            address: derived.address(network)?.to_string(),
+            network,
+            script_pubkey: derived.script_pubkey(),
+            template: template.to_string(),
+            params,
+            descriptor: descriptor.to_string(),
+            index: descriptor.has_wildcard().then_some(index),
+        })
+    }
+
+    /// Origin of address `index` of `manager`
+    pub fn from_manager(manager: &AddressManager, template: &str, params: BTreeMap<String, String>, index: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::derived(template, params, &manager.descriptor, index, manager.network)
+    }
+
+    /// Origin of the vault `templates::build(version, params)` creates
+    pub fn from_template(version: u32, params: &TemplateParams, network: Network) -> Result<Self, Box<dyn std::error::Error>> {
+        let descriptor = inspect::parse(&templates::build(version, params)?.to_string())?;
+        Self::derived(&format!("template-v{}", version), template_params(params), &descriptor, 0, network)
+    }
+
+    /// Addresses, scripts and spend paths of the recorded descriptor at the recorded index
+    pub fn report(&self) -> Result<DescriptorReport, Box<dyn std::error::Error>> {
+        DescriptorReport::new(&inspect::parse(&self.descriptor)?, self.index.unwrap_or(0))
+    }
+}
+
+impl fmt::Display for AddressOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "address: {} ({})", self.address, self.network)?;
+        writeln!(f, "template: {}", self.template)?;
+        for (name, value) in &self.params {
+            writeln!(f, "  {}: {}", name, value)?;
+        }
+        writeln!(f, "descriptor: {}", self.descriptor)?;
+        match self.index {
+            Some(index) => writeln!(f, "index: {}", index),
+            None => writeln!(f, "index: none (not ranged)"),
+        }
+    }
+}
+
+/// Named parameters of a vault template, as recorded in its origin
+pub fn template_params(params: &TemplateParams) -> BTreeMap<String, String> {
+    let federation: Vec<String> = params.federation.iter().map(|k| k.to_string()).collect();
+    let mut named = BTreeMap::new();
+    named.insert("internal_key".to_string(), params.internal_key.to_string());
+    named.insert("federation".to_string(), federation.join(","));
+    named.insert("threshold".to_string(), params.threshold.to_string());
+    named.insert("federation_csv".to_string(), params.federation_csv.to_string());
+    if let Some(recovery_key) = params.recovery_key {
+        named.insert("recovery_key".to_string(), recovery_key.to_string());
+        named.insert("recovery_csv".to_string(), params.recovery_csv.to_string());
+    }
+    named
+}
+
+/// Every recorded origin, by scriptPubKey (hex)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProvenanceIndex {
+    pub origins: BTreeMap<String, Vec<AddressOrigin>>,
+}
+
+impl ProvenanceIndex {
+    /// Add `origin`; recording the same origin twice keeps one copy. Returns whether it was new.
+    pub fn record(&mut self, origin: AddressOrigin) -> bool {
+        let origins = self.origins.entry(origin.script_pubkey.to_hex_string()).or_default();
+        if origins.contains(&origin) {
+            return false;
+        }
+        origins.push(origin);
+        true
+    }
+
+    /// Record addresses `indices` of `manager`
+    pub fn record_manager(&mut self, manager: &AddressManager, template: &str, params: &BTreeMap<String, String>, indices: std::ops::Range<u32>) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut added = 0;
+        for index in indices {
+            added += self.record(AddressOrigin::from_manager(manager, template, params.clone(), index)?) as usize;
+        }
+        Ok(added)
+    }
+
+    /// Origins of `script_pubkey`, oldest first; more than one means two templates produced it
+    pub fn lookup_script(&self, script_pubkey: &Script) -> &[AddressOrigin] {
+        self.origins.get(&script_pubkey.to_hex_string()).map_or(&[], Vec::as_slice)
+    }
+
+    /// Origins of `address`, on whatever network it was recorded for
+    pub fn lookup(&self, address: &Address<NetworkUnchecked>) -> &[AddressOrigin] {
+        self.lookup_script(&address.payload.script_pubkey())
+    }
+
+    pub fn len(&self) -> usize {
+        self.origins.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.origins.is_empty()
+    }
+
+    /// Record `origin` in the index file at `path`, creating it if needed
+    pub fn append(path: &Path, origin: AddressOrigin) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut index = Self::load(path)?.unwrap_or_default();
+        if index.record(origin) {
+            index.save(path)?;
+        }
+        Ok(index)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        state_file::save_json(path, self)
+    }
+
+    /// `None` if nothing was saved yet
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        state_file::load_json(path)
+    }
+}
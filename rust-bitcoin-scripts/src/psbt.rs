@@ -0,0 +1,199 @@
+//! PSBT helpers: creation from a descriptor and UTXO set, local signing with in-memory keys,
+//! base64 encoding, merging partial signatures from several signers and finalizing.
+//!
+//! `create` fills `witness_utxo`/`non_witness_utxo`, `redeem_script`, `witness_script`, the taproot
+//! fields and BIP32 derivations from the descriptor, which is what `sign` uses to decide which
+//! keys sign which input. Covers the crate's patterns: `sh(multi)`, `wsh(...)`, `sh(wsh(...))`,
+//! `wpkh` and `tr(...)` (key path and script leaves).
+
+use crate::backend::WalletBackend;
+use crate::read_only;
+use crate::taproot::KeyPathTweak;
+use base64::Engine;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::key::KeyPair;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::taproot::{self, TapLeafHash};
+use bitcoin::{ecdsa, OutPoint, PrivateKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::psbt::{PsbtExt, PsbtInputExt};
+use miniscript::Descriptor;
+
+/// Sequence of inputs without a relative timelock: signals replaceability (BIP125) and leaves
+/// the lock time enabled
+pub const DEFAULT_SEQUENCE: Sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+
+/// An output to spend. `prev_tx` is required for non-segwit descriptors (BIP174 `non_witness_utxo`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendableUtxo {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    pub prev_tx: Option<Transaction>,
+    pub sequence: Sequence,
+}
+
+impl SpendableUtxo {
+    pub fn new(outpoint: OutPoint, txout: TxOut) -> Self {
+        Self { outpoint, txout, prev_tx: None, sequence: DEFAULT_SEQUENCE }
+    }
+}
+
+/// Unsigned PSBT spending `utxos`, all locked by `descriptor`, to `outputs`
+pub fn create(descriptor: &Descriptor<DefiniteDescriptorKey>, utxos: &[SpendableUtxo], outputs: Vec<TxOut>, lock_time: LockTime) -> Result<Psbt, Box<dyn std::error::Error>> {
+    let tx = Transaction {
+        version: 2,
+        lock_time,
+        input: utxos.iter().map(|u| TxIn { previous_output: u.outpoint, script_sig: ScriptBuf::new(), sequence: u.sequence, witness: Witness::new() }).collect(),
+        output: outputs,
+    };
+    let mut psbt = Psbt::from_unsigned_tx(tx)?;
+    for (index, utxo) in utxos.iter().enumerate() {
+        update_input(&mut psbt, index, descriptor, utxo)?;
+    }
+    Ok(psbt)
+}
+
+/// Fill input `index` from its descriptor and spent output, checking they match
+pub fn update_input(psbt: &mut Psbt, index: usize, descriptor: &Descriptor<DefiniteDescriptorKey>, utxo: &SpendableUtxo) -> Result<(), Box<dyn std::error::Error>> {
+    let input = psbt.inputs.get_mut(index).ok_or_else(|| format!("PSBT has no input {}", index))?;
+    let derived = input.update_with_descriptor_unchecked(descriptor)?;
+    if derived.script_pubkey() != utxo.txout.script_pubkey {
+        return Err(format!("input {}: descriptor does not produce the spent scriptPubKey", index).into());
+    }
+    let segwit = descriptor.desc_type().segwit_version().is_some();
+    if let Some(prev_tx) = &utxo.prev_tx {
+        if prev_tx.txid() != utxo.outpoint.txid || prev_tx.output.get(utxo.outpoint.vout as usize) != Some(&utxo.txout) {
+            return Err(format!("input {}: prev_tx does not contain the spent output", index).into());
+        }
+        input.non_witness_utxo = Some(prev_tx.clone());
+    } else if !segwit {
+        return Err(format!("input {}: non-segwit inputs need the previous transaction", index).into());
+    }
+    if segwit {
+        input.witness_utxo = Some(utxo.txout.clone());
+    }
+    Ok(())
+}
+
+fn spent_output(psbt: &Psbt, index: usize) -> Option<TxOut> {
+    let input = &psbt.inputs[index];
+    input.witness_utxo.clone().or_else(|| {
+        let vout = psbt.unsigned_tx.input[index].previous_output.vout as usize;
+        input.non_witness_utxo.as_ref().and_then(|tx| tx.output.get(vout).cloned())
+    })
+}
+
+/// Sign every input the given keys can sign, as described by the fields `create` filled in:
+/// ECDSA keys listed in `bip32_derivation`, the taproot internal key (key path, tweaked) and
+/// x-only keys whose `tap_key_origins` name a leaf. All signatures use SIGHASH_ALL/DEFAULT.
+/// Returns the number of signatures added. In read-only mode the signatures are only counted
+/// and `psbt` is left unchanged.
+pub fn sign(psbt: &mut Psbt, keys: &[PrivateKey]) -> Result<usize, Box<dyn std::error::Error>> {
+    if read_only::is_enabled() {
+        let added = add_signatures(&mut psbt.clone(), keys)?;
+        read_only::record("sign", format!("{} signature(s) for {} not added", added, psbt.unsigned_tx.txid()));
+        return Ok(added);
+    }
+    add_signatures(psbt, keys)
+}
+
+fn add_signatures(psbt: &mut Psbt, keys: &[PrivateKey]) -> Result<usize, Box<dyn std::error::Error>> {
+    let secp = Secp256k1::new();
+    let tx = psbt.unsigned_tx.clone();
+    let prevouts: Option<Vec<TxOut>> = (0..psbt.inputs.len()).map(|i| spent_output(psbt, i)).collect();
+    let mut cache = SighashCache::new(&tx);
+    let mut added = 0;
+    for index in 0..psbt.inputs.len() {
+        let spent = spent_output(psbt, index).ok_or_else(|| format!("input {} has no UTXO information", index))?;
+        let input = &mut psbt.inputs[index];
+        if input.tap_internal_key.is_some() {
+            let prevouts = prevouts.as_ref().ok_or("taproot signing needs the UTXO of every input")?;
+            for key in keys {
+                let keypair = KeyPair::from_secret_key(&secp, &key.inner);
+                let x_only = keypair.x_only_public_key().0;
+                if input.tap_internal_key == Some(x_only) {
+                    let sighash = cache.taproot_key_spend_signature_hash(index, &Prevouts::All(prevouts), TapSighashType::Default)?;
+                    let signer = KeyPathTweak::new(&secp, x_only, input.tap_merkle_root).signer(&secp, &keypair)?;
+                    input.tap_key_sig = Some(signer.sign_sighash(&secp, sighash, TapSighashType::Default));
+                    added += 1;
+                }
+                let leaves: Vec<TapLeafHash> = input.tap_key_origins.get(&x_only).map(|(leaves, _)| leaves.clone()).unwrap_or_default();
+                for (script, version) in input.tap_scripts.values() {
+                    let leaf_hash = TapLeafHash::from_script(script, *version);
+                    if !leaves.contains(&leaf_hash) {
+                        continue;
+                    }
+                    let sighash = cache.taproot_script_spend_signature_hash(index, &Prevouts::All(prevouts), leaf_hash, TapSighashType::Default)?;
+                    let sig = secp.sign_schnorr_no_aux_rand(&Message::from_slice(sighash.as_ref())?, &keypair);
+                    input.tap_script_sigs.insert((x_only, leaf_hash), taproot::Signature { sig, hash_ty: TapSighashType::Default });
+                    added += 1;
+                }
+            }
+            continue;
+        }
+        for key in keys {
+            let public_key = key.public_key(&secp);
+            if !input.bip32_derivation.contains_key(&public_key.inner) {
+                continue;
+            }
+            let sighash = if let Some(witness_script) = &input.witness_script {
+                cache.segwit_signature_hash(index, witness_script, spent.value, EcdsaSighashType::All)?.to_byte_array()
+            } else if spent.script_pubkey.is_v0_p2wpkh() || input.redeem_script.as_ref().map_or(false, |s| s.is_v0_p2wpkh()) {
+                let script_code = ScriptBuf::new_p2pkh(&public_key.pubkey_hash());
+                cache.segwit_signature_hash(index, &script_code, spent.value, EcdsaSighashType::All)?.to_byte_array()
+            } else {
+                let script_code = input.redeem_script.clone().unwrap_or_else(|| spent.script_pubkey.clone());
+                cache.legacy_signature_hash(index, &script_code, EcdsaSighashType::All.to_u32())?.to_byte_array()
+            };
+            let sig = secp.sign_ecdsa(&Message::from_slice(&sighash)?, &key.inner);
+            input.partial_sigs.insert(public_key, ecdsa::Signature::sighash_all(sig));
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
+pub fn to_base64(psbt: &Psbt) -> String {
+    base64::engine::general_purpose::STANDARD.encode(psbt.serialize())
+}
+
+pub fn from_base64(s: &str) -> Result<Psbt, Box<dyn std::error::Error>> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(s)?;
+    Ok(Psbt::deserialize(&bytes)?)
+}
+
+/// Merge the signatures and metadata of `theirs` into `ours` (BIP174 combiner)
+pub fn merge(mut ours: Psbt, theirs: Psbt) -> Result<Psbt, Box<dyn std::error::Error>> {
+    ours.combine(theirs)?;
+    Ok(ours)
+}
+
+/// Hand `psbt` to `wallet` for the inputs it controls (e.g. fee inputs) without letting it
+/// finalize, then merge its signatures back into our copy so our own partial signatures survive.
+pub async fn process_with_wallet(wallet: &impl WalletBackend, psbt: Psbt) -> Result<Psbt, Box<dyn std::error::Error>> {
+    let processed = wallet.sign_psbt(&psbt).await?;
+    merge(psbt, processed)
+}
+
+/// Finalize every input with the miniscript finalizer and extract the network transaction.
+/// Fails unless every input ends up with a final scriptSig or witness that `verify::verify_spend` accepts.
+pub fn finalize(mut psbt: Psbt) -> Result<Transaction, Box<dyn std::error::Error>> {
+    let secp = Secp256k1::verification_only();
+    psbt.finalize_mut(&secp).map_err(|errors| format!("PSBT finalization failed: {:?}", errors))?;
+    for (i, input) in psbt.inputs.iter().enumerate() {
+        let has_witness = input.final_script_witness.as_ref().map_or(false, |w| !w.is_empty());
+        let has_script_sig = input.final_script_sig.as_ref().map_or(false, |s| !s.is_empty());
+        if !has_witness && !has_script_sig {
+            return Err(format!("input {} has no final witness or scriptSig", i).into());
+        }
+    }
+    let prevouts = (0..psbt.inputs.len())
+        .map(|i| spent_output(&psbt, i).ok_or_else(|| format!("input {} has no witness_utxo or non_witness_utxo", i)))
+        .collect::<Result<Vec<TxOut>, String>>()?;
+    let tx = psbt.extract_tx();
+    crate::verify::verify_spend(&tx, &prevouts)?;
+    Ok(tx)
+}
@@ -0,0 +1,120 @@
+//! Process-wide read-only (dry-run) mode for staging against real chain data.
+//!
+//! While enabled, RPC calls that broadcast or sign (`sendrawtransaction`, wallet sends, wallet
+//! signing, fee bumps, ...) never reach the node and local PSBT signing leaves the PSBT untouched.
+//! Each intercepted operation is logged and recorded, and a simulated result is returned where one
+//! can be derived: a raw broadcast is checked with `testmempoolaccept` and answers the txid the
+//! broadcast would have had; signing returns its input unchanged and incomplete. Wallet sends
+//! have no such answer and fail with `ReadOnlyError`.
+
+use log::info;
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SIMULATED: Mutex<Vec<SimulatedOperation>> = Mutex::new(Vec::new());
+
+/// RPCs that broadcast or commit wallet funds
+const BROADCAST_METHODS: &[&str] = &[
+    "sendrawtransaction", "submitpackage", "sendtoaddress", "sendmany", "send", "sendall", "bumpfee",
+];
+/// RPCs that produce signatures
+const SIGNING_METHODS: &[&str] = &[
+    "walletprocesspsbt", "signrawtransactionwithwallet", "signrawtransactionwithkey", "psbtbumpfee", "signmessage",
+];
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn disable() {
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Enable read-only mode when `WRAPYIELD_READ_ONLY` is `1`, `true` or `yes`; returns whether it is on
+pub fn enable_from_env() -> bool {
+    if std::env::var("WRAPYIELD_READ_ONLY").map_or(false, |v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes")) {
+        enable();
+    }
+    is_enabled()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedOperation {
+    /// RPC method, or `sign` for local PSBT signing
+    pub operation: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadOnlyError {
+    /// The operation has no result that can be produced without executing it
+    NotSimulated { operation: String },
+}
+
+impl fmt::Display for ReadOnlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadOnlyError::NotSimulated { operation } => write!(f, "read-only mode: {} was not executed", operation),
+        }
+    }
+}
+
+impl std::error::Error for ReadOnlyError {}
+
+/// Log and remember an operation that was simulated instead of executed
+pub fn record(operation: &str, detail: String) {
+    info!("[read-only] simulated {}: {}", operation, detail);
+    SIMULATED.lock().unwrap_or_else(|e| e.into_inner()).push(SimulatedOperation { operation: operation.to_string(), detail });
+}
+
+/// Drain the operations simulated so far
+pub fn take_simulated() -> Vec<SimulatedOperation> {
+    std::mem::take(&mut *SIMULATED.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Whether read-only mode must keep `method` from reaching the node.
+/// `walletprocesspsbt` with `sign` = false only updates the PSBT and is let through.
+pub fn intercepts(method: &str, params: &Value) -> bool {
+    if method == "walletprocesspsbt" && params.get(1).and_then(Value::as_bool) == Some(false) {
+        return false;
+    }
+    BROADCAST_METHODS.contains(&method) || SIGNING_METHODS.contains(&method)
+}
+
+/// Result returned for an intercepted `method`. For `sendrawtransaction`, `mempool_check` is the
+/// node's `testmempoolaccept` answer for the same transaction; a rejection is reported like a
+/// failed broadcast would be.
+pub fn simulate(method: &str, params: &Value, mempool_check: Option<&Value>) -> Result<Value, Box<dyn std::error::Error>> {
+    match method {
+        "sendrawtransaction" => {
+            let check = mempool_check.and_then(|c| c.get(0)).ok_or("testmempoolaccept returned no result")?;
+            let txid = check["txid"].as_str().unwrap_or_default().to_string();
+            if check["allowed"].as_bool() != Some(true) {
+                let reason = check["reject-reason"].as_str().unwrap_or("rejected").to_string();
+                record(method, format!("{} would be rejected: {}", txid, reason));
+                return Err(format!("RPC error: {} (read-only testmempoolaccept)", reason).into());
+            }
+            record(method, format!("{} would be accepted", txid));
+            Ok(json!(txid))
+        }
+        "walletprocesspsbt" => {
+            record(method, "signing skipped".to_string());
+            Ok(json!({ "psbt": params[0].clone(), "complete": false }))
+        }
+        "signrawtransactionwithwallet" | "signrawtransactionwithkey" => {
+            record(method, "signing skipped".to_string());
+            Ok(json!({ "hex": params[0].clone(), "complete": false }))
+        }
+        _ => {
+            record(method, format!("not executed, params {}", params));
+            Err(ReadOnlyError::NotSimulated { operation: method.to_string() }.into())
+        }
+    }
+}
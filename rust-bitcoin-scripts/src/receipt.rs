@@ -0,0 +1,174 @@
+//! What a spend left behind once broadcast: `SpendReceipt` carries the ids, fee, path and
+//! witness sizes of the transaction and, after `refresh`, the block that confirmed it.
+//! `SpendHistory` keeps receipts in a JSON file so confirmations can be checked later. A receipt
+//! tagged with the `Job` it was for is what `accounting::FeeLedger` attributes its fee to.
+
+use crate::backend::ChainBackend;
+use crate::fees;
+use crate::state_file;
+use bitcoin::{Amount, BlockHash, FeeRate, Transaction, Txid, Wtxid};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Block a transaction confirmed in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockRef {
+    pub hash: BlockHash,
+    pub height: u64,
+}
+
+/// What a spend was for
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Job {
+    DepositSweep,
+    /// Peg-out batch number `batch`
+    PegOutBatch { batch: u64 },
+    Clawback,
+    Consolidation,
+    Recovery,
+    Other { name: String },
+}
+
+impl Job {
+    /// Product the fee is booked to
+    pub fn product(&self) -> &str {
+        match self {
+            Job::DepositSweep => "deposit_sweep",
+            Job::PegOutBatch { .. } => "peg_out",
+            Job::Clawback => "clawback",
+            Job::Consolidation => "consolidation",
+            Job::Recovery => "recovery",
+            Job::Other { name } => name,
+        }
+    }
+
+    /// Which one of the product's jobs, where they are numbered
+    pub fn reference(&self) -> Option<String> {
+        match self {
+            Job::PegOutBatch { batch } => Some(batch.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reference() {
+            Some(reference) => write!(f, "{} #{}", self.product(), reference),
+            None => write!(f, "{}", self.product()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpendReceipt {
+    pub txid: Txid,
+    pub wtxid: Wtxid,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub fee: Amount,
+    pub feerate: FeeRate,
+    /// Which spend path the inputs took, e.g. "script", "taproot_key"
+    pub path_used: String,
+    /// Serialized witness size of each input, in input order
+    pub witness_sizes: Vec<usize>,
+    /// Unix time of the broadcast
+    pub broadcast_time: u64,
+    /// `None` until confirmed
+    pub block: Option<BlockRef>,
+    /// `None` for receipts nobody tagged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<Job>,
+}
+
+impl SpendReceipt {
+    /// Receipt for `tx`, broadcast just now
+    pub fn new(tx: &Transaction, fee: Amount, path_used: impl Into<String>) -> Self {
+        let weight = tx.weight().to_wu().max(1);
+        Self {
+            txid: tx.txid(),
+            wtxid: tx.wtxid(),
+            fee,
+            feerate: FeeRate::from_sat_per_kwu(fee.to_sat() * 1_000 / weight),
+            path_used: path_used.into(),
+            witness_sizes: tx.input.iter().map(|i| i.witness.serialized_len()).collect(),
+            broadcast_time: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            block: None,
+            job: None,
+        }
+    }
+
+    /// Tag the receipt with the job the spend was for
+    pub fn for_job(mut self, job: Job) -> Self {
+        self.job = Some(job);
+        self
+    }
+
+    /// Broadcast `tx` (checked against the relay floor) and return its receipt
+    pub async fn broadcast(backend: &impl ChainBackend, tx: &Transaction, fee: Amount, path_used: impl Into<String>) -> Result<Self, Box<dyn std::error::Error>> {
+        fees::broadcast_above_floor(backend, tx, fee).await?;
+        Ok(Self::new(tx, fee, path_used))
+    }
+
+    /// Look the transaction up and record the block once it is confirmed
+    pub async fn refresh(&mut self, backend: &impl ChainBackend) -> Result<Option<&BlockRef>, Box<dyn std::error::Error>> {
+        let status = backend.tx_status(&self.txid).await?.ok_or_else(|| format!("{} is unknown to the backend", self.txid))?;
+        self.block = status.block.map(|(hash, height)| BlockRef { hash, height });
+        Ok(self.block.as_ref())
+    }
+}
+
+/// Receipts of past spends, oldest first
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpendHistory {
+    pub receipts: Vec<SpendReceipt>,
+}
+
+impl SpendHistory {
+    /// Add `receipt`, replacing an earlier one for the same transaction
+    pub fn record(&mut self, receipt: SpendReceipt) {
+        match self.receipts.iter_mut().find(|r| r.txid == receipt.txid) {
+            Some(existing) => *existing = receipt,
+            None => self.receipts.push(receipt),
+        }
+    }
+
+    pub fn get(&self, txid: &Txid) -> Option<&SpendReceipt> {
+        self.receipts.iter().find(|r| r.txid == *txid)
+    }
+
+    /// Receipts without a confirming block
+    pub fn pending(&self) -> impl Iterator<Item = &SpendReceipt> {
+        self.receipts.iter().filter(|r| r.block.is_none())
+    }
+
+    /// Refresh every pending receipt; returns how many are now confirmed
+    pub async fn refresh(&mut self, backend: &impl ChainBackend) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut confirmed = 0;
+        for receipt in self.receipts.iter_mut().filter(|r| r.block.is_none()) {
+            if receipt.refresh(backend).await?.is_some() {
+                confirmed += 1;
+            }
+        }
+        Ok(confirmed)
+    }
+
+    /// Record `receipt` in the history file at `path`, creating it if needed
+    pub fn append(path: &Path, receipt: SpendReceipt) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut history = Self::load(path)?.unwrap_or_default();
+        history.record(receipt);
+        history.save(path)?;
+        Ok(history)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        state_file::save_json(path, self)
+    }
+
+    /// `None` if nothing was saved yet
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        state_file::load_json(path)
+    }
+}
@@ -0,0 +1,123 @@
+//! Recovery transactions pre-signed at deposit time.
+//!
+//! A `RecoveryTemplate` describes the spend of a vault output to a cold-storage descriptor
+//! through the vault's CSV branch: which descriptor locks the deposit, how much it holds, the
+//! relative delay and the fee. `presign` signs it for a known deposit outpoint and the result is
+//! stored with `save` until it is needed. If the deposit confirms under a different txid
+//! (e.g. the funding transaction was replaced), `for_deposit` signs the template again for the
+//! new outpoint; the old signatures commit to the old txid and are useless.
+
+use crate::amount::deduct_fee_for;
+use crate::backend::ChainBackend;
+use crate::psbt::{self, SpendableUtxo};
+use crate::receipt::{Job, SpendReceipt};
+use crate::state_file;
+use bitcoin::absolute::LockTime;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{Amount, OutPoint, PrivateKey, PublicKey, Sequence, Transaction, TxOut, Txid};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Everything needed to (re)build the recovery spend except the deposit outpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecoveryTemplate {
+    /// Descriptor locking the deposit; must have a branch satisfiable after `csv` blocks
+    pub vault_descriptor: String,
+    pub cold_descriptor: String,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub amount: Amount,
+    pub csv: u16,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub fee: Amount,
+}
+
+impl RecoveryTemplate {
+    pub fn new(vault: &Descriptor<PublicKey>, cold: &Descriptor<PublicKey>, amount: Amount, csv: u16, fee: Amount) -> Result<Self, Box<dyn std::error::Error>> {
+        deduct_fee_for(amount, fee, &cold.script_pubkey())?;
+        Ok(Self { vault_descriptor: vault.to_string(), cold_descriptor: cold.to_string(), amount, csv, fee })
+    }
+
+    pub fn vault(&self) -> Result<Descriptor<PublicKey>, Box<dyn std::error::Error>> {
+        Ok(Descriptor::from_str(&self.vault_descriptor)?)
+    }
+
+    pub fn cold(&self) -> Result<Descriptor<PublicKey>, Box<dyn std::error::Error>> {
+        Ok(Descriptor::from_str(&self.cold_descriptor)?)
+    }
+
+    /// The deposit output this template spends
+    pub fn prevout(&self) -> Result<TxOut, Box<dyn std::error::Error>> {
+        Ok(TxOut { value: self.amount.to_sat(), script_pubkey: self.vault()?.script_pubkey() })
+    }
+
+    /// Find the deposit in `funding`: the output paying the vault with the template amount
+    pub fn find_deposit(&self, funding: &Transaction) -> Result<OutPoint, Box<dyn std::error::Error>> {
+        let prevout = self.prevout()?;
+        let vout = funding.output.iter().position(|o| *o == prevout).ok_or_else(|| format!("{} has no {} output to the vault", funding.txid(), self.amount))?;
+        Ok(OutPoint::new(funding.txid(), vout as u32))
+    }
+
+    /// Unsigned recovery spending `deposit`, with the CSV delay in its sequence
+    pub fn unsigned(&self, deposit: OutPoint) -> Result<Psbt, Box<dyn std::error::Error>> {
+        let vault: Descriptor<DefiniteDescriptorKey> = Descriptor::from_str(&self.vault_descriptor)?;
+        let cold = self.cold()?.script_pubkey();
+        let value = deduct_fee_for(self.amount, self.fee, &cold)?;
+        let mut utxo = SpendableUtxo::new(deposit, self.prevout()?);
+        utxo.sequence = Sequence::from_height(self.csv);
+        psbt::create(&vault, &[utxo], vec![TxOut { value: value.to_sat(), script_pubkey: cold }], LockTime::ZERO)
+    }
+
+    /// Sign the recovery of `deposit` with `keys`; the result is checked with `verify::verify_spend`
+    pub fn presign(&self, deposit: OutPoint, keys: &[PrivateKey]) -> Result<PresignedRecovery, Box<dyn std::error::Error>> {
+        let mut unsigned = self.unsigned(deposit)?;
+        psbt::sign(&mut unsigned, keys)?;
+        let tx = psbt::finalize(unsigned)?;
+        Ok(PresignedRecovery { template: self.clone(), deposit, tx })
+    }
+}
+
+/// A signed recovery, kept until the vault needs it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresignedRecovery {
+    pub template: RecoveryTemplate,
+    pub deposit: OutPoint,
+    pub tx: Transaction,
+}
+
+impl PresignedRecovery {
+    pub fn txid(&self) -> Txid {
+        self.tx.txid()
+    }
+
+    /// First height the recovery can be mined at, for a deposit confirmed at `deposit_height`
+    pub fn valid_from(&self, deposit_height: u64) -> u64 {
+        deposit_height + self.template.csv as u64
+    }
+
+    /// This recovery if `funding` is the expected deposit transaction, otherwise the template
+    /// signed again for the deposit output `funding` actually contains
+    pub fn for_deposit(&self, funding: &Transaction, keys: &[PrivateKey]) -> Result<Self, Box<dyn std::error::Error>> {
+        let deposit = self.template.find_deposit(funding)?;
+        if deposit == self.deposit {
+            return Ok(self.clone());
+        }
+        self.template.presign(deposit, keys)
+    }
+
+    /// Broadcast the stored transaction; fails on the node until the CSV delay has passed
+    pub async fn rebroadcast(&self, backend: &impl ChainBackend) -> Result<SpendReceipt, Box<dyn std::error::Error>> {
+        Ok(SpendReceipt::broadcast(backend, &self.tx, self.template.fee, "recovery").await?.for_job(Job::Recovery))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        state_file::save_json(path, self)
+    }
+
+    /// `None` if nothing was saved yet
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        state_file::load_json(path)
+    }
+}
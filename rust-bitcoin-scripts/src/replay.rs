@@ -0,0 +1,121 @@
+//! Replay of historical blocks through the scanner for backtesting.
+//!
+//! A replay feeds a height range from the backend through `BlockScanner` with read-only mode
+//! switched on, so nothing downstream of detection can broadcast or sign. The report carries the
+//! detected deposits and spends, to compare against what was expected, and timings split into
+//! block fetch and classification to measure scanner throughput on real data.
+
+use crate::backend::ChainBackend;
+use crate::read_only;
+use crate::scanner::{BlockScanner, ScanEvent};
+use bitcoin::OutPoint;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayReport {
+    pub from: u64,
+    pub to: u64,
+    pub blocks: u64,
+    pub transactions: u64,
+    pub events: Vec<ScanEvent>,
+    /// Time spent in `getblockhash`/`getblock`
+    pub fetch_time: Duration,
+    /// Time spent classifying transactions, including fallback prevout lookups
+    pub classify_time: Duration,
+    /// Height and total time of the slowest block
+    pub slowest_block: Option<(u64, Duration)>,
+    pub fallback_lookups: u64,
+}
+
+impl ReplayReport {
+    pub fn deposits(&self) -> usize {
+        self.events.iter().filter(|e| matches!(e, ScanEvent::Deposit { .. })).count()
+    }
+
+    pub fn spends(&self) -> usize {
+        self.events.iter().filter(|e| matches!(e, ScanEvent::Spend { .. })).count()
+    }
+
+    pub fn blocks_per_sec(&self) -> f64 {
+        self.blocks as f64 / (self.fetch_time + self.classify_time).as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn transactions_per_sec(&self) -> f64 {
+        self.transactions as f64 / (self.fetch_time + self.classify_time).as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Compare detected events with `expected`, matching on kind, outpoint and height
+    pub fn compare(&self, expected: &[ScanEvent]) -> DetectionDiff {
+        let key = |e: &ScanEvent| match e {
+            ScanEvent::Deposit { outpoint, height, .. } => (false, *outpoint, *height),
+            ScanEvent::Spend { outpoint, height, .. } => (true, *outpoint, *height),
+        };
+        let detected: Vec<(bool, OutPoint, u64)> = self.events.iter().map(key).collect();
+        let wanted: Vec<(bool, OutPoint, u64)> = expected.iter().map(key).collect();
+        DetectionDiff {
+            missed: expected.iter().filter(|e| !detected.contains(&key(e))).cloned().collect(),
+            unexpected: self.events.iter().filter(|e| !wanted.contains(&key(e))).cloned().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DetectionDiff {
+    pub missed: Vec<ScanEvent>,
+    pub unexpected: Vec<ScanEvent>,
+}
+
+impl DetectionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.missed.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Scan heights `from..=to` in read-only mode. The mode is switched back off afterwards unless
+/// it was already on.
+pub async fn replay(backend: &impl ChainBackend, scanner: &mut BlockScanner, from: u64, to: u64) -> Result<ReplayReport, Box<dyn std::error::Error>> {
+    if from > to {
+        return Err(format!("empty replay range {}..={}", from, to).into());
+    }
+    let was_read_only = read_only::is_enabled();
+    read_only::enable();
+    let result = replay_range(backend, scanner, from, to).await;
+    if !was_read_only {
+        read_only::disable();
+    }
+    result
+}
+
+async fn replay_range(backend: &impl ChainBackend, scanner: &mut BlockScanner, from: u64, to: u64) -> Result<ReplayReport, Box<dyn std::error::Error>> {
+    let lookups_before = scanner.fallback_lookups;
+    let mut report = ReplayReport {
+        from,
+        to,
+        blocks: 0,
+        transactions: 0,
+        events: Vec::new(),
+        fetch_time: Duration::ZERO,
+        classify_time: Duration::ZERO,
+        slowest_block: None,
+        fallback_lookups: 0,
+    };
+    for height in from..=to {
+        let started = Instant::now();
+        let hash = backend.block_hash(height).await?;
+        let block = scanner.fetch_block(backend, &hash).await?;
+        let fetched = Instant::now();
+        report.events.extend(scanner.scan_block_json(backend, &block).await?);
+        let classified = Instant::now();
+
+        report.blocks += 1;
+        report.transactions += block["tx"].as_array().map_or(0, |txs| txs.len() as u64);
+        report.fetch_time += fetched - started;
+        report.classify_time += classified - fetched;
+        let total = classified - started;
+        if report.slowest_block.map_or(true, |(_, slowest)| total > slowest) {
+            report.slowest_block = Some((height, total));
+        }
+    }
+    report.fallback_lookups = scanner.fallback_lookups - lookups_before;
+    Ok(report)
+}
@@ -0,0 +1,87 @@
+//! Amount reporting: sats, BTC and an optional fiat estimate for CLI and JSON outputs
+
+use bitcoin::Amount;
+use serde::Serialize;
+use std::fmt;
+
+/// Source of a BTC price in some fiat currency. Returning `None` simply omits the fiat estimate.
+pub trait PriceSource {
+    fn currency(&self) -> &str;
+    fn btc_price(&self) -> Option<f64>;
+}
+
+/// No fiat estimate (the default for regtest)
+pub struct NoPrice;
+
+impl PriceSource for NoPrice {
+    fn currency(&self) -> &str {
+        ""
+    }
+    fn btc_price(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// A fixed, caller-supplied price (e.g. from config or a price feed fetched elsewhere)
+pub struct FixedPrice {
+    pub currency: String,
+    pub price: f64,
+}
+
+impl PriceSource for FixedPrice {
+    fn currency(&self) -> &str {
+        &self.currency
+    }
+    fn btc_price(&self) -> Option<f64> {
+        Some(self.price)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FiatEstimate {
+    pub currency: String,
+    pub value: f64,
+}
+
+/// An amount rendered in every unit we report. `btc` is a string so it is exact and locale-independent.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AmountReport {
+    pub sats: u64,
+    pub btc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fiat: Option<FiatEstimate>,
+}
+
+impl AmountReport {
+    pub fn new(amount: Amount, price: &dyn PriceSource) -> Self {
+        let fiat = price.btc_price().map(|p| FiatEstimate {
+            currency: price.currency().to_string(),
+            value: (amount.to_sat() as f64 / 100_000_000.0 * p * 100.0).round() / 100.0,
+        });
+        Self {
+            sats: amount.to_sat(),
+            btc: format_btc(amount),
+            fiat,
+        }
+    }
+
+    pub fn sats(amount: Amount) -> Self {
+        Self::new(amount, &NoPrice)
+    }
+}
+
+impl fmt::Display for AmountReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sats ({} BTC", self.sats, self.btc)?;
+        if let Some(fiat) = &self.fiat {
+            write!(f, ", ~{:.2} {}", fiat.value, fiat.currency)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Format an amount as BTC with exactly 8 decimals using integer math (no f64 rounding)
+pub fn format_btc(amount: Amount) -> String {
+    let sats = amount.to_sat();
+    format!("{}.{:08}", sats / 100_000_000, sats % 100_000_000)
+}
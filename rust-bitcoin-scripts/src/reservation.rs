@@ -0,0 +1,138 @@
+//! UTXO reservations so concurrent transaction builders never select the same output.
+//!
+//! Reservations are all-or-nothing per call and expire after a TTL, so a builder that crashes or
+//! abandons a batch cannot strand vault UTXOs. Optionally mirrored on the node with `lockunspent`
+//! (only meaningful for outputs the node wallet knows about; the lock does not survive a restart).
+
+use crate::test_setup::BitcoinRPC;
+use bitcoin::OutPoint;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reservation {
+    pub owner: String,
+    pub expires: Instant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReservationConflict {
+    pub outpoint: OutPoint,
+    pub owner: String,
+}
+
+impl fmt::Display for ReservationConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is reserved by {}", self.outpoint, self.owner)
+    }
+}
+
+impl std::error::Error for ReservationConflict {}
+
+/// Cheaply cloneable handle to a shared reservation table
+#[derive(Clone)]
+pub struct UtxoReservations {
+    table: Arc<Mutex<HashMap<OutPoint, Reservation>>>,
+    pub default_ttl: Duration,
+}
+
+impl UtxoReservations {
+    pub fn new(default_ttl: Duration) -> Self {
+        Self { table: Arc::new(Mutex::new(HashMap::new())), default_ttl }
+    }
+
+    /// Reserve every outpoint for `owner` or none of them. Re-reserving an owner's own outpoint
+    /// extends its TTL.
+    pub fn reserve(&self, owner: &str, outpoints: &[OutPoint], ttl: Option<Duration>) -> Result<(), ReservationConflict> {
+        let now = Instant::now();
+        let mut table = self.table.lock().unwrap();
+        table.retain(|_, r| r.expires > now);
+        if let Some((outpoint, r)) = outpoints.iter()
+            .find_map(|o| table.get(o).filter(|r| r.owner != owner).map(|r| (*o, r)))
+        {
+            return Err(ReservationConflict { outpoint, owner: r.owner.clone() });
+        }
+        let expires = now + ttl.unwrap_or(self.default_ttl);
+        for outpoint in outpoints {
+            table.insert(*outpoint, Reservation { owner: owner.to_string(), expires });
+        }
+        Ok(())
+    }
+
+    /// Pick and reserve candidates (in order) that are not held by anyone else until `target_sats` is covered
+    pub fn select(&self, owner: &str, candidates: &[(OutPoint, u64)], target_sats: u64) -> Result<Vec<(OutPoint, u64)>, Box<dyn std::error::Error>> {
+        let now = Instant::now();
+        let mut table = self.table.lock().unwrap();
+        table.retain(|_, r| r.expires > now);
+        let mut selected = Vec::new();
+        let mut total = 0;
+        for (outpoint, value) in candidates {
+            if total >= target_sats {
+                break;
+            }
+            if table.get(outpoint).map_or(true, |r| r.owner == owner) {
+                selected.push((*outpoint, *value));
+                total += value;
+            }
+        }
+        if total < target_sats {
+            return Err(format!("only {} sats unreserved, need {}", total, target_sats).into());
+        }
+        let expires = now + self.default_ttl;
+        for (outpoint, _) in &selected {
+            table.insert(*outpoint, Reservation { owner: owner.to_string(), expires });
+        }
+        Ok(selected)
+    }
+
+    /// Release the given outpoints if held by `owner`
+    pub fn release(&self, owner: &str, outpoints: &[OutPoint]) {
+        let mut table = self.table.lock().unwrap();
+        for outpoint in outpoints {
+            if table.get(outpoint).map_or(false, |r| r.owner == owner) {
+                table.remove(outpoint);
+            }
+        }
+    }
+
+    /// Release the outpoints regardless of owner (e.g. after a stalled signing round)
+    pub fn release_all(&self, outpoints: &[OutPoint]) {
+        let mut table = self.table.lock().unwrap();
+        for outpoint in outpoints {
+            table.remove(outpoint);
+        }
+    }
+
+    pub fn reserved_by(&self, outpoint: &OutPoint) -> Option<String> {
+        let table = self.table.lock().unwrap();
+        table.get(outpoint).filter(|r| r.expires > Instant::now()).map(|r| r.owner.clone())
+    }
+
+    /// Reserve locally, then lock the outpoints in the node wallet
+    pub async fn reserve_on_node(&self, rpc: &BitcoinRPC, owner: &str, outpoints: &[OutPoint]) -> Result<(), Box<dyn std::error::Error>> {
+        self.reserve(owner, outpoints, None)?;
+        if let Err(e) = lock_unspent(rpc, outpoints, false).await {
+            self.release(owner, outpoints);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub async fn release_on_node(&self, rpc: &BitcoinRPC, owner: &str, outpoints: &[OutPoint]) -> Result<(), Box<dyn std::error::Error>> {
+        self.release(owner, outpoints);
+        lock_unspent(rpc, outpoints, true).await
+    }
+}
+
+/// `lockunspent`; `unlock` false locks the outpoints against wallet coin selection
+pub async fn lock_unspent(rpc: &BitcoinRPC, outpoints: &[OutPoint], unlock: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let list: Vec<_> = outpoints.iter().map(|o| json!({"txid": o.txid.to_string(), "vout": o.vout})).collect();
+    let result = rpc.call_rpc("lockunspent", json!([unlock, list])).await?;
+    if result.as_bool() != Some(true) {
+        return Err(format!("lockunspent returned {}", result).into());
+    }
+    Ok(())
+}
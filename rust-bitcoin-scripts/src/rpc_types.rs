@@ -0,0 +1,269 @@
+//! Typed results of the Bitcoin Core RPCs the crate relies on.
+//!
+//! Only the fields we use are declared; anything else in a response is ignored. Amounts are
+//! BTC decimals on the wire and `Amount` here, hashes and scripts are parsed from hex.
+
+use crate::outputs::{classify, OutputType};
+use bitcoin::amount::serde::as_btc;
+use bitcoin::consensus::encode::deserialize;
+use bitcoin::{Amount, BlockHash, OutPoint, ScriptBuf, Transaction, Txid, Wtxid};
+use serde::Deserialize;
+
+/// `getblockchaininfo`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BlockchainInfo {
+    /// "main", "test", "testnet4", "signet" or "regtest"
+    pub chain: String,
+    pub blocks: u64,
+    pub headers: u64,
+    #[serde(rename = "bestblockhash")]
+    pub best_block_hash: BlockHash,
+    #[serde(rename = "mediantime")]
+    pub median_time: u64,
+    #[serde(rename = "initialblockdownload")]
+    pub initial_block_download: bool,
+    pub pruned: bool,
+}
+
+/// One entry of `listunspent`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ListUnspentEntry {
+    pub txid: Txid,
+    pub vout: u32,
+    pub address: Option<String>,
+    pub label: Option<String>,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pubkey: ScriptBuf,
+    #[serde(with = "as_btc")]
+    pub amount: Amount,
+    pub confirmations: u32,
+    pub spendable: bool,
+    pub solvable: bool,
+    #[serde(rename = "desc")]
+    pub descriptor: Option<String>,
+    pub safe: bool,
+}
+
+impl ListUnspentEntry {
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint::new(self.txid, self.vout)
+    }
+}
+
+/// `signrawtransactionwithwallet` / `signrawtransactionwithkey`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SignRawTransactionResult {
+    pub hex: String,
+    pub complete: bool,
+    #[serde(default)]
+    pub errors: Vec<SignRawTransactionError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SignRawTransactionError {
+    pub txid: Txid,
+    pub vout: u32,
+    pub error: String,
+}
+
+impl SignRawTransactionResult {
+    pub fn transaction(&self) -> Result<Transaction, Box<dyn std::error::Error>> {
+        Ok(deserialize(&hex::decode(&self.hex)?)?)
+    }
+}
+
+/// `scriptPubKey` object of a decoded output
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ScriptPubKeyInfo {
+    pub hex: ScriptBuf,
+    pub address: Option<String>,
+    #[serde(rename = "type")]
+    pub script_type: String,
+}
+
+impl ScriptPubKeyInfo {
+    /// Classified from the script itself, so types older nodes call "nonstandard" are recognized
+    pub fn output_type(&self) -> OutputType {
+        classify(&self.hex)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RawTxInput {
+    /// Absent for the coinbase input
+    pub txid: Option<Txid>,
+    pub vout: Option<u32>,
+    pub coinbase: Option<String>,
+    pub sequence: u32,
+    #[serde(default, rename = "txinwitness")]
+    pub witness: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RawTxOutput {
+    #[serde(with = "as_btc")]
+    pub value: Amount,
+    pub n: u32,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pubkey: ScriptPubKeyInfo,
+}
+
+/// Verbose `getrawtransaction`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GetRawTransactionResult {
+    pub txid: Txid,
+    pub hash: Wtxid,
+    pub hex: String,
+    pub size: u64,
+    pub vsize: u64,
+    pub weight: u64,
+    pub version: i32,
+    #[serde(rename = "locktime")]
+    pub lock_time: u32,
+    pub vin: Vec<RawTxInput>,
+    pub vout: Vec<RawTxOutput>,
+    #[serde(rename = "blockhash")]
+    pub block_hash: Option<BlockHash>,
+    /// Absent while unconfirmed
+    pub confirmations: Option<u32>,
+    #[serde(rename = "blocktime")]
+    pub block_time: Option<u64>,
+}
+
+impl GetRawTransactionResult {
+    pub fn transaction(&self) -> Result<Transaction, Box<dyn std::error::Error>> {
+        Ok(deserialize(&hex::decode(&self.hex)?)?)
+    }
+
+    /// First output paying `address`
+    pub fn output_to_address(&self, address: &str) -> Option<&RawTxOutput> {
+        self.vout.iter().find(|out| out.script_pubkey.address.as_deref() == Some(address))
+    }
+
+    /// First output with `script_pubkey`
+    pub fn output_to_script(&self, script_pubkey: &ScriptBuf) -> Option<&RawTxOutput> {
+        self.vout.iter().find(|out| &out.script_pubkey.hex == script_pubkey)
+    }
+}
+
+/// One entry of `testmempoolaccept`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TestMempoolAcceptResult {
+    pub txid: Txid,
+    pub allowed: bool,
+    #[serde(rename = "reject-reason")]
+    pub reject_reason: Option<String>,
+    pub vsize: Option<u64>,
+}
+
+/// `estimatesmartfee`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct EstimateSmartFeeResult {
+    /// BTC per kvB; absent when the node has too little data (always the case on a fresh regtest)
+    #[serde(rename = "feerate", default, with = "as_btc::opt")]
+    pub fee_rate: Option<Amount>,
+    pub errors: Option<Vec<String>>,
+    /// Confirmation target the estimate was actually found for
+    pub blocks: u32,
+}
+
+/// `getwalletinfo`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WalletInfo {
+    #[serde(rename = "walletname")]
+    pub wallet_name: String,
+    /// Pregenerated external keys; for descriptor wallets the smallest count over the
+    /// active external descriptors
+    #[serde(rename = "keypoolsize")]
+    pub keypool_size: u64,
+    /// Absent on nodes predating descriptor wallets
+    pub descriptors: Option<bool>,
+}
+
+/// `getmempoolinfo`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MempoolInfo {
+    pub loaded: bool,
+    pub size: u64,
+    pub bytes: u64,
+    pub usage: u64,
+    #[serde(rename = "maxmempool")]
+    pub max_mempool: u64,
+    /// BTC per kvB; rises above `min_relay_tx_fee` once the mempool is full and evicting
+    #[serde(rename = "mempoolminfee", with = "as_btc")]
+    pub mempool_min_fee: Amount,
+    /// BTC per kvB; the node's `-minrelaytxfee`
+    #[serde(rename = "minrelaytxfee", with = "as_btc")]
+    pub min_relay_tx_fee: Amount,
+}
+
+/// `getmempoolentry`; sizes are virtual bytes
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MempoolEntry {
+    pub vsize: u64,
+    pub weight: u64,
+    /// Unconfirmed ancestors, including this transaction
+    #[serde(rename = "ancestorcount")]
+    pub ancestor_count: u64,
+    #[serde(rename = "ancestorsize")]
+    pub ancestor_size: u64,
+    pub fees: MempoolEntryFees,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MempoolEntryFees {
+    #[serde(with = "as_btc")]
+    pub base: Amount,
+    /// `base` plus any `prioritisetransaction` delta
+    #[serde(with = "as_btc")]
+    pub modified: Amount,
+    /// Modified fees of the transaction and all its unconfirmed ancestors
+    #[serde(with = "as_btc")]
+    pub ancestor: Amount,
+    #[serde(with = "as_btc")]
+    pub descendant: Amount,
+}
+
+/// An unspent output found for an address or descriptor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub amount: Amount,
+    pub script_pubkey: ScriptBuf,
+    /// 0 while in the mempool
+    pub confirmations: u32,
+}
+
+/// `scantxoutset start`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ScanTxOutSetResult {
+    pub success: bool,
+    /// Chain height the UTXO set was scanned at
+    pub height: u64,
+    pub unspents: Vec<ScanTxOutSetUnspent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ScanTxOutSetUnspent {
+    pub txid: Txid,
+    pub vout: u32,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pubkey: ScriptBuf,
+    #[serde(rename = "desc")]
+    pub descriptor: String,
+    #[serde(with = "as_btc")]
+    pub amount: Amount,
+    /// Height of the block the output was created in
+    pub height: u64,
+}
+
+impl ScanTxOutSetResult {
+    pub fn utxos(&self) -> Vec<Utxo> {
+        self.unspents.iter().map(|u| Utxo {
+            outpoint: OutPoint::new(u.txid, u.vout),
+            amount: u.amount,
+            script_pubkey: u.script_pubkey.clone(),
+            confirmations: (self.height + 1).saturating_sub(u.height) as u32,
+        }).collect()
+    }
+}
@@ -0,0 +1,206 @@
+//! Vault spends through miniscript's `Satisfier`: signatures made with the keys of a keystore
+//! entry, hash preimages and the input's own sequence and lock time, handed to
+//! `Descriptor::satisfy`, which builds the witness and scriptSig.
+//!
+//! This is the default way to spend the `wsh`/`sh(wsh)` timelock vaults of `timelock_csv` and
+//! `timelock_cltv`: the descriptor picks the cheapest branch the held keys, preimages and mature
+//! timelocks allow. The stack builders in `witness` stay for callers that need one particular
+//! branch; for the two vault branches they produce the same witness.
+//!
+//! `tr(...)` descriptors are spent along a `spend::Planner` plan, which picks the key path or the
+//! cheapest leaf the held keys and mature timelocks allow: `KeystoreSatisfier::taproot` signs
+//! only that path and the descriptor adds the leaf script and control block from its own spend
+//! info, so no `TaprootBuilder` has to be kept next to it.
+
+use crate::keystore::KeyEntry;
+use crate::spend::{SpendPath, SpendPlan};
+use crate::taproot::KeyPathTweak;
+use crate::verify;
+use bitcoin::absolute::LockTime;
+use bitcoin::address::WitnessVersion;
+use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
+use bitcoin::key::{KeyPair, XOnlyPublicKey};
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::taproot::{self, LeafVersion, TapLeafHash};
+use bitcoin::{ecdsa, Amount, Sequence, Transaction, TxOut};
+use miniscript::bitcoin::PublicKey;
+use miniscript::{hash256, Descriptor, Preimage32, Satisfier};
+use std::collections::HashMap;
+
+/// Signatures of a keystore entry's keys for one input, plus what the input offers for
+/// `older`/`after` and any preimages added with `with_preimage`
+#[derive(Debug, Clone)]
+pub struct KeystoreSatisfier {
+    sigs: HashMap<PublicKey, ecdsa::Signature>,
+    tap_key_sig: Option<taproot::Signature>,
+    tap_leaf_sigs: HashMap<(XOnlyPublicKey, TapLeafHash), taproot::Signature>,
+    /// Keys behind the taproot signatures
+    tap_signers: Vec<PublicKey>,
+    preimages: Vec<Preimage32>,
+    sequence: Sequence,
+    lock_time: LockTime,
+}
+
+impl KeystoreSatisfier {
+    /// Sign input `input_index` of `tx`, which spends `amount` locked by `entry.descriptor`, with
+    /// every key of `entry` (SIGHASH_ALL). The input's sequence and the transaction's lock time
+    /// are taken as they are now, so set them before building the satisfier.
+    pub fn new(entry: &KeyEntry, tx: &Transaction, input_index: usize, amount: Amount) -> Result<Self, Box<dyn std::error::Error>> {
+        let input = tx.input.get(input_index).ok_or_else(|| format!("transaction has no input {}", input_index))?;
+        if entry.descriptor.desc_type().segwit_version() != Some(WitnessVersion::V0) {
+            return Err(format!("the keystore satisfier signs segwit v0 inputs only, not {}", entry.descriptor).into());
+        }
+        let script_code = entry.descriptor.script_code()?;
+        let sighash = SighashCache::new(tx).segwit_signature_hash(input_index, &script_code, amount.to_sat(), EcdsaSighashType::All)?;
+        let message = Message::from_slice(&sighash[..])?;
+        let secp = Secp256k1::new();
+        let sigs = entry.signers().iter()
+            .map(|key| (key.public_key(&secp), ecdsa::Signature::sighash_all(secp.sign_ecdsa(&message, &key.inner))))
+            .collect();
+        Ok(Self { sigs, ..Self::empty(input.sequence, tx.lock_time) })
+    }
+
+    /// Sign input `input_index` of `tx`, which spends `prevouts[input_index]` locked by the `tr`
+    /// descriptor of `entry`, along `plan.path` with the held keys among `plan.signers`
+    /// (SIGHASH_DEFAULT). `prevouts` are the outputs every input spends, in input order.
+    pub fn taproot(entry: &KeyEntry, tx: &Transaction, input_index: usize, prevouts: &[TxOut], plan: &SpendPlan) -> Result<Self, Box<dyn std::error::Error>> {
+        let input = tx.input.get(input_index).ok_or_else(|| format!("transaction has no input {}", input_index))?;
+        let Descriptor::Tr(tr) = &entry.descriptor else { return Err(format!("{} is not a taproot descriptor", entry.descriptor).into()) };
+        if prevouts.len() != tx.input.len() {
+            return Err(format!("{} prevouts for {} inputs", prevouts.len(), tx.input.len()).into());
+        }
+        let secp = Secp256k1::new();
+        let held = |key: &PublicKey| {
+            entry.signers().into_iter().map(|k| KeyPair::from_secret_key(&secp, &k.inner))
+                .find(|k| k.x_only_public_key().0 == key.inner.x_only_public_key().0)
+                .ok_or_else(|| format!("the plan needs key {}, which {} does not hold", key, entry.descriptor))
+        };
+        let mut cache = SighashCache::new(tx);
+        let mut satisfier = Self::empty(input.sequence, tx.lock_time);
+        match &plan.path {
+            SpendPath::TaprootKey => {
+                let keypair = held(tr.internal_key())?;
+                let sighash = cache.taproot_key_spend_signature_hash(input_index, &Prevouts::All(prevouts), TapSighashType::Default)?;
+                let tweak = KeyPathTweak::new(&secp, keypair.x_only_public_key().0, tr.spend_info().merkle_root());
+                satisfier.tap_key_sig = Some(tweak.signer(&secp, &keypair)?.sign_sighash(&secp, sighash, TapSighashType::Default));
+                satisfier.tap_signers.push(*tr.internal_key());
+            }
+            SpendPath::TaprootLeaf { script, .. } => {
+                if !tr.iter_scripts().any(|(_, ms)| ms.encode() == *script) {
+                    return Err(format!("the planned leaf is not in {}", entry.descriptor).into());
+                }
+                let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+                let sighash = cache.taproot_script_spend_signature_hash(input_index, &Prevouts::All(prevouts), leaf_hash, TapSighashType::Default)?;
+                let message = Message::from_slice(sighash.as_ref())?;
+                for signer in &plan.signers {
+                    let keypair = held(signer)?;
+                    let sig = taproot::Signature { sig: secp.sign_schnorr_no_aux_rand(&message, &keypair), hash_ty: TapSighashType::Default };
+                    satisfier.tap_leaf_sigs.insert((keypair.x_only_public_key().0, leaf_hash), sig);
+                    satisfier.tap_signers.push(*signer);
+                }
+            }
+            SpendPath::Script => return Err("the plan is not for a taproot output".into()),
+        }
+        Ok(satisfier)
+    }
+
+    fn empty(sequence: Sequence, lock_time: LockTime) -> Self {
+        Self {
+            sigs: HashMap::new(),
+            tap_key_sig: None,
+            tap_leaf_sigs: HashMap::new(),
+            tap_signers: Vec::new(),
+            preimages: Vec::new(),
+            sequence,
+            lock_time,
+        }
+    }
+
+    /// Offer `preimage` for any `sha256`, `hash256`, `ripemd160` or `hash160` fragment it opens
+    pub fn with_preimage(mut self, preimage: Preimage32) -> Self {
+        self.preimages.push(preimage);
+        self
+    }
+
+    /// Keys this satisfier holds a signature for
+    pub fn signers(&self) -> Vec<PublicKey> {
+        let mut keys: Vec<PublicKey> = self.sigs.keys().chain(&self.tap_signers).copied().collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    fn preimage_for(&self, matches: impl Fn(&Preimage32) -> bool) -> Option<Preimage32> {
+        self.preimages.iter().find(|p| matches(p)).copied()
+    }
+}
+
+impl Satisfier<PublicKey> for KeystoreSatisfier {
+    fn lookup_ecdsa_sig(&self, key: &PublicKey) -> Option<ecdsa::Signature> {
+        self.sigs.get(key).copied()
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<taproot::Signature> {
+        self.tap_key_sig
+    }
+
+    fn lookup_tap_leaf_script_sig(&self, key: &PublicKey, leaf_hash: &TapLeafHash) -> Option<taproot::Signature> {
+        self.tap_leaf_sigs.get(&(key.inner.x_only_public_key().0, *leaf_hash)).copied()
+    }
+
+    fn lookup_sha256(&self, hash: &sha256::Hash) -> Option<Preimage32> {
+        self.preimage_for(|p| sha256::Hash::hash(p) == *hash)
+    }
+
+    fn lookup_hash256(&self, hash: &hash256::Hash) -> Option<Preimage32> {
+        self.preimage_for(|p| hash256::Hash::hash(p) == *hash)
+    }
+
+    fn lookup_ripemd160(&self, hash: &ripemd160::Hash) -> Option<Preimage32> {
+        self.preimage_for(|p| ripemd160::Hash::hash(p) == *hash)
+    }
+
+    fn lookup_hash160(&self, hash: &hash160::Hash) -> Option<Preimage32> {
+        self.preimage_for(|p| hash160::Hash::hash(p) == *hash)
+    }
+
+    fn check_older(&self, sequence: Sequence) -> bool {
+        <Sequence as Satisfier<PublicKey>>::check_older(&self.sequence, sequence)
+    }
+
+    fn check_after(&self, lock_time: LockTime) -> bool {
+        <LockTime as Satisfier<PublicKey>>::check_after(&self.lock_time, lock_time)
+    }
+}
+
+/// Sign input `input_index` of `tx` with the keys of `entry` and set the witness and scriptSig
+/// `entry.descriptor` picks for them, `preimages` and the input's timelock fields. The finished
+/// input is checked with `verify::verify_input` against the `amount` output it spends.
+pub fn satisfy_input(entry: &KeyEntry, tx: &mut Transaction, input_index: usize, amount: Amount, preimages: &[Preimage32]) -> Result<(), Box<dyn std::error::Error>> {
+    let satisfier = preimages.iter().fold(KeystoreSatisfier::new(entry, tx, input_index, amount)?, |s, p| s.with_preimage(*p));
+    entry.descriptor.satisfy(&mut tx.input[input_index], &satisfier)
+        .map_err(|e| format!("input {}: cannot satisfy {} with keys {:?}: {}", input_index, entry.descriptor, satisfier.signers(), e))?;
+    let prevout = TxOut { value: amount.to_sat(), script_pubkey: entry.descriptor.script_pubkey() };
+    verify::verify_input(tx, input_index, &prevout)?;
+    Ok(())
+}
+
+/// Spend input `input_index` of `tx` from the `tr` descriptor of `entry` along `plan`: sign the
+/// planned path and let the descriptor build the witness, with the leaf script and control block
+/// of a script path. The input's sequence and the lock time must already be what the plan asks
+/// for; set them before signing any input. The input is checked with `verify::verify_input_with`.
+pub fn satisfy_taproot_input(entry: &KeyEntry, tx: &mut Transaction, input_index: usize, prevouts: &[TxOut], plan: &SpendPlan, preimages: &[Preimage32]) -> Result<(), Box<dyn std::error::Error>> {
+    let sequence = tx.input.get(input_index).ok_or_else(|| format!("transaction has no input {}", input_index))?.sequence;
+    if plan.sequence.is_relative_lock_time() && sequence != plan.sequence {
+        return Err(format!("input {} has sequence {}, the plan needs {}", input_index, sequence, plan.sequence).into());
+    }
+    if plan.lock_time != LockTime::ZERO && !tx.lock_time.is_implied_by(plan.lock_time) {
+        return Err(format!("transaction lock time {} does not reach {}", tx.lock_time, plan.lock_time).into());
+    }
+    let satisfier = preimages.iter().fold(KeystoreSatisfier::taproot(entry, tx, input_index, prevouts, plan)?, |s, p| s.with_preimage(*p));
+    entry.descriptor.satisfy(&mut tx.input[input_index], &satisfier)
+        .map_err(|e| format!("input {}: cannot satisfy {} along {}: {}", input_index, entry.descriptor, plan.path, e))?;
+    verify::verify_input_with(tx, input_index, prevouts)?;
+    Ok(())
+}
@@ -0,0 +1,256 @@
+//! Block scanner: detects deposits to watched scripts and spends of tracked outputs.
+//!
+//! Blocks are asked for with their inputs' prevouts (`getblock <hash> 3` on Core), so spends of
+//! watched scripts are classified without a transaction lookup per input. Nodes older than Core
+//! 23 either reject verbosity 3 or return inputs without `prevout`, and Esplora never supplies
+//! them; the scanner then asks for plain blocks and looks prevouts up through
+//! `ChainBackend::transaction` only for inputs it cannot classify from its own tracked set. Every
+//! block scanned with `scan_block` is reported to `telemetry`.
+
+use crate::backend::ChainBackend;
+use crate::cancel::{guarded, interruption, CancellationToken, Interrupted};
+use crate::state_file;
+use crate::telemetry;
+use bitcoin::{Amount, BlockHash, OutPoint, ScriptBuf, Txid};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackedOutput {
+    pub value_sats: u64,
+    pub script_pubkey: ScriptBuf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanEvent {
+    Deposit { outpoint: OutPoint, value_sats: u64, script_pubkey: ScriptBuf, height: u64 },
+    Spend { outpoint: OutPoint, value_sats: u64, spending_txid: Txid, input_index: usize, height: u64 },
+}
+
+/// Result of a scan that may stop early; resume from `last_scanned + 1`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanProgress {
+    pub events: Vec<ScanEvent>,
+    /// Highest height fully scanned, `None` if none was
+    pub last_scanned: Option<u64>,
+    pub interrupted: Option<Interrupted>,
+}
+
+/// Persistent scanner state, so a restarted service resumes after `last_scanned`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScannerCheckpoint {
+    pub last_scanned: Option<u64>,
+    /// Hex scriptPubKeys
+    pub watched_scripts: Vec<String>,
+    pub tracked: Vec<CheckpointOutput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointOutput {
+    /// `txid:vout`
+    pub outpoint: String,
+    pub value_sats: u64,
+    pub script_pubkey: String,
+}
+
+impl ScannerCheckpoint {
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        state_file::save_json(path, self)
+    }
+
+    /// `None` if nothing was saved yet
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        state_file::load_json(path)
+    }
+}
+
+/// Report a scanned block to `telemetry`
+fn record_progress(block: &Value, events: &[ScanEvent], elapsed: Duration) {
+    let height = block["height"].as_u64().unwrap_or_default();
+    let deposits = events.iter().filter(|e| matches!(e, ScanEvent::Deposit { .. })).count();
+    let attributes = [
+        ("block.height", height.to_string()),
+        ("block.hash", block["hash"].as_str().unwrap_or_default().to_string()),
+        ("events", events.len().to_string()),
+    ];
+    telemetry::record_span("scan_block", elapsed, &attributes, None);
+    telemetry::observe("wrapyield.scanner.block_duration", elapsed, &[]);
+    telemetry::add("wrapyield.scanner.events", deposits as u64, &[("kind", "deposit".to_string())]);
+    telemetry::add("wrapyield.scanner.events", (events.len() - deposits) as u64, &[("kind", "spend".to_string())]);
+    telemetry::set_gauge("wrapyield.scanner.height", height as f64, &[]);
+}
+
+#[derive(Default)]
+pub struct BlockScanner {
+    pub watched_scripts: HashSet<ScriptBuf>,
+    pub tracked: HashMap<OutPoint, TrackedOutput>,
+    /// `None` until the first block is fetched; then whether the node returns prevouts
+    pub prevout_support: Option<bool>,
+    /// Number of transaction lookups done by the fallback path (0 on modern nodes)
+    pub fallback_lookups: u64,
+}
+
+impl BlockScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn checkpoint(&self, last_scanned: Option<u64>) -> ScannerCheckpoint {
+        let mut watched_scripts: Vec<String> = self.watched_scripts.iter().map(|s| hex::encode(s.as_bytes())).collect();
+        watched_scripts.sort();
+        let mut tracked: Vec<CheckpointOutput> = self.tracked.iter().map(|(outpoint, output)| CheckpointOutput {
+            outpoint: outpoint.to_string(),
+            value_sats: output.value_sats,
+            script_pubkey: hex::encode(output.script_pubkey.as_bytes()),
+        }).collect();
+        tracked.sort_by(|a, b| a.outpoint.cmp(&b.outpoint));
+        ScannerCheckpoint { last_scanned, watched_scripts, tracked }
+    }
+
+    pub fn from_checkpoint(checkpoint: &ScannerCheckpoint) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut scanner = Self::new();
+        for script in &checkpoint.watched_scripts {
+            scanner.watch_script(ScriptBuf::from_bytes(hex::decode(script)?));
+        }
+        for output in &checkpoint.tracked {
+            let script_pubkey = ScriptBuf::from_bytes(hex::decode(&output.script_pubkey)?);
+            scanner.track_outpoint(OutPoint::from_str(&output.outpoint)?, TrackedOutput { value_sats: output.value_sats, script_pubkey });
+        }
+        Ok(scanner)
+    }
+
+    pub fn watch_script(&mut self, script_pubkey: ScriptBuf) {
+        self.watched_scripts.insert(script_pubkey);
+    }
+
+    pub fn track_outpoint(&mut self, outpoint: OutPoint, output: TrackedOutput) {
+        self.tracked.insert(outpoint, output);
+    }
+
+    /// Scan heights `from..=to` in order. The block hashes are fetched up front, in one batch
+    /// on Core.
+    pub async fn scan_range(&mut self, backend: &impl ChainBackend, from: u64, to: u64) -> Result<Vec<ScanEvent>, Box<dyn std::error::Error>> {
+        let mut events = Vec::new();
+        for hash in backend.block_hashes(from..=to).await? {
+            events.extend(self.scan_hash(backend, &hash).await?);
+        }
+        Ok(events)
+    }
+
+    /// Like `scan_range`, but each block must finish within `per_block` and cancellation is
+    /// honoured between and during blocks. Events of fully scanned blocks are kept.
+    pub async fn scan_range_cancellable(&mut self, backend: &impl ChainBackend, from: u64, to: u64, token: &CancellationToken, per_block: Duration) -> Result<ScanProgress, Box<dyn std::error::Error>> {
+        let mut progress = ScanProgress { events: Vec::new(), last_scanned: None, interrupted: None };
+        for height in from..=to {
+            let operation = format!("scan of block {}", height);
+            let result = guarded(token, &operation, per_block, async {
+                let hash = backend.block_hash(height).await?;
+                self.scan_hash(backend, &hash).await
+            }).await;
+            match result {
+                Ok(events) => {
+                    progress.events.extend(events);
+                    progress.last_scanned = Some(height);
+                }
+                Err(e) => match interruption(e.as_ref()) {
+                    Some(interrupted) => {
+                        progress.interrupted = Some(interrupted.clone());
+                        break;
+                    }
+                    None => return Err(e),
+                },
+            }
+        }
+        Ok(progress)
+    }
+
+    pub async fn scan_block(&mut self, backend: &impl ChainBackend, block_hash: &str) -> Result<Vec<ScanEvent>, Box<dyn std::error::Error>> {
+        self.scan_hash(backend, &BlockHash::from_str(block_hash)?).await
+    }
+
+    async fn scan_hash(&mut self, backend: &impl ChainBackend, block_hash: &BlockHash) -> Result<Vec<ScanEvent>, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let block = self.fetch_block(backend, block_hash).await?;
+        let events = self.scan_block_json(backend, &block).await?;
+        record_progress(&block, &events, started.elapsed());
+        Ok(events)
+    }
+
+    pub(crate) async fn fetch_block(&mut self, backend: &impl ChainBackend, block_hash: &BlockHash) -> Result<Value, Box<dyn std::error::Error>> {
+        if self.prevout_support != Some(false) {
+            match backend.block(block_hash, true).await {
+                Ok(block) => return Ok(block),
+                Err(e) if self.prevout_support.is_none() => {
+                    info!("blocks with prevouts unsupported ({}), falling back to plain blocks", e);
+                    self.prevout_support = Some(false);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        backend.block(block_hash, false).await
+    }
+
+    /// Classify every transaction in a block as `ChainBackend::block` returns it (the layout of
+    /// a verbosity 2 or 3 `getblock` result)
+    pub async fn scan_block_json(&mut self, backend: &impl ChainBackend, block: &Value) -> Result<Vec<ScanEvent>, Box<dyn std::error::Error>> {
+        let height = block["height"].as_u64().unwrap_or_default();
+        let mut events = Vec::new();
+        for tx in block["tx"].as_array().ok_or("getblock result has no tx array")? {
+            let txid = Txid::from_str(tx["txid"].as_str().ok_or("tx without txid")?)?;
+            for (input_index, vin) in tx["vin"].as_array().into_iter().flatten().enumerate() {
+                if vin.get("coinbase").is_some() {
+                    continue;
+                }
+                let prev_txid = vin["txid"].as_str().ok_or_else(|| format!("input {} of {} has no txid", input_index, txid))?;
+                let vout = vin["vout"].as_u64().ok_or_else(|| format!("input {} of {} has no vout", input_index, txid))?;
+                let outpoint = OutPoint::new(Txid::from_str(prev_txid)?, u32::try_from(vout)?);
+                let spent = match self.tracked.remove(&outpoint) {
+                    Some(output) => Some(output),
+                    None => self.prevout_for_input(backend, vin, outpoint).await?
+                        .filter(|output| self.watched_scripts.contains(&output.script_pubkey)),
+                };
+                if let Some(output) = spent {
+                    events.push(ScanEvent::Spend { outpoint, value_sats: output.value_sats, spending_txid: txid, input_index, height });
+                }
+            }
+            for (vout, out) in tx["vout"].as_array().into_iter().flatten().enumerate() {
+                let script_pubkey = ScriptBuf::from_bytes(hex::decode(out["scriptPubKey"]["hex"].as_str().unwrap_or_default())?);
+                if self.watched_scripts.contains(&script_pubkey) {
+                    let outpoint = OutPoint::new(txid, vout as u32);
+                    let value_sats = Amount::from_btc(out["value"].as_f64().unwrap_or_default())?.to_sat();
+                    self.tracked.insert(outpoint, TrackedOutput { value_sats, script_pubkey: script_pubkey.clone() });
+                    events.push(ScanEvent::Deposit { outpoint, value_sats, script_pubkey, height });
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    /// Prevout of an input we don't track: from the block itself when the node supplies it,
+    /// otherwise (old nodes and Esplora, and only while scripts are being watched) by fetching
+    /// the transaction that created it.
+    async fn prevout_for_input(&mut self, backend: &impl ChainBackend, vin: &Value, outpoint: OutPoint) -> Result<Option<TrackedOutput>, Box<dyn std::error::Error>> {
+        if let Some(prevout) = vin.get("prevout") {
+            self.prevout_support = Some(true);
+            return Ok(Some(TrackedOutput {
+                value_sats: Amount::from_btc(prevout["value"].as_f64().unwrap_or_default())?.to_sat(),
+                script_pubkey: ScriptBuf::from_bytes(hex::decode(prevout["scriptPubKey"]["hex"].as_str().unwrap_or_default())?),
+            }));
+        }
+        if self.prevout_support.is_none() {
+            self.prevout_support = Some(false);
+        }
+        if self.watched_scripts.is_empty() {
+            return Ok(None);
+        }
+        self.fallback_lookups += 1;
+        let prev_tx = backend.transaction(&outpoint.txid).await?;
+        let out = prev_tx.output.get(outpoint.vout as usize).ok_or_else(|| format!("{} has no output {}", outpoint.txid, outpoint.vout))?;
+        Ok(Some(TrackedOutput { value_sats: out.value, script_pubkey: out.script_pubkey.clone() }))
+    }
+}
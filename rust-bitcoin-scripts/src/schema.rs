@@ -0,0 +1,166 @@
+//! Versioned JSON documents for every machine-readable output (vault descriptors, spend receipts, audit reports)
+//!
+//! Every document is wrapped as `{"schema_version": N, "kind": "...", ...fields}`. Amounts are integer
+//! sats and BTC values are strings, so output never depends on float formatting or locale.
+//! Readers ignore unknown fields, so adding an optional field does not require a version bump;
+//! removing or changing the meaning of a field does.
+
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::fmt;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A document type with a stable `kind` tag and a published JSON Schema
+pub trait SchemaKind {
+    const KIND: &'static str;
+    fn json_schema() -> Value;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultDescriptorDoc {
+    pub descriptor: String,
+    pub address: String,
+    pub network: String,
+    pub script_pubkey_hex: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_satisfaction_weight: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpendReceiptDoc {
+    pub txid: String,
+    pub fee_sats: u64,
+    pub vsize: u64,
+    pub path_used: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditReportDoc {
+    pub descriptor: String,
+    pub utxo_count: u64,
+    pub total_sats: u64,
+    pub total_btc: String,
+    #[serde(default)]
+    pub findings: Vec<String>,
+}
+
+impl SchemaKind for VaultDescriptorDoc {
+    const KIND: &'static str = "vault_descriptor";
+    fn json_schema() -> Value {
+        object_schema(Self::KIND, &[
+            ("descriptor", "string"),
+            ("address", "string"),
+            ("network", "string"),
+            ("script_pubkey_hex", "string"),
+        ], &[("max_satisfaction_weight", "integer")])
+    }
+}
+
+impl SchemaKind for SpendReceiptDoc {
+    const KIND: &'static str = "spend_receipt";
+    fn json_schema() -> Value {
+        object_schema(Self::KIND, &[
+            ("txid", "string"),
+            ("fee_sats", "integer"),
+            ("vsize", "integer"),
+            ("path_used", "string"),
+        ], &[("block_hash", "string")])
+    }
+}
+
+impl SchemaKind for AuditReportDoc {
+    const KIND: &'static str = "audit_report";
+    fn json_schema() -> Value {
+        object_schema(Self::KIND, &[
+            ("descriptor", "string"),
+            ("utxo_count", "integer"),
+            ("total_sats", "integer"),
+            ("total_btc", "string"),
+        ], &[("findings", "array")])
+    }
+}
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Json(serde_json::Error),
+    WrongKind { expected: &'static str, found: String },
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Json(e) => write!(f, "invalid document: {}", e),
+            SchemaError::WrongKind { expected, found } => write!(f, "expected kind {}, found {}", expected, found),
+            SchemaError::UnsupportedVersion(v) => write!(f, "schema_version {} is newer than supported {}", v, SCHEMA_VERSION),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<serde_json::Error> for SchemaError {
+    fn from(e: serde_json::Error) -> Self {
+        SchemaError::Json(e)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    schema_version: u32,
+    kind: String,
+    #[serde(flatten)]
+    body: T,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    schema_version: u32,
+    kind: String,
+}
+
+/// Wrap a document in its versioned envelope
+pub fn to_value<T: Serialize + SchemaKind>(doc: &T) -> Result<Value, SchemaError> {
+    Ok(serde_json::to_value(Envelope { schema_version: SCHEMA_VERSION, kind: T::KIND.to_string(), body: doc })?)
+}
+
+pub fn to_json_pretty<T: Serialize + SchemaKind>(doc: &T) -> Result<String, SchemaError> {
+    Ok(serde_json::to_string_pretty(&to_value(doc)?)?)
+}
+
+/// Parse a versioned document, rejecting other kinds and newer schema versions
+pub fn from_json<T: DeserializeOwned + SchemaKind>(s: &str) -> Result<T, SchemaError> {
+    // Kind and version first, so another document is reported as such rather than as a
+    // missing field
+    let header: Header = serde_json::from_str(s)?;
+    if header.kind != T::KIND {
+        return Err(SchemaError::WrongKind { expected: T::KIND, found: header.kind });
+    }
+    if header.schema_version > SCHEMA_VERSION {
+        return Err(SchemaError::UnsupportedVersion(header.schema_version));
+    }
+    let envelope: Envelope<T> = serde_json::from_str(s)?;
+    Ok(envelope.body)
+}
+
+fn object_schema(kind: &str, required: &[(&str, &str)], optional: &[(&str, &str)]) -> Value {
+    let mut properties = serde_json::Map::new();
+    properties.insert("schema_version".to_string(), json!({"type": "integer", "minimum": 1}));
+    properties.insert("kind".to_string(), json!({"const": kind}));
+    for (name, ty) in required.iter().chain(optional.iter()) {
+        properties.insert(name.to_string(), json!({"type": ty}));
+    }
+    let mut required_names = vec!["schema_version".to_string(), "kind".to_string()];
+    required_names.extend(required.iter().map(|(name, _)| name.to_string()));
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "$id": format!("wrapyield/{}/v{}", kind, SCHEMA_VERSION),
+        "type": "object",
+        "required": required_names,
+        "properties": properties,
+    })
+}
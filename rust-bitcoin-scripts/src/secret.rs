@@ -0,0 +1,88 @@
+//! Wrappers for secret key material: zeroized on drop, redacted in `Debug`, compared in constant time.
+//!
+//! `secp256k1::SecretKey` and `PrivateKey` are `Copy`, so every temporary made from
+//! `SigningKey::expose` is the caller's to keep short-lived; the long-lived copy lives only here.
+
+use miniscript::bitcoin::{Network, PrivateKey, PublicKey, secp256k1};
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use std::fmt;
+use zeroize::Zeroizing;
+
+pub struct SigningKey {
+    bytes: Secret<[u8; 32]>,
+    pub network: Network,
+    pub public_key: PublicKey,
+}
+
+impl SigningKey {
+    pub fn new(secret: secp256k1::SecretKey, network: Network) -> Self {
+        let secp = secp256k1::Secp256k1::signing_only();
+        let public_key = PublicKey::new(secp256k1::PublicKey::from_secret_key(&secp, &secret));
+        Self { bytes: Secret::new(secret.secret_bytes()), network, public_key }
+    }
+
+    pub fn random(network: Network) -> Self {
+        let mut data = Zeroizing::new([0u8; 32]);
+        loop {
+            rand::thread_rng().fill_bytes(data.as_mut());
+            if let Ok(secret) = secp256k1::SecretKey::from_slice(data.as_ref()) {
+                return Self::new(secret, network);
+            }
+        }
+    }
+
+    /// WIF only tells mainnet from the test networks, so keys of the other test networks come
+    /// back as `Network::Testnet`; `from_wif_for` restores the exact network
+    pub fn from_wif(wif: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let key = PrivateKey::from_wif(wif)?;
+        Ok(Self::new(key.inner, key.network))
+    }
+
+    /// `from_wif` for a key known to be for `network`
+    pub fn from_wif_for(wif: &str, network: Network) -> Result<Self, Box<dyn std::error::Error>> {
+        let key = PrivateKey::from_wif(wif)?;
+        if !same_wif_network(key.network, network) {
+            return Err(format!("key {} is for {}, expected {}", key.public_key(&secp256k1::Secp256k1::signing_only()), key.network, network).into());
+        }
+        Ok(Self::new(key.inner, network))
+    }
+
+    /// A temporary `PrivateKey` for signing; do not store it
+    pub fn expose(&self) -> PrivateKey {
+        let secret = secp256k1::SecretKey::from_slice(self.bytes.expose_secret()).expect("validated on construction");
+        PrivateKey::new(secret, self.network)
+    }
+
+    pub fn to_wif(&self) -> Zeroizing<String> {
+        Zeroizing::new(self.expose().to_wif())
+    }
+}
+
+impl Clone for SigningKey {
+    fn clone(&self) -> Self {
+        Self { bytes: Secret::new(*self.bytes.expose_secret()), network: self.network, public_key: self.public_key }
+    }
+}
+
+// Equal across networks that share a WIF encoding, so a key survives `to_wif` and `from_wif`
+impl PartialEq for SigningKey {
+    fn eq(&self, other: &Self) -> bool {
+        same_wif_network(self.network, other.network) && ct_eq(self.bytes.expose_secret(), other.bytes.expose_secret())
+    }
+}
+
+fn same_wif_network(a: Network, b: Network) -> bool {
+    (a == Network::Bitcoin) == (b == Network::Bitcoin)
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SigningKey({}, [REDACTED])", self.public_key)
+    }
+}
+
+/// Constant-time equality for secrets, MACs and tokens
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
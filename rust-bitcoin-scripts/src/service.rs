@@ -0,0 +1,224 @@
+//! Long-running service: the watcher, broadcaster and coordinator actors wired to one shutdown
+//! token and a state directory.
+//!
+//! On SIGINT/SIGTERM the token is cancelled, so every actor stops taking requests, saves its state
+//! (scanner checkpoint, waiting transactions, collecting signing rounds) under `state_dir` and
+//! exits; the service then waits for them within a grace period and unloads its wallets.
+//! With `telemetry` configured, an exporter task sends traces and metrics to the OTLP collector
+//! every `telemetry_interval` and once more on shutdown. `spawn_consolidator` adds a
+//! `consolidation::Consolidator` job on the same actors.
+
+use crate::actors::{BroadcasterHandle, BroadcasterOptions, CoordinatorHandle, CoordinatorOptions, WatcherHandle, WatcherOptions};
+use crate::cancel::{CancellationToken, OperationTimeouts};
+use crate::consolidation::Consolidator;
+use crate::read_only;
+use crate::reservation::UtxoReservations;
+use crate::scanner::{BlockScanner, ScanEvent};
+use crate::telemetry::{self, OtlpConfig, OtlpExporter};
+use crate::test_setup::BitcoinRPC;
+use crate::webhooks::LifecycleEvent;
+use log::{info, warn};
+use serde_json::json;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+pub const SCANNER_CHECKPOINT_FILE: &str = "scanner.json";
+pub const HOLDING_QUEUE_FILE: &str = "holding_queue.json";
+pub const SIGNING_ROUNDS_FILE: &str = "signing_rounds.json";
+
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    pub state_dir: PathBuf,
+    /// Wallets unloaded on shutdown
+    pub wallets: Vec<String>,
+    /// How often new blocks are scanned and held transactions re-checked
+    pub poll_interval: Duration,
+    /// How often signing round timeouts are evaluated
+    pub round_check_interval: Duration,
+    /// How long each actor gets to save its state after shutdown is requested
+    pub grace_period: Duration,
+    pub timeouts: OperationTimeouts,
+    /// Default time-to-live of UTXO reservations
+    pub reservation_ttl: Duration,
+    /// Switch on process-wide read-only mode at start: broadcasts and signing are simulated
+    pub read_only: bool,
+    /// Record telemetry and export it to this collector
+    pub telemetry: Option<OtlpConfig>,
+    pub telemetry_interval: Duration,
+}
+
+impl ServiceConfig {
+    pub fn new(state_dir: PathBuf) -> Self {
+        Self {
+            state_dir,
+            wallets: Vec::new(),
+            poll_interval: Duration::from_secs(10),
+            round_check_interval: Duration::from_secs(5),
+            grace_period: Duration::from_secs(10),
+            timeouts: OperationTimeouts::default(),
+            reservation_ttl: Duration::from_secs(600),
+            read_only: false,
+            telemetry: None,
+            telemetry_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Receivers for everything the actors publish
+pub struct ServiceEvents {
+    pub scans: mpsc::UnboundedReceiver<ScanEvent>,
+    pub lifecycle: mpsc::UnboundedReceiver<LifecycleEvent>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShutdownReport {
+    /// Actors that saved their state and exited
+    pub stopped: Vec<String>,
+    /// Actors still running when the grace period ran out (or that panicked)
+    pub timed_out: Vec<String>,
+    pub wallets_closed: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+pub struct Service {
+    pub watcher: WatcherHandle,
+    pub broadcaster: BroadcasterHandle,
+    pub coordinator: CoordinatorHandle,
+    pub reservations: UtxoReservations,
+    shutdown: CancellationToken,
+    tasks: Vec<(&'static str, JoinHandle<()>)>,
+    rpc: BitcoinRPC,
+    config: ServiceConfig,
+}
+
+impl Service {
+    /// Spawn the actors, resuming whatever state a previous run saved in `config.state_dir`
+    pub fn start(rpc: BitcoinRPC, scanner: BlockScanner, config: ServiceConfig) -> Result<(Self, ServiceEvents), Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&config.state_dir)?;
+        if config.read_only {
+            read_only::enable();
+            info!("read-only mode: broadcasts and signing are simulated");
+        }
+        let shutdown = CancellationToken::new();
+        let (scans_tx, scans) = mpsc::unbounded_channel();
+        let (lifecycle_tx, lifecycle) = mpsc::unbounded_channel();
+        let reservations = UtxoReservations::new(config.reservation_ttl);
+
+        let (watcher, watcher_task) = WatcherHandle::spawn_with(rpc.clone(), scanner, scans_tx, WatcherOptions {
+            shutdown: shutdown.clone(),
+            timeouts: config.timeouts,
+            checkpoint_path: Some(config.state_dir.join(SCANNER_CHECKPOINT_FILE)),
+        })?;
+        let (broadcaster, broadcaster_task) = BroadcasterHandle::spawn_with(rpc.clone(), BroadcasterOptions {
+            interval: config.poll_interval,
+            shutdown: shutdown.clone(),
+            timeouts: config.timeouts,
+            state_path: Some(config.state_dir.join(HOLDING_QUEUE_FILE)),
+        })?;
+        let (coordinator, coordinator_task) = CoordinatorHandle::spawn_with(reservations.clone(), lifecycle_tx, CoordinatorOptions {
+            interval: config.round_check_interval,
+            shutdown: shutdown.clone(),
+            state_path: Some(config.state_dir.join(SIGNING_ROUNDS_FILE)),
+        })?;
+
+        let mut tasks = vec![("watcher", watcher_task), ("broadcaster", broadcaster_task), ("coordinator", coordinator_task)];
+        if let Some(otlp) = config.telemetry.clone() {
+            telemetry::enable();
+            info!("exporting telemetry to {}", otlp.endpoint);
+            let exporter = OtlpExporter::new(otlp);
+            let (interval, token) = (config.telemetry_interval, shutdown.clone());
+            tasks.push(("telemetry", tokio::spawn(async move { exporter.run(interval, &token).await })));
+        }
+
+        let service = Self {
+            watcher,
+            broadcaster,
+            coordinator,
+            reservations,
+            shutdown,
+            tasks,
+            rpc,
+            config,
+        };
+        Ok((service, ServiceEvents { scans, lifecycle }))
+    }
+
+    /// Run `consolidator` every `interval` as one more task of the service, stopped with the rest
+    pub fn spawn_consolidator(&mut self, consolidator: Consolidator, interval: Duration) {
+        let task = consolidator.run(self.rpc.clone(), self.reservations.clone(), self.coordinator.clone(), self.broadcaster.clone(), interval, self.shutdown.clone());
+        self.tasks.push(("consolidation", tokio::spawn(task)));
+    }
+
+    /// Cancelling this token has the same effect as a termination signal
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Scan new blocks every poll interval until a signal arrives or the token is cancelled,
+    /// then shut down
+    pub async fn run_until_signal(self) -> ShutdownReport {
+        let signal = shutdown_signal();
+        tokio::pin!(signal);
+        let mut ticker = tokio::time::interval(self.config.poll_interval);
+        loop {
+            tokio::select! {
+                received = &mut signal => {
+                    match received {
+                        Ok(name) => info!("received {}, shutting down", name),
+                        Err(e) => warn!("signal handling failed ({}), shutting down", e),
+                    }
+                    break;
+                }
+                _ = self.shutdown.cancelled() => break,
+                _ = ticker.tick() => {
+                    if let Err(e) = self.watcher.catch_up().await {
+                        warn!("block scan failed: {}", e);
+                    }
+                }
+            }
+        }
+        self.shutdown().await
+    }
+
+    /// Stop the actors, wait for them to save their state and unload the wallets
+    pub async fn shutdown(self) -> ShutdownReport {
+        let mut report = ShutdownReport::default();
+        self.shutdown.cancel();
+        for (name, task) in self.tasks {
+            match tokio::time::timeout(self.config.grace_period, task).await {
+                Ok(Ok(())) => report.stopped.push(name.to_string()),
+                Ok(Err(e)) => {
+                    report.errors.push(format!("{}: {}", name, e));
+                    report.timed_out.push(name.to_string());
+                }
+                Err(_) => report.timed_out.push(name.to_string()),
+            }
+        }
+        for wallet in &self.config.wallets {
+            match self.rpc.call_rpc("unloadwallet", json!([wallet])).await {
+                Ok(_) => report.wallets_closed.push(wallet.clone()),
+                Err(e) => report.errors.push(format!("unloadwallet {}: {}", wallet, e)),
+            }
+        }
+        report
+    }
+}
+
+/// Resolves with the name of the first SIGINT or SIGTERM received
+pub async fn shutdown_signal() -> std::io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|_| "SIGINT"),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.map(|_| "SIGINT")
+    }
+}
@@ -0,0 +1,297 @@
+//! Decomposed signature-hash preimages for segwit v0 (BIP143) and taproot (BIP341) inputs.
+//!
+//! `SighashCache` only hands out the final digest. Here every field of the message is computed
+//! and kept, so a mismatch against another implementation can be pinned to a single field, and
+//! signers that want the preimage rather than the digest can be given `to_bytes()`. `digest()`
+//! hashes the same bytes and equals what `SighashCache` produces. Annexes are not supported.
+
+use bitcoin::consensus::encode::serialize;
+use bitcoin::hashes::{sha256, sha256d, Hash};
+use bitcoin::sighash::{SegwitV0Sighash, TapSighash, TapSighashType};
+use bitcoin::taproot::TapLeafHash;
+use bitcoin::{Amount, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxOut};
+use std::fmt;
+
+const ANYONECANPAY: u32 = 0x80;
+const SIGHASH_NONE: u32 = 0x02;
+const SIGHASH_SINGLE: u32 = 0x03;
+
+/// Which message an input signs
+#[derive(Debug, Clone, PartialEq)]
+pub enum SighashPath {
+    /// `script_code` is the witness script for P2WSH and the P2PKH script for P2WPKH
+    SegwitV0 { script_code: ScriptBuf },
+    TaprootKey,
+    /// `code_separator_pos` is the opcode position of the last executed OP_CODESEPARATOR
+    TaprootScript { leaf_hash: TapLeafHash, code_separator_pos: Option<u32> },
+}
+
+/// BIP143 message fields, in serialization order
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegwitV0Preimage {
+    pub version: i32,
+    /// All zero with ANYONECANPAY
+    pub hash_prevouts: sha256d::Hash,
+    /// All zero with ANYONECANPAY, NONE or SINGLE
+    pub hash_sequence: sha256d::Hash,
+    pub outpoint: OutPoint,
+    pub script_code: ScriptBuf,
+    pub amount: Amount,
+    pub sequence: Sequence,
+    /// All outputs, only the matching output for SINGLE, zero for NONE or SINGLE without a match
+    pub hash_outputs: sha256d::Hash,
+    pub lock_time: u32,
+    pub sighash_type: u32,
+}
+
+/// The spent input as it appears in a BIP341 message
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaprootInputData {
+    Index(u32),
+    /// With ANYONECANPAY the input is committed to directly instead of by index
+    AnyoneCanPay { outpoint: OutPoint, amount: Amount, script_pubkey: ScriptBuf, sequence: Sequence },
+}
+
+/// BIP341 message fields, in serialization order
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaprootPreimage {
+    pub epoch: u8,
+    pub hash_type: TapSighashType,
+    pub version: i32,
+    pub lock_time: u32,
+    /// These four are absent with ANYONECANPAY
+    pub sha_prevouts: Option<sha256::Hash>,
+    pub sha_amounts: Option<sha256::Hash>,
+    pub sha_scriptpubkeys: Option<sha256::Hash>,
+    pub sha_sequences: Option<sha256::Hash>,
+    /// Absent with NONE or SINGLE
+    pub sha_outputs: Option<sha256::Hash>,
+    /// `2 * ext_flag + annex_present`
+    pub spend_type: u8,
+    pub input: TaprootInputData,
+    /// Present with SINGLE
+    pub sha_single_output: Option<sha256::Hash>,
+    /// The BIP342 extension, present for script-path spends
+    pub leaf_hash: Option<TapLeafHash>,
+    pub key_version: Option<u8>,
+    pub code_separator_pos: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SighashPreimage {
+    SegwitV0(SegwitV0Preimage),
+    Taproot(TaprootPreimage),
+}
+
+/// Decompose the message input `input_index` of `tx` signs with `sighash_type`. `prevouts` are
+/// the outputs spent by every input of `tx`, in input order.
+pub fn preimage(tx: &Transaction, input_index: usize, prevouts: &[TxOut], path: &SighashPath, sighash_type: u32) -> Result<SighashPreimage, Box<dyn std::error::Error>> {
+    let input = tx.input.get(input_index).ok_or_else(|| format!("input {} out of range ({} inputs)", input_index, tx.input.len()))?;
+    if prevouts.len() != tx.input.len() {
+        return Err(format!("{} prevouts for {} inputs", prevouts.len(), tx.input.len()).into());
+    }
+    let prevout = &prevouts[input_index];
+    let anyone_can_pay = sighash_type & ANYONECANPAY != 0;
+    let base = sighash_type & 0x1f;
+
+    match path {
+        SighashPath::SegwitV0 { script_code } => {
+            let hash_prevouts = if anyone_can_pay {
+                sha256d::Hash::all_zeros()
+            } else {
+                sha256d::Hash::hash(&tx.input.iter().flat_map(|i| serialize(&i.previous_output)).collect::<Vec<u8>>())
+            };
+            let hash_sequence = if anyone_can_pay || base == SIGHASH_NONE || base == SIGHASH_SINGLE {
+                sha256d::Hash::all_zeros()
+            } else {
+                sha256d::Hash::hash(&tx.input.iter().flat_map(|i| serialize(&i.sequence)).collect::<Vec<u8>>())
+            };
+            let hash_outputs = match base {
+                SIGHASH_NONE => sha256d::Hash::all_zeros(),
+                SIGHASH_SINGLE => tx.output.get(input_index).map_or_else(sha256d::Hash::all_zeros, |o| sha256d::Hash::hash(&serialize(o))),
+                _ => sha256d::Hash::hash(&tx.output.iter().flat_map(serialize).collect::<Vec<u8>>()),
+            };
+            Ok(SighashPreimage::SegwitV0(SegwitV0Preimage {
+                version: tx.version,
+                hash_prevouts,
+                hash_sequence,
+                outpoint: input.previous_output,
+                script_code: script_code.clone(),
+                amount: Amount::from_sat(prevout.value),
+                sequence: input.sequence,
+                hash_outputs,
+                lock_time: tx.lock_time.to_consensus_u32(),
+                sighash_type,
+            }))
+        }
+        SighashPath::TaprootKey | SighashPath::TaprootScript { .. } => {
+            let hash_type = u8::try_from(sighash_type).ok()
+                .and_then(|t| TapSighashType::from_consensus_u8(t).ok())
+                .ok_or_else(|| format!("invalid taproot sighash type {:#x}", sighash_type))?;
+            let output_type = sighash_type & 0x03;
+            let sha = |bytes: Vec<u8>| Some(sha256::Hash::hash(&bytes));
+            let (sha_prevouts, sha_amounts, sha_scriptpubkeys, sha_sequences) = if anyone_can_pay {
+                (None, None, None, None)
+            } else {
+                (
+                    sha(tx.input.iter().flat_map(|i| serialize(&i.previous_output)).collect()),
+                    sha(prevouts.iter().flat_map(|o| o.value.to_le_bytes()).collect()),
+                    sha(prevouts.iter().flat_map(|o| serialize(&o.script_pubkey)).collect()),
+                    sha(tx.input.iter().flat_map(|i| serialize(&i.sequence)).collect()),
+                )
+            };
+            let sha_outputs = match output_type {
+                SIGHASH_NONE | SIGHASH_SINGLE => None,
+                _ => sha(tx.output.iter().flat_map(serialize).collect()),
+            };
+            let sha_single_output = if output_type == SIGHASH_SINGLE {
+                let output = tx.output.get(input_index).ok_or("SIGHASH_SINGLE without a matching output")?;
+                sha(serialize(output))
+            } else {
+                None
+            };
+            let input_data = if anyone_can_pay {
+                TaprootInputData::AnyoneCanPay {
+                    outpoint: input.previous_output,
+                    amount: Amount::from_sat(prevout.value),
+                    script_pubkey: prevout.script_pubkey.clone(),
+                    sequence: input.sequence,
+                }
+            } else {
+                TaprootInputData::Index(input_index as u32)
+            };
+            let (leaf_hash, key_version, code_separator_pos) = match path {
+                SighashPath::TaprootScript { leaf_hash, code_separator_pos } => (Some(*leaf_hash), Some(0), Some(code_separator_pos.unwrap_or(u32::MAX))),
+                _ => (None, None, None),
+            };
+            Ok(SighashPreimage::Taproot(TaprootPreimage {
+                epoch: 0,
+                hash_type,
+                version: tx.version,
+                lock_time: tx.lock_time.to_consensus_u32(),
+                sha_prevouts,
+                sha_amounts,
+                sha_scriptpubkeys,
+                sha_sequences,
+                sha_outputs,
+                spend_type: if leaf_hash.is_some() { 2 } else { 0 },
+                input: input_data,
+                sha_single_output,
+                leaf_hash,
+                key_version,
+                code_separator_pos,
+            }))
+        }
+    }
+}
+
+impl SighashPreimage {
+    /// The exact bytes that are hashed: the BIP143 preimage, or the BIP341 epoch and SigMsg
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            SighashPreimage::SegwitV0(p) => {
+                out.extend(p.version.to_le_bytes());
+                out.extend(p.hash_prevouts.to_byte_array());
+                out.extend(p.hash_sequence.to_byte_array());
+                out.extend(serialize(&p.outpoint));
+                out.extend(serialize(&p.script_code));
+                out.extend(p.amount.to_sat().to_le_bytes());
+                out.extend(serialize(&p.sequence));
+                out.extend(p.hash_outputs.to_byte_array());
+                out.extend(p.lock_time.to_le_bytes());
+                out.extend(p.sighash_type.to_le_bytes());
+            }
+            SighashPreimage::Taproot(p) => {
+                out.push(p.epoch);
+                out.push(p.hash_type as u8);
+                out.extend(p.version.to_le_bytes());
+                out.extend(p.lock_time.to_le_bytes());
+                for hash in [p.sha_prevouts, p.sha_amounts, p.sha_scriptpubkeys, p.sha_sequences, p.sha_outputs].into_iter().flatten() {
+                    out.extend(hash.to_byte_array());
+                }
+                out.push(p.spend_type);
+                match &p.input {
+                    TaprootInputData::Index(index) => out.extend(index.to_le_bytes()),
+                    TaprootInputData::AnyoneCanPay { outpoint, amount, script_pubkey, sequence } => {
+                        out.extend(serialize(outpoint));
+                        out.extend(amount.to_sat().to_le_bytes());
+                        out.extend(serialize(script_pubkey));
+                        out.extend(serialize(sequence));
+                    }
+                }
+                if let Some(hash) = p.sha_single_output {
+                    out.extend(hash.to_byte_array());
+                }
+                if let Some(leaf_hash) = p.leaf_hash {
+                    out.extend(leaf_hash.to_byte_array());
+                    out.extend(p.key_version);
+                    out.extend(p.code_separator_pos.unwrap_or(u32::MAX).to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+
+    /// Double SHA256 (BIP143) or the TapSighash tagged hash (BIP341) of `to_bytes()`
+    pub fn digest(&self) -> [u8; 32] {
+        let bytes = self.to_bytes();
+        match self {
+            SighashPreimage::SegwitV0(_) => SegwitV0Sighash::hash(&bytes).to_byte_array(),
+            SighashPreimage::Taproot(_) => TapSighash::hash(&bytes).to_byte_array(),
+        }
+    }
+}
+
+fn script_hex(script: &Script) -> String {
+    hex::encode(script.as_bytes())
+}
+
+impl fmt::Display for SighashPreimage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SighashPreimage::SegwitV0(p) => {
+                writeln!(f, "version: {}", p.version)?;
+                writeln!(f, "hash_prevouts: {}", p.hash_prevouts)?;
+                writeln!(f, "hash_sequence: {}", p.hash_sequence)?;
+                writeln!(f, "outpoint: {}", p.outpoint)?;
+                writeln!(f, "script_code: {}", script_hex(&p.script_code))?;
+                writeln!(f, "amount: {}", p.amount.to_sat())?;
+                writeln!(f, "sequence: {:#010x}", p.sequence.to_consensus_u32())?;
+                writeln!(f, "hash_outputs: {}", p.hash_outputs)?;
+                writeln!(f, "lock_time: {}", p.lock_time)?;
+                writeln!(f, "sighash_type: {:#x}", p.sighash_type)?;
+            }
+            SighashPreimage::Taproot(p) => {
+                writeln!(f, "epoch: {}", p.epoch)?;
+                writeln!(f, "hash_type: {:#04x}", p.hash_type as u8)?;
+                writeln!(f, "version: {}", p.version)?;
+                writeln!(f, "lock_time: {}", p.lock_time)?;
+                for (name, hash) in [("sha_prevouts", p.sha_prevouts), ("sha_amounts", p.sha_amounts), ("sha_scriptpubkeys", p.sha_scriptpubkeys), ("sha_sequences", p.sha_sequences), ("sha_outputs", p.sha_outputs)] {
+                    if let Some(hash) = hash {
+                        writeln!(f, "{}: {}", name, hash)?;
+                    }
+                }
+                writeln!(f, "spend_type: {}", p.spend_type)?;
+                match &p.input {
+                    TaprootInputData::Index(index) => writeln!(f, "input_index: {}", index)?,
+                    TaprootInputData::AnyoneCanPay { outpoint, amount, script_pubkey, sequence } => {
+                        writeln!(f, "outpoint: {}", outpoint)?;
+                        writeln!(f, "amount: {}", amount.to_sat())?;
+                        writeln!(f, "script_pubkey: {}", script_hex(script_pubkey))?;
+                        writeln!(f, "sequence: {:#010x}", sequence.to_consensus_u32())?;
+                    }
+                }
+                if let Some(hash) = p.sha_single_output {
+                    writeln!(f, "sha_single_output: {}", hash)?;
+                }
+                if let Some(leaf_hash) = p.leaf_hash {
+                    writeln!(f, "leaf_hash: {}", leaf_hash)?;
+                    writeln!(f, "key_version: {}", p.key_version.unwrap_or(0))?;
+                    writeln!(f, "code_separator_pos: {:#010x}", p.code_separator_pos.unwrap_or(u32::MAX))?;
+                }
+            }
+        }
+        write!(f, "digest: {}", hex::encode(self.digest()))
+    }
+}
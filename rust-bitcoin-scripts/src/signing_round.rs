@@ -0,0 +1,227 @@
+//! Multi-party signing rounds with a timeout policy.
+//!
+//! A round collects partial signatures from a fixed signer set until `quorum` of them have
+//! responded. If the window expires first, the round is marked stalled and yields an
+//! `Escalation` naming the unresponsive signers and the UTXOs to release back to selection.
+//! Rounds still collecting at shutdown are saved as `RoundSnapshot`s and resumed on restart with
+//! the time already spent counted against their window. Rounds that reach quorum or stall are
+//! reported to `telemetry`.
+
+use crate::psbt;
+use crate::state_file;
+use crate::telemetry;
+use crate::webhooks::LifecycleEvent;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::OutPoint;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundState {
+    Collecting,
+    Complete,
+    Stalled,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Escalation {
+    pub round_id: String,
+    pub unresponsive: Vec<String>,
+    pub released_utxos: Vec<OutPoint>,
+}
+
+impl Escalation {
+    pub fn to_event(&self) -> LifecycleEvent {
+        LifecycleEvent::SigningRoundStalled { round_id: self.round_id.clone(), missing_signers: self.unresponsive.clone() }
+    }
+}
+
+pub struct SigningRound {
+    pub id: String,
+    pub psbt: Psbt,
+    pub signers: Vec<String>,
+    pub quorum: usize,
+    pub responded: Vec<String>,
+    pub locked_utxos: Vec<OutPoint>,
+    pub started: Instant,
+    pub timeout: Duration,
+    pub state: RoundState,
+}
+
+impl SigningRound {
+    pub fn new(id: &str, psbt: Psbt, signers: Vec<String>, quorum: usize, timeout: Duration) -> Self {
+        let locked_utxos = psbt.unsigned_tx.input.iter().map(|i| i.previous_output).collect();
+        Self {
+            id: id.to_string(),
+            psbt,
+            signers,
+            quorum,
+            responded: Vec::new(),
+            locked_utxos,
+            started: Instant::now(),
+            timeout,
+            state: RoundState::Collecting,
+        }
+    }
+
+    /// Merge a signer's partial PSBT; returns true once quorum is reached
+    pub fn submit(&mut self, signer: &str, partial: Psbt) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.state != RoundState::Collecting {
+            return Err(format!("round {} is {:?}", self.id, self.state).into());
+        }
+        if !self.signers.iter().any(|s| s == signer) {
+            return Err(format!("{} is not a signer in round {}", signer, self.id).into());
+        }
+        self.psbt = psbt::merge(self.psbt.clone(), partial)?;
+        if !self.responded.iter().any(|s| s == signer) {
+            self.responded.push(signer.to_string());
+        }
+        if self.responded.len() >= self.quorum {
+            self.state = RoundState::Complete;
+            self.record_outcome(Instant::now());
+        }
+        Ok(self.state == RoundState::Complete)
+    }
+
+    /// Report the round, which just completed or stalled at `now`
+    fn record_outcome(&self, now: Instant) {
+        let state = match self.state {
+            RoundState::Collecting => "collecting",
+            RoundState::Complete => "complete",
+            RoundState::Stalled => "stalled",
+        };
+        let error = (self.state == RoundState::Stalled).then(|| format!("stalled without {}", self.unresponsive().join(", ")));
+        let attributes = [
+            ("round.id", self.id.clone()),
+            ("round.quorum", self.quorum.to_string()),
+            ("round.responded", self.responded.len().to_string()),
+            ("state", state.to_string()),
+        ];
+        telemetry::record_span("signing_round", now.saturating_duration_since(self.started), &attributes, error);
+        telemetry::observe("wrapyield.signing_round.duration", now.saturating_duration_since(self.started), &[("state", state.to_string())]);
+        telemetry::add("wrapyield.signing_rounds", 1, &[("state", state.to_string())]);
+    }
+
+    pub fn unresponsive(&self) -> Vec<String> {
+        self.signers.iter().filter(|s| !self.responded.contains(s)).cloned().collect()
+    }
+
+    /// Mark the round stalled if its window has passed at `now` without quorum
+    pub fn check_timeout(&mut self, now: Instant) -> Option<Escalation> {
+        if self.state != RoundState::Collecting || now.duration_since(self.started) < self.timeout {
+            return None;
+        }
+        self.state = RoundState::Stalled;
+        self.record_outcome(now);
+        Some(Escalation {
+            round_id: self.id.clone(),
+            unresponsive: self.unresponsive(),
+            released_utxos: std::mem::take(&mut self.locked_utxos),
+        })
+    }
+}
+
+/// Serializable state of a round that was still collecting signatures
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoundSnapshot {
+    pub id: String,
+    /// Base64 PSBT with every partial signature merged so far
+    pub psbt: String,
+    pub signers: Vec<String>,
+    pub quorum: usize,
+    pub responded: Vec<String>,
+    /// `txid:vout`
+    pub locked_utxos: Vec<String>,
+    pub elapsed_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl SigningRound {
+    pub fn snapshot(&self, now: Instant) -> RoundSnapshot {
+        RoundSnapshot {
+            id: self.id.clone(),
+            psbt: psbt::to_base64(&self.psbt),
+            signers: self.signers.clone(),
+            quorum: self.quorum,
+            responded: self.responded.clone(),
+            locked_utxos: self.locked_utxos.iter().map(|o| o.to_string()).collect(),
+            elapsed_secs: now.saturating_duration_since(self.started).as_secs(),
+            timeout_secs: self.timeout.as_secs(),
+        }
+    }
+
+    /// Rebuild a round from a snapshot; its window keeps running from where it stopped
+    pub fn resume(snapshot: &RoundSnapshot) -> Result<Self, Box<dyn std::error::Error>> {
+        let locked_utxos = snapshot.locked_utxos.iter().map(|o| OutPoint::from_str(o)).collect::<Result<_, _>>()?;
+        let now = Instant::now();
+        Ok(Self {
+            id: snapshot.id.clone(),
+            psbt: psbt::from_base64(&snapshot.psbt)?,
+            signers: snapshot.signers.clone(),
+            quorum: snapshot.quorum,
+            responded: snapshot.responded.clone(),
+            locked_utxos,
+            started: now.checked_sub(Duration::from_secs(snapshot.elapsed_secs)).unwrap_or(now),
+            timeout: Duration::from_secs(snapshot.timeout_secs),
+            state: RoundState::Collecting,
+        })
+    }
+}
+
+/// Open rounds plus a running count of missed rounds per signer
+#[derive(Default)]
+pub struct SigningRounds {
+    pub rounds: BTreeMap<String, SigningRound>,
+    pub missed_rounds: HashMap<String, u32>,
+}
+
+impl SigningRounds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(&mut self, round: SigningRound) {
+        self.rounds.insert(round.id.clone(), round);
+    }
+
+    pub fn submit(&mut self, round_id: &str, signer: &str, partial: Psbt) -> Result<bool, Box<dyn std::error::Error>> {
+        self.rounds.get_mut(round_id).ok_or_else(|| format!("unknown round {}", round_id))?.submit(signer, partial)
+    }
+
+    /// Expire overdue rounds, record who missed them and return the escalations
+    pub fn tick(&mut self, now: Instant) -> Vec<Escalation> {
+        let escalations: Vec<Escalation> = self.rounds.values_mut().filter_map(|round| round.check_timeout(now)).collect();
+        for escalation in &escalations {
+            for signer in &escalation.unresponsive {
+                *self.missed_rounds.entry(signer.clone()).or_default() += 1;
+            }
+        }
+        escalations
+    }
+
+    /// Snapshots of every round still collecting signatures
+    pub fn abandon(&self, now: Instant) -> Vec<RoundSnapshot> {
+        self.rounds.values().filter(|r| r.state == RoundState::Collecting).map(|r| r.snapshot(now)).collect()
+    }
+
+    /// Write the collecting rounds to `path` as JSON
+    pub fn persist(&self, path: &Path, now: Instant) -> Result<usize, Box<dyn std::error::Error>> {
+        let snapshots = self.abandon(now);
+        state_file::save_json(path, &snapshots)?;
+        Ok(snapshots.len())
+    }
+
+    /// Load rounds persisted by `persist`; a missing file yields no rounds. Callers must
+    /// re-reserve `locked_utxos` of the resumed rounds.
+    pub fn resume(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut rounds = Self::new();
+        let snapshots: Vec<RoundSnapshot> = state_file::load_json(path)?.unwrap_or_default();
+        for snapshot in &snapshots {
+            rounds.open(SigningRound::resume(snapshot)?);
+        }
+        Ok(rounds)
+    }
+}
@@ -10,6 +10,11 @@ use hex::ToHex;
 
 /// Demonstrates creating a simple Taproot output with a single internal key and a single script path.
 pub fn simple_taproot_demo() {
+    simple_taproot_demo_on(Network::Regtest)
+}
+
+/// `simple_taproot_demo` with the address encoded for `network`
+pub fn simple_taproot_demo_on(network: Network) {
     // 1. Setup secp256k1 context and a random internal key
     let secp = Secp256k1::new();
     let sk_bytes = [4; 32];
@@ -30,6 +35,6 @@ pub fn simple_taproot_demo() {
     println!("Taproot output key: {}", taproot_output_key);
 
     // 4. Create a Taproot address
-    let address = Address::p2tr_tweaked(taproot_output_key, Network::Regtest);
+    let address = Address::p2tr_tweaked(taproot_output_key, network);
     println!("Taproot address: {}", address);
 } 
\ No newline at end of file
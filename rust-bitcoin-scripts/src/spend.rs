@@ -0,0 +1,268 @@
+//! Spend planning: which path of a descriptor can be taken now, and at what cost.
+//!
+//! The planner runs miniscript satisfaction with placeholder signatures for the keys we hold and
+//! with only the timelocks that are already mature, so miniscript itself picks the smallest
+//! available witness. The placeholders identify the signers in the resulting witness, and each
+//! mature timelock is switched off in turn to find the ones the path actually depends on; those
+//! become the plan's `lock_time` and `sequence`. Taproot outputs are planned per spend path
+//! (key path and every leaf) and the cheapest one wins.
+//!
+//! Relative timelocks are only matured by confirmations; time-based `older` is never considered
+//! satisfiable.
+
+use crate::locktime::SEQUENCE_TYPE_FLAG;
+use crate::psbt::DEFAULT_SEQUENCE;
+use crate::taproot_tree::x_only;
+use bitcoin::absolute::LockTime;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::{KeyPair, Message, Secp256k1, SecretKey};
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use bitcoin::{ecdsa, taproot, PublicKey, ScriptBuf, Sequence, VarInt};
+use miniscript::policy::Liftable;
+use miniscript::{Descriptor, Miniscript, Satisfier, Tap};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpendPath {
+    /// The only path of a non-taproot descriptor
+    Script,
+    TaprootKey,
+    TaprootLeaf { script: ScriptBuf, depth: u8 },
+}
+
+impl fmt::Display for SpendPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpendPath::Script => write!(f, "script"),
+            SpendPath::TaprootKey => write!(f, "taproot_key"),
+            SpendPath::TaprootLeaf { depth, .. } => write!(f, "taproot_leaf@{}", depth),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendPlan {
+    pub path: SpendPath,
+    /// Keys whose signatures the chosen satisfaction uses
+    pub signers: Vec<PublicKey>,
+    pub lock_time: LockTime,
+    pub sequence: Sequence,
+    /// Weight units of the scriptSig and witness
+    pub satisfaction_weight: usize,
+}
+
+/// What we hold and where the chain is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Planner {
+    pub keys: HashSet<PublicKey>,
+    /// Current tip; the spend is planned for the next block
+    pub height: u32,
+    pub median_time_past: u32,
+    /// Confirmations of the output being spent (0 if unconfirmed)
+    pub confirmations: u32,
+}
+
+impl Planner {
+    pub fn new(keys: impl IntoIterator<Item = PublicKey>, height: u32, median_time_past: u32, confirmations: u32) -> Self {
+        Self { keys: keys.into_iter().collect(), height, median_time_past, confirmations }
+    }
+
+    /// The cheapest path that is satisfiable now
+    pub fn plan(&self, descriptor: &Descriptor<PublicKey>) -> Result<SpendPlan, Box<dyn std::error::Error>> {
+        self.candidates(descriptor)?.into_iter().next().ok_or_else(|| {
+            format!("no spending path is satisfiable with {} held key(s) at height {} with {} confirmation(s)", self.keys.len(), self.height, self.confirmations).into()
+        })
+    }
+
+    /// Every path satisfiable now, cheapest first. Non-taproot descriptors yield at most one
+    /// plan: miniscript already chooses the cheapest satisfaction among their branches.
+    pub fn candidates(&self, descriptor: &Descriptor<PublicKey>) -> Result<Vec<SpendPlan>, Box<dyn std::error::Error>> {
+        let mut plans = Vec::new();
+        match descriptor {
+            Descriptor::Tr(tr) => {
+                let internal = x_only(tr.internal_key());
+                if self.keys.iter().any(|k| x_only(k) == internal) {
+                    plans.push(SpendPlan {
+                        path: SpendPath::TaprootKey,
+                        signers: vec![*tr.internal_key()],
+                        lock_time: LockTime::ZERO,
+                        sequence: DEFAULT_SEQUENCE,
+                        // count, 64-byte signature
+                        satisfaction_weight: 1 + 1 + 64,
+                    });
+                }
+                for (depth, ms) in tr.iter_scripts() {
+                    if let Some(plan) = self.plan_leaf(ms, depth)? {
+                        plans.push(plan);
+                    }
+                }
+            }
+            _ => {
+                let policy = descriptor.lift()?;
+                let satisfy = |satisfier: &PlanningSatisfier| descriptor.get_satisfaction(satisfier).ok();
+                if let Some(plan) = self.plan_with(&policy.relative_timelocks(), &policy.absolute_timelocks(), satisfy, SpendPath::Script) {
+                    plans.push(plan);
+                }
+            }
+        }
+        plans.sort_by_key(|p| p.satisfaction_weight);
+        Ok(plans)
+    }
+
+    fn plan_leaf(&self, ms: &Miniscript<PublicKey, Tap>, depth: u8) -> Result<Option<SpendPlan>, Box<dyn std::error::Error>> {
+        let policy = ms.lift()?;
+        let script = ms.encode();
+        let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+        let satisfy = |satisfier: &PlanningSatisfier| {
+            ms.satisfy(LeafSatisfier { inner: satisfier, leaf_hash }).ok().map(|witness| (witness, ScriptBuf::new()))
+        };
+        let path = SpendPath::TaprootLeaf { script: script.clone(), depth };
+        Ok(self.plan_with(&policy.relative_timelocks(), &policy.absolute_timelocks(), satisfy, path).map(|mut plan| {
+            // The leaf script and control block are part of the witness
+            let control_block = 33 + 32 * depth as usize;
+            plan.satisfaction_weight += VarInt(script.len() as u64).len() + script.len() + VarInt(control_block as u64).len() + control_block;
+            plan
+        }))
+    }
+
+    /// Satisfy with every mature timelock, then find which of them the satisfaction needs
+    fn plan_with<F>(&self, relative: &[u32], absolute: &[u32], satisfy: F, path: SpendPath) -> Option<SpendPlan>
+    where
+        F: Fn(&PlanningSatisfier) -> Option<(Vec<Vec<u8>>, ScriptBuf)>,
+    {
+        let mut satisfier = PlanningSatisfier::new(self);
+        let (witness, script_sig) = satisfy(&satisfier)?;
+        let mut required_relative = None;
+        let mut required_absolute = None;
+        let mature_relative: Vec<u32> = relative.iter().copied().filter(|v| satisfier.older_mature(*v)).collect();
+        let mature_absolute: Vec<u32> = absolute.iter().copied().filter(|v| satisfier.after_mature(*v)).collect();
+        for value in mature_relative {
+            satisfier.disabled_older.insert(value);
+            if satisfy(&satisfier).map_or(true, |(w, s)| w != witness || s != script_sig) {
+                required_relative = required_relative.max(Some(value));
+            }
+            satisfier.disabled_older.remove(&value);
+        }
+        for value in mature_absolute {
+            satisfier.disabled_after.insert(value);
+            if satisfy(&satisfier).map_or(true, |(w, s)| w != witness || s != script_sig) {
+                required_absolute = required_absolute.max(Some(value));
+            }
+            satisfier.disabled_after.remove(&value);
+        }
+        let signers = satisfier.signers_in(&witness, &script_sig);
+        Some(SpendPlan {
+            path,
+            signers,
+            lock_time: required_absolute.map_or(LockTime::ZERO, LockTime::from_consensus),
+            sequence: required_relative.map_or(DEFAULT_SEQUENCE, Sequence::from_consensus),
+            satisfaction_weight: satisfaction_weight(&witness, &script_sig),
+        })
+    }
+}
+
+/// Weight the satisfaction adds over an input with an empty scriptSig and no witness
+fn satisfaction_weight(witness: &[Vec<u8>], script_sig: &ScriptBuf) -> usize {
+    let script_sig_weight = 4 * (VarInt(script_sig.len() as u64).len() - 1 + script_sig.len());
+    let witness_weight = match witness.len() {
+        0 => 0,
+        n => VarInt(n as u64).len() + witness.iter().map(|e| VarInt(e.len() as u64).len() + e.len()).sum::<usize>(),
+    };
+    script_sig_weight + witness_weight
+}
+
+/// Hands out a distinct placeholder signature per held key and accepts mature timelocks
+struct PlanningSatisfier<'a> {
+    planner: &'a Planner,
+    ecdsa_sigs: HashMap<PublicKey, ecdsa::Signature>,
+    schnorr_sigs: HashMap<XOnlyPublicKey, taproot::Signature>,
+    disabled_older: HashSet<u32>,
+    disabled_after: HashSet<u32>,
+}
+
+impl<'a> PlanningSatisfier<'a> {
+    fn new(planner: &'a Planner) -> Self {
+        let secp = Secp256k1::new();
+        let signer = SecretKey::from_slice(&[1; 32]).expect("valid placeholder key");
+        let keypair = KeyPair::from_secret_key(&secp, &signer);
+        let mut ecdsa_sigs = HashMap::new();
+        let mut schnorr_sigs = HashMap::new();
+        for (i, key) in planner.keys.iter().enumerate() {
+            let mut digest = [0u8; 32];
+            digest[..8].copy_from_slice(&(i as u64 + 1).to_le_bytes());
+            let message = Message::from_slice(&digest).expect("32 bytes");
+            ecdsa_sigs.insert(*key, ecdsa::Signature::sighash_all(secp.sign_ecdsa(&message, &signer)));
+            schnorr_sigs.insert(x_only(key), taproot::Signature { sig: secp.sign_schnorr_no_aux_rand(&message, &keypair), hash_ty: bitcoin::sighash::TapSighashType::Default });
+        }
+        Self { planner, ecdsa_sigs, schnorr_sigs, disabled_older: HashSet::new(), disabled_after: HashSet::new() }
+    }
+
+    fn older_mature(&self, value: u32) -> bool {
+        value & SEQUENCE_TYPE_FLAG == 0 && value & 0xffff <= self.planner.confirmations
+    }
+
+    fn after_mature(&self, value: u32) -> bool {
+        let lock_time = LockTime::from_consensus(value);
+        if lock_time.is_block_height() {
+            value <= self.planner.height
+        } else {
+            value < self.planner.median_time_past
+        }
+    }
+
+    /// Held keys whose placeholder signature appears in the satisfaction
+    fn signers_in(&self, witness: &[Vec<u8>], script_sig: &ScriptBuf) -> Vec<PublicKey> {
+        let mut elements: Vec<Vec<u8>> = witness.to_vec();
+        elements.extend(script_sig.instructions().filter_map(|i| i.ok()?.push_bytes().map(|b| b.as_bytes().to_vec())));
+        let mut signers: Vec<PublicKey> = self.planner.keys.iter()
+            .filter(|key| {
+                let ecdsa = self.ecdsa_sigs[key].to_vec();
+                let schnorr = self.schnorr_sigs[&x_only(key)].to_vec();
+                elements.iter().any(|e| *e == ecdsa || *e == schnorr)
+            })
+            .copied()
+            .collect();
+        signers.sort();
+        signers
+    }
+}
+
+impl Satisfier<PublicKey> for PlanningSatisfier<'_> {
+    fn lookup_ecdsa_sig(&self, key: &PublicKey) -> Option<ecdsa::Signature> {
+        self.ecdsa_sigs.get(key).copied()
+    }
+
+    fn check_older(&self, sequence: Sequence) -> bool {
+        let value = sequence.to_consensus_u32();
+        self.older_mature(value) && !self.disabled_older.contains(&value)
+    }
+
+    fn check_after(&self, lock_time: LockTime) -> bool {
+        let value = lock_time.to_consensus_u32();
+        self.after_mature(value) && !self.disabled_after.contains(&value)
+    }
+}
+
+/// `PlanningSatisfier` for one tapscript leaf
+struct LeafSatisfier<'a, 'b> {
+    inner: &'b PlanningSatisfier<'a>,
+    leaf_hash: TapLeafHash,
+}
+
+impl Satisfier<PublicKey> for LeafSatisfier<'_, '_> {
+    fn lookup_tap_leaf_script_sig(&self, key: &PublicKey, leaf_hash: &TapLeafHash) -> Option<taproot::Signature> {
+        if *leaf_hash != self.leaf_hash {
+            return None;
+        }
+        self.inner.schnorr_sigs.get(&x_only(key)).copied()
+    }
+
+    fn check_older(&self, sequence: Sequence) -> bool {
+        self.inner.check_older(sequence)
+    }
+
+    fn check_after(&self, lock_time: LockTime) -> bool {
+        self.inner.check_after(lock_time)
+    }
+}
@@ -0,0 +1,50 @@
+//! Crash-safe state files. Contents are written to a temporary file next to the target, synced
+//! and renamed over it, so a restart after a crash finds either the old or the new state, never a
+//! truncated one.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// `.<name>.tmp` in the directory of `path`, so the rename stays on one filesystem
+fn temp_path(path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let name = path.file_name().ok_or_else(|| format!("{} does not name a file", path.display()))?;
+    let mut temp = OsString::from(".");
+    temp.push(name);
+    temp.push(".tmp");
+    Ok(path.with_file_name(temp))
+}
+
+/// Replace the contents of `path` in one step
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let temp = temp_path(path)?;
+    let mut file = std::fs::File::create(&temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// The contents of `path`; `None` if nothing was saved yet
+pub(crate) fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    match std::fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `value` as pretty JSON, written with `write_atomic`
+pub(crate) fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    write_atomic(path, serde_json::to_string_pretty(value)?.as_bytes())
+}
+
+/// What `save_json` wrote to `path`; `None` if nothing was saved yet
+pub(crate) fn load_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, Box<dyn std::error::Error>> {
+    match read_if_exists(path)? {
+        Some(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,103 @@
+//! One side of a hash-locked atomic swap, built on `htlc::Htlc`.
+//!
+//! The initiator makes a random 32-byte secret and locks coins in an HTLC with its SHA256; the
+//! participant locks coins on the other chain behind the same hash. Whoever claims first reveals
+//! the secret in their claim witness, and the other side reads it back from that transaction
+//! with `learn_secret` to claim in turn. A `Coordinator` made with `participate` holds only the
+//! hash until then.
+//!
+//! Claim and refund transactions have one input and one output; their witnesses come from
+//! `Htlc::claim_witness` and `Htlc::refund_witness`, which check them with the interpreter.
+
+use crate::amount::deduct_fee_for;
+use crate::htlc::{HashLock, Htlc, HtlcKind, HtlcParams, HtlcPath};
+use crate::psbt::{SpendableUtxo, DEFAULT_SEQUENCE};
+use bitcoin::absolute::LockTime;
+use bitcoin::secp256k1::{Secp256k1, Signing};
+use bitcoin::{Amount, PrivateKey, ScriptBuf, Transaction, TxIn, TxOut, Witness};
+use miniscript::bitcoin::PublicKey;
+use miniscript::Descriptor;
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+pub struct Coordinator {
+    pub htlc: Htlc,
+    secret: Option<Zeroizing<[u8; 32]>>,
+}
+
+impl Coordinator {
+    /// Start a swap with a fresh secret: coins in the HTLC go to `receiver` against the secret,
+    /// or back to `sender` from `timeout`
+    pub fn initiate(kind: HtlcKind, receiver: PublicKey, sender: PublicKey, timeout: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut secret = Zeroizing::new([0u8; 32]);
+        rand::thread_rng().fill_bytes(secret.as_mut());
+        let htlc = Htlc::new(kind, HtlcParams { receiver, sender, hash_lock: HashLock::sha256(&secret), timeout })?;
+        Ok(Self { htlc, secret: Some(secret) })
+    }
+
+    /// Join a swap whose hash the counterparty published; the secret is learnt from their claim
+    pub fn participate(htlc: Htlc) -> Self {
+        Self { htlc, secret: None }
+    }
+
+    pub fn hash_lock(&self) -> HashLock {
+        self.htlc.params.hash_lock
+    }
+
+    /// Descriptor to fund
+    pub fn funding_descriptor(&self) -> &Descriptor<PublicKey> {
+        &self.htlc.descriptor
+    }
+
+    /// The preimage, once generated or learnt
+    pub fn secret(&self) -> Option<&[u8; 32]> {
+        self.secret.as_deref()
+    }
+
+    /// Signed transaction claiming `utxo` to `destination` with the secret and `receiver_key`
+    pub fn claim_tx<C: Signing>(&self, secp: &Secp256k1<C>, utxo: &SpendableUtxo, destination: ScriptBuf, fee: Amount, receiver_key: &PrivateKey) -> Result<Transaction, Box<dyn std::error::Error>> {
+        let secret = self.secret().ok_or("the secret is not known yet")?;
+        let mut tx = self.unsigned(utxo, destination, fee, LockTime::ZERO)?;
+        let sig = self.htlc.sign(secp, &tx, 0, std::slice::from_ref(&utxo.txout), HtlcPath::Claim, receiver_key)?;
+        tx.input[0].witness = self.htlc.claim_witness(&tx, 0, std::slice::from_ref(&utxo.txout), &sig, secret)?;
+        Ok(tx)
+    }
+
+    /// Signed transaction refunding `utxo` to `destination` with `sender_key`; it is final only
+    /// from the HTLC's timeout
+    pub fn refund_tx<C: Signing>(&self, secp: &Secp256k1<C>, utxo: &SpendableUtxo, destination: ScriptBuf, fee: Amount, sender_key: &PrivateKey) -> Result<Transaction, Box<dyn std::error::Error>> {
+        let mut tx = self.unsigned(utxo, destination, fee, self.htlc.refund_lock_time())?;
+        let sig = self.htlc.sign(secp, &tx, 0, std::slice::from_ref(&utxo.txout), HtlcPath::Refund, sender_key)?;
+        tx.input[0].witness = self.htlc.refund_witness(&tx, 0, std::slice::from_ref(&utxo.txout), &sig)?;
+        Ok(tx)
+    }
+
+    /// The first 32-byte witness element of `tx` that opens the hash lock
+    pub fn extract_preimage(&self, tx: &Transaction) -> Option<[u8; 32]> {
+        tx.input
+            .iter()
+            .flat_map(|input| input.witness.iter())
+            .filter_map(|element| <[u8; 32]>::try_from(element).ok())
+            .find(|candidate| self.hash_lock().matches(candidate))
+    }
+
+    /// Take the secret from the counterparty's broadcast `claim` and keep it
+    pub fn learn_secret(&mut self, claim: &Transaction) -> Result<&[u8; 32], Box<dyn std::error::Error>> {
+        let preimage = self.extract_preimage(claim).ok_or_else(|| format!("{} does not reveal the preimage", claim.txid()))?;
+        self.secret = Some(Zeroizing::new(preimage));
+        Ok(self.secret().expect("just set"))
+    }
+
+    fn unsigned(&self, utxo: &SpendableUtxo, destination: ScriptBuf, fee: Amount, lock_time: LockTime) -> Result<Transaction, Box<dyn std::error::Error>> {
+        if utxo.txout.script_pubkey != self.htlc.script_pubkey() {
+            return Err(format!("{} is not locked by this HTLC", utxo.outpoint).into());
+        }
+        let value = deduct_fee_for(Amount::from_sat(utxo.txout.value), fee, &destination)?;
+        Ok(Transaction {
+            version: 2,
+            lock_time,
+            input: vec![TxIn { previous_output: utxo.outpoint, script_sig: ScriptBuf::new(), sequence: DEFAULT_SEQUENCE, witness: Witness::new() }],
+            output: vec![TxOut { value: value.to_sat(), script_pubkey: destination }],
+        })
+    }
+}
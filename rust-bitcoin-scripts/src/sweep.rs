@@ -0,0 +1,85 @@
+//! Emergency sweep of a vault through its backup key.
+//!
+//! `sweep_backup_path` collects every confirmed UTXO paying the vault descriptor, plans each one
+//! for the backup key alone (so the finalizer takes the `pk(backup)` path and its timelocks end
+//! up in the sequences and lock time), and spends them all to one destination in a single
+//! transaction at an urgent fee rate.
+
+use crate::backend::ChainBackend;
+use crate::fees::{self, FeePlan};
+use crate::locktime::{self, LockTimePolicy};
+use crate::psbt::{self, SpendableUtxo};
+use crate::receipt::SpendReceipt;
+use crate::rpc_types::Utxo;
+use crate::spend::Planner;
+use bitcoin::absolute::LockTime;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Amount, PrivateKey, PublicKey, Transaction, TxOut, Txid};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+/// Confirmation target of the sweep's fee estimate
+pub const SWEEP_CONF_TARGET: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepResult {
+    pub txid: Txid,
+    pub transaction: Transaction,
+    pub fee: Amount,
+    /// The UTXOs spent, in input order
+    pub swept: Vec<Utxo>,
+    pub receipt: SpendReceipt,
+}
+
+/// Highest of two absolute lock times; they must be in the same unit unless one is zero
+fn later(a: LockTime, b: LockTime) -> Result<LockTime, Box<dyn std::error::Error>> {
+    if a == LockTime::ZERO || b == LockTime::ZERO {
+        return Ok(if a == LockTime::ZERO { b } else { a });
+    }
+    if !a.is_same_unit(b) {
+        return Err("inputs need lock times in different units".into());
+    }
+    Ok(if a.to_consensus_u32() >= b.to_consensus_u32() { a } else { b })
+}
+
+/// Spend every confirmed UTXO of `descriptor` to `destination` with `backup_key` alone and
+/// broadcast the transaction. Fails without broadcasting if any UTXO cannot be spent by the
+/// backup key yet.
+pub async fn sweep_backup_path(backend: &impl ChainBackend, descriptor: &Descriptor<PublicKey>, backup_key: &PrivateKey, destination: &str) -> Result<SweepResult, Box<dyn std::error::Error>> {
+    let secp = Secp256k1::new();
+    let backup = backup_key.public_key(&secp);
+    let destination = backend.parse_address(destination)?.script_pubkey();
+    let definite = Descriptor::<DefiniteDescriptorKey>::from_str(&descriptor.to_string())?;
+    let segwit = descriptor.desc_type().segwit_version().is_some();
+
+    let swept: Vec<Utxo> = backend.descriptor_utxos(descriptor).await?.into_iter().filter(|u| u.confirmations > 0).collect();
+    if swept.is_empty() {
+        return Err(format!("no UTXOs pay {}", descriptor).into());
+    }
+    let tip = backend.chain_tip().await?;
+    let mut utxos = Vec::new();
+    let mut lock_time = LockTime::ZERO;
+    for utxo in &swept {
+        let planner = Planner::new([backup], tip.height as u32, tip.median_time_past as u32, utxo.confirmations);
+        let plan = planner.plan(descriptor).map_err(|e| format!("{}: {}", utxo.outpoint, e))?;
+        lock_time = later(lock_time, plan.lock_time)?;
+        let mut spendable = SpendableUtxo::new(utxo.outpoint, TxOut { value: utxo.amount.to_sat(), script_pubkey: utxo.script_pubkey.clone() });
+        spendable.sequence = plan.sequence;
+        if !segwit {
+            spendable.prev_tx = Some(backend.transaction(&utxo.outpoint.txid).await?);
+        }
+        utxos.push(spendable);
+    }
+
+    let total = swept.iter().map(|u| u.amount).sum::<Amount>();
+    let rate = fees::estimate_fee_rate(backend, SWEEP_CONF_TARGET).await?;
+    let inputs = vec![descriptor; swept.len()];
+    let fee_plan = FeePlan::sweep(&inputs, total, destination, rate)?;
+    let lock_time = locktime::reconcile(lock_time, tip.height as u32, LockTimePolicy::default());
+    let mut unsigned = psbt::create(&definite, &utxos, fee_plan.outputs, lock_time)?;
+    psbt::sign(&mut unsigned, &[*backup_key])?;
+    let transaction = psbt::finalize(unsigned)?;
+    let receipt = SpendReceipt::broadcast(backend, &transaction, fee_plan.fee, "backup").await?;
+    Ok(SweepResult { txid: receipt.txid, transaction, fee: fee_plan.fee, swept, receipt })
+}
@@ -0,0 +1,360 @@
+//! Two-leaf taproot vault: key-path spend, an immediate `<K> CHECKSIG` leaf and a timelocked
+//! `<R> CHECKSIGVERIFY <h> CLTV` recovery leaf. Both leaves are miniscript (`pk(K)` and
+//! `and_v(v:pk(R),after(h))`), so `sign_leaf` can check its witness with the interpreter.
+//!
+//! Wraps the construction `simple_taproot_tests.rs` builds by hand so callers only pick a leaf
+//! and supply a signature; sighash, control block and witness layout come from here.
+//! `spend_script_path` and `spend_key_path` do the same for any tree, with any sighash type, and
+//! `sign_inputs` signs several taproot inputs of one transaction, each along its own path.
+//! Key-path signatures only come from a `KeyPathSigner`, which `KeyPathTweak` builds from the
+//! untweaked internal keypair.
+
+use crate::verify::verify_witness;
+use crate::weak_keys::KeyPolicy;
+use bitcoin::absolute::LockTime;
+use bitcoin::blockdata::opcodes::all::{OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CLTV};
+use bitcoin::blockdata::script::{Builder, Instruction, Script};
+use bitcoin::key::{KeyPair, TapTweak, TweakedKeyPair, TweakedPublicKey, XOnlyPublicKey};
+use bitcoin::secp256k1::{Message, Secp256k1, Signing, Verification};
+use bitcoin::sighash::{Prevouts, ScriptPath, SighashCache, TapSighash, TapSighashType};
+use bitcoin::taproot::{self, ControlBlock, LeafVersion, TapNodeHash, TapTweakHash, TaprootBuilder, TaprootSpendInfo};
+use bitcoin::{Address, Network, ScriptBuf, Transaction, TxOut, Witness};
+use miniscript::descriptor::DescriptorPublicKey;
+use serde::{Deserialize, Serialize};
+
+/// Script leaves of a `TaprootVault`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VaultLeaf {
+    /// `<immediate_key> OP_CHECKSIG`
+    Immediate,
+    /// `<recovery_key> OP_CHECKSIGVERIFY <recovery_height> OP_CLTV`
+    Recovery,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaprootVaultParams {
+    pub internal_key: XOnlyPublicKey,
+    pub immediate_key: XOnlyPublicKey,
+    pub recovery_key: XOnlyPublicKey,
+    /// Block height the recovery leaf unlocks at
+    pub recovery_height: u32,
+    pub network: Network,
+    pub key_policy: KeyPolicy,
+}
+
+impl TaprootVaultParams {
+    /// One key on every path, as in the two-leaf regtest example
+    pub fn single_key(key: XOnlyPublicKey, recovery_height: u32, network: Network) -> Self {
+        Self { internal_key: key, immediate_key: key, recovery_key: key, recovery_height, network, key_policy: KeyPolicy::default() }
+    }
+
+    /// Keys derived at `index` from descriptor keys with origin (`hd::HdWallet::descriptor_key`)
+    pub fn from_descriptor_keys(internal: &DescriptorPublicKey, immediate: &DescriptorPublicKey, recovery: &DescriptorPublicKey, index: u32, recovery_height: u32, network: Network) -> Result<Self, Box<dyn std::error::Error>> {
+        let secp = Secp256k1::verification_only();
+        let derive = |key: &DescriptorPublicKey| -> Result<XOnlyPublicKey, Box<dyn std::error::Error>> {
+            Ok(key.clone().at_derivation_index(index)?.derive_public_key(&secp)?.inner.x_only_public_key().0)
+        };
+        Ok(Self {
+            internal_key: derive(internal)?,
+            immediate_key: derive(immediate)?,
+            recovery_key: derive(recovery)?,
+            recovery_height,
+            network,
+            key_policy: KeyPolicy::default(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TaprootVault {
+    pub internal_key: XOnlyPublicKey,
+    pub immediate_key: XOnlyPublicKey,
+    pub recovery_key: XOnlyPublicKey,
+    pub recovery_lock_time: LockTime,
+    pub network: Network,
+    pub spend_info: TaprootSpendInfo,
+}
+
+impl TaprootVault {
+    pub fn new<C: Verification>(secp: &Secp256k1<C>, params: TaprootVaultParams) -> Result<Self, Box<dyn std::error::Error>> {
+        for key in [&params.internal_key, &params.immediate_key, &params.recovery_key] {
+            params.key_policy.check_x_only(key)?;
+        }
+        let recovery_lock_time = LockTime::from_height(params.recovery_height)?;
+        let immediate = immediate_script(&params.immediate_key);
+        let recovery = recovery_script(params.recovery_height, &params.recovery_key);
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(1, immediate)?
+            .add_leaf(1, recovery)?
+            .finalize(secp, params.internal_key)
+            .map_err(|_| "taproot tree is incomplete")?;
+        Ok(Self {
+            internal_key: params.internal_key,
+            immediate_key: params.immediate_key,
+            recovery_key: params.recovery_key,
+            recovery_lock_time,
+            network: params.network,
+            spend_info,
+        })
+    }
+
+    pub fn key_path_tweak(&self) -> KeyPathTweak {
+        KeyPathTweak::from_spend_info(&self.spend_info)
+    }
+
+    pub fn output_key(&self) -> TweakedPublicKey {
+        self.spend_info.output_key()
+    }
+
+    pub fn address(&self) -> Address {
+        Address::p2tr_tweaked(self.output_key(), self.network)
+    }
+
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        self.address().script_pubkey()
+    }
+
+    pub fn script(&self, leaf: VaultLeaf) -> ScriptBuf {
+        match leaf {
+            VaultLeaf::Immediate => immediate_script(&self.immediate_key),
+            VaultLeaf::Recovery => recovery_script(self.recovery_lock_time.to_consensus_u32(), &self.recovery_key),
+        }
+    }
+
+    /// Key that must sign for `leaf`
+    pub fn signing_key(&self, leaf: VaultLeaf) -> XOnlyPublicKey {
+        match leaf {
+            VaultLeaf::Immediate => self.immediate_key,
+            VaultLeaf::Recovery => self.recovery_key,
+        }
+    }
+
+    pub fn control_block_for(&self, leaf: VaultLeaf) -> ControlBlock {
+        self.spend_info
+            .control_block(&(self.script(leaf), LeafVersion::TapScript))
+            .expect("leaf is part of the tree")
+    }
+
+    /// BIP341 script-path sighash (`SIGHASH_DEFAULT`) for spending input `input_index` via `leaf`.
+    /// `prevouts` are the outputs spent by every input of `tx`, in input order.
+    pub fn sighash_for_leaf(&self, tx: &Transaction, input_index: usize, prevouts: &[TxOut], leaf: VaultLeaf) -> Result<TapSighash, Box<dyn std::error::Error>> {
+        let script = self.script(leaf);
+        let mut cache = SighashCache::new(tx);
+        Ok(cache.taproot_script_spend_signature_hash(input_index, &Prevouts::All(prevouts), ScriptPath::new(&script, LeafVersion::TapScript), TapSighashType::Default)?)
+    }
+
+    /// BIP341 key-path sighash (`SIGHASH_DEFAULT`)
+    pub fn key_spend_sighash(&self, tx: &Transaction, input_index: usize, prevouts: &[TxOut]) -> Result<TapSighash, Box<dyn std::error::Error>> {
+        let mut cache = SighashCache::new(tx);
+        Ok(cache.taproot_key_spend_signature_hash(input_index, &Prevouts::All(prevouts), TapSighashType::Default)?)
+    }
+
+    /// `<sig> <leaf_script> <control_block>`
+    pub fn finalize_witness(&self, leaf: VaultLeaf, signature: &taproot::Signature) -> Witness {
+        let mut witness = Witness::new();
+        witness.push(signature.to_vec());
+        witness.push(self.script(leaf).as_bytes());
+        witness.push(self.control_block_for(leaf).serialize());
+        witness
+    }
+
+    /// `<sig>` made with the tweaked output key
+    pub fn key_spend_witness(&self, signature: &taproot::Signature) -> Witness {
+        Witness::from_slice(&[signature.to_vec()])
+    }
+
+    /// Sign input `input_index` for `leaf` with `keypair` and return the finished witness, once
+    /// `verify::verify_witness` accepts it
+    pub fn sign_leaf<C: Signing>(&self, secp: &Secp256k1<C>, tx: &Transaction, input_index: usize, prevouts: &[TxOut], leaf: VaultLeaf, keypair: &KeyPair) -> Result<Witness, Box<dyn std::error::Error>> {
+        if keypair.x_only_public_key().0 != self.signing_key(leaf) {
+            return Err(format!("key does not sign the {:?} leaf", leaf).into());
+        }
+        let witness = spend_script_path(secp, &self.spend_info, &self.script(leaf), keypair, tx, input_index, prevouts, TapSighashType::Default)?;
+        verify_witness(tx, input_index, prevouts, &witness)?;
+        Ok(witness)
+    }
+
+    /// Sign input `input_index` through the key path; `keypair` is the untweaked internal key.
+    /// The witness is checked like `sign_leaf`'s.
+    pub fn sign_key_spend<C: Signing + Verification>(&self, secp: &Secp256k1<C>, tx: &Transaction, input_index: usize, prevouts: &[TxOut], keypair: &KeyPair) -> Result<Witness, Box<dyn std::error::Error>> {
+        let witness = self.key_path_tweak().signer(secp, keypair)?.sign(secp, tx, input_index, prevouts, TapSighashType::Default)?;
+        verify_witness(tx, input_index, prevouts, &witness)?;
+        Ok(witness)
+    }
+
+    /// Input spend through `leaf`, or through the key path for `None`
+    pub fn input_spend(&self, leaf: Option<VaultLeaf>, keypair: KeyPair) -> TaprootInputSpend<'_> {
+        match leaf {
+            Some(leaf) => TaprootInputSpend::ScriptPath { spend_info: &self.spend_info, leaf_script: self.script(leaf), keypair },
+            None => TaprootInputSpend::KeyPath { internal_keypair: keypair, merkle_root: self.spend_info.merkle_root() },
+        }
+    }
+}
+
+/// The tweak between an internal key and the output key, recorded so key-path signing cannot use
+/// the wrong key: only the holder of `internal_key` signs, and the tweak by `merkle_root` is
+/// applied by `signer`, never by the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyPathTweak {
+    /// Untweaked key whose keypair must apply the tweak
+    pub internal_key: XOnlyPublicKey,
+    /// `None` for a key-only output
+    pub merkle_root: Option<TapNodeHash>,
+    pub tweak: TapTweakHash,
+    pub output_key: XOnlyPublicKey,
+}
+
+impl KeyPathTweak {
+    pub fn new<C: Verification>(secp: &Secp256k1<C>, internal_key: XOnlyPublicKey, merkle_root: Option<TapNodeHash>) -> Self {
+        let output_key = internal_key.tap_tweak(secp, merkle_root).0.to_inner();
+        Self { internal_key, merkle_root, tweak: TapTweakHash::from_key_and_tweak(internal_key, merkle_root), output_key }
+    }
+
+    pub fn from_spend_info(spend_info: &TaprootSpendInfo) -> Self {
+        Self {
+            internal_key: spend_info.internal_key(),
+            merkle_root: spend_info.merkle_root(),
+            tweak: TapTweakHash::from_key_and_tweak(spend_info.internal_key(), spend_info.merkle_root()),
+            output_key: spend_info.output_key().to_inner(),
+        }
+    }
+
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        ScriptBuf::new_v1_p2tr_tweaked(self.output_key.dangerous_assume_tweaked())
+    }
+
+    /// Tweak `keypair` for signing; it must be the untweaked internal key
+    pub fn signer<C: Signing + Verification>(&self, secp: &Secp256k1<C>, keypair: &KeyPair) -> Result<KeyPathSigner, Box<dyn std::error::Error>> {
+        let key = keypair.x_only_public_key().0;
+        if key == self.output_key {
+            return Err("keypair is already tweaked; pass the internal keypair".into());
+        }
+        if key != self.internal_key {
+            return Err(format!("keypair {} is not the internal key {}", key, self.internal_key).into());
+        }
+        let keypair = keypair.tap_tweak(secp, self.merkle_root);
+        if TweakedPublicKey::from_keypair(keypair).to_inner() != self.output_key {
+            return Err("tweaked keypair does not match the recorded output key".into());
+        }
+        Ok(KeyPathSigner { tweak: *self, keypair })
+    }
+}
+
+/// A keypair tweaked by `KeyPathTweak::signer`; the only way to produce key-path signatures here
+#[derive(Debug, Clone, Copy)]
+pub struct KeyPathSigner {
+    tweak: KeyPathTweak,
+    keypair: TweakedKeyPair,
+}
+
+impl KeyPathSigner {
+    pub fn tweak(&self) -> &KeyPathTweak {
+        &self.tweak
+    }
+
+    pub fn sign_sighash<C: Signing>(&self, secp: &Secp256k1<C>, sighash: TapSighash, sighash_type: TapSighashType) -> taproot::Signature {
+        let sig = secp.sign_schnorr_no_aux_rand(&Message::from_slice(sighash.as_ref()).expect("32 bytes"), &self.keypair.to_inner());
+        taproot::Signature { sig, hash_ty: sighash_type }
+    }
+
+    /// Sign input `input_index` of `tx`, which must spend this output, and return its witness
+    pub fn sign<C: Signing>(&self, secp: &Secp256k1<C>, tx: &Transaction, input_index: usize, prevouts: &[TxOut], sighash_type: TapSighashType) -> Result<Witness, Box<dyn std::error::Error>> {
+        let spent = prevouts.get(input_index).ok_or_else(|| format!("no prevout for input {}", input_index))?;
+        if spent.script_pubkey != self.tweak.script_pubkey() {
+            return Err("tweaked key does not match the spent output".into());
+        }
+        let mut cache = SighashCache::new(tx);
+        let sighash = cache.taproot_key_spend_signature_hash(input_index, &Prevouts::All(prevouts), sighash_type)?;
+        Ok(Witness::from_slice(&[self.sign_sighash(secp, sighash, sighash_type).to_vec()]))
+    }
+}
+
+/// Sign input `input_index` of `tx` through the key path of an output whose internal key is
+/// `internal_keypair` and script tree is `merkle_root` (`None` for a key-only output), and return
+/// the one-element witness. The keypair is tweaked here; `sighash_type` other than `Default`
+/// appends its flag byte to the signature.
+pub fn spend_key_path<C: Signing + Verification>(secp: &Secp256k1<C>, internal_keypair: &KeyPair, merkle_root: Option<TapNodeHash>, tx: &Transaction, input_index: usize, prevouts: &[TxOut], sighash_type: TapSighashType) -> Result<Witness, Box<dyn std::error::Error>> {
+    let tweak = KeyPathTweak::new(secp, internal_keypair.x_only_public_key().0, merkle_root);
+    tweak.signer(secp, internal_keypair)?.sign(secp, tx, input_index, prevouts, sighash_type)
+}
+
+/// Sign input `input_index` of `tx` through `leaf_script`, a single-signature leaf of the tree
+/// behind `spend_info`, and return the `<sig> <leaf_script> <control_block>` witness. `prevouts`
+/// are the outputs spent by every input of `tx`, in input order; `sighash_type` other than
+/// `Default` appends its flag byte to the signature.
+#[allow(clippy::too_many_arguments)]
+pub fn spend_script_path<C: Signing>(secp: &Secp256k1<C>, spend_info: &TaprootSpendInfo, leaf_script: &Script, keypair: &KeyPair, tx: &Transaction, input_index: usize, prevouts: &[TxOut], sighash_type: TapSighashType) -> Result<Witness, Box<dyn std::error::Error>> {
+    let key = keypair.x_only_public_key().0.serialize();
+    let signs_leaf = leaf_script.instructions().any(|i| matches!(i, Ok(Instruction::PushBytes(push)) if push.as_bytes() == key.as_slice()));
+    if !signs_leaf {
+        return Err("key does not appear in the leaf script".into());
+    }
+    let control_block = spend_info
+        .control_block(&(leaf_script.to_owned(), LeafVersion::TapScript))
+        .ok_or("leaf is not part of the taproot tree")?;
+    let mut cache = SighashCache::new(tx);
+    let sighash = cache.taproot_script_spend_signature_hash(input_index, &Prevouts::All(prevouts), ScriptPath::new(leaf_script, LeafVersion::TapScript), sighash_type)?;
+    let sig = secp.sign_schnorr_no_aux_rand(&Message::from_slice(sighash.as_ref())?, keypair);
+
+    let mut witness = Witness::new();
+    witness.push(taproot::Signature { sig, hash_ty: sighash_type }.to_vec());
+    witness.push(leaf_script.as_bytes());
+    witness.push(control_block.serialize());
+    Ok(witness)
+}
+
+/// How one input of a multi-input taproot spend is signed
+#[derive(Debug, Clone)]
+pub enum TaprootInputSpend<'a> {
+    /// Key path of the output with internal key `internal_keypair` and script tree `merkle_root`
+    KeyPath { internal_keypair: KeyPair, merkle_root: Option<TapNodeHash> },
+    /// `leaf_script` of the tree behind `spend_info`, signed by `keypair`
+    ScriptPath { spend_info: &'a TaprootSpendInfo, leaf_script: ScriptBuf, keypair: KeyPair },
+}
+
+/// Sign the inputs of `tx` listed in `spends`, each along its own path, and set their witnesses.
+/// `prevouts` are the outputs spent by every input of `tx`, in input order. Each input is checked
+/// against its own prevout and gets its own sighash and control block; all sighashes are computed
+/// before any witness is set. Inputs not listed are left untouched.
+pub fn sign_inputs<C: Signing + Verification>(secp: &Secp256k1<C>, tx: &mut Transaction, prevouts: &[TxOut], spends: &[(usize, TaprootInputSpend)], sighash_type: TapSighashType) -> Result<(), Box<dyn std::error::Error>> {
+    if prevouts.len() != tx.input.len() {
+        return Err(format!("{} prevouts for {} inputs", prevouts.len(), tx.input.len()).into());
+    }
+    let unsigned = tx.clone();
+    let mut witnesses = Vec::with_capacity(spends.len());
+    for (position, (index, spend)) in spends.iter().enumerate() {
+        if spends[..position].iter().any(|(other, _)| other == index) {
+            return Err(format!("input {} is listed twice", index).into());
+        }
+        let spent = prevouts.get(*index).ok_or_else(|| format!("transaction has no input {}", index))?;
+        let witness = match spend {
+            TaprootInputSpend::KeyPath { internal_keypair, merkle_root } => {
+                spend_key_path(secp, internal_keypair, *merkle_root, &unsigned, *index, prevouts, sighash_type)
+            }
+            TaprootInputSpend::ScriptPath { spend_info, leaf_script, keypair } => {
+                if spent.script_pubkey != ScriptBuf::new_v1_p2tr_tweaked(spend_info.output_key()) {
+                    return Err(format!("input {}: taproot tree does not match the spent output", index).into());
+                }
+                spend_script_path(secp, spend_info, leaf_script, keypair, &unsigned, *index, prevouts, sighash_type)
+            }
+        };
+        witnesses.push((*index, witness.map_err(|e| format!("input {}: {}", index, e))?));
+    }
+    for (index, witness) in witnesses {
+        tx.input[index].witness = witness;
+    }
+    Ok(())
+}
+
+fn immediate_script(key: &XOnlyPublicKey) -> ScriptBuf {
+    Builder::new().push_x_only_key(key).push_opcode(OP_CHECKSIG).into_script()
+}
+
+fn recovery_script(height: u32, key: &XOnlyPublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_x_only_key(key)
+        .push_opcode(OP_CHECKSIGVERIFY)
+        .push_int(height as i64)
+        .push_opcode(OP_CLTV)
+        .into_script()
+}
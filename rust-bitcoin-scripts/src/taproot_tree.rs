@@ -0,0 +1,274 @@
+//! Explicit taproot script trees: the tree shape is given by the caller (as in the BIP341 test
+//! vectors) rather than chosen by `TaprootBuilder`'s weight-based construction.
+//!
+//! Leaf and branch hashes, the output key tweak and control blocks are computed directly from the
+//! BIP341 definitions so the result can be checked against the published vectors. Trees are
+//! checked against `TreeLimits` (depth, leaf count, duplicate leaves) and problems come back as a
+//! `TreeError` instead of a builder panic or an opaque `TaprootBuilderError`.
+//!
+//! The hash and merkle-path helpers at the bottom are standalone so external verifiers (and proof
+//! payloads for other chains) can recompute a commitment without going through `TaprootSpendInfo`.
+
+use bitcoin::key::{TapTweak, TweakedPublicKey, XOnlyPublicKey};
+use bitcoin::secp256k1::{Parity, Secp256k1, Verification};
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TapNodeHash, TapTweakHash, TaprootSpendInfo};
+use bitcoin::hashes::Hash;
+use bitcoin::{PublicKey, Script, ScriptBuf};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Consensus limit on the merkle path length of a control block
+pub const MAX_TREE_DEPTH: usize = 128;
+
+/// Guardrails applied when building or validating a tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeLimits {
+    /// Clamped to `MAX_TREE_DEPTH`
+    pub max_depth: usize,
+    pub max_leaves: usize,
+    /// Identical leaves are consensus-valid (each gets its own control block) but usually a mistake
+    pub allow_duplicate_leaves: bool,
+}
+
+impl Default for TreeLimits {
+    fn default() -> Self {
+        Self { max_depth: MAX_TREE_DEPTH, max_leaves: 4096, allow_duplicate_leaves: false }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeError {
+    Empty,
+    DepthExceeded { depth: usize, max: usize },
+    TooManyLeaves { count: usize, max: usize },
+    /// Leaves at `first` and `second` (in `leaves()` order) have the same script and version
+    DuplicateLeaf { first: usize, second: usize, leaf_hash: TapLeafHash },
+    /// The depths given to `from_depths` do not describe a complete binary tree
+    IncompleteTree,
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeError::Empty => write!(f, "script tree has no leaves"),
+            TreeError::DepthExceeded { depth, max } => write!(f, "leaf depth {} exceeds maximum {}", depth, max),
+            TreeError::TooManyLeaves { count, max } => write!(f, "{} leaves exceeds maximum {}", count, max),
+            TreeError::DuplicateLeaf { first, second, leaf_hash } => write!(f, "leaves {} and {} are identical ({})", first, second, leaf_hash),
+            TreeError::IncompleteTree => write!(f, "leaf depths do not form a complete tree"),
+        }
+    }
+}
+
+impl std::error::Error for TreeError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptTree {
+    Leaf { script: ScriptBuf, version: LeafVersion },
+    Branch(Box<ScriptTree>, Box<ScriptTree>),
+}
+
+/// One leaf with its depth and the sibling hashes from the leaf up to the root
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeafInfo {
+    pub script: ScriptBuf,
+    pub version: LeafVersion,
+    pub depth: usize,
+    pub merkle_path: Vec<TapNodeHash>,
+}
+
+impl LeafInfo {
+    pub fn leaf_hash(&self) -> TapLeafHash {
+        tapleaf_hash(&self.script, self.version)
+    }
+}
+
+impl ScriptTree {
+    pub fn leaf(script: ScriptBuf) -> Self {
+        ScriptTree::Leaf { script, version: LeafVersion::TapScript }
+    }
+
+    pub fn branch(left: ScriptTree, right: ScriptTree) -> Self {
+        ScriptTree::Branch(Box::new(left), Box::new(right))
+    }
+
+    /// Build a tree from leaves listed in depth-first order with their depths, the same input
+    /// `TaprootBuilder::add_leaf` takes, and validate it against `limits`
+    pub fn from_depths(leaves: &[(usize, ScriptBuf)], limits: &TreeLimits) -> Result<Self, TreeError> {
+        if leaves.is_empty() {
+            return Err(TreeError::Empty);
+        }
+        if leaves.len() > limits.max_leaves {
+            return Err(TreeError::TooManyLeaves { count: leaves.len(), max: limits.max_leaves });
+        }
+        let max_depth = limits.max_depth.min(MAX_TREE_DEPTH);
+        let mut stack: Vec<(usize, ScriptTree)> = Vec::new();
+        for (depth, script) in leaves {
+            if *depth > max_depth {
+                return Err(TreeError::DepthExceeded { depth: *depth, max: max_depth });
+            }
+            let mut node = (*depth, ScriptTree::leaf(script.clone()));
+            while let Some((top_depth, _)) = stack.last() {
+                if *top_depth != node.0 {
+                    break;
+                }
+                if node.0 == 0 {
+                    return Err(TreeError::IncompleteTree);
+                }
+                let (_, left) = stack.pop().unwrap();
+                node = (node.0 - 1, ScriptTree::branch(left, node.1));
+            }
+            stack.push(node);
+        }
+        match stack.pop() {
+            Some((0, tree)) if stack.is_empty() => {
+                tree.validate(limits)?;
+                Ok(tree)
+            }
+            _ => Err(TreeError::IncompleteTree),
+        }
+    }
+
+    /// Check depth, leaf count and duplicate leaves against `limits`
+    pub fn validate(&self, limits: &TreeLimits) -> Result<(), TreeError> {
+        let leaves = self.leaves();
+        if leaves.len() > limits.max_leaves {
+            return Err(TreeError::TooManyLeaves { count: leaves.len(), max: limits.max_leaves });
+        }
+        let max_depth = limits.max_depth.min(MAX_TREE_DEPTH);
+        if let Some(deepest) = leaves.iter().map(|l| l.depth).max().filter(|d| *d > max_depth) {
+            return Err(TreeError::DepthExceeded { depth: deepest, max: max_depth });
+        }
+        if !limits.allow_duplicate_leaves {
+            let mut seen: HashMap<TapLeafHash, usize> = HashMap::new();
+            for (index, leaf) in leaves.iter().enumerate() {
+                let leaf_hash = leaf.leaf_hash();
+                if let Some(first) = seen.insert(leaf_hash, index) {
+                    return Err(TreeError::DuplicateLeaf { first, second: index, leaf_hash });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// TapLeaf hash for a leaf, TapBranch hash (children sorted) for a branch
+    pub fn node_hash(&self) -> TapNodeHash {
+        match self {
+            ScriptTree::Leaf { script, version } => tapleaf_hash(script, *version).into(),
+            ScriptTree::Branch(left, right) => tapbranch(left.node_hash(), right.node_hash()),
+        }
+    }
+
+    /// Sibling hashes from the first leaf matching `script` and `version` up to the root
+    pub fn merkle_path(&self, script: &Script, version: LeafVersion) -> Option<Vec<TapNodeHash>> {
+        self.leaves().into_iter().find(|l| l.script.as_script() == script && l.version == version).map(|l| l.merkle_path)
+    }
+
+    /// Leaves in left-to-right order
+    pub fn leaves(&self) -> Vec<LeafInfo> {
+        let mut out = Vec::new();
+        self.collect_leaves(0, &mut Vec::new(), &mut out);
+        out
+    }
+
+    fn collect_leaves(&self, depth: usize, siblings: &mut Vec<TapNodeHash>, out: &mut Vec<LeafInfo>) {
+        match self {
+            ScriptTree::Leaf { script, version } => out.push(LeafInfo {
+                script: script.clone(),
+                version: *version,
+                depth,
+                merkle_path: siblings.iter().rev().copied().collect(),
+            }),
+            ScriptTree::Branch(left, right) => {
+                siblings.push(right.node_hash());
+                left.collect_leaves(depth + 1, siblings, out);
+                siblings.pop();
+                siblings.push(left.node_hash());
+                right.collect_leaves(depth + 1, siblings, out);
+                siblings.pop();
+            }
+        }
+    }
+}
+
+/// Output of committing an internal key to an optional script tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeOutput {
+    pub internal_key: XOnlyPublicKey,
+    pub merkle_root: Option<TapNodeHash>,
+    pub tweak: TapTweakHash,
+    pub output_key: TweakedPublicKey,
+    pub output_key_parity: Parity,
+    pub leaves: Vec<LeafInfo>,
+}
+
+impl TreeOutput {
+    pub fn new<C: Verification>(secp: &Secp256k1<C>, internal_key: XOnlyPublicKey, tree: Option<&ScriptTree>) -> Self {
+        let merkle_root = tree.map(ScriptTree::node_hash);
+        let (output_key, output_key_parity) = internal_key.tap_tweak(secp, merkle_root);
+        Self {
+            internal_key,
+            merkle_root,
+            tweak: TapTweakHash::from_key_and_tweak(internal_key, merkle_root),
+            output_key,
+            output_key_parity,
+            leaves: tree.map(ScriptTree::leaves).unwrap_or_default(),
+        }
+    }
+
+    /// Like `new`, but rejects trees outside `limits`
+    pub fn checked<C: Verification>(secp: &Secp256k1<C>, internal_key: XOnlyPublicKey, tree: &ScriptTree, limits: &TreeLimits) -> Result<Self, TreeError> {
+        tree.validate(limits)?;
+        Ok(Self::new(secp, internal_key, Some(tree)))
+    }
+
+    /// `OP_1 <output key>`
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        ScriptBuf::new_v1_p2tr_tweaked(self.output_key)
+    }
+
+    /// Control block for the leaf at `index` (in `leaves` order)
+    pub fn control_block(&self, index: usize) -> Result<ControlBlock, Box<dyn std::error::Error>> {
+        let leaf = self.leaves.get(index).ok_or("leaf index out of range")?;
+        let mut bytes = vec![leaf.version.to_consensus() | self.output_key_parity.to_u8()];
+        bytes.extend_from_slice(&self.internal_key.serialize());
+        for node in &leaf.merkle_path {
+            bytes.extend_from_slice(&node.to_byte_array());
+        }
+        Ok(ControlBlock::decode(&bytes)?)
+    }
+}
+
+/// TapLeaf tagged hash of the leaf version and the compact-size prefixed script
+pub fn tapleaf_hash(script: &Script, version: LeafVersion) -> TapLeafHash {
+    TapLeafHash::from_script(script, version)
+}
+
+/// TapBranch tagged hash of two nodes; the children are sorted first, so argument order is irrelevant
+pub fn tapbranch(a: TapNodeHash, b: TapNodeHash) -> TapNodeHash {
+    TapNodeHash::from_node_hashes(a, b)
+}
+
+/// Root reached by folding `merkle_path` (leaf to root) onto `leaf_hash`
+pub fn merkle_root(leaf_hash: TapLeafHash, merkle_path: &[TapNodeHash]) -> TapNodeHash {
+    merkle_path.iter().fold(TapNodeHash::from(leaf_hash), |node, sibling| tapbranch(node, *sibling))
+}
+
+/// Whether `merkle_path` commits `leaf_hash` to `root`
+pub fn verify_merkle_path(root: TapNodeHash, leaf_hash: TapLeafHash, merkle_path: &[TapNodeHash]) -> bool {
+    merkle_root(leaf_hash, merkle_path) == root
+}
+
+/// Merkle path of a leaf in a tree built by `TaprootBuilder` (or a miniscript descriptor)
+pub fn spend_info_merkle_path(spend_info: &TaprootSpendInfo, script: &Script, version: LeafVersion) -> Option<Vec<TapNodeHash>> {
+    spend_info.control_block(&(script.to_owned(), version)).map(|cb| cb.merkle_branch.as_inner().to_vec())
+}
+
+/// Path hashes concatenated in leaf-to-root order, 32 bytes each
+pub fn encode_merkle_path(merkle_path: &[TapNodeHash]) -> Vec<u8> {
+    merkle_path.iter().flat_map(|node| node.to_byte_array()).collect()
+}
+
+/// The x-only key a compressed key becomes in taproot
+pub(crate) fn x_only(key: &PublicKey) -> XOnlyPublicKey {
+    key.inner.x_only_public_key().0
+}
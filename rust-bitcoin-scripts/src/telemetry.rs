@@ -0,0 +1,374 @@
+//! Process-wide traces and metrics of signing rounds, RPC calls and block scanning, exported to
+//! an OpenTelemetry collector over OTLP/HTTP.
+//!
+//! Nothing is recorded until `enable` (or `enable_from_env`). From then on:
+//! - every RPC that reaches the node is an `rpc <method>` span and a sample of the
+//!   `wrapyield.rpc.duration` histogram, by `rpc.method` and `outcome`;
+//! - a signing round that reaches quorum or stalls is a `signing_round` span covering its whole
+//!   window, a sample of `wrapyield.signing_round.duration` and a count in
+//!   `wrapyield.signing_rounds`, by `state`;
+//! - every scanned block is a `scan_block` span and a sample of
+//!   `wrapyield.scanner.block_duration`, adds its events to `wrapyield.scanner.events` by `kind`
+//!   and sets the `wrapyield.scanner.height` gauge.
+//!
+//! `OtlpExporter` posts the spans recorded since the last export to `<endpoint>/v1/traces` and
+//! the cumulative metrics to `<endpoint>/v1/metrics`, in the JSON encoding of OTLP that
+//! collectors accept next to protobuf. Histograms are durations in seconds with the
+//! `LATENCY_BOUNDS` buckets. At most `MAX_BUFFERED_SPANS` spans wait for export; the oldest are
+//! dropped first.
+
+use crate::cancel::CancellationToken;
+use log::warn;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Bucket bounds of every histogram, in seconds
+pub const LATENCY_BOUNDS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 60.0];
+pub const MAX_BUFFERED_SPANS: usize = 10_000;
+pub const DEFAULT_SERVICE_NAME: &str = "wrapyield";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Start of the cumulative metrics, in Unix nanoseconds
+static STARTED: AtomicU64 = AtomicU64::new(0);
+static RECORDED: Mutex<Recorded> = Mutex::new(Recorded::new());
+
+/// Metric name and sorted attributes
+type SeriesKey = (String, Vec<(String, String)>);
+
+struct Recorded {
+    spans: VecDeque<SpanRecord>,
+    counters: BTreeMap<SeriesKey, u64>,
+    gauges: BTreeMap<SeriesKey, f64>,
+    histograms: BTreeMap<SeriesKey, Histogram>,
+}
+
+impl Recorded {
+    const fn new() -> Self {
+        Self { spans: VecDeque::new(), counters: BTreeMap::new(), gauges: BTreeMap::new(), histograms: BTreeMap::new() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Histogram {
+    /// One more than `LATENCY_BOUNDS`, the last for everything above
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanRecord {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub name: String,
+    pub start_unix_nanos: u64,
+    pub end_unix_nanos: u64,
+    pub attributes: Vec<(String, String)>,
+    /// Set if the operation failed
+    pub error: Option<String>,
+}
+
+pub fn enable() {
+    let _ = STARTED.compare_exchange(0, unix_nanos(SystemTime::now()), Ordering::SeqCst, Ordering::SeqCst);
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn disable() {
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Enable recording and return an exporter when `OTEL_EXPORTER_OTLP_ENDPOINT` is set (see
+/// `OtlpConfig::from_env`)
+pub fn enable_from_env() -> Result<Option<OtlpExporter>, Box<dyn std::error::Error>> {
+    let Some(config) = OtlpConfig::from_env()? else { return Ok(None) };
+    enable();
+    Ok(Some(OtlpExporter::new(config)))
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+}
+
+fn series(name: &str, attributes: &[(&str, String)]) -> SeriesKey {
+    let mut attributes: Vec<(String, String)> = attributes.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+    attributes.sort();
+    (name.to_string(), attributes)
+}
+
+fn recorded() -> std::sync::MutexGuard<'static, Recorded> {
+    RECORDED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Add `value` to the counter `name`
+pub fn add(name: &str, value: u64, attributes: &[(&str, String)]) {
+    if is_enabled() {
+        *recorded().counters.entry(series(name, attributes)).or_default() += value;
+    }
+}
+
+/// Set the gauge `name` to `value`
+pub fn set_gauge(name: &str, value: f64, attributes: &[(&str, String)]) {
+    if is_enabled() {
+        recorded().gauges.insert(series(name, attributes), value);
+    }
+}
+
+/// Add a sample of `duration` to the histogram `name`
+pub fn observe(name: &str, duration: Duration, attributes: &[(&str, String)]) {
+    if !is_enabled() {
+        return;
+    }
+    let secs = duration.as_secs_f64();
+    let mut recorded = recorded();
+    let histogram = recorded.histograms.entry(series(name, attributes))
+        .or_insert_with(|| Histogram { buckets: vec![0; LATENCY_BOUNDS.len() + 1], count: 0, sum: 0.0 });
+    histogram.buckets[LATENCY_BOUNDS.iter().position(|bound| secs <= *bound).unwrap_or(LATENCY_BOUNDS.len())] += 1;
+    histogram.count += 1;
+    histogram.sum += secs;
+}
+
+/// Record a span that ended now after `duration`, in a trace of its own
+pub fn record_span(name: &str, duration: Duration, attributes: &[(&str, String)], error: Option<String>) {
+    if !is_enabled() {
+        return;
+    }
+    let end = SystemTime::now();
+    let span = SpanRecord {
+        trace_id: rand::random(),
+        span_id: rand::random(),
+        name: name.to_string(),
+        start_unix_nanos: unix_nanos(end.checked_sub(duration).unwrap_or(UNIX_EPOCH)),
+        end_unix_nanos: unix_nanos(end),
+        attributes: series(name, attributes).1,
+        error,
+    };
+    let mut recorded = recorded();
+    if recorded.spans.len() >= MAX_BUFFERED_SPANS {
+        recorded.spans.pop_front();
+    }
+    recorded.spans.push_back(span);
+}
+
+/// A span `name` plus a sample of `histogram`, both carrying `attributes` and the `outcome`
+pub fn record_timed(name: &str, histogram: &str, duration: Duration, attributes: &[(&str, String)], error: Option<String>) {
+    if !is_enabled() {
+        return;
+    }
+    let mut with_outcome = attributes.to_vec();
+    with_outcome.push(("outcome", if error.is_some() { "error" } else { "ok" }.to_string()));
+    observe(histogram, duration, &with_outcome);
+    record_span(name, duration, &with_outcome, error);
+}
+
+/// Spans recorded since the last call
+pub fn take_spans() -> Vec<SpanRecord> {
+    std::mem::take(&mut recorded().spans).into()
+}
+
+/// Put back spans an export failed to deliver, ahead of newer ones and within the buffer limit
+fn requeue_spans(spans: Vec<SpanRecord>) {
+    let mut recorded = recorded();
+    let mut spans = VecDeque::from(spans);
+    spans.append(&mut recorded.spans);
+    let excess = spans.len().saturating_sub(MAX_BUFFERED_SPANS);
+    spans.drain(..excess);
+    recorded.spans = spans;
+}
+
+/// Forget every span and metric
+pub fn reset() {
+    *recorded() = Recorded::new();
+}
+
+fn attributes_json(attributes: &[(String, String)]) -> Vec<Value> {
+    attributes.iter().map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } })).collect()
+}
+
+fn resource_json(service_name: &str) -> Value {
+    json!({ "attributes": attributes_json(&[("service.name".to_string(), service_name.to_string())]) })
+}
+
+fn scope_json() -> Value {
+    json!({ "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") })
+}
+
+/// OTLP `ExportTraceServiceRequest` for `spans`
+pub fn traces_json(service_name: &str, spans: &[SpanRecord]) -> Value {
+    let spans: Vec<Value> = spans.iter().map(|span| {
+        // RPCs are calls to another service, everything else happens in-process
+        let kind = if span.name.starts_with("rpc ") { 3 } else { 1 };
+        let status = match &span.error {
+            Some(message) => json!({ "code": 2, "message": message }),
+            None => json!({ "code": 1 }),
+        };
+        json!({
+            "traceId": hex::encode(span.trace_id),
+            "spanId": hex::encode(span.span_id),
+            "name": span.name,
+            "kind": kind,
+            "startTimeUnixNano": span.start_unix_nanos.to_string(),
+            "endTimeUnixNano": span.end_unix_nanos.to_string(),
+            "attributes": attributes_json(&span.attributes),
+            "status": status,
+        })
+    }).collect();
+    json!({ "resourceSpans": [{ "resource": resource_json(service_name), "scopeSpans": [{ "scope": scope_json(), "spans": spans }] }] })
+}
+
+/// OTLP `ExportMetricsServiceRequest` with every metric recorded so far, cumulative since `enable`
+pub fn metrics_json(service_name: &str) -> Value {
+    let start = STARTED.load(Ordering::SeqCst).to_string();
+    let now = unix_nanos(SystemTime::now()).to_string();
+    let recorded = recorded();
+    let mut metrics: BTreeMap<String, Value> = BTreeMap::new();
+    let mut push = |name: &str, kind: &str, point: Value| {
+        let metric = metrics.entry(name.to_string()).or_insert_with(|| {
+            let mut data = match kind {
+                "sum" => json!({ "aggregationTemporality": 2, "isMonotonic": true }),
+                "histogram" => json!({ "aggregationTemporality": 2 }),
+                _ => json!({}),
+            };
+            data["dataPoints"] = json!([]);
+            let mut metric = json!({ "name": name });
+            metric[kind] = data;
+            if kind == "histogram" {
+                metric["unit"] = json!("s");
+            }
+            metric
+        });
+        metric[kind]["dataPoints"].as_array_mut().unwrap().push(point);
+    };
+    for ((name, attributes), value) in &recorded.counters {
+        push(name, "sum", json!({
+            "attributes": attributes_json(attributes), "startTimeUnixNano": start, "timeUnixNano": now, "asInt": value.to_string(),
+        }));
+    }
+    for ((name, attributes), value) in &recorded.gauges {
+        push(name, "gauge", json!({ "attributes": attributes_json(attributes), "timeUnixNano": now, "asDouble": value }));
+    }
+    for ((name, attributes), histogram) in &recorded.histograms {
+        push(name, "histogram", json!({
+            "attributes": attributes_json(attributes),
+            "startTimeUnixNano": start,
+            "timeUnixNano": now,
+            "count": histogram.count.to_string(),
+            "sum": histogram.sum,
+            "bucketCounts": histogram.buckets.iter().map(u64::to_string).collect::<Vec<_>>(),
+            "explicitBounds": LATENCY_BOUNDS,
+        }));
+    }
+    let metrics: Vec<Value> = metrics.into_values().collect();
+    json!({ "resourceMetrics": [{ "resource": resource_json(service_name), "scopeMetrics": [{ "scope": scope_json(), "metrics": metrics }] }] })
+}
+
+/// Where to send telemetry
+#[derive(Debug, Clone, PartialEq)]
+pub struct OtlpConfig {
+    /// Collector base URL, e.g. `http://localhost:4318`; `/v1/traces` and `/v1/metrics` are appended
+    pub endpoint: String,
+    pub service_name: String,
+    /// Extra request headers, e.g. an API key of a hosted collector
+    pub headers: Vec<(String, String)>,
+}
+
+impl OtlpConfig {
+    pub fn new(endpoint: &str) -> Self {
+        Self { endpoint: endpoint.trim_end_matches('/').to_string(), service_name: DEFAULT_SERVICE_NAME.to_string(), headers: Vec::new() }
+    }
+
+    /// From the standard `OTEL_EXPORTER_OTLP_ENDPOINT`, `OTEL_EXPORTER_OTLP_HEADERS` and
+    /// `OTEL_SERVICE_NAME` variables; `None` without an endpoint
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// `from_env` with the variables looked up by `var`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let Some(endpoint) = var("OTEL_EXPORTER_OTLP_ENDPOINT").filter(|e| !e.trim().is_empty()) else { return Ok(None) };
+        let mut config = Self::new(endpoint.trim());
+        if let Some(name) = var("OTEL_SERVICE_NAME").filter(|n| !n.trim().is_empty()) {
+            config.service_name = name.trim().to_string();
+        }
+        // `key1=value1,key2=value2`
+        for header in var("OTEL_EXPORTER_OTLP_HEADERS").unwrap_or_default().split(',').filter(|h| !h.trim().is_empty()) {
+            let (key, value) = header.split_once('=').ok_or_else(|| format!("OTEL_EXPORTER_OTLP_HEADERS entry {:?} is not key=value", header))?;
+            config.headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+        Ok(Some(config))
+    }
+}
+
+/// What one `OtlpExporter::export` delivered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportSummary {
+    pub spans: usize,
+    pub metrics: usize,
+}
+
+pub struct OtlpExporter {
+    pub config: OtlpConfig,
+    client: reqwest::Client,
+}
+
+impl OtlpExporter {
+    pub fn new(config: OtlpConfig) -> Self {
+        Self { config, client: reqwest::Client::new() }
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> Result<(), Box<dyn std::error::Error>> {
+        let mut request = self.client.post(format!("{}{}", self.config.endpoint, path))
+            .header("Content-Type", "application/json")
+            .json(body);
+        for (key, value) in &self.config.headers {
+            request = request.header(key, value);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(format!("OTLP export to {} failed: HTTP {}", path, response.status()).into());
+        }
+        Ok(())
+    }
+
+    /// Send the spans recorded since the last export and the current metrics. Spans the
+    /// collector did not take are kept for the next export.
+    pub async fn export(&self) -> Result<ExportSummary, Box<dyn std::error::Error>> {
+        let spans = take_spans();
+        let exported = spans.len();
+        if !spans.is_empty() {
+            if let Err(e) = self.post("/v1/traces", &traces_json(&self.config.service_name, &spans)).await {
+                requeue_spans(spans);
+                return Err(e);
+            }
+        }
+        let metrics = metrics_json(&self.config.service_name);
+        let count = metrics["resourceMetrics"][0]["scopeMetrics"][0]["metrics"].as_array().map_or(0, Vec::len);
+        if count > 0 {
+            self.post("/v1/metrics", &metrics).await?;
+        }
+        Ok(ExportSummary { spans: exported, metrics: count })
+    }
+
+    /// Export every `interval` until `token` is cancelled, then once more. Failed exports are
+    /// logged and retried on the next tick.
+    pub async fn run(&self, interval: Duration, token: &CancellationToken) {
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = tokio::time::sleep(interval) => {}
+            }
+            if let Err(e) = self.export().await {
+                warn!("telemetry export failed: {}", e);
+            }
+        }
+        if let Err(e) = self.export().await {
+            warn!("final telemetry export failed: {}", e);
+        }
+    }
+}
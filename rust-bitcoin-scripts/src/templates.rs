@@ -0,0 +1,105 @@
+//! Versioned vault templates and upgrade negotiation between counterparties.
+//!
+//! Each template version fixes the shape of the vault descriptor. When both sides support a newer
+//! version than the one in use, `VersionedVault::upgrade` builds the new descriptor and keeps the
+//! previous ones, so outputs already locked under an older template stay watchable and spendable.
+//! Every key is checked against `params.key_policy` before a descriptor is built.
+
+use crate::nums::{unspendable_internal_key, NumsProof};
+use crate::weak_keys::KeyPolicy;
+use miniscript::bitcoin::PublicKey;
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+/// Template versions this build can construct, oldest first
+pub const SUPPORTED_VERSIONS: &[u32] = &[1, 2];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateParams {
+    pub internal_key: PublicKey,
+    pub federation: Vec<PublicKey>,
+    pub threshold: usize,
+    /// Relative delay (blocks) before the federation leaf is spendable
+    pub federation_csv: u16,
+    /// Used from version 2: key and delay of the extra recovery leaf
+    pub recovery_key: Option<PublicKey>,
+    pub recovery_csv: u16,
+    pub key_policy: KeyPolicy,
+}
+
+impl TemplateParams {
+    /// Disable the key path: the internal key becomes the NUMS point of `tag`. The proof goes
+    /// into the manifest (`VaultManifest::with_nums_proof`) so auditors can check it.
+    pub fn without_key_path(self, tag: &str) -> Result<(Self, NumsProof), Box<dyn std::error::Error>> {
+        let (internal_key, proof) = unspendable_internal_key(tag)?;
+        Ok((Self { internal_key, ..self }, proof))
+    }
+}
+
+/// Vault descriptor for template `version`:
+/// - v1: `tr(internal, and_v(v:multi_a(k, federation), older(federation_csv)))`
+/// - v2: v1 plus a recovery leaf `and_v(v:pk(recovery), older(recovery_csv))`
+pub fn build(version: u32, params: &TemplateParams) -> Result<Descriptor<PublicKey>, Box<dyn std::error::Error>> {
+    let federation: Vec<String> = params.federation.iter().map(|k| k.to_string()).collect();
+    let federation_leaf = format!("and_v(v:multi_a({},{}),older({}))", params.threshold, federation.join(","), params.federation_csv);
+    let tree = match version {
+        1 => federation_leaf,
+        2 => {
+            let recovery = params.recovery_key.ok_or("template v2 requires a recovery key")?;
+            if params.recovery_csv <= params.federation_csv {
+                return Err("recovery delay must be longer than the federation delay".into());
+            }
+            format!("{{{},and_v(v:pk({}),older({}))}}", federation_leaf, recovery, params.recovery_csv)
+        }
+        v => return Err(format!("unsupported template version {}", v).into()),
+    };
+    let descriptor = Descriptor::from_str(&format!("tr({},{})", params.internal_key, tree))?;
+    params.key_policy.check_descriptor(&descriptor)?;
+    Ok(descriptor)
+}
+
+/// Highest template version both sides support
+pub fn negotiate(ours: &[u32], theirs: &[u32]) -> Option<u32> {
+    ours.iter().filter(|v| theirs.contains(v)).max().copied()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionedVault {
+    pub version: u32,
+    pub descriptor: Descriptor<PublicKey>,
+    /// Earlier (version, descriptor) pairs, still valid for outputs created under them
+    pub previous: Vec<(u32, Descriptor<PublicKey>)>,
+}
+
+impl VersionedVault {
+    pub fn new(version: u32, params: &TemplateParams) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self { version, descriptor: build(version, params)?, previous: Vec::new() })
+    }
+
+    /// Move to the highest version both sides support, if it is newer than the current one.
+    /// Returns the new version when an upgrade happened.
+    pub fn upgrade(&mut self, params: &TemplateParams, counterparty_versions: &[u32]) -> Result<Option<u32>, Box<dyn std::error::Error>> {
+        match negotiate(SUPPORTED_VERSIONS, counterparty_versions) {
+            Some(version) if version > self.version => {
+                let descriptor = build(version, params)?;
+                let old = std::mem::replace(&mut self.descriptor, descriptor);
+                self.previous.push((self.version, old));
+                self.version = version;
+                Ok(Some(version))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Current and previous descriptors, newest first
+    pub fn all_descriptors(&self) -> Vec<(u32, &Descriptor<PublicKey>)> {
+        std::iter::once((self.version, &self.descriptor))
+            .chain(self.previous.iter().rev().map(|(v, d)| (*v, d)))
+            .collect()
+    }
+
+    /// Template version and descriptor a given scriptPubKey was created under
+    pub fn descriptor_for_script(&self, script_pubkey: &bitcoin::Script) -> Option<(u32, &Descriptor<PublicKey>)> {
+        self.all_descriptors().into_iter().find(|(_, d)| d.script_pubkey().as_script() == script_pubkey)
+    }
+}
@@ -0,0 +1,184 @@
+//! Multi-vault tenancy: many independent vault descriptors served by one watcher.
+//!
+//! Every block is scanned once against the union of all vaults' scripts; each event is routed to
+//! the vault owning the script (deposits) or the spent outpoint (spends). Vaults keep their own
+//! configuration, event subscribers and metrics, and may not share scripts with each other.
+
+use crate::backend::ChainBackend;
+use crate::scanner::{BlockScanner, ScanEvent};
+use miniscript::descriptor::DescriptorPublicKey;
+use miniscript::Descriptor;
+use bitcoin::{OutPoint, ScriptBuf};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeePolicy {
+    pub conf_target: u16,
+    pub max_fee_rate_sat_vb: f64,
+}
+
+impl Default for FeePolicy {
+    fn default() -> Self {
+        Self { conf_target: 6, max_fee_rate_sat_vb: 100.0 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VaultConfig {
+    pub id: String,
+    pub descriptor: Descriptor<DescriptorPublicKey>,
+    /// Confirmations before a deposit counts as confirmed
+    pub min_confirmations: u64,
+    pub fee_policy: FeePolicy,
+    pub signers: Vec<String>,
+    pub quorum: usize,
+    /// Derivation indices watched for ranged descriptors
+    pub lookahead: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VaultMetrics {
+    pub deposits: u64,
+    pub spends: u64,
+    pub received_sats: u64,
+    pub spent_sats: u64,
+    pub last_event_height: u64,
+}
+
+struct Tenant {
+    config: VaultConfig,
+    metrics: VaultMetrics,
+    subscribers: Vec<mpsc::UnboundedSender<ScanEvent>>,
+}
+
+#[derive(Default)]
+pub struct VaultRegistry {
+    tenants: BTreeMap<String, Tenant>,
+    script_owner: HashMap<ScriptBuf, String>,
+    outpoint_owner: HashMap<OutPoint, String>,
+    scanner: BlockScanner,
+}
+
+impl VaultRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a vault; fails on a duplicate id or a script already owned by another vault
+    pub fn add(&mut self, config: VaultConfig) -> Result<(), Box<dyn std::error::Error>> {
+        if self.tenants.contains_key(&config.id) {
+            return Err(format!("vault {} already registered", config.id).into());
+        }
+        let range = if config.descriptor.has_wildcard() { 0..config.lookahead } else { 0..1 };
+        let mut scripts = Vec::new();
+        for index in range {
+            let script = config.descriptor.at_derivation_index(index)?.script_pubkey();
+            if let Some(owner) = self.script_owner.get(&script) {
+                return Err(format!("vault {} shares script {} with vault {}", config.id, script, owner).into());
+            }
+            scripts.push(script);
+        }
+        for script in scripts {
+            self.scanner.watch_script(script.clone());
+            self.script_owner.insert(script, config.id.clone());
+        }
+        self.tenants.insert(config.id.clone(), Tenant { config, metrics: VaultMetrics::default(), subscribers: Vec::new() });
+        Ok(())
+    }
+
+    /// Stop watching a vault and drop its subscribers
+    pub fn remove(&mut self, id: &str) -> Option<VaultConfig> {
+        let tenant = self.tenants.remove(id)?;
+        self.script_owner.retain(|script, owner| {
+            let keep = owner != id;
+            if !keep {
+                self.scanner.watched_scripts.remove(script);
+            }
+            keep
+        });
+        self.outpoint_owner.retain(|outpoint, owner| {
+            let keep = owner != id;
+            if !keep {
+                self.scanner.tracked.remove(outpoint);
+            }
+            keep
+        });
+        Some(tenant.config)
+    }
+
+    pub fn config(&self, id: &str) -> Option<&VaultConfig> {
+        self.tenants.get(id).map(|t| &t.config)
+    }
+
+    pub fn metrics(&self, id: &str) -> Option<&VaultMetrics> {
+        self.tenants.get(id).map(|t| &t.metrics)
+    }
+
+    pub fn vault_ids(&self) -> Vec<String> {
+        self.tenants.keys().cloned().collect()
+    }
+
+    /// Event stream for one vault only
+    pub fn subscribe(&mut self, id: &str) -> Result<mpsc::UnboundedReceiver<ScanEvent>, Box<dyn std::error::Error>> {
+        let tenant = self.tenants.get_mut(id).ok_or_else(|| format!("unknown vault {}", id))?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        tenant.subscribers.push(tx);
+        Ok(rx)
+    }
+
+    /// Whether an event at `event_height` meets the vault's confirmation policy at `tip_height`
+    pub fn is_confirmed(&self, id: &str, event_height: u64, tip_height: u64) -> bool {
+        self.tenants.get(id).map_or(false, |t| tip_height + 1 >= event_height + t.config.min_confirmations)
+    }
+
+    pub async fn scan_range(&mut self, backend: &impl ChainBackend, from: u64, to: u64) -> Result<Vec<(String, ScanEvent)>, Box<dyn std::error::Error>> {
+        let mut routed = Vec::new();
+        for height in from..=to {
+            let hash = backend.block_hash(height).await?;
+            let events = self.scanner.scan_block(backend, &hash.to_string()).await?;
+            routed.extend(self.dispatch(events));
+        }
+        Ok(routed)
+    }
+
+    /// Scan one block as `ChainBackend::block` returns it and route its events
+    pub async fn scan_block_json(&mut self, backend: &impl ChainBackend, block: &Value) -> Result<Vec<(String, ScanEvent)>, Box<dyn std::error::Error>> {
+        let events = self.scanner.scan_block_json(backend, block).await?;
+        Ok(self.dispatch(events))
+    }
+
+    fn dispatch(&mut self, events: Vec<ScanEvent>) -> Vec<(String, ScanEvent)> {
+        let mut routed = Vec::new();
+        for event in events {
+            let owner = match &event {
+                ScanEvent::Deposit { outpoint, script_pubkey, .. } => {
+                    let owner = self.script_owner.get(script_pubkey).cloned();
+                    if let Some(id) = &owner {
+                        self.outpoint_owner.insert(*outpoint, id.clone());
+                    }
+                    owner
+                }
+                ScanEvent::Spend { outpoint, .. } => self.outpoint_owner.remove(outpoint),
+            };
+            let Some(id) = owner else { continue };
+            let Some(tenant) = self.tenants.get_mut(&id) else { continue };
+            match &event {
+                ScanEvent::Deposit { value_sats, height, .. } => {
+                    tenant.metrics.deposits += 1;
+                    tenant.metrics.received_sats += value_sats;
+                    tenant.metrics.last_event_height = *height;
+                }
+                ScanEvent::Spend { value_sats, height, .. } => {
+                    tenant.metrics.spends += 1;
+                    tenant.metrics.spent_sats += value_sats;
+                    tenant.metrics.last_event_height = *height;
+                }
+            }
+            tenant.subscribers.retain(|s| s.send(event.clone()).is_ok());
+            routed.push((id, event));
+        }
+        routed
+    }
+}
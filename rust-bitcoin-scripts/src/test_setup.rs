@@ -1,20 +1,474 @@
 use serde_json::{json, Value};
+use crate::amount;
+use crate::read_only;
+use crate::telemetry;
+use crate::rpc_types::{BlockchainInfo, EstimateSmartFeeResult, GetRawTransactionResult, ListUnspentEntry, MempoolEntry, MempoolInfo, ScanTxOutSetResult, SignRawTransactionResult, TestMempoolAcceptResult, Utxo, WalletInfo};
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::{Amount, BlockHash, Transaction, Txid};
+use miniscript::Descriptor;
+use serde::de::DeserializeOwned;
 use base64::Engine;
 use miniscript::bitcoin::{PublicKey, PrivateKey, Network, secp256k1};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+/// Connection pool and keep-alive tuning for the underlying HTTP client.
+/// Bitcoin Core's RPC server is HTTP/1.1 only; requests are never pipelined (hyper does not
+/// support it), so concurrency comes from the number of pooled connections.
+#[derive(Debug, Clone)]
+pub struct RpcPoolConfig {
+    /// Max idle connections kept per host; should roughly match Core's `-rpcthreads`
+    pub max_idle_per_host: usize,
+    pub idle_timeout: Option<Duration>,
+    pub tcp_keepalive: Option<Duration>,
+    pub tcp_nodelay: bool,
+    pub http1_only: bool,
+}
+
+impl Default for RpcPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: 4,
+            idle_timeout: Some(Duration::from_secs(90)),
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            tcp_nodelay: true,
+            http1_only: true,
+        }
+    }
+}
+
+impl RpcPoolConfig {
+    pub fn build_client(&self) -> Result<reqwest::Client, reqwest::Error> {
+        let mut builder = reqwest::Client::builder()
+            .pool_max_idle_per_host(self.max_idle_per_host)
+            .pool_idle_timeout(self.idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive)
+            .tcp_nodelay(self.tcp_nodelay);
+        if self.http1_only {
+            builder = builder.http1_only();
+        }
+        builder.build()
+    }
+}
+
+/// Retries and timeouts of RPC calls. Only transient failures are retried (see
+/// `RpcError::is_retriable`); when it is unclear whether the node ran a call (a timeout or a
+/// dropped connection), calls that move coins or mine are not retried, so they never run twice.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts per call, the first included; 1 disables retries
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for each further one up to `max_backoff`
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Per attempt; `None` waits as long as the HTTP client does
+    pub timeout: Option<Duration>,
+    /// Per attempt of calls that may legitimately take minutes (UTXO set scans, rescans,
+    /// loading wallets)
+    pub slow_timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(8),
+            timeout: Some(Duration::from_secs(60)),
+            slow_timeout: Some(Duration::from_secs(900)),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// No retries and no timeout beyond the HTTP client's
+    pub fn none() -> Self {
+        Self { max_attempts: 1, timeout: None, slow_timeout: None, ..Self::default() }
+    }
+
+    /// Wait before retry number `retry` (1 for the first)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff.saturating_mul(1u32.checked_shl(retry.saturating_sub(1)).unwrap_or(u32::MAX)).min(self.max_backoff)
+    }
+
+    /// Timeout of an attempt running `methods` (several for a batch)
+    pub fn timeout_for(&self, methods: &[&str]) -> Option<Duration> {
+        if methods.iter().any(|m| SLOW_METHODS.contains(m)) {
+            self.slow_timeout
+        } else {
+            self.timeout
+        }
+    }
+}
+
+const SLOW_METHODS: &[&str] = &["scantxoutset", "rescanblockchain", "importdescriptors", "loadwallet", "createwallet", "gettxoutsetinfo"];
+/// Calls that must not run twice when it is unclear whether the first attempt reached the node
+const NON_IDEMPOTENT_METHODS: &[&str] = &["sendtoaddress", "sendmany", "send", "sendall", "bumpfee", "psbtbumpfee", "generatetoaddress", "generatetodescriptor", "generateblock"];
+
+/// Bitcoin Core RPC error codes (`src/rpc/protocol.h`)
+pub const RPC_WALLET_ERROR: i64 = -4;
+pub const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+pub const RPC_INVALID_PARAMETER: i64 = -8;
+pub const RPC_CLIENT_NOT_CONNECTED: i64 = -9;
+pub const RPC_CLIENT_IN_INITIAL_DOWNLOAD: i64 = -10;
+pub const RPC_WALLET_INVALID_LABEL_NAME: i64 = -11;
+pub const RPC_WALLET_NOT_FOUND: i64 = -18;
+pub const RPC_VERIFY_ERROR: i64 = -25;
+pub const RPC_VERIFY_REJECTED: i64 = -26;
+pub const RPC_VERIFY_ALREADY_IN_CHAIN: i64 = -27;
+pub const RPC_IN_WARMUP: i64 = -28;
+pub const RPC_WALLET_ALREADY_LOADED: i64 = -35;
+
+/// Failures callers branch on. Core reuses codes (every mempool rejection is
+/// `RPC_VERIFY_REJECTED`), so some are told apart by the reject reason in the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreError {
+    /// `createwallet` of a name that already has a wallet on disk
+    WalletExists,
+    /// `loadwallet` of a wallet that is loaded
+    WalletAlreadyLoaded,
+    /// The wallet named in the URL is not loaded
+    WalletNotFound,
+    /// The absolute or relative (BIP68) lock time is not reached yet
+    NonFinal,
+    /// An input is unknown or already spent
+    MissingInputs,
+    /// Conflicts with a mempool transaction it cannot replace
+    MempoolConflict,
+    /// The node already has the transaction, in its mempool or chain
+    AlreadyKnown,
+    /// No such transaction, block, address or label
+    NotFound,
+    /// Another `scantxoutset` is running
+    ScanInProgress,
+}
+
+impl CoreError {
+    /// The well-known failure behind `error`, if it is one
+    pub fn of(error: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        if let Some(rejection) = error.downcast_ref::<MempoolRejection>() {
+            return rejection.kind;
+        }
+        error.downcast_ref::<RpcError>().and_then(RpcError::core_error)
+    }
+
+    /// A mempool reject reason (`testmempoolaccept`'s `reject-reason`, or the message of a
+    /// rejected `sendrawtransaction`)
+    pub fn from_reject_reason(reason: &str) -> Option<Self> {
+        if reason.starts_with("non-final") || reason.starts_with("non-BIP68-final") {
+            Some(CoreError::NonFinal)
+        } else if reason.starts_with("txn-mempool-conflict") {
+            Some(CoreError::MempoolConflict)
+        } else if reason.starts_with("missing-inputs") || reason.contains("missingorspent") || reason.starts_with("Missing inputs") {
+            Some(CoreError::MissingInputs)
+        } else if reason.starts_with("txn-already-in-mempool") || reason.starts_with("txn-already-known") {
+            Some(CoreError::AlreadyKnown)
+        } else {
+            None
+        }
+    }
+}
+
+/// `testmempoolaccept` refused a transaction, so `BitcoinRPC::test_and_send` did not broadcast it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MempoolRejection {
+    pub txid: Txid,
+    /// Core's reject reason, e.g. `non-BIP68-final`
+    pub reason: String,
+    /// The reason, if it is a well-known one
+    pub kind: Option<CoreError>,
+}
+
+impl std::fmt::Display for MempoolRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "mempool rejects {}: {}", self.txid, self.reason)
+    }
+}
+
+impl std::error::Error for MempoolRejection {}
+
+/// A call that failed at the node or never got an answer, as opposed to a malformed reply
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcError {
+    /// The node answered with a JSON-RPC error
+    Node { code: i64, message: String },
+    /// The node's HTTP work queue is full (HTTP 503); the call was not run
+    Busy,
+    /// No answer within the policy's timeout
+    Timeout(Duration),
+}
+
+impl RpcError {
+    pub(crate) fn from_json(error: &Value) -> Self {
+        RpcError::Node {
+            code: error["code"].as_i64().unwrap_or_default(),
+            message: error["message"].as_str().map(str::to_string).unwrap_or_else(|| error.to_string()),
+        }
+    }
+
+    /// Transient: the node is starting, syncing, busy or loading a wallet, and the same call is
+    /// expected to succeed later. Rejections such as `RPC_VERIFY_REJECTED` are final.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            RpcError::Node { code, message } => match *code {
+                RPC_IN_WARMUP | RPC_CLIENT_IN_INITIAL_DOWNLOAD | RPC_CLIENT_NOT_CONNECTED => true,
+                RPC_WALLET_ERROR => message.contains("currently rescanning") || message.contains("already loading"),
+                _ => false,
+            },
+            RpcError::Busy | RpcError::Timeout(_) => true,
+        }
+    }
+
+    /// Which `CoreError` this is, if any
+    pub fn core_error(&self) -> Option<CoreError> {
+        let RpcError::Node { code, message } = self else { return None };
+        Some(match *code {
+            RPC_WALLET_ERROR if message.contains("Database already exists") => CoreError::WalletExists,
+            RPC_WALLET_ALREADY_LOADED => CoreError::WalletAlreadyLoaded,
+            // Core before 22.0 reported a loaded wallet as a generic wallet error
+            RPC_WALLET_ERROR if message.contains("already loaded") => CoreError::WalletAlreadyLoaded,
+            RPC_WALLET_NOT_FOUND => CoreError::WalletNotFound,
+            RPC_VERIFY_REJECTED | RPC_VERIFY_ERROR => return CoreError::from_reject_reason(message),
+            RPC_VERIFY_ALREADY_IN_CHAIN => CoreError::AlreadyKnown,
+            RPC_INVALID_ADDRESS_OR_KEY if message.starts_with("No such") || message.contains("not found") => CoreError::NotFound,
+            RPC_WALLET_INVALID_LABEL_NAME if message.starts_with("No addresses with label") => CoreError::NotFound,
+            RPC_INVALID_PARAMETER if message.starts_with("Scan already in progress") => CoreError::ScanInProgress,
+            _ => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcError::Node { code, message } => write!(f, "RPC error {}: {}", code, message),
+            RpcError::Busy => write!(f, "RPC server busy (work queue full)"),
+            RpcError::Timeout(after) => write!(f, "RPC call timed out after {:?}", after),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// Whether a failed attempt may be retried; `idempotent` is false if running the call twice
+/// could do harm, in which case only failures known to precede the node running it qualify
+fn should_retry(error: &(dyn std::error::Error + 'static), idempotent: bool) -> bool {
+    if let Some(e) = error.downcast_ref::<RpcError>() {
+        return match e {
+            RpcError::Timeout(_) => idempotent,
+            e => e.is_retriable(),
+        };
+    }
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+        return e.is_connect() || (idempotent && (e.is_timeout() || e.is_request()));
+    }
+    false
+}
+
+/// Label of wallet addresses that receive mined coins and fund test outputs
+pub const FUNDING_LABEL: &str = "funding";
+/// Label of wallet addresses that spends pay back to
+pub const DESTINATION_LABEL: &str = "destination";
+
+/// Options shared by the wallet funding wrappers (`send`, `walletcreatefundedpsbt`)
+#[derive(Debug, Clone, Default)]
+pub struct SendOptions {
+    pub fee_rate_sat_vb: Option<f64>,
+    /// "legacy", "p2sh-segwit", "bech32" or "bech32m"
+    pub change_type: Option<String>,
+    /// Output indices the fee is deducted from
+    pub subtract_fee_from_outputs: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SendResult {
+    pub complete: bool,
+    pub txid: Option<String>,
+    pub hex: Option<String>,
+    pub psbt: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FundedPsbt {
+    /// base64 PSBT
+    pub psbt: String,
+    pub fee: Amount,
+    pub change_position: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessedPsbt {
+    pub psbt: String,
+    pub complete: bool,
+}
+
+/// How often `wait_for_confirmations` polls the node
+pub const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Why `wait_for_confirmations` gave up
+#[derive(Debug, Clone, PartialEq)]
+pub enum WaitError {
+    /// `confirmations` is the depth reached when time ran out (0 while in the mempool)
+    Timeout { txid: Txid, confirmations: u32, wanted: u32 },
+    /// Neither in the mempool nor in a block: replaced, evicted or reorged out and conflicted
+    Replaced { txid: Txid },
+}
+
+impl std::fmt::Display for WaitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WaitError::Timeout { txid, confirmations, wanted } => write!(f, "{} has {} of {} confirmations after the timeout", txid, confirmations, wanted),
+            WaitError::Replaced { txid } => write!(f, "{} is no longer in the mempool or the chain", txid),
+        }
+    }
+}
+
+impl std::error::Error for WaitError {}
+
+/// How to authenticate against the node's RPC server
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcAuth {
+    UserPass { user: String, password: String },
+    /// Path to Core's `.cookie` file (`__cookie__:<password>`), rewritten on every node restart
+    CookieFile(PathBuf),
+}
+
+impl RpcAuth {
+    /// Base64 credentials for the `Authorization: Basic` header; a cookie file is read here
+    pub fn basic_credentials(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let credentials = match self {
+            RpcAuth::UserPass { user, password } => format!("{}:{}", user, password),
+            RpcAuth::CookieFile(path) => {
+                let cookie = std::fs::read_to_string(path)
+                    .map_err(|e| format!("cannot read RPC cookie {}: {}", path.display(), e))?;
+                let cookie = cookie.trim().to_string();
+                if !cookie.contains(':') {
+                    return Err(format!("malformed RPC cookie in {}", path.display()).into());
+                }
+                cookie
+            }
+        };
+        Ok(base64::engine::general_purpose::STANDARD.encode(credentials))
+    }
+}
+
+/// Network and default RPC port of a chain, named as `Network` parses it or as Core reports it
+/// in `getblockchaininfo` (`main`, `test`, `testnet4`, ...). testnet4 shares testnet3's address
+/// encoding, so both map to `Network::Testnet` and only the port tells them apart.
+pub fn parse_chain(name: &str) -> Result<(Network, u16), String> {
+    match name.trim() {
+        "main" | "mainnet" => Ok((Network::Bitcoin, 8332)),
+        "test" | "testnet3" => Ok((Network::Testnet, 18332)),
+        "testnet4" => Ok((Network::Testnet, 48332)),
+        other => Network::from_str(other).map(|n| (n, default_rpc_port(n))).map_err(|e| e.to_string()),
+    }
+}
+
+/// Default RPC port of Bitcoin Core for `network` (testnet3 for `Network::Testnet`)
+pub fn default_rpc_port(network: Network) -> u16 {
+    match network {
+        Network::Bitcoin => 8332,
+        Network::Testnet => 18332,
+        Network::Signet => 38332,
+        _ => 18443,
+    }
+}
+
+/// Endpoint, credentials and chain of the node to talk to
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcConfig {
+    pub url: String,
+    pub auth: RpcAuth,
+    /// Chain the node runs; addresses generated against this node are encoded for it
+    pub network: Network,
+}
+
+impl Default for RpcConfig {
+    /// The local regtest node the tests have always used
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:18443".to_string(),
+            auth: RpcAuth::UserPass { user: "bitcoin".to_string(), password: "localtest".to_string() },
+            network: Network::Regtest,
+        }
+    }
+}
+
+impl RpcConfig {
+    /// Read `WRAPYIELD_NETWORK` (see `parse_chain`), `WRAPYIELD_RPC_URL`, `WRAPYIELD_RPC_COOKIE`,
+    /// `WRAPYIELD_RPC_USER` and `WRAPYIELD_RPC_PASSWORD`; unset variables keep the defaults
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Like `from_env`, reading variables through `var`. Without a URL the network's default
+    /// port on localhost is used; a cookie file takes precedence over user/password.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, Box<dyn std::error::Error>> {
+        let defaults = Self::default();
+        let (network, port) = match var("WRAPYIELD_NETWORK") {
+            Some(n) => parse_chain(&n).map_err(|e| format!("WRAPYIELD_NETWORK: {}", e))?,
+            None => (defaults.network, default_rpc_port(defaults.network)),
+        };
+        let url = var("WRAPYIELD_RPC_URL").unwrap_or_else(|| format!("http://localhost:{}", port));
+        let auth = match (var("WRAPYIELD_RPC_COOKIE"), var("WRAPYIELD_RPC_USER"), var("WRAPYIELD_RPC_PASSWORD")) {
+            (Some(cookie), _, _) => RpcAuth::CookieFile(PathBuf::from(cookie)),
+            (None, Some(user), Some(password)) => RpcAuth::UserPass { user, password },
+            (None, None, None) => defaults.auth,
+            _ => return Err("WRAPYIELD_RPC_USER and WRAPYIELD_RPC_PASSWORD must be set together".into()),
+        };
+        Ok(Self { url, auth, network })
+    }
+}
+
+#[derive(Clone)]
 pub struct BitcoinRPC {
     pub url: String,
     pub client: reqwest::Client,
     pub auth: String,
+    pub network: Network,
+    pub retry: RetryPolicy,
 }
 
 impl BitcoinRPC {
+    /// Node from the `WRAPYIELD_*` environment (see `RpcConfig::from_env`), which without any
+    /// of those variables is the local regtest node at `localhost:18443`.
+    /// Panics on an invalid environment; use `from_env` to handle that.
     pub fn new() -> Self {
-        let url = "http://localhost:18443".to_string();
-        let client = reqwest::Client::new();
-        let auth = base64::engine::general_purpose::STANDARD.encode("bitcoin:localtest");
-        Self { url, client, auth }
+        Self::from_env().expect("invalid RPC configuration in environment")
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(RpcConfig::from_env()?)
+    }
+
+    pub fn with_config(config: RpcConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            auth: config.auth.basic_credentials()?,
+            url: config.url,
+            client: reqwest::Client::new(),
+            network: config.network,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    /// Parse an address returned by (or destined for) this node, rejecting other networks
+    pub fn parse_address(&self, address: &str) -> Result<bitcoin::Address, Box<dyn std::error::Error>> {
+        Ok(bitcoin::Address::from_str(address)?.require_network(self.network)?)
+    }
+
+    /// Replace the HTTP client with one built from `config`
+    pub fn with_pool_config(self, config: &RpcPoolConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            client: config.build_client()?,
+            ..self
+        })
+    }
+
+    pub fn with_retry_policy(self, retry: RetryPolicy) -> Self {
+        Self { retry, ..self }
     }
 
     pub fn with_wallet(&self, wallet: &str) -> Self {
@@ -23,75 +477,409 @@ impl BitcoinRPC {
             url,
             client: self.client.clone(),
             auth: self.auth.clone(),
+            network: self.network,
+            retry: self.retry,
         }
     }
+    /// Call `method` on the node; in read-only mode broadcasting and signing calls are simulated
+    /// (see `read_only`). Transient failures are retried under `retry` (see `RetryPolicy`); calls
+    /// that reach the node are timed, retries included (see `telemetry`).
     pub async fn call_rpc(&self, method: &str, params: serde_json::Value) -> Result<Value, Box<dyn std::error::Error>> {
+        if read_only::is_enabled() && read_only::intercepts(method, &params) {
+            let mempool_check = match method {
+                "sendrawtransaction" => Some(self.request("testmempoolaccept", json!([[params[0].clone()]])).await?),
+                _ => None,
+            };
+            return read_only::simulate(method, &params, mempool_check.as_ref());
+        }
+        let started = Instant::now();
+        let result = self.request(method, params).await;
+        let error = result.as_ref().err().map(|e| e.to_string());
+        telemetry::record_timed(&format!("rpc {}", method), "wrapyield.rpc.duration", started.elapsed(), &[("rpc.method", method.to_string())], error);
+        result
+    }
+
+    /// `call_rpc` with the result deserialized into `T`
+    pub async fn call_typed<T: DeserializeOwned>(&self, method: &str, params: serde_json::Value) -> Result<T, Box<dyn std::error::Error>> {
+        let result = self.call_rpc(method, params).await?;
+        serde_json::from_value(result).map_err(|e| format!("unexpected {} result: {}", method, e).into())
+    }
+
+    /// Send `calls` as one JSON-RPC batch: a single round trip, run by the node in order, so a
+    /// call may rely on the effects of the ones before it. The outer error is the request
+    /// failing as a whole; each call has its own result, in call order. In read-only mode a batch
+    /// holding an intercepted call runs call by call through `call_rpc` instead.
+    pub async fn call_batch(&self, calls: Vec<(&str, serde_json::Value)>) -> Result<Vec<Result<Value, Box<dyn std::error::Error>>>, Box<dyn std::error::Error>> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+        if read_only::is_enabled() && calls.iter().any(|(method, params)| read_only::intercepts(method, params)) {
+            let mut results = Vec::with_capacity(calls.len());
+            for (method, params) in calls {
+                results.push(self.call_rpc(method, params).await);
+            }
+            return Ok(results);
+        }
+        let started = Instant::now();
+        let result = self.request_batch(&calls).await;
+        let methods = calls.iter().map(|(method, _)| *method).collect::<Vec<_>>().join(",");
+        let error = match &result {
+            Ok(results) => results.iter().find_map(|r| r.as_ref().err()).map(|e| e.to_string()),
+            Err(e) => Some(e.to_string()),
+        };
+        telemetry::record_timed("rpc batch", "wrapyield.rpc.duration", started.elapsed(), &[("rpc.method", methods), ("rpc.batch_size", calls.len().to_string())], error);
+        result
+    }
+
+    async fn request_batch(&self, calls: &[(&str, serde_json::Value)]) -> Result<Vec<Result<Value, Box<dyn std::error::Error>>>, Box<dyn std::error::Error>> {
+        let req = json!(calls.iter().enumerate()
+            .map(|(id, (method, params))| json!({ "jsonrpc": "1.0", "id": id, "method": method, "params": params }))
+            .collect::<Vec<_>>());
+        let methods: Vec<&str> = calls.iter().map(|(method, _)| *method).collect();
+        let resp_json = self.post_retrying(&methods, &req).await?;
+        let responses = match resp_json.as_array() {
+            Some(responses) => responses,
+            None if !resp_json["error"].is_null() => return Err(RpcError::from_json(&resp_json["error"]).into()),
+            None => return Err("RPC batch response is not an array".into()),
+        };
+        // The node may answer in any order; match responses to calls by id
+        let mut results: Vec<Option<Result<Value, Box<dyn std::error::Error>>>> = calls.iter().map(|_| None).collect();
+        for response in responses {
+            let slot = response["id"].as_u64().and_then(|id| results.get_mut(id as usize))
+                .ok_or_else(|| format!("RPC batch response with unknown id {}", response["id"]))?;
+            *slot = Some(if response["error"].is_null() {
+                Ok(response["result"].clone())
+            } else {
+                Err(RpcError::from_json(&response["error"]).into())
+            });
+        }
+        results.into_iter().zip(calls)
+            .map(|(result, (method, _))| result.ok_or_else(|| format!("RPC batch response has no result for {}", method).into()))
+            .collect()
+    }
+
+    async fn request(&self, method: &str, params: serde_json::Value) -> Result<Value, Box<dyn std::error::Error>> {
         let req = json!({
             "jsonrpc": "1.0",
             "id": "rust",
             "method": method,
             "params": params,
         });
+        let resp_json = self.post_retrying(&[method], &req).await?;
+        if resp_json["error"].is_null() {
+            Ok(resp_json["result"].clone())
+        } else {
+            Err(RpcError::from_json(&resp_json["error"]).into())
+        }
+    }
+
+    /// Post `req`, running `methods`, under the retry policy. A JSON-RPC error answer is retried
+    /// here too when it is transient, and otherwise returned as the reply for the caller to read.
+    async fn post_retrying(&self, methods: &[&str], req: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let idempotent = methods.iter().all(|m| !NON_IDEMPOTENT_METHODS.contains(m));
+        let mut attempt = 1;
+        loop {
+            // Scoped so the (non-Send) error is gone before the backoff await
+            {
+                let result = match self.retry.timeout_for(methods) {
+                    Some(limit) => tokio::time::timeout(limit, self.post(req)).await.unwrap_or_else(|_| Err(RpcError::Timeout(limit).into())),
+                    None => self.post(req).await,
+                };
+                let retry = attempt < self.retry.max_attempts && match &result {
+                    Ok(reply) => reply.get("error").filter(|e| !e.is_null()).is_some_and(|e| RpcError::from_json(e).is_retriable()),
+                    Err(e) => should_retry(e.as_ref(), idempotent),
+                };
+                if !retry {
+                    return result;
+                }
+            }
+            tokio::time::sleep(self.retry.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn post(&self, req: &Value) -> Result<Value, Box<dyn std::error::Error>> {
         let resp = self.client.post(&self.url)
             .header("Authorization", format!("Basic {}", self.auth))
-            .json(&req)
+            .json(req)
             .send()
             .await?;
-        let resp_json: Value = resp.json().await?;
-        if resp_json["error"].is_null() {
-            Ok(resp_json["result"].clone())
-        } else {
-            Err(format!("RPC error: {:?}", resp_json["error"]).into())
+        if resp.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+            return Err(RpcError::Busy.into());
         }
+        Ok(resp.json().await?)
     }
     pub async fn get_new_address(&self) -> Result<String, Box<dyn std::error::Error>> {
-        let addr = self.call_rpc("getnewaddress", json!([])).await?;
-        Ok(addr.as_str().unwrap().to_string())
+        self.call_typed("getnewaddress", json!([])).await
+    }
+    /// `count` fresh wallet addresses in one round trip
+    pub async fn get_new_addresses(&self, count: usize) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let results = self.call_batch(vec![("getnewaddress", json!([])); count]).await?;
+        results.into_iter().map(|r| Ok(serde_json::from_value(r?)?)).collect()
+    }
+    /// Public keys of `count` fresh wallet addresses, in two round trips whatever `count` is
+    pub async fn generate_keys(&self, count: usize) -> Result<Vec<PublicKey>, Box<dyn std::error::Error>> {
+        let addresses = self.get_new_addresses(count).await?;
+        let infos = self.call_batch(addresses.iter().map(|a| ("getaddressinfo", json!([a]))).collect()).await?;
+        infos.into_iter().zip(&addresses).map(|(info, address)| {
+            let info = info?;
+            let pubkey = info["pubkey"].as_str().ok_or_else(|| format!("wallet has no public key for {}", address))?;
+            Ok(PublicKey::from_str(pubkey)?)
+        }).collect()
     }
-    pub async fn send_to_address(&self, address: &str, amount: f64) -> Result<String, Box<dyn std::error::Error>> {
-        let txid = self.call_rpc("sendtoaddress", json!([address, amount])).await?;
-        Ok(txid.as_str().unwrap().to_string())
+    pub async fn send_to_address(&self, address: &str, amount: Amount) -> Result<String, Box<dyn std::error::Error>> {
+        self.call_typed("sendtoaddress", json!([address, amount::to_rpc(amount)])).await
     }
     pub async fn generate_to_address(&self, blocks: u32, address: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let hashes = self.call_rpc("generatetoaddress", json!([blocks, address])).await?;
-        Ok(hashes.as_array().unwrap().iter().map(|h| h.as_str().unwrap().to_string()).collect())
+        self.call_typed("generatetoaddress", json!([blocks, address])).await
     }
-    pub async fn get_balance(&self) -> Result<f64, Box<dyn std::error::Error>> {
-        let balance = self.call_rpc("getbalance", json!([])).await?;
-        Ok(balance.as_f64().unwrap())
+    pub async fn get_balance(&self) -> Result<Amount, Box<dyn std::error::Error>> {
+        amount::from_rpc(&self.call_rpc("getbalance", json!([])).await?)
     }
-    pub async fn create_raw_transaction(&self, inputs: Vec<Value>, outputs: HashMap<String, f64>) -> Result<String, Box<dyn std::error::Error>> {
-        let tx = self.call_rpc("createrawtransaction", json!([inputs, outputs])).await?;
-        Ok(tx.as_str().unwrap().to_string())
+    pub async fn create_raw_transaction(&self, inputs: Vec<Value>, outputs: HashMap<String, Amount>) -> Result<String, Box<dyn std::error::Error>> {
+        let outputs: HashMap<String, Value> = outputs.into_iter().map(|(address, value)| (address, amount::to_rpc(value))).collect();
+        self.call_typed("createrawtransaction", json!([inputs, outputs])).await
     }
     pub async fn send_raw_transaction(&self, hex: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let txid = self.call_rpc("sendrawtransaction", json!([hex])).await?;
-        Ok(txid.as_str().unwrap().to_string())
+        self.call_typed("sendrawtransaction", json!([hex])).await
     }
     pub async fn create_wallet(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
         let result = self.call_rpc("createwallet", json!([name, false, false, "", false, true, true])).await;
         match result {
             Ok(_) => Ok(()),
-            Err(e) => {
-                if e.to_string().contains("Database already exists") {
-                    Ok(())
-                } else {
-                    Err(e)
-                }
-            }
+            Err(e) if CoreError::of(&*e) == Some(CoreError::WalletExists) => Ok(()),
+            Err(e) => Err(e),
         }
     }
-    
-    pub async fn generate_keys(&self, count: u32) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let mut addresses = Vec::new();
-        for _ in 0..count {
-            let addr = self.call_rpc("getnewaddress", json!([])).await?;
-            addresses.push(addr.as_str().unwrap().to_string());
+
+    pub async fn get_wallet_info(&self) -> Result<WalletInfo, Box<dyn std::error::Error>> {
+        self.call_typed("getwalletinfo", json!([])).await
+    }
+    /// Top the wallet's keypool up to at least `size` keys; returns the resulting keypool size
+    pub async fn ensure_keypool(&self, size: u64) -> Result<u64, Box<dyn std::error::Error>> {
+        let info = self.get_wallet_info().await?;
+        if info.keypool_size >= size {
+            return Ok(info.keypool_size);
         }
-        Ok(addresses)
+        self.call_rpc("keypoolrefill", json!([size])).await?;
+        Ok(self.get_wallet_info().await?.keypool_size)
+    }
+    /// Fresh receive address tagged with `label`, so it can be found again with `addresses_by_label`
+    pub async fn new_labeled_address(&self, label: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.call_typed("getnewaddress", json!([label])).await
     }
+    /// Addresses the wallet has given `label`; empty when the label was never used
+    pub async fn addresses_by_label(&self, label: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        match self.call_typed::<HashMap<String, Value>>("getaddressesbylabel", json!([label])).await {
+            Ok(addresses) => Ok(addresses.into_keys().collect()),
+            Err(e) if CoreError::of(&*e) == Some(CoreError::NotFound) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+    /// Load wallet `name`; a wallet that is already loaded is fine
     pub async fn load_wallet(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let _ = self.call_rpc("loadwallet", json!([name])).await?;
-        Ok(())
+        match self.call_rpc("loadwallet", json!([name])).await {
+            Ok(_) => Ok(()),
+            Err(e) if CoreError::of(&*e) == Some(CoreError::WalletAlreadyLoaded) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Pay `outputs` from the node wallet using Core's `send` RPC (funds, signs and broadcasts)
+    pub async fn send(&self, outputs: &[(String, Amount)], options: &SendOptions) -> Result<SendResult, Box<dyn std::error::Error>> {
+        let outputs: Vec<Value> = outputs.iter().map(|(address, value)| json!({ address.as_str(): amount::to_rpc(*value) })).collect();
+        let mut opts = serde_json::Map::new();
+        if let Some(fee_rate) = options.fee_rate_sat_vb {
+            opts.insert("fee_rate".to_string(), json!(fee_rate));
+        }
+        if let Some(change_type) = &options.change_type {
+            opts.insert("change_type".to_string(), json!(change_type));
+        }
+        if !options.subtract_fee_from_outputs.is_empty() {
+            opts.insert("subtract_fee_from_outputs".to_string(), json!(options.subtract_fee_from_outputs));
+        }
+        let result = self.call_rpc("send", json!([outputs, null, "unset", null, opts])).await?;
+        Ok(SendResult {
+            complete: result["complete"].as_bool().unwrap_or(false),
+            txid: result["txid"].as_str().map(str::to_string),
+            hex: result["hex"].as_str().map(str::to_string),
+            psbt: result["psbt"].as_str().map(str::to_string),
+        })
+    }
+    /// Build a funded (unsigned) PSBT paying `outputs`, optionally with preselected `inputs`
+    pub async fn wallet_create_funded_psbt(&self, inputs: Vec<Value>, outputs: &[(String, Amount)], options: &SendOptions) -> Result<FundedPsbt, Box<dyn std::error::Error>> {
+        let outputs: Vec<Value> = outputs.iter().map(|(address, value)| json!({ address.as_str(): amount::to_rpc(*value) })).collect();
+        let mut opts = serde_json::Map::new();
+        if let Some(fee_rate) = options.fee_rate_sat_vb {
+            opts.insert("fee_rate".to_string(), json!(fee_rate));
+        }
+        if let Some(change_type) = &options.change_type {
+            opts.insert("change_type".to_string(), json!(change_type));
+        }
+        if !options.subtract_fee_from_outputs.is_empty() {
+            opts.insert("subtractFeeFromOutputs".to_string(), json!(options.subtract_fee_from_outputs));
+        }
+        let result = self.call_rpc("walletcreatefundedpsbt", json!([inputs, outputs, 0, opts])).await?;
+        Ok(FundedPsbt {
+            psbt: result["psbt"].as_str().ok_or("walletcreatefundedpsbt returned no psbt")?.to_string(),
+            fee: amount::from_rpc(&result["fee"])?,
+            change_position: result["changepos"].as_i64().unwrap_or(-1),
+        })
+    }
+    /// `walletprocesspsbt`: sign the inputs the wallet controls with SIGHASH_ALL; `finalize` = false keeps partial sigs
+    pub async fn wallet_process_psbt(&self, psbt: &str, sign: bool, finalize: bool) -> Result<ProcessedPsbt, Box<dyn std::error::Error>> {
+        let result = self.call_rpc("walletprocesspsbt", json!([psbt, sign, "ALL", true, finalize])).await?;
+        Ok(ProcessedPsbt {
+            psbt: result["psbt"].as_str().ok_or("walletprocesspsbt returned no psbt")?.to_string(),
+            complete: result["complete"].as_bool().unwrap_or(false),
+        })
+    }
+
+    pub async fn get_blockchain_info(&self) -> Result<BlockchainInfo, Box<dyn std::error::Error>> {
+        self.call_typed("getblockchaininfo", json!([])).await
+    }
+    /// Fail unless the node runs the chain this client encodes addresses for
+    pub async fn check_network(&self) -> Result<BlockchainInfo, Box<dyn std::error::Error>> {
+        let info = self.get_blockchain_info().await?;
+        let (network, _) = parse_chain(&info.chain)?;
+        if network != self.network {
+            return Err(format!("node runs {} but the client is configured for {}", info.chain, self.network).into());
+        }
+        Ok(info)
+    }
+    pub async fn get_block_count(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        self.call_typed("getblockcount", json!([])).await
+    }
+    /// Hashes of the blocks at `heights`, in one round trip
+    pub async fn get_block_hashes(&self, heights: impl IntoIterator<Item = u64>) -> Result<Vec<BlockHash>, Box<dyn std::error::Error>> {
+        let results = self.call_batch(heights.into_iter().map(|h| ("getblockhash", json!([h]))).collect()).await?;
+        results.into_iter().map(|r| Ok(serde_json::from_value(r?)?)).collect()
+    }
+    pub async fn get_block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn std::error::Error>> {
+        self.call_typed("getblockhash", json!([height])).await
+    }
+    pub async fn get_block_header(&self, hash: &BlockHash) -> Result<Header, Box<dyn std::error::Error>> {
+        let hex: String = self.call_typed("getblockheader", json!([hash.to_string(), false])).await?;
+        Ok(bitcoin::consensus::encode::deserialize(&hex::decode(hex)?)?)
+    }
+    /// Verbose `getrawtransaction`; needs `-txindex` unless the transaction is in the mempool or the wallet
+    pub async fn get_raw_transaction_verbose(&self, txid: &Txid) -> Result<GetRawTransactionResult, Box<dyn std::error::Error>> {
+        self.call_typed("getrawtransaction", json!([txid.to_string(), true])).await
+    }
+    /// Resolve once `txid` is `confirmations` deep, polling every `CONFIRMATION_POLL_INTERVAL`.
+    /// Fails with a `WaitError` on `timeout` or when the transaction disappears.
+    pub async fn wait_for_confirmations(&self, txid: &Txid, confirmations: u32, timeout: Duration) -> Result<GetRawTransactionResult, Box<dyn std::error::Error>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let depth = match self.get_raw_transaction_verbose(txid).await {
+                Ok(raw) if raw.confirmations.unwrap_or(0) >= confirmations => return Ok(raw),
+                Ok(raw) => raw.confirmations.unwrap_or(0),
+                Err(e) if CoreError::of(&*e) == Some(CoreError::NotFound) => return Err(WaitError::Replaced { txid: *txid }.into()),
+                Err(e) => return Err(e),
+            };
+            if tokio::time::Instant::now() + CONFIRMATION_POLL_INTERVAL > deadline {
+                return Err(WaitError::Timeout { txid: *txid, confirmations: depth, wanted: confirmations }.into());
+            }
+            tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+        }
+    }
+    /// Wallet UTXOs with at least `min_conf` confirmations
+    pub async fn list_unspent(&self, min_conf: u32) -> Result<Vec<ListUnspentEntry>, Box<dyn std::error::Error>> {
+        self.call_typed("listunspent", json!([min_conf])).await
+    }
+    /// Wallet UTXOs with at least `min_conf` confirmations on addresses labelled `label`
+    pub async fn list_utxos_by_label(&self, label: &str, min_conf: u32) -> Result<Vec<ListUnspentEntry>, Box<dyn std::error::Error>> {
+        Ok(self.list_unspent(min_conf).await?.into_iter().filter(|u| u.label.as_deref() == Some(label)).collect())
+    }
+    /// Sum of `list_utxos_by_label`
+    pub async fn get_balance_by_label(&self, label: &str, min_conf: u32) -> Result<Amount, Box<dyn std::error::Error>> {
+        Ok(self.list_utxos_by_label(label, min_conf).await?.iter().map(|u| u.amount).sum())
+    }
+    /// Every label in the wallet, including the default `""`
+    pub async fn list_labels(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.call_typed("listlabels", json!([])).await
+    }
+    pub async fn sign_raw_transaction_with_wallet(&self, hex: &str) -> Result<SignRawTransactionResult, Box<dyn std::error::Error>> {
+        self.call_typed("signrawtransactionwithwallet", json!([hex])).await
+    }
+    pub async fn estimate_smart_fee(&self, conf_target: u16) -> Result<EstimateSmartFeeResult, Box<dyn std::error::Error>> {
+        self.call_typed("estimatesmartfee", json!([conf_target])).await
+    }
+    pub async fn get_mempool_info(&self) -> Result<MempoolInfo, Box<dyn std::error::Error>> {
+        self.call_typed("getmempoolinfo", json!([])).await
+    }
+    /// Fails when `txid` is not in the mempool
+    pub async fn get_mempool_entry(&self, txid: &Txid) -> Result<MempoolEntry, Box<dyn std::error::Error>> {
+        self.call_typed("getmempoolentry", json!([txid.to_string()])).await
+    }
+    pub async fn test_mempool_accept(&self, hexes: &[String]) -> Result<Vec<TestMempoolAcceptResult>, Box<dyn std::error::Error>> {
+        self.call_typed("testmempoolaccept", json!([hexes])).await
+    }
+    /// Broadcast `tx` if `testmempoolaccept` allows it, and fail with a `MempoolRejection`
+    /// carrying the reject reason if not
+    pub async fn test_and_send(&self, tx: &Transaction) -> Result<Txid, Box<dyn std::error::Error>> {
+        let hex = serialize_hex(tx);
+        let result = self.test_mempool_accept(std::slice::from_ref(&hex)).await?.into_iter().next()
+            .ok_or("testmempoolaccept returned no result")?;
+        if !result.allowed {
+            let reason = result.reject_reason.unwrap_or_else(|| "no reject reason given".to_string());
+            return Err(MempoolRejection { txid: result.txid, kind: CoreError::from_reject_reason(&reason), reason }.into());
+        }
+        Ok(Txid::from_str(&self.send_raw_transaction(&hex).await?)?)
+    }
+
+    /// Scan the UTXO set for outputs matching `scan_objects` (`addr(...)` or descriptors).
+    /// Only one scan can run per node, so a scan already in progress is waited for.
+    pub async fn scan_tx_out_set(&self, scan_objects: &[String]) -> Result<ScanTxOutSetResult, Box<dyn std::error::Error>> {
+        self.scan_tx_out_set_objects(json!(scan_objects)).await
+    }
+    /// `scan_tx_out_set` with raw scan objects, e.g. `{"desc": ..., "range": [0, 999]}`
+    pub async fn scan_tx_out_set_objects(&self, scan_objects: Value) -> Result<ScanTxOutSetResult, Box<dyn std::error::Error>> {
+        let mut attempts = 0;
+        loop {
+            match self.call_typed("scantxoutset", json!(["start", scan_objects])).await {
+                Err(e) if CoreError::of(&*e) == Some(CoreError::ScanInProgress) && attempts < 50 => attempts += 1,
+                result => return result,
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+    /// Unspent outputs paying `address`: confirmed ones from the UTXO set, plus unconfirmed ones
+    /// when this client points at a wallet that watches the address. Both lookups share one
+    /// round trip.
+    pub async fn find_utxos_for_address(&self, address: &str) -> Result<Vec<Utxo>, Box<dyn std::error::Error>> {
+        let mut attempts = 0;
+        let (scan, unconfirmed) = loop {
+            let mut results = self.call_batch(vec![
+                ("scantxoutset", json!(["start", [format!("addr({})", address)]])),
+                ("listunspent", json!([0, 0, [address]])),
+            ]).await?.into_iter();
+            match (results.next(), results.next()) {
+                (Some(Err(e)), _) if CoreError::of(&*e) == Some(CoreError::ScanInProgress) && attempts < 50 => {
+                    attempts += 1;
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                (Some(scan), Some(unconfirmed)) => break (scan?, unconfirmed),
+                _ => return Err("RPC batch returned too few results".into()),
+            }
+        };
+        let mut utxos = serde_json::from_value::<ScanTxOutSetResult>(scan).map_err(|e| format!("unexpected scantxoutset result: {}", e))?.utxos();
+        // Without a wallet listunspent fails; the UTXO set alone is the answer then
+        if let Ok(unconfirmed) = unconfirmed {
+            add_wallet_entries(&mut utxos, serde_json::from_value(unconfirmed).unwrap_or_default());
+        }
+        Ok(utxos)
+    }
+    /// Unspent outputs paying `descriptor` (confirmed only; the UTXO set has no mempool outputs)
+    pub async fn find_utxos_for_descriptor(&self, descriptor: &Descriptor<PublicKey>) -> Result<Vec<Utxo>, Box<dyn std::error::Error>> {
+        Ok(self.scan_tx_out_set(&[descriptor.to_string()]).await?.utxos())
+    }
+}
+
+fn add_wallet_entries(utxos: &mut Vec<Utxo>, unconfirmed: Vec<ListUnspentEntry>) {
+    for entry in unconfirmed {
+        if !utxos.iter().any(|u| u.outpoint == entry.outpoint()) {
+            utxos.push(Utxo { outpoint: entry.outpoint(), amount: entry.amount, script_pubkey: entry.script_pubkey, confirmations: entry.confirmations });
+        }
     }
 }
@@ -1,4 +1,5 @@
-use miniscript::{Descriptor, bitcoin::{Network, PrivateKey, secp256k1, PublicKey}};
+use crate::witness::{nested_vault_descriptor, vault_descriptor, VaultTimelock};
+use miniscript::{Descriptor, MiniscriptKey, bitcoin::{Network, PrivateKey, secp256k1, PublicKey}};
 use rand::RngCore;
 use std::str::FromStr;
 
@@ -8,26 +9,65 @@ fn random_secret_key() -> secp256k1::SecretKey {
     secp256k1::SecretKey::from_slice(&data).unwrap()
 }
 
-pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+pub fn run(network: Network) -> Result<(), Box<dyn std::error::Error>> {
     println!("Relative Timelock Example: 2-of-3 after 10 blocks, or backup key anytime");
     let secp = secp256k1::Secp256k1::new();
     let key1 = random_secret_key();
     let key2 = random_secret_key();
     let key3 = random_secret_key();
-    let backup_key = PublicKey::from_private_key(&secp, &PrivateKey::new(random_secret_key(), Network::Regtest));
-    let privkey1 = PrivateKey::new(key1, Network::Regtest);
-    let privkey2 = PrivateKey::new(key2, Network::Regtest);
-    let privkey3 = PrivateKey::new(key3, Network::Regtest);
+    let backup_key = PublicKey::from_private_key(&secp, &PrivateKey::new(random_secret_key(), network));
+    let privkey1 = PrivateKey::new(key1, network);
+    let privkey2 = PrivateKey::new(key2, network);
+    let privkey3 = PrivateKey::new(key3, network);
     let pubkey1 = PublicKey::from_private_key(&secp, &privkey1);
     let pubkey2 = PublicKey::from_private_key(&secp, &privkey2);
     let pubkey3 = PublicKey::from_private_key(&secp, &privkey3);
-    let csv_descriptor_str = format!("wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))", backup_key, pubkey1, pubkey2, pubkey3);
-    println!("CSV Descriptor: {}", csv_descriptor_str);
-    let csv_descriptor: Descriptor<PublicKey> = Descriptor::from_str(&csv_descriptor_str)?;
+    let csv_descriptor = csv_vault_descriptor(backup_key, &[pubkey1, pubkey2, pubkey3], 2, 10)?;
+    println!("CSV Descriptor: {}", csv_descriptor);
     println!("Parsed CSV descriptor: {:?}", csv_descriptor);
     let csv_script = csv_descriptor.script_pubkey();
     println!("CSV Script: {:?}", csv_script);
-    let csv_address = csv_descriptor.address(Network::Regtest)?;
-    println!("CSV Regtest Address: {}", csv_address);
+    let csv_address = csv_descriptor.address(network)?;
+    println!("CSV {} Address: {}", network, csv_address);
+    let (simple_descriptor, _, _, simple_address) = simple_csv_descriptor_on(10, network)?;
+    println!("Simple CSV Descriptor: {}", simple_descriptor);
+    println!("Simple CSV Address: {}", simple_address);
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// Generate a simple CSV descriptor and address for a single key and relative delay in blocks
+pub fn simple_csv_descriptor(blocks: u16) -> Result<(Descriptor<PublicKey>, PrivateKey, PublicKey, String), Box<dyn std::error::Error>> {
+    simple_csv_descriptor_on(blocks, Network::Regtest)
+}
+
+/// `simple_csv_descriptor` with key and address for `network`
+pub fn simple_csv_descriptor_on(blocks: u16, network: Network) -> Result<(Descriptor<PublicKey>, PrivateKey, PublicKey, String), Box<dyn std::error::Error>> {
+    if blocks == 0 {
+        return Err("relative timelock must be at least one block".into());
+    }
+    let secp = secp256k1::Secp256k1::new();
+    let privkey = PrivateKey::new(random_secret_key(), network);
+    let pubkey = PublicKey::from_private_key(&secp, &privkey);
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(and_v(v:pk({}),older({})))", pubkey, blocks))?;
+    let address = descriptor.address(network)?.to_string();
+    Ok((descriptor, privkey, pubkey, address))
+}
+
+/// `backup` any time, or `threshold` of `signers` once the output is `csv_delay` blocks old.
+/// Keys are bare keys or descriptor keys with origin (`hd::HdWallet::descriptor_key`).
+pub fn csv_vault_descriptor<Pk>(backup: Pk, signers: &[Pk], threshold: usize, csv_delay: u16) -> Result<Descriptor<Pk>, Box<dyn std::error::Error>>
+where
+    Pk: MiniscriptKey,
+    Descriptor<Pk>: FromStr<Err = miniscript::Error>,
+{
+    vault_descriptor(backup, signers, threshold, VaultTimelock::Relative(csv_delay.into()))
+}
+
+/// `csv_vault_descriptor` nested as `sh(wsh(...))`, for counterparties that can only pay P2SH
+pub fn nested_csv_vault_descriptor<Pk>(backup: Pk, signers: &[Pk], threshold: usize, csv_delay: u16) -> Result<Descriptor<Pk>, Box<dyn std::error::Error>>
+where
+    Pk: MiniscriptKey,
+    Descriptor<Pk>: FromStr<Err = miniscript::Error>,
+{
+    nested_vault_descriptor(backup, signers, threshold, VaultTimelock::Relative(csv_delay.into()))
+}
@@ -0,0 +1,254 @@
+//! Timeout tree for mass exit: one pooled output for many depositors that each can leave alone
+//! after a long absolute timelock if the federation disappears.
+//!
+//! The pool is the root of a binary tree of outputs. Every branch output is
+//! `tr(NUMS, multi_a(k, federation))` and is split in two by a fan-out transaction the federation
+//! pre-signs at deposit time with `lock_time = timeout`, so it cannot be used before then. Each
+//! depositor is assigned one leaf output, `tr(NUMS, {multi_a(k, federation),
+//! and_v(v:pk(user), after(timeout))})`. To exit, a depositor broadcasts the fan-outs on the
+//! path from the root to their leaf (about log2(n) transactions, anyone may broadcast them) and
+//! spends the leaf with their own key; everyone else's leaves stay where they are. Until the
+//! timeout the federation spends the pool cooperatively through its `multi_a` leaf.
+//!
+//! Each fan-out pays `node_fee`, so a branch holds the sum of its leaves plus the fees of the
+//! fan-outs below it. The tree and its leaf assignments depend only on the parameters and the
+//! deposits, in order, so `save` keeps just those.
+
+use crate::amount::deduct_fee_for;
+use crate::nums::unspendable_internal_key;
+use crate::psbt::{self, SpendableUtxo};
+use crate::state_file;
+use bitcoin::absolute::LockTime;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Amount, OutPoint, PrivateKey, ScriptBuf, Transaction, TxOut};
+use miniscript::bitcoin::PublicKey;
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Tag of the NUMS internal key every tree output uses (see `nums`)
+pub const NUMS_TAG: &str = "WrapYield/timeout-tree";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeoutTreeParams {
+    pub federation: Vec<PublicKey>,
+    pub threshold: usize,
+    /// Block height from which fan-outs are valid and depositors can spend their leaf
+    pub timeout: u32,
+    /// Fee of each fan-out transaction
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub node_fee: Amount,
+}
+
+/// Which leaf of the tree belongs to a depositor
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeafAssignment {
+    pub user: PublicKey,
+    pub amount: Amount,
+    /// Node of the leaf output
+    pub node: usize,
+    /// Branch nodes from the root down to the leaf's parent; their fan-outs make up the exit
+    pub path: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+    /// Leaf output of assignment `usize`
+    Leaf(usize),
+    /// Output split into `left` and `right` (outputs 0 and 1 of the fan-out)
+    Branch { left: usize, right: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeNode {
+    pub descriptor: Descriptor<PublicKey>,
+    pub value: Amount,
+    pub kind: NodeKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeoutTree {
+    pub params: TimeoutTreeParams,
+    pub assignments: Vec<LeafAssignment>,
+    /// Node 0 is the root (the pool); children follow their parent (pre-order)
+    pub nodes: Vec<TreeNode>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedTree {
+    params: TimeoutTreeParams,
+    deposits: Vec<SavedDeposit>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedDeposit {
+    user: PublicKey,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    amount: Amount,
+}
+
+impl TimeoutTree {
+    /// Assign one leaf per deposit, in order. Users must be distinct.
+    pub fn new(params: TimeoutTreeParams, deposits: &[(PublicKey, Amount)]) -> Result<Self, Box<dyn std::error::Error>> {
+        if deposits.is_empty() {
+            return Err("a timeout tree needs at least one deposit".into());
+        }
+        if params.threshold == 0 || params.threshold > params.federation.len() {
+            return Err(format!("threshold {} of {} federation keys", params.threshold, params.federation.len()).into());
+        }
+        if LockTime::from_height(params.timeout).is_err() {
+            return Err(format!("timeout {} is not a block height", params.timeout).into());
+        }
+        for (i, (user, _)) in deposits.iter().enumerate() {
+            if deposits[..i].iter().any(|(other, _)| other == user) {
+                return Err(format!("{} has more than one deposit", user).into());
+            }
+        }
+        let (internal, _) = unspendable_internal_key(NUMS_TAG)?;
+        let federation = format!("multi_a({},{})", params.threshold, params.federation.iter().map(|k| k.to_string()).collect::<Vec<_>>().join(","));
+        let mut tree = Self {
+            assignments: deposits.iter().map(|(user, amount)| LeafAssignment { user: *user, amount: *amount, node: 0, path: Vec::new() }).collect(),
+            nodes: Vec::new(),
+            params,
+        };
+        tree.build(0, deposits.len(), &mut Vec::new(), &internal, &federation)?;
+        Ok(tree)
+    }
+
+    /// Add the subtree of assignments `lo..hi` and return its node
+    fn build(&mut self, lo: usize, hi: usize, path: &mut Vec<usize>, internal: &PublicKey, federation: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let node = self.nodes.len();
+        if hi - lo == 1 {
+            let user = self.assignments[lo].user;
+            let descriptor = Descriptor::from_str(&format!("tr({},{{{},and_v(v:pk({}),after({}))}})", internal, federation, user, self.params.timeout))?;
+            self.nodes.push(TreeNode { descriptor, value: self.assignments[lo].amount, kind: NodeKind::Leaf(lo) });
+            self.assignments[lo].node = node;
+            self.assignments[lo].path = path.clone();
+            return Ok(node);
+        }
+        // Placeholder until both children are known
+        self.nodes.push(TreeNode { descriptor: Descriptor::from_str(&format!("tr({},{})", internal, federation))?, value: Amount::ZERO, kind: NodeKind::Leaf(lo) });
+        path.push(node);
+        let mid = lo + (hi - lo + 1) / 2;
+        let left = self.build(lo, mid, path, internal, federation)?;
+        let right = self.build(mid, hi, path, internal, federation)?;
+        path.pop();
+        let value = self.nodes[left].value.checked_add(self.nodes[right].value)
+            .and_then(|v| v.checked_add(self.params.node_fee))
+            .ok_or("timeout tree value overflows")?;
+        self.nodes[node].value = value;
+        self.nodes[node].kind = NodeKind::Branch { left, right };
+        Ok(node)
+    }
+
+    /// Descriptor of the pool that deposits are paid into
+    pub fn root_descriptor(&self) -> &Descriptor<PublicKey> {
+        &self.nodes[0].descriptor
+    }
+
+    /// What the pool must hold: every deposit plus every fan-out fee
+    pub fn root_value(&self) -> Amount {
+        self.nodes[0].value
+    }
+
+    pub fn assignment(&self, user: &PublicKey) -> Option<&LeafAssignment> {
+        self.assignments.iter().find(|a| a.user == *user)
+    }
+
+    /// Unsigned fan-out of every branch node when the pool is `root`, by node
+    pub fn fan_outs(&self, root: OutPoint) -> Result<Vec<(usize, Psbt)>, Box<dyn std::error::Error>> {
+        let mut outpoints = vec![None; self.nodes.len()];
+        outpoints[0] = Some(root);
+        let mut fan_outs = Vec::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let NodeKind::Branch { left, right } = node.kind else { continue };
+            let outpoint = outpoints[index].ok_or("fan-out of a node before its parent")?;
+            let descriptor: Descriptor<DefiniteDescriptorKey> = Descriptor::from_str(&node.descriptor.to_string())?;
+            let utxo = SpendableUtxo::new(outpoint, TxOut { value: node.value.to_sat(), script_pubkey: node.descriptor.script_pubkey() });
+            let outputs = [left, right].iter()
+                .map(|child| TxOut { value: self.nodes[*child].value.to_sat(), script_pubkey: self.nodes[*child].descriptor.script_pubkey() })
+                .collect();
+            let unsigned = psbt::create(&descriptor, &[utxo], outputs, LockTime::from_height(self.params.timeout)?)?;
+            let txid = unsigned.unsigned_tx.txid();
+            outpoints[left] = Some(OutPoint::new(txid, 0));
+            outpoints[right] = Some(OutPoint::new(txid, 1));
+            fan_outs.push((index, unsigned));
+        }
+        Ok(fan_outs)
+    }
+
+    /// Have the federation sign every fan-out for the pool output `root`. `keys` must reach the
+    /// threshold; every transaction is checked with `verify::verify_spend`.
+    pub fn presign(&self, root: OutPoint, keys: &[PrivateKey]) -> Result<PresignedTree, Box<dyn std::error::Error>> {
+        let mut fan_outs = Vec::new();
+        for (node, mut unsigned) in self.fan_outs(root)? {
+            psbt::sign(&mut unsigned, keys)?;
+            fan_outs.push((node, psbt::finalize(unsigned)?));
+        }
+        Ok(PresignedTree { tree: self.clone(), root, fan_outs })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let saved = SavedTree { params: self.params.clone(), deposits: self.assignments.iter().map(|a| SavedDeposit { user: a.user, amount: a.amount }).collect() };
+        state_file::save_json(path, &saved)
+    }
+
+    /// `None` if nothing was saved yet
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let saved: SavedTree = match state_file::load_json(path)? {
+            Some(saved) => saved,
+            None => return Ok(None),
+        };
+        let deposits: Vec<(PublicKey, Amount)> = saved.deposits.iter().map(|d| (d.user, d.amount)).collect();
+        Ok(Some(Self::new(saved.params, &deposits)?))
+    }
+}
+
+/// A tree whose fan-outs the federation has signed for a funded pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresignedTree {
+    pub tree: TimeoutTree,
+    pub root: OutPoint,
+    /// Signed fan-out of each branch node, parents before children
+    pub fan_outs: Vec<(usize, Transaction)>,
+}
+
+impl PresignedTree {
+    fn fan_out(&self, node: usize) -> Option<&Transaction> {
+        self.fan_outs.iter().find(|(n, _)| *n == node).map(|(_, tx)| tx)
+    }
+
+    /// The fan-outs `user` broadcasts to reach their leaf, root first
+    pub fn exit_path(&self, user: &PublicKey) -> Result<Vec<&Transaction>, Box<dyn std::error::Error>> {
+        let assignment = self.tree.assignment(user).ok_or_else(|| format!("{} has no leaf in this tree", user))?;
+        assignment.path.iter()
+            .map(|node| self.fan_out(*node).ok_or_else(|| format!("node {} has no signed fan-out", node).into()))
+            .collect()
+    }
+
+    /// Outpoint of `user`'s leaf once the exit path is confirmed
+    pub fn leaf_outpoint(&self, user: &PublicKey) -> Result<OutPoint, Box<dyn std::error::Error>> {
+        let assignment = self.tree.assignment(user).ok_or_else(|| format!("{} has no leaf in this tree", user))?;
+        let Some(parent) = assignment.path.last() else { return Ok(self.root) };
+        let tx = self.fan_out(*parent).ok_or_else(|| format!("node {} has no signed fan-out", parent))?;
+        let vout = tx.output.iter().position(|o| o.script_pubkey == self.tree.nodes[assignment.node].descriptor.script_pubkey())
+            .ok_or("fan-out does not pay the leaf")?;
+        Ok(OutPoint::new(tx.txid(), vout as u32))
+    }
+
+    /// Spend `user`'s leaf to `destination` with their key alone, valid from the timeout
+    pub fn exit(&self, user: &PrivateKey, destination: &ScriptBuf, fee: Amount) -> Result<Transaction, Box<dyn std::error::Error>> {
+        let public = user.public_key(&Secp256k1::new());
+        let assignment = self.tree.assignment(&public).ok_or_else(|| format!("{} has no leaf in this tree", public))?;
+        let leaf = &self.tree.nodes[assignment.node];
+        let descriptor: Descriptor<DefiniteDescriptorKey> = Descriptor::from_str(&leaf.descriptor.to_string())?;
+        let value = deduct_fee_for(leaf.value, fee, destination)?;
+        let utxo = SpendableUtxo::new(self.leaf_outpoint(&public)?, TxOut { value: leaf.value.to_sat(), script_pubkey: leaf.descriptor.script_pubkey() });
+        let mut unsigned = psbt::create(&descriptor, &[utxo], vec![TxOut { value: value.to_sat(), script_pubkey: destination.clone() }], LockTime::from_height(self.tree.params.timeout)?)?;
+        psbt::sign(&mut unsigned, &[*user])?;
+        psbt::finalize(unsigned)
+    }
+}
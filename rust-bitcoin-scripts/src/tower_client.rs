@@ -0,0 +1,291 @@
+//! Client for third-party watchtowers speaking The Eye of Satoshi (TEOS) user protocol, so
+//! federation members can hand breach responses to towers they do not run.
+//!
+//! A session starts with `register`, which gives the tower the user's public key and returns the
+//! subscription (slots and expiry). Each appointment is the response transaction encrypted with
+//! ChaCha20-Poly1305 under `sha256(breach txid)` (zero nonce) and filed under its `Locator`, the
+//! first 16 bytes of the breach txid: the tower learns nothing until the breach is on chain, and
+//! then can decrypt and broadcast the response. Requests are signed by the user and receipts by
+//! the tower with Lightning message signatures (zbase32 recoverable ECDSA over
+//! `sha256d("Lightning Signed Message:" || message)`). A receipt signs the user's request
+//! signature followed by the tower's start block; receipts not signed by the expected tower are
+//! rejected.
+//!
+//! Clawbacks of a `watchtower::Watchtower` are uploaded with `upload_clawbacks`. Towers broadcast
+//! as soon as the breach appears, so only `Trigger::OnConfirmation` clawbacks with a deadline can
+//! be outsourced.
+
+use crate::secret::SigningKey;
+use crate::watchtower::{Trigger, Watchtower};
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::{sha256, sha256d, Hash};
+use bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use bitcoin::{Transaction, Txid};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde_json::{json, Value};
+use std::fmt;
+
+const MESSAGE_PREFIX: &[u8] = b"Lightning Signed Message:";
+const ZBASE32_ALPHABET: &[u8; 32] = b"ybndrfg8ejkmcpqxot1uwisza345h769";
+
+/// Where a tower files an appointment: the first 16 bytes of the breach txid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Locator(pub [u8; 16]);
+
+impl Locator {
+    pub fn new(breach: &Txid) -> Self {
+        let mut locator = [0u8; 16];
+        locator.copy_from_slice(&breach.as_byte_array()[..16]);
+        Self(locator)
+    }
+}
+
+impl fmt::Display for Locator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+fn blob_cipher(breach: &Txid) -> ChaCha20Poly1305 {
+    let key = sha256::Hash::hash(breach.as_byte_array());
+    ChaCha20Poly1305::new(Key::from_slice(key.as_byte_array()))
+}
+
+/// `response` encrypted so that only someone who has seen `breach` can read it
+pub fn encrypt_blob(response: &Transaction, breach: &Txid) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    blob_cipher(breach).encrypt(Nonce::from_slice(&[0u8; 12]), serialize(response).as_slice())
+        .map_err(|_| "cannot encrypt appointment".into())
+}
+
+pub fn decrypt_blob(blob: &[u8], breach: &Txid) -> Result<Transaction, Box<dyn std::error::Error>> {
+    let plaintext = blob_cipher(breach).decrypt(Nonce::from_slice(&[0u8; 12]), blob)
+        .map_err(|_| format!("appointment blob does not decrypt with breach {}", breach))?;
+    Ok(deserialize(&plaintext)?)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Appointment {
+    pub locator: Locator,
+    pub encrypted_blob: Vec<u8>,
+    /// Blocks the response has to confirm in once the breach is seen
+    pub to_self_delay: u32,
+}
+
+impl Appointment {
+    pub fn new(breach: &Txid, response: &Transaction, to_self_delay: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self { locator: Locator::new(breach), encrypted_blob: encrypt_blob(response, breach)?, to_self_delay })
+    }
+
+    /// The bytes the user signs: locator, blob, then the delay as big-endian u32
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut bytes = self.locator.0.to_vec();
+        bytes.extend_from_slice(&self.encrypted_blob);
+        bytes.extend_from_slice(&self.to_self_delay.to_be_bytes());
+        bytes
+    }
+}
+
+fn message_hash(message: &[u8]) -> Message {
+    let mut data = MESSAGE_PREFIX.to_vec();
+    data.extend_from_slice(message);
+    Message::from_slice(sha256d::Hash::hash(&data).as_byte_array()).expect("32 bytes")
+}
+
+/// Lightning message signature of `message` by `key`, zbase32 encoded
+pub fn sign_message(message: &[u8], key: &SigningKey) -> String {
+    let secp = Secp256k1::signing_only();
+    let (recovery_id, compact) = secp.sign_ecdsa_recoverable(&message_hash(message), &key.expose().inner).serialize_compact();
+    let mut bytes = vec![31 + recovery_id.to_i32() as u8];
+    bytes.extend_from_slice(&compact);
+    zbase32_encode(&bytes)
+}
+
+/// The key that made `signature` over `message`
+pub fn recover_signer(message: &[u8], signature: &str) -> Result<PublicKey, Box<dyn std::error::Error>> {
+    let bytes = zbase32_decode(signature)?;
+    if bytes.len() != 65 || !(31..=34).contains(&bytes[0]) {
+        return Err("malformed message signature".into());
+    }
+    let signature = RecoverableSignature::from_compact(&bytes[1..], RecoveryId::from_i32(bytes[0] as i32 - 31)?)?;
+    Ok(Secp256k1::verification_only().recover_ecdsa(&message_hash(message), &signature)?)
+}
+
+fn zbase32_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ZBASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ZBASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn zbase32_decode(s: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut out = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in s.bytes() {
+        let value = ZBASE32_ALPHABET.iter().position(|a| *a == c).ok_or("invalid zbase32 character")?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+/// What `register` got from the tower
+#[derive(Debug, Clone, PartialEq)]
+pub struct TowerSession {
+    pub available_slots: u32,
+    /// Block height the subscription ends at
+    pub subscription_expiry: u32,
+}
+
+/// The tower's signed acceptance of an appointment
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppointmentReceipt {
+    pub locator: Locator,
+    /// Our signature of the appointment, as sent
+    pub user_signature: String,
+    /// Height the tower started watching at
+    pub start_block: u32,
+    pub tower_signature: String,
+    pub available_slots: u32,
+}
+
+impl AppointmentReceipt {
+    /// The bytes the tower signs: the user signature string, then the start block as big-endian u32
+    pub fn to_vec(&self) -> Vec<u8> {
+        receipt_message(&self.user_signature, self.start_block)
+    }
+}
+
+/// What a tower signs to accept an appointment the user signed with `user_signature`
+pub fn receipt_message(user_signature: &str, start_block: u32) -> Vec<u8> {
+    let mut bytes = user_signature.as_bytes().to_vec();
+    bytes.extend_from_slice(&start_block.to_be_bytes());
+    bytes
+}
+
+/// What `upload_clawbacks` did
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadReport {
+    pub receipts: Vec<AppointmentReceipt>,
+    /// Clawbacks a tower cannot hold, with why
+    pub skipped: Vec<(Txid, String)>,
+}
+
+/// One federation member's account at one tower
+#[derive(Debug, Clone)]
+pub struct TowerClient {
+    /// e.g. `http://tower.example:9814`
+    pub url: String,
+    /// The tower's public key; receipts must be signed with it
+    pub tower_id: PublicKey,
+    pub user_key: SigningKey,
+    pub client: reqwest::Client,
+}
+
+impl TowerClient {
+    pub fn new(url: &str, tower_id: PublicKey, user_key: SigningKey) -> Self {
+        Self { url: url.trim_end_matches('/').to_string(), tower_id, user_key, client: reqwest::Client::new() }
+    }
+
+    pub fn user_id(&self) -> PublicKey {
+        self.user_key.expose().public_key(&Secp256k1::signing_only()).inner
+    }
+
+    async fn post(&self, endpoint: &str, body: Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let resp = self.client.post(format!("{}/{}", self.url, endpoint)).json(&body).send().await?;
+        let status = resp.status();
+        let reply: Value = resp.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            let reason = reply["error"].as_str().map(str::to_string).unwrap_or_else(|| reply.to_string());
+            return Err(format!("tower {} refused {}: {} {}", self.url, endpoint, status, reason).into());
+        }
+        Ok(reply)
+    }
+
+    /// Register (or renew) the subscription of this user
+    pub async fn register(&self) -> Result<TowerSession, Box<dyn std::error::Error>> {
+        let user_id = self.user_id().to_string();
+        let reply = self.post("register", json!({ "user_id": user_id })).await?;
+        if reply["user_id"].as_str() != Some(user_id.as_str()) {
+            return Err(format!("tower registered {} instead of {}", reply["user_id"], user_id).into());
+        }
+        Ok(TowerSession {
+            available_slots: u32_field(&reply, "available_slots")?,
+            subscription_expiry: u32_field(&reply, "subscription_expiry")?,
+        })
+    }
+
+    /// Hand the tower `response` to broadcast if `breach` appears
+    pub async fn add_appointment(&self, breach: &Txid, response: &Transaction, to_self_delay: u32) -> Result<AppointmentReceipt, Box<dyn std::error::Error>> {
+        let appointment = Appointment::new(breach, response, to_self_delay)?;
+        let user_signature = sign_message(&appointment.to_vec(), &self.user_key);
+        let reply = self.post("add_appointment", json!({
+            "appointment": {
+                "locator": appointment.locator.to_string(),
+                "encrypted_blob": hex::encode(&appointment.encrypted_blob),
+                "to_self_delay": to_self_delay,
+            },
+            "signature": user_signature,
+        })).await?;
+        let receipt = AppointmentReceipt {
+            locator: appointment.locator,
+            user_signature,
+            start_block: u32_field(&reply, "start_block")?,
+            tower_signature: reply["signature"].as_str().ok_or("tower receipt has no signature")?.to_string(),
+            available_slots: u32_field(&reply, "available_slots")?,
+        };
+        if recover_signer(&receipt.to_vec(), &receipt.tower_signature)? != self.tower_id {
+            return Err(format!("receipt for {} is not signed by tower {}", receipt.locator, self.tower_id).into());
+        }
+        Ok(receipt)
+    }
+
+    /// The tower's view of the appointment filed under `locator`
+    pub async fn get_appointment(&self, locator: &Locator) -> Result<Value, Box<dyn std::error::Error>> {
+        let message = format!("get appointment {}", locator);
+        self.post("get_appointment", json!({
+            "locator": locator.to_string(),
+            "signature": sign_message(message.as_bytes(), &self.user_key),
+        })).await
+    }
+
+    /// Upload every clawback of `watchtower` a tower can hold, keyed by the txid of the output it spends
+    pub async fn upload_clawbacks(&self, watchtower: &Watchtower) -> Result<UploadReport, Box<dyn std::error::Error>> {
+        let mut report = UploadReport { receipts: Vec::new(), skipped: Vec::new() };
+        for clawback in &watchtower.clawbacks {
+            if clawback.trigger != Trigger::OnConfirmation {
+                report.skipped.push((clawback.tx.txid(), "only broadcast-on-confirmation clawbacks can be outsourced".to_string()));
+                continue;
+            }
+            let delay = match clawback.deadline_blocks {
+                Some(delay) => delay,
+                None => {
+                    report.skipped.push((clawback.tx.txid(), "no deadline, so no to_self_delay to give the tower".to_string()));
+                    continue;
+                }
+            };
+            report.receipts.push(self.add_appointment(&clawback.spends.txid, &clawback.tx, delay).await?);
+        }
+        Ok(report)
+    }
+}
+
+fn u32_field(reply: &Value, name: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    let value = reply[name].as_u64().ok_or_else(|| format!("tower reply has no {}", name))?;
+    Ok(u32::try_from(value)?)
+}
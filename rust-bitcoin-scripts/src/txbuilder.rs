@@ -0,0 +1,255 @@
+//! One transaction spending outputs of different descriptors.
+//!
+//! `psbt::create` takes a single descriptor for every input; `TxBuilder` keeps a descriptor per
+//! input, so a P2SH multisig, a `wsh` timelock and a taproot leaf can be spent together. Each
+//! input's PSBT fields come from its own descriptor, and `psbt::sign` then picks the sighash
+//! algorithm per input: legacy for P2SH, BIP143 for segwit v0 and BIP341 over the spent
+//! outputs of every input (`Prevouts::All`) for taproot. Legacy inputs need `prev_tx`, which
+//! also supplies their spent output to the taproot sighash.
+//!
+//! Inputs signal replaceability (BIP125) unless `rbf(false)` is set; inputs carrying a relative
+//! timelock always do. With a change output and stored signers, `bump_fee` replaces an
+//! unconfirmed transaction by taking the extra fee from the change and signing again.
+//!
+//! When the stuck transaction cannot be replaced, `create_cpfp_child` spends one of its outputs
+//! that we control instead, paying enough for the child and the parent's unconfirmed ancestors
+//! to reach the target rate together.
+
+use crate::backend::ChainBackend;
+use crate::fees;
+use crate::outputs::dust_limit;
+use crate::psbt::{self, SpendableUtxo};
+use crate::receipt::SpendReceipt;
+use crate::rpc_types::MempoolEntry;
+use crate::secret::SigningKey;
+use bitcoin::absolute::LockTime;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{Amount, FeeRate, OutPoint, PrivateKey, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuilderInput {
+    pub descriptor: Descriptor<PublicKey>,
+    pub utxo: SpendableUtxo,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxBuilder {
+    pub inputs: Vec<BuilderInput>,
+    pub outputs: Vec<TxOut>,
+    pub lock_time: LockTime,
+    /// Index in `outputs` of the change, which pays for fee bumps
+    pub change: Option<usize>,
+    /// Signal replaceability on inputs with the default sequence; on by default
+    pub rbf: bool,
+    /// Keys `sign_stored` and `bump_fee` sign with
+    pub signers: Vec<SigningKey>,
+}
+
+impl Default for TxBuilder {
+    fn default() -> Self {
+        Self { inputs: Vec::new(), outputs: Vec::new(), lock_time: LockTime::ZERO, change: None, rbf: true, signers: Vec::new() }
+    }
+}
+
+/// Whether any input of `tx` signals BIP125 replaceability
+pub fn signals_rbf(tx: &Transaction) -> bool {
+    tx.input.iter().any(|i| i.sequence.is_rbf())
+}
+
+/// A fee bump that was signed and broadcast
+#[derive(Debug, Clone, PartialEq)]
+pub struct BumpedTx {
+    /// Builder of the replacement, for bumping again
+    pub builder: TxBuilder,
+    pub transaction: Transaction,
+    pub receipt: SpendReceipt,
+}
+
+/// A signed child paying for its unconfirmed parent
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpfpChild {
+    pub builder: TxBuilder,
+    pub transaction: Transaction,
+    pub fee: Amount,
+}
+
+impl TxBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spend `utxo`, locked by `descriptor`; its sequence is used as is
+    pub fn add_input(&mut self, descriptor: &Descriptor<PublicKey>, utxo: SpendableUtxo) -> Result<&mut Self, Box<dyn std::error::Error>> {
+        if descriptor.script_pubkey() != utxo.txout.script_pubkey {
+            return Err(format!("{}: descriptor does not produce the spent scriptPubKey", utxo.outpoint).into());
+        }
+        if self.inputs.iter().any(|i| i.utxo.outpoint == utxo.outpoint) {
+            return Err(format!("{} is already an input", utxo.outpoint).into());
+        }
+        if descriptor.desc_type().segwit_version().is_none() && utxo.prev_tx.is_none() {
+            return Err(format!("{}: non-segwit inputs need the previous transaction", utxo.outpoint).into());
+        }
+        self.inputs.push(BuilderInput { descriptor: descriptor.clone(), utxo });
+        Ok(self)
+    }
+
+    pub fn add_output(&mut self, output: TxOut) -> &mut Self {
+        self.outputs.push(output);
+        self
+    }
+
+    /// Add the change output; fee bumps are taken from it
+    pub fn add_change(&mut self, output: TxOut) -> &mut Self {
+        self.change = Some(self.outputs.len());
+        self.outputs.push(output);
+        self
+    }
+
+    pub fn lock_time(&mut self, lock_time: LockTime) -> &mut Self {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// `false` opts out of replaceability: inputs with the default sequence get
+    /// `ENABLE_LOCKTIME_NO_RBF` instead
+    pub fn rbf(&mut self, enabled: bool) -> &mut Self {
+        self.rbf = enabled;
+        self
+    }
+
+    pub fn add_signer(&mut self, key: SigningKey) -> &mut Self {
+        self.signers.push(key);
+        self
+    }
+
+    fn sequence(&self, utxo: &SpendableUtxo) -> Sequence {
+        if !self.rbf && utxo.sequence == psbt::DEFAULT_SEQUENCE {
+            Sequence::ENABLE_LOCKTIME_NO_RBF
+        } else {
+            utxo.sequence
+        }
+    }
+
+    pub fn input_value(&self) -> Amount {
+        self.inputs.iter().map(|i| Amount::from_sat(i.utxo.txout.value)).sum()
+    }
+
+    /// Inputs minus outputs
+    pub fn fee(&self) -> Result<Amount, Box<dyn std::error::Error>> {
+        let outputs = self.outputs.iter().map(|o| Amount::from_sat(o.value)).sum::<Amount>();
+        self.input_value().checked_sub(outputs).ok_or_else(|| format!("outputs ({}) exceed inputs ({})", outputs, self.input_value()).into())
+    }
+
+    /// Unsigned PSBT with every input filled from its own descriptor
+    pub fn psbt(&self) -> Result<Psbt, Box<dyn std::error::Error>> {
+        if self.inputs.is_empty() || self.outputs.is_empty() {
+            return Err("a transaction needs at least one input and one output".into());
+        }
+        self.fee()?;
+        let tx = Transaction {
+            version: 2,
+            lock_time: self.lock_time,
+            input: self.inputs.iter().map(|i| TxIn { previous_output: i.utxo.outpoint, script_sig: ScriptBuf::new(), sequence: self.sequence(&i.utxo), witness: Witness::new() }).collect(),
+            output: self.outputs.clone(),
+        };
+        let mut unsigned = Psbt::from_unsigned_tx(tx)?;
+        for (index, input) in self.inputs.iter().enumerate() {
+            let definite = Descriptor::<DefiniteDescriptorKey>::from_str(&input.descriptor.to_string())?;
+            psbt::update_input(&mut unsigned, index, &definite, &input.utxo)?;
+        }
+        Ok(unsigned)
+    }
+
+    /// Sign every input with `keys` and finalize; fails unless every input is fully signed
+    pub fn sign(&self, keys: &[PrivateKey]) -> Result<Transaction, Box<dyn std::error::Error>> {
+        let mut unsigned = self.psbt()?;
+        psbt::sign(&mut unsigned, keys)?;
+        psbt::finalize(unsigned)
+    }
+
+    /// `sign` with the stored signers
+    pub fn sign_stored(&self) -> Result<Transaction, Box<dyn std::error::Error>> {
+        if self.signers.is_empty() {
+            return Err("no stored signers".into());
+        }
+        let keys: Vec<PrivateKey> = self.signers.iter().map(SigningKey::expose).collect();
+        self.sign(&keys)
+    }
+
+    /// Builder for a replacement of `original` (built by this builder) paying `new_feerate`,
+    /// with the extra fee taken from the change. The new fee also covers BIP125's increment:
+    /// at least the old fee plus 1 sat/vB of the replacement.
+    pub fn bumped(&self, original: &Transaction, new_feerate: FeeRate) -> Result<TxBuilder, Box<dyn std::error::Error>> {
+        if !signals_rbf(original) {
+            return Err(format!("{} does not signal replaceability", original.txid()).into());
+        }
+        let same_inputs = original.input.len() == self.inputs.len() && original.input.iter().zip(&self.inputs).all(|(a, b)| a.previous_output == b.utxo.outpoint);
+        if !same_inputs || original.output != self.outputs {
+            return Err(format!("{} was not built by this builder", original.txid()).into());
+        }
+        let change = self.change.ok_or("no change output to take the fee from")?;
+        let old_fee = self.fee()?;
+        let at_rate = fees::fee_at(new_feerate, original.weight()).ok_or("fee overflow")?;
+        let increment = fees::fee_at_vsize(FeeRate::BROADCAST_MIN, original.vsize() as u64).ok_or("fee overflow")?;
+        let new_fee = at_rate.max(old_fee + increment);
+        let mut bumped = self.clone();
+        let output = &mut bumped.outputs[change];
+        let value = Amount::from_sat(output.value)
+            .checked_sub(new_fee - old_fee)
+            .filter(|v| *v >= dust_limit(&output.script_pubkey))
+            .ok_or_else(|| format!("change of {} sat cannot pay a fee of {}", output.value, new_fee))?;
+        output.value = value.to_sat();
+        Ok(bumped)
+    }
+}
+
+/// Replace the unconfirmed `original`, built by `builder`, with a version paying `new_feerate`:
+/// the change shrinks, every input is signed again with the stored signers and the replacement
+/// is broadcast.
+pub async fn bump_fee(backend: &impl ChainBackend, builder: &TxBuilder, original: &Transaction, new_feerate: FeeRate) -> Result<BumpedTx, Box<dyn std::error::Error>> {
+    let bumped = builder.bumped(original, new_feerate)?;
+    let transaction = bumped.sign_stored()?;
+    let receipt = SpendReceipt::broadcast(backend, &transaction, bumped.fee()?, "rbf").await?;
+    Ok(BumpedTx { builder: bumped, transaction, receipt })
+}
+
+/// Builder for a child spending output `vout` of `parent`, locked by `descriptor`, to
+/// `destination`. The fee brings the child and the ancestors in `parent_entry` to `feerate_target`;
+/// a parent that already pays enough still gets a child paying the target for itself.
+pub fn cpfp_builder(parent: &Transaction, vout: u32, parent_entry: &MempoolEntry, feerate_target: FeeRate, descriptor: &Descriptor<PublicKey>, destination: ScriptBuf) -> Result<TxBuilder, Box<dyn std::error::Error>> {
+    let txout = parent.output.get(vout as usize).ok_or_else(|| format!("{} has no output {}", parent.txid(), vout))?.clone();
+    let mut utxo = SpendableUtxo::new(OutPoint::new(parent.txid(), vout), txout.clone());
+    utxo.prev_tx = Some(parent.clone());
+    let output = TxOut { value: 0, script_pubkey: destination };
+    let child_vsize = fees::vsize(&[descriptor], std::slice::from_ref(&output))?;
+    let own = fees::fee_at_vsize(feerate_target, child_vsize).ok_or("fee overflow")?;
+    let package = fees::fee_at_vsize(feerate_target, parent_entry.ancestor_size + child_vsize).ok_or("fee overflow")?;
+    let fee = package.checked_sub(parent_entry.fees.ancestor).unwrap_or(Amount::ZERO).max(own);
+    let value = Amount::from_sat(txout.value)
+        .checked_sub(fee)
+        .filter(|v| *v >= dust_limit(&output.script_pubkey))
+        .ok_or_else(|| format!("output of {} sat cannot pay a fee of {}", txout.value, fee))?;
+    let mut builder = TxBuilder::new();
+    builder.add_input(descriptor, utxo)?;
+    builder.add_output(TxOut { value: value.to_sat(), ..output });
+    Ok(builder)
+}
+
+/// Child of the unconfirmed `parent_txid` spending its output `vout`, which `descriptor` locks
+/// and `signers` can sign, to `destination`, paying enough for the package to reach
+/// `feerate_target`. The parent's fee comes from `ChainBackend::mempool_entry`. The child is signed but not
+/// broadcast; relay it with `SpendReceipt::broadcast`.
+pub async fn create_cpfp_child(backend: &impl ChainBackend, parent_txid: &Txid, vout: u32, feerate_target: FeeRate, descriptor: &Descriptor<PublicKey>, signers: &[SigningKey], destination: ScriptBuf) -> Result<CpfpChild, Box<dyn std::error::Error>> {
+    let entry = backend.mempool_entry(parent_txid).await?;
+    let parent = backend.transaction(parent_txid).await?;
+    let mut builder = cpfp_builder(&parent, vout, &entry, feerate_target, descriptor, destination)?;
+    for signer in signers {
+        builder.add_signer(signer.clone());
+    }
+    let transaction = builder.sign_stored()?;
+    let fee = builder.fee()?;
+    Ok(CpfpChild { builder, transaction, fee })
+}
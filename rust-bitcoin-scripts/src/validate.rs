@@ -0,0 +1,34 @@
+//! Pre-broadcast validation of hand-built witnesses, naming the stack element that failed.
+//!
+//! The checks themselves are `verify`'s (the miniscript interpreter decides, failures are
+//! replayed op by op by `interpreter::trace_input`); this module is the front for them.
+//! `failure_point` answers the debugging question directly: the op an input stops at and the
+//! `Blame` that op was stopped by, taken from the replay rather than inferred from the error.
+
+use crate::interpreter::trace_input;
+use bitcoin::sighash::Prevouts;
+use bitcoin::{Transaction, TxOut};
+
+pub use crate::interpreter::{Blame, ExecutionTrace, TraceStep};
+pub use crate::verify::{prevouts, verify_and_send, verify_input, verify_input_with, verify_spend, InputFailure, VerifyError};
+
+/// Where a replayed input stopped
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailurePoint {
+    /// The failing op, with the stack it was given
+    pub step: TraceStep,
+    pub error: String,
+    /// The stack element (or timelock field) the op rejected; `None` if the op failed on
+    /// something the script pushed itself
+    pub blame: Option<Blame>,
+}
+
+/// Replay input `input_index` of `tx` against `prevouts` (the outputs every input spends, in
+/// input order) and return the op it fails at; `None` if it succeeds or has no script to
+/// replay (taproot key path)
+pub fn failure_point(tx: &Transaction, input_index: usize, prevouts: &[TxOut]) -> Option<FailurePoint> {
+    let prevout = prevouts.get(input_index)?;
+    let trace = trace_input(tx, input_index, prevout, &Prevouts::All(prevouts))?;
+    let step = trace.failing_step()?.clone();
+    Some(FailurePoint { step, error: trace.error?, blame: trace.blame })
+}
@@ -0,0 +1,234 @@
+//! Peg-in / peg-out state machine of a wrapYield vault.
+//!
+//! `Deposit -> Locked -> YieldAccrual -> Unbonding -> Withdrawn`:
+//! - Deposit: the user's funding transaction pays the locked descriptor, a `threshold`-of-n
+//!   federation multisig; it becomes Locked after `min_confirmations`.
+//! - YieldAccrual: the wrapped token is live on the other chain; nothing moves on Bitcoin.
+//! - Unbonding: the federation moves the coins to the unbonding descriptor, which the user can
+//!   spend after `unbonding_csv` blocks and the federation can claw back before that.
+//! - Withdrawn: the user's spend of the unbonding output.
+//!
+//! Transitions that move coins take the signed transaction and check it against the state
+//! (right outpoint, right script, amount, sequence) and with `verify::verify_spend` before
+//! advancing. The state serializes to JSON so a restarted service resumes where it was.
+//! A frozen vault (`freeze`, see `incident`) refuses to build peg-out transactions.
+
+use crate::amount::deduct_fee_for;
+use crate::classic_multisig::{multisig_descriptor, MultisigKind};
+use crate::psbt::{self, SpendableUtxo};
+use crate::state_file;
+use crate::verify::verify_spend;
+use bitcoin::absolute::LockTime;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{Amount, Network, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxOut, Txid};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultParams {
+    pub user: PublicKey,
+    pub federation: Vec<PublicKey>,
+    pub threshold: usize,
+    /// Blocks the unbonding output must age before the user can withdraw
+    pub unbonding_csv: u16,
+    pub min_confirmations: u32,
+    pub network: Network,
+}
+
+impl VaultParams {
+    /// Federation custody while the deposit is locked
+    pub fn locked_descriptor(&self) -> Result<Descriptor<PublicKey>, Box<dyn std::error::Error>> {
+        multisig_descriptor(self.threshold, &self.federation, MultisigKind::Wsh)
+    }
+
+    /// The federation any time, or the user after `unbonding_csv` blocks
+    pub fn unbonding_descriptor(&self) -> Result<Descriptor<PublicKey>, Box<dyn std::error::Error>> {
+        let federation: Vec<String> = self.federation.iter().map(|k| k.to_string()).collect();
+        Ok(Descriptor::from_str(&format!(
+            "wsh(or_d(multi({},{}),and_v(v:pk({}),older({}))))",
+            self.threshold, federation.join(","), self.user, self.unbonding_csv
+        ))?)
+    }
+}
+
+/// Where a vault is; every state carries the output currently holding the coins
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum VaultState {
+    /// `height` is `None` while the funding transaction is unconfirmed
+    Deposit { utxo: OutPoint, #[serde(with = "bitcoin::amount::serde::as_sat")] amount: Amount, height: Option<u64> },
+    Locked { utxo: OutPoint, #[serde(with = "bitcoin::amount::serde::as_sat")] amount: Amount, height: u64 },
+    YieldAccrual { utxo: OutPoint, #[serde(with = "bitcoin::amount::serde::as_sat")] amount: Amount, since_height: u64 },
+    Unbonding { utxo: OutPoint, #[serde(with = "bitcoin::amount::serde::as_sat")] amount: Amount, height: Option<u64> },
+    Withdrawn { txid: Txid, #[serde(with = "bitcoin::amount::serde::as_sat")] amount: Amount },
+}
+
+impl VaultState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VaultState::Deposit { .. } => "deposit",
+            VaultState::Locked { .. } => "locked",
+            VaultState::YieldAccrual { .. } => "yield_accrual",
+            VaultState::Unbonding { .. } => "unbonding",
+            VaultState::Withdrawn { .. } => "withdrawn",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Protocol {
+    pub params: VaultParams,
+    pub state: VaultState,
+    /// Why peg-outs are frozen, if they are
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frozen: Option<String>,
+}
+
+fn wrong_state(expected: &str, state: &VaultState) -> Box<dyn std::error::Error> {
+    format!("expected the {} state, vault is in {}", expected, state.name()).into()
+}
+
+fn definite(descriptor: &Descriptor<PublicKey>) -> Result<Descriptor<DefiniteDescriptorKey>, Box<dyn std::error::Error>> {
+    Ok(Descriptor::from_str(&descriptor.to_string())?)
+}
+
+/// Check that `tx` spends exactly `utxo` (locked by `prevout`) into a first output paying
+/// `script_pubkey`, and that the spend is valid
+fn check_spend(tx: &Transaction, utxo: OutPoint, prevout: &TxOut, script_pubkey: &ScriptBuf) -> Result<Amount, Box<dyn std::error::Error>> {
+    if tx.input.len() != 1 || tx.input[0].previous_output != utxo {
+        return Err(format!("transaction must spend exactly {}", utxo).into());
+    }
+    let output = tx.output.first().filter(|o| &o.script_pubkey == script_pubkey).ok_or("first output does not pay the expected script")?;
+    verify_spend(tx, std::slice::from_ref(prevout))?;
+    Ok(Amount::from_sat(output.value))
+}
+
+impl Protocol {
+    /// Start from the user's funding transaction; `height` if it is already confirmed
+    pub fn deposit(params: VaultParams, funding: &Transaction, height: Option<u64>) -> Result<Self, Box<dyn std::error::Error>> {
+        let script_pubkey = params.locked_descriptor()?.script_pubkey();
+        let vout = funding.output.iter().position(|o| o.script_pubkey == script_pubkey).ok_or("funding transaction does not pay the vault")?;
+        let amount = Amount::from_sat(funding.output[vout].value);
+        Ok(Self { params, state: VaultState::Deposit { utxo: OutPoint::new(funding.txid(), vout as u32), amount, height }, frozen: None })
+    }
+
+    /// The output holding the coins and what it is worth, until withdrawn
+    pub fn utxo(&self) -> Option<(OutPoint, Amount)> {
+        match &self.state {
+            VaultState::Deposit { utxo, amount, .. }
+            | VaultState::Locked { utxo, amount, .. }
+            | VaultState::YieldAccrual { utxo, amount, .. }
+            | VaultState::Unbonding { utxo, amount, .. } => Some((*utxo, *amount)),
+            VaultState::Withdrawn { .. } => None,
+        }
+    }
+
+    /// Stop building unbonding and withdrawal transactions until `unfreeze`. Transitions that
+    /// record transactions already signed still apply, so the state follows the chain.
+    pub fn freeze(&mut self, reason: &str) {
+        self.frozen = Some(reason.to_string());
+    }
+
+    pub fn unfreeze(&mut self) {
+        self.frozen = None;
+    }
+
+    fn check_not_frozen(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.frozen {
+            Some(reason) => Err(format!("peg-outs are frozen: {}", reason).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Deposit -> Locked once the funding transaction, mined at `height`, has enough confirmations at `tip`
+    pub fn lock(&mut self, height: u64, tip: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let VaultState::Deposit { utxo, amount, .. } = self.state else { return Err(wrong_state("deposit", &self.state)) };
+        let confirmations = (tip + 1).saturating_sub(height);
+        if confirmations < self.params.min_confirmations as u64 {
+            self.state = VaultState::Deposit { utxo, amount, height: Some(height) };
+            return Err(format!("deposit has {} of {} confirmations", confirmations, self.params.min_confirmations).into());
+        }
+        self.state = VaultState::Locked { utxo, amount, height };
+        Ok(())
+    }
+
+    /// Locked -> YieldAccrual: the wrapped token was minted at Bitcoin height `tip`
+    pub fn start_accrual(&mut self, tip: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let VaultState::Locked { utxo, amount, .. } = self.state else { return Err(wrong_state("locked", &self.state)) };
+        self.state = VaultState::YieldAccrual { utxo, amount, since_height: tip };
+        Ok(())
+    }
+
+    /// Unsigned federation transaction moving the locked coins to the unbonding descriptor
+    pub fn build_unbonding(&self, fee: Amount) -> Result<Psbt, Box<dyn std::error::Error>> {
+        self.check_not_frozen()?;
+        let VaultState::YieldAccrual { utxo, amount, .. } = self.state else { return Err(wrong_state("yield_accrual", &self.state)) };
+        let locked = self.params.locked_descriptor()?;
+        let unbonding = self.params.unbonding_descriptor()?.script_pubkey();
+        let value = deduct_fee_for(amount, fee, &unbonding)?;
+        let spendable = SpendableUtxo::new(utxo, TxOut { value: amount.to_sat(), script_pubkey: locked.script_pubkey() });
+        psbt::create(&definite(&locked)?, &[spendable], vec![TxOut { value: value.to_sat(), script_pubkey: unbonding }], LockTime::ZERO)
+    }
+
+    /// YieldAccrual -> Unbonding with the signed unbonding transaction
+    pub fn unbond(&mut self, signed: &Transaction) -> Result<(), Box<dyn std::error::Error>> {
+        let VaultState::YieldAccrual { utxo, amount, .. } = self.state else { return Err(wrong_state("yield_accrual", &self.state)) };
+        let prevout = TxOut { value: amount.to_sat(), script_pubkey: self.params.locked_descriptor()?.script_pubkey() };
+        let value = check_spend(signed, utxo, &prevout, &self.params.unbonding_descriptor()?.script_pubkey())?;
+        self.state = VaultState::Unbonding { utxo: OutPoint::new(signed.txid(), 0), amount: value, height: None };
+        Ok(())
+    }
+
+    /// Record the height the unbonding transaction confirmed at
+    pub fn unbonding_confirmed(&mut self, height: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let VaultState::Unbonding { utxo, amount, .. } = self.state else { return Err(wrong_state("unbonding", &self.state)) };
+        self.state = VaultState::Unbonding { utxo, amount, height: Some(height) };
+        Ok(())
+    }
+
+    /// First height a withdrawal can be mined at, once the unbonding transaction confirmed
+    pub fn withdrawable_at(&self) -> Option<u64> {
+        match self.state {
+            VaultState::Unbonding { height: Some(height), .. } => Some(height + self.params.unbonding_csv as u64),
+            _ => None,
+        }
+    }
+
+    /// Unsigned user transaction paying the unbonded coins to `destination`; only valid for
+    /// inclusion from `withdrawable_at` on, which must be no later than the block after `tip`
+    pub fn build_withdrawal(&self, destination: ScriptBuf, fee: Amount, tip: u64) -> Result<Psbt, Box<dyn std::error::Error>> {
+        self.check_not_frozen()?;
+        let VaultState::Unbonding { utxo, amount, .. } = self.state else { return Err(wrong_state("unbonding", &self.state)) };
+        let ready = self.withdrawable_at().ok_or("unbonding transaction is not confirmed yet")?;
+        if tip + 1 < ready {
+            return Err(format!("unbonding delay ends at height {}, tip is {}", ready, tip).into());
+        }
+        let unbonding = self.params.unbonding_descriptor()?;
+        let value = deduct_fee_for(amount, fee, &destination)?;
+        let mut spendable = SpendableUtxo::new(utxo, TxOut { value: amount.to_sat(), script_pubkey: unbonding.script_pubkey() });
+        spendable.sequence = Sequence::from_height(self.params.unbonding_csv);
+        psbt::create(&definite(&unbonding)?, &[spendable], vec![TxOut { value: value.to_sat(), script_pubkey: destination }], LockTime::ZERO)
+    }
+
+    /// Unbonding -> Withdrawn with the user's signed withdrawal
+    pub fn withdraw(&mut self, signed: &Transaction) -> Result<(), Box<dyn std::error::Error>> {
+        let VaultState::Unbonding { utxo, amount, .. } = self.state else { return Err(wrong_state("unbonding", &self.state)) };
+        let prevout = TxOut { value: amount.to_sat(), script_pubkey: self.params.unbonding_descriptor()?.script_pubkey() };
+        let destination = signed.output.first().ok_or("withdrawal has no output")?.script_pubkey.clone();
+        let value = check_spend(signed, utxo, &prevout, &destination)?;
+        self.state = VaultState::Withdrawn { txid: signed.txid(), amount: value };
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        state_file::save_json(path, self)
+    }
+
+    /// `None` if nothing was saved yet
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        state_file::load_json(path)
+    }
+}
@@ -0,0 +1,168 @@
+//! One-call check of a finished transaction: every input's scriptSig and witness are run against
+//! the output it spends with the miniscript interpreter, before anything reaches a node.
+//!
+//! This is the last step of `psbt::finalize`, `Migration::sign_spend` and the witness builders
+//! (`TaprootVault::sign_leaf`, `classic_multisig::sign_p2sh_multisig`, `Htlc::claim_witness`,
+//! ...), and what `differential::local_verdict` reports. Timelocks are checked against the
+//! transaction's own sequence and locktime fields only; whether the chain is far enough along is
+//! the node's call.
+//!
+//! Every failing input carries an `ExecutionTrace`: its script replayed op by op by
+//! `interpreter::trace_input`, with the stack before and after each op, up to the one that
+//! failed. Its `Blame` is what that op was stopped by: the witness element it rejected (the
+//! signature no remaining key accepts, within a multisig too) or nLockTime/nSequence for an
+//! unmet timelock.
+//!
+//! `verify_and_send` runs the check against prevouts fetched from the node before handing the
+//! transaction to `BitcoinRPC::test_and_send`.
+
+use crate::interpreter::trace_input;
+use crate::test_setup::BitcoinRPC;
+use bitcoin::consensus::encode::deserialize;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::sighash::Prevouts;
+use bitcoin::{Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::interpreter::Error as InterpreterError;
+use miniscript::Interpreter;
+use std::borrow::Borrow;
+use std::fmt;
+
+pub use crate::interpreter::{Blame, ExecutionTrace, TraceStep};
+
+/// One input that does not satisfy its prevout
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputFailure {
+    pub input_index: usize,
+    pub reason: String,
+    /// The input's script replayed op by op (`interpreter::trace_input`); `None` if it has no
+    /// script to step through
+    pub trace: Option<ExecutionTrace>,
+    pub blame: Option<Blame>,
+}
+
+impl fmt::Display for InputFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "input {}: {}", self.input_index, self.reason)?;
+        if let Some(blame) = &self.blame {
+            write!(f, ", blaming {}", blame)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyError {
+    PrevoutCount { inputs: usize, prevouts: usize },
+    /// Every failing input, in input order
+    Inputs(Vec<InputFailure>),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::PrevoutCount { inputs, prevouts } => write!(f, "{} prevouts for {} inputs", prevouts, inputs),
+            VerifyError::Inputs(failures) => {
+                let reasons: Vec<String> = failures.iter().map(InputFailure::to_string).collect();
+                write!(f, "{}", reasons.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Check every input of `tx` against `prevouts` (the outputs it spends, in input order)
+pub fn verify_spend(tx: &Transaction, prevouts: &[TxOut]) -> Result<(), VerifyError> {
+    if prevouts.len() != tx.input.len() {
+        return Err(VerifyError::PrevoutCount { inputs: tx.input.len(), prevouts: prevouts.len() });
+    }
+    let failures: Vec<InputFailure> = (0..tx.input.len())
+        .filter_map(|index| check_input(tx, index, &prevouts[index], &Prevouts::All(prevouts)).err())
+        .collect();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(VerifyError::Inputs(failures))
+    }
+}
+
+/// Check input `input_index` alone. Only the spent output is known, which is enough for every
+/// input type except taproot (its signatures commit to all prevouts).
+pub fn verify_input(tx: &Transaction, input_index: usize, prevout: &TxOut) -> Result<(), VerifyError> {
+    if input_index >= tx.input.len() {
+        return Err(VerifyError::PrevoutCount { inputs: tx.input.len(), prevouts: input_index + 1 });
+    }
+    check_input(tx, input_index, prevout, &Prevouts::One(input_index, prevout)).map_err(|e| VerifyError::Inputs(vec![e]))
+}
+
+/// Check input `input_index` alone against `prevouts` (the outputs every input spends, in input
+/// order), as taproot inputs need; the other inputs may still be unsigned
+pub fn verify_input_with(tx: &Transaction, input_index: usize, prevouts: &[TxOut]) -> Result<(), VerifyError> {
+    if prevouts.len() != tx.input.len() || input_index >= tx.input.len() {
+        return Err(VerifyError::PrevoutCount { inputs: tx.input.len(), prevouts: prevouts.len() });
+    }
+    check_input(tx, input_index, &prevouts[input_index], &Prevouts::All(prevouts)).map_err(|e| VerifyError::Inputs(vec![e]))
+}
+
+/// Check `witness` as the witness of input `input_index` of `tx` against `prevouts` (the
+/// outputs every input spends, in input order), before a builder hands it out
+pub fn verify_witness(tx: &Transaction, input_index: usize, prevouts: &[TxOut], witness: &Witness) -> Result<(), VerifyError> {
+    let mut tx = tx.clone();
+    if let Some(input) = tx.input.get_mut(input_index) {
+        input.witness = witness.clone();
+    }
+    verify_input_with(&tx, input_index, prevouts)
+}
+
+fn check_input<T: Borrow<TxOut>>(tx: &Transaction, index: usize, prevout: &TxOut, prevouts: &Prevouts<'_, T>) -> Result<(), InputFailure> {
+    let secp = Secp256k1::verification_only();
+    let input = &tx.input[index];
+    let failure = |reason: String, error: Option<&InterpreterError>| {
+        let trace = trace_input(tx, index, prevout, prevouts);
+        let blame = match &trace {
+            Some(trace) => trace.blame.clone(),
+            None => error.and_then(|e| key_path_blame(e, input)),
+        };
+        InputFailure { input_index: index, reason, trace, blame }
+    };
+    let interpreter = Interpreter::from_txdata(&prevout.script_pubkey, &input.script_sig, &input.witness, input.sequence, tx.lock_time)
+        .map_err(|e| failure(e.to_string(), None))?;
+    for step in interpreter.iter(&secp, tx, index, prevouts) {
+        if let Err(e) = step {
+            return Err(failure(e.to_string(), Some(&e)));
+        }
+    }
+    Ok(())
+}
+
+/// What to blame on an input with no script to replay: the signature of a taproot key-path
+/// spend, the only element it has (an annex is never blamed)
+fn key_path_blame(error: &InterpreterError, input: &TxIn) -> Option<Blame> {
+    match error {
+        InterpreterError::InvalidSchnorrSignature(_) | InterpreterError::SchnorrSig(_) | InterpreterError::InvalidSchnorrSighashType(_) => {
+            input.witness.nth(0).map(|sig| Blame::Element { index: 0, value: sig.to_vec() })
+        }
+        _ => None,
+    }
+}
+
+/// The outputs `tx` spends, in input order, from the node
+pub async fn prevouts(rpc: &BitcoinRPC, tx: &Transaction) -> Result<Vec<TxOut>, Box<dyn std::error::Error>> {
+    let mut prevouts = Vec::with_capacity(tx.input.len());
+    for input in &tx.input {
+        let outpoint = input.previous_output;
+        let raw = rpc.get_raw_transaction_verbose(&outpoint.txid).await?;
+        let prev: Transaction = deserialize(&hex::decode(&raw.hex)?)?;
+        let output = prev.output.get(outpoint.vout as usize).cloned()
+            .ok_or_else(|| format!("{} has no output {}", outpoint.txid, outpoint.vout))?;
+        prevouts.push(output);
+    }
+    Ok(prevouts)
+}
+
+/// `verify_spend` against the prevouts on the node, then `test_and_send`
+pub async fn verify_and_send(rpc: &BitcoinRPC, tx: &Transaction) -> Result<Txid, Box<dyn std::error::Error>> {
+    let prevouts = prevouts(rpc, tx).await?;
+    verify_spend(tx, &prevouts)?;
+    rpc.test_and_send(tx).await
+}
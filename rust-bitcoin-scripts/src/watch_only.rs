@@ -0,0 +1,84 @@
+//! Watch-only auditing mode: descriptors and public keys only, never any private key material.
+//!
+//! Third-party attestors load the vault descriptors through `WatchOnly::load`, which refuses to
+//! start if anything it was given contains a private key (xprv/tprv, WIF, or a descriptor whose
+//! parse yields a non-empty key map).
+
+use crate::scanner::BlockScanner;
+use miniscript::bitcoin::PrivateKey;
+use miniscript::descriptor::DescriptorPublicKey;
+use miniscript::Descriptor;
+use bitcoin::secp256k1::Secp256k1;
+use std::fmt;
+
+#[derive(Debug)]
+pub struct SecretsLoaded {
+    /// Index of the offending input and what was found, never the secret itself
+    pub findings: Vec<(usize, &'static str)>,
+}
+
+impl fmt::Display for SecretsLoaded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "watch-only mode refused private key material:")?;
+        for (index, kind) in &self.findings {
+            write!(f, " [input {}: {}]", index, kind)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SecretsLoaded {}
+
+/// The "no secrets loaded" assertion: fails if any input carries private key material
+pub fn assert_no_secrets(inputs: &[String]) -> Result<(), SecretsLoaded> {
+    let secp = Secp256k1::new();
+    let mut findings = Vec::new();
+    for (index, input) in inputs.iter().enumerate() {
+        if let Ok((_, key_map)) = Descriptor::parse_descriptor(&secp, input) {
+            if !key_map.is_empty() {
+                findings.push((index, "descriptor with private keys"));
+                continue;
+            }
+        }
+        for token in input.split(|c: char| !c.is_ascii_alphanumeric()) {
+            if token.starts_with("xprv") || token.starts_with("tprv") {
+                findings.push((index, "extended private key"));
+            } else if PrivateKey::from_wif(token).is_ok() {
+                findings.push((index, "WIF private key"));
+            }
+        }
+    }
+    if findings.is_empty() {
+        Ok(())
+    } else {
+        Err(SecretsLoaded { findings })
+    }
+}
+
+/// Public-only view of a set of vault descriptors
+pub struct WatchOnly {
+    pub descriptors: Vec<Descriptor<DescriptorPublicKey>>,
+}
+
+impl WatchOnly {
+    /// Parse descriptors after asserting no secrets were supplied
+    pub fn load(descriptors: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        assert_no_secrets(descriptors)?;
+        let descriptors = descriptors.iter()
+            .map(|d| d.parse::<Descriptor<DescriptorPublicKey>>())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { descriptors })
+    }
+
+    /// A scanner watching every script of these descriptors (indices `0..lookahead` for ranged ones)
+    pub fn scanner(&self, lookahead: u32) -> Result<BlockScanner, Box<dyn std::error::Error>> {
+        let mut scanner = BlockScanner::new();
+        for descriptor in &self.descriptors {
+            let range = if descriptor.has_wildcard() { 0..lookahead } else { 0..1 };
+            for index in range {
+                scanner.watch_script(descriptor.at_derivation_index(index)?.script_pubkey());
+            }
+        }
+        Ok(scanner)
+    }
+}
@@ -0,0 +1,207 @@
+//! Export for third-party watchtowers: the scripts to watch, the pre-signed clawbacks to
+//! broadcast and when to broadcast them, and nothing a watchtower could spend with.
+//!
+//! The vault side builds a `WatchtowerExport` from public descriptors and fully signed
+//! transactions and writes it as a versioned `schema` document. The watchtower side reads it with
+//! `Watchtower::import`, which rejects documents carrying private key material (see
+//! `watch_only::assert_no_secrets`), clawbacks that do not verify against the outputs they spend,
+//! and clawbacks spending a script the export does not watch, since their trigger would never be
+//! seen. The export is deterministic: the same vault state always gives the same document.
+
+use crate::recovery::PresignedRecovery;
+use crate::scanner::BlockScanner;
+use crate::schema::{self, SchemaKind};
+use crate::verify;
+use crate::watch_only;
+use bitcoin::consensus::encode::{deserialize, serialize_hex};
+use bitcoin::{Network, OutPoint, ScriptBuf, Transaction, TxOut};
+use miniscript::bitcoin::PublicKey;
+use miniscript::Descriptor;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// When a watchtower broadcasts a clawback, counted from the confirmation of the output it spends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    /// As soon as the output confirms; it only exists if someone started a contested spend
+    OnConfirmation,
+    /// Once the output has `blocks` confirmations, e.g. a recovery behind a CSV delay
+    AfterConfirmations { blocks: u32 },
+}
+
+impl Trigger {
+    pub fn is_met(&self, confirmations: u32) -> bool {
+        match self {
+            Trigger::OnConfirmation => confirmations >= 1,
+            Trigger::AfterConfirmations { blocks } => confirmations >= *blocks,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchedScript {
+    pub script_pubkey_hex: String,
+    /// Free-form name for the watchtower's alerts
+    pub label: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrevoutDoc {
+    pub value_sats: u64,
+    pub script_pubkey_hex: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClawbackDoc {
+    pub txid: String,
+    pub tx_hex: String,
+    /// `txid:vout` of the output whose confirmation triggers the broadcast
+    pub spends: String,
+    /// Outputs spent by every input of the clawback, in input order, to verify it with
+    pub prevouts: Vec<PrevoutDoc>,
+    pub trigger: Trigger,
+    /// Confirmations of `spends` after which a competing timelocked path opens; the clawback must
+    /// confirm before then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline_blocks: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchtowerExport {
+    pub network: String,
+    pub scripts: Vec<WatchedScript>,
+    pub clawbacks: Vec<ClawbackDoc>,
+}
+
+impl SchemaKind for WatchtowerExport {
+    const KIND: &'static str = "watchtower_export";
+    fn json_schema() -> Value {
+        json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "$id": format!("wrapyield/{}/v{}", Self::KIND, schema::SCHEMA_VERSION),
+            "type": "object",
+            "required": ["schema_version", "kind", "network", "scripts", "clawbacks"],
+        })
+    }
+}
+
+impl WatchtowerExport {
+    pub fn new(network: Network) -> Self {
+        Self { network: network.to_string(), scripts: Vec::new(), clawbacks: Vec::new() }
+    }
+
+    /// Watch the output script of `descriptor`; watching it twice is a no-op
+    pub fn watch_descriptor(&mut self, descriptor: &Descriptor<PublicKey>, label: &str) {
+        self.watch_script(&descriptor.script_pubkey(), label);
+    }
+
+    pub fn watch_script(&mut self, script_pubkey: &ScriptBuf, label: &str) {
+        let script_pubkey_hex = script_pubkey.to_hex_string();
+        if !self.scripts.iter().any(|s| s.script_pubkey_hex == script_pubkey_hex) {
+            self.scripts.push(WatchedScript { script_pubkey_hex, label: label.to_string() });
+            self.scripts.sort_by(|a, b| a.script_pubkey_hex.cmp(&b.script_pubkey_hex));
+        }
+    }
+
+    /// Add a fully signed clawback of `spends`, an input of `tx`. `prevouts` are the outputs
+    /// every input spends, in input order; the spent script is watched as well.
+    pub fn add_clawback(&mut self, tx: &Transaction, spends: OutPoint, prevouts: &[TxOut], trigger: Trigger, deadline_blocks: Option<u32>) -> Result<(), Box<dyn std::error::Error>> {
+        let index = tx.input.iter().position(|i| i.previous_output == spends)
+            .ok_or_else(|| format!("clawback {} does not spend {}", tx.txid(), spends))?;
+        verify::verify_spend(tx, prevouts).map_err(|e| format!("clawback {} is not fully signed: {}", tx.txid(), e))?;
+        if let (Trigger::AfterConfirmations { blocks }, Some(deadline)) = (trigger, deadline_blocks) {
+            if blocks >= deadline {
+                return Err(format!("clawback {} triggers after {} confirmations, at or past its deadline of {}", tx.txid(), blocks, deadline).into());
+            }
+        }
+        self.watch_script(&prevouts[index].script_pubkey, &format!("clawback {}", tx.txid()));
+        let txid = tx.txid().to_string();
+        self.clawbacks.retain(|c| c.txid != txid);
+        self.clawbacks.push(ClawbackDoc {
+            txid,
+            tx_hex: serialize_hex(tx),
+            spends: spends.to_string(),
+            prevouts: prevouts.iter().map(|p| PrevoutDoc { value_sats: p.value, script_pubkey_hex: p.script_pubkey.to_hex_string() }).collect(),
+            trigger,
+            deadline_blocks,
+        });
+        self.clawbacks.sort_by(|a, b| (&a.spends, &a.txid).cmp(&(&b.spends, &b.txid)));
+        Ok(())
+    }
+
+    /// Add a pre-signed recovery, broadcast once its deposit has matured the CSV delay
+    pub fn add_recovery(&mut self, recovery: &PresignedRecovery) -> Result<(), Box<dyn std::error::Error>> {
+        let trigger = Trigger::AfterConfirmations { blocks: recovery.template.csv as u32 };
+        self.add_clawback(&recovery.tx, recovery.deposit, &[recovery.template.prevout()?], trigger, None)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(schema::to_json_pretty(self)?)
+    }
+}
+
+/// A clawback the watchtower holds, ready to broadcast when its trigger is met
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArmedClawback {
+    pub tx: Transaction,
+    pub spends: OutPoint,
+    pub trigger: Trigger,
+    pub deadline_blocks: Option<u32>,
+}
+
+/// The watchtower side of an export, validated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchtower {
+    pub network: Network,
+    /// Watched scripts with their labels
+    pub scripts: Vec<(ScriptBuf, String)>,
+    pub clawbacks: Vec<ArmedClawback>,
+}
+
+impl Watchtower {
+    /// Parse and validate an export written by `WatchtowerExport::to_json`
+    pub fn import(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        // Checked on the raw document, so secrets in any field or unknown field are caught
+        watch_only::assert_no_secrets(&[json.to_string()])?;
+        let export: WatchtowerExport = schema::from_json(json)?;
+        let network = Network::from_str(&export.network)?;
+        let scripts = export.scripts.iter()
+            .map(|s| Ok((ScriptBuf::from_hex(&s.script_pubkey_hex)?, s.label.clone())))
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+        let mut clawbacks = Vec::new();
+        for doc in &export.clawbacks {
+            let tx: Transaction = deserialize(&hex::decode(&doc.tx_hex)?)?;
+            if tx.txid().to_string() != doc.txid {
+                return Err(format!("clawback {} decodes to {}", doc.txid, tx.txid()).into());
+            }
+            let prevouts = doc.prevouts.iter()
+                .map(|p| Ok(TxOut { value: p.value_sats, script_pubkey: ScriptBuf::from_hex(&p.script_pubkey_hex)? }))
+                .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+            let spends = OutPoint::from_str(&doc.spends)?;
+            let index = tx.input.iter().position(|i| i.previous_output == spends)
+                .ok_or_else(|| format!("clawback {} does not spend {}", doc.txid, spends))?;
+            verify::verify_spend(&tx, &prevouts).map_err(|e| format!("clawback {} does not verify: {}", doc.txid, e))?;
+            if !scripts.iter().any(|(script, _)| *script == prevouts[index].script_pubkey) {
+                return Err(format!("clawback {} spends a script the export does not watch", doc.txid).into());
+            }
+            clawbacks.push(ArmedClawback { tx, spends, trigger: doc.trigger, deadline_blocks: doc.deadline_blocks });
+        }
+        Ok(Self { network, scripts, clawbacks })
+    }
+
+    /// A scanner watching every exported script; its deposit events are the trigger outputs
+    pub fn scanner(&self) -> BlockScanner {
+        let mut scanner = BlockScanner::new();
+        for (script, _) in &self.scripts {
+            scanner.watch_script(script.clone());
+        }
+        scanner
+    }
+
+    /// Clawbacks of `outpoint` to broadcast now that it has `confirmations`
+    pub fn due(&self, outpoint: OutPoint, confirmations: u32) -> Vec<&ArmedClawback> {
+        self.clawbacks.iter().filter(|c| c.spends == outpoint && c.trigger.is_met(confirmations)).collect()
+    }
+}
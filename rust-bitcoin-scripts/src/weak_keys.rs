@@ -0,0 +1,86 @@
+//! Weak-key checks for vault builders.
+//!
+//! Rejects keys whose discrete log is publicly known: the `[b; 32]` test-style secret constants
+//! and small multiples of the generator. Fixtures that deliberately use such keys pass them via
+//! `KeyPolicy::allowlist`.
+
+use miniscript::bitcoin::PublicKey;
+use miniscript::{Descriptor, ForEachKey};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Generator multiples `1..=SMALL_SCALAR_LIMIT` are treated as known
+pub const SMALL_SCALAR_LIMIT: u32 = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeakKey {
+    pub key: XOnlyPublicKey,
+}
+
+impl fmt::Display for WeakKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "key {} has a publicly known private key", self.key)
+    }
+}
+
+impl std::error::Error for WeakKey {}
+
+/// Which weak keys a builder tolerates (none by default)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyPolicy {
+    pub allowlist: Vec<XOnlyPublicKey>,
+}
+
+impl KeyPolicy {
+    /// Allow the given fixture keys, e.g. `[5; 32]` test keys
+    pub fn allow(keys: &[PublicKey]) -> Self {
+        Self { allowlist: keys.iter().map(|k| k.inner.x_only_public_key().0).collect() }
+    }
+
+    pub fn check_key(&self, key: &PublicKey) -> Result<(), WeakKey> {
+        self.check_x_only(&key.inner.x_only_public_key().0)
+    }
+
+    pub fn check_x_only(&self, key: &XOnlyPublicKey) -> Result<(), WeakKey> {
+        if known_keys().contains(key) && !self.allowlist.contains(key) {
+            return Err(WeakKey { key: *key });
+        }
+        Ok(())
+    }
+
+    pub fn check_descriptor(&self, descriptor: &Descriptor<PublicKey>) -> Result<(), WeakKey> {
+        let mut result = Ok(());
+        descriptor.for_each_key(|pk| {
+            result = self.check_key(pk);
+            result.is_ok()
+        });
+        result
+    }
+}
+
+/// Whether a secret key is one of the known weak scalars
+pub fn is_weak_secret(secret: &SecretKey) -> bool {
+    let bytes = secret.secret_bytes();
+    bytes.iter().all(|b| *b == bytes[0])
+        || (bytes[..28].iter().all(|b| *b == 0) && u32::from_be_bytes([bytes[28], bytes[29], bytes[30], bytes[31]]) <= SMALL_SCALAR_LIMIT)
+}
+
+fn known_keys() -> &'static HashSet<XOnlyPublicKey> {
+    static KNOWN: OnceLock<HashSet<XOnlyPublicKey>> = OnceLock::new();
+    KNOWN.get_or_init(|| {
+        let secp = Secp256k1::signing_only();
+        let repeated = (1..=255u8).map(|b| [b; 32]);
+        let small = (1..=SMALL_SCALAR_LIMIT).map(|k| {
+            let mut bytes = [0u8; 32];
+            bytes[28..].copy_from_slice(&k.to_be_bytes());
+            bytes
+        });
+        repeated.chain(small)
+            .filter_map(|bytes| SecretKey::from_slice(&bytes).ok())
+            .map(|sk| sk.x_only_public_key(&secp).0)
+            .collect()
+    })
+}
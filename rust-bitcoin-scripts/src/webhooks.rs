@@ -0,0 +1,127 @@
+//! Webhook notifications for vault lifecycle events.
+//!
+//! Each delivery is a JSON POST signed with HMAC-SHA256 over the raw body using the endpoint's shared
+//! secret; the hex MAC is sent in `X-WrapYield-Signature`. Failed deliveries (transport errors or
+//! non-2xx responses) are retried with exponential backoff up to `max_attempts`.
+
+use crate::secret::ct_eq;
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const SIGNATURE_HEADER: &str = "X-WrapYield-Signature";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LifecycleEvent {
+    DepositConfirmed { txid: String, vout: u32, value_sats: u64, confirmations: u64 },
+    PegOutBroadcast { txid: String, destinations: Vec<String>, fee_sats: u64 },
+    ClawbackTriggered { txid: String, path_used: String },
+    /// A broadcast clawback is still unconfirmed close to its deadline
+    ClawbackStuck { txid: String, blocks_pending: u64, blocks_left: u64, fee_rate_sat_vb: u64 },
+    SigningRoundStalled { round_id: String, missing_signers: Vec<String> },
+}
+
+impl LifecycleEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LifecycleEvent::DepositConfirmed { .. } => "deposit_confirmed",
+            LifecycleEvent::PegOutBroadcast { .. } => "peg_out_broadcast",
+            LifecycleEvent::ClawbackTriggered { .. } => "clawback_triggered",
+            LifecycleEvent::ClawbackStuck { .. } => "clawback_stuck",
+            LifecycleEvent::SigningRoundStalled { .. } => "signing_round_stalled",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    pub secret: Vec<u8>,
+    /// Event names this endpoint receives; empty means all
+    pub events: Vec<String>,
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+}
+
+impl WebhookEndpoint {
+    pub fn new(url: &str, secret: &[u8]) -> Self {
+        Self {
+            url: url.to_string(),
+            secret: secret.to_vec(),
+            events: Vec::new(),
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+        }
+    }
+
+    pub fn subscribes_to(&self, event: &LifecycleEvent) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event.name())
+    }
+}
+
+/// Outcome of delivering one event to one endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub url: String,
+    pub attempts: u32,
+    pub error: Option<String>,
+}
+
+/// Hex HMAC-SHA256 of `body` under `secret`
+pub fn sign_payload(secret: &[u8], body: &[u8]) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret);
+    engine.input(body);
+    hmac::Hmac::<sha256::Hash>::from_engine(engine).to_string()
+}
+
+/// Check a received signature, as a webhook consumer would
+pub fn verify_payload(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    ct_eq(sign_payload(secret, body).as_bytes(), signature.to_lowercase().as_bytes())
+}
+
+pub struct Webhooks {
+    pub endpoints: Vec<WebhookEndpoint>,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub fn new(endpoints: Vec<WebhookEndpoint>) -> Self {
+        Self { endpoints, client: reqwest::Client::new() }
+    }
+
+    /// Deliver `event` to every subscribed endpoint, retrying each independently
+    pub async fn fire(&self, event: &LifecycleEvent) -> Result<Vec<Delivery>, Box<dyn std::error::Error>> {
+        let body = serde_json::to_vec(event)?;
+        let deliveries = self.endpoints.iter()
+            .filter(|endpoint| endpoint.subscribes_to(event))
+            .map(|endpoint| self.deliver(endpoint, &body));
+        Ok(futures::future::join_all(deliveries).await)
+    }
+
+    async fn deliver(&self, endpoint: &WebhookEndpoint, body: &[u8]) -> Delivery {
+        let signature = sign_payload(&endpoint.secret, body);
+        let mut backoff = endpoint.initial_backoff;
+        let mut last_error = None;
+        for attempt in 1..=endpoint.max_attempts.max(1) {
+            let response = self.client.post(&endpoint.url)
+                .header("Content-Type", "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .body(body.to_vec())
+                .send()
+                .await;
+            match response {
+                Ok(r) if r.status().is_success() => {
+                    return Delivery { url: endpoint.url.clone(), attempts: attempt, error: None };
+                }
+                Ok(r) => last_error = Some(format!("HTTP {}", r.status())),
+                Err(e) => last_error = Some(e.to_string()),
+            }
+            if attempt < endpoint.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+        Delivery { url: endpoint.url.clone(), attempts: endpoint.max_attempts.max(1), error: last_error }
+    }
+}
@@ -0,0 +1,167 @@
+//! Witness construction for the `wsh(or_d(pk(A),and_v(v:multi(k,...),older(n)|after(n))))` vaults
+//! and their `sh(wsh(...))` nested variants.
+//!
+//! The builders check the descriptor has that shape, put signatures in the order CHECKMULTISIG
+//! expects and add the dummy and branch-selection elements, so callers only supply signatures.
+//! Spends normally go through `satisfier::satisfy_input`, which lets the descriptor pick the
+//! branch; these builders are for forcing one branch and produce the same stacks.
+//!
+//! Stack layouts (bottom to top):
+//! - backup path: `<sig_A> <witness_script>`
+//! - multisig path: `<> <sig_1> .. <sig_k> <> <witness_script>`; the leading empty element is the
+//!   CHECKMULTISIG dummy, the one before the script makes `pk(A)` fail so `or_d` takes the right branch.
+//!
+//! A nested vault spends with the same witness; its scriptSig pushes the P2WSH program as the
+//! redeem script (`build_vault_script_sig`). Native vaults get an empty scriptSig.
+//!
+//! `vault_descriptor` and `nested_vault_descriptor` build these vaults for either timelock.
+
+use crate::locktime::LOCKTIME_THRESHOLD;
+use miniscript::bitcoin::PublicKey;
+use miniscript::descriptor::{ShInner, Wsh, WshInner};
+use miniscript::{Descriptor, Miniscript, MiniscriptKey, Segwitv0, Terminal};
+use bitcoin::ecdsa::Signature;
+use bitcoin::{ScriptBuf, Witness};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Timelock guarding the multisig branch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VaultTimelock {
+    /// `older(n)`: relative, enforced through the input's nSequence
+    Relative(u32),
+    /// `after(n)`: absolute, enforced through the transaction's nLockTime
+    Absolute(u32),
+}
+
+/// `backup` any time, or `threshold` of `signers` once `timelock` is met.
+/// Keys are bare keys or descriptor keys with origin (`hd::HdWallet::descriptor_key`).
+pub fn vault_descriptor<Pk>(backup: Pk, signers: &[Pk], threshold: usize, timelock: VaultTimelock) -> Result<Descriptor<Pk>, Box<dyn std::error::Error>>
+where
+    Pk: MiniscriptKey,
+    Descriptor<Pk>: FromStr<Err = miniscript::Error>,
+{
+    let vault = vault_fragment(&backup, signers, threshold, timelock)?;
+    Ok(Descriptor::from_str(&format!("wsh({})", vault))?)
+}
+
+/// `vault_descriptor` nested as `sh(wsh(...))`, for counterparties that can only pay P2SH
+pub fn nested_vault_descriptor<Pk>(backup: Pk, signers: &[Pk], threshold: usize, timelock: VaultTimelock) -> Result<Descriptor<Pk>, Box<dyn std::error::Error>>
+where
+    Pk: MiniscriptKey,
+    Descriptor<Pk>: FromStr<Err = miniscript::Error>,
+{
+    let vault = vault_fragment(&backup, signers, threshold, timelock)?;
+    Ok(Descriptor::from_str(&format!("sh(wsh({}))", vault))?)
+}
+
+fn vault_fragment<Pk: MiniscriptKey>(backup: &Pk, signers: &[Pk], threshold: usize, timelock: VaultTimelock) -> Result<String, Box<dyn std::error::Error>> {
+    if threshold == 0 || threshold > signers.len() {
+        return Err(format!("threshold {} out of range for {} signers", threshold, signers.len()).into());
+    }
+    let lock = match timelock {
+        VaultTimelock::Relative(0) => return Err("relative timelock must be at least one block".into()),
+        VaultTimelock::Relative(n) => format!("older({})", n),
+        VaultTimelock::Absolute(n) if n == 0 || n >= LOCKTIME_THRESHOLD => return Err(format!("lock height {} is not a block height", n).into()),
+        VaultTimelock::Absolute(n) => format!("after({})", n),
+    };
+    let signers: Vec<String> = signers.iter().map(|k| k.to_string()).collect();
+    Ok(format!("or_d(pk({}),and_v(v:multi({},{}),{}))", backup, threshold, signers.join(","), lock))
+}
+
+/// The parts of an `or_d(pk(A),and_v(v:multi(k,...),older/after(n)))` vault
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultShape {
+    pub backup_key: PublicKey,
+    pub threshold: usize,
+    pub multisig_keys: Vec<PublicKey>,
+    pub timelock: VaultTimelock,
+    pub witness_script: ScriptBuf,
+    /// `<0 <sha256(witness_script)>>` for `sh(wsh(...))`, empty for `wsh(...)`
+    pub script_sig: ScriptBuf,
+}
+
+impl VaultShape {
+    pub fn from_descriptor(descriptor: &Descriptor<PublicKey>) -> Result<Self, Box<dyn std::error::Error>> {
+        let wsh = match descriptor {
+            Descriptor::Wsh(wsh) => wsh,
+            Descriptor::Sh(sh) => match sh.as_inner() {
+                ShInner::Wsh(wsh) => wsh,
+                _ => return Err("vault witnesses are built for wsh and sh(wsh) descriptors only".into()),
+            },
+            _ => return Err("vault witnesses are built for wsh and sh(wsh) descriptors only".into()),
+        };
+        let ms = vault_miniscript(wsh)?;
+        let shape_error = || format!("expected or_d(pk(A),and_v(v:multi(k,...),older|after(n))), got {}", ms);
+        let (left, right) = match &ms.node {
+            Terminal::OrD(left, right) => (left, right),
+            _ => return Err(shape_error().into()),
+        };
+        let backup_key = match &left.node {
+            Terminal::Check(inner) => match &inner.node {
+                Terminal::PkK(key) => *key,
+                _ => return Err(shape_error().into()),
+            },
+            _ => return Err(shape_error().into()),
+        };
+        let (multi, lock) = match &right.node {
+            Terminal::AndV(multi, lock) => (multi, lock),
+            _ => return Err(shape_error().into()),
+        };
+        let (threshold, multisig_keys) = match &multi.node {
+            Terminal::Verify(inner) => match &inner.node {
+                Terminal::Multi(k, keys) => (*k, keys.clone()),
+                _ => return Err(shape_error().into()),
+            },
+            _ => return Err(shape_error().into()),
+        };
+        let timelock = match &lock.node {
+            Terminal::Older(seq) => VaultTimelock::Relative(seq.to_consensus_u32()),
+            Terminal::After(lock_time) => VaultTimelock::Absolute(lock_time.to_consensus_u32()),
+            _ => return Err(shape_error().into()),
+        };
+        Ok(Self { backup_key, threshold, multisig_keys, timelock, witness_script: descriptor.explicit_script()?, script_sig: descriptor.unsigned_script_sig() })
+    }
+}
+
+fn vault_miniscript(wsh: &Wsh<PublicKey>) -> Result<&Miniscript<PublicKey, Segwitv0>, Box<dyn std::error::Error>> {
+    match wsh.as_inner() {
+        WshInner::Ms(ms) => Ok(ms),
+        WshInner::SortedMulti(_) => Err("sortedmulti is not a vault descriptor".into()),
+    }
+}
+
+/// scriptSig to go with either witness: the P2WSH program for a nested vault, empty otherwise
+pub fn build_vault_script_sig(descriptor: &Descriptor<PublicKey>) -> Result<ScriptBuf, Box<dyn std::error::Error>> {
+    Ok(VaultShape::from_descriptor(descriptor)?.script_sig)
+}
+
+/// `<sig_A> <witness_script>`
+pub fn build_backup_path_witness(descriptor: &Descriptor<PublicKey>, backup_sig: &Signature) -> Result<Witness, Box<dyn std::error::Error>> {
+    let shape = VaultShape::from_descriptor(descriptor)?;
+    let mut witness = Witness::new();
+    witness.push(backup_sig.to_vec());
+    witness.push(shape.witness_script.as_bytes());
+    Ok(witness)
+}
+
+/// `<> <sig_1> .. <sig_k> <> <witness_script>` with signatures ordered as their keys appear in
+/// `multi`. Exactly `k` signatures from multisig keys are required; extras are ignored in key order.
+pub fn build_multisig_timelock_witness(descriptor: &Descriptor<PublicKey>, sigs: &HashMap<PublicKey, Signature>) -> Result<Witness, Box<dyn std::error::Error>> {
+    let shape = VaultShape::from_descriptor(descriptor)?;
+    let ordered: Vec<&Signature> = shape.multisig_keys.iter()
+        .filter_map(|key| sigs.get(key))
+        .take(shape.threshold)
+        .collect();
+    if ordered.len() < shape.threshold {
+        return Err(format!("need {} multisig signatures, have {}", shape.threshold, ordered.len()).into());
+    }
+    let mut witness = Witness::new();
+    witness.push(Vec::<u8>::new());
+    for sig in ordered {
+        witness.push(sig.to_vec());
+    }
+    witness.push(Vec::<u8>::new());
+    witness.push(shape.witness_script.as_bytes());
+    Ok(witness)
+}
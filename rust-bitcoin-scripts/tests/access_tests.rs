@@ -0,0 +1,25 @@
+use bitcoin_scripts::access::{AccessControl, AccessError, Action, Identity, Role};
+
+#[test]
+fn test_roles_gate_actions() {
+    let mut acl = AccessControl::new();
+    acl.add_api_key("op-key", "operator-1", Role::Operator);
+    acl.add_api_key("signer-key", "signer-1", Role::Signer);
+    acl.add_certificate("AABBCC", "auditor-1", Role::Auditor);
+
+    let operator = Identity::ApiKey("op-key".to_string());
+    let signer = Identity::ApiKey("signer-key".to_string());
+    let auditor = Identity::CertFingerprint("aabbcc".to_string());
+
+    assert!(acl.authorize(&operator, Action::InitiatePegOut).is_ok());
+    assert!(acl.authorize(&signer, Action::SubmitPartialSignature).is_ok());
+    assert!(acl.authorize(&auditor, Action::ReadState).is_ok());
+
+    assert_eq!(
+        acl.authorize(&operator, Action::SubmitPartialSignature).unwrap_err(),
+        AccessError::Forbidden { principal: "operator-1".to_string(), action: Action::SubmitPartialSignature }
+    );
+    assert!(acl.authorize(&signer, Action::InitiatePegOut).is_err());
+    assert!(acl.authorize(&auditor, Action::InitiatePegOut).is_err());
+    assert_eq!(acl.authorize(&Identity::ApiKey("guess".to_string()), Action::ReadState).unwrap_err(), AccessError::Unauthenticated);
+}
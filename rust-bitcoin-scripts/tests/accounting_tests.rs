@@ -0,0 +1,85 @@
+use bitcoin_scripts::accounting::{ExportFormat, FeeLedger, UNATTRIBUTED};
+use bitcoin_scripts::receipt::{BlockRef, Job, SpendHistory, SpendReceipt};
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::{Amount, BlockHash, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+
+fn receipt(previous: u8, fee: u64, broadcast_time: u64, height: Option<u64>, job: Option<Job>) -> SpendReceipt {
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::from_byte_array([previous; 32]), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+        output: vec![TxOut { value: 10_000, script_pubkey: ScriptBuf::new() }],
+    };
+    let mut receipt = SpendReceipt::new(&tx, Amount::from_sat(fee), "script");
+    receipt.broadcast_time = broadcast_time;
+    receipt.block = height.map(|height| BlockRef { hash: BlockHash::all_zeros(), height });
+    receipt.job = job;
+    receipt
+}
+
+fn history() -> SpendHistory {
+    SpendHistory {
+        receipts: vec![
+            receipt(1, 1_000, 300, Some(10), Some(Job::PegOutBatch { batch: 7 })),
+            receipt(2, 500, 100, Some(9), Some(Job::DepositSweep)),
+            receipt(3, 2_000, 400, Some(11), Some(Job::PegOutBatch { batch: 8 })),
+            receipt(4, 700, 500, None, Some(Job::Clawback)),
+            receipt(5, 300, 200, Some(9), None),
+            receipt(6, 900, 900, Some(20), Some(Job::Consolidation)),
+        ],
+    }
+}
+
+#[test]
+fn test_every_fee_is_attributed_to_a_product() {
+    let history = history();
+    let ledger = FeeLedger::from_history(&history, 0, 600);
+    assert_eq!(ledger.entries.iter().map(|e| e.broadcast_time).collect::<Vec<_>>(), vec![100, 200, 300, 400, 500]);
+    assert_eq!(ledger.entries[2].product, "peg_out");
+    assert_eq!(ledger.entries[2].reference.as_deref(), Some("7"));
+    assert_eq!(ledger.entries[1].product, UNATTRIBUTED);
+
+    let totals: Vec<(&str, usize, u64, u64)> = ledger.totals.iter().map(|t| (t.product.as_str(), t.transactions, t.booked_sats, t.pending_sats)).collect();
+    assert_eq!(totals, vec![("clawback", 1, 0, 700), ("deposit_sweep", 1, 500, 0), ("peg_out", 2, 3_000, 0), (UNATTRIBUTED, 1, 300, 0)]);
+    // Every sat in the period is accounted for
+    let in_period: u64 = history.receipts.iter().filter(|r| r.broadcast_time < 600).map(|r| r.fee.to_sat()).sum();
+    assert_eq!(ledger.booked() + ledger.pending(), Amount::from_sat(in_period));
+    assert_eq!(ledger.booked(), Amount::from_sat(3_800));
+}
+
+#[test]
+fn test_csv_and_json_exports() {
+    let mut history = history();
+    history.receipts.push(receipt(7, 100, 50, Some(8), Some(Job::Other { name: "audit, q3".to_string() })));
+    let ledger = FeeLedger::from_history(&history, 0, 350);
+
+    let csv = ledger.to_csv();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "txid,product,reference,path_used,fee_sats,fee_btc,feerate_sat_per_kwu,broadcast_time,block_height,status");
+    assert_eq!(lines.len(), 5);
+    assert!(lines[1].contains(",\"audit, q3\",,script,100,0.00000100,"), "{}", lines[1]);
+    assert!(lines[4].contains(",peg_out,7,script,1000,0.00001000,"), "{}", lines[4]);
+    assert!(lines[4].ends_with(",300,10,booked"));
+
+    let path = std::env::temp_dir().join(format!("wrapyield-fees-{}.json", std::process::id()));
+    ledger.export(&path, ExportFormat::Json).unwrap();
+    let read: FeeLedger = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(read, ledger);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_job_tags_survive_the_history_file() {
+    let path = std::env::temp_dir().join(format!("wrapyield-fee-history-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    assert!(FeeLedger::load_history(&path, 0, u64::MAX).unwrap().entries.is_empty());
+
+    history().save(&path).unwrap();
+    let loaded = SpendHistory::load(&path).unwrap().unwrap();
+    assert_eq!(loaded.receipts[0].job, Some(Job::PegOutBatch { batch: 7 }));
+    assert_eq!(loaded.receipts[4].job, None);
+    assert_eq!(Job::PegOutBatch { batch: 7 }.to_string(), "peg_out #7");
+    assert_eq!(FeeLedger::load_history(&path, 0, u64::MAX).unwrap().entries.len(), 6);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,64 @@
+use bitcoin_scripts::actors::CoordinatorHandle;
+use bitcoin_scripts::reservation::UtxoReservations;
+use bitcoin_scripts::signing_round::SigningRound;
+use bitcoin_scripts::webhooks::LifecycleEvent;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, absolute::LockTime};
+use bitcoin::hashes::Hash;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+fn unsigned_psbt(vout: u32) -> Psbt {
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), vout),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut { value: 10_000, script_pubkey: ScriptBuf::new() }],
+    };
+    Psbt::from_unsigned_tx(tx).unwrap()
+}
+
+#[tokio::test]
+async fn test_coordinator_actor_handles_concurrent_rounds() {
+    let reservations = UtxoReservations::new(Duration::from_secs(600));
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    let coordinator = CoordinatorHandle::spawn(reservations.clone(), events_tx, Duration::from_secs(3600));
+    let signers = vec!["alice".to_string(), "bob".to_string()];
+
+    for vout in 0..20u32 {
+        reservations.reserve("coordinator", &[OutPoint::new(Txid::all_zeros(), vout)], None).unwrap();
+        let round = SigningRound::new(&format!("round-{}", vout), unsigned_psbt(vout), signers.clone(), 2, Duration::from_secs(30));
+        coordinator.open(round).await.unwrap();
+    }
+
+    // Every even round gets both signatures from concurrently running submitters
+    let submissions = (0..20u32).step_by(2).flat_map(|vout| {
+        signers.iter().map(move |signer| (vout, signer.clone()))
+    }).map(|(vout, signer)| {
+        let coordinator = coordinator.clone();
+        tokio::spawn(async move { coordinator.submit(&format!("round-{}", vout), &signer, unsigned_psbt(vout)).await.unwrap() })
+    });
+    let completed = futures::future::join_all(submissions).await.into_iter().filter(|r| *r.as_ref().unwrap()).count();
+    assert_eq!(completed, 10);
+
+    let escalations = coordinator.tick(Instant::now() + Duration::from_secs(31)).await.unwrap();
+    assert_eq!(escalations.len(), 10);
+    for vout in 0..20u32 {
+        let reserved = reservations.reserved_by(&OutPoint::new(Txid::all_zeros(), vout)).is_some();
+        assert_eq!(reserved, vout % 2 == 0, "only stalled rounds release their UTXOs");
+    }
+    let mut stalled = 0;
+    while let Ok(event) = events_rx.try_recv() {
+        assert!(matches!(event, LifecycleEvent::SigningRoundStalled { ref missing_signers, .. } if missing_signers.len() == 2));
+        stalled += 1;
+    }
+    assert_eq!(stalled, 10);
+
+    assert!(coordinator.take("round-0").await.is_ok());
+    assert!(coordinator.take("round-0").await.is_err());
+}
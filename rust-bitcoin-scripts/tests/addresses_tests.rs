@@ -0,0 +1,92 @@
+use bitcoin_scripts::addresses::{AddressManager, DEFAULT_GAP_LIMIT};
+use bitcoin_scripts::flows::mine;
+use bitcoin_scripts::hd::{account_path, parse_mnemonic, HdWallet, Purpose};
+use bitcoin_scripts::scanner::ScanEvent;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::hashes::Hash;
+use bitcoin::{Amount, Network, OutPoint, Txid};
+use miniscript::descriptor::DescriptorPublicKey;
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+const ABANDON: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+fn descriptor(template: &str) -> Descriptor<DescriptorPublicKey> {
+    descriptor_on_chain(template, 0)
+}
+
+fn descriptor_on_chain(template: &str, chain: u32) -> Descriptor<DescriptorPublicKey> {
+    let wallet = HdWallet::from_mnemonic(&parse_mnemonic(ABANDON).unwrap(), "", Network::Regtest).unwrap();
+    let path = account_path(Purpose::Bip86, Network::Regtest, 0).unwrap();
+    Descriptor::from_str(&template.replace('K', &wallet.descriptor_key(&path, chain).unwrap().to_string())).unwrap()
+}
+
+#[test]
+fn test_issues_sequential_addresses_and_skips_used() {
+    assert!(AddressManager::new(Descriptor::from_str("wpkh(02e6642fd69bd211f93f7f1f36ca51a26a5290eb2dd1b0d8279a87bb0d480c8443)").unwrap(), Network::Regtest).is_err());
+
+    let mut manager = AddressManager::new(descriptor("tr(K)"), Network::Regtest).unwrap();
+    assert_eq!(manager.gap_limit, DEFAULT_GAP_LIMIT);
+    let (first, address) = manager.next_address().unwrap();
+    assert_eq!((first, address.clone()), (0, manager.address(0).unwrap()));
+    assert_eq!(manager.next_address().unwrap().0, 1);
+
+    manager.mark_used(7);
+    assert_eq!(manager.next_address().unwrap().0, 8);
+    assert_eq!(manager.index_of(&address.script_pubkey()).unwrap(), Some(0));
+    assert_eq!(manager.index_of(&manager.address(8 + DEFAULT_GAP_LIMIT + 1).unwrap().script_pubkey()).unwrap(), None);
+
+    let deposit = ScanEvent::Deposit { outpoint: OutPoint::new(Txid::all_zeros(), 0), value_sats: 1_000, script_pubkey: manager.address(12).unwrap().script_pubkey(), height: 1 };
+    assert_eq!(manager.observe(&[deposit.clone()]).unwrap(), vec![12]);
+    assert!(manager.observe(&[deposit]).unwrap().is_empty());
+    assert_eq!(manager.next_index(), 13);
+    assert_eq!(manager.used().iter().copied().collect::<Vec<_>>(), vec![7, 12]);
+}
+
+#[test]
+fn test_wsh_ranged_descriptor_and_state_round_trip() {
+    let manager = AddressManager::new(descriptor("wsh(and_v(v:pk(K),older(144)))"), Network::Regtest).unwrap().with_gap_limit(5);
+    assert_ne!(manager.address(0).unwrap(), manager.address(1).unwrap());
+    assert!(manager.address(0).unwrap().to_string().starts_with("bcrt1q"));
+
+    let path = std::env::temp_dir().join(format!("wrapyield-addresses-{}.json", std::process::id()));
+    assert!(AddressManager::load(&path).unwrap().is_none());
+    let mut manager = manager;
+    manager.mark_used(3);
+    manager.save(&path).unwrap();
+    // Saved through a temporary file that is renamed into place
+    let temp = path.with_file_name(format!(".{}.tmp", path.file_name().unwrap().to_str().unwrap()));
+    assert!(!temp.exists());
+    let loaded = AddressManager::load(&path).unwrap().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.state(), manager.state());
+    assert_eq!(loaded.address(3).unwrap(), manager.address(3).unwrap());
+}
+
+#[tokio::test]
+async fn test_scans_recover_used_indices() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("addresses_wallet").await;
+    let _ = rpc.load_wallet("addresses_wallet").await;
+    let rpc = rpc.with_wallet("addresses_wallet");
+    let from = mine(&rpc, 101).await.unwrap() + 1;
+
+    // A fresh descriptor per run, so earlier runs leave nothing behind
+    let chain = 2 + std::process::id() % 100_000;
+    let mut issuer = AddressManager::new(descriptor_on_chain("tr(K)", chain), Network::Regtest).unwrap().with_gap_limit(5);
+    for _ in 0..4 {
+        issuer.next_address().unwrap();
+    }
+    rpc.send_to_address(&issuer.address(1).unwrap().to_string(), Amount::from_sat(50_000)).await.unwrap();
+    rpc.send_to_address(&issuer.address(6).unwrap().to_string(), Amount::from_sat(50_000)).await.unwrap();
+    let to = mine(&rpc, 1).await.unwrap();
+
+    // A restored manager that has forgotten what it issued
+    let mut restored = AddressManager::new(issuer.descriptor.clone(), Network::Regtest).unwrap().with_gap_limit(5);
+    assert_eq!(restored.scan_utxo_set(&rpc).await.unwrap(), vec![1, 6]);
+    assert_eq!(restored.next_index(), 7);
+
+    let mut restored = AddressManager::new(issuer.descriptor.clone(), Network::Regtest).unwrap().with_gap_limit(5);
+    assert_eq!(restored.scan_blocks(&rpc, from, to).await.unwrap(), vec![1, 6]);
+    assert_eq!(restored.next_address().unwrap().0, 7);
+}
@@ -0,0 +1,32 @@
+use bitcoin_scripts::amount::{deduct_fee, deduct_fee_for, from_rpc, to_rpc, FeeError};
+use bitcoin::{Amount, ScriptBuf};
+use serde_json::json;
+
+#[test]
+fn test_rpc_round_trip_is_exact() {
+    for sats in [1, 29_900_000, 99_999_999, 2_099_999_997_690_000] {
+        let amount = Amount::from_sat(sats);
+        assert_eq!(from_rpc(&to_rpc(amount)).unwrap(), amount);
+        // Core prints amounts as JSON numbers with up to 8 decimals
+        let number: serde_json::Value = serde_json::from_str(to_rpc(amount).as_str().unwrap()).unwrap();
+        assert_eq!(from_rpc(&number).unwrap(), amount);
+    }
+    assert_eq!(to_rpc(Amount::from_sat(10_000_000)), json!("0.10000000"));
+    assert!(from_rpc(&json!(null)).is_err());
+    assert!(from_rpc(&json!(0.000000001)).is_err(), "sub-satoshi precision");
+}
+
+#[test]
+fn test_fee_deduction_is_checked() {
+    // ((0.29 - 0.001) * 100_000_000.0) as u64 is 28899999
+    assert_eq!(deduct_fee(Amount::from_sat(29_000_000), Amount::from_sat(100_000)).unwrap(), Amount::from_sat(28_900_000));
+    assert_eq!(
+        deduct_fee(Amount::from_sat(50_000), Amount::from_sat(100_000)),
+        Err(FeeError::FeeExceedsAmount { amount: Amount::from_sat(50_000), fee: Amount::from_sat(100_000) })
+    );
+
+    let p2wpkh = ScriptBuf::new_v0_p2wpkh(&bitcoin::WPubkeyHash::from_raw_hash(bitcoin::hashes::Hash::all_zeros()));
+    let dust = p2wpkh.dust_value();
+    assert_eq!(deduct_fee_for(dust + Amount::from_sat(1_000), Amount::from_sat(1_000), &p2wpkh).unwrap(), dust);
+    assert!(matches!(deduct_fee_for(dust + Amount::from_sat(999), Amount::from_sat(1_000), &p2wpkh), Err(FeeError::BelowDust { .. })));
+}
@@ -0,0 +1,66 @@
+use bitcoin_scripts::analyzer::{analyze, path_delays, Delay, RaceParams, RaceWarning};
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use std::str::FromStr;
+use std::time::Duration;
+
+fn keys() -> Vec<PublicKey> {
+    let secp = secp256k1::Secp256k1::new();
+    [5u8, 6, 7, 8].iter()
+        .map(|b| PublicKey::from_private_key(&secp, &PrivateKey::new(secp256k1::SecretKey::from_slice(&[*b; 32]).unwrap(), Network::Regtest)))
+        .collect()
+}
+
+#[test]
+fn test_required_delays() {
+    let params = RaceParams::default();
+    // 30 minutes of 2-minute blocks, plus 6 to confirm
+    assert_eq!(params.required_blocks(), 21);
+    assert_eq!(params.required_secs() % 512, 0);
+    assert!(params.required_secs() >= 30 * 60 + 6 * 600);
+    let slow = RaceParams { watchtower_reaction: Duration::from_secs(3 * 3600), ..params };
+    assert_eq!(slow.required_blocks(), 96);
+}
+
+#[test]
+fn test_well_spaced_vault_passes() {
+    let k = keys();
+    let wsh = Descriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(144))))", k[3], k[0], k[1], k[2])).unwrap();
+    assert_eq!(path_delays(&wsh, &RaceParams::default()).unwrap(), vec![Ok(Delay::Immediate), Ok(Delay::Blocks(144))]);
+    assert!(analyze(&wsh, &RaceParams::default()).unwrap().is_empty());
+
+    let tr = Descriptor::from_str(&format!("tr({},{{and_v(v:pk({}),older(144)),and_v(v:pk({}),older(288))}})", k[3], k[0], k[1])).unwrap();
+    assert!(analyze(&tr, &RaceParams::default()).unwrap().is_empty());
+}
+
+#[test]
+fn test_short_and_racing_delays_get_suggestions() {
+    let k = keys();
+    let tr = Descriptor::from_str(&format!("tr({},{{and_v(v:pk({}),older(10)),and_v(v:pk({}),older(12))}})", k[3], k[0], k[1])).unwrap();
+    let warnings = analyze(&tr, &RaceParams::default()).unwrap();
+    assert!(warnings.contains(&RaceWarning::DelayTooShort { path: 1, delay: Delay::Blocks(10), suggested: Delay::Blocks(21) }), "{:?}", warnings);
+    assert!(warnings.contains(&RaceWarning::DelayTooShort { path: 2, delay: Delay::Blocks(12), suggested: Delay::Blocks(21) }));
+    assert!(warnings.contains(&RaceWarning::TimelockRace { earlier: 1, later: 2, gap: Delay::Blocks(2), suggested_later: Delay::Blocks(31) }));
+    assert!(warnings.iter().all(|w| !w.to_string().is_empty()));
+}
+
+#[test]
+fn test_missing_clawback_absolute_and_time_locks() {
+    let k = keys();
+    let timelocked_only = Descriptor::from_str(&format!("wsh(and_v(v:pk({}),older(144)))", k[0])).unwrap();
+    assert_eq!(analyze(&timelocked_only, &RaceParams::default()).unwrap(), vec![RaceWarning::NoClawbackPath]);
+
+    // CLTV at 1_100: fine 200 blocks out, too close 10 blocks out, unknown without a tip
+    let cltv = Descriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:pk({}),after(1100))))", k[3], k[0])).unwrap();
+    assert!(analyze(&cltv, &RaceParams { tip_height: Some(900), ..RaceParams::default() }).unwrap().is_empty());
+    assert_eq!(
+        analyze(&cltv, &RaceParams { tip_height: Some(1090), ..RaceParams::default() }).unwrap(),
+        vec![RaceWarning::DelayTooShort { path: 1, delay: Delay::Blocks(10), suggested: Delay::Blocks(21) }]
+    );
+    assert!(matches!(analyze(&cltv, &RaceParams::default()).unwrap()[..], [RaceWarning::Unanalyzed { path: 1, .. }]));
+
+    // older(4194305) is one 512-second unit
+    let time = Descriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:pk({}),older(4194305))))", k[3], k[0])).unwrap();
+    let warnings = analyze(&time, &RaceParams::default()).unwrap();
+    assert!(matches!(warnings[..], [RaceWarning::DelayTooShort { path: 1, delay: Delay::Seconds(512), .. }]), "{:?}", warnings);
+}
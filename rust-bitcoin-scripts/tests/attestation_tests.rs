@@ -0,0 +1,37 @@
+use bitcoin_scripts::attestation::attest;
+use bitcoin::Network;
+
+const COMMIT: &str = "0123abcd0123abcd0123abcd0123abcd0123abcd";
+
+const XPUB: &str = "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp";
+
+#[test]
+fn test_attestation_is_reproducible() {
+    let descriptors = vec![format!("wpkh({}/0/*)", XPUB), format!("tr({}/1/0)", XPUB)];
+    let first = attest(COMMIT, Network::Regtest, &descriptors, 5).unwrap();
+    let second = attest(COMMIT, Network::Regtest, &descriptors, 5).unwrap();
+    assert_eq!(first, second);
+    assert_eq!(first.addresses.len(), 6, "5 ranged + 1 fixed");
+    assert!(first.verify_digest().unwrap());
+
+    let other_commit = attest("fedc9876fedc9876fedc9876fedc9876fedc9876", Network::Regtest, &descriptors, 5).unwrap();
+    assert_ne!(first.digest, other_commit.digest);
+
+    let mut tampered = first.clone();
+    tampered.addresses[2].address = tampered.addresses[3].address.clone();
+    assert!(!tampered.verify_digest().unwrap());
+}
+
+#[test]
+fn test_attestation_refuses_private_keys() {
+    let descriptors = vec!["wpkh(cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy)".to_string()];
+    assert!(attest(COMMIT, Network::Regtest, &descriptors, 1).is_err());
+}
+
+#[test]
+fn test_attestation_requires_a_full_commit() {
+    let descriptors = vec![format!("wpkh({}/0/*)", XPUB)];
+    for commit in ["", "0123abcd", "0123abcd0123abcd0123abcd0123abcd0123abcg", "0123abcd0123abcd0123abcd0123abcd0123abcd0"] {
+        assert!(attest(commit, Network::Regtest, &descriptors, 1).is_err(), "{:?}", commit);
+    }
+}
@@ -0,0 +1,193 @@
+use bitcoin_scripts::backend::{electrum_script_hash, esplora_script_hash, ChainBackend, ElectrumBackend, EsploraBackend};
+use bitcoin_scripts::fees::{estimate_fee_rate, DEFAULT_MIN_RELAY_RATE};
+use bitcoin_scripts::flows::mine;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::deserialize;
+use bitcoin::hashes::Hash;
+use bitcoin::{Address, Amount, BlockHash, FeeRate, Network, ScriptBuf, Txid};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+/// P2PKH of 1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa, the Electrum protocol documentation's example
+fn genesis_script() -> ScriptBuf {
+    ScriptBuf::from_hex("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac").unwrap()
+}
+
+/// Header whose only non-zero field is `time`
+fn header_hex(time: u32) -> String {
+    let mut header = vec![0u8; 80];
+    header[68..72].copy_from_slice(&time.to_le_bytes());
+    hex::encode(header)
+}
+
+/// Electrum server answering each connection's request with `respond(method, params)`
+async fn electrum_server(respond: fn(&str, &Value) -> Value) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(socket);
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            let request: Value = serde_json::from_str(&line).unwrap();
+            let mut response = respond(request["method"].as_str().unwrap(), &request["params"]);
+            response["id"] = request["id"].clone();
+            reader.get_mut().write_all(format!("{}\n", response).as_bytes()).await.unwrap();
+        }
+    });
+    address
+}
+
+/// HTTP server answering each request with `respond(method, path)` as (status, body)
+async fn esplora_server(respond: fn(&str, &str) -> (&'static str, String)) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/api", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 8192];
+            let n = socket.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            let mut first = request.lines().next().unwrap().split(' ');
+            let (method, path) = (first.next().unwrap().to_string(), first.next().unwrap().trim_start_matches("/api").to_string());
+            let (status, body) = respond(&method, &path);
+            let response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    url
+}
+
+#[test]
+fn test_script_hashes() {
+    assert_eq!(electrum_script_hash(&genesis_script()), "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161");
+    let mut reversed = hex::decode(esplora_script_hash(&genesis_script())).unwrap();
+    reversed.reverse();
+    assert_eq!(hex::encode(reversed), electrum_script_hash(&genesis_script()));
+}
+
+#[tokio::test]
+async fn test_electrum_backend_against_mock_server() {
+    let address = electrum_server(|method, params| match method {
+        "blockchain.scripthash.listunspent" => {
+            assert_eq!(params[0], "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161");
+            json!({ "result": [
+                { "tx_hash": "11".repeat(32), "tx_pos": 1, "height": 95, "value": 50_000 },
+                { "tx_hash": "22".repeat(32), "tx_pos": 0, "height": 0, "value": 7_000 },
+            ] })
+        }
+        "blockchain.headers.subscribe" => json!({ "result": { "height": 100, "hex": "" } }),
+        "blockchain.block.headers" => {
+            assert_eq!((params[0].as_u64(), params[1].as_u64()), (Some(90), Some(11)));
+            let headers: String = (0..11u32).map(|i| header_hex(1_000 + i * 7 % 11)).collect();
+            json!({ "result": { "count": 11, "hex": headers, "max": 2016 } })
+        }
+        "blockchain.block.header" => json!({ "result": header_hex(params[0].as_u64().unwrap() as u32) }),
+        "blockchain.estimatefee" if params[0] == 1 => json!({ "result": -1 }),
+        "blockchain.estimatefee" => json!({ "result": 0.0002 }),
+        "blockchain.relayfee" => json!({ "result": 0.00001 }),
+        "blockchain.transaction.get" if params[0] == "33".repeat(32) => json!({ "error": { "code": 1, "message": "verbose transactions are currently unsupported" } }),
+        "blockchain.transaction.get" if params[0] == "44".repeat(32) => json!({ "result": { "confirmations": 150, "blockhash": "55".repeat(32) } }),
+        "blockchain.transaction.get" => json!({ "error": { "code": 2, "message": "daemon error: transaction not found" } }),
+        _ => json!({ "error": { "code": -32601, "message": "unknown method" } }),
+    }).await;
+    let backend = ElectrumBackend::new(&address, Network::Testnet);
+
+    let utxos = backend.utxos(&genesis_script()).await.unwrap();
+    assert_eq!(utxos.len(), 2);
+    assert_eq!((utxos[0].outpoint.vout, utxos[0].amount, utxos[0].confirmations), (1, Amount::from_sat(50_000), 6));
+    assert_eq!(utxos[1].confirmations, 0);
+    assert_eq!(backend.tip_height().await.unwrap(), 100);
+    let tip = backend.chain_tip().await.unwrap();
+    assert_eq!((tip.height, tip.median_time_past), (100, 1_005));
+    let header: Header = deserialize(&hex::decode(header_hex(42)).unwrap()).unwrap();
+    assert_eq!(backend.block_hash(42).await.unwrap(), header.block_hash());
+    assert!(backend.block(&header.block_hash(), true).await.is_err(), "Electrum serves no blocks");
+    assert_eq!(backend.fee_rate(1).await.unwrap(), None);
+    // 0.0002 BTC/kvB = 20 sat/vB
+    assert_eq!(backend.fee_rate(6).await.unwrap(), Some(FeeRate::from_sat_per_vb_unchecked(20)));
+    assert_eq!(backend.relay_floor(DEFAULT_MIN_RELAY_RATE).await.unwrap().rate(), FeeRate::from_sat_per_vb_unchecked(1));
+    assert_eq!(backend.tx_status(&Txid::all_zeros()).await.unwrap(), None);
+    let rejected = backend.tx_status(&Txid::from_str(&"33".repeat(32)).unwrap()).await.unwrap_err().to_string();
+    assert!(rejected.contains("Fulcrum"), "{}", rejected);
+    // More confirmations than the tip allows is an inconsistent reply, not an underflow
+    assert!(backend.tx_status(&Txid::from_str(&"44".repeat(32)).unwrap()).await.is_err());
+    assert!(backend.call("server.nonsense", json!([])).await.unwrap_err().to_string().contains("unknown method"));
+
+    // The generic estimate has no regtest fallback on other networks
+    assert_eq!(estimate_fee_rate(&backend, 6).await.unwrap(), FeeRate::from_sat_per_vb_unchecked(20));
+    assert!(estimate_fee_rate(&backend, 1).await.is_err());
+}
+
+#[tokio::test]
+async fn test_esplora_backend_against_mock_server() {
+    let url = esplora_server(|method, path| match (method, path) {
+        ("GET", "/blocks/tip/height") => ("200 OK", "200".to_string()),
+        ("GET", "/blocks/tip/hash") => ("200 OK", "88".repeat(32)),
+        ("GET", "/block-height/199") => ("200 OK", "44".repeat(32)),
+        ("GET", p) if p == format!("/block/{}", "88".repeat(32)) => ("200 OK", json!({ "id": "88".repeat(32), "height": 200, "mediantime": 1_700_000_000 }).to_string()),
+        ("GET", p) if p == format!("/tx/{}", "99".repeat(32)) => ("200 OK", json!({ "weight": 561, "fee": 1_410, "status": { "confirmed": false } }).to_string()),
+        ("GET", "/fee-estimates") => ("200 OK", json!({ "1": 30.5, "3": 12.0, "6": 8.0, "144": 1.2 }).to_string()),
+        ("GET", p) if p.starts_with("/scripthash/") => ("200 OK", json!([
+            { "txid": "33".repeat(32), "vout": 2, "value": 12_345, "status": { "confirmed": true, "block_height": 199, "block_hash": "44".repeat(32) } },
+            { "txid": "55".repeat(32), "vout": 0, "value": 1_000, "status": { "confirmed": false } },
+        ]).to_string()),
+        ("GET", p) if p.starts_with(&format!("/tx/{}/status", "66".repeat(32))) => ("200 OK", json!({ "confirmed": true, "block_height": 198, "block_hash": "77".repeat(32) }).to_string()),
+        ("GET", p) if p.starts_with("/tx/") => ("404 Not Found", "Transaction not found".to_string()),
+        _ => ("404 Not Found", String::new()),
+    }).await;
+    let backend = EsploraBackend::new(&url, Network::Signet);
+
+    let script = genesis_script();
+    let utxos = backend.utxos(&script).await.unwrap();
+    assert_eq!((utxos[0].outpoint.vout, utxos[0].amount, utxos[0].confirmations), (2, Amount::from_sat(12_345), 2));
+    assert_eq!(utxos[1].confirmations, 0);
+    assert_eq!(utxos[1].script_pubkey, script);
+    // Target 4 uses the 3-block estimate, 12 sat/vB
+    assert_eq!(backend.fee_rate(4).await.unwrap(), Some(FeeRate::from_sat_per_vb_unchecked(12)));
+    assert_eq!(backend.fee_rate(1).await.unwrap(), Some(FeeRate::from_sat_per_kwu(7_625)));
+
+    let status = backend.tx_status(&Txid::from_str(&"66".repeat(32)).unwrap()).await.unwrap().unwrap();
+    assert_eq!(status.confirmations, 3);
+    assert_eq!(status.block.unwrap().1, 198);
+    assert_eq!(backend.tx_status(&Txid::all_zeros()).await.unwrap(), None);
+    assert!(backend.transaction(&Txid::all_zeros()).await.is_err());
+
+    let tip = backend.chain_tip().await.unwrap();
+    assert_eq!((tip.height, tip.median_time_past), (200, 1_700_000_000));
+    assert_eq!(backend.block_hash(199).await.unwrap(), BlockHash::from_str(&"44".repeat(32)).unwrap());
+    // Esplora has no ancestor data, so the entry is the transaction alone
+    let entry = backend.mempool_entry(&Txid::from_str(&"99".repeat(32)).unwrap()).await.unwrap();
+    assert_eq!((entry.vsize, entry.ancestor_size, entry.fees.ancestor), (141, 141, Amount::from_sat(1_410)));
+}
+
+#[tokio::test]
+async fn test_core_rpc_as_chain_backend() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("backend_wallet").await;
+    let _ = rpc.load_wallet("backend_wallet").await;
+    let rpc = rpc.with_wallet("backend_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let address = Address::from_str(&rpc.get_new_address().await.unwrap()).unwrap().assume_checked();
+    let txid = Txid::from_str(&rpc.send_to_address(&address.to_string(), Amount::from_sat(40_000)).await.unwrap()).unwrap();
+    assert_eq!(rpc.tx_status(&txid).await.unwrap().unwrap().confirmations, 0);
+    let height = mine(&rpc, 2).await.unwrap();
+    let status = rpc.tx_status(&txid).await.unwrap().unwrap();
+    assert_eq!((status.confirmations, status.block.map(|b| b.1)), (2, Some(height - 1)));
+    assert_eq!(rpc.transaction(&txid).await.unwrap().txid(), txid);
+    assert_eq!(rpc.tx_status(&Txid::all_zeros()).await.unwrap(), None);
+
+    let utxos = ChainBackend::utxos(&rpc, &address.script_pubkey()).await.unwrap();
+    assert!(utxos.iter().any(|u| u.outpoint.txid == txid && u.amount == Amount::from_sat(40_000)));
+    assert_eq!(rpc.tip_height().await.unwrap(), height);
+    assert_eq!(rpc.chain_tip().await.unwrap().height, height);
+    let hashes = rpc.block_hashes(height - 1..=height).await.unwrap();
+    assert_eq!(hashes[1], rpc.block_hash(height).await.unwrap());
+    assert_eq!(rpc.block(&hashes[0], false).await.unwrap()["height"], height - 1);
+    assert!(estimate_fee_rate(&rpc, 6).await.unwrap() >= rpc.relay_floor(DEFAULT_MIN_RELAY_RATE).await.unwrap().rate());
+}
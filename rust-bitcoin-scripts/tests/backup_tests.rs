@@ -0,0 +1,76 @@
+use bitcoin_scripts::backup::{self, BackupContents, BackupEntry, FORMAT_VERSION};
+use std::path::PathBuf;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wrapyield-backup-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_backup_round_trip_restores_every_file() {
+    let dir = scratch("roundtrip");
+    let state = dir.join("state");
+    std::fs::create_dir_all(state.join("rounds")).unwrap();
+    std::fs::write(state.join("scanner.json"), br#"{"last_scanned":120}"#).unwrap();
+    std::fs::write(state.join("rounds").join("r1.json"), b"[]").unwrap();
+    let descriptors = dir.join("descriptors.txt");
+    std::fs::write(&descriptors, b"wsh(multi(2,...))\n").unwrap();
+
+    let archive = dir.join("backup.wybak");
+    let contents = backup::create(&archive, &[state.clone(), descriptors], "correct horse").unwrap();
+    let paths: Vec<&str> = contents.entries.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, vec!["descriptors.txt", "state/rounds/r1.json", "state/scanner.json"]);
+    let raw = std::fs::read(&archive).unwrap();
+    assert!(!raw.windows(12).any(|w| w == b"last_scanned"), "archive must not contain plaintext");
+
+    let target = dir.join("restored");
+    let restored = backup::restore(&archive, &target, "correct horse", false).unwrap();
+    assert_eq!(restored.len(), 3);
+    assert_eq!(std::fs::read(target.join("state/scanner.json")).unwrap(), br#"{"last_scanned":120}"#);
+    assert_eq!(std::fs::read(target.join("descriptors.txt")).unwrap(), b"wsh(multi(2,...))\n");
+
+    // Restoring again over the same files needs --overwrite
+    assert!(backup::restore(&archive, &target, "correct horse", false).is_err());
+    assert!(backup::restore(&archive, &target, "correct horse", true).is_ok());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_wrong_passphrase_and_tampering_are_rejected() {
+    let dir = scratch("tamper");
+    let file = dir.join("keystore.json");
+    std::fs::write(&file, b"{}").unwrap();
+    let archive = dir.join("backup.wybak");
+    backup::create(&archive, &[file], "passphrase").unwrap();
+
+    let mut raw = std::fs::read(&archive).unwrap();
+    assert!(backup::decrypt(&raw, "passphrase").is_ok());
+    assert!(backup::decrypt(&raw, "Passphrase").is_err());
+    let last = raw.len() - 1;
+    raw[last] ^= 1;
+    assert!(backup::decrypt(&raw, "passphrase").is_err());
+    assert!(backup::decrypt(b"not an archive", "passphrase").is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_entry_hashes_and_paths_are_verified() {
+    let entry = |path: &str, sha256: &str| BackupEntry { path: path.to_string(), sha256: sha256.to_string(), data: "aGk=".to_string() };
+    // sha256("hi")
+    let hi = "8f434346648f6b96df89dda901c5176b10a6d83961dd3c1ac88b59b2dc327aa4";
+    let contents = |entries| BackupContents { version: FORMAT_VERSION, crate_version: "0".to_string(), created_at: 0, entries };
+    assert!(contents(vec![entry("ok/file", hi)]).verify().is_ok());
+    assert!(contents(vec![entry("ok/file", &"00".repeat(32))]).verify().is_err());
+    assert!(contents(vec![entry("../escape", hi)]).verify().is_err());
+    assert!(contents(vec![entry("/etc/passwd", hi)]).verify().is_err());
+
+    // A crafted archive with a traversal path is refused before anything is written
+    let dir = scratch("traversal");
+    let archive = dir.join("evil.wybak");
+    std::fs::write(&archive, backup::encrypt(&contents(vec![entry("../escape", hi)]), "p").unwrap()).unwrap();
+    assert!(backup::restore(&archive, &dir.join("target"), "p", true).is_err());
+    assert!(!dir.join("escape").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
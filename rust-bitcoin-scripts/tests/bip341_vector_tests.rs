@@ -0,0 +1,79 @@
+//! BIP341 `scriptPubKey` wallet test vectors (bip-0341/wallet-test-vectors.json) run against the
+//! explicit-tree helpers in `taproot_tree`. Sighash vectors are not repeated here: signature hashes
+//! come from rust-bitcoin's `SighashCache`, which is tested against the same file upstream.
+
+use bitcoin_scripts::taproot_tree::{ScriptTree, TreeOutput};
+use bitcoin::hashes::Hash;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::taproot::{LeafVersion, TaprootBuilder};
+use bitcoin::{Address, Network, ScriptBuf};
+use serde_json::Value;
+use std::str::FromStr;
+
+fn parse_tree(node: &Value) -> ScriptTree {
+    match node {
+        Value::Array(children) => ScriptTree::branch(parse_tree(&children[0]), parse_tree(&children[1])),
+        leaf => ScriptTree::Leaf {
+            script: ScriptBuf::from_bytes(hex::decode(leaf["script"].as_str().unwrap()).unwrap()),
+            version: LeafVersion::from_consensus(leaf["leafVersion"].as_u64().unwrap() as u8).unwrap(),
+        },
+    }
+}
+
+fn vectors() -> Vec<Value> {
+    serde_json::from_str(include_str!("data/bip341_script_pubkey_vectors.json")).unwrap()
+}
+
+#[test]
+fn test_bip341_script_pubkey_vectors() {
+    let secp = Secp256k1::verification_only();
+    for (i, vector) in vectors().iter().enumerate() {
+        let internal_key = XOnlyPublicKey::from_str(vector["given"]["internalPubkey"].as_str().unwrap()).unwrap();
+        let tree = match &vector["given"]["scriptTree"] {
+            Value::Null => None,
+            node => Some(parse_tree(node)),
+        };
+        let output = TreeOutput::new(&secp, internal_key, tree.as_ref());
+        let intermediary = &vector["intermediary"];
+        let expected = &vector["expected"];
+
+        assert_eq!(output.merkle_root.map(|r| hex::encode(r.to_byte_array())).as_deref(), intermediary["merkleRoot"].as_str(), "vector {} merkle root", i);
+        assert_eq!(hex::encode(output.tweak.to_byte_array()), intermediary["tweak"].as_str().unwrap(), "vector {} tweak", i);
+        assert_eq!(hex::encode(output.output_key.to_inner().serialize()), intermediary["tweakedPubkey"].as_str().unwrap(), "vector {} output key", i);
+        assert_eq!(hex::encode(output.script_pubkey().as_bytes()), expected["scriptPubKey"].as_str().unwrap(), "vector {} scriptPubKey", i);
+        assert_eq!(Address::p2tr_tweaked(output.output_key, Network::Bitcoin).to_string(), expected["bip350Address"].as_str().unwrap(), "vector {} address", i);
+
+        let leaf_hashes: Vec<String> = output.leaves.iter().map(|l| hex::encode(l.leaf_hash().to_byte_array())).collect();
+        let expected_hashes: Vec<String> = intermediary["leafHashes"].as_array().into_iter().flatten()
+            .map(|h| h.as_str().unwrap().to_string()).collect();
+        assert_eq!(leaf_hashes, expected_hashes, "vector {} leaf hashes", i);
+
+        for (j, expected_cb) in expected["scriptPathControlBlocks"].as_array().into_iter().flatten().enumerate() {
+            let control_block = output.control_block(j).unwrap();
+            assert_eq!(hex::encode(control_block.serialize()), expected_cb.as_str().unwrap(), "vector {} control block {}", i, j);
+            assert!(control_block.verify_taproot_commitment(&secp, output.output_key.to_inner(), &output.leaves[j].script));
+        }
+    }
+}
+
+#[test]
+fn test_explicit_tree_matches_taproot_builder() {
+    let secp = Secp256k1::new();
+    let vector = &vectors()[5];
+    let internal_key = XOnlyPublicKey::from_str(vector["given"]["internalPubkey"].as_str().unwrap()).unwrap();
+    let tree = parse_tree(&vector["given"]["scriptTree"]);
+    let output = TreeOutput::new(&secp, internal_key, Some(&tree));
+
+    let mut builder = TaprootBuilder::new();
+    for leaf in &output.leaves {
+        builder = builder.add_leaf(leaf.depth as u8, leaf.script.clone()).unwrap();
+    }
+    let spend_info = builder.finalize(&secp, internal_key).unwrap();
+    assert_eq!(spend_info.merkle_root(), output.merkle_root);
+    assert_eq!(spend_info.output_key(), output.output_key);
+    for (j, leaf) in output.leaves.iter().enumerate() {
+        let expected = spend_info.control_block(&(leaf.script.clone(), leaf.version)).unwrap();
+        assert_eq!(expected, output.control_block(j).unwrap());
+    }
+}
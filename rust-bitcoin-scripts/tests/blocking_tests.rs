@@ -0,0 +1,27 @@
+#![cfg(feature = "blocking")]
+
+use bitcoin_scripts::blocking::{BitcoinRpcBlocking, BlockingChainBackend};
+use bitcoin::hashes::Hash;
+use bitcoin::Txid;
+use serde_json::json;
+
+#[test]
+fn test_blocking_rpc_round_trip() {
+    let rpc = BitcoinRpcBlocking::new();
+    let _ = rpc.create_wallet("testwallet");
+    let _ = rpc.load_wallet("testwallet");
+    let rpc = rpc.with_wallet("testwallet");
+    let block_count = rpc.call_rpc("getblockcount", json!([])).unwrap();
+    println!("Current block height: {}", block_count);
+    let address = rpc.get_new_address().unwrap();
+    let hashes = rpc.generate_to_address(1, &address).unwrap();
+    assert_eq!(hashes.len(), 1);
+    let new_count = rpc.call_rpc("getblockcount", json!([])).unwrap();
+    assert_eq!(new_count.as_u64().unwrap(), block_count.as_u64().unwrap() + 1);
+
+    let tip = rpc.chain_tip().unwrap();
+    assert_eq!(tip.height, new_count.as_u64().unwrap());
+    assert_eq!(rpc.block_hash(tip.height).unwrap().to_string(), hashes[0]);
+    assert_eq!(rpc.block(&rpc.block_hash(tip.height).unwrap(), false).unwrap()["height"], tip.height);
+    assert_eq!(rpc.tx_status(&Txid::all_zeros()).unwrap(), None);
+}
@@ -0,0 +1,131 @@
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::broadcast::{is_final, ChainTip, HoldingQueue, HoldStatus};
+use bitcoin_scripts::hybrid::sign_wsh_input;
+use bitcoin_scripts::psbt;
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use std::str::FromStr;
+use bitcoin::{Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness, absolute::LockTime};
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+
+fn tx_with_locktime(lock_time: u32, sequence: Sequence) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: LockTime::from_consensus(lock_time),
+        input: vec![TxIn { previous_output: OutPoint::null(), script_sig: ScriptBuf::new(), sequence, witness: Witness::default() }],
+        output: vec![],
+    }
+}
+
+#[test]
+fn test_is_final_rules() {
+    let tip = ChainTip { height: 100, median_time_past: 1_700_000_000 };
+    assert!(is_final(&tx_with_locktime(0, Sequence(0xfffffffe)), tip));
+    assert!(is_final(&tx_with_locktime(100, Sequence(0xfffffffe)), tip), "locktime == tip height is final");
+    assert!(!is_final(&tx_with_locktime(101, Sequence(0xfffffffe)), tip), "the next block must be above the locktime");
+    assert!(is_final(&tx_with_locktime(101, Sequence::MAX), tip), "all-final sequences disable nLockTime");
+    assert!(is_final(&tx_with_locktime(1_699_999_999, Sequence(0xfffffffe)), tip));
+    assert!(!is_final(&tx_with_locktime(1_700_000_000, Sequence(0xfffffffe)), tip), "time locks compare strictly against MTP");
+}
+
+#[tokio::test]
+async fn test_holding_queue_broadcasts_when_final() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("testwallet").await;
+    let _ = rpc.load_wallet("testwallet").await;
+    let funding_address = rpc.get_new_address().await.unwrap();
+    let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
+
+    let secp = secp256k1::Secp256k1::new();
+    let privkey = PrivateKey::new(secp256k1::SecretKey::from_slice(&[11; 32]).unwrap(), Network::Regtest);
+    let pubkey = PublicKey::from_private_key(&secp, &privkey);
+    let tip = ChainTip::fetch(&rpc).await.unwrap();
+    let cltv_height = tip.height as u32 + 4;
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(and_v(v:pk({}),after({})))", pubkey, cltv_height)).unwrap();
+    let address = descriptor.address(Network::Regtest).unwrap().to_string();
+
+    let txid = rpc.send_to_address(&address, Amount::from_sat(10_000_000)).await.unwrap();
+    let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
+    let utxo = rpc.find_utxos_for_address(&address).await.unwrap().into_iter()
+        .find(|u| u.outpoint.txid.to_string() == txid)
+        .expect("CLTV output not found");
+    let amount = utxo.amount.to_sat();
+
+    let destination = rpc.get_new_address().await.unwrap();
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::from_height(cltv_height).unwrap(),
+        input: vec![TxIn {
+            previous_output: utxo.outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence(0xfffffffe),
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut { value: amount - 10_000, script_pubkey: Address::from_str(&destination).unwrap().assume_checked().script_pubkey() }],
+    };
+    let mut unsigned = Psbt::from_unsigned_tx(tx).unwrap();
+    unsigned.inputs[0].witness_utxo = Some(TxOut { value: amount, script_pubkey: descriptor.script_pubkey() });
+    unsigned.inputs[0].witness_script = Some(descriptor.explicit_script().unwrap());
+    sign_wsh_input(&mut unsigned, 0, &[privkey]).unwrap();
+    let signed = psbt::finalize(unsigned).unwrap();
+
+    let mut queue = HoldingQueue::new();
+    let held = queue.hold(signed);
+    assert!(queue.poll(&rpc).await.unwrap().is_empty(), "must not broadcast before the locktime");
+    assert!(matches!(queue.status(&held), Some(HoldStatus::Waiting { .. })));
+
+    let _ = rpc.generate_to_address(3, &funding_address).await.unwrap();
+    let broadcast = queue.poll(&rpc).await.unwrap();
+    assert_eq!(broadcast, vec![held]);
+    assert_eq!(queue.status(&held), Some(&HoldStatus::Broadcast { txid: held.to_string() }));
+    let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
+}
+
+#[test]
+fn test_priority_policy_boosts_within_floor_and_cap() {
+    use bitcoin::FeeRate;
+    use bitcoin_scripts::broadcast::PriorityPolicy;
+    let policy = PriorityPolicy { boost_percent: 300, cap: FeeRate::from_sat_per_vb(50).unwrap() };
+    let rate = |sat_vb| FeeRate::from_sat_per_vb(sat_vb).unwrap();
+    assert_eq!(policy.rate(rate(10), rate(1)).unwrap(), rate(30));
+    assert_eq!(policy.rate(rate(40), rate(1)).unwrap(), rate(50));
+    assert_eq!(policy.rate(rate(1), rate(20)).unwrap(), rate(20));
+    assert!(policy.rate(rate(1), rate(60)).is_err());
+}
+
+#[tokio::test]
+async fn test_priority_broadcast_reaches_every_node() {
+    use bitcoin_scripts::broadcast::{broadcast_priority, PriorityPolicy};
+    use bitcoin_scripts::fees::FeePlan;
+    use bitcoin_scripts::flows::{fund_descriptor, mine};
+    use miniscript::descriptor::DefiniteDescriptorKey;
+
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("priority_wallet").await;
+    let _ = rpc.load_wallet("priority_wallet").await;
+    let rpc = rpc.with_wallet("priority_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let secp = secp256k1::Secp256k1::new();
+    let key = PrivateKey::new(secp256k1::SecretKey::from_slice(&[77u8; 32]).unwrap(), Network::Regtest);
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("wpkh({})", key.public_key(&secp))).unwrap();
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(100_000)).await.unwrap();
+    let destination = Address::from_str(&rpc.get_new_address().await.unwrap()).unwrap().assume_checked();
+    let deadline = rpc.get_block_count().await.unwrap() + 10;
+
+    let build = |rate| {
+        let plan = FeePlan::sweep(&[&funded.descriptor], funded.amount(), destination.script_pubkey(), rate)?;
+        let definite = Descriptor::<DefiniteDescriptorKey>::from_str(&funded.descriptor.to_string())?;
+        let mut unsigned = psbt::create(&definite, &[funded.utxo.clone()], plan.outputs, LockTime::ZERO)?;
+        psbt::sign(&mut unsigned, &[key])?;
+        Ok((psbt::finalize(unsigned)?, plan.fee))
+    };
+    // The same node twice: the second submission finds it already in the mempool
+    let policy = PriorityPolicy::default();
+    let outcome = broadcast_priority(&[rpc.clone(), rpc.clone()], deadline, &policy, build).await.unwrap();
+    assert_eq!(outcome.accepted_by.len(), 2, "{:?}", outcome.rejected_by);
+    assert!(outcome.fee_rate <= policy.cap);
+    assert!(outcome.blocks_left <= 10);
+
+    assert!(broadcast_priority(&[rpc.clone()], 1, &policy, build).await.is_err());
+}
@@ -0,0 +1,123 @@
+use bitcoin_scripts::actors::{BroadcasterHandle, BroadcasterOptions, CoordinatorHandle, CoordinatorOptions, WatcherHandle, WatcherOptions};
+use bitcoin_scripts::cancel::{guarded, interruption, CancellationToken, Interrupted, OperationTimeouts};
+use bitcoin_scripts::reservation::UtxoReservations;
+use bitcoin_scripts::scanner::BlockScanner;
+use bitcoin_scripts::signing_round::{RoundState, SigningRound, SigningRounds};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, absolute::LockTime};
+use bitcoin::hashes::Hash;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+fn unsigned_psbt(vout: u32) -> Psbt {
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), vout),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut { value: 10_000, script_pubkey: ScriptBuf::new() }],
+    };
+    Psbt::from_unsigned_tx(tx).unwrap()
+}
+
+#[tokio::test]
+async fn test_guarded_reports_timeout_and_cancellation() {
+    let token = CancellationToken::new();
+    let ok: Result<u32, Box<dyn std::error::Error>> = guarded(&token, "fast", Duration::from_secs(1), async { Ok(7) }).await;
+    assert_eq!(ok.unwrap(), 7);
+
+    let slow = guarded(&token, "slow", Duration::from_millis(20), async {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        Ok(())
+    }).await.unwrap_err();
+    assert_eq!(interruption(slow.as_ref()), Some(&Interrupted::TimedOut { operation: "slow".to_string(), after: Duration::from_millis(20) }));
+
+    token.cancel();
+    let cancelled = guarded(&token, "anything", Duration::from_secs(5), async { Ok(()) }).await.unwrap_err();
+    assert_eq!(interruption(cancelled.as_ref()), Some(&Interrupted::Cancelled { operation: "anything".to_string() }));
+}
+
+#[tokio::test]
+async fn test_coordinator_persists_collecting_rounds_on_shutdown() {
+    let state_path = std::env::temp_dir().join(format!("wrapyield-rounds-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    let signers = vec!["alice".to_string(), "bob".to_string()];
+    let shutdown = CancellationToken::new();
+    let (events_tx, _events_rx) = mpsc::unbounded_channel();
+    let options = CoordinatorOptions { interval: Duration::from_secs(3600), shutdown: shutdown.clone(), state_path: Some(state_path.clone()) };
+    let (coordinator, task) = CoordinatorHandle::spawn_with(UtxoReservations::new(Duration::from_secs(600)), events_tx, options).unwrap();
+
+    coordinator.open(SigningRound::new("partial", unsigned_psbt(0), signers.clone(), 2, Duration::from_secs(600))).await.unwrap();
+    coordinator.open(SigningRound::new("done", unsigned_psbt(1), signers.clone(), 1, Duration::from_secs(600))).await.unwrap();
+    assert!(!coordinator.submit("partial", "alice", unsigned_psbt(0)).await.unwrap());
+    assert!(coordinator.submit("done", "bob", unsigned_psbt(1)).await.unwrap());
+
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(1), task).await.expect("coordinator stops promptly").unwrap();
+    assert!(coordinator.open(SigningRound::new("late", unsigned_psbt(2), signers, 1, Duration::from_secs(1))).await.is_err());
+
+    let resumed = SigningRounds::resume(&state_path).unwrap();
+    assert_eq!(resumed.rounds.len(), 1, "only rounds still collecting are saved");
+    let round = &resumed.rounds["partial"];
+    assert_eq!(round.state, RoundState::Collecting);
+    assert_eq!(round.responded, vec!["alice".to_string()]);
+    assert_eq!(round.locked_utxos, vec![OutPoint::new(Txid::all_zeros(), 0)]);
+    assert_eq!(round.psbt, unsigned_psbt(0));
+
+    // A restarted coordinator picks the round up where it stopped
+    let (events_tx, _events_rx) = mpsc::unbounded_channel();
+    let options = CoordinatorOptions { interval: Duration::from_secs(3600), shutdown: CancellationToken::new(), state_path: Some(state_path.clone()) };
+    let (coordinator, _task) = CoordinatorHandle::spawn_with(UtxoReservations::new(Duration::from_secs(600)), events_tx, options).unwrap();
+    assert!(coordinator.submit("partial", "bob", unsigned_psbt(0)).await.unwrap());
+    std::fs::remove_file(&state_path).unwrap();
+}
+
+#[test]
+fn test_resumed_round_keeps_elapsed_time() {
+    let signers = vec!["alice".to_string(), "bob".to_string()];
+    let round = SigningRound::new("r", unsigned_psbt(0), signers, 2, Duration::from_secs(60));
+    let snapshot = round.snapshot(Instant::now() + Duration::from_secs(50));
+    assert_eq!(snapshot.elapsed_secs, 50);
+    let mut resumed = SigningRound::resume(&snapshot).unwrap();
+    assert!(resumed.check_timeout(Instant::now() + Duration::from_secs(11)).is_some());
+}
+
+#[tokio::test]
+async fn test_broadcaster_stops_on_shutdown() {
+    let shutdown = CancellationToken::new();
+    let options = BroadcasterOptions { interval: Duration::from_millis(10), shutdown: shutdown.clone(), timeouts: OperationTimeouts::default(), state_path: None };
+    let (broadcaster, task) = BroadcasterHandle::spawn_with(BitcoinRPC::new(), options).unwrap();
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(1), task).await.expect("broadcaster stops promptly").unwrap();
+    assert!(broadcaster.poll().await.is_err());
+}
+
+#[tokio::test]
+async fn test_watcher_scan_is_bounded_by_block_deadline() {
+    // A node that accepts connections and never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((socket, _)) = listener.accept().await {
+            held.push(socket);
+        }
+    });
+    let mut rpc = BitcoinRPC::new();
+    rpc.url = format!("http://127.0.0.1:{}", port);
+
+    let timeouts = OperationTimeouts { scan_block: Duration::from_millis(100), ..OperationTimeouts::default() };
+    let (events_tx, _events_rx) = mpsc::unbounded_channel();
+    let options = WatcherOptions { timeouts, ..WatcherOptions::default() };
+    let (watcher, _task) = WatcherHandle::spawn_with(rpc, BlockScanner::new(), events_tx, options).unwrap();
+    let started = Instant::now();
+    let error = watcher.scan_range(1, 10).await.unwrap_err().to_string();
+    assert!(error.contains("scan of block 1 timed out"), "{}", error);
+    assert!(error.contains("last scanned height: None"), "{}", error);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
@@ -1,21 +1,130 @@
-use bitcoin_scripts::test_setup::{BitcoinRPC};
-use bitcoin_scripts::classic_multisig::{create_multisig, create_redeem_script};
-use miniscript::bitcoin::Network;
+use bitcoin_scripts::amount::{deduct_fee, from_rpc};
+use bitcoin_scripts::test_setup::{BitcoinRPC, DESTINATION_LABEL, FUNDING_LABEL};
+use bitcoin_scripts::classic_multisig::{build_p2sh_multisig_script_sig, create_multisig, create_multisig_with_keys, create_redeem_script, parse_multisig_redeem_script, sign_legacy_p2sh_input, sign_p2sh_multisig, MultisigKind};
+use bitcoin_scripts::psbt::{self, SpendableUtxo};
+use bitcoin_scripts::verify::verify_spend;
+use miniscript::bitcoin::{Amount, Network, PrivateKey, PublicKey, secp256k1};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use bitcoin::absolute::LockTime;
+use bitcoin::consensus::encode::serialize;
+use bitcoin::hashes::Hash;
+use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use std::str::FromStr;
 use hex;
 use serde_json::json;
 use std::collections::HashMap;
 
+mod common;
+use common::key;
+
+/// Flat fee for the hand-built spends
+const FEE: Amount = Amount::from_sat(100_000);
+
+/// Sign a spend of `descriptor` with `signers` and return it with the output it spends
+fn spend(descriptor: &Descriptor<PublicKey>, signers: &[PrivateKey]) -> (Transaction, TxOut) {
+    let definite = Descriptor::<DefiniteDescriptorKey>::from_str(&descriptor.to_string()).unwrap();
+    let prev_tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+        output: vec![TxOut { value: 100_000, script_pubkey: descriptor.script_pubkey() }],
+    };
+    let mut utxo = SpendableUtxo::new(OutPoint::new(prev_tx.txid(), 0), prev_tx.output[0].clone());
+    utxo.prev_tx = Some(prev_tx.clone());
+    let mut unsigned = psbt::create(&definite, &[utxo], vec![TxOut { value: 90_000, script_pubkey: descriptor.script_pubkey() }], LockTime::ZERO).unwrap();
+    psbt::sign(&mut unsigned, signers).unwrap();
+    (psbt::finalize(unsigned).unwrap(), prev_tx.output[0].clone())
+}
+
+#[test]
+fn test_multisig_with_keys_per_kind() {
+    let secp = secp256k1::Secp256k1::new();
+    let keys: Vec<PrivateKey> = (51u8..=55).map(key).collect();
+    let pubkeys: Vec<PublicKey> = keys.iter().map(|k| k.public_key(&secp)).collect();
+
+    for kind in [MultisigKind::Sh, MultisigKind::Wsh, MultisigKind::ShWsh, MultisigKind::TrMultiA] {
+        let multisig = create_multisig_with_keys(3, &pubkeys, kind).unwrap();
+        assert_eq!(multisig.address, multisig.descriptor.address(Network::Regtest).unwrap().to_string(), "{:?}", kind);
+        let spk = multisig.descriptor.script_pubkey();
+        match kind {
+            MultisigKind::Sh => {
+                assert_eq!(spk, multisig.redeem_script.clone().unwrap().to_p2sh());
+                assert!(multisig.witness_script.is_none());
+            }
+            MultisigKind::Wsh => assert_eq!(spk, multisig.witness_script.clone().unwrap().to_v0_p2wsh()),
+            MultisigKind::ShWsh => {
+                let witness_script = multisig.witness_script.clone().unwrap();
+                assert_eq!(multisig.redeem_script.clone().unwrap(), witness_script.to_v0_p2wsh());
+                assert_eq!(spk, witness_script.to_v0_p2wsh().to_p2sh());
+            }
+            MultisigKind::TrMultiA => {
+                assert!(spk.is_v1_p2tr());
+                assert!(multisig.witness_script.clone().unwrap().as_bytes().ends_with(&[0x53, 0x9c]), "OP_3 OP_NUMEQUAL");
+            }
+        }
+
+        // Any three of the five keys spend it
+        let (tx, prevout) = spend(&multisig.descriptor, &keys[1..4]);
+        assert_eq!(verify_spend(&tx, &[prevout]), Ok(()), "{:?}", kind);
+    }
+}
+
+#[test]
+fn test_multisig_with_keys_rejects_bad_thresholds_and_key_counts() {
+    let secp = secp256k1::Secp256k1::new();
+    let pubkeys: Vec<PublicKey> = (60u8..80).map(|b| key(b).public_key(&secp)).collect();
+    assert!(create_multisig_with_keys(0, &pubkeys[..3], MultisigKind::Wsh).is_err());
+    assert!(create_multisig_with_keys(4, &pubkeys[..3], MultisigKind::Wsh).is_err());
+    // 16 keys no longer fit a 520-byte P2SH redeemScript, but do fit P2WSH and tapscript
+    assert!(create_multisig_with_keys(2, &pubkeys[..16], MultisigKind::Sh).is_err());
+    assert!(create_multisig_with_keys(2, &pubkeys[..16], MultisigKind::Wsh).is_ok());
+    assert!(create_multisig_with_keys(2, &pubkeys, MultisigKind::TrMultiA).is_ok());
+}
+
+#[test]
+fn test_local_p2sh_multisig_signing_matches_psbt_finalizer() {
+    let secp = secp256k1::Secp256k1::new();
+    let keys: Vec<PrivateKey> = (81u8..=83).map(key).collect();
+    let pubkeys: Vec<PublicKey> = keys.iter().map(|k| k.public_key(&secp)).collect();
+    let multisig = create_multisig_with_keys(2, &pubkeys, MultisigKind::Sh).unwrap();
+    let redeem_script = multisig.redeem_script.clone().unwrap();
+    assert_eq!(parse_multisig_redeem_script(&redeem_script).unwrap(), (2, pubkeys.clone()));
+
+    // RFC6979 signatures over the same legacy sighash come out byte for byte the same
+    let (finalized, prevout) = spend(&multisig.descriptor, &keys[1..]);
+    let mut tx = finalized.clone();
+    tx.input[0].script_sig = ScriptBuf::new();
+    sign_p2sh_multisig(&secp, &mut tx, 0, &redeem_script, &keys[1..]).unwrap();
+    assert_eq!(tx.input[0].script_sig, finalized.input[0].script_sig);
+    assert_eq!(verify_spend(&tx, &[prevout]), Ok(()));
+
+    // Signatures given in reverse key order still come out in script order
+    let sigs = HashMap::from([
+        (pubkeys[2], sign_legacy_p2sh_input(&secp, &tx, 0, &redeem_script, &keys[2]).unwrap()),
+        (pubkeys[0], sign_legacy_p2sh_input(&secp, &tx, 0, &redeem_script, &keys[0]).unwrap()),
+    ]);
+    let script_sig = build_p2sh_multisig_script_sig(&redeem_script, &sigs).unwrap();
+    let pushes: Vec<_> = script_sig.instructions().map(|i| i.unwrap()).collect();
+    assert_eq!(pushes.len(), 4);
+    assert_eq!(pushes[1].push_bytes().unwrap().as_bytes(), sigs[&pubkeys[0]].to_vec().as_slice());
+
+    assert!(build_p2sh_multisig_script_sig(&redeem_script, &HashMap::from([(pubkeys[0], sigs[&pubkeys[0]])])).is_err());
+    assert!(sign_p2sh_multisig(&secp, &mut tx, 0, &redeem_script, &[key(99)]).is_err());
+    assert!(parse_multisig_redeem_script(&multisig.descriptor.script_pubkey()).is_err());
+}
+
 #[tokio::test]
 async fn test_fund_and_spend_classic_multisig() {
     let rpc = BitcoinRPC::new();
     let _ = rpc.create_wallet("testwallet").await;
     let _ = rpc.load_wallet("testwallet").await;
-    // Generate some keys in the wallet to ensure it has addresses
-    let _ = rpc.generate_keys(5).await.unwrap();
+    // Make sure the wallet has keys to hand out
+    rpc.ensure_keypool(5).await.unwrap();
     let multisig_info = create_multisig().unwrap();
-    let funding_address = rpc.get_new_address().await.unwrap();
+    let funding_address = rpc.new_labeled_address(FUNDING_LABEL).await.unwrap();
     let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
-    let send_amount = 0.1;
+    let send_amount = Amount::from_sat(10_000_000);
     let txid = rpc.send_to_address(&multisig_info.address, send_amount).await.unwrap();
     let _ = rpc.generate_to_address(6, &funding_address).await.unwrap();
     let raw_tx_details = rpc.call_rpc("getrawtransaction", json!([txid, true])).await.unwrap();
@@ -26,39 +135,24 @@ async fn test_fund_and_spend_classic_multisig() {
         })
         .expect("Multisig output not found in transaction");
     let output = &raw_tx_details["vout"].as_array().unwrap()[vout];
-    let amount = output["value"].as_f64().unwrap();
-    let script_pub_key = output["scriptPubKey"]["hex"].as_str().unwrap();
-    let destination_address = rpc.get_new_address().await.unwrap();
-    let inputs = vec![json!({
-        "txid": txid,
-        "vout": vout
-    })];
-    let mut outputs = HashMap::new();
-    outputs.insert(destination_address.clone(), amount - 0.001);
-    let raw_tx = rpc.create_raw_transaction(inputs, outputs).await.unwrap();
+    let amount = from_rpc(&output["value"]).unwrap();
+    let script_pub_key = ScriptBuf::from_hex(output["scriptPubKey"]["hex"].as_str().unwrap()).unwrap();
+    let destination_address = rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap();
+    // Built and signed locally: the node only relays it
+    let mut tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::from_str(&txid).unwrap(), vout as u32), script_sig: ScriptBuf::new(), sequence: Sequence::ENABLE_RBF_NO_LOCKTIME, witness: Witness::new() }],
+        output: vec![TxOut {
+            value: deduct_fee(amount, FEE).unwrap().to_sat(),
+            script_pubkey: bitcoin::Address::from_str(&destination_address).unwrap().assume_checked().script_pubkey(),
+        }],
+    };
     let redeem_script = create_redeem_script(&multisig_info.public_keys);
-    let redeem_script_hex = hex::encode(redeem_script.as_bytes());
-    let privkeys_wif: Vec<String> = multisig_info.private_keys.iter()
-        .take(2)
-        .map(|pk| pk.to_wif())
-        .collect();
-    let prevtxs = vec![json!({
-        "txid": txid,
-        "vout": vout,
-        "scriptPubKey": script_pub_key,
-        "redeemScript": redeem_script_hex
-    })];
-    let params = json!([
-        raw_tx,
-        privkeys_wif,
-        prevtxs
-    ]);
-    let signed_result = rpc.call_rpc("signrawtransactionwithkey", params).await.unwrap();
-    if signed_result["complete"].as_bool().unwrap() {
-        let signed_hex = signed_result["hex"].as_str().unwrap();
-        let _ = rpc.send_raw_transaction(signed_hex).await.unwrap();
-        let _ = rpc.generate_to_address(6, &funding_address).await.unwrap();
-    } else {
-        panic!("Failed to sign transaction: {:?}", signed_result["errors"]);
-    }
-} 
\ No newline at end of file
+    let signers: Vec<PrivateKey> = multisig_info.private_keys.iter().skip(1).map(|k| k.expose()).collect();
+    sign_p2sh_multisig(&secp256k1::Secp256k1::new(), &mut tx, 0, &redeem_script, &signers).unwrap();
+    assert!(tx.input[0].witness.is_empty());
+    verify_spend(&tx, &[TxOut { value: amount.to_sat(), script_pubkey: script_pub_key }]).unwrap();
+    rpc.send_raw_transaction(&hex::encode(serialize(&tx))).await.unwrap();
+    let _ = rpc.generate_to_address(6, &funding_address).await.unwrap();
+}
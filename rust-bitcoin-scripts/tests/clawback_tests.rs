@@ -0,0 +1,116 @@
+use bitcoin_scripts::broadcast::{PriorityOutcome, PriorityPolicy};
+use bitcoin_scripts::clawback::{ClawbackStatus, ClawbackWatch, EscalationPolicy};
+use bitcoin_scripts::fees::FeePlan;
+use bitcoin_scripts::flows::{fund_descriptor, mine, mine_censoring};
+use bitcoin_scripts::psbt;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::timelock_csv::csv_vault_descriptor;
+use bitcoin_scripts::webhooks::LifecycleEvent;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Amount, FeeRate, PrivateKey, PublicKey, Transaction, Txid};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+mod common;
+use common::key;
+
+fn policy() -> EscalationPolicy {
+    EscalationPolicy {
+        priority: PriorityPolicy { boost_percent: 100, cap: FeeRate::from_sat_per_vb(50).unwrap() },
+        escalate_every: 2,
+        step_percent: 50,
+        alert_margin: 3,
+    }
+}
+
+#[test]
+fn test_escalation_schedule() {
+    let outcome = PriorityOutcome {
+        txid: Txid::all_zeros(),
+        fee_rate: FeeRate::from_sat_per_vb(2).unwrap(),
+        fee: Amount::from_sat(300),
+        blocks_left: 10,
+        accepted_by: vec![],
+        rejected_by: vec![],
+    };
+    let mut watch = ClawbackWatch::from_outcome(&outcome, 110, policy(), 100);
+    assert_eq!(watch.escalation(101).bump_to, None);
+    assert_eq!(watch.escalation(102).bump_to, Some(FeeRate::from_sat_per_vb(3).unwrap()));
+    assert!(!watch.escalation(102).alert);
+
+    // Small rates still go up by at least the incremental relay fee
+    watch.fee_rate = FeeRate::from_sat_per_kwu(300);
+    assert_eq!(watch.escalation(102).bump_to, Some(FeeRate::from_sat_per_kwu(550)));
+
+    // Close to the deadline: straight to the cap, and alert
+    let late = watch.escalation(107);
+    assert_eq!((late.bump_to, late.alert), (Some(policy().priority.cap), true));
+    watch.fee_rate = policy().priority.cap;
+    let capped = watch.escalation(109);
+    assert_eq!((capped.bump_to, capped.alert), (None, true));
+}
+
+#[tokio::test]
+async fn test_censored_clawback_escalates_and_alerts_before_timelock_matures() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("clawback_wallet").await;
+    let _ = rpc.load_wallet("clawback_wallet").await;
+    let rpc = rpc.with_wallet("clawback_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let secp = Secp256k1::new();
+    let keys: Vec<PrivateKey> = [61u8, 62, 63, 64].iter().map(|b| key(*b)).collect();
+    let k: Vec<PublicKey> = keys.iter().map(|p| p.public_key(&secp)).collect();
+    let descriptor = csv_vault_descriptor(k[3], &k[..3], 2, 12).unwrap();
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(200_000)).await.unwrap();
+    // The 2-of-3 path can be mined from `funded.height + 12` on
+    let deadline = funded.height + 11;
+    let destination = rpc.parse_address(&rpc.get_new_address().await.unwrap()).unwrap().script_pubkey();
+
+    // Clawback through the backup key
+    let build = |rate| {
+        let plan = FeePlan::sweep(&[&funded.descriptor], funded.amount(), destination.clone(), rate)?;
+        let definite = Descriptor::<DefiniteDescriptorKey>::from_str(&funded.descriptor.to_string())?;
+        let mut unsigned = psbt::create(&definite, &[funded.utxo.clone()], plan.outputs, LockTime::ZERO)?;
+        psbt::sign(&mut unsigned, &keys[3..])?;
+        let tx: Transaction = psbt::finalize(unsigned)?;
+        Ok((tx, plan.fee))
+    };
+    let nodes = [rpc.clone()];
+    let mut watch = ClawbackWatch::start(&nodes, deadline, policy(), build).await.unwrap();
+    let first = watch.txid;
+
+    let mut bumped_rates = vec![watch.fee_rate];
+    let mut alerts = Vec::new();
+    loop {
+        let tip = mine_censoring(&rpc, 1).await.unwrap();
+        let (status, raised) = watch.poll(&nodes, build).await.unwrap();
+        assert!(!matches!(status, ClawbackStatus::Confirmed { .. }), "censored blocks confirmed the clawback");
+        if let ClawbackStatus::Bumped { fee_rate, .. } = status {
+            bumped_rates.push(fee_rate);
+        }
+        for alert in &raised {
+            let LifecycleEvent::ClawbackStuck { blocks_left, .. } = alert else { panic!("unexpected alert {:?}", alert) };
+            assert!(*blocks_left <= 3);
+        }
+        alerts.extend(raised);
+        if deadline - tip <= 2 {
+            break;
+        }
+    }
+    assert!(bumped_rates.windows(2).all(|w| w[0] < w[1]), "{:?}", bumped_rates);
+    assert!(bumped_rates.len() >= 4);
+    assert_eq!(*bumped_rates.last().unwrap(), policy().priority.cap);
+    assert!(alerts.len() >= 2);
+    assert_ne!(watch.txid, first);
+    assert!(watch.replaced.contains(&first));
+
+    // An honest miner includes the last replacement, before the timelocked path matures
+    let tip = mine(&rpc, 1).await.unwrap();
+    assert!(tip < funded.height + 12);
+    let (status, _) = watch.poll(&nodes, build).await.unwrap();
+    assert_eq!(status, ClawbackStatus::Confirmed { txid: watch.txid, confirmations: 1 });
+}
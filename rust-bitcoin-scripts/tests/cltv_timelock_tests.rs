@@ -1,6 +1,12 @@
-use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::amount::{deduct_fee, from_rpc};
+use bitcoin_scripts::test_setup::{BitcoinRPC, CoreError, DESTINATION_LABEL, FUNDING_LABEL};
+use bitcoin_scripts::flows::{fund_descriptor, mine, FundedUtxo};
+use bitcoin_scripts::keystore::KeyEntry;
+use bitcoin_scripts::satisfier::satisfy_input;
+use bitcoin_scripts::secret::SigningKey;
+use bitcoin_scripts::verify::verify_spend;
+use bitcoin_scripts::witness::{build_backup_path_witness, build_multisig_timelock_witness, build_vault_script_sig, nested_vault_descriptor, vault_descriptor, VaultTimelock};
 use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
-use miniscript::Descriptor;
 use serde_json::json;
 use std::collections::HashMap;
 use std::str::FromStr;
@@ -10,12 +16,28 @@ use bitcoin::secp256k1::{Secp256k1, Message};
 use bitcoin::consensus::encode::serialize;
 use bitcoin::sighash::{SighashCache, EcdsaSighashType};
 
+/// Flat fee for the hand-built spends
+const FEE: Amount = Amount::from_sat(100_000);
+
+#[test]
+fn test_cltv_vault_descriptors() {
+    let secp = Secp256k1::new();
+    let k: Vec<PublicKey> = (1u8..=4).map(|b| PrivateKey::new(secp256k1::SecretKey::from_slice(&[b; 32]).unwrap(), Network::Regtest).public_key(&secp)).collect();
+    let vault = vault_descriptor(k[3], &k[..3], 2, VaultTimelock::Absolute(500)).unwrap();
+    assert_eq!(vault.to_string().split('#').next().unwrap(), format!("wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),after(500))))", k[3], k[0], k[1], k[2]));
+    let nested = nested_vault_descriptor(k[3], &k[..3], 2, VaultTimelock::Absolute(500)).unwrap();
+    assert_eq!(nested.to_string().split('#').next().unwrap(), format!("sh({})", vault.to_string().split('#').next().unwrap()));
+    assert!(vault_descriptor(k[3], &k[..3], 0, VaultTimelock::Absolute(500)).is_err());
+    assert!(vault_descriptor(k[3], &k[..3], 2, VaultTimelock::Absolute(0)).is_err());
+    assert!(nested_vault_descriptor(k[3], &k[..3], 2, VaultTimelock::Absolute(500_000_000)).is_err());
+}
+
 #[tokio::test]
 async fn test_fund_and_spend_cltv_timelock() {
     let rpc = BitcoinRPC::new();
     let _ = rpc.create_wallet("testwallet").await;
     let _ = rpc.load_wallet("testwallet").await;
-    let _ = rpc.generate_keys(5).await.unwrap();
+    rpc.ensure_keypool(5).await.unwrap();
 
     // Generate keys for 2-of-3 and backup
     let secp = secp256k1::Secp256k1::new();
@@ -32,19 +54,15 @@ async fn test_fund_and_spend_cltv_timelock() {
     let pubkey3 = PublicKey::from_private_key(&secp, &privkey3);
     let backup_pubkey = PublicKey::from_private_key(&secp, &backup_privkey);
     let cltv_height = 10u32;
-    let descriptor_str = format!(
-        "wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),after({}))))",
-        backup_pubkey, pubkey1, pubkey2, pubkey3, cltv_height
-    );
-    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&descriptor_str).unwrap();
+    let descriptor = vault_descriptor(backup_pubkey, &[pubkey1, pubkey2, pubkey3], 2, VaultTimelock::Absolute(cltv_height)).unwrap();
     let address = descriptor.address(Network::Regtest).unwrap();
-    println!("CLTV Timelock Descriptor: {}", descriptor_str);
+    println!("CLTV Timelock Descriptor: {}", descriptor);
     println!("CLTV Timelock Address: {}", address);
 
     // Fund the address
-    let funding_address = rpc.get_new_address().await.unwrap();
+    let funding_address = rpc.new_labeled_address(FUNDING_LABEL).await.unwrap();
     let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
-    let send_amount = 0.1;
+    let send_amount = Amount::from_sat(10_000_000);
     let txid = rpc.send_to_address(&address.to_string(), send_amount).await.unwrap();
     // Mine enough blocks to pass the timelock
     let _ = rpc.generate_to_address(cltv_height + 1, &funding_address).await.unwrap();
@@ -56,9 +74,9 @@ async fn test_fund_and_spend_cltv_timelock() {
         })
         .expect("CLTV output not found in transaction");
     let output = &raw_tx_details["vout"].as_array().unwrap()[vout];
-    let amount = output["value"].as_f64().unwrap();
+    let amount = from_rpc(&output["value"]).unwrap();
     let redeem_script = descriptor.explicit_script().unwrap();
-    let destination_address = rpc.get_new_address().await.unwrap();
+    let destination_address = rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap();
 
     // --- Path 1: Single-sig (pk(backup_pubkey)) ---
     let input_index = 0;
@@ -72,7 +90,7 @@ async fn test_fund_and_spend_cltv_timelock() {
             witness: Witness::default(),
         }],
         output: vec![TxOut {
-            value: Amount::from_btc(amount - 0.001).unwrap().to_sat(),
+            value: deduct_fee(amount, FEE).unwrap().to_sat(),
             script_pubkey: Address::from_str(&destination_address).unwrap().assume_checked().script_pubkey(),
         }],
     };
@@ -80,17 +98,12 @@ async fn test_fund_and_spend_cltv_timelock() {
     let sighash = cache.segwit_signature_hash(
         input_index,
         &redeem_script,
-        Amount::from_btc(amount).unwrap().to_sat(),
+        amount.to_sat(),
         EcdsaSighashType::All,
     ).unwrap();
     let msg = Message::from_slice(&sighash[..]).unwrap();
     let sig_backup = secp.sign_ecdsa(&msg, &backup_privkey.inner);
-    let mut witness = Witness::new();
-    let mut sig_backup_der = sig_backup.serialize_der().to_vec();
-    sig_backup_der.push(EcdsaSighashType::All as u8);
-    witness.push(sig_backup_der);
-    witness.push(redeem_script.as_bytes().to_vec());
-    tx.input[input_index].witness = witness;
+    tx.input[input_index].witness = build_backup_path_witness(&descriptor, &bitcoin::ecdsa::Signature::sighash_all(sig_backup)).unwrap();
     println!("Single-sig path: raw tx hex = {}", hex::encode(serialize(&tx)));
     let res = rpc.send_raw_transaction(&hex::encode(serialize(&tx))).await;
     if let Err(e) = res {
@@ -99,9 +112,9 @@ async fn test_fund_and_spend_cltv_timelock() {
     let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
 
     // --- Fund a new UTXO for Path 2 ---
-    let send_amount2 = 0.1;
+    let send_amount2 = Amount::from_sat(10_000_000);
     let txid2 = rpc.send_to_address(&address.to_string(), send_amount2).await.unwrap();
-    println!("Sent {} BTC to CLTV timelock address (for 2-of-3+timelock path): {}", send_amount2, txid2);
+    println!("Sent {} to CLTV timelock address (for 2-of-3+timelock path): {}", send_amount2, txid2);
     // Mine enough blocks to pass the timelock
     let _ = rpc.generate_to_address(cltv_height + 1, &funding_address).await.unwrap();
     let raw_tx_details2 = rpc.call_rpc("getrawtransaction", json!([txid2, true])).await.unwrap();
@@ -112,7 +125,7 @@ async fn test_fund_and_spend_cltv_timelock() {
         })
         .expect("CLTV output not found in transaction");
     let output2 = &raw_tx_details2["vout"].as_array().unwrap()[vout2];
-    let amount2 = output2["value"].as_f64().unwrap();
+    let amount2 = from_rpc(&output2["value"]).unwrap();
 
     // --- Path 2: 2-of-3 Multisig + Timelock ---
     let mut tx2 = Transaction {
@@ -125,7 +138,7 @@ async fn test_fund_and_spend_cltv_timelock() {
             witness: Witness::default(),
         }],
         output: vec![TxOut {
-            value: Amount::from_btc(amount2 - 0.001).unwrap().to_sat(),
+            value: deduct_fee(amount2, FEE).unwrap().to_sat(),
             script_pubkey: Address::from_str(&destination_address).unwrap().assume_checked().script_pubkey(),
         }],
     };
@@ -133,27 +146,73 @@ async fn test_fund_and_spend_cltv_timelock() {
     let sighash2 = cache2.segwit_signature_hash(
         input_index,
         &redeem_script,
-        Amount::from_btc(amount2).unwrap().to_sat(),
+        amount2.to_sat(),
         EcdsaSighashType::All,
     ).unwrap();
     let msg2 = Message::from_slice(&sighash2[..]).unwrap();
     let sig_b = secp.sign_ecdsa(&msg2, &privkey2.inner);
     let sig_c = secp.sign_ecdsa(&msg2, &privkey3.inner);
-    let mut witness2 = Witness::new();
-    witness2.push(vec![]); // Dummy for OP_CHECKMULTISIG
-    let mut sig_b_der = sig_b.serialize_der().to_vec();
-    sig_b_der.push(EcdsaSighashType::All as u8);
-    let mut sig_c_der = sig_c.serialize_der().to_vec();
-    sig_c_der.push(EcdsaSighashType::All as u8);
-    witness2.push(sig_b_der);
-    witness2.push(sig_c_der);
-    witness2.push(vec![]); // Missing third signature
-    witness2.push(redeem_script.as_bytes().to_vec());
-    tx2.input[input_index].witness = witness2;
+    let sigs = HashMap::from([
+        (pubkey2, bitcoin::ecdsa::Signature::sighash_all(sig_b)),
+        (pubkey3, bitcoin::ecdsa::Signature::sighash_all(sig_c)),
+    ]);
+    tx2.input[input_index].witness = build_multisig_timelock_witness(&descriptor, &sigs).unwrap();
     println!("2-of-3+timelock path: raw tx hex = {}", hex::encode(serialize(&tx2)));
     let res2 = rpc.send_raw_transaction(&hex::encode(serialize(&tx2))).await;
     if let Err(e) = res2 {
         panic!("2-of-3+timelock path failed: {:?}", e);
     }
     let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
-} 
\ No newline at end of file
+} 
+fn nested_vault_spend(funded: &FundedUtxo, destination: &Address, lock_time: LockTime) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time,
+        input: vec![TxIn {
+            previous_output: funded.outpoint(),
+            script_sig: build_vault_script_sig(&funded.descriptor).unwrap(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut { value: deduct_fee(funded.amount(), FEE).unwrap().to_sat(), script_pubkey: destination.script_pubkey() }],
+    }
+}
+
+/// Keystore entry for `descriptor` holding `keys[i]` for each `i` in `indices`
+fn held(descriptor: &miniscript::Descriptor<PublicKey>, keys: &[PrivateKey], indices: &[usize]) -> KeyEntry {
+    KeyEntry::new(descriptor.clone(), Network::Regtest, indices.iter().map(|i| SigningKey::new(keys[*i].inner, Network::Regtest)).collect()).unwrap()
+}
+
+#[tokio::test]
+async fn test_spend_nested_cltv_vault_both_paths() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("nested_wallet").await;
+    let _ = rpc.load_wallet("nested_wallet").await;
+    let rpc = rpc.with_wallet("nested_wallet");
+    let tip = mine(&rpc, 101).await.unwrap() as u32;
+    let secp = Secp256k1::new();
+    let keys: Vec<PrivateKey> = (5u8..=8).map(|b| PrivateKey::new(secp256k1::SecretKey::from_slice(&[b; 32]).unwrap(), Network::Regtest)).collect();
+    let k: Vec<PublicKey> = keys.iter().map(|key| key.public_key(&secp)).collect();
+    let lock_height = tip + 5;
+    let descriptor = nested_vault_descriptor(k[3], &k[..3], 2, VaultTimelock::Absolute(lock_height)).unwrap();
+    let destination = Address::from_str(&rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap()).unwrap().assume_checked();
+
+    // Backup key before the lock height
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(1_000_000)).await.unwrap();
+    let mut tx = nested_vault_spend(&funded, &destination, LockTime::ZERO);
+    satisfy_input(&held(&descriptor, &keys, &[3]), &mut tx, 0, funded.amount(), &[]).unwrap();
+    verify_spend(&tx, std::slice::from_ref(&funded.utxo.txout)).unwrap();
+    rpc.send_raw_transaction(&hex::encode(serialize(&tx))).await.unwrap();
+    mine(&rpc, 1).await.unwrap();
+
+    // 2-of-3 from the lock height on
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(1_000_000)).await.unwrap();
+    let mut tx = nested_vault_spend(&funded, &destination, LockTime::from_height(lock_height).unwrap());
+    satisfy_input(&held(&descriptor, &keys, &[1, 2]), &mut tx, 0, funded.amount(), &[]).unwrap();
+    verify_spend(&tx, std::slice::from_ref(&funded.utxo.txout)).unwrap();
+    let early = rpc.test_and_send(&tx).await.unwrap_err();
+    assert_eq!(CoreError::of(&*early), Some(CoreError::NonFinal), "{}", early);
+    mine(&rpc, 5).await.unwrap();
+    rpc.test_and_send(&tx).await.unwrap();
+    mine(&rpc, 1).await.unwrap();
+}
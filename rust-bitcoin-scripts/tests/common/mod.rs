@@ -0,0 +1,14 @@
+//! Fixtures shared by the integration tests
+#![allow(dead_code)]
+
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{Network, PrivateKey, PublicKey};
+
+/// Regtest key whose secret is `byte` repeated
+pub fn key(byte: u8) -> PrivateKey {
+    PrivateKey::new(SecretKey::from_slice(&[byte; 32]).unwrap(), Network::Regtest)
+}
+
+pub fn pk(byte: u8) -> PublicKey {
+    key(byte).public_key(&Secp256k1::new())
+}
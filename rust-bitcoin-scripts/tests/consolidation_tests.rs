@@ -0,0 +1,112 @@
+use bitcoin_scripts::actors::{BroadcasterHandle, CoordinatorHandle};
+use bitcoin_scripts::consolidation::{ConsolidationPolicy, ConsolidationStep, Consolidator, Decision, CONSOLIDATION_OWNER};
+use bitcoin_scripts::flows::{fund_descriptor, mine};
+use bitcoin_scripts::psbt;
+use bitcoin_scripts::reservation::UtxoReservations;
+use bitcoin_scripts::rpc_types::Utxo;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{Amount, FeeRate, Network, OutPoint, PrivateKey, PublicKey, Txid};
+use miniscript::Descriptor;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+fn keys(fresh: bool) -> Vec<PrivateKey> {
+    (1u8..=3).map(|b| {
+        let secret = if fresh { rand::random::<[u8; 32]>() } else { [b; 32] };
+        PrivateKey::new(SecretKey::from_slice(&secret).unwrap(), Network::Regtest)
+    }).collect()
+}
+
+fn vault(keys: &[PrivateKey]) -> Descriptor<PublicKey> {
+    let secp = Secp256k1::new();
+    let pks: Vec<String> = keys.iter().map(|k| k.public_key(&secp).to_string()).collect();
+    Descriptor::from_str(&format!("wsh(multi(2,{}))", pks.join(","))).unwrap()
+}
+
+fn utxo(descriptor: &Descriptor<PublicKey>, vout: u32, sats: u64, confirmations: u32) -> Utxo {
+    Utxo { outpoint: OutPoint::new(Txid::from_byte_array([4; 32]), vout), amount: Amount::from_sat(sats), script_pubkey: descriptor.script_pubkey(), confirmations }
+}
+
+fn sat_vb(rate: u64) -> FeeRate {
+    FeeRate::from_sat_per_vb_unchecked(rate)
+}
+
+#[test]
+fn test_policy_consolidates_the_smallest_worthwhile_outputs() {
+    let descriptor = vault(&keys(false));
+    let policy = ConsolidationPolicy { min_utxos: 3, max_fee_rate: sat_vb(5), max_inputs: 3, min_confirmations: 6, conf_target: 144 };
+    let utxos = vec![
+        utxo(&descriptor, 0, 500_000, 10),
+        utxo(&descriptor, 1, 20_000, 10),
+        utxo(&descriptor, 2, 30_000, 10),
+        utxo(&descriptor, 3, 40_000, 10),
+        // Costs more to spend than it is worth at 5 sat/vB
+        utxo(&descriptor, 4, 300, 10),
+        // Too young
+        utxo(&descriptor, 5, 10_000, 2),
+    ];
+
+    let Decision::Consolidate(plan) = policy.plan(&descriptor, &utxos, sat_vb(5), LockTime::ZERO).unwrap() else { panic!("should consolidate") };
+    assert_eq!(plan.inputs.iter().map(|o| o.vout).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(plan.input_value, Amount::from_sat(90_000));
+    let outputs = &plan.psbt.unsigned_tx.output;
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0].script_pubkey, descriptor.script_pubkey());
+    assert_eq!(Amount::from_sat(outputs[0].value) + plan.fee, plan.input_value);
+
+    assert!(matches!(policy.plan(&descriptor, &utxos, sat_vb(6), LockTime::ZERO).unwrap(), Decision::Wait(reason) if reason.contains("ceiling")));
+    assert!(matches!(policy.plan(&descriptor, &utxos[..2], sat_vb(5), LockTime::ZERO).unwrap(), Decision::Wait(reason) if reason.contains("2 spendable")));
+    // Outputs of other scripts are not the vault's to consolidate
+    let other = vault(&keys(true));
+    assert!(matches!(policy.plan(&other, &utxos, sat_vb(5), LockTime::ZERO).unwrap(), Decision::Wait(_)));
+    assert!(ConsolidationPolicy { max_inputs: 1, ..policy }.plan(&descriptor, &utxos, sat_vb(5), LockTime::ZERO).is_err());
+}
+
+#[tokio::test]
+async fn test_background_job_consolidates_through_a_signing_round() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("consolidation_wallet").await;
+    let _ = rpc.load_wallet("consolidation_wallet").await;
+    let rpc = rpc.with_wallet("consolidation_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let keys = keys(true);
+    let descriptor = vault(&keys);
+    for sats in [100_000, 200_000, 300_000] {
+        fund_descriptor(&rpc, &descriptor, Amount::from_sat(sats)).await.unwrap();
+    }
+
+    let reservations = UtxoReservations::new(Duration::from_secs(600));
+    let (events, _lifecycle) = mpsc::unbounded_channel();
+    let coordinator = CoordinatorHandle::spawn(reservations.clone(), events, Duration::from_secs(3600));
+    let broadcaster = BroadcasterHandle::spawn(rpc.clone(), Duration::from_secs(3600));
+    let policy = ConsolidationPolicy { min_utxos: 3, min_confirmations: 1, ..ConsolidationPolicy::default() };
+    let signers = vec!["alice".to_string(), "bob".to_string(), "carol".to_string()];
+    let mut job = Consolidator::new(policy, descriptor.clone(), signers, 2, Duration::from_secs(600));
+
+    let ConsolidationStep::Opened { round_id, psbt: unsigned, fee } = job.tick(&rpc, &reservations, &coordinator, &broadcaster).await.unwrap() else { panic!("should open a round") };
+    assert_eq!(unsigned.unsigned_tx.input.len(), 3);
+    assert!(unsigned.unsigned_tx.input.iter().all(|i| reservations.reserved_by(&i.previous_output).as_deref() == Some(CONSOLIDATION_OWNER)));
+    assert_eq!(job.tick(&rpc, &reservations, &coordinator, &broadcaster).await.unwrap(), ConsolidationStep::Signing { round_id: round_id.clone() });
+
+    // Two federation members sign their copies and submit them
+    for (signer, key) in [("alice", keys[0]), ("carol", keys[2])] {
+        let mut partial = unsigned.clone();
+        psbt::sign(&mut partial, &[key]).unwrap();
+        coordinator.submit(&round_id, signer, partial).await.unwrap();
+    }
+    let ConsolidationStep::Broadcast { txid, .. } = job.tick(&rpc, &reservations, &coordinator, &broadcaster).await.unwrap() else { panic!("should finalize") };
+    assert_eq!(broadcaster.poll().await.unwrap(), vec![txid]);
+    assert_eq!(job.tick(&rpc, &reservations, &coordinator, &broadcaster).await.unwrap(), ConsolidationStep::Confirming { txid });
+
+    mine(&rpc, 1).await.unwrap();
+    assert!(matches!(job.tick(&rpc, &reservations, &coordinator, &broadcaster).await.unwrap(), ConsolidationStep::Idle { .. }));
+    let utxos = rpc.find_utxos_for_descriptor(&descriptor).await.unwrap();
+    assert_eq!(utxos.len(), 1);
+    assert_eq!(utxos[0].outpoint.txid, txid);
+    assert_eq!(utxos[0].amount + fee, Amount::from_sat(600_000));
+}
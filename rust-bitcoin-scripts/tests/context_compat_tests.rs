@@ -0,0 +1,115 @@
+//! Every policy template the crate builds, parsed as a sane miniscript in each script context
+//! (bare/P2SH, P2WSH, tapscript), with the contexts it must be rejected from pinned down and a
+//! full satisfaction produced wherever it is accepted.
+
+use bitcoin::absolute::LockTime;
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use bitcoin::{ecdsa, taproot, PublicKey, Sequence};
+use miniscript::{Legacy, Miniscript, ScriptContext, Segwitv0, SigType, Tap};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+mod common;
+use common::key;
+
+const KEYS: [u8; 5] = [21, 22, 23, 24, 25];
+
+/// Fill `A`..`E` in a template with the test keys
+fn fill(template: &str) -> String {
+    let secp = Secp256k1::new();
+    KEYS.iter().zip(['A', 'B', 'C', 'D', 'E']).fold(template.to_string(), |t, (b, name)| t.replace(name, &key(*b).public_key(&secp).to_string()))
+}
+
+struct Template {
+    name: &'static str,
+    miniscript: &'static str,
+    legacy: bool,
+    segwit: bool,
+    tap: bool,
+}
+
+/// The miniscript inside each descriptor the crate constructs
+const TEMPLATES: &[Template] = &[
+    // classic_multisig: sh(multi(2,...))
+    Template { name: "classic 2-of-3", miniscript: "multi(2,A,B,C)", legacy: true, segwit: true, tap: false },
+    // timelock_cltv::run: backup key or 2-of-3 after an absolute height
+    Template { name: "cltv vault", miniscript: "or_d(pk(D),and_v(v:multi(2,A,B,C),after(500)))", legacy: true, segwit: true, tap: false },
+    // timelock_csv::run: backup key or 2-of-3 after a relative delay
+    Template { name: "csv vault", miniscript: "or_d(pk(D),and_v(v:multi(2,A,B,C),older(10)))", legacy: true, segwit: true, tap: false },
+    // timelock_cltv::simple_cltv_descriptor
+    Template { name: "simple cltv", miniscript: "and_v(v:pk(A),after(100))", legacy: true, segwit: true, tap: true },
+    // timelock_csv::simple_csv_descriptor
+    Template { name: "simple csv", miniscript: "and_v(v:pk(A),older(10))", legacy: true, segwit: true, tap: true },
+    // templates::build federation leaf: multi_a only exists in tapscript
+    Template { name: "federation leaf", miniscript: "and_v(v:multi_a(2,A,B,C),older(10))", legacy: false, segwit: false, tap: true },
+    // templates::build v2 recovery leaf
+    Template { name: "recovery leaf", miniscript: "and_v(v:pk(E),older(1000))", legacy: true, segwit: true, tap: true },
+    // taproot::TaprootVault recovery leaf, written as miniscript
+    Template { name: "taproot vault recovery", miniscript: "and_v(v:pk(E),after(1000))", legacy: true, segwit: true, tap: true },
+];
+
+/// Satisfy `ms` with a signature from every test key and both timelocks met
+fn satisfy_fully<Ctx: ScriptContext>(ms: &Miniscript<PublicKey, Ctx>) -> Result<Vec<Vec<u8>>, miniscript::Error> {
+    let secp = Secp256k1::new();
+    let message = Message::from_slice(&[1; 32]).unwrap();
+    let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+    let mut ecdsa_sigs = HashMap::new();
+    let mut schnorr_sigs = HashMap::new();
+    for byte in KEYS {
+        let private = key(byte);
+        let public = private.public_key(&secp);
+        match Ctx::sig_type() {
+            SigType::Ecdsa => {
+                ecdsa_sigs.insert(public, ecdsa::Signature::sighash_all(secp.sign_ecdsa(&message, &private.inner)));
+            }
+            SigType::Schnorr => {
+                let keypair = bitcoin::secp256k1::KeyPair::from_secret_key(&secp, &private.inner);
+                let sig = taproot::Signature { sig: secp.sign_schnorr_no_aux_rand(&message, &keypair), hash_ty: bitcoin::sighash::TapSighashType::Default };
+                schnorr_sigs.insert((public, leaf_hash), sig);
+            }
+        }
+    }
+    ms.satisfy((ecdsa_sigs, schnorr_sigs, Sequence::from_height(1000), LockTime::from_height(1000).unwrap()))
+}
+
+fn check_context<Ctx: ScriptContext>(template: &Template, expected: bool, context: &str) {
+    let parsed = Miniscript::<PublicKey, Ctx>::from_str(&fill(template.miniscript));
+    assert_eq!(parsed.is_ok(), expected, "{} in {}: {:?}", template.name, context, parsed.err());
+    if let Ok(ms) = parsed {
+        let witness = satisfy_fully(&ms);
+        assert!(witness.is_ok(), "{} not satisfiable in {}: {:?}", template.name, context, witness.err());
+        assert!(ms.satisfy((HashMap::<PublicKey, ecdsa::Signature>::new(), Sequence::MAX, LockTime::ZERO)).is_err(),
+            "{} satisfied in {} without signatures", template.name, context);
+    }
+}
+
+#[test]
+fn test_templates_per_context() {
+    for template in TEMPLATES {
+        check_context::<Legacy>(template, template.legacy, "legacy");
+        check_context::<Segwitv0>(template, template.segwit, "segwitv0");
+        check_context::<Tap>(template, template.tap, "tap");
+    }
+}
+
+#[test]
+fn test_wsh_only_templates_are_reported() {
+    let wsh_without_tap: Vec<&str> = TEMPLATES.iter().filter(|t| t.segwit && !t.tap).map(|t| t.name).collect();
+    assert_eq!(wsh_without_tap, vec!["classic 2-of-3", "cltv vault", "csv vault"]);
+}
+
+#[test]
+fn test_relative_timelock_gates_satisfaction_in_ecdsa_contexts() {
+    let template = fill("and_v(v:pk(E),older(1000))");
+    let secp = Secp256k1::new();
+    let e = key(25);
+    let sig = ecdsa::Signature::sighash_all(secp.sign_ecdsa(&Message::from_slice(&[1; 32]).unwrap(), &e.inner));
+    let sigs: HashMap<PublicKey, ecdsa::Signature> = [(e.public_key(&secp), sig)].into_iter().collect();
+    let ms = Miniscript::<PublicKey, Segwitv0>::from_str(&template).unwrap();
+    assert!(ms.satisfy((sigs.clone(), Sequence::from_height(999))).is_err());
+    assert!(ms.satisfy((sigs.clone(), Sequence::from_height(1000))).is_ok());
+    let ms = Miniscript::<PublicKey, Legacy>::from_str(&template).unwrap();
+    assert!(ms.satisfy((sigs.clone(), Sequence::from_height(999))).is_err());
+    assert!(ms.satisfy((sigs, Sequence::from_height(1000))).is_ok());
+}
@@ -1,6 +1,14 @@
-use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::amount::{deduct_fee, from_rpc, to_rpc};
+use bitcoin_scripts::test_setup::{BitcoinRPC, CoreError, MempoolRejection, DESTINATION_LABEL, FUNDING_LABEL};
+use bitcoin_scripts::flows::{fund_descriptor, mine, FundedUtxo};
+use bitcoin_scripts::timelock_csv::{csv_vault_descriptor, nested_csv_vault_descriptor, simple_csv_descriptor};
+use bitcoin_scripts::keystore::KeyEntry;
+use bitcoin_scripts::satisfier::satisfy_input;
+use bitcoin_scripts::secret::SigningKey;
+use bitcoin_scripts::verify::verify_spend;
+use bitcoin_scripts::witness::{build_backup_path_witness, build_multisig_timelock_witness, build_vault_script_sig};
+use bitcoin_scripts::report::AmountReport;
 use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
-use miniscript::Descriptor;
 use serde_json::json;
 use std::collections::HashMap;
 use std::str::FromStr;
@@ -10,6 +18,31 @@ use bitcoin::secp256k1::{Secp256k1, Message};
 use bitcoin::consensus::encode::serialize;
 use bitcoin::sighash::{SighashCache, EcdsaSighashType};
 
+/// Flat fee for the hand-built spends
+const FEE: Amount = Amount::from_sat(100_000);
+
+#[test]
+fn test_csv_descriptor_helpers() {
+    let (descriptor, _, pubkey, address) = simple_csv_descriptor(144).unwrap();
+    assert_eq!(descriptor.to_string().split('#').next().unwrap(), format!("wsh(and_v(v:pk({}),older(144)))", pubkey));
+    assert_eq!(address, descriptor.address(Network::Regtest).unwrap().to_string());
+    assert!(simple_csv_descriptor(0).is_err());
+
+    let secp = Secp256k1::new();
+    let k: Vec<PublicKey> = (1u8..=4).map(|b| PublicKey::from_private_key(&secp, &PrivateKey::new(secp256k1::SecretKey::from_slice(&[b; 32]).unwrap(), Network::Regtest))).collect();
+    let vault = csv_vault_descriptor(k[3], &k[..3], 2, 10).unwrap();
+    assert_eq!(vault.to_string().split('#').next().unwrap(), format!("wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))", k[3], k[0], k[1], k[2]));
+    assert!(csv_vault_descriptor(k[3], &k[..3], 0, 10).is_err());
+    assert!(csv_vault_descriptor(k[3], &k[..3], 4, 10).is_err());
+    assert!(csv_vault_descriptor(k[3], &k[..3], 2, 0).is_err());
+
+    let nested = nested_csv_vault_descriptor(k[3], &k[..3], 2, 10).unwrap();
+    assert_eq!(nested.to_string().split('#').next().unwrap(), format!("sh({})", vault.to_string().split('#').next().unwrap()));
+    assert_eq!(nested.explicit_script().unwrap(), vault.explicit_script().unwrap());
+    assert!(nested.address(Network::Regtest).unwrap().to_string().starts_with('2'));
+    assert!(nested_csv_vault_descriptor(k[3], &k[..3], 4, 10).is_err());
+}
+
 #[tokio::test]
 async fn test_fund_and_spend_csv_timelock() {
     let rpc = BitcoinRPC::new();
@@ -17,8 +50,8 @@ async fn test_fund_and_spend_csv_timelock() {
     let _ = rpc.create_wallet("testwallet").await; //
     // Try to load the wallet
     let _ = rpc.load_wallet("testwallet").await;
-    // Generate some keys in the wallet to ensure it has addresses
-    let _ = rpc.generate_keys(5).await.unwrap();
+    // Make sure the wallet has keys to hand out
+    rpc.ensure_keypool(5).await.unwrap();
    
     // Create CSV timelock descriptor (10 block relative timelock)
     let secp = secp256k1::Secp256k1::new();
@@ -37,20 +70,15 @@ async fn test_fund_and_spend_csv_timelock() {
     let pubkey3 = PublicKey::from_private_key(&secp, &privkey3);
     let backup_pubkey = PublicKey::from_private_key(&secp, &backup_privkey);
     
-    let csv_descriptor_str = format!(
-        "wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))",
-        backup_pubkey, pubkey1, pubkey2, pubkey3
-    );
-    
-    println!("CSV Timelock Descriptor: {}", csv_descriptor_str);
+    let descriptor = csv_vault_descriptor(backup_pubkey, &[pubkey1, pubkey2, pubkey3], 2, 10).unwrap();
+    println!("CSV Timelock Descriptor: {}", descriptor);
     
-    // Parse descriptor and get address
-    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&csv_descriptor_str).unwrap();
+    // Get address
     let address = descriptor.address(Network::Regtest).unwrap();
     println!("CSV Timelock Address: {}", address);
     
     // Get a funding address
-    let funding_address = rpc.get_new_address().await.unwrap();
+    let funding_address = rpc.new_labeled_address(FUNDING_LABEL).await.unwrap();
     println!("Funding address: {}", funding_address);
     
     // Generate some coins to the funding address
@@ -58,9 +86,9 @@ async fn test_fund_and_spend_csv_timelock() {
     println!("Generated {} blocks", block_hashes.len());
     
     // Send funds to the timelock address
-    let send_amount = 0.1;
+    let send_amount = Amount::from_sat(10_000_000);
     let txid = rpc.send_to_address(&address.to_string(), send_amount).await.unwrap();
-    println!("Sent {} BTC to CSV timelock address: {}", send_amount, txid);
+    println!("Sent {} to CSV timelock address: {}", send_amount, txid);
     
     // Generate a few more blocks to confirm the transaction
     let _ = rpc.generate_to_address(6, &funding_address).await.unwrap();
@@ -75,8 +103,8 @@ async fn test_fund_and_spend_csv_timelock() {
         .expect("Timelock output not found in transaction");
     
     let output = &raw_tx_details["vout"].as_array().unwrap()[vout];
-    let amount = output["value"].as_f64().unwrap();
-    println!("CSV timelock UTXO found: {} BTC at vout {}", amount, vout);
+    let amount = from_rpc(&output["value"]).unwrap();
+    println!("CSV timelock UTXO found: {} at vout {}", AmountReport::sats(amount), vout);
     
     // Get the witness script from the descriptor
     let witness_script = match &descriptor {
@@ -87,17 +115,17 @@ async fn test_fund_and_spend_csv_timelock() {
     println!("CSV timelock witness script: {}", witness_script_hex);
     
     // Test spending with backup key
-    let destination_address = rpc.get_new_address().await.unwrap();
+    let destination_address = rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap();
     let inputs = vec![json!({
         "txid": txid,
         "vout": vout
     })];
     
     let mut outputs = HashMap::new();
-    let output_amount = amount - 0.001;
+    let output_amount = deduct_fee(amount, FEE).unwrap();
     outputs.insert(destination_address.clone(), output_amount);
     
-    println!("CSV timelock spend: creating transaction with output amount = {} BTC", output_amount);
+    println!("CSV timelock spend: creating transaction with output amount = {}", AmountReport::sats(output_amount));
     
     let raw_tx = rpc.create_raw_transaction(inputs, outputs).await.unwrap();
     println!("CSV timelock spend: raw transaction hex = {}", raw_tx);
@@ -110,7 +138,7 @@ async fn test_fund_and_spend_csv_timelock() {
         "vout": vout,
         "scriptPubKey": output["scriptPubKey"]["hex"].as_str().unwrap(),
         "witnessScript": witness_script_hex,
-        "amount": amount
+        "amount": to_rpc(amount)
     })];
     
     let params = json!([
@@ -151,7 +179,7 @@ async fn test_fund_and_spend_csv_timelock() {
             witness: Witness::default(),
         }],
         output: vec![TxOut {
-            value: Amount::from_btc(amount - 0.001).unwrap().to_sat(),
+            value: deduct_fee(amount, FEE).unwrap().to_sat(),
             script_pubkey: Address::from_str(&destination_address).unwrap().assume_checked().script_pubkey(),
         }],
     };
@@ -159,17 +187,12 @@ async fn test_fund_and_spend_csv_timelock() {
     let sighash = cache.segwit_signature_hash(
         input_index,
         &redeem_script,
-        Amount::from_btc(amount).unwrap().to_sat(),
+        amount.to_sat(),
         EcdsaSighashType::All,
     ).unwrap();
     let msg = Message::from_slice(&sighash[..]).unwrap();
     let sig_a = secp.sign_ecdsa(&msg, &backup_privkey.inner);
-    let mut witness = Witness::new();
-    let mut sig_a_der = sig_a.serialize_der().to_vec();
-    sig_a_der.push(EcdsaSighashType::All as u8);
-    witness.push(sig_a_der);
-    witness.push(redeem_script.as_bytes().to_vec());
-    tx.input[input_index].witness = witness;
+    tx.input[input_index].witness = build_backup_path_witness(&descriptor, &bitcoin::ecdsa::Signature::sighash_all(sig_a)).unwrap();
     // Debug print: show the witness stack for the single-sig path
     println!("=== DEBUG: single-sig path witness stack ===");
     for (i, elem) in tx.input[input_index].witness.iter().enumerate() {
@@ -185,9 +208,9 @@ async fn test_fund_and_spend_csv_timelock() {
 
     // --- Fund a new UTXO for Path 2 ---
     // Send funds to the timelock address again
-    let send_amount2 = 0.1;
+    let send_amount2 = Amount::from_sat(10_000_000);
     let txid2 = rpc.send_to_address(&address.to_string(), send_amount2).await.unwrap();
-    println!("Sent {} BTC to CSV timelock address (for 2-of-3+timelock path): {}", send_amount2, txid2);
+    println!("Sent {} to CSV timelock address (for 2-of-3+timelock path): {}", send_amount2, txid2);
     // Generate a few more blocks to confirm the transaction
     let _ = rpc.generate_to_address(6, &funding_address).await.unwrap();
     // Generate enough blocks to satisfy the CSV timelock (older(10))
@@ -201,7 +224,7 @@ async fn test_fund_and_spend_csv_timelock() {
         })
         .expect("Timelock output not found in transaction");
     let output2 = &raw_tx_details2["vout"].as_array().unwrap()[vout2];
-    let amount2 = output2["value"].as_f64().unwrap();
+    let amount2 = from_rpc(&output2["value"]).unwrap();
 
     println!("=== DEBUG: About to construct 2-of-3+timelock transaction ===");
 
@@ -216,7 +239,7 @@ async fn test_fund_and_spend_csv_timelock() {
             witness: Witness::default(),
         }],
         output: vec![TxOut {
-            value: Amount::from_btc(amount2 - 0.001).unwrap().to_sat(),
+            value: deduct_fee(amount2, FEE).unwrap().to_sat(),
             script_pubkey: Address::from_str(&destination_address).unwrap().assume_checked().script_pubkey(),
         }],
     };
@@ -224,24 +247,17 @@ async fn test_fund_and_spend_csv_timelock() {
     let sighash2 = cache2.segwit_signature_hash(
         input_index,
         &redeem_script,
-        Amount::from_btc(amount2).unwrap().to_sat(),
+        amount2.to_sat(),
         EcdsaSighashType::All,
     ).unwrap();
     let msg2 = Message::from_slice(&sighash2[..]).unwrap();
     let sig_b = secp.sign_ecdsa(&msg2, &privkey2.inner);
     let sig_c = secp.sign_ecdsa(&msg2, &privkey3.inner);
-    let mut witness2 = Witness::new();
-    // Only the multisig dummy is needed for CHECKMULTISIG
-    witness2.push(vec![]); // Dummy for OP_CHECKMULTISIG
-    let mut sig_b_der = sig_b.serialize_der().to_vec();
-    sig_b_der.push(EcdsaSighashType::All as u8);
-    let mut sig_c_der = sig_c.serialize_der().to_vec();
-    sig_c_der.push(EcdsaSighashType::All as u8);
-    witness2.push(sig_b_der);
-    witness2.push(sig_c_der);
-    witness2.push(vec![]); // Missing third signature
-    witness2.push(redeem_script.as_bytes().to_vec());
-    tx2.input[input_index].witness = witness2;
+    let sigs = HashMap::from([
+        (pubkey2, bitcoin::ecdsa::Signature::sighash_all(sig_b)),
+        (pubkey3, bitcoin::ecdsa::Signature::sighash_all(sig_c)),
+    ]);
+    tx2.input[input_index].witness = build_multisig_timelock_witness(&descriptor, &sigs).unwrap();
     // Debug print: show the witness stack for the 2-of-3+timelock path
     println!("=== DEBUG: 2-of-3+timelock path witness stack ===");
     for (i, elem) in tx2.input[input_index].witness.iter().enumerate() {
@@ -288,19 +304,72 @@ async fn test_csv_timelock_descriptor() {
     let pubkey3 = PublicKey::from_private_key(&secp, &privkey3);
     let backup_pubkey = PublicKey::from_private_key(&secp, &backup_privkey);
     
-    let csv_descriptor_str = format!(
-        "wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))",
-        backup_pubkey, pubkey1, pubkey2, pubkey3
-    );
-    
-    println!("CSV Timelock Descriptor: {}", csv_descriptor_str);
+    let descriptor = csv_vault_descriptor(backup_pubkey, &[pubkey1, pubkey2, pubkey3], 2, 10).unwrap();
+    println!("CSV Timelock Descriptor: {}", descriptor);
     
-    // Parse descriptor and get address
-    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&csv_descriptor_str).unwrap();
+    // Get address
     let address = descriptor.address(Network::Regtest).unwrap();
     println!("CSV Timelock Address: {}", address);
     
     // Verify the descriptor can be parsed and address generated
     assert!(!address.to_string().is_empty(), "Address should not be empty");
     println!("CSV timelock descriptor test completed successfully!");
-} 
\ No newline at end of file
+} 
+/// Unsigned one-input spend of `funded` to `destination`, with the vault's scriptSig
+fn nested_vault_spend(funded: &FundedUtxo, destination: &Address, sequence: Sequence) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: funded.outpoint(),
+            script_sig: build_vault_script_sig(&funded.descriptor).unwrap(),
+            sequence,
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut { value: deduct_fee(funded.amount(), FEE).unwrap().to_sat(), script_pubkey: destination.script_pubkey() }],
+    }
+}
+
+/// Keystore entry for `descriptor` holding `keys[i]` for each `i` in `indices`
+fn held(descriptor: &miniscript::Descriptor<PublicKey>, keys: &[PrivateKey], indices: &[usize]) -> KeyEntry {
+    KeyEntry::new(descriptor.clone(), Network::Regtest, indices.iter().map(|i| SigningKey::new(keys[*i].inner, Network::Regtest)).collect()).unwrap()
+}
+
+#[tokio::test]
+async fn test_spend_nested_csv_vault_both_paths() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("nested_wallet").await;
+    let _ = rpc.load_wallet("nested_wallet").await;
+    let rpc = rpc.with_wallet("nested_wallet");
+    mine(&rpc, 101).await.unwrap();
+    let secp = Secp256k1::new();
+    let keys: Vec<PrivateKey> = (5u8..=8).map(|b| PrivateKey::new(secp256k1::SecretKey::from_slice(&[b; 32]).unwrap(), Network::Regtest)).collect();
+    let k: Vec<PublicKey> = keys.iter().map(|key| key.public_key(&secp)).collect();
+    let descriptor = nested_csv_vault_descriptor(k[3], &k[..3], 2, 10).unwrap();
+    let destination = Address::from_str(&rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap()).unwrap().assume_checked();
+
+    // Backup key, no delay
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(1_000_000)).await.unwrap();
+    assert!(funded.address.starts_with('2'));
+    let mut tx = nested_vault_spend(&funded, &destination, Sequence::ENABLE_RBF_NO_LOCKTIME);
+    satisfy_input(&held(&descriptor, &keys, &[3]), &mut tx, 0, funded.amount(), &[]).unwrap();
+    verify_spend(&tx, std::slice::from_ref(&funded.utxo.txout)).unwrap();
+    rpc.send_raw_transaction(&hex::encode(serialize(&tx))).await.unwrap();
+    mine(&rpc, 1).await.unwrap();
+
+    // 2-of-3 once the output is 10 blocks old
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(1_000_000)).await.unwrap();
+    let mut tx = nested_vault_spend(&funded, &destination, Sequence(10));
+    satisfy_input(&held(&descriptor, &keys, &[0, 2]), &mut tx, 0, funded.amount(), &[]).unwrap();
+    verify_spend(&tx, std::slice::from_ref(&funded.utxo.txout)).unwrap();
+    let early = rpc.test_and_send(&tx).await.unwrap_err();
+    assert_eq!(CoreError::of(&*early), Some(CoreError::NonFinal), "{}", early);
+    mine(&rpc, 9).await.unwrap();
+    // Without the scriptSig the P2SH hash check fails
+    let mut bare = tx.clone();
+    bare.input[0].script_sig = ScriptBuf::new();
+    let err = rpc.test_and_send(&bare).await.unwrap_err();
+    assert!(err.downcast_ref::<MempoolRejection>().is_some_and(|r| r.kind.is_none()), "{}", err);
+    rpc.test_and_send(&tx).await.unwrap();
+    mine(&rpc, 1).await.unwrap();
+}
@@ -0,0 +1,51 @@
+use bitcoin_scripts::demo::{self, demo_descriptor, DemoPath, DEMO_CSV_DELAY};
+use bitcoin_scripts::spend::SpendPath;
+use bitcoin_scripts::test_setup::{BitcoinRPC, RpcConfig};
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{Amount, Network, PrivateKey, PublicKey};
+use std::str::FromStr;
+
+fn pk(byte: u8) -> PublicKey {
+    PrivateKey::new(SecretKey::from_slice(&[byte; 32]).unwrap(), Network::Regtest).public_key(&Secp256k1::new())
+}
+
+#[tokio::test]
+async fn test_demo_paths_and_network_guard() {
+    for path in [DemoPath::Backup, DemoPath::Multisig, DemoPath::TaprootLeaf] {
+        assert_eq!(DemoPath::from_str(&path.to_string()).unwrap(), path);
+    }
+    assert!(DemoPath::from_str("keypath").is_err());
+
+    let keys = [pk(1), pk(2), pk(3), pk(4)];
+    assert!(demo_descriptor(DemoPath::Multisig, &keys).unwrap().to_string().starts_with("wsh(or_d("));
+    let tr = demo_descriptor(DemoPath::TaprootLeaf, &keys).unwrap();
+    assert!(tr.to_string().contains(&format!("older({})", DEMO_CSV_DELAY)));
+
+    // Refused before anything is sent to the node
+    let signet = BitcoinRPC::with_config(RpcConfig { network: Network::Signet, ..RpcConfig::default() }).unwrap();
+    assert!(demo::run(&signet, DemoPath::Backup, Amount::from_sat(100_000)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_vault_demo_spends_every_path_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("demo_wallet").await;
+    let _ = rpc.load_wallet("demo_wallet").await;
+    let rpc = rpc.with_wallet("demo_wallet");
+
+    let backup = demo::run(&rpc, DemoPath::Backup, Amount::from_sat(200_000)).await.unwrap();
+    assert_eq!(backup.blocks_waited, 0);
+    assert_eq!(backup.spend.plan.path, SpendPath::Script);
+    assert_eq!(backup.spend.transaction.input[0].previous_output, backup.funded.outpoint());
+
+    let multisig = demo::run(&rpc, DemoPath::Multisig, Amount::from_sat(200_000)).await.unwrap();
+    assert_eq!(multisig.blocks_waited, DEMO_CSV_DELAY as u32 - 1);
+    assert_eq!(multisig.spend.plan.signers.len(), 2);
+
+    let leaf = demo::run(&rpc, DemoPath::TaprootLeaf, Amount::from_sat(200_000)).await.unwrap();
+    assert!(matches!(leaf.spend.plan.path, SpendPath::TaprootLeaf { .. }));
+    let report = leaf.to_string();
+    assert!(report.contains(&format!("funding txid: {}", leaf.funded.outpoint().txid)), "{}", report);
+    assert!(report.contains(&format!("spend txid: {}", leaf.spend.txid)), "{}", report);
+    assert_eq!(rpc.get_raw_transaction_verbose(&leaf.spend.txid).await.unwrap().confirmations, Some(1));
+}
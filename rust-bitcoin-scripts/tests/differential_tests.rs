@@ -0,0 +1,99 @@
+use bitcoin_scripts::differential::{compare, local_verdict, nodes_from_env};
+use bitcoin_scripts::flows::{fund_descriptor, mine};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{Amount, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, absolute::LockTime};
+use miniscript::Descriptor;
+use serde_json::json;
+use std::str::FromStr;
+
+fn public_key() -> PublicKey {
+    PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[0x42; 32]).unwrap()))
+}
+
+/// A p2wpkh spend of a made-up prevout, signed correctly
+fn signed_spend() -> (Transaction, Vec<TxOut>) {
+    let prevout = TxOut { value: 100_000, script_pubkey: ScriptBuf::new_v0_p2wpkh(&public_key().wpubkey_hash().unwrap()) };
+    (sign_spend(OutPoint::new(Txid::all_zeros(), 0), &prevout), vec![prevout])
+}
+
+/// Spend `prevout`, paid to key 0x42, to the same script less 1000 sats
+fn sign_spend(outpoint: OutPoint, prevout: &TxOut) -> Transaction {
+    let secp = Secp256k1::new();
+    let sk = SecretKey::from_slice(&[0x42; 32]).unwrap();
+    let pk = public_key();
+    let mut tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut { value: prevout.value - 1_000, script_pubkey: prevout.script_pubkey.clone() }],
+    };
+    let script_code = ScriptBuf::new_p2pkh(&pk.pubkey_hash());
+    let sighash = SighashCache::new(&tx).segwit_signature_hash(0, &script_code, prevout.value, EcdsaSighashType::All).unwrap();
+    let sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&Message::from_slice(&sighash[..]).unwrap(), &sk));
+    tx.input[0].witness = Witness::from_slice(&[sig.to_vec(), pk.to_bytes()]);
+    tx
+}
+
+#[test]
+fn test_local_interpreter_accepts_valid_and_rejects_tampered_spend() {
+    let (tx, prevouts) = signed_spend();
+    assert!(local_verdict(&tx, &prevouts).accepted());
+
+    let mut tampered = tx.clone();
+    tampered.output[0].value -= 1;
+    let verdict = local_verdict(&tampered, &prevouts);
+    assert!(!verdict.accepted(), "signature no longer commits to the outputs");
+
+    assert!(!local_verdict(&tx, &[]).accepted());
+}
+
+/// Give `node` every block of `source` up to its tip, so a prevout confirmed on `source` is in
+/// the UTXO set of both. Blocks the node already has are reported as duplicates and skipped.
+async fn sync_blocks(source: &BitcoinRPC, node: &BitcoinRPC) {
+    let tip = source.get_block_count().await.unwrap();
+    for height in 1..=tip {
+        let hash = source.get_block_hash(height).await.unwrap();
+        let block = source.call_rpc("getblock", json!([hash.to_string(), 0])).await.unwrap();
+        node.call_rpc("submitblock", json!([block])).await.unwrap();
+    }
+    assert_eq!(node.get_block_hash(tip).await.unwrap(), source.get_block_hash(tip).await.unwrap(), "the external node follows another chain");
+}
+
+/// Runs only with WRAPYIELD_DIFFERENTIAL=1 and WRAPYIELD_DIFF_RPC_URL pointing at a second
+/// regtest node, which is handed the local node's blocks so the spent output exists on both
+#[tokio::test]
+async fn test_differential_against_external_nodes() {
+    let Some(nodes) = nodes_from_env() else { return };
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("differential_wallet").await;
+    let _ = rpc.load_wallet("differential_wallet").await;
+    let rpc = rpc.with_wallet("differential_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("wpkh({})", public_key())).unwrap();
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(100_000)).await.unwrap();
+    for (_, node) in &nodes {
+        sync_blocks(&rpc, node).await;
+        let prevout = node.call_rpc("gettxout", json!([funded.utxo.outpoint.txid.to_string(), funded.utxo.outpoint.vout])).await.unwrap();
+        assert!(!prevout.is_null(), "the prevout is unspent on the external node");
+    }
+
+    let tx = sign_spend(funded.utxo.outpoint, &funded.utxo.txout);
+    let report = compare(&tx, &[funded.utxo.txout.clone()], &nodes).await.unwrap();
+    assert!(report.all_accept(), "{:?}", report.verdicts);
+
+    // Every validator sees the same broken signature
+    let mut tampered = tx.clone();
+    tampered.output[0].value -= 1;
+    let report = compare(&tampered, &[funded.utxo.txout.clone()], &nodes).await.unwrap();
+    assert!(report.agree(), "{:?}", report.verdicts);
+    assert!(!report.verdicts[0].accepted());
+}
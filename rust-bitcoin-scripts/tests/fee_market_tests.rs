@@ -0,0 +1,96 @@
+use bitcoin_scripts::fee_market::{FeeMarket, FeeSample, PegOutSla, Schedule, SendReason};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::FeeRate;
+use std::collections::BTreeMap;
+
+const HOUR: u64 = 3600;
+
+fn sat_vb(rate: u64) -> FeeRate {
+    FeeRate::from_sat_per_vb_unchecked(rate)
+}
+
+fn sample(time: u64, six_block_rate: u64) -> FeeSample {
+    FeeSample { time, height: time / 600, estimates: BTreeMap::from([(6, sat_vb(six_block_rate))]), floor: sat_vb(1) }
+}
+
+/// Two days of hourly samples: 40 sat/vB during the day, 8 sat/vB for the six night hours
+fn two_days() -> FeeMarket {
+    let mut market = FeeMarket::default();
+    for hour in 0..48 {
+        market.record(sample(hour * HOUR, if hour % 24 < 6 { 8 } else { 40 }));
+    }
+    market
+}
+
+#[test]
+fn test_history_is_ordered_pruned_and_summarised() {
+    let mut market = FeeMarket::new(10 * HOUR);
+    for hour in [3, 1, 2, 0] {
+        market.record(sample(hour * HOUR, hour + 1));
+    }
+    assert_eq!(market.samples.iter().map(|s| s.time / HOUR).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    market.record(sample(12 * HOUR, 5));
+    assert_eq!(market.samples.iter().map(|s| s.time / HOUR).collect::<Vec<_>>(), vec![2, 3, 12]);
+
+    let market = two_days();
+    assert_eq!(market.percentile(6, 50, 24 * HOUR), Some(sat_vb(40)));
+    assert_eq!(market.percentile(6, 10, 24 * HOUR), Some(sat_vb(8)));
+    assert_eq!(market.percentile(6, 100, 24 * HOUR), Some(sat_vb(40)));
+    assert_eq!(market.percentile(144, 50, 24 * HOUR), None);
+    // The floor lifts estimates below it
+    let mut floored = sample(0, 1);
+    floored.floor = sat_vb(3);
+    assert_eq!(floored.rate(6), Some(sat_vb(3)));
+
+    let path = std::env::temp_dir().join(format!("wrapyield-fee-market-{}.json", std::process::id()));
+    market.save(&path).unwrap();
+    assert_eq!(FeeMarket::load(&path).unwrap().unwrap(), market);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_forecast_finds_the_cheap_window_a_patient_sender_reaches() {
+    let market = two_days();
+    // Ends in the evening at 40 sat/vB; within a day the night comes round again
+    let day = market.forecast(6, 24 * HOUR).unwrap();
+    assert_eq!((day.current, day.expected_low, day.windows), (sat_vb(40), sat_vb(8), 1));
+    // Most 2-hour stretches are all daytime
+    let short = market.forecast(6, 2 * HOUR).unwrap();
+    assert_eq!(short.expected_low, sat_vb(40));
+    assert_eq!(short.windows, 23);
+    // Longer than the history: nothing better than now is known
+    let long = market.forecast(6, 72 * HOUR).unwrap();
+    assert_eq!((long.expected_low, long.windows), (sat_vb(40), 0));
+    assert!(market.forecast(2, HOUR).is_none());
+}
+
+#[test]
+fn test_sla_defers_non_urgent_batches_to_cheaper_windows() {
+    let sla = PegOutSla::default();
+    let mut market = two_days();
+    let now = market.latest().unwrap().time;
+
+    assert_eq!(sla.schedule(&market, now, false, now).unwrap(), Schedule::Defer { current: sat_vb(40), target: sat_vb(8), deadline: now + 24 * HOUR });
+    assert_eq!(sla.schedule(&market, now, true, now).unwrap(), Schedule::Now { rate: sat_vb(40), reason: SendReason::Urgent });
+    // Queued a day ago: out of time, send at whatever it costs
+    assert_eq!(sla.schedule(&market, now - 24 * HOUR, false, now).unwrap(), Schedule::Now { rate: sat_vb(40), reason: SendReason::Deadline });
+
+    // Night falls and the rate drops within tolerance of the expected low
+    market.record(sample(now + 6 * HOUR, 8));
+    let later = now + 6 * HOUR;
+    assert_eq!(sla.schedule(&market, now, false, later).unwrap(), Schedule::Now { rate: sat_vb(8), reason: SendReason::Cheap });
+
+    assert!(sla.schedule(&FeeMarket::default(), now, false, now).is_err());
+}
+
+#[tokio::test]
+async fn test_poll_records_the_regtest_market() {
+    let rpc = BitcoinRPC::new();
+    let mut market = FeeMarket::default();
+    let sample = market.poll(&rpc).await.unwrap().clone();
+    assert_eq!(sample.height, rpc.get_block_count().await.unwrap());
+    // A fresh regtest node has no estimates, only the relay floor
+    assert!(sample.floor >= sat_vb(1));
+    assert!(sample.estimates.keys().all(|target| sample.rate(*target).unwrap() >= sample.floor));
+    assert_eq!(market.samples.len(), 1);
+}
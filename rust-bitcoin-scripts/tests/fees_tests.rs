@@ -0,0 +1,132 @@
+use bitcoin_scripts::fees::{self, broadcast_above_floor, estimate_fee_rate, from_btc_per_kvb, min_relay_rate_from_vars, spend_weight, vsize, FeePlan, RelayFloor, DEFAULT_MIN_RELAY_RATE, REGTEST_FALLBACK_RATE};
+use bitcoin_scripts::psbt::{self, SpendableUtxo};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Amount, FeeRate, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+mod common;
+use common::key;
+
+fn descriptor(template: &str) -> Descriptor<PublicKey> {
+    let secp = Secp256k1::new();
+    let s = template.replace('A', &key(11).public_key(&secp).to_string()).replace('B', &key(12).public_key(&secp).to_string());
+    Descriptor::from_str(&s).unwrap()
+}
+
+/// Sign a spend of `descriptor` to `outputs` and return the finalized transaction
+fn signed(descriptor: &Descriptor<PublicKey>, outputs: Vec<TxOut>) -> Transaction {
+    let definite = Descriptor::<DefiniteDescriptorKey>::from_str(&descriptor.to_string()).unwrap();
+    let prev_tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+        output: vec![TxOut { value: 100_000, script_pubkey: descriptor.script_pubkey() }],
+    };
+    let utxo = SpendableUtxo { outpoint: OutPoint::new(prev_tx.txid(), 0), txout: prev_tx.output[0].clone(), prev_tx: Some(prev_tx.clone()), sequence: Sequence::ENABLE_RBF_NO_LOCKTIME };
+    let mut unsigned = psbt::create(&definite, &[utxo], outputs, LockTime::ZERO).unwrap();
+    psbt::sign(&mut unsigned, &[key(11), key(12)]).unwrap();
+    psbt::finalize(unsigned).unwrap()
+}
+
+#[test]
+fn test_weight_estimate_bounds_the_signed_transaction() {
+    for template in ["wpkh(A)", "wsh(multi(2,A,B))", "tr(A)", "pkh(A)", "sh(wpkh(A))"] {
+        let descriptor = descriptor(template);
+        let outputs = vec![TxOut { value: 90_000, script_pubkey: descriptor.script_pubkey() }];
+        let estimate = spend_weight(&[&descriptor], &outputs).unwrap();
+        let actual = signed(&descriptor, outputs.clone()).weight();
+        assert!(estimate >= actual, "{}: estimated {} < actual {}", template, estimate, actual);
+        // Only signature length and the witness-count varint are over-estimated
+        assert!(estimate.to_wu() - actual.to_wu() <= 16, "{}: estimated {} for {}", template, estimate, actual);
+        assert_eq!(vsize(&[&descriptor], &outputs).unwrap(), estimate.to_vbytes_ceil());
+    }
+}
+
+#[test]
+fn test_change_is_added_only_above_dust() {
+    let input = descriptor("wpkh(A)");
+    let payment = TxOut { value: 50_000, script_pubkey: descriptor("wpkh(B)").script_pubkey() };
+    let change_script = input.script_pubkey();
+    let rate = FeeRate::from_sat_per_vb(10).unwrap();
+
+    let plan = FeePlan::with_change(&[&input], Amount::from_sat(100_000), vec![payment.clone()], change_script.clone(), rate).unwrap();
+    assert_eq!(plan.change, Some(1));
+    assert_eq!(plan.fee, fees::fee_at(rate, plan.weight).unwrap());
+    assert_eq!(plan.outputs[1].value, 100_000 - 50_000 - plan.fee.to_sat());
+
+    // A remainder too small for a change output is left to the fee
+    let plan = FeePlan::with_change(&[&input], Amount::from_sat(51_500), vec![payment.clone()], change_script.clone(), rate).unwrap();
+    assert_eq!((plan.change, plan.fee, plan.outputs.len()), (None, Amount::from_sat(1_500), 1));
+
+    assert!(FeePlan::with_change(&[&input], Amount::from_sat(50_500), vec![payment.clone()], change_script.clone(), rate).is_err());
+    assert!(FeePlan::with_change(&[&input], Amount::from_sat(40_000), vec![payment], change_script, rate).is_err());
+}
+
+#[test]
+fn test_sweep_pays_everything_but_the_fee() {
+    let input = descriptor("wsh(multi(2,A,B))");
+    let plan = FeePlan::sweep(&[&input], Amount::from_sat(100_000), input.script_pubkey(), REGTEST_FALLBACK_RATE).unwrap();
+    assert_eq!(plan.outputs[0].value + plan.fee.to_sat(), 100_000);
+    assert_eq!(plan.fee, fees::fee_at(REGTEST_FALLBACK_RATE, plan.weight).unwrap());
+    assert!(FeePlan::sweep(&[&input], Amount::from_sat(500), input.script_pubkey(), REGTEST_FALLBACK_RATE).is_err());
+}
+
+#[test]
+fn test_btc_per_kvb_conversion_rounds_up() {
+    assert_eq!(from_btc_per_kvb(Amount::from_sat(1_000)), FeeRate::from_sat_per_vb(1).unwrap());
+    assert_eq!(from_btc_per_kvb(Amount::from_sat(1_001)).to_sat_per_kwu(), 251);
+}
+
+#[tokio::test]
+async fn test_regtest_estimate_falls_back() {
+    let rpc = BitcoinRPC::new();
+    let rate = estimate_fee_rate(&rpc, 6).await.unwrap();
+    assert!(rate >= FeeRate::BROADCAST_MIN);
+}
+
+#[test]
+fn test_relay_floor_is_the_highest_of_its_rates() {
+    let d = descriptor("wpkh(A)");
+    let tx = signed(&d, vec![TxOut { value: 99_000, script_pubkey: d.script_pubkey() }]);
+    let vsize = tx.vsize() as u64;
+    let mut floor = RelayFloor { min_relay: DEFAULT_MIN_RELAY_RATE, mempool_min: DEFAULT_MIN_RELAY_RATE, configured: DEFAULT_MIN_RELAY_RATE };
+    assert_eq!(floor.check(&tx, Amount::from_sat(vsize)), Ok(()));
+
+    // Congested mempool
+    floor.mempool_min = FeeRate::from_sat_per_vb(20).unwrap();
+    let error = floor.check(&tx, Amount::from_sat(vsize)).unwrap_err();
+    assert_eq!(error.required, Amount::from_sat(20 * vsize));
+    assert_eq!(floor.check(&tx, Amount::from_sat(20 * vsize)), Ok(()));
+
+    floor.configured = FeeRate::from_sat_per_vb(25).unwrap();
+    assert_eq!(floor.rate(), floor.configured);
+    assert!(floor.check(&tx, Amount::from_sat(20 * vsize)).is_err());
+}
+
+#[test]
+fn test_min_relay_rate_from_vars() {
+    assert_eq!(min_relay_rate_from_vars(|_| None).unwrap(), DEFAULT_MIN_RELAY_RATE);
+    let rate = min_relay_rate_from_vars(|name| (name == "WRAPYIELD_MIN_RELAY_FEERATE").then(|| "5".to_string())).unwrap();
+    assert_eq!(rate, FeeRate::from_sat_per_vb(5).unwrap());
+    assert!(min_relay_rate_from_vars(|_| Some("fast".to_string())).is_err());
+}
+
+#[tokio::test]
+async fn test_estimate_and_broadcast_respect_node_relay_floor() {
+    let rpc = BitcoinRPC::new();
+    let info = rpc.get_mempool_info().await.unwrap();
+    assert!(info.min_relay_tx_fee > Amount::ZERO);
+    let floor = RelayFloor::fetch(&rpc, DEFAULT_MIN_RELAY_RATE).await.unwrap();
+    assert!(estimate_fee_rate(&rpc, 2).await.unwrap() >= floor.rate());
+
+    // Rejected locally, before the node sees it
+    let d = descriptor("wpkh(A)");
+    let tx = signed(&d, vec![TxOut { value: 99_990, script_pubkey: d.script_pubkey() }]);
+    let error = broadcast_above_floor(&rpc, &tx, Amount::from_sat(10)).await.unwrap_err();
+    assert!(error.to_string().contains("relay floor"), "{}", error);
+}
@@ -0,0 +1,91 @@
+use bitcoin_scripts::flows::{fund_descriptor, mine, plan_spend, spend_utxo, spend_utxo_estimated};
+use bitcoin_scripts::spend::SpendPath;
+use bitcoin_scripts::test_setup::{BitcoinRPC, WaitError};
+use bitcoin_scripts::timelock_csv::csv_vault_descriptor;
+use bitcoin::hashes::Hash;
+use bitcoin::{Amount, Sequence, Txid};
+use miniscript::bitcoin::{PrivateKey, PublicKey, secp256k1};
+use std::str::FromStr;
+use std::time::Duration;
+
+mod common;
+use common::key;
+
+#[tokio::test]
+async fn test_fund_plan_and_spend_csv_vault() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("flows_wallet").await;
+    let _ = rpc.load_wallet("flows_wallet").await;
+    let rpc = rpc.with_wallet("flows_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let secp = secp256k1::Secp256k1::new();
+    let keys: Vec<PrivateKey> = [41u8, 42, 43, 44].iter().map(|b| key(*b)).collect();
+    let k: Vec<PublicKey> = keys.iter().map(|p| p.public_key(&secp)).collect();
+    let descriptor = csv_vault_descriptor(k[3], &k[..3], 2, 10).unwrap();
+    let destination = rpc.get_new_address().await.unwrap();
+
+    // Backup key: spendable right away, no relative lock
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(200_000)).await.unwrap();
+    assert_eq!(funded.amount(), Amount::from_sat(200_000));
+    let plan = plan_spend(&rpc, &funded, &keys[3..]).await.unwrap();
+    assert_eq!(plan.signers, vec![k[3]]);
+    let spent = spend_utxo(&rpc, &funded, &plan, &keys[3..], &destination, Amount::from_sat(1_000)).await.unwrap();
+    assert_eq!(spent.transaction.input[0].previous_output, funded.outpoint());
+    assert_eq!(spent.transaction.output[0].value, 199_000);
+    let tip = rpc.get_block_count().await.unwrap() as u32;
+    assert!(spent.transaction.lock_time.to_consensus_u32() + 100 > tip, "lock time not set against fee sniping");
+
+    // 2-of-3: planned only once the output has 10 confirmations
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(200_000)).await.unwrap();
+    assert!(plan_spend(&rpc, &funded, &keys[..2]).await.is_err());
+    mine(&rpc, 9).await.unwrap();
+    let plan = plan_spend(&rpc, &funded, &keys[..2]).await.unwrap();
+    assert_eq!((plan.path.clone(), plan.sequence), (SpendPath::Script, Sequence::from_height(10)));
+    let spent = spend_utxo(&rpc, &funded, &plan, &keys, &destination, Amount::from_sat(1_000)).await.unwrap();
+    assert_eq!(spent.transaction.input[0].sequence, Sequence::from_height(10));
+    let mut receipt = spent.receipt.clone();
+    assert_eq!((receipt.txid, receipt.path_used.as_str(), receipt.fee), (spent.txid, "script", Amount::from_sat(1_000)));
+    assert_eq!(receipt.witness_sizes, vec![spent.transaction.input[0].witness.serialized_len()]);
+    assert_eq!(receipt.refresh(&rpc).await.unwrap(), None);
+    let height = mine(&rpc, 1).await.unwrap();
+    assert_eq!(receipt.refresh(&rpc).await.unwrap().map(|b| b.height), Some(height));
+
+    // Fee larger than the output
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(10_000)).await.unwrap();
+    let plan = plan_spend(&rpc, &funded, &keys[3..]).await.unwrap();
+    assert!(spend_utxo(&rpc, &funded, &plan, &keys[3..], &destination, Amount::from_sat(20_000)).await.is_err());
+
+    // Estimated fee: at least the minimum relay fee for the transaction actually sent
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(200_000)).await.unwrap();
+    let plan = plan_spend(&rpc, &funded, &keys[3..]).await.unwrap();
+    let spent = spend_utxo_estimated(&rpc, &funded, &plan, &keys[3..], &destination, 6).await.unwrap();
+    assert!(spent.fee.to_sat() >= spent.transaction.vsize() as u64);
+    assert_eq!(spent.transaction.output[0].value + spent.fee.to_sat(), 200_000);
+}
+
+#[tokio::test]
+async fn test_wait_for_confirmations_times_out_and_detects_missing_transactions() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("flows_wait_wallet").await;
+    let _ = rpc.load_wallet("flows_wait_wallet").await;
+    let rpc = rpc.with_wallet("flows_wait_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let address = rpc.get_new_address().await.unwrap();
+    let txid = Txid::from_str(&rpc.send_to_address(&address, Amount::from_sat(50_000)).await.unwrap()).unwrap();
+    let error = rpc.wait_for_confirmations(&txid, 1, Duration::from_millis(500)).await.unwrap_err();
+    assert_eq!(error.downcast_ref::<WaitError>(), Some(&WaitError::Timeout { txid, confirmations: 0, wanted: 1 }));
+
+    // Resolves once a block arrives while waiting
+    let mining = async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        mine(&rpc, 2).await.unwrap();
+    };
+    let (confirmed, _) = tokio::join!(rpc.wait_for_confirmations(&txid, 2, Duration::from_secs(30)), mining);
+    assert!(confirmed.unwrap().confirmations.unwrap() >= 2);
+
+    let unknown = Txid::from_byte_array([7; 32]);
+    let error = rpc.wait_for_confirmations(&unknown, 1, Duration::from_secs(5)).await.unwrap_err();
+    assert_eq!(error.downcast_ref::<WaitError>(), Some(&WaitError::Replaced { txid: unknown }));
+}
@@ -0,0 +1,93 @@
+use bitcoin_scripts::classic_multisig::{multisig_descriptor, MultisigKind};
+use bitcoin_scripts::hd::{account_path, derive, generate_mnemonic, parse_mnemonic, HdWallet, Purpose};
+use bitcoin_scripts::psbt::{self, SpendableUtxo};
+use bitcoin_scripts::taproot::{TaprootVault, TaprootVaultParams};
+use bitcoin_scripts::timelock_csv::csv_vault_descriptor;
+use bitcoin_scripts::verify::verify_spend;
+use bitcoin_scripts::witness::{vault_descriptor, VaultTimelock};
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::descriptor::DescriptorPublicKey;
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+const ABANDON: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+#[test]
+fn test_mnemonic_generation_and_parsing() {
+    let mnemonic = generate_mnemonic(24).unwrap();
+    assert_eq!(mnemonic.word_count(), 24);
+    assert_eq!(parse_mnemonic(&mnemonic.to_string()).unwrap(), *mnemonic);
+    assert!(generate_mnemonic(13).is_err());
+    assert!(parse_mnemonic("abandon abandon abandon").is_err());
+}
+
+#[test]
+fn test_bip86_test_vector() {
+    let wallet = HdWallet::from_mnemonic(&parse_mnemonic(ABANDON).unwrap(), "", Network::Bitcoin).unwrap();
+    let path = account_path(Purpose::Bip86, Network::Bitcoin, 0).unwrap();
+    assert_eq!(wallet.fingerprint(&Secp256k1::new()).to_string(), "73c5da0a");
+    assert_eq!(wallet.account_xpub(&path).unwrap().to_string(),
+        "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ");
+
+    let key = wallet.descriptor_key(&path, 0).unwrap();
+    assert!(key.to_string().starts_with("[73c5da0a/86'/0'/0']xpub6BgBgses"), "{}", key);
+    assert!(key.to_string().ends_with("/0/*"));
+    let descriptor: Descriptor<DescriptorPublicKey> = Descriptor::from_str(&format!("tr({})", key)).unwrap();
+    assert_eq!(derive(&descriptor, 0).unwrap().address(Network::Bitcoin).unwrap().to_string(),
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr");
+    assert!(format!("{:?}", wallet).contains("REDACTED"));
+    assert_eq!(wallet.clone(), wallet);
+    assert_ne!(HdWallet::from_mnemonic(&parse_mnemonic(ABANDON).unwrap(), "TREZOR", Network::Bitcoin).unwrap(), wallet);
+}
+
+#[test]
+fn test_hd_multisig_spend_signs_with_derived_keys() {
+    let secp = Secp256k1::new();
+    let wallets: Vec<HdWallet> = (0..3).map(|_| HdWallet::from_mnemonic(&generate_mnemonic(12).unwrap(), "", Network::Regtest).unwrap()).collect();
+    let path = account_path(Purpose::Bip48Wsh, Network::Regtest, 0).unwrap();
+    assert_eq!(path.to_string().trim_start_matches("m/"), "48'/1'/0'/2'");
+    let keys: Vec<DescriptorPublicKey> = wallets.iter().map(|w| w.descriptor_key(&path, 0).unwrap()).collect();
+
+    let ranged = multisig_descriptor(2, &keys, MultisigKind::Wsh).unwrap();
+    assert!(ranged.has_wildcard());
+    assert!(csv_vault_descriptor(keys[2].clone(), &keys[..2], 2, 10).unwrap().has_wildcard());
+    assert!(vault_descriptor(keys[2].clone(), &keys[..2], 2, VaultTimelock::Absolute(500)).unwrap().has_wildcard());
+
+    let definite = ranged.at_derivation_index(5).unwrap();
+    let script_pubkey = derive(&ranged, 5).unwrap().script_pubkey();
+    assert_eq!(definite.script_pubkey(), script_pubkey);
+    let prev_tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+        output: vec![TxOut { value: 100_000, script_pubkey: script_pubkey.clone() }],
+    };
+    let utxo = SpendableUtxo::new(OutPoint::new(prev_tx.txid(), 0), prev_tx.output[0].clone());
+    let mut unsigned = psbt::create(&definite, &[utxo], vec![TxOut { value: 90_000, script_pubkey }], LockTime::ZERO).unwrap();
+    let origin = unsigned.inputs[0].bip32_derivation.values().next().unwrap();
+    assert!(origin.1.to_string().ends_with("/0/5"));
+
+    let signers = [wallets[0].signing_key(&path, 0, 5).unwrap(), wallets[2].signing_key(&path, 0, 5).unwrap()];
+    assert!(unsigned.inputs[0].bip32_derivation.contains_key(&signers[0].public_key(&secp).inner));
+    assert_eq!(psbt::sign(&mut unsigned, &signers).unwrap(), 2);
+    let tx = psbt::finalize(unsigned).unwrap();
+    assert_eq!(verify_spend(&tx, &prev_tx.output), Ok(()));
+}
+
+#[test]
+fn test_taproot_vault_from_descriptor_keys() {
+    let secp = Secp256k1::new();
+    let wallet = HdWallet::from_mnemonic(&parse_mnemonic(ABANDON).unwrap(), "", Network::Regtest).unwrap();
+    let path = account_path(Purpose::Bip86, Network::Regtest, 0).unwrap();
+    let (internal, leaves) = (wallet.descriptor_key(&path, 0).unwrap(), wallet.descriptor_key(&path, 1).unwrap());
+
+    let params = TaprootVaultParams::from_descriptor_keys(&internal, &leaves, &leaves, 3, 500, Network::Regtest).unwrap();
+    assert_eq!(params.internal_key, wallet.signing_key(&path, 0, 3).unwrap().public_key(&secp).inner.x_only_public_key().0);
+    assert_eq!(params.recovery_key, wallet.signing_key(&path, 1, 3).unwrap().public_key(&secp).inner.x_only_public_key().0);
+    let vault = TaprootVault::new(&secp, params).unwrap();
+    let other = TaprootVault::new(&secp, TaprootVaultParams::from_descriptor_keys(&internal, &leaves, &leaves, 4, 500, Network::Regtest).unwrap()).unwrap();
+    assert_ne!(vault.script_pubkey(), other.script_pubkey());
+}
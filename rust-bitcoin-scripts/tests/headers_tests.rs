@@ -0,0 +1,95 @@
+use bitcoin_scripts::headers::{median_time_past, HeaderTracker};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::block::{Header, Version};
+use bitcoin::hashes::Hash;
+use bitcoin::hash_types::TxMerkleNode;
+use bitcoin::{BlockHash, CompactTarget};
+
+fn header(prev: BlockHash, time: u32, nonce: u32) -> Header {
+    Header { version: Version::ONE, prev_blockhash: prev, merkle_root: TxMerkleNode::all_zeros(), time, bits: CompactTarget::from_consensus(0x207fffff), nonce }
+}
+
+/// Headers 0..times.len() with the given timestamps, connected to a new tracker
+fn chain(tracker: &mut HeaderTracker, times: &[u32]) -> Vec<Header> {
+    let mut prev = BlockHash::all_zeros();
+    let mut headers = Vec::new();
+    for (height, time) in times.iter().enumerate() {
+        let h = header(prev, *time, 0);
+        tracker.connect(height as u64, &h).unwrap();
+        prev = h.block_hash();
+        headers.push(h);
+    }
+    headers
+}
+
+#[test]
+fn test_median_of_last_eleven_timestamps() {
+    assert_eq!(median_time_past(&[5, 1, 3]), Some(3));
+    assert_eq!(median_time_past(&[]), None);
+
+    let mut tracker = HeaderTracker::new();
+    // Out-of-order timestamps, as miners are allowed to produce
+    let times: Vec<u32> = vec![1000, 1600, 1100, 1700, 1200, 1800, 1300, 1900, 1400, 2000, 1500, 2100, 900];
+    chain(&mut tracker, &times);
+    assert_eq!(tracker.headers().count(), 11);
+    let mut window = times[2..].to_vec();
+    window.sort();
+    assert_eq!(tracker.current_median_time_past(), Some(window[5]));
+    assert_eq!(tracker.chain_tip().unwrap().height, 12);
+    // Heights whose window starts before the first tracked header are unknown
+    assert_eq!(tracker.median_time_past_at(11), None);
+}
+
+#[test]
+fn test_reorg_replaces_headers_and_gaps_are_rejected() {
+    let mut tracker = HeaderTracker::new();
+    let headers = chain(&mut tracker, &[100, 200, 300, 400]);
+    let replacement = header(headers[1].block_hash(), 250, 1);
+    tracker.connect(2, &replacement).unwrap();
+    assert_eq!(tracker.tip().unwrap().hash, replacement.block_hash());
+    assert_eq!(tracker.headers().count(), 3);
+    // Near genesis the median is over the blocks that exist
+    assert_eq!(tracker.current_median_time_past(), Some(200));
+
+    assert!(tracker.connect(4, &header(replacement.block_hash(), 500, 0)).is_err());
+    assert!(tracker.connect(3, &header(headers[2].block_hash(), 500, 0)).is_err());
+}
+
+#[test]
+fn test_time_locks_against_mtp() {
+    let mut tracker = HeaderTracker::with_capacity(30);
+    let times: Vec<u32> = (0..30).map(|i| 500_000_000 + i * 600).collect();
+    chain(&mut tracker, &times);
+    let mtp = tracker.current_median_time_past().unwrap();
+    assert_eq!(mtp, times[24]);
+    assert_eq!(tracker.absolute_time_mature(mtp - 1), Some(true));
+    assert_eq!(tracker.absolute_time_mature(mtp), Some(false));
+    assert_eq!(tracker.absolute_time_mature(800_000), None);
+
+    // Confirmed at height 15: the lock runs from the MTP of block 14 (times[9])
+    let elapsed = mtp - times[9];
+    assert_eq!(tracker.relative_time_mature(15, (elapsed / 512) as u16), Some(true));
+    assert_eq!(tracker.relative_time_mature(15, (elapsed / 512 + 1) as u16), Some(false));
+    assert_eq!(tracker.relative_time_mature(0, 1), None);
+}
+
+#[tokio::test]
+async fn test_sync_matches_node_median_time() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("headers_wallet").await;
+    let _ = rpc.load_wallet("headers_wallet").await;
+    let rpc = rpc.with_wallet("headers_wallet");
+    let address = rpc.get_new_address().await.unwrap();
+    rpc.generate_to_address(12, &address).await.unwrap();
+
+    let mut tracker = HeaderTracker::new();
+    assert!(tracker.sync(&rpc).await.unwrap());
+    let info = rpc.get_blockchain_info().await.unwrap();
+    assert_eq!(tracker.chain_tip().unwrap().height, info.blocks);
+    assert_eq!(tracker.current_median_time_past().unwrap() as u64, info.median_time);
+    assert!(!tracker.sync(&rpc).await.unwrap());
+
+    rpc.generate_to_address(1, &address).await.unwrap();
+    assert!(tracker.sync(&rpc).await.unwrap());
+    assert_eq!(tracker.current_median_time_past().unwrap() as u64, rpc.get_blockchain_info().await.unwrap().median_time);
+}
@@ -0,0 +1,123 @@
+use bitcoin_scripts::flows::{fund_descriptor, mine};
+use bitcoin_scripts::htlc::{HashLock, Htlc, HtlcParams, HtlcPath};
+use bitcoin_scripts::test_setup::{BitcoinRPC, DESTINATION_LABEL};
+use bitcoin_scripts::verify::verify_spend;
+use bitcoin::absolute::LockTime;
+use bitcoin::consensus::encode::serialize;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use std::str::FromStr;
+
+mod common;
+use common::key;
+
+const PREIMAGE: [u8; 32] = [42; 32];
+
+fn params(hash_lock: HashLock, timeout: u32) -> HtlcParams {
+    let secp = Secp256k1::new();
+    HtlcParams { receiver: key(131).public_key(&secp), sender: key(132).public_key(&secp), hash_lock, timeout }
+}
+
+fn spend(outpoint: OutPoint, value: u64, lock_time: LockTime, destination: ScriptBuf) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time,
+        input: vec![TxIn { previous_output: outpoint, script_sig: ScriptBuf::new(), sequence: Sequence::ENABLE_RBF_NO_LOCKTIME, witness: Witness::new() }],
+        output: vec![TxOut { value, script_pubkey: destination }],
+    }
+}
+
+#[test]
+fn test_claim_and_refund_witnesses_satisfy_both_output_types() {
+    let secp = Secp256k1::new();
+    for hash_lock in [HashLock::sha256(&PREIMAGE), HashLock::hash160(&PREIMAGE)] {
+        for htlc in [Htlc::wsh(params(hash_lock, 200)).unwrap(), Htlc::tr(params(hash_lock, 200)).unwrap()] {
+            let prevouts = vec![TxOut { value: 100_000, script_pubkey: htlc.script_pubkey() }];
+            let outpoint = OutPoint::new(Txid::all_zeros(), 0);
+            let destination = htlc.script_pubkey();
+
+            let mut claim = spend(outpoint, 90_000, LockTime::ZERO, destination.clone());
+            let sig = htlc.sign(&secp, &claim, 0, &prevouts, HtlcPath::Claim, &key(131)).unwrap();
+            claim.input[0].witness = htlc.claim_witness(&claim, 0, &prevouts, &sig, &PREIMAGE).unwrap();
+            assert_eq!(verify_spend(&claim, &prevouts), Ok(()), "{}", htlc.descriptor);
+            assert!(htlc.claim_witness(&claim, 0, &prevouts, &sig, &[7; 32]).is_err(), "wrong preimage");
+
+            let mut refund = spend(outpoint, 90_000, htlc.refund_lock_time(), destination.clone());
+            let sig = htlc.sign(&secp, &refund, 0, &prevouts, HtlcPath::Refund, &key(132)).unwrap();
+            refund.input[0].witness = htlc.refund_witness(&refund, 0, &prevouts, &sig).unwrap();
+            assert_eq!(verify_spend(&refund, &prevouts), Ok(()), "{}", htlc.descriptor);
+
+            // No refund witness before the timeout
+            let early = spend(outpoint, 90_000, LockTime::from_height(199).unwrap(), destination);
+            let sig = htlc.sign(&secp, &early, 0, &prevouts, HtlcPath::Refund, &key(132)).unwrap();
+            assert!(htlc.refund_witness(&early, 0, &prevouts, &sig).is_err());
+
+            // Only the path's key signs
+            assert!(htlc.sign(&secp, &claim, 0, &prevouts, HtlcPath::Claim, &key(132)).is_err());
+        }
+    }
+}
+
+#[test]
+fn test_htlc_descriptor_shapes() {
+    let hash_lock = HashLock::sha256(&PREIMAGE);
+    let wsh = Htlc::wsh(params(hash_lock, 200)).unwrap();
+    let p = params(hash_lock, 200);
+    assert_eq!(
+        wsh.descriptor.to_string().split('#').next().unwrap(),
+        format!("wsh(andor(pk({}),sha256({}),and_v(v:pk({}),after(200))))", p.receiver, hash_lock_hex(&hash_lock), p.sender)
+    );
+    assert_eq!(wsh.script(HtlcPath::Claim).unwrap(), wsh.script(HtlcPath::Refund).unwrap());
+
+    let tr = Htlc::tr(params(hash_lock, 200)).unwrap();
+    assert_ne!(tr.script(HtlcPath::Claim).unwrap(), tr.script(HtlcPath::Refund).unwrap());
+    // Each hash gets its own internal key
+    let other = Htlc::tr(params(HashLock::sha256(&[1; 32]), 200)).unwrap();
+    assert_ne!(tr.descriptor.to_string().split(',').next(), other.descriptor.to_string().split(',').next());
+    assert!(Htlc::wsh(params(hash_lock, 0)).is_err());
+}
+
+fn hash_lock_hex(hash_lock: &HashLock) -> String {
+    match hash_lock {
+        HashLock::Sha256(hash) => hash.to_string(),
+        HashLock::Hash160(hash) => hash.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_htlc_claim_and_refund_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("htlc_wallet").await;
+    let _ = rpc.load_wallet("htlc_wallet").await;
+    let rpc = rpc.with_wallet("htlc_wallet");
+    let tip = mine(&rpc, 101).await.unwrap();
+    let secp = Secp256k1::new();
+    let destination = Address::from_str(&rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap()).unwrap().assume_checked().script_pubkey();
+
+    let timeout = tip as u32 + 10;
+    for htlc in [Htlc::wsh(params(HashLock::sha256(&PREIMAGE), timeout)).unwrap(), Htlc::tr(params(HashLock::hash160(&PREIMAGE), timeout)).unwrap()] {
+        // Claim right away with the preimage
+        let funded = fund_descriptor(&rpc, &htlc.descriptor, Amount::from_sat(1_000_000)).await.unwrap();
+        let prevouts = vec![funded.utxo.txout.clone()];
+        let mut claim = spend(funded.outpoint(), 990_000, LockTime::ZERO, destination.clone());
+        let sig = htlc.sign(&secp, &claim, 0, &prevouts, HtlcPath::Claim, &key(131)).unwrap();
+        claim.input[0].witness = htlc.claim_witness(&claim, 0, &prevouts, &sig, &PREIMAGE).unwrap();
+        rpc.send_raw_transaction(&hex::encode(serialize(&claim))).await.unwrap();
+
+        // The refund is non-final until the timeout, then goes through
+        let funded = fund_descriptor(&rpc, &htlc.descriptor, Amount::from_sat(1_000_000)).await.unwrap();
+        let prevouts = vec![funded.utxo.txout.clone()];
+        let mut refund = spend(funded.outpoint(), 990_000, htlc.refund_lock_time(), destination.clone());
+        let sig = htlc.sign(&secp, &refund, 0, &prevouts, HtlcPath::Refund, &key(132)).unwrap();
+        refund.input[0].witness = htlc.refund_witness(&refund, 0, &prevouts, &sig).unwrap();
+        let hex = hex::encode(serialize(&refund));
+        let tip = rpc.get_block_count().await.unwrap();
+        if tip < timeout as u64 {
+            assert!(rpc.send_raw_transaction(&hex).await.is_err(), "refund accepted before the timeout");
+            mine(&rpc, (timeout as u64 - tip) as u32).await.unwrap();
+        }
+        rpc.send_raw_transaction(&hex).await.unwrap();
+        mine(&rpc, 1).await.unwrap();
+    }
+}
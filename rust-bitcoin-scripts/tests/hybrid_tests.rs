@@ -0,0 +1,54 @@
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::hybrid::{build_hybrid_psbt, sign_hybrid, VaultInput};
+use bitcoin_scripts::locktime::LockTimePolicy;
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use std::str::FromStr;
+use std::time::Duration;
+use bitcoin::{Amount, Sequence, absolute::LockTime};
+use bitcoin::consensus::encode::serialize_hex;
+
+#[tokio::test]
+async fn test_hybrid_vault_and_wallet_spend() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("hybrid_wallet").await;
+    let _ = rpc.load_wallet("hybrid_wallet").await;
+    let rpc = rpc.with_wallet("hybrid_wallet");
+    let funding_address = rpc.get_new_address().await.unwrap();
+    let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
+
+    let secp = secp256k1::Secp256k1::new();
+    let privkeys: Vec<PrivateKey> = [5u8, 6, 7, 8].iter()
+        .map(|b| PrivateKey::new(secp256k1::SecretKey::from_slice(&[*b; 32]).unwrap(), Network::Regtest))
+        .collect();
+    let pubkeys: Vec<PublicKey> = privkeys.iter().map(|pk| PublicKey::from_private_key(&secp, pk)).collect();
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!(
+        "wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))",
+        pubkeys[3], pubkeys[0], pubkeys[1], pubkeys[2]
+    )).unwrap();
+    let vault_address = descriptor.address(Network::Regtest).unwrap().to_string();
+
+    let txid = rpc.send_to_address(&vault_address, Amount::from_sat(10_000_000)).await.unwrap();
+    let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
+    let utxo = rpc.find_utxos_for_descriptor(&descriptor).await.unwrap().into_iter()
+        .find(|u| u.outpoint.txid.to_string() == txid)
+        .expect("Vault output not found");
+
+    // The vault output is forwarded in full; the wallet input pays the fee
+    let vault = VaultInput {
+        outpoint: utxo.outpoint,
+        amount: utxo.amount,
+        descriptor,
+        sequence: Sequence(0xfffffffd),
+    };
+    let destination = rpc.get_new_address().await.unwrap();
+    let psbt = build_hybrid_psbt(&rpc, &vault, &destination, Amount::from_sat(20_000), LockTime::ZERO, LockTimePolicy::default()).await.unwrap();
+    let tx = sign_hybrid(&rpc, psbt, &[privkeys[3]]).await.unwrap();
+    assert_eq!(tx.input.len(), 2);
+    assert!(tx.input.iter().all(|i| !i.witness.is_empty()));
+    assert_eq!(tx.output[0].value, utxo.amount.to_sat());
+
+    rpc.send_raw_transaction(&serialize_hex(&tx)).await.unwrap();
+    let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
+    rpc.wait_for_confirmations(&tx.txid(), 1, Duration::from_secs(30)).await.expect("Hybrid spend not confirmed");
+}
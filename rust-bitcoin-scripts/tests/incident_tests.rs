@@ -0,0 +1,137 @@
+use bitcoin_scripts::addresses::AddressManager;
+use bitcoin_scripts::flows::{fund_descriptor, mine, spend_utxo};
+use bitcoin_scripts::incident::{self, RecoveryOutcome};
+use bitcoin_scripts::inspect;
+use bitcoin_scripts::keystore::{KeyEntry, Keystore};
+use bitcoin_scripts::secret::SigningKey;
+use bitcoin_scripts::spend::Planner;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::vault::{Protocol, VaultParams, VaultState};
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::{Amount, Network, OutPoint, PublicKey, Sequence, Txid};
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+mod common;
+use common::{key, pk};
+
+const XPUB: &str = "[73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*";
+
+fn signing_key(byte: u8) -> SigningKey {
+    SigningKey::new(SecretKey::from_slice(&[byte; 32]).unwrap(), Network::Regtest)
+}
+
+#[test]
+fn test_freeze_stops_addresses_and_peg_outs_until_lifted() {
+    let dir = std::env::temp_dir();
+    let addresses = dir.join(format!("wrapyield-incident-addresses-{}.json", std::process::id()));
+    let vault = dir.join(format!("wrapyield-incident-vault-{}.json", std::process::id()));
+    let missing = dir.join(format!("wrapyield-incident-missing-{}.json", std::process::id()));
+
+    let mut manager = AddressManager::new(inspect::parse(&format!("wpkh({})", XPUB)).unwrap(), Network::Regtest).unwrap();
+    manager.next_address().unwrap();
+    manager.save(&addresses).unwrap();
+    let params = VaultParams { user: pk(81), federation: vec![pk(82), pk(83), pk(84)], threshold: 2, unbonding_csv: 5, min_confirmations: 2, network: Network::Regtest };
+    let utxo = OutPoint::new(Txid::all_zeros(), 0);
+    let protocol = Protocol { params, state: VaultState::YieldAccrual { utxo, amount: Amount::from_sat(1_000_000), since_height: 100 }, frozen: None };
+    protocol.save(&vault).unwrap();
+
+    // A missing file fails the whole freeze before anything is written
+    assert!(incident::freeze(&[addresses.clone(), missing], &[vault.clone()], "key leak").is_err());
+    assert_eq!(AddressManager::load(&addresses).unwrap().unwrap().frozen(), None);
+
+    let report = incident::freeze(&[addresses.clone()], &[vault.clone()], "key leak").unwrap();
+    assert_eq!(report.vaults, vec![(vault.clone(), Some((utxo, Amount::from_sat(1_000_000))))]);
+    let mut manager = AddressManager::load(&addresses).unwrap().unwrap();
+    assert_eq!(manager.frozen(), Some("key leak"));
+    assert!(manager.next_address().unwrap_err().to_string().contains("key leak"));
+    assert_eq!(manager.next_index(), 1);
+    let protocol = Protocol::load(&vault).unwrap().unwrap();
+    assert!(protocol.build_unbonding(Amount::from_sat(1_000)).unwrap_err().to_string().contains("frozen"));
+
+    incident::unfreeze(&[addresses.clone()], &[vault.clone()]).unwrap();
+    assert_eq!(AddressManager::load(&addresses).unwrap().unwrap().next_address().unwrap().0, 1);
+    Protocol::load(&vault).unwrap().unwrap().build_unbonding(Amount::from_sat(1_000)).unwrap();
+    // Unfrozen state files look as before
+    assert!(!std::fs::read_to_string(&addresses).unwrap().contains("frozen"));
+    std::fs::remove_file(&addresses).unwrap();
+    std::fs::remove_file(&vault).unwrap();
+}
+
+#[tokio::test]
+async fn test_trace_descendants_across_blocks_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("incident_wallet").await;
+    let _ = rpc.load_wallet("incident_wallet").await;
+    let rpc = rpc.with_wallet("incident_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let first: Descriptor<PublicKey> = Descriptor::from_str(&format!("wpkh({})", pk(101))).unwrap();
+    let second: Descriptor<PublicKey> = Descriptor::from_str(&format!("wpkh({})", pk(102))).unwrap();
+    let funded = fund_descriptor(&rpc, &first, Amount::from_sat(500_000)).await.unwrap();
+    let root = funded.outpoint().txid;
+
+    // root -> hop one block later -> hop two blocks after that
+    let tip = rpc.get_block_count().await.unwrap() as u32;
+    let plan = Planner::new([pk(101)], tip, 0, 1).plan(&first).unwrap();
+    let hop = spend_utxo(&rpc, &funded, &plan, &[key(101)], &second.address(Network::Regtest).unwrap().to_string(), Amount::from_sat(1_000)).await.unwrap();
+    let hop_height = mine(&rpc, 1).await.unwrap();
+    mine(&rpc, 1).await.unwrap();
+    let mut onward = funded.clone();
+    onward.descriptor = second.clone();
+    onward.utxo.outpoint = OutPoint::new(hop.txid, 0);
+    onward.utxo.txout = hop.transaction.output[0].clone();
+    let plan = Planner::new([pk(102)], tip, 0, 1).plan(&second).unwrap();
+    let last = spend_utxo(&rpc, &onward, &plan, &[key(102)], &rpc.get_new_address().await.unwrap(), Amount::from_sat(1_000)).await.unwrap();
+    let last_height = mine(&rpc, 1).await.unwrap();
+
+    let trace = incident::trace_descendants(&rpc, &root, None).await.unwrap();
+    assert_eq!(trace.root_height, funded.height);
+    assert_eq!(trace.descendants(), vec![hop.txid, last.txid]);
+    assert_eq!((trace.hops[0].depth, trace.hops[0].spent, trace.hops[0].height), (1, funded.outpoint(), hop_height));
+    assert_eq!((trace.hops[1].depth, trace.hops[1].spent, trace.hops[1].height), (2, OutPoint::new(hop.txid, 0), last_height));
+    // The coins sit in the last hop's output; the first hop's is spent
+    assert!(trace.unspent.iter().any(|(outpoint, _, depth)| *outpoint == OutPoint::new(last.txid, 0) && *depth == 2));
+    assert!(trace.unspent.iter().all(|(outpoint, _, _)| outpoint.txid != hop.txid));
+    assert!(trace.to_string().contains(&last.txid.to_string()));
+
+    // Stopping before the last hop leaves the coins at the first one
+    let partial = incident::trace_descendants(&rpc, &root, Some(hop_height)).await.unwrap();
+    assert_eq!(partial.descendants(), vec![hop.txid]);
+    assert!(partial.unspent.iter().any(|(outpoint, _, _)| *outpoint == OutPoint::new(hop.txid, 0)));
+    assert!(incident::trace_descendants(&rpc, &root, Some(funded.height - 1)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_prepare_recovery_for_held_paths_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("incident_recovery_wallet").await;
+    let _ = rpc.load_wallet("incident_recovery_wallet").await;
+    let rpc = rpc.with_wallet("incident_recovery_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    // We hold only the backup key of the vault, so recovery waits for its timelock
+    let vault: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(or_d(multi(2,{},{}),and_v(v:pk({}),older(3))))", pk(111), pk(112), pk(113))).unwrap();
+    let mut keystore = Keystore::new();
+    keystore.insert("vault", KeyEntry::new(vault.clone(), Network::Regtest, vec![signing_key(113)]).unwrap(), false).unwrap();
+    let funded = fund_descriptor(&rpc, &vault, Amount::from_sat(400_000)).await.unwrap();
+    let destination = rpc.get_new_address().await.unwrap();
+
+    let prepared = incident::prepare_recovery(&rpc, &keystore, &[], &destination).await.unwrap();
+    assert_eq!(prepared.len(), 1);
+    assert_eq!(prepared[0].utxo.outpoint, funded.outpoint());
+    assert!(matches!(prepared[0].outcome, RecoveryOutcome::Blocked { .. }), "{}", prepared[0]);
+    assert!(incident::prepare_recovery(&rpc, &keystore, &["other".to_string()], &destination).await.is_err());
+
+    mine(&rpc, 2).await.unwrap();
+    let prepared = incident::prepare_recovery(&rpc, &keystore, &["vault".to_string()], &destination).await.unwrap();
+    let RecoveryOutcome::Ready { transaction, fee, .. } = &prepared[0].outcome else { panic!("{}", prepared[0]) };
+    assert_eq!(transaction.input[0].sequence, Sequence::from_height(3));
+    assert_eq!(Amount::from_sat(transaction.output[0].value) + *fee, funded.amount());
+    assert_eq!(transaction.output[0].script_pubkey, rpc.parse_address(&destination).unwrap().script_pubkey());
+    // Prepared, not broadcast: the node would take it, and the UTXO is still there
+    assert!(rpc.test_mempool_accept(&[serialize_hex(transaction)]).await.unwrap()[0].allowed);
+    assert_eq!(rpc.find_utxos_for_descriptor(&vault).await.unwrap().len(), 1);
+}
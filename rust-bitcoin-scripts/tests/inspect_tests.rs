@@ -0,0 +1,50 @@
+use bitcoin_scripts::inspect::{self, requirements, DescriptorReport};
+use bitcoin_scripts::timelock_csv::csv_vault_descriptor;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{Network, PrivateKey, PublicKey};
+
+/// BIP86 account 0 of the "abandon ... about" mnemonic
+const BIP86_XPUB: &str = "[73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*";
+
+fn pk(byte: u8) -> PublicKey {
+    PrivateKey::new(SecretKey::from_slice(&[byte; 32]).unwrap(), Network::Regtest).public_key(&Secp256k1::new())
+}
+
+#[test]
+fn test_inspect_ranged_taproot_descriptor() {
+    let descriptor = inspect::parse(&format!("tr({})", BIP86_XPUB)).unwrap();
+    assert_eq!(inspect::address(&descriptor, 0, Network::Bitcoin).unwrap().to_string(), "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr");
+    assert_ne!(inspect::address(&descriptor, 1, Network::Bitcoin).unwrap(), inspect::address(&descriptor, 0, Network::Bitcoin).unwrap());
+
+    let report = DescriptorReport::new(&descriptor, 0).unwrap();
+    assert_eq!(report.index, Some(0));
+    assert_eq!(report.addresses.len(), inspect::NETWORKS.len());
+    assert!(report.script.is_none());
+    assert_eq!(report.paths.len(), 1);
+    let text = report.to_string();
+    assert!(text.contains("address (bitcoin): bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"), "{}", text);
+    assert!(text.contains("address (regtest): bcrt1p"), "{}", text);
+}
+
+#[test]
+fn test_inspect_lists_timelock_requirements_per_path() {
+    let vault = csv_vault_descriptor(pk(4), &[pk(1), pk(2), pk(3)], 2, 144).unwrap();
+    let descriptor = inspect::parse(&vault.to_string()).unwrap();
+    let report = DescriptorReport::new(&descriptor, 0).unwrap();
+    assert_eq!(report.index, None);
+    assert_eq!(report.script, Some(vault.explicit_script().unwrap()));
+    assert_eq!(report.max_satisfaction_weight, vault.max_weight_to_satisfy().unwrap());
+    // Backup key alone, and each 2-of-3 subset after 144 blocks
+    assert_eq!(report.paths.len(), 4);
+    let timelocked: Vec<_> = report.paths.iter().filter(|c| c.older == 144).collect();
+    assert_eq!(timelocked.len(), 3);
+    assert_eq!(requirements(timelocked[0]), vec!["nSequence of 144 blocks".to_string()]);
+    assert!(report.to_string().contains("needs nSequence of 144 blocks"));
+
+    let hashed = inspect::parse(&format!("wsh(or_d(pk({}),and_v(v:sha256({}),after(1700000000))))", pk(1), "11".repeat(32))).unwrap();
+    let report = DescriptorReport::new(&hashed, 0).unwrap();
+    let locked = report.paths.iter().find(|c| c.preimage).unwrap();
+    assert_eq!(requirements(locked), vec!["nLockTime at timestamp 1700000000 or later".to_string(), "a hash preimage".to_string()]);
+
+    assert!(inspect::parse("wsh(pk(nonsense))").is_err());
+}
@@ -0,0 +1,151 @@
+use bitcoin_scripts::differential::local_verdict;
+use bitcoin_scripts::interpreter::{trace_input, ExecutionTrace};
+use bitcoin_scripts::psbt::{self, SpendableUtxo};
+use bitcoin_scripts::witness::{build_backup_path_witness, build_multisig_timelock_witness};
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache};
+use bitcoin::{Network, OutPoint, PrivateKey, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, absolute::LockTime};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+const VALUE: u64 = 100_000;
+
+fn keys() -> Vec<(SecretKey, PublicKey)> {
+    let secp = Secp256k1::new();
+    [5u8, 6, 7, 8].iter().map(|b| {
+        let sk = SecretKey::from_slice(&[*b; 32]).unwrap();
+        (sk, PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &sk)))
+    }).collect()
+}
+
+/// Backup key 8, or 2-of-3 of keys 5-7 after 10 blocks
+fn vault() -> Descriptor<PublicKey> {
+    let k = keys();
+    Descriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))", k[3].1, k[0].1, k[1].1, k[2].1)).unwrap()
+}
+
+fn unsigned_spend(descriptor: &Descriptor<PublicKey>, sequence: Sequence) -> (Transaction, TxOut) {
+    let prevout = TxOut { value: VALUE, script_pubkey: descriptor.script_pubkey() };
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence, witness: Witness::default() }],
+        output: vec![TxOut { value: VALUE - 1_000, script_pubkey: prevout.script_pubkey.clone() }],
+    };
+    (tx, prevout)
+}
+
+fn sign(tx: &Transaction, descriptor: &Descriptor<PublicKey>, sk: &SecretKey) -> bitcoin::ecdsa::Signature {
+    let script = descriptor.explicit_script().unwrap();
+    let sighash = SighashCache::new(tx).segwit_signature_hash(0, &script, VALUE, EcdsaSighashType::All).unwrap();
+    bitcoin::ecdsa::Signature::sighash_all(Secp256k1::new().sign_ecdsa(&Message::from_slice(&sighash[..]).unwrap(), sk))
+}
+
+/// Keys 5 and 7 sign the multisig path
+fn multisig_spend(sequence: Sequence) -> (Transaction, TxOut) {
+    let k = keys();
+    let descriptor = vault();
+    let (mut tx, prevout) = unsigned_spend(&descriptor, sequence);
+    let sigs = HashMap::from([(k[0].1, sign(&tx, &descriptor, &k[0].0)), (k[2].1, sign(&tx, &descriptor, &k[2].0))]);
+    tx.input[0].witness = build_multisig_timelock_witness(&descriptor, &sigs).unwrap();
+    (tx, prevout)
+}
+
+fn replay(tx: &Transaction, prevout: &TxOut) -> ExecutionTrace {
+    trace_input(tx, 0, prevout, &Prevouts::All(&[prevout.clone()])).unwrap()
+}
+
+/// `[<>, sig 5, sig 7, 2, key 5, key 6, key 7, 3]`, what the multisig op is handed
+fn multisig_operands(witness: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let k = keys();
+    vec![vec![], witness[1].clone(), witness[2].clone(), vec![2], k[0].1.to_bytes(), k[1].1.to_bytes(), k[2].1.to_bytes(), vec![3]]
+}
+
+#[test]
+fn test_backup_and_multisig_paths_execute() {
+    let k = keys();
+    let descriptor = vault();
+    let (mut tx, prevout) = unsigned_spend(&descriptor, Sequence::ENABLE_RBF_NO_LOCKTIME);
+    let sig = sign(&tx, &descriptor, &k[3].0);
+    tx.input[0].witness = build_backup_path_witness(&descriptor, &sig).unwrap();
+    let backup = replay(&tx, &prevout);
+    assert!(backup.succeeded(), "{}", backup);
+    // <key 8> OP_CHECKSIG consumes the signature and leaves true
+    assert_eq!(backup.steps[1].op, "OP_CHECKSIG");
+    assert_eq!(backup.steps[1].stack_before, vec![sig.to_vec(), k[3].1.to_bytes()]);
+    assert_eq!(backup.steps[1].stack_after, vec![vec![1]]);
+    // The multisig branch after OP_IFDUP OP_NOTIF is skipped
+    assert!(backup.steps.iter().any(|s| !s.executed));
+
+    let (tx, prevout) = multisig_spend(Sequence(10));
+    let multisig = replay(&tx, &prevout);
+    assert!(multisig.succeeded(), "{}", multisig);
+    let witness = tx.input[0].witness.to_vec();
+    let checkmultisig = multisig.steps.iter().find(|s| s.op == "OP_CHECKMULTISIGVERIFY").unwrap();
+    assert_eq!(checkmultisig.stack_before, multisig_operands(&witness));
+    assert!(checkmultisig.stack_after.is_empty());
+    // CSV leaves its argument, which is the truthy result
+    assert_eq!(multisig.steps.last().unwrap().stack_after, vec![vec![10]]);
+}
+
+#[test]
+fn test_trace_pinpoints_unmet_relative_timelock() {
+    let (tx, prevout) = multisig_spend(Sequence(9));
+    let trace = replay(&tx, &prevout);
+    assert!(!trace.succeeded());
+    let failing = trace.failing_step().unwrap();
+    assert_eq!(failing.op, "OP_CSV");
+    assert_eq!(failing.stack_before, vec![vec![10]]);
+    assert_eq!(failing.stack_after, vec![vec![10]]);
+    assert!(trace.to_string().ends_with("FAILED: nSequence 9 below required 10"), "{}", trace);
+}
+
+#[test]
+fn test_trace_pinpoints_misordered_multisig_signatures() {
+    let (mut tx, prevout) = multisig_spend(Sequence(10));
+    let mut items = tx.input[0].witness.to_vec();
+    items.swap(1, 2);
+    tx.input[0].witness = Witness::from_slice(&items);
+    let trace = replay(&tx, &prevout);
+    let failing = trace.failing_step().unwrap();
+    assert_eq!(failing.op, "OP_CHECKMULTISIGVERIFY");
+    // The stack before the failing op still shows what CHECKMULTISIG was given, sig 7 first
+    assert_eq!(failing.stack_before, multisig_operands(&items));
+    assert!(trace.error.as_ref().unwrap().contains("NULLFAIL"), "{}", trace);
+}
+
+#[test]
+fn test_wpkh_and_tapscript_inputs_are_traced() {
+    let secp = Secp256k1::new();
+    let key = |byte: u8| PrivateKey::new(SecretKey::from_slice(&[byte; 32]).unwrap(), Network::Regtest);
+    for (template, signers) in [("wpkh(A)", vec![key(11)]), ("tr(A,pk(B))", vec![key(12)])] {
+        let s = template.replace('A', &key(11).public_key(&secp).to_string()).replace('B', &key(12).public_key(&secp).to_string());
+        let descriptor = Descriptor::<DefiniteDescriptorKey>::from_str(&s).unwrap();
+        let prevout = TxOut { value: VALUE, script_pubkey: descriptor.script_pubkey() };
+        let utxo = SpendableUtxo::new(OutPoint::new(Txid::all_zeros(), 0), prevout.clone());
+        let mut unsigned = psbt::create(&descriptor, &[utxo], vec![TxOut { value: VALUE - 1_000, script_pubkey: prevout.script_pubkey.clone() }], LockTime::ZERO).unwrap();
+        psbt::sign(&mut unsigned, &signers).unwrap();
+        let tx = psbt::finalize(unsigned).unwrap();
+
+        let trace = replay(&tx, &prevout);
+        assert!(trace.succeeded(), "{}: {}", template, trace);
+        assert_eq!(trace.steps.last().unwrap().op, "OP_CHECKSIG", "{}", template);
+        assert_eq!(trace.steps.last().unwrap().stack_after, vec![vec![1]], "{}", template);
+    }
+}
+
+#[test]
+fn test_local_verdict_carries_trace_for_rejected_p2wsh_input() {
+    let (tx, prevout) = multisig_spend(Sequence(9));
+    let verdict = local_verdict(&tx, &[prevout.clone()]);
+    assert!(!verdict.accepted());
+    assert_eq!(verdict.trace.unwrap().failing_step().unwrap().op, "OP_CSV");
+
+    let (tx, prevout) = multisig_spend(Sequence(10));
+    let verdict = local_verdict(&tx, &[prevout]);
+    assert!(verdict.accepted(), "{:?}", verdict.rejection);
+    assert!(verdict.trace.is_none());
+}
@@ -0,0 +1,67 @@
+use bitcoin_scripts::classic_multisig::create_multisig;
+use bitcoin_scripts::keystore::{KeyEntry, Keystore};
+use bitcoin_scripts::secret::SigningKey;
+use bitcoin_scripts::timelock_csv::csv_vault_descriptor;
+use miniscript::bitcoin::{Network, PublicKey};
+use std::path::PathBuf;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wrapyield-keystore-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_keystore_survives_a_restart() {
+    let dir = scratch("roundtrip");
+    let path = dir.join("keys.wykey");
+    assert!(Keystore::load(&path, "pw").unwrap().is_none());
+
+    let multisig = create_multisig().unwrap();
+    let keys: Vec<SigningKey> = (0..4).map(|_| SigningKey::random(Network::Regtest)).collect();
+    let pubkeys: Vec<PublicKey> = keys.iter().map(|k| k.public_key).collect();
+    let vault = csv_vault_descriptor(pubkeys[3], &pubkeys[..3], 2, 10).unwrap();
+
+    let mut store = Keystore::new();
+    store.insert("multisig", KeyEntry::from_multisig(&multisig, Network::Regtest).unwrap(), false).unwrap();
+    store.insert("vault", KeyEntry::new(vault.clone(), Network::Regtest, keys[..2].to_vec()).unwrap(), false).unwrap();
+    store.save(&path, "correct horse").unwrap();
+
+    let raw = std::fs::read(&path).unwrap();
+    let wif = keys[0].to_wif();
+    assert!(!raw.windows(wif.len()).any(|w| w == wif.as_bytes()), "keystore must not contain plaintext keys");
+
+    let reloaded = Keystore::load(&path, "correct horse").unwrap().unwrap();
+    assert_eq!(reloaded, store);
+    let entry = reloaded.get("vault").unwrap();
+    assert_eq!(entry.descriptor, vault);
+    assert_eq!(entry.address().unwrap(), vault.address(Network::Regtest).unwrap());
+    assert_eq!(entry.signers(), vec![keys[0].expose(), keys[1].expose()]);
+    assert_eq!(reloaded.get("multisig").unwrap().address().unwrap().to_string(), multisig.address);
+
+    assert!(Keystore::load(&path, "wrong").is_err());
+}
+
+#[test]
+fn test_entries_are_checked() {
+    let keys: Vec<SigningKey> = (0..4).map(|_| SigningKey::random(Network::Regtest)).collect();
+    let pubkeys: Vec<PublicKey> = keys.iter().map(|k| k.public_key).collect();
+    let vault = csv_vault_descriptor(pubkeys[3], &pubkeys[..3], 2, 10).unwrap();
+
+    let stranger = SigningKey::random(Network::Regtest);
+    assert!(KeyEntry::new(vault.clone(), Network::Regtest, vec![stranger]).is_err());
+    assert!(KeyEntry::new(vault.clone(), Network::Testnet, vec![keys[0].clone()]).is_err());
+
+    let mut store = Keystore::new();
+    store.insert("vault", KeyEntry::new(vault.clone(), Network::Regtest, vec![]).unwrap(), false).unwrap();
+    assert!(store.insert("vault", KeyEntry::new(vault.clone(), Network::Regtest, vec![]).unwrap(), false).is_err());
+    assert!(store.insert("vault", KeyEntry::new(vault, Network::Regtest, vec![keys[3].clone()]).unwrap(), true).is_ok());
+    assert_eq!(store.get("vault").unwrap().keys.len(), 1);
+    assert!(store.remove("vault").is_some());
+
+    let mut sealed = store.encrypt("pw").unwrap();
+    let last = sealed.len() - 1;
+    sealed[last] ^= 1;
+    assert!(Keystore::decrypt(&sealed, "pw").is_err());
+}
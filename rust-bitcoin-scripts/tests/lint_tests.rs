@@ -0,0 +1,38 @@
+use bitcoin_scripts::lint::{lint, LintFinding};
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+fn keys() -> Vec<PublicKey> {
+    let secp = secp256k1::Secp256k1::new();
+    [5u8, 6, 7, 8].iter()
+        .map(|b| PublicKey::from_private_key(&secp, &PrivateKey::new(secp256k1::SecretKey::from_slice(&[*b; 32]).unwrap(), Network::Regtest)))
+        .collect()
+}
+
+#[test]
+fn test_clean_vaults_have_no_findings() {
+    let k = keys();
+    let wsh = Descriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))", k[3], k[0], k[1], k[2])).unwrap();
+    assert!(lint(&wsh).unwrap().is_empty());
+    let tr = Descriptor::from_str(&format!("tr({},{{pk({}),and_v(v:pk({}),older(10))}})", k[3], k[0], k[1])).unwrap();
+    assert!(lint(&tr).unwrap().is_empty());
+}
+
+#[test]
+fn test_reuse_and_duplicates_are_flagged() {
+    let k = keys();
+    let x = |pk: &PublicKey| pk.inner.x_only_public_key().0;
+    let tr = Descriptor::from_str(&format!(
+        "tr({},{{pk({}),{{and_v(v:pk({}),older(10)),pk({})}}}})",
+        k[2], k[0], k[0], k[2]
+    )).unwrap();
+    let findings = lint(&tr).unwrap();
+    assert!(findings.contains(&LintFinding::KeyPathKeyInLeaf { key: x(&k[2]), leaves: vec![2] }));
+    assert!(findings.contains(&LintFinding::KeyReusedAcrossPaths { key: x(&k[0]), paths: vec![0, 1] }));
+
+    let duplicate = Descriptor::from_str(&format!("tr({},{{pk({}),{{pk({}),pk({})}}}})", k[3], k[0], k[0], k[1])).unwrap();
+    let findings = lint(&duplicate).unwrap();
+    assert!(findings.iter().any(|f| matches!(f, LintFinding::DuplicateLeaf { depths, .. } if depths == &vec![1, 2])));
+    assert!(findings.iter().all(|f| f.to_string().len() > 10));
+}
@@ -0,0 +1,36 @@
+use bitcoin_scripts::locktime::{anti_fee_sniping, reconcile, reconcile_with, LockTimePolicy};
+use bitcoin::absolute::LockTime;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+#[test]
+fn test_anti_fee_sniping_stays_within_a_hundred_blocks_of_the_tip() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let heights: Vec<u32> = (0..1000).map(|_| anti_fee_sniping(800_000, &mut rng).to_consensus_u32()).collect();
+    assert!(heights.iter().all(|h| (799_901..=800_000).contains(h)));
+    let at_tip = heights.iter().filter(|h| **h == 800_000).count();
+    // About nine in ten use the tip itself
+    assert!((850..=950).contains(&at_tip), "{} of 1000 at the tip", at_tip);
+    assert!(anti_fee_sniping(5, &mut rng).to_consensus_u32() <= 5);
+}
+
+#[test]
+fn test_reconcile_with_cltv_requirements() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let policy = LockTimePolicy::AntiFeeSniping;
+
+    // No requirement, or one already met: raised towards the tip
+    for required in [LockTime::ZERO, LockTime::from_height(500).unwrap()] {
+        let lock_time = reconcile_with(required, 1_000, policy, &mut rng).to_consensus_u32();
+        assert!((901..=1_000).contains(&lock_time), "{} from {}", lock_time, required);
+    }
+    // A requirement above the tip is kept (the spend is not final yet)
+    assert_eq!(reconcile_with(LockTime::from_height(1_050).unwrap(), 1_000, policy, &mut rng), LockTime::from_height(1_050).unwrap());
+    // A time lock cannot be combined with a height
+    let time = LockTime::from_time(1_700_000_000).unwrap();
+    assert_eq!(reconcile_with(time, 1_000, policy, &mut rng), time);
+
+    assert_eq!(reconcile(LockTime::ZERO, 1_000, LockTimePolicy::RequiredOnly), LockTime::ZERO);
+    assert_eq!(reconcile(LockTime::from_height(500).unwrap(), 1_000, LockTimePolicy::RequiredOnly), LockTime::from_height(500).unwrap());
+    assert_eq!(LockTimePolicy::default(), LockTimePolicy::AntiFeeSniping);
+}
@@ -0,0 +1,90 @@
+use bitcoin_scripts::manifest::{SignedManifest, VaultManifest};
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use bitcoin::secp256k1::{KeyPair, XOnlyPublicKey};
+use std::str::FromStr;
+
+fn vault_descriptors() -> (Descriptor<PublicKey>, Descriptor<PublicKey>) {
+    let secp = secp256k1::Secp256k1::new();
+    let pubkeys: Vec<PublicKey> = [5u8, 6, 7, 8].iter()
+        .map(|b| PublicKey::from_private_key(&secp, &PrivateKey::new(secp256k1::SecretKey::from_slice(&[*b; 32]).unwrap(), Network::Regtest)))
+        .collect();
+    let wsh = Descriptor::from_str(&format!(
+        "wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))",
+        pubkeys[3], pubkeys[0], pubkeys[1], pubkeys[2]
+    )).unwrap();
+    let tr = Descriptor::from_str(&format!(
+        "tr({},and_v(v:multi_a(2,{},{},{}),older(10)))",
+        pubkeys[3], pubkeys[0], pubkeys[1], pubkeys[2]
+    )).unwrap();
+    (wsh, tr)
+}
+
+fn signer() -> KeyPair {
+    let secp = secp256k1::Secp256k1::new();
+    KeyPair::from_secret_key(&secp, &secp256k1::SecretKey::from_slice(&[9; 32]).unwrap())
+}
+
+fn trusted() -> Vec<XOnlyPublicKey> {
+    vec![signer().x_only_public_key().0]
+}
+
+#[test]
+fn test_manifest_sign_and_verify_round_trip() {
+    let (wsh, tr) = vault_descriptors();
+    for (descriptor, provenance) in [(wsh, None), (tr, Some("hoisted backup key"))] {
+        let manifest = VaultManifest::from_descriptor(&descriptor, Network::Regtest, provenance).unwrap();
+        assert_eq!(manifest.federation_pubkeys.len(), 4);
+        assert!(!manifest.descriptor_checksum.is_empty());
+        let signed = manifest.sign(&signer()).unwrap();
+        let json = signed.to_json().unwrap();
+        let parsed = SignedManifest::from_json(&json).unwrap();
+        parsed.verify(&trusted()).unwrap();
+    }
+}
+
+#[test]
+fn test_manifest_tampering_is_detected() {
+    let (_, tr) = vault_descriptors();
+    let manifest = VaultManifest::from_descriptor(&tr, Network::Regtest, Some("nums")).unwrap();
+    assert_eq!(manifest.scripts.len(), 1);
+    assert_eq!(manifest.scripts[0].kind, "tapleaf");
+
+    let mut signed = manifest.sign(&signer()).unwrap();
+    signed.manifest.address = "bcrt1pattacker".to_string();
+    assert!(signed.verify(&trusted()).is_err(), "altered address must fail verification");
+
+    let mut resigned = signed.manifest.clone().sign(&signer()).unwrap();
+    assert!(resigned.verify(&trusted()).is_err(), "a validly signed but inconsistent manifest must still fail");
+    resigned.manifest = VaultManifest::from_descriptor(&tr, Network::Regtest, Some("nums")).unwrap();
+    assert!(resigned.verify(&trusted()).is_err(), "signature no longer covers the manifest");
+}
+
+#[test]
+fn test_manifest_records_key_path_tweak() {
+    let (wsh, tr) = vault_descriptors();
+    let manifest = VaultManifest::from_descriptor(&tr, Network::Regtest, None).unwrap();
+    let tweak = manifest.internal_key.as_ref().and_then(|k| k.key_path_tweak).unwrap();
+    assert_eq!(tweak.script_pubkey(), tr.script_pubkey());
+    assert!(tweak.merkle_root.is_some());
+    assert!(VaultManifest::from_descriptor(&wsh, Network::Regtest, None).unwrap().internal_key.is_none());
+
+    // A manifest claiming a different tweak no longer matches its descriptor
+    let mut forged = manifest.clone();
+    forged.internal_key.as_mut().unwrap().key_path_tweak.as_mut().unwrap().merkle_root = None;
+    assert!(forged.sign(&signer()).unwrap().verify(&trusted()).is_err());
+}
+
+#[test]
+fn test_manifest_from_an_untrusted_signer_is_rejected() {
+    let (wsh, _) = vault_descriptors();
+    let manifest = VaultManifest::from_descriptor(&wsh, Network::Regtest, None).unwrap();
+    let secp = secp256k1::Secp256k1::new();
+    let attacker = KeyPair::from_secret_key(&secp, &secp256k1::SecretKey::from_slice(&[10; 32]).unwrap());
+    let signed = manifest.sign(&attacker).unwrap();
+    // Well-formed and validly signed, but not by a key the auditor trusts
+    let e = signed.verify(&trusted()).unwrap_err();
+    assert!(e.to_string().contains("not a trusted signer"), "{}", e);
+    assert!(signed.verify(&[]).is_err());
+    signed.verify(&[attacker.x_only_public_key().0, trusted()[0]]).unwrap();
+}
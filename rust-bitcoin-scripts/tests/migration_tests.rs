@@ -0,0 +1,110 @@
+use bitcoin_scripts::amount::from_rpc;
+use bitcoin_scripts::test_setup::{BitcoinRPC, FUNDING_LABEL};
+use bitcoin_scripts::migration::{wsh_to_tr, KeyPath, NUMS_INTERNAL_KEY};
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use serde_json::json;
+use std::str::FromStr;
+use std::time::Duration;
+use bitcoin::{OutPoint, Sequence, absolute::LockTime, Amount};
+use bitcoin::consensus::encode::serialize_hex;
+
+fn fixed_keys() -> (Vec<PrivateKey>, Vec<PublicKey>) {
+    let secp = secp256k1::Secp256k1::new();
+    let privkeys: Vec<PrivateKey> = [5u8, 6, 7, 8].iter()
+        .map(|b| PrivateKey::new(secp256k1::SecretKey::from_slice(&[*b; 32]).unwrap(), Network::Regtest))
+        .collect();
+    let pubkeys = privkeys.iter().map(|pk| PublicKey::from_private_key(&secp, pk)).collect();
+    (privkeys, pubkeys)
+}
+
+#[test]
+fn test_migrate_csv_vault_hoists_backup_key() {
+    let (_, pubkeys) = fixed_keys();
+    let descriptor_str = format!(
+        "wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))",
+        pubkeys[3], pubkeys[0], pubkeys[1], pubkeys[2]
+    );
+    let old: Descriptor<PublicKey> = Descriptor::from_str(&descriptor_str).unwrap();
+    let migration = wsh_to_tr(&old, Network::Regtest).unwrap();
+    println!("Old descriptor: {}", migration.old_descriptor);
+    println!("New descriptor: {}", migration.new_descriptor);
+    println!("New address: {}", migration.new_address);
+    assert_eq!(migration.key_path, KeyPath::Hoisted(pubkeys[3]));
+    assert!(migration.new_address.starts_with("bcrt1p"), "migrated address should be taproot");
+    assert!(migration.new_descriptor.to_string().contains("multi_a(2,"));
+    assert!(migration.verify().unwrap());
+}
+
+#[test]
+fn test_migrate_without_key_branch_uses_nums_key() {
+    let (_, pubkeys) = fixed_keys();
+    let descriptor_str = format!("wsh(and_v(v:multi(2,{},{},{}),older(10)))", pubkeys[0], pubkeys[1], pubkeys[2]);
+    let old: Descriptor<PublicKey> = Descriptor::from_str(&descriptor_str).unwrap();
+    let migration = wsh_to_tr(&old, Network::Regtest).unwrap();
+    assert_eq!(migration.key_path, KeyPath::Unspendable(PublicKey::from_str(NUMS_INTERNAL_KEY).unwrap()));
+    assert!(migration.verify().unwrap());
+}
+
+#[test]
+fn test_migrate_rewrites_every_multi_fragment() {
+    let (_, pubkeys) = fixed_keys();
+    let descriptor_str = format!(
+        "wsh(or_d(multi(1,{},{}),and_v(v:multi(2,{},{}),older(10))))",
+        pubkeys[3], pubkeys[0], pubkeys[1], pubkeys[2]
+    );
+    let old: Descriptor<PublicKey> = Descriptor::from_str(&descriptor_str).unwrap();
+    let migration = wsh_to_tr(&old, Network::Regtest).unwrap();
+    let new = migration.new_descriptor.to_string();
+    assert!(new.contains("or_d(multi_a(1,") && new.contains("v:multi_a(2,"), "{}", new);
+    assert!(!new.contains("(multi(") && !new.contains(":multi("), "{}", new);
+    assert!(migration.verify().unwrap());
+}
+
+#[test]
+fn test_migrate_rejects_non_wsh() {
+    let (_, pubkeys) = fixed_keys();
+    let old: Descriptor<PublicKey> = Descriptor::from_str(&format!("wpkh({})", pubkeys[0])).unwrap();
+    assert!(wsh_to_tr(&old, Network::Regtest).is_err());
+}
+
+#[tokio::test]
+async fn test_migration_spend_via_backup_path() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("testwallet").await;
+    let _ = rpc.load_wallet("testwallet").await;
+    rpc.ensure_keypool(5).await.unwrap();
+
+    let (privkeys, pubkeys) = fixed_keys();
+    let descriptor_str = format!(
+        "wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))",
+        pubkeys[3], pubkeys[0], pubkeys[1], pubkeys[2]
+    );
+    let old: Descriptor<PublicKey> = Descriptor::from_str(&descriptor_str).unwrap();
+    let old_address = old.address(Network::Regtest).unwrap();
+    let migration = wsh_to_tr(&old, Network::Regtest).unwrap();
+
+    // Fund the old vault
+    let funding_address = rpc.new_labeled_address(FUNDING_LABEL).await.unwrap();
+    let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
+    let txid = rpc.send_to_address(&old_address.to_string(), Amount::from_sat(10_000_000)).await.unwrap();
+    let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
+    let raw_tx_details = rpc.call_rpc("getrawtransaction", json!([txid, true])).await.unwrap();
+    let vout = raw_tx_details["vout"].as_array().unwrap()
+        .iter()
+        .position(|output| {
+            output["scriptPubKey"]["address"].as_str().unwrap() == old_address.to_string()
+        })
+        .expect("Old vault output not found in transaction");
+    let amount = from_rpc(&raw_tx_details["vout"][vout]["value"]).unwrap();
+
+    // Move the funds into the taproot vault using the backup key
+    let outpoint = OutPoint::new(bitcoin::Txid::from_str(&txid).unwrap(), vout as u32);
+    let mut tx = migration.build_spend(outpoint, amount, Amount::from_sat(100_000), Sequence(0xfffffffd), LockTime::ZERO).unwrap();
+    migration.sign_spend(&mut tx, 0, amount, &[privkeys[3]]).unwrap();
+    rpc.send_raw_transaction(&serialize_hex(&tx)).await.unwrap();
+    let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
+
+    let spent = rpc.wait_for_confirmations(&tx.txid(), 1, Duration::from_secs(30)).await.expect("Migration spend not confirmed");
+    assert_eq!(spent.vout[0].script_pubkey.address.as_deref(), Some(migration.new_address.as_str()));
+}
@@ -0,0 +1,86 @@
+use bitcoin_scripts::manifest::{SignedManifest, VaultManifest};
+use bitcoin_scripts::migration::NUMS_INTERNAL_KEY;
+use bitcoin_scripts::nums::{unspendable_internal_key, NumsProof};
+use bitcoin_scripts::templates::{build, TemplateParams};
+use bitcoin_scripts::weak_keys::KeyPolicy;
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use bitcoin::secp256k1::KeyPair;
+use std::str::FromStr;
+
+fn keys() -> Vec<PublicKey> {
+    let secp = secp256k1::Secp256k1::new();
+    [5u8, 6, 7, 8, 9].iter()
+        .map(|b| PublicKey::from_private_key(&secp, &PrivateKey::new(secp256k1::SecretKey::from_slice(&[*b; 32]).unwrap(), Network::Regtest)))
+        .collect()
+}
+
+fn params() -> TemplateParams {
+    let keys = keys();
+    TemplateParams {
+        internal_key: keys[3],
+        federation: keys[0..3].to_vec(),
+        threshold: 2,
+        federation_csv: 10,
+        recovery_key: Some(keys[4]),
+        recovery_csv: 1000,
+        key_policy: KeyPolicy::allow(&keys),
+    }
+}
+
+fn signer() -> KeyPair {
+    let secp = secp256k1::Secp256k1::new();
+    KeyPair::from_secret_key(&secp, &secp256k1::SecretKey::from_slice(&[9; 32]).unwrap())
+}
+
+#[test]
+fn test_bip341_h_matches_the_migration_key() {
+    let h = PublicKey::from_str(NUMS_INTERNAL_KEY).unwrap().inner.x_only_public_key().0;
+    assert_eq!(NumsProof::Bip341H.key().unwrap(), h);
+    NumsProof::Bip341H.verify(&h).unwrap();
+}
+
+#[test]
+fn test_tagged_derivation_is_deterministic_and_checked() {
+    let (key, proof) = NumsProof::derive("wrapyield vault 7").unwrap();
+    assert_eq!(NumsProof::derive("wrapyield vault 7").unwrap(), (key, proof.clone()));
+    assert_ne!(NumsProof::derive("wrapyield vault 8").unwrap().0, key);
+    proof.verify(&key).unwrap();
+    assert!(proof.verify(&NumsProof::Bip341H.key().unwrap()).is_err());
+    assert!(NumsProof::derive("").is_err());
+
+    // Only the first counter that lands on the curve is accepted
+    let NumsProof::Tagged { tag, counter } = proof else { panic!("expected a tagged proof") };
+    for later in counter + 1..counter + 8 {
+        assert!(NumsProof::Tagged { tag: tag.clone(), counter: later }.key().is_err());
+    }
+}
+
+#[test]
+fn test_manifest_proves_key_path_unspendable() {
+    let (params, proof) = params().without_key_path("wrapyield vault 7").unwrap();
+    let descriptor = build(2, &params).unwrap();
+    let Descriptor::Tr(tr) = &descriptor else { panic!("expected tr") };
+    assert_eq!(*tr.internal_key(), unspendable_internal_key("wrapyield vault 7").unwrap().0);
+
+    let manifest = VaultManifest::from_descriptor(&descriptor, Network::Regtest, Some("nums")).unwrap()
+        .with_nums_proof(proof.clone()).unwrap();
+    assert!(!manifest.federation_pubkeys.contains(&params.internal_key.to_string()));
+    assert_eq!(manifest.verify_key_path_unspendable().unwrap(), &proof);
+
+    let signed = manifest.sign(&signer()).unwrap();
+    let parsed = SignedManifest::from_json(&signed.to_json().unwrap()).unwrap();
+    parsed.verify(&[signer().x_only_public_key().0]).unwrap();
+    assert!(signed.to_json().unwrap().contains("\"scheme\": \"tagged\""));
+
+    // A proof for another tag does not verify, even when re-signed
+    let mut forged = parsed.manifest.clone();
+    forged.internal_key.as_mut().unwrap().nums_proof = Some(NumsProof::derive("other vault").unwrap().1);
+    assert!(forged.verify_key_path_unspendable().is_err());
+    assert!(forged.sign(&signer()).unwrap().verify(&[signer().x_only_public_key().0]).is_err());
+
+    // A vault with a real key path has nothing to prove
+    let keyed = VaultManifest::from_descriptor(&build(2, &self::params()).unwrap(), Network::Regtest, Some("hoisted")).unwrap();
+    assert!(keyed.verify_key_path_unspendable().is_err());
+    assert!(keyed.with_nums_proof(proof).is_err());
+}
@@ -0,0 +1,64 @@
+use bitcoin_scripts::op_return::{commit, decode, decode_with, encode, Commitment, DataPolicy, Decoded};
+use bitcoin::{ScriptBuf, TxOut};
+
+fn recipient(value: u64) -> TxOut {
+    TxOut { value, script_pubkey: ScriptBuf::from_bytes(vec![0x00, 0x14].into_iter().chain([7u8; 20]).collect()) }
+}
+
+#[test]
+fn test_small_payload_fits_one_standard_output() {
+    let payload = vec![0xab; 75];
+    let outputs = encode(&payload, &DataPolicy::default()).unwrap();
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0].value, 0);
+    // OP_RETURN OP_PUSHDATA1 <80 bytes>: the 83 bytes `-datacarriersize` allows
+    assert_eq!(outputs[0].script_pubkey.len(), 83);
+    assert_eq!(decode(&outputs).unwrap(), Decoded::Payload(payload));
+    assert_eq!(decode(&encode(&[], &DataPolicy::default()).unwrap()).unwrap(), Decoded::Payload(vec![]));
+}
+
+#[test]
+fn test_large_payload_is_chunked_when_policy_allows() {
+    let payload: Vec<u8> = (0..=255u8).cycle().take(400).collect();
+    let policy = DataPolicy { max_payload: 80, multiple_outputs: true };
+    let chunks = encode(&payload, &policy).unwrap();
+    assert_eq!(chunks.len(), 6);
+
+    // Recipients interleaved with the data outputs do not disturb decoding
+    let mut outputs = vec![recipient(50_000)];
+    for chunk in chunks.iter().cloned() {
+        outputs.push(chunk);
+        outputs.push(recipient(1_000));
+    }
+    assert_eq!(decode(&outputs).unwrap(), Decoded::Payload(payload.clone()));
+
+    let mut reordered = chunks.clone();
+    reordered.swap(1, 2);
+    assert!(decode(&reordered).is_err());
+    assert!(decode(&chunks[..5]).is_err());
+}
+
+#[test]
+fn test_large_payload_is_committed_under_default_policy() {
+    let payload = vec![0x42; 500];
+    let outputs = encode(&payload, &DataPolicy::default()).unwrap();
+    assert_eq!(outputs, vec![commit(&payload).unwrap()]);
+    match decode(&outputs).unwrap() {
+        Decoded::Commitment(c) => {
+            assert_eq!(c, Commitment::to(&payload));
+            assert_eq!(c.len, 500);
+        }
+        other => panic!("expected a commitment, got {:?}", other),
+    }
+    assert_eq!(decode_with(&outputs, Some(&payload)).unwrap(), payload);
+    assert!(decode_with(&outputs, Some(&[0x42; 499])).is_err());
+    assert!(decode_with(&outputs, None).is_err());
+}
+
+#[test]
+fn test_foreign_outputs_are_ignored_and_bad_policies_rejected() {
+    let foreign = TxOut { value: 0, script_pubkey: ScriptBuf::from_bytes(vec![0x6a, 0x03, 1, 2, 3]) };
+    assert!(decode(&[foreign, recipient(1)]).is_err());
+    assert!(encode(b"data", &DataPolicy { max_payload: 5, multiple_outputs: true }).is_err());
+    assert!(encode(&[0; 100], &DataPolicy { max_payload: 20, multiple_outputs: false }).is_err());
+}
@@ -0,0 +1,63 @@
+use bitcoin_scripts::amount::deduct_fee_for;
+use bitcoin_scripts::outputs::{classify, dust_limit, is_p2a, op_return_output, p2a_output, p2a_script, witness_program_script, OutputType};
+use bitcoin_scripts::rpc_types::ScriptPubKeyInfo;
+use bitcoin::address::WitnessVersion;
+use bitcoin::{Address, Amount, Network, ScriptBuf};
+use std::str::FromStr;
+
+#[test]
+fn test_p2a_is_recognized_and_relayable() {
+    let script = p2a_script();
+    assert_eq!(script.as_bytes(), &[0x51, 0x02, 0x4e, 0x73]);
+    assert!(is_p2a(&script));
+    assert_eq!(classify(&script), OutputType::P2a);
+    assert_eq!(classify(&script).to_string(), "anchor");
+    assert_eq!(dust_limit(&script), Amount::from_sat(240));
+    assert_eq!(p2a_output(Amount::ZERO).value, 0);
+    // The well-known regtest anchor address
+    assert_eq!(Address::from_script(&script, Network::Regtest).unwrap().to_string(), "bcrt1pfeesnyr2tx");
+    assert!(deduct_fee_for(Amount::from_sat(1_000), Amount::from_sat(800), &script).is_err());
+}
+
+#[test]
+fn test_classify_standard_and_unknown_outputs() {
+    let zeros = |n: usize| "00".repeat(n);
+    let cases = [
+        (format!("76a914{}88ac", zeros(20)), OutputType::P2pkh),
+        (format!("a914{}87", zeros(20)), OutputType::P2sh),
+        (format!("0014{}", zeros(20)), OutputType::P2wpkh),
+        (format!("0020{}", zeros(32)), OutputType::P2wsh),
+        (format!("5120{}", zeros(32)), OutputType::P2tr),
+        ("6a03010203".to_string(), OutputType::OpReturn),
+        (format!("5210{}", zeros(16)), OutputType::WitnessUnknown { version: 2, program_len: 16 }),
+        ("51020000".to_string(), OutputType::WitnessUnknown { version: 1, program_len: 2 }),
+        (format!("0010{}", zeros(16)), OutputType::NonStandard),
+        ("ac".to_string(), OutputType::NonStandard),
+    ];
+    for (hex, expected) in cases {
+        let script = ScriptBuf::from_bytes(hex::decode(&hex).unwrap());
+        assert_eq!(classify(&script), expected, "{}", hex);
+    }
+}
+
+#[test]
+fn test_builders_for_data_and_future_witness_versions() {
+    let data = op_return_output(b"hello").unwrap();
+    assert_eq!((data.value, classify(&data.script_pubkey)), (0, OutputType::OpReturn));
+    assert_eq!(dust_limit(&data.script_pubkey), Amount::ZERO);
+
+    let v2 = witness_program_script(WitnessVersion::V2, &[7; 32]).unwrap();
+    assert_eq!(classify(&v2), OutputType::WitnessUnknown { version: 2, program_len: 32 });
+    // Witness outputs share one dust limit, whatever their version
+    assert_eq!(dust_limit(&v2), dust_limit(&witness_program_script(WitnessVersion::V1, &[7; 32]).unwrap()));
+    assert!(Address::from_script(&v2, Network::Regtest).is_ok());
+    assert!(witness_program_script(WitnessVersion::V2, &[7; 41]).is_err());
+    assert!(witness_program_script(WitnessVersion::V0, &[7; 16]).is_err());
+}
+
+#[test]
+fn test_decoded_script_pubkey_classifies_from_hex() {
+    let info: ScriptPubKeyInfo = serde_json::from_value(serde_json::json!({"hex": "51024e73", "type": "nonstandard"})).unwrap();
+    assert_eq!(info.output_type(), OutputType::P2a);
+    assert!(Address::from_str("bcrt1pfeesnyr2tx").is_ok());
+}
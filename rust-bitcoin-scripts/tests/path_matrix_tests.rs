@@ -0,0 +1,91 @@
+use bitcoin_scripts::path_matrix::{self, PathOutcome};
+use bitcoin_scripts::spend::SpendPath;
+use bitcoin_scripts::templates::{build, TemplateParams};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::timelock_csv::csv_vault_descriptor;
+use bitcoin_scripts::flows::mine;
+use bitcoin_scripts::weak_keys::KeyPolicy;
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+fn keys(bytes: &[u8]) -> Vec<PrivateKey> {
+    bytes.iter().map(|b| PrivateKey::new(secp256k1::SecretKey::from_slice(&[*b; 32]).unwrap(), Network::Regtest)).collect()
+}
+
+fn public(keys: &[PrivateKey]) -> Vec<PublicKey> {
+    let secp = secp256k1::Secp256k1::new();
+    keys.iter().map(|k| k.public_key(&secp)).collect()
+}
+
+/// Template v2: key path, 2-of-3 federation leaf after 3 blocks, recovery leaf after 5
+fn template(keys: &[PrivateKey]) -> Descriptor<PublicKey> {
+    let k = public(keys);
+    let params = TemplateParams {
+        internal_key: k[3],
+        federation: k[..3].to_vec(),
+        threshold: 2,
+        federation_csv: 3,
+        recovery_key: Some(k[4]),
+        recovery_csv: 5,
+        key_policy: KeyPolicy::allow(&k),
+    };
+    build(2, &params).unwrap()
+}
+
+#[test]
+fn test_cases_cover_key_path_leaves_and_signer_subsets() {
+    let keys = keys(&[111, 112, 113, 114, 115]);
+    let k = public(&keys);
+    let cases = path_matrix::cases(&template(&keys)).unwrap();
+    assert_eq!(cases.len(), 1 + 3 + 1);
+    assert_eq!(cases[0].path, SpendPath::TaprootKey);
+    assert_eq!(cases[0].keys, [k[3]].into());
+    let federation: Vec<_> = cases.iter().filter(|c| c.older == 3).collect();
+    assert_eq!(federation.len(), 3);
+    assert!(federation.iter().all(|c| c.keys.len() == 2 && !c.keys.contains(&k[4])));
+    let recovery = cases.iter().find(|c| c.older == 5).unwrap();
+    assert_eq!(recovery.keys, [k[4]].into());
+    assert!(matches!(recovery.path, SpendPath::TaprootLeaf { .. }));
+}
+
+#[test]
+fn test_cases_split_or_d_arms_of_segwit_descriptors() {
+    let keys = keys(&[116, 117, 118, 119]);
+    let k = public(&keys);
+    let cases = path_matrix::cases(&csv_vault_descriptor(k[3], &k[..3], 2, 10).unwrap()).unwrap();
+    assert_eq!(cases.len(), 1 + 3);
+    assert!(cases.iter().all(|c| c.path == SpendPath::Script));
+    assert!(cases.iter().any(|c| c.keys == [k[3]].into() && c.older == 0));
+    assert_eq!(cases.iter().filter(|c| c.older == 10 && c.keys.len() == 2).count(), 3);
+
+    // Hash locks and timestamps are listed but need more than keys and blocks
+    let hashed: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:sha256({}),after(1700000000))))", k[0], "11".repeat(32))).unwrap();
+    let cases = path_matrix::cases(&hashed).unwrap();
+    assert_eq!(cases.len(), 2);
+    assert!(cases.iter().any(|c| c.preimage && c.after == 1_700_000_000 && c.keys.is_empty()));
+}
+
+#[tokio::test]
+async fn test_every_template_path_spends_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("path_matrix_wallet").await;
+    let _ = rpc.load_wallet("path_matrix_wallet").await;
+    let rpc = rpc.with_wallet("path_matrix_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let tr_keys = keys(&[121, 122, 123, 124, 125]);
+    let wsh_keys = keys(&[126, 127, 128, 129]);
+    let w = public(&wsh_keys);
+    for (descriptor, keys) in [(template(&tr_keys), tr_keys.clone()), (csv_vault_descriptor(w[3], &w[..3], 2, 4).unwrap(), wsh_keys.clone())] {
+        let results = path_matrix::run(&rpc, &descriptor, &keys).await.unwrap();
+        for result in &results {
+            assert!(matches!(result.outcome, PathOutcome::Spent { .. }), "{}: {:?}", result.case, result.outcome);
+        }
+    }
+
+    // Without the internal key the key path is skipped, the leaves still run
+    let results = path_matrix::run(&rpc, &template(&tr_keys), &[tr_keys[0], tr_keys[1], tr_keys[2], tr_keys[4]]).await.unwrap();
+    assert!(matches!(results[0].outcome, PathOutcome::Skipped(_)));
+    assert!(results[1..].iter().all(|r| matches!(r.outcome, PathOutcome::Spent { .. })));
+}
@@ -0,0 +1,89 @@
+use bitcoin_scripts::addresses::AddressManager;
+use bitcoin_scripts::inspect;
+use bitcoin_scripts::provenance::{template_params, AddressOrigin, ProvenanceIndex};
+use bitcoin_scripts::templates::{self, TemplateParams};
+use bitcoin_scripts::weak_keys::KeyPolicy;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{Address, Network, PrivateKey, PublicKey};
+use std::collections::BTreeMap;
+
+/// BIP86 account 0 of the "abandon ... about" mnemonic
+const BIP86_XPUB: &str = "[73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*";
+
+fn params() -> TemplateParams {
+    let secp = Secp256k1::new();
+    let keys: Vec<PublicKey> = [5u8, 6, 7, 8, 9].iter()
+        .map(|b| PrivateKey::new(SecretKey::from_slice(&[*b; 32]).unwrap(), Network::Regtest).public_key(&secp))
+        .collect();
+    TemplateParams {
+        internal_key: keys[3],
+        federation: keys[0..3].to_vec(),
+        threshold: 2,
+        federation_csv: 10,
+        recovery_key: Some(keys[4]),
+        recovery_csv: 1000,
+        key_policy: KeyPolicy::allow(&keys),
+    }
+}
+
+fn unchecked(address: &str) -> Address<NetworkUnchecked> {
+    address.parse().unwrap()
+}
+
+#[test]
+fn test_lookup_template_and_manager_addresses() {
+    let mut index = ProvenanceIndex::default();
+    let vault = AddressOrigin::from_template(2, &params(), Network::Regtest).unwrap();
+    assert_eq!(vault.template, "template-v2");
+    assert_eq!(vault.params["threshold"], "2");
+    assert_eq!(vault.params, template_params(&params()));
+    assert_eq!(vault.index, None);
+    assert_eq!(vault.script_pubkey, templates::build(2, &params()).unwrap().script_pubkey());
+    assert!(index.record(vault.clone()));
+    assert!(!index.record(vault.clone()));
+
+    let manager = AddressManager::new(inspect::parse(&format!("tr({})", BIP86_XPUB)).unwrap(), Network::Bitcoin).unwrap();
+    let mut deposit = BTreeMap::new();
+    deposit.insert("account".to_string(), "0".to_string());
+    assert_eq!(index.record_manager(&manager, "bip86-deposit", &deposit, 0..5).unwrap(), 5);
+    assert_eq!(index.len(), 6);
+
+    // BIP86 test vector: m/86'/0'/0'/0/0
+    let found = index.lookup(&unchecked("bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"));
+    assert_eq!(found.len(), 1);
+    assert_eq!((found[0].template.as_str(), found[0].index), ("bip86-deposit", Some(0)));
+    assert_eq!(found[0].address, manager.address(0).unwrap().to_string());
+    let text = found[0].to_string();
+    assert!(text.contains("index: 0") && text.contains("account: 0"), "{}", text);
+
+    // The same script encoded for another network still resolves
+    let mainnet = Address::from_script(&vault.script_pubkey, Network::Bitcoin).unwrap().to_string();
+    assert_eq!(index.lookup(&unchecked(&mainnet)), &[vault.clone()]);
+    assert!(index.lookup(&unchecked(&manager.address(5).unwrap().to_string())).is_empty());
+
+    // Who can spend it: the federation after 10 blocks, or the recovery key after 1000
+    let report = vault.report().unwrap();
+    assert!(report.paths.iter().any(|c| c.older == 10 && c.keys.len() == 2));
+    assert!(report.paths.iter().any(|c| c.older == 1000 && c.keys.contains(&params().recovery_key.unwrap())));
+}
+
+#[test]
+fn test_index_file_round_trip() {
+    let path = std::env::temp_dir().join(format!("wrapyield-provenance-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(ProvenanceIndex::load(&path).unwrap(), None);
+
+    let descriptor = inspect::parse(&format!("wpkh({})", BIP86_XPUB)).unwrap();
+    let first = AddressOrigin::derived("descriptor", BTreeMap::new(), &descriptor, 3, Network::Regtest).unwrap();
+    let second = AddressOrigin::from_template(1, &params(), Network::Regtest).unwrap();
+    ProvenanceIndex::append(&path, first.clone()).unwrap();
+    ProvenanceIndex::append(&path, second).unwrap();
+    let index = ProvenanceIndex::append(&path, first.clone()).unwrap();
+    assert_eq!(index.len(), 2);
+
+    let loaded = ProvenanceIndex::load(&path).unwrap().unwrap();
+    assert_eq!(loaded, index);
+    assert_eq!(loaded.lookup_script(&first.script_pubkey), &[first]);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,149 @@
+use bitcoin_scripts::test_setup::{BitcoinRPC, SendOptions};
+use bitcoin_scripts::psbt;
+use bitcoin::consensus::encode::serialize_hex;
+
+#[tokio::test]
+async fn test_wallet_process_psbt_round_trip() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("psbt_wallet").await;
+    let _ = rpc.load_wallet("psbt_wallet").await;
+    let rpc = rpc.with_wallet("psbt_wallet");
+    let funding_address = rpc.get_new_address().await.unwrap();
+    let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
+
+    let destination = rpc.get_new_address().await.unwrap();
+    let funded = rpc.wallet_create_funded_psbt(vec![], &[(destination, Amount::from_sat(30_000_000))], &SendOptions::default()).await.unwrap();
+    let ours = psbt::from_base64(&funded.psbt).unwrap();
+    assert_eq!(psbt::to_base64(&ours), funded.psbt, "base64 round trip must be lossless");
+
+    // Core signs its inputs but leaves finalization to us
+    let merged = psbt::process_with_wallet(&rpc, ours).await.unwrap();
+    assert!(merged.inputs.iter().all(|input| !input.partial_sigs.is_empty()), "wallet signatures missing after merge");
+    let tx = psbt::finalize(merged).unwrap();
+    let txid = rpc.send_raw_transaction(&serialize_hex(&tx)).await.unwrap();
+    let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
+    assert_eq!(txid, tx.txid().to_string());
+}
+
+use bitcoin_scripts::differential::local_verdict;
+use bitcoin_scripts::psbt::SpendableUtxo;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{Amount, Network, OutPoint, PrivateKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+mod common;
+use common::{key, pk};
+
+fn descriptor(template: &str) -> Descriptor<DefiniteDescriptorKey> {
+    let s = template.replace('A', &pk(11).to_string()).replace('B', &pk(12).to_string()).replace('C', &pk(13).to_string());
+    Descriptor::from_str(&s).unwrap()
+}
+
+fn funding_tx(descriptor: &Descriptor<DefiniteDescriptorKey>) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+        output: vec![TxOut { value: 100_000, script_pubkey: descriptor.script_pubkey() }],
+    }
+}
+
+/// Create, sign with `signers`, finalize and check the spend with the local interpreter
+fn spend(template: &str, signers: &[u8], sequence: Sequence, lock_time: LockTime) -> (Psbt, usize) {
+    let descriptor = descriptor(template);
+    let prev_tx = funding_tx(&descriptor);
+    let utxo = SpendableUtxo { outpoint: OutPoint::new(prev_tx.txid(), 0), txout: prev_tx.output[0].clone(), prev_tx: Some(prev_tx.clone()), sequence };
+    let outputs = vec![TxOut { value: 90_000, script_pubkey: descriptor.script_pubkey() }];
+    let mut unsigned = psbt::create(&descriptor, &[utxo], outputs, lock_time).unwrap();
+    let keys: Vec<PrivateKey> = signers.iter().map(|b| key(*b)).collect();
+    let added = psbt::sign(&mut unsigned, &keys).unwrap();
+    let tx = psbt::finalize(unsigned.clone()).unwrap();
+    let verdict = local_verdict(&tx, &prev_tx.output);
+    assert!(verdict.accepted(), "{}: {:?}", template, verdict.rejection);
+    (unsigned, added)
+}
+
+#[test]
+fn test_create_sign_finalize_for_descriptor_patterns() {
+    let (signed, added) = spend("sh(multi(2,A,B))", &[11, 12], Sequence::MAX, LockTime::ZERO);
+    assert_eq!(added, 2);
+    assert!(signed.inputs[0].redeem_script.is_some() && signed.inputs[0].non_witness_utxo.is_some());
+    assert!(signed.inputs[0].witness_utxo.is_none(), "legacy inputs carry the full previous transaction");
+
+    let (signed, added) = spend("wsh(or_d(pk(C),and_v(v:multi(2,A,B),older(10))))", &[11, 12], Sequence(10), LockTime::ZERO);
+    assert_eq!(added, 2);
+    assert!(signed.inputs[0].witness_script.is_some() && signed.inputs[0].witness_utxo.is_some());
+    assert_eq!(signed.inputs[0].bip32_derivation.len(), 3);
+
+    spend("sh(wsh(multi(1,A,B)))", &[12], Sequence::MAX, LockTime::ZERO);
+    spend("wpkh(A)", &[11], Sequence::MAX, LockTime::ZERO);
+
+    let (signed, added) = spend("tr(A,{pk(B),and_v(v:pk(C),after(100))})", &[11], Sequence::MAX, LockTime::ZERO);
+    assert_eq!(added, 1);
+    assert!(signed.inputs[0].tap_key_sig.is_some());
+    assert_eq!(signed.inputs[0].tap_scripts.len(), 2);
+
+    let (signed, added) = spend("tr(A,{pk(B),and_v(v:pk(C),after(100))})", &[13], Sequence::ENABLE_LOCKTIME_NO_RBF, LockTime::from_height(100).unwrap());
+    assert_eq!(added, 1);
+    assert!(signed.inputs[0].tap_key_sig.is_none());
+    assert_eq!(signed.inputs[0].tap_script_sigs.len(), 1);
+}
+
+#[test]
+fn test_create_rejects_inconsistent_utxos() {
+    let legacy = descriptor("sh(multi(2,A,B))");
+    let prev_tx = funding_tx(&legacy);
+    let without_prev = SpendableUtxo::new(OutPoint::new(prev_tx.txid(), 0), prev_tx.output[0].clone());
+    assert!(psbt::create(&legacy, &[without_prev.clone()], vec![], LockTime::ZERO).is_err(), "legacy input without prev_tx");
+
+    let other = descriptor("wpkh(C)");
+    assert!(psbt::create(&other, &[without_prev], vec![], LockTime::ZERO).is_err(), "descriptor does not match the spent output");
+}
+
+async fn fund(rpc: &BitcoinRPC, descriptor: &Descriptor<DefiniteDescriptorKey>, miner: &str) -> SpendableUtxo {
+    let address = descriptor.address(Network::Regtest).unwrap().to_string();
+    let txid = rpc.send_to_address(&address, Amount::from_sat(10_000_000)).await.unwrap();
+    rpc.generate_to_address(1, miner).await.unwrap();
+    let hex = rpc.call_rpc("getrawtransaction", serde_json::json!([txid, false])).await.unwrap();
+    let prev_tx: Transaction = bitcoin::consensus::encode::deserialize(&hex::decode(hex.as_str().unwrap()).unwrap()).unwrap();
+    let vout = prev_tx.output.iter().position(|o| o.script_pubkey == descriptor.script_pubkey()).unwrap();
+    let mut utxo = SpendableUtxo::new(OutPoint::new(prev_tx.txid(), vout as u32), prev_tx.output[vout].clone());
+    utxo.prev_tx = Some(prev_tx);
+    utxo
+}
+
+#[tokio::test]
+async fn test_mixed_legacy_and_taproot_psbt_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("psbt_local_wallet").await;
+    let _ = rpc.load_wallet("psbt_local_wallet").await;
+    let rpc = rpc.with_wallet("psbt_local_wallet");
+    let miner = rpc.get_new_address().await.unwrap();
+    rpc.generate_to_address(101, &miner).await.unwrap();
+
+    let legacy = descriptor("sh(multi(2,A,B))");
+    let tr = descriptor("tr(A,{pk(B),and_v(v:pk(C),after(100))})");
+    let utxos = [fund(&rpc, &legacy, &miner).await, fund(&rpc, &tr, &miner).await];
+
+    let destination = bitcoin::Address::from_str(&miner).unwrap().require_network(Network::Regtest).unwrap();
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: utxos.iter().map(|u| TxIn { previous_output: u.outpoint, script_sig: ScriptBuf::new(), sequence: u.sequence, witness: Witness::new() }).collect(),
+        output: vec![TxOut { value: 19_990_000, script_pubkey: destination.script_pubkey() }],
+    };
+    let mut unsigned = Psbt::from_unsigned_tx(tx).unwrap();
+    psbt::update_input(&mut unsigned, 0, &legacy, &utxos[0]).unwrap();
+    psbt::update_input(&mut unsigned, 1, &tr, &utxos[1]).unwrap();
+    // Two ECDSA signatures for the multisig, the key-path signature and one for the pk(B) leaf
+    assert_eq!(psbt::sign(&mut unsigned, &[key(11), key(12)]).unwrap(), 4);
+
+    let tx = psbt::finalize(unsigned).unwrap();
+    let txid = rpc.send_raw_transaction(&serialize_hex(&tx)).await.unwrap();
+    rpc.generate_to_address(1, &miner).await.unwrap();
+    assert_eq!(txid, tx.txid().to_string());
+}
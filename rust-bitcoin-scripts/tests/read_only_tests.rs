@@ -0,0 +1,97 @@
+use bitcoin_scripts::psbt::{self, SpendableUtxo};
+use bitcoin_scripts::read_only::{self, ReadOnlyError};
+use bitcoin_scripts::test_setup::{BitcoinRPC, SendOptions};
+use bitcoin::absolute::LockTime;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{Amount, Network, OutPoint, PrivateKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use serde_json::json;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+// The mode is process-wide; tests that switch it run one at a time
+static MODE: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_only_broadcast_and_signing_calls_are_intercepted() {
+    assert!(read_only::intercepts("sendrawtransaction", &json!(["00"])));
+    assert!(read_only::intercepts("sendtoaddress", &json!(["addr", 1.0])));
+    assert!(read_only::intercepts("walletprocesspsbt", &json!(["cHNidP8="])));
+    assert!(read_only::intercepts("walletprocesspsbt", &json!(["cHNidP8=", true])));
+    assert!(!read_only::intercepts("walletprocesspsbt", &json!(["cHNidP8=", false])));
+    assert!(!read_only::intercepts("getblockcount", &json!([])));
+    assert!(!read_only::intercepts("testmempoolaccept", &json!([["00"]])));
+}
+
+#[test]
+fn test_simulated_results() {
+    let _mode = MODE.lock().unwrap_or_else(|e| e.into_inner());
+    read_only::take_simulated();
+    let accepted = json!([{ "txid": "aa", "allowed": true }]);
+    assert_eq!(read_only::simulate("sendrawtransaction", &json!(["00"]), Some(&accepted)).unwrap(), json!("aa"));
+    let rejected = json!([{ "txid": "bb", "allowed": false, "reject-reason": "non-final" }]);
+    let err = read_only::simulate("sendrawtransaction", &json!(["00"]), Some(&rejected)).unwrap_err();
+    assert!(err.to_string().contains("non-final"));
+    let processed = read_only::simulate("walletprocesspsbt", &json!(["cHNidP8="]), None).unwrap();
+    assert_eq!(processed, json!({ "psbt": "cHNidP8=", "complete": false }));
+    let err = read_only::simulate("sendtoaddress", &json!(["addr", 1.0]), None).unwrap_err();
+    assert_eq!(err.downcast_ref::<ReadOnlyError>(), Some(&ReadOnlyError::NotSimulated { operation: "sendtoaddress".to_string() }));
+
+    let log: Vec<String> = read_only::take_simulated().into_iter().map(|op| op.operation).collect();
+    assert_eq!(log, vec!["sendrawtransaction", "sendrawtransaction", "walletprocesspsbt", "sendtoaddress"]);
+}
+
+#[test]
+fn test_local_signing_is_counted_but_not_applied() {
+    let _mode = MODE.lock().unwrap_or_else(|e| e.into_inner());
+    let key = PrivateKey::new(SecretKey::from_slice(&[11; 32]).unwrap(), Network::Regtest);
+    let descriptor: Descriptor<DefiniteDescriptorKey> = Descriptor::from_str(&format!("wpkh({})", key.public_key(&Secp256k1::new()))).unwrap();
+    let prev_tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+        output: vec![TxOut { value: 100_000, script_pubkey: descriptor.script_pubkey() }],
+    };
+    let utxo = SpendableUtxo::new(OutPoint::new(prev_tx.txid(), 0), prev_tx.output[0].clone());
+    let outputs = vec![TxOut { value: 90_000, script_pubkey: descriptor.script_pubkey() }];
+    let mut unsigned = psbt::create(&descriptor, &[utxo], outputs, LockTime::ZERO).unwrap();
+
+    read_only::enable();
+    let added = psbt::sign(&mut unsigned, &[key]);
+    read_only::disable();
+    assert_eq!(added.unwrap(), 1);
+    assert!(unsigned.inputs[0].partial_sigs.is_empty());
+    assert_eq!(read_only::take_simulated().len(), 1);
+
+    assert_eq!(psbt::sign(&mut unsigned, &[key]).unwrap(), 1);
+    assert_eq!(unsigned.inputs[0].partial_sigs.len(), 1);
+}
+
+#[tokio::test]
+#[allow(clippy::await_holding_lock)] // only serializes against the synchronous tests above
+async fn test_read_only_never_reaches_the_mempool() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("read_only_wallet").await;
+    let _ = rpc.load_wallet("read_only_wallet").await;
+    let rpc = rpc.with_wallet("read_only_wallet");
+    let address = rpc.get_new_address().await.unwrap();
+    rpc.generate_to_address(101, &address).await.unwrap();
+    let funded = rpc.wallet_create_funded_psbt(vec![], &[(address.clone(), Amount::from_sat(50_000_000))], &SendOptions::default()).await.unwrap();
+    let signed = psbt::process_with_wallet(&rpc, psbt::from_base64(&funded.psbt).unwrap()).await.unwrap();
+    let tx = psbt::finalize(signed).unwrap();
+
+    let _mode = MODE.lock().unwrap_or_else(|e| e.into_inner());
+    read_only::enable();
+    let simulated = rpc.send_raw_transaction(&serialize_hex(&tx)).await;
+    let send = rpc.send_to_address(&address, Amount::from_sat(10_000_000)).await;
+    let balance = rpc.get_balance().await;
+    read_only::disable();
+
+    assert_eq!(simulated.unwrap(), tx.txid().to_string());
+    assert!(send.is_err());
+    assert!(balance.is_ok(), "reads still go through");
+    assert!(rpc.call_rpc("getmempoolentry", json!([tx.txid().to_string()])).await.is_err(), "transaction must not be broadcast");
+}
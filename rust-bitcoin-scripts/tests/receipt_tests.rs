@@ -0,0 +1,55 @@
+use bitcoin_scripts::receipt::{BlockRef, SpendHistory, SpendReceipt};
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::{Amount, BlockHash, FeeRate, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+
+fn tx(previous: u8) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![
+            TxIn { previous_output: OutPoint::new(Txid::from_byte_array([previous; 32]), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::from_slice(&[vec![1u8; 72], vec![2u8; 33]]) },
+            TxIn { previous_output: OutPoint::new(Txid::from_byte_array([previous; 32]), 1), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() },
+        ],
+        output: vec![TxOut { value: 10_000, script_pubkey: ScriptBuf::new() }],
+    }
+}
+
+#[test]
+fn test_receipt_records_ids_fee_rate_and_witness_sizes() {
+    let tx = tx(1);
+    let fee = Amount::from_sat(tx.vsize() as u64 * 4);
+    let receipt = SpendReceipt::new(&tx, fee, "script");
+    assert_eq!((receipt.txid, receipt.wtxid), (tx.txid(), tx.wtxid()));
+    assert_ne!(receipt.txid.to_byte_array(), receipt.wtxid.to_byte_array());
+    // 1 + (1 + 72) + (1 + 33), and the empty witness
+    assert_eq!(receipt.witness_sizes, vec![108, 1]);
+    // vsize rounds weight up, so the rate is at least 4 sat/vB and only just
+    assert!(receipt.feerate >= FeeRate::from_sat_per_vb(4).unwrap());
+    assert!(receipt.feerate.to_sat_per_kwu() < 1_010);
+    assert!(receipt.broadcast_time > 0);
+    assert_eq!(receipt.block, None);
+}
+
+#[test]
+fn test_history_records_and_persists_receipts() {
+    let path = std::env::temp_dir().join(format!("wrapyield-history-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(SpendHistory::load(&path).unwrap(), None);
+
+    let first = SpendReceipt::new(&tx(1), Amount::from_sat(500), "script");
+    let second = SpendReceipt::new(&tx(2), Amount::from_sat(700), "taproot_key");
+    SpendHistory::append(&path, first.clone()).unwrap();
+    let history = SpendHistory::append(&path, second.clone()).unwrap();
+    assert_eq!(history.pending().count(), 2);
+
+    // Recording the same transaction again replaces its receipt
+    let mut confirmed = first.clone();
+    confirmed.block = Some(BlockRef { hash: BlockHash::all_zeros(), height: 120 });
+    let history = SpendHistory::append(&path, confirmed.clone()).unwrap();
+    assert_eq!(history.receipts.len(), 2);
+    assert_eq!(history.get(&first.txid), Some(&confirmed));
+    assert_eq!(history.pending().map(|r| r.txid).collect::<Vec<_>>(), vec![second.txid]);
+    assert_eq!(SpendHistory::load(&path).unwrap(), Some(history));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,93 @@
+use bitcoin_scripts::flows::{fund_descriptor, mine};
+use bitcoin_scripts::recovery::{PresignedRecovery, RecoveryTemplate};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::verify::verify_spend;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::{Amount, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+mod common;
+use common::{key, pk};
+
+/// Federation 2-of-2 any time, recovery key 93 after `csv` blocks; cold storage is key 94
+fn template(amount: Amount, csv: u16) -> RecoveryTemplate {
+    let vault: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(or_d(multi(2,{},{}),and_v(v:pk({}),older({}))))", pk(91), pk(92), pk(93), csv)).unwrap();
+    let cold: Descriptor<PublicKey> = Descriptor::from_str(&format!("wpkh({})", pk(94))).unwrap();
+    RecoveryTemplate::new(&vault, &cold, amount, csv, Amount::from_sat(1_000)).unwrap()
+}
+
+fn funding(template: &RecoveryTemplate, previous: u8) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::from_byte_array([previous; 32]), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+        output: vec![template.prevout().unwrap()],
+    }
+}
+
+#[test]
+fn test_presigned_recovery_is_rebound_to_a_replaced_deposit() {
+    let template = template(Amount::from_sat(200_000), 10);
+    let expected = funding(&template, 1);
+    let recovery = template.presign(template.find_deposit(&expected).unwrap(), &[key(93)]).unwrap();
+    assert_eq!(recovery.tx.input[0].sequence, Sequence::from_height(10));
+    assert_eq!(recovery.tx.output[0].value, 199_000);
+    assert_eq!(recovery.tx.output[0].script_pubkey, template.cold().unwrap().script_pubkey());
+    assert_eq!(recovery.valid_from(100), 110);
+    assert_eq!(recovery.for_deposit(&expected, &[]).unwrap(), recovery);
+
+    // The deposit confirmed under another txid: the old signature does not verify, the re-signed one does
+    let replaced = funding(&template, 2);
+    let prevouts = [template.prevout().unwrap()];
+    let mut stale = recovery.tx.clone();
+    stale.input[0].previous_output = template.find_deposit(&replaced).unwrap();
+    assert!(verify_spend(&stale, &prevouts).is_err());
+    let rebound = recovery.for_deposit(&replaced, &[key(93)]).unwrap();
+    assert_eq!(rebound.deposit.txid, replaced.txid());
+    assert_eq!(verify_spend(&rebound.tx, &prevouts), Ok(()));
+
+    // Nothing to sign with, or a deposit of a different amount
+    assert!(recovery.for_deposit(&replaced, &[]).is_err());
+    let mut other = replaced.clone();
+    other.output[0].value = 150_000;
+    assert!(recovery.for_deposit(&other, &[key(93)]).is_err());
+}
+
+#[test]
+fn test_presigned_recovery_save_and_load() {
+    let template = template(Amount::from_sat(50_000), 6);
+    let recovery = template.presign(template.find_deposit(&funding(&template, 3)).unwrap(), &[key(93)]).unwrap();
+    let path = std::env::temp_dir().join(format!("wrapyield-recovery-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(PresignedRecovery::load(&path).unwrap(), None);
+    recovery.save(&path).unwrap();
+    assert_eq!(PresignedRecovery::load(&path).unwrap(), Some(recovery));
+    std::fs::remove_file(&path).unwrap();
+
+    // Fee leaves a dust output
+    assert!(RecoveryTemplate::new(&template.vault().unwrap(), &template.cold().unwrap(), Amount::from_sat(1_200), 6, Amount::from_sat(1_000)).is_err());
+}
+
+#[tokio::test]
+async fn test_presigned_recovery_rebroadcasts_after_delay() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("recovery_wallet").await;
+    let _ = rpc.load_wallet("recovery_wallet").await;
+    let rpc = rpc.with_wallet("recovery_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let template = template(Amount::from_sat(300_000), 5);
+    let funded = fund_descriptor(&rpc, &template.vault().unwrap(), template.amount).await.unwrap();
+    let recovery = template.presign(funded.outpoint(), &[key(93)]).unwrap();
+
+    mine(&rpc, 3).await.unwrap();
+    assert!(recovery.rebroadcast(&rpc).await.is_err(), "CSV delay has not passed");
+    let tip = mine(&rpc, 1).await.unwrap();
+    assert_eq!(tip + 1, recovery.valid_from(funded.height));
+    let mut receipt = recovery.rebroadcast(&rpc).await.unwrap();
+    assert_eq!((receipt.txid, receipt.path_used.as_str()), (recovery.txid(), "recovery"));
+    let height = mine(&rpc, 1).await.unwrap();
+    assert_eq!(receipt.refresh(&rpc).await.unwrap().map(|b| b.height), Some(height));
+}
@@ -0,0 +1,75 @@
+use bitcoin_scripts::read_only;
+use bitcoin_scripts::replay::{self, ReplayReport};
+use bitcoin_scripts::scanner::{BlockScanner, ScanEvent};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::hashes::Hash;
+use bitcoin::{Address, Amount, Network, OutPoint, ScriptBuf, Txid};
+use serde_json::json;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+fn deposit(vout: u32, height: u64) -> ScanEvent {
+    ScanEvent::Deposit { outpoint: OutPoint::new(Txid::all_zeros(), vout), value_sats: 1_000, script_pubkey: ScriptBuf::new(), height }
+}
+
+#[test]
+fn test_compare_reports_missed_and_unexpected_events() {
+    let report = ReplayReport {
+        from: 1,
+        to: 2,
+        blocks: 2,
+        transactions: 10,
+        events: vec![deposit(0, 1), deposit(1, 2)],
+        fetch_time: Duration::from_millis(600),
+        classify_time: Duration::from_millis(400),
+        slowest_block: Some((2, Duration::from_millis(700))),
+        fallback_lookups: 0,
+    };
+    assert_eq!(report.deposits(), 2);
+    assert!((report.blocks_per_sec() - 2.0).abs() < 1e-9);
+    assert!((report.transactions_per_sec() - 10.0).abs() < 1e-9);
+    assert!(report.compare(&[deposit(0, 1), deposit(1, 2)]).is_empty());
+
+    let diff = report.compare(&[deposit(0, 1), deposit(2, 2)]);
+    assert_eq!(diff.missed, vec![deposit(2, 2)]);
+    assert_eq!(diff.unexpected, vec![deposit(1, 2)]);
+}
+
+#[tokio::test]
+async fn test_replay_detects_historical_deposit_and_spend() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("replay_wallet").await;
+    let _ = rpc.load_wallet("replay_wallet").await;
+    let rpc = rpc.with_wallet("replay_wallet");
+    let funding_address = rpc.get_new_address().await.unwrap();
+    rpc.generate_to_address(101, &funding_address).await.unwrap();
+    let watched_address = rpc.get_new_address().await.unwrap();
+    let watched_script = Address::from_str(&watched_address).unwrap().require_network(Network::Regtest).unwrap().script_pubkey();
+
+    let from = rpc.call_rpc("getblockcount", json!([])).await.unwrap().as_u64().unwrap() + 1;
+    let txid = rpc.send_to_address(&watched_address, Amount::from_sat(20_000_000)).await.unwrap();
+    rpc.generate_to_address(1, &funding_address).await.unwrap();
+    let vout = rpc.call_rpc("gettransaction", json!([txid])).await.unwrap()["details"].as_array().unwrap()
+        .iter().find(|d| d["address"] == json!(watched_address)).unwrap()["vout"].as_u64().unwrap() as u32;
+    let outpoint = OutPoint::new(Txid::from_str(&txid).unwrap(), vout);
+    let mut outputs = HashMap::new();
+    outputs.insert(funding_address.clone(), Amount::from_sat(19_900_000));
+    let raw = rpc.create_raw_transaction(vec![json!({"txid": txid, "vout": vout})], outputs).await.unwrap();
+    let signed = rpc.call_rpc("signrawtransactionwithwallet", json!([raw])).await.unwrap();
+    let spend_txid = Txid::from_str(&rpc.send_raw_transaction(signed["hex"].as_str().unwrap()).await.unwrap()).unwrap();
+    rpc.generate_to_address(2, &funding_address).await.unwrap();
+
+    let mut scanner = BlockScanner::new();
+    scanner.watch_script(watched_script.clone());
+    let report = replay::replay(&rpc, &mut scanner, from, from + 2).await.unwrap();
+    assert!(!read_only::is_enabled(), "read-only mode must be restored");
+    assert_eq!(report.blocks, 3);
+    assert!(report.transactions >= 5);
+    let expected = vec![
+        ScanEvent::Deposit { outpoint, value_sats: 20_000_000, script_pubkey: watched_script, height: from },
+        ScanEvent::Spend { outpoint, value_sats: 20_000_000, spending_txid: spend_txid, input_index: 0, height: from + 1 },
+    ];
+    assert!(report.compare(&expected).is_empty(), "{:?}", report.compare(&expected));
+    println!("{:.1} blocks/s over {} blocks", report.blocks_per_sec(), report.blocks);
+}
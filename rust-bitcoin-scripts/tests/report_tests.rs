@@ -0,0 +1,25 @@
+use bitcoin_scripts::report::{AmountReport, FixedPrice, NoPrice, format_btc};
+use bitcoin::Amount;
+
+#[test]
+fn test_format_btc_is_exact() {
+    assert_eq!(format_btc(Amount::from_sat(0)), "0.00000000");
+    assert_eq!(format_btc(Amount::from_sat(9_990_000)), "0.09990000");
+    assert_eq!(format_btc(Amount::from_sat(2_100_000_000_000_000)), "21000000.00000000");
+}
+
+#[test]
+fn test_amount_report_with_and_without_price() {
+    let amount = Amount::from_sat(10_000_000);
+    let report = AmountReport::new(amount, &NoPrice);
+    assert_eq!(report.to_string(), "10000000 sats (0.10000000 BTC)");
+    assert!(report.fiat.is_none());
+
+    let price = FixedPrice { currency: "USD".to_string(), price: 60_000.0 };
+    let report = AmountReport::new(amount, &price);
+    assert_eq!(report.to_string(), "10000000 sats (0.10000000 BTC, ~6000.00 USD)");
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["sats"], 10_000_000);
+    assert_eq!(json["btc"], "0.10000000");
+    assert_eq!(json["fiat"]["currency"], "USD");
+}
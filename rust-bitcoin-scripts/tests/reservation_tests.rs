@@ -0,0 +1,68 @@
+use bitcoin_scripts::reservation::UtxoReservations;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::{OutPoint, Txid};
+use bitcoin::hashes::Hash;
+use serde_json::json;
+use std::str::FromStr;
+use std::time::Duration;
+
+fn outpoint(vout: u32) -> OutPoint {
+    OutPoint::new(Txid::all_zeros(), vout)
+}
+
+#[test]
+fn test_reservation_is_all_or_nothing_and_expires() {
+    let reservations = UtxoReservations::new(Duration::from_secs(60));
+    reservations.reserve("batch-a", &[outpoint(0), outpoint(1)], None).unwrap();
+    let conflict = reservations.reserve("batch-b", &[outpoint(2), outpoint(1)], None).unwrap_err();
+    assert_eq!(conflict.outpoint, outpoint(1));
+    assert_eq!(reservations.reserved_by(&outpoint(2)), None, "failed reservation holds nothing");
+
+    reservations.release("batch-b", &[outpoint(0)]);
+    assert_eq!(reservations.reserved_by(&outpoint(0)).as_deref(), Some("batch-a"), "only the owner releases");
+    reservations.release("batch-a", &[outpoint(0)]);
+    assert_eq!(reservations.reserved_by(&outpoint(0)), None);
+
+    reservations.reserve("batch-c", &[outpoint(5)], Some(Duration::from_millis(20))).unwrap();
+    std::thread::sleep(Duration::from_millis(40));
+    reservations.reserve("batch-d", &[outpoint(5)], None).unwrap();
+}
+
+#[test]
+fn test_concurrent_builders_never_share_utxos() {
+    let reservations = UtxoReservations::new(Duration::from_secs(60));
+    let candidates: Vec<(OutPoint, u64)> = (0..10).map(|v| (outpoint(v), 10_000)).collect();
+    let handles: Vec<_> = (0..8).map(|i| {
+        let reservations = reservations.clone();
+        let candidates = candidates.clone();
+        std::thread::spawn(move || reservations.select(&format!("builder-{}", i), &candidates, 20_000).ok())
+    }).collect();
+    let selections: Vec<Vec<(OutPoint, u64)>> = handles.into_iter().filter_map(|h| h.join().unwrap()).collect();
+    assert_eq!(selections.len(), 5, "10 UTXOs cover exactly five 2-input batches");
+    let mut all: Vec<OutPoint> = selections.iter().flatten().map(|(o, _)| *o).collect();
+    all.sort();
+    all.dedup();
+    assert_eq!(all.len(), 10);
+}
+
+#[tokio::test]
+async fn test_reservation_locks_on_node() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("testwallet").await;
+    let _ = rpc.load_wallet("testwallet").await;
+    let address = rpc.get_new_address().await.unwrap();
+    let _ = rpc.generate_to_address(101, &address).await.unwrap();
+
+    let unspent = rpc.call_rpc("listunspent", json!([1])).await.unwrap();
+    let utxo = &unspent.as_array().unwrap()[0];
+    let op = OutPoint::new(Txid::from_str(utxo["txid"].as_str().unwrap()).unwrap(), utxo["vout"].as_u64().unwrap() as u32);
+
+    let reservations = UtxoReservations::new(Duration::from_secs(60));
+    reservations.reserve_on_node(&rpc, "batch-a", &[op]).await.unwrap();
+    let locked = rpc.call_rpc("listlockunspent", json!([])).await.unwrap();
+    assert!(locked.as_array().unwrap().iter().any(|l| l["txid"] == op.txid.to_string() && l["vout"] == op.vout));
+
+    reservations.release_on_node(&rpc, "batch-a", &[op]).await.unwrap();
+    let locked = rpc.call_rpc("listlockunspent", json!([])).await.unwrap();
+    assert!(!locked.as_array().unwrap().iter().any(|l| l["txid"] == op.txid.to_string() && l["vout"] == op.vout));
+}
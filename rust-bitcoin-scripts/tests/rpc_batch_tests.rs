@@ -0,0 +1,78 @@
+use bitcoin_scripts::flows::{mine, mine_censoring};
+use bitcoin_scripts::test_setup::{BitcoinRPC, RpcConfig};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Answer one request with `body` and hand back what was posted
+async fn serve_once(listener: TcpListener, body: &'static str) -> String {
+    let (mut socket, _) = listener.accept().await.unwrap();
+    let mut buf = vec![0u8; 65536];
+    let n = socket.read(&mut buf).await.unwrap();
+    let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+    socket.write_all(response.as_bytes()).await.unwrap();
+    String::from_utf8_lossy(&buf[..n]).to_string()
+}
+
+#[tokio::test]
+async fn test_batch_matches_responses_by_id() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let rpc = BitcoinRPC::with_config(RpcConfig { url: format!("http://{}", listener.local_addr().unwrap()), ..RpcConfig::default() }).unwrap();
+    // Out of order, with the middle call failing
+    let server = tokio::spawn(serve_once(listener, r#"[{"id":2,"result":812,"error":null},{"id":0,"result":"00ff","error":null},{"id":1,"result":null,"error":{"code":-8,"message":"Block height out of range"}}]"#));
+
+    let results = rpc.call_batch(vec![("getblockhash", json!([0])), ("getblockhash", json!([999])), ("getblockcount", json!([]))]).await.unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap(), &json!("00ff"));
+    assert!(results[1].as_ref().unwrap_err().to_string().contains("out of range"));
+    assert_eq!(results[2].as_ref().unwrap(), &json!(812));
+
+    let request: Value = serde_json::from_str(server.await.unwrap().split("\r\n\r\n").nth(1).unwrap()).unwrap();
+    let calls = request.as_array().unwrap();
+    assert_eq!(calls.len(), 3);
+    assert_eq!((calls[1]["id"].clone(), calls[1]["method"].clone(), calls[1]["params"].clone()), (json!(1), json!("getblockhash"), json!([999])));
+    assert!(rpc.call_batch(Vec::new()).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_batch_with_a_missing_response_fails() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let rpc = BitcoinRPC::with_config(RpcConfig { url: format!("http://{}", listener.local_addr().unwrap()), ..RpcConfig::default() }).unwrap();
+    let server = tokio::spawn(serve_once(listener, r#"[{"id":0,"result":1,"error":null}]"#));
+    let err = rpc.call_batch(vec![("getblockcount", json!([])), ("getbestblockhash", json!([]))]).await.unwrap_err();
+    assert!(err.to_string().contains("getbestblockhash"));
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_batched_keys_blocks_and_scans_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("batch_wallet").await;
+    let _ = rpc.load_wallet("batch_wallet").await;
+    let rpc = rpc.with_wallet("batch_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let keys = rpc.generate_keys(5).await.unwrap();
+    assert_eq!(keys.len(), 5);
+    assert!(keys.iter().all(|k| k.compressed));
+    assert!((1..5).all(|i| !keys[..i].contains(&keys[i])));
+
+    let start = rpc.get_block_count().await.unwrap();
+    let tip = mine(&rpc, 3).await.unwrap();
+    assert_eq!(tip, start + 3);
+    assert_eq!(mine_censoring(&rpc, 2).await.unwrap(), tip + 2);
+    let hashes = rpc.get_block_hashes(start + 1..=tip).await.unwrap();
+    assert_eq!(hashes.len(), 3);
+    assert_eq!(hashes[2], rpc.get_block_hash(tip).await.unwrap());
+
+    let address = rpc.get_new_address().await.unwrap();
+    rpc.send_to_address(&address, bitcoin::Amount::from_sat(50_000)).await.unwrap();
+    // Unconfirmed, so only the wallet half of the batched lookup sees it
+    let utxos = rpc.find_utxos_for_address(&address).await.unwrap();
+    assert_eq!(utxos.len(), 1);
+    assert_eq!(utxos[0].confirmations, 0);
+    mine(&rpc, 1).await.unwrap();
+    let utxos = rpc.find_utxos_for_address(&address).await.unwrap();
+    assert_eq!(utxos.len(), 1);
+    assert_eq!(utxos[0].amount, bitcoin::Amount::from_sat(50_000));
+}
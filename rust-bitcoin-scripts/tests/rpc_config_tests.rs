@@ -0,0 +1,98 @@
+use bitcoin_scripts::classic_multisig::create_multisig_on;
+use bitcoin_scripts::test_setup::{default_rpc_port, parse_chain, BitcoinRPC, RpcAuth, RpcConfig};
+use bitcoin_scripts::timelock_cltv::simple_cltv_descriptor_on;
+use base64::Engine;
+use miniscript::bitcoin::Network;
+use std::collections::HashMap;
+
+fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let map: HashMap<String, String> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    move |name| map.get(name).cloned()
+}
+
+#[test]
+fn test_empty_environment_is_local_regtest() {
+    let config = RpcConfig::from_vars(vars(&[])).unwrap();
+    assert_eq!(config, RpcConfig::default());
+    let rpc = BitcoinRPC::with_config(config).unwrap();
+    assert_eq!(rpc.url, "http://localhost:18443");
+    assert_eq!(rpc.auth, base64::engine::general_purpose::STANDARD.encode("bitcoin:localtest"));
+    assert_eq!(rpc.network, Network::Regtest);
+    assert_eq!(rpc.with_wallet("w").network, Network::Regtest);
+}
+
+#[test]
+fn test_network_selects_default_port_and_explicit_url_wins() {
+    let config = RpcConfig::from_vars(vars(&[("WRAPYIELD_NETWORK", "signet")])).unwrap();
+    assert_eq!(config.network, Network::Signet);
+    assert_eq!(config.url, format!("http://localhost:{}", default_rpc_port(Network::Signet)));
+
+    let config = RpcConfig::from_vars(vars(&[
+        ("WRAPYIELD_NETWORK", "testnet"),
+        ("WRAPYIELD_RPC_URL", "http://node.internal:9999"),
+        ("WRAPYIELD_RPC_USER", "alice"),
+        ("WRAPYIELD_RPC_PASSWORD", "secret"),
+    ])).unwrap();
+    assert_eq!(config.url, "http://node.internal:9999");
+    assert_eq!(config.auth, RpcAuth::UserPass { user: "alice".to_string(), password: "secret".to_string() });
+
+    assert!(RpcConfig::from_vars(vars(&[("WRAPYIELD_NETWORK", "moonnet")])).is_err());
+    assert!(RpcConfig::from_vars(vars(&[("WRAPYIELD_RPC_USER", "alice")])).is_err());
+}
+
+#[test]
+fn test_chain_names_include_testnet4() {
+    assert_eq!(parse_chain("main").unwrap(), (Network::Bitcoin, 8332));
+    assert_eq!(parse_chain("test").unwrap(), (Network::Testnet, 18332));
+    assert_eq!(parse_chain("testnet4").unwrap(), (Network::Testnet, 48332));
+    assert_eq!(parse_chain("signet").unwrap(), (Network::Signet, default_rpc_port(Network::Signet)));
+    assert!(parse_chain("testnet5").is_err());
+
+    let config = RpcConfig::from_vars(vars(&[("WRAPYIELD_NETWORK", "testnet4")])).unwrap();
+    assert_eq!((config.network, config.url.as_str()), (Network::Testnet, "http://localhost:48332"));
+}
+
+#[test]
+fn test_cookie_file_auth() {
+    let dir = std::env::temp_dir().join(format!("wrapyield-cookie-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cookie = dir.join(".cookie");
+    std::fs::write(&cookie, "__cookie__:abc123\n").unwrap();
+
+    let config = RpcConfig::from_vars(vars(&[
+        ("WRAPYIELD_RPC_COOKIE", cookie.to_str().unwrap()),
+        ("WRAPYIELD_RPC_USER", "ignored"),
+    ])).unwrap();
+    assert_eq!(config.auth, RpcAuth::CookieFile(cookie.clone()));
+    let rpc = BitcoinRPC::with_config(config).unwrap();
+    assert_eq!(rpc.auth, base64::engine::general_purpose::STANDARD.encode("__cookie__:abc123"));
+
+    std::fs::write(&cookie, "garbage").unwrap();
+    assert!(RpcAuth::CookieFile(cookie.clone()).basic_credentials().is_err());
+    assert!(RpcAuth::CookieFile(dir.join("missing")).basic_credentials().is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_addresses_follow_the_configured_network() {
+    let config = RpcConfig { network: Network::Testnet, ..RpcConfig::default() };
+    let rpc = BitcoinRPC::with_config(config).unwrap();
+
+    let multisig = create_multisig_on(rpc.network).unwrap();
+    assert!(multisig.address.starts_with('2'));
+    assert!(rpc.parse_address(&multisig.address).is_ok());
+    let (_, privkey, _, address) = simple_cltv_descriptor_on(100, rpc.network);
+    assert_eq!(privkey.network, Network::Testnet);
+    assert!(address.starts_with("tb1"));
+
+    let (_, _, _, regtest_address) = simple_cltv_descriptor_on(100, Network::Regtest);
+    assert!(rpc.parse_address(&regtest_address).is_err());
+}
+
+#[tokio::test]
+async fn test_check_network_against_the_node() {
+    let rpc = BitcoinRPC::new();
+    assert_eq!(rpc.check_network().await.unwrap().chain, "regtest");
+    let signet = BitcoinRPC::with_config(RpcConfig { network: Network::Signet, ..RpcConfig::default() }).unwrap();
+    assert!(signet.check_network().await.unwrap_err().to_string().contains("configured for signet"));
+}
@@ -0,0 +1,40 @@
+use bitcoin_scripts::test_setup::{BitcoinRPC, RpcPoolConfig};
+use futures::future::join_all;
+use serde_json::json;
+use std::time::Instant;
+
+/// Fetch the last `blocks` blocks the way a block scanner would (getblockhash + getblock),
+/// `concurrency` requests at a time, and return blocks per second.
+async fn scan_throughput(rpc: &BitcoinRPC, tip: u64, blocks: u64, concurrency: usize) -> f64 {
+    let heights: Vec<u64> = (tip.saturating_sub(blocks - 1)..=tip).collect();
+    let start = Instant::now();
+    for chunk in heights.chunks(concurrency) {
+        let results = join_all(chunk.iter().map(|h| async move {
+            let hash = rpc.call_rpc("getblockhash", json!([h])).await?;
+            rpc.call_rpc("getblock", json!([hash, 2])).await
+        })).await;
+        for result in results {
+            assert!(result.unwrap()["tx"].is_array());
+        }
+    }
+    heights.len() as f64 / start.elapsed().as_secs_f64()
+}
+
+#[tokio::test]
+async fn test_rpc_pool_throughput() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("testwallet").await;
+    let _ = rpc.load_wallet("testwallet").await;
+    let address = rpc.get_new_address().await.unwrap();
+    let _ = rpc.generate_to_address(200, &address).await.unwrap();
+    let tip = rpc.call_rpc("getblockcount", json!([])).await.unwrap().as_u64().unwrap();
+
+    let serial = scan_throughput(&rpc, tip, 200, 1).await;
+    println!("Serial scan: {:.1} blocks/s", serial);
+
+    let config = RpcPoolConfig { max_idle_per_host: 8, ..RpcPoolConfig::default() };
+    let pooled = BitcoinRPC::new().with_pool_config(&config).unwrap();
+    let concurrent = scan_throughput(&pooled, tip, 200, 8).await;
+    println!("Pooled scan (8 connections): {:.1} blocks/s", concurrent);
+    assert!(concurrent > 0.0 && serial > 0.0);
+}
@@ -0,0 +1,149 @@
+use bitcoin_scripts::test_setup::{BitcoinRPC, CoreError, RetryPolicy, RpcConfig, RpcError, RPC_IN_WARMUP, RPC_VERIFY_ERROR, RPC_VERIFY_REJECTED, RPC_WALLET_ERROR};
+use serde_json::json;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// What the mock node does with one request
+#[derive(Clone, Copy)]
+enum Reply {
+    Json(&'static str),
+    Status(&'static str),
+    Hang,
+}
+
+/// Serve `replies` one connection each and return how many requests arrived
+async fn serve(listener: TcpListener, replies: Vec<Reply>) -> usize {
+    let mut served = 0;
+    for reply in replies {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = vec![0u8; 65536];
+        let _ = socket.read(&mut buf).await.unwrap();
+        served += 1;
+        let response = match reply {
+            Reply::Json(body) => format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body),
+            Reply::Status(status) => format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status),
+            Reply::Hang => {
+                // Hold the connection open without answering, and move on to the next one
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    drop(socket);
+                });
+                continue;
+            }
+        };
+        socket.write_all(response.as_bytes()).await.unwrap();
+    }
+    served
+}
+
+fn fast_policy() -> RetryPolicy {
+    RetryPolicy { max_attempts: 4, initial_backoff: Duration::from_millis(5), max_backoff: Duration::from_millis(20), timeout: Some(Duration::from_millis(100)), slow_timeout: None }
+}
+
+async fn mock(replies: Vec<Reply>) -> (BitcoinRPC, tokio::task::JoinHandle<usize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = RpcConfig { url: format!("http://{}", listener.local_addr().unwrap()), ..RpcConfig::default() };
+    let rpc = BitcoinRPC::with_config(config).unwrap().with_retry_policy(fast_policy());
+    (rpc, tokio::spawn(serve(listener, replies)))
+}
+
+const WARMING_UP: &str = r#"{"result":null,"error":{"code":-28,"message":"Loading block index..."},"id":"rust"}"#;
+
+#[test]
+fn test_backoff_doubles_up_to_the_cap_and_codes_are_classified() {
+    let policy = RetryPolicy { initial_backoff: Duration::from_millis(100), max_backoff: Duration::from_secs(1), ..RetryPolicy::default() };
+    assert_eq!(policy.backoff(1), Duration::from_millis(100));
+    assert_eq!(policy.backoff(3), Duration::from_millis(400));
+    assert_eq!(policy.backoff(5), Duration::from_secs(1));
+    assert_eq!(policy.backoff(40), Duration::from_secs(1));
+    assert_eq!(policy.timeout_for(&["getblock"]), policy.timeout);
+    assert_eq!(policy.timeout_for(&["getblockhash", "scantxoutset"]), policy.slow_timeout);
+    assert_eq!(RetryPolicy::none().max_attempts, 1);
+
+    assert!(RpcError::Node { code: RPC_IN_WARMUP, message: "Loading wallet...".to_string() }.is_retriable());
+    assert!(RpcError::Node { code: -4, message: "Wallet is currently rescanning. Abort existing rescan or wait.".to_string() }.is_retriable());
+    assert!(!RpcError::Node { code: -4, message: "Insufficient funds".to_string() }.is_retriable());
+    assert!(!RpcError::Node { code: RPC_VERIFY_REJECTED, message: "min relay fee not met".to_string() }.is_retriable());
+    assert!(RpcError::Busy.is_retriable());
+}
+
+#[tokio::test]
+async fn test_warmup_and_busy_node_are_retried() {
+    let (rpc, server) = mock(vec![Reply::Json(WARMING_UP), Reply::Status("503 Service Unavailable"), Reply::Json(r#"{"result":812,"error":null,"id":"rust"}"#)]).await;
+    assert_eq!(rpc.call_rpc("getblockcount", json!([])).await.unwrap(), json!(812));
+    assert_eq!(server.await.unwrap(), 3);
+
+    // Still warming up after the last attempt: the node's error comes back
+    let (rpc, server) = mock(vec![Reply::Json(WARMING_UP); 4]).await;
+    let err = rpc.call_rpc("getblockcount", json!([])).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<RpcError>(), Some(&RpcError::Node { code: RPC_IN_WARMUP, message: "Loading block index...".to_string() }));
+    assert_eq!(server.await.unwrap(), 4);
+}
+
+#[tokio::test]
+async fn test_rejections_and_ambiguous_sends_are_not_retried() {
+    let (rpc, server) = mock(vec![Reply::Json(r#"{"result":null,"error":{"code":-26,"message":"min relay fee not met"},"id":"rust"}"#)]).await;
+    let err = rpc.call_rpc("sendrawtransaction", json!(["00"])).await.unwrap_err();
+    assert!(err.to_string().contains("min relay fee not met"));
+    assert_eq!(server.await.unwrap(), 1);
+
+    // A timed out read is simply asked again
+    let (rpc, server) = mock(vec![Reply::Hang, Reply::Json(r#"{"result":"00ff","error":null,"id":"rust"}"#)]).await;
+    assert_eq!(rpc.call_rpc("getbestblockhash", json!([])).await.unwrap(), json!("00ff"));
+    assert_eq!(server.await.unwrap(), 2);
+
+    // A timed out payment may have gone through; sending it again could pay twice
+    let (rpc, server) = mock(vec![Reply::Hang]).await;
+    let err = rpc.call_rpc("sendtoaddress", json!(["bcrt1q", 0.1])).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<RpcError>(), Some(RpcError::Timeout(_))));
+    assert_eq!(server.await.unwrap(), 1);
+}
+
+#[test]
+fn test_well_known_core_errors_are_classified_by_code_and_reason() {
+    let node = |code: i64, message: &str| RpcError::Node { code, message: message.to_string() };
+    let cases = [
+        (node(RPC_WALLET_ERROR, "Wallet file verification failed. Failed to create database path '/w'. Database already exists."), Some(CoreError::WalletExists)),
+        (node(-35, "Wallet \"w\" is already loaded."), Some(CoreError::WalletAlreadyLoaded)),
+        (node(RPC_WALLET_ERROR, "Wallet file verification failed. Refusing to load database. Data file '/w' is already loaded."), Some(CoreError::WalletAlreadyLoaded)),
+        (node(-18, "Requested wallet does not exist or is not loaded"), Some(CoreError::WalletNotFound)),
+        (node(RPC_VERIFY_REJECTED, "non-final"), Some(CoreError::NonFinal)),
+        (node(RPC_VERIFY_REJECTED, "non-BIP68-final"), Some(CoreError::NonFinal)),
+        (node(RPC_VERIFY_REJECTED, "txn-mempool-conflict"), Some(CoreError::MempoolConflict)),
+        (node(RPC_VERIFY_ERROR, "bad-txns-inputs-missingorspent"), Some(CoreError::MissingInputs)),
+        (node(-27, "Transaction outputs already in utxo set"), Some(CoreError::AlreadyKnown)),
+        (node(-5, "No such mempool or blockchain transaction. Use gettransaction for wallet transactions."), Some(CoreError::NotFound)),
+        (node(-8, "Scan already in progress, use action \"abort\" or \"status\""), Some(CoreError::ScanInProgress)),
+        // Same codes, other reasons
+        (node(RPC_VERIFY_REJECTED, "min relay fee not met"), None),
+        (node(RPC_WALLET_ERROR, "Insufficient funds"), None),
+        (node(-5, "Invalid address"), None),
+        (RpcError::Busy, None),
+    ];
+    for (error, expected) in cases {
+        assert_eq!(error.core_error(), expected, "{}", error);
+    }
+    // testmempoolaccept reports the same conditions by reason alone
+    assert_eq!(CoreError::from_reject_reason("non-BIP68-final"), Some(CoreError::NonFinal));
+    assert_eq!(CoreError::from_reject_reason("missing-inputs"), Some(CoreError::MissingInputs));
+    assert_eq!(CoreError::from_reject_reason("mandatory-script-verify-flag-failed (Script failed an OP_EQUALVERIFY operation)"), None);
+    let boxed: Box<dyn std::error::Error> = node(RPC_VERIFY_REJECTED, "non-final").into();
+    assert_eq!(CoreError::of(&*boxed), Some(CoreError::NonFinal));
+    let other: Box<dyn std::error::Error> = "non-final".into();
+    assert_eq!(CoreError::of(&*other), None, "only node errors are classified, never plain text");
+}
+
+#[tokio::test]
+async fn test_existing_and_loaded_wallets_are_not_errors() {
+    let (rpc, server) = mock(vec![
+        Reply::Json(r#"{"result":null,"error":{"code":-4,"message":"Wallet file verification failed. Failed to create database path '/w'. Database already exists."},"id":"rust"}"#),
+        Reply::Json(r#"{"result":null,"error":{"code":-35,"message":"Wallet \"w\" is already loaded."},"id":"rust"}"#),
+        Reply::Json(r#"{"result":null,"error":{"code":-18,"message":"Wallet file not found."},"id":"rust"}"#),
+    ]).await;
+    rpc.create_wallet("w").await.unwrap();
+    rpc.load_wallet("w").await.unwrap();
+    let err = rpc.load_wallet("missing").await.unwrap_err();
+    assert_eq!(CoreError::of(&*err), Some(CoreError::WalletNotFound));
+    assert_eq!(server.await.unwrap(), 3);
+}
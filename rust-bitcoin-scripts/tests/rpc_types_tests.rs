@@ -0,0 +1,102 @@
+use bitcoin_scripts::rpc_types::{BlockchainInfo, GetRawTransactionResult, ListUnspentEntry, SignRawTransactionResult, TestMempoolAcceptResult};
+use bitcoin_scripts::test_setup::{BitcoinRPC, DESTINATION_LABEL, FUNDING_LABEL};
+use bitcoin::{Amount, ScriptBuf, Txid};
+use serde_json::json;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+#[test]
+fn test_core_responses_deserialize() {
+    let info: BlockchainInfo = serde_json::from_value(json!({
+        "chain": "regtest", "blocks": 150, "headers": 150,
+        "bestblockhash": "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+        "difficulty": 4.6e-10, "time": 1700000000, "mediantime": 1699999000, "verificationprogress": 1,
+        "initialblockdownload": false, "chainwork": "00", "size_on_disk": 1000, "pruned": false, "warnings": ""
+    })).unwrap();
+    assert_eq!((info.blocks, info.median_time, info.chain.as_str()), (150, 1699999000, "regtest"));
+
+    let utxo: ListUnspentEntry = serde_json::from_value(json!({
+        "txid": TXID, "vout": 1, "address": "bcrt1qexample", "label": "",
+        "scriptPubKey": "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+        "amount": 0.3, "confirmations": 6, "spendable": true, "solvable": true,
+        "desc": "wpkh([d34db33f/84h/1h/0h/0/1]02aa)#abcd", "parent_descs": [], "safe": true
+    })).unwrap();
+    assert_eq!(utxo.amount, Amount::from_sat(30_000_000));
+    assert_eq!(utxo.outpoint().txid, Txid::from_str(TXID).unwrap());
+    assert!(utxo.script_pubkey.is_v0_p2wpkh());
+
+    let signed: SignRawTransactionResult = serde_json::from_value(json!({
+        "hex": "00", "complete": false,
+        "errors": [{ "txid": TXID, "vout": 0, "witness": [], "scriptSig": "", "sequence": 4294967295u32, "error": "Unable to sign input" }]
+    })).unwrap();
+    assert!(!signed.complete);
+    assert_eq!(signed.errors[0].error, "Unable to sign input");
+
+    let accept: Vec<TestMempoolAcceptResult> = serde_json::from_value(json!([
+        { "txid": TXID, "wtxid": TXID, "allowed": false, "reject-reason": "non-final" }
+    ])).unwrap();
+    assert_eq!(accept[0].reject_reason.as_deref(), Some("non-final"));
+
+    // A missing field is a typed error instead of a panic in the caller
+    assert!(serde_json::from_value::<BlockchainInfo>(json!({ "chain": "regtest" })).is_err());
+}
+
+#[tokio::test]
+async fn test_typed_wrappers_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("typed_rpc_wallet").await;
+    let _ = rpc.load_wallet("typed_rpc_wallet").await;
+    let rpc = rpc.with_wallet("typed_rpc_wallet");
+    assert!(rpc.ensure_keypool(10).await.unwrap() >= 10);
+    let address = rpc.new_labeled_address(FUNDING_LABEL).await.unwrap();
+    rpc.generate_to_address(101, &address).await.unwrap();
+    assert!(rpc.addresses_by_label(FUNDING_LABEL).await.unwrap().contains(&address));
+    assert!(rpc.addresses_by_label("never-used").await.unwrap().is_empty());
+
+    let info = rpc.get_blockchain_info().await.unwrap();
+    assert_eq!(info.chain, "regtest");
+    assert_eq!(rpc.get_block_count().await.unwrap(), info.blocks);
+    assert_eq!(rpc.get_block_hash(info.blocks).await.unwrap(), info.best_block_hash);
+
+    let destination = rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap();
+    assert!(!rpc.addresses_by_label(FUNDING_LABEL).await.unwrap().contains(&destination));
+    let txid = Txid::from_str(&rpc.send_to_address(&destination, Amount::from_sat(25_000_000)).await.unwrap()).unwrap();
+    let raw: GetRawTransactionResult = rpc.get_raw_transaction_verbose(&txid).await.unwrap();
+    assert_eq!(raw.txid, txid);
+    assert_eq!(raw.transaction().unwrap().txid(), txid);
+    let output = raw.output_to_address(&destination).expect("payment output");
+    assert_eq!(output.value, Amount::from_sat(25_000_000));
+    assert!(raw.confirmations.is_none());
+    let entry = rpc.get_mempool_entry(&txid).await.unwrap();
+    assert_eq!(entry.vsize, raw.vsize);
+    assert!(entry.fees.base > Amount::ZERO && entry.fees.ancestor >= entry.fees.base);
+
+    rpc.generate_to_address(1, &address).await.unwrap();
+    let utxo = rpc.list_unspent(1).await.unwrap().into_iter()
+        .find(|u| u.txid == txid && u.vout == output.n).expect("listunspent entry");
+    assert_eq!(utxo.script_pubkey, output.script_pubkey.hex);
+    assert_eq!(utxo.label.as_deref(), Some(DESTINATION_LABEL));
+
+    // Coinbase outputs to the funding address stay apart from the payment
+    let labels = rpc.list_labels().await.unwrap();
+    assert!(labels.iter().any(|l| l == FUNDING_LABEL) && labels.iter().any(|l| l == DESTINATION_LABEL));
+    let destination_utxos = rpc.list_utxos_by_label(DESTINATION_LABEL, 1).await.unwrap();
+    assert!(destination_utxos.iter().any(|u| u.outpoint() == utxo.outpoint()));
+    assert!(rpc.list_utxos_by_label(FUNDING_LABEL, 1).await.unwrap().iter().all(|u| u.label.as_deref() == Some(FUNDING_LABEL)));
+    let destination_balance = rpc.get_balance_by_label(DESTINATION_LABEL, 1).await.unwrap();
+    assert_eq!(destination_balance, destination_utxos.iter().map(|u| u.amount).sum());
+    assert!(destination_balance >= Amount::from_sat(25_000_000));
+    assert_eq!(rpc.get_balance_by_label("never-used", 0).await.unwrap(), Amount::ZERO);
+
+    let mut outputs = HashMap::new();
+    outputs.insert(address.clone(), Amount::from_sat(24_900_000));
+    let unsigned = rpc.create_raw_transaction(vec![json!({ "txid": txid.to_string(), "vout": utxo.vout })], outputs).await.unwrap();
+    let signed = rpc.sign_raw_transaction_with_wallet(&unsigned).await.unwrap();
+    assert!(signed.complete && signed.errors.is_empty());
+    let accept = rpc.test_mempool_accept(&[signed.hex.clone()]).await.unwrap();
+    assert!(accept[0].allowed, "{:?}", accept[0].reject_reason);
+    assert_eq!(accept[0].txid, signed.transaction().unwrap().txid());
+    assert_ne!(signed.transaction().unwrap().output[0].script_pubkey, ScriptBuf::new());
+}
@@ -0,0 +1,148 @@
+use bitcoin_scripts::keystore::KeyEntry;
+use bitcoin_scripts::satisfier::{satisfy_input, satisfy_taproot_input, KeystoreSatisfier};
+use bitcoin_scripts::secret::SigningKey;
+use bitcoin_scripts::spend::{Planner, SpendPath};
+use bitcoin_scripts::timelock_csv::csv_vault_descriptor;
+use bitcoin_scripts::verify::verify_spend;
+use bitcoin_scripts::witness::{build_backup_path_witness, build_multisig_timelock_witness, nested_vault_descriptor, VaultTimelock};
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::taproot::LeafVersion;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{Amount, Network, OutPoint, PrivateKey, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::{Descriptor, Satisfier};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+const AMOUNT: Amount = Amount::from_sat(100_000);
+
+fn keys() -> Vec<PrivateKey> {
+    (5u8..=8).map(|b| PrivateKey::new(SecretKey::from_slice(&[b; 32]).unwrap(), Network::Regtest)).collect()
+}
+
+fn pks() -> Vec<PublicKey> {
+    keys().iter().map(|k| k.public_key(&Secp256k1::new())).collect()
+}
+
+fn entry(descriptor: &Descriptor<PublicKey>, held: &[usize]) -> KeyEntry {
+    let keys = keys();
+    KeyEntry::new(descriptor.clone(), Network::Regtest, held.iter().map(|i| SigningKey::new(keys[*i].inner, Network::Regtest)).collect()).unwrap()
+}
+
+fn spend(sequence: Sequence, lock_time: LockTime) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::from_str(&"11".repeat(32)).unwrap(), 0), script_sig: ScriptBuf::new(), sequence, witness: Witness::new() }],
+        output: vec![TxOut { value: AMOUNT.to_sat() - 1_000, script_pubkey: ScriptBuf::new() }],
+    }
+}
+
+#[test]
+fn test_satisfier_picks_vault_branch_like_manual_builders() {
+    let k = pks();
+    let descriptor = csv_vault_descriptor(k[3], &k[..3], 2, 10).unwrap();
+
+    // Backup key alone
+    let mut tx = spend(Sequence::ENABLE_RBF_NO_LOCKTIME, LockTime::ZERO);
+    let backup = entry(&descriptor, &[3]);
+    let sig = KeystoreSatisfier::new(&backup, &tx, 0, AMOUNT).unwrap().lookup_ecdsa_sig(&k[3]).unwrap();
+    satisfy_input(&backup, &mut tx, 0, AMOUNT, &[]).unwrap();
+    assert_eq!(tx.input[0].witness, build_backup_path_witness(&descriptor, &sig).unwrap());
+    assert!(tx.input[0].script_sig.is_empty());
+
+    // Two signers, only once the input's sequence matures the delay
+    let signers = entry(&descriptor, &[0, 2]);
+    let mut early = spend(Sequence(9), LockTime::ZERO);
+    assert!(satisfy_input(&signers, &mut early, 0, AMOUNT, &[]).is_err());
+    let mut tx = spend(Sequence(10), LockTime::ZERO);
+    let satisfier = KeystoreSatisfier::new(&signers, &tx, 0, AMOUNT).unwrap();
+    let mut expected = vec![k[0], k[2]];
+    expected.sort();
+    assert_eq!(satisfier.signers(), expected);
+    let sigs: HashMap<PublicKey, bitcoin::ecdsa::Signature> = [k[0], k[2]].iter().map(|pk| (*pk, satisfier.lookup_ecdsa_sig(pk).unwrap())).collect();
+    satisfy_input(&signers, &mut tx, 0, AMOUNT, &[]).unwrap();
+    assert_eq!(tx.input[0].witness, build_multisig_timelock_witness(&descriptor, &sigs).unwrap());
+
+    // With the backup key held as well, the cheaper backup branch wins
+    let mut tx = spend(Sequence(10), LockTime::ZERO);
+    satisfy_input(&entry(&descriptor, &[0, 2, 3]), &mut tx, 0, AMOUNT, &[]).unwrap();
+    assert_eq!(tx.input[0].witness.len(), 2);
+}
+
+#[test]
+fn test_satisfier_nested_cltv_vault_and_lock_time() {
+    let k = pks();
+    let descriptor = nested_vault_descriptor(k[3], &k[..3], 2, VaultTimelock::Absolute(500)).unwrap();
+    let signers = entry(&descriptor, &[1, 2]);
+
+    let mut early = spend(Sequence::ENABLE_RBF_NO_LOCKTIME, LockTime::from_height(499).unwrap());
+    assert!(satisfy_input(&signers, &mut early, 0, AMOUNT, &[]).is_err());
+    let mut tx = spend(Sequence::ENABLE_RBF_NO_LOCKTIME, LockTime::from_height(500).unwrap());
+    satisfy_input(&signers, &mut tx, 0, AMOUNT, &[]).unwrap();
+    assert_eq!(tx.input[0].script_sig, descriptor.unsigned_script_sig());
+    assert_eq!(tx.input[0].witness.len(), 5);
+}
+
+#[test]
+fn test_satisfier_preimages_and_unsupported_descriptors() {
+    let k = pks();
+    let preimage = [42u8; 32];
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(and_v(v:pk({}),sha256({})))", k[0], sha256::Hash::hash(&preimage))).unwrap();
+    let held = entry(&descriptor, &[0]);
+    let mut tx = spend(Sequence::ENABLE_RBF_NO_LOCKTIME, LockTime::ZERO);
+    assert!(satisfy_input(&held, &mut tx, 0, AMOUNT, &[[1u8; 32]]).is_err());
+    satisfy_input(&held, &mut tx, 0, AMOUNT, &[[1u8; 32], preimage]).unwrap();
+    assert_eq!(tx.input[0].witness.nth(0).unwrap(), &preimage[..]);
+
+    let tr: Descriptor<PublicKey> = Descriptor::from_str(&format!("tr({})", k[0])).unwrap();
+    assert!(KeystoreSatisfier::new(&entry(&tr, &[0]), &tx, 0, AMOUNT).is_err());
+    assert!(KeystoreSatisfier::new(&held, &tx, 1, AMOUNT).is_err());
+}
+
+#[test]
+fn test_taproot_spend_follows_planned_path() {
+    let k = pks();
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("tr({},{{pk({}),and_v(v:pk({}),older(6))}})", k[0], k[1], k[2])).unwrap();
+    let Descriptor::Tr(tr) = &descriptor else { unreachable!() };
+    let prevouts = vec![TxOut { value: AMOUNT.to_sat(), script_pubkey: descriptor.script_pubkey() }];
+
+    // Internal key held: key path, a single signature
+    let internal = entry(&descriptor, &[0, 1]);
+    let plan = Planner::new([k[0], k[1]], 200, 0, 1).plan(&descriptor).unwrap();
+    assert_eq!(plan.path, SpendPath::TaprootKey);
+    let mut tx = spend(plan.sequence, plan.lock_time);
+    satisfy_taproot_input(&internal, &mut tx, 0, &prevouts, &plan, &[]).unwrap();
+    assert_eq!(tx.input[0].witness.len(), 1);
+    verify_spend(&tx, &prevouts).unwrap();
+
+    // Leaf key only: the leaf it unlocks, with its script and control block
+    let leaf = entry(&descriptor, &[1]);
+    let plan = Planner::new([k[1]], 200, 0, 1).plan(&descriptor).unwrap();
+    let SpendPath::TaprootLeaf { script, .. } = plan.path.clone() else { panic!("{}", plan.path) };
+    let mut tx = spend(plan.sequence, plan.lock_time);
+    satisfy_taproot_input(&leaf, &mut tx, 0, &prevouts, &plan, &[]).unwrap();
+    assert_eq!(KeystoreSatisfier::taproot(&leaf, &tx, 0, &prevouts, &plan).unwrap().signers(), vec![k[1]]);
+    let control_block = tr.spend_info().control_block(&(script.clone(), LeafVersion::TapScript)).unwrap();
+    assert_eq!(tx.input[0].witness.nth(1).unwrap(), script.as_bytes());
+    assert_eq!(tx.input[0].witness.nth(2).unwrap(), &control_block.serialize()[..]);
+    verify_spend(&tx, &prevouts).unwrap();
+    // The plan needs a key this entry does not hold
+    assert!(KeystoreSatisfier::taproot(&entry(&descriptor, &[2]), &tx, 0, &prevouts, &plan).is_err());
+
+    // Timelocked leaf: only once the output has matured, and only with the planned sequence
+    let timelocked = entry(&descriptor, &[2]);
+    assert!(Planner::new([k[2]], 200, 0, 5).plan(&descriptor).is_err());
+    let plan = Planner::new([k[2]], 200, 0, 6).plan(&descriptor).unwrap();
+    assert_eq!(plan.sequence, Sequence::from_height(6));
+    let mut early = spend(Sequence::ENABLE_RBF_NO_LOCKTIME, LockTime::ZERO);
+    assert!(satisfy_taproot_input(&timelocked, &mut early, 0, &prevouts, &plan, &[]).is_err());
+    let mut tx = spend(plan.sequence, plan.lock_time);
+    satisfy_taproot_input(&timelocked, &mut tx, 0, &prevouts, &plan, &[]).unwrap();
+    assert_eq!(tx.input[0].witness.len(), 3);
+    verify_spend(&tx, &prevouts).unwrap();
+
+    // Segwit v0 descriptors go through `satisfy_input`
+    let wsh = csv_vault_descriptor(k[3], &k[..3], 2, 10).unwrap();
+    assert!(KeystoreSatisfier::taproot(&entry(&wsh, &[3]), &tx, 0, &prevouts, &plan).is_err());
+}
@@ -0,0 +1,47 @@
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::scanner::{BlockScanner, ScanEvent};
+use bitcoin::{Address, Amount, Network};
+use serde_json::json;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[tokio::test]
+async fn test_scanner_detects_deposit_and_spend() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("scanner_wallet").await;
+    let _ = rpc.load_wallet("scanner_wallet").await;
+    let rpc = rpc.with_wallet("scanner_wallet");
+    let funding_address = rpc.get_new_address().await.unwrap();
+    let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
+
+    // Watch a wallet address so the wallet can sign the spend for us
+    let watched_address = rpc.get_new_address().await.unwrap();
+    let watched_script = Address::from_str(&watched_address).unwrap().require_network(Network::Regtest).unwrap().script_pubkey();
+    let mut scanner = BlockScanner::new();
+    scanner.watch_script(watched_script.clone());
+
+    let txid = rpc.send_to_address(&watched_address, Amount::from_sat(10_000_000)).await.unwrap();
+    let deposit_block = rpc.generate_to_address(1, &funding_address).await.unwrap();
+    let events = scanner.scan_block(&rpc, &deposit_block[0]).await.unwrap();
+    let deposit = events.iter().find_map(|e| match e {
+        ScanEvent::Deposit { outpoint, value_sats, .. } => Some((*outpoint, *value_sats)),
+        _ => None,
+    }).expect("deposit not detected");
+    assert_eq!(deposit.0.txid.to_string(), txid);
+    assert_eq!(deposit.1, 10_000_000);
+
+    // Spend the deposit back to the funding address
+    let inputs = vec![json!({"txid": txid, "vout": deposit.0.vout})];
+    let mut outputs = HashMap::new();
+    outputs.insert(funding_address.clone(), Amount::from_sat(9_900_000));
+    let raw_tx = rpc.create_raw_transaction(inputs, outputs).await.unwrap();
+    let signed = rpc.call_rpc("signrawtransactionwithwallet", json!([raw_tx])).await.unwrap();
+    let spend_txid = rpc.send_raw_transaction(signed["hex"].as_str().unwrap()).await.unwrap();
+    let spend_block = rpc.generate_to_address(1, &funding_address).await.unwrap();
+    let events = scanner.scan_block(&rpc, &spend_block[0]).await.unwrap();
+    assert!(events.iter().any(|e| matches!(e,
+        ScanEvent::Spend { outpoint, spending_txid, .. } if *outpoint == deposit.0 && spending_txid.to_string() == spend_txid
+    )), "spend not detected: {:?}", events);
+    println!("Prevout support: {:?}, fallback lookups: {}", scanner.prevout_support, scanner.fallback_lookups);
+    assert!(scanner.tracked.is_empty());
+}
@@ -0,0 +1,63 @@
+use bitcoin_scripts::schema::{self, SchemaKind, SchemaError, VaultDescriptorDoc, SpendReceiptDoc, AuditReportDoc, SCHEMA_VERSION};
+
+fn sample_vault() -> VaultDescriptorDoc {
+    VaultDescriptorDoc {
+        descriptor: "wsh(pk(02aaaa))".to_string(),
+        address: "bcrt1qexample".to_string(),
+        network: "regtest".to_string(),
+        script_pubkey_hex: "0020".to_string(),
+        max_satisfaction_weight: None,
+    }
+}
+
+#[test]
+fn test_envelope_round_trip() {
+    let doc = sample_vault();
+    let value = schema::to_value(&doc).unwrap();
+    assert_eq!(value["schema_version"], SCHEMA_VERSION);
+    assert_eq!(value["kind"], "vault_descriptor");
+    assert!(value.get("max_satisfaction_weight").is_none(), "absent optional fields are omitted");
+    let parsed: VaultDescriptorDoc = schema::from_json(&value.to_string()).unwrap();
+    assert_eq!(parsed, doc);
+}
+
+#[test]
+fn test_v1_reader_ignores_added_fields() {
+    // A document from a newer producer that only added fields must still parse
+    let json = r#"{
+        "schema_version": 1,
+        "kind": "spend_receipt",
+        "txid": "00",
+        "fee_sats": 1000,
+        "vsize": 150,
+        "path_used": "backup",
+        "some_future_field": {"nested": true}
+    }"#;
+    let receipt: SpendReceiptDoc = schema::from_json(json).unwrap();
+    assert_eq!(receipt.fee_sats, 1000);
+    assert_eq!(receipt.block_hash, None);
+}
+
+#[test]
+fn test_rejects_wrong_kind_and_newer_version() {
+    let value = schema::to_value(&sample_vault()).unwrap();
+    match schema::from_json::<AuditReportDoc>(&value.to_string()) {
+        Err(SchemaError::WrongKind { expected, .. }) => assert_eq!(expected, "audit_report"),
+        other => panic!("expected WrongKind, got {:?}", other),
+    }
+    let mut newer = value.clone();
+    newer["schema_version"] = serde_json::json!(SCHEMA_VERSION + 1);
+    assert!(matches!(
+        schema::from_json::<VaultDescriptorDoc>(&newer.to_string()),
+        Err(SchemaError::UnsupportedVersion(_))
+    ));
+}
+
+#[test]
+fn test_published_schemas_list_required_fields() {
+    let schema = AuditReportDoc::json_schema();
+    let required: Vec<&str> = schema["required"].as_array().unwrap().iter().map(|v| v.as_str().unwrap()).collect();
+    assert_eq!(required, vec!["schema_version", "kind", "descriptor", "utxo_count", "total_sats", "total_btc"]);
+    assert_eq!(VaultDescriptorDoc::json_schema()["properties"]["kind"]["const"], "vault_descriptor");
+    assert_eq!(SpendReceiptDoc::json_schema()["properties"]["fee_sats"]["type"], "integer");
+}
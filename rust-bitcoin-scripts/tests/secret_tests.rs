@@ -0,0 +1,29 @@
+use bitcoin_scripts::classic_multisig::create_multisig;
+use bitcoin_scripts::secret::{ct_eq, SigningKey};
+use miniscript::bitcoin::{Network, secp256k1};
+
+#[test]
+fn test_secret_bytes_never_appear_in_debug_output() {
+    let info = create_multisig().unwrap();
+    let debug = format!("{:?}", info);
+    for key in &info.private_keys {
+        let secret_hex = hex::encode(key.expose().inner.secret_bytes());
+        assert!(!debug.contains(&secret_hex));
+        assert!(!debug.contains(key.to_wif().as_str()));
+        assert!(debug.contains(&key.public_key.to_string()));
+    }
+    assert!(debug.contains("[REDACTED]"));
+}
+
+#[test]
+fn test_signing_key_round_trip_and_equality() {
+    let key = SigningKey::new(secp256k1::SecretKey::from_slice(&[0x42; 32]).unwrap(), Network::Regtest);
+    let restored = SigningKey::from_wif(&key.to_wif()).unwrap();
+    assert_eq!(key, restored);
+    assert_eq!(key.public_key, restored.public_key);
+    assert_ne!(key, SigningKey::random(Network::Regtest));
+
+    assert!(ct_eq(b"abc", b"abc"));
+    assert!(!ct_eq(b"abc", b"abd"));
+    assert!(!ct_eq(b"abc", b"ab"));
+}
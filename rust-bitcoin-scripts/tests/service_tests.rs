@@ -0,0 +1,75 @@
+use bitcoin_scripts::broadcast::HoldStatus;
+use bitcoin_scripts::scanner::{BlockScanner, ScannerCheckpoint, TrackedOutput};
+use bitcoin_scripts::service::{Service, ServiceConfig, HOLDING_QUEUE_FILE, SCANNER_CHECKPOINT_FILE, SIGNING_ROUNDS_FILE};
+use bitcoin_scripts::signing_round::SigningRound;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, absolute::LockTime};
+use bitcoin::hashes::Hash;
+use std::time::Duration;
+
+fn future_tx() -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: LockTime::from_height(400_000_000).unwrap(),
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 7),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut { value: 10_000, script_pubkey: ScriptBuf::new() }],
+    }
+}
+
+fn config(name: &str) -> ServiceConfig {
+    let dir = std::env::temp_dir().join(format!("wrapyield-service-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = ServiceConfig::new(dir);
+    config.poll_interval = Duration::from_secs(3600);
+    config.grace_period = Duration::from_secs(2);
+    config
+}
+
+#[tokio::test]
+async fn test_shutdown_flushes_state_and_restart_resumes_it() {
+    let config = config("flush");
+    let mut scanner = BlockScanner::new();
+    scanner.watch_script(ScriptBuf::from_bytes(vec![0x51]));
+    scanner.track_outpoint(OutPoint::new(Txid::all_zeros(), 1), TrackedOutput { value_sats: 5_000, script_pubkey: ScriptBuf::from_bytes(vec![0x51]) });
+
+    let (service, _events) = Service::start(BitcoinRPC::new(), scanner, config.clone()).unwrap();
+    let txid = service.broadcaster.hold(future_tx()).await.unwrap();
+    let psbt = Psbt::from_unsigned_tx(future_tx()).unwrap();
+    service.coordinator.open(SigningRound::new("r1", psbt, vec!["alice".to_string(), "bob".to_string()], 2, Duration::from_secs(600))).await.unwrap();
+    let broadcaster = service.broadcaster.clone();
+
+    let report = service.shutdown().await;
+    assert_eq!(report.stopped, vec!["watcher", "broadcaster", "coordinator"]);
+    assert!(report.timed_out.is_empty() && report.errors.is_empty(), "{:?}", report);
+    assert!(broadcaster.hold(future_tx()).await.is_err(), "no new work after shutdown");
+
+    let checkpoint = ScannerCheckpoint::load(&config.state_dir.join(SCANNER_CHECKPOINT_FILE)).unwrap().unwrap();
+    assert_eq!(checkpoint.watched_scripts, vec!["51".to_string()]);
+    assert_eq!(checkpoint.tracked.len(), 1);
+    assert!(config.state_dir.join(HOLDING_QUEUE_FILE).exists());
+    assert!(config.state_dir.join(SIGNING_ROUNDS_FILE).exists());
+
+    let (service, _events) = Service::start(BitcoinRPC::new(), BlockScanner::new(), config.clone()).unwrap();
+    assert!(matches!(service.broadcaster.status(txid).await.unwrap(), Some(HoldStatus::Waiting { .. })));
+    assert!(!service.coordinator.submit("r1", "alice", Psbt::from_unsigned_tx(future_tx()).unwrap()).await.unwrap());
+    service.shutdown().await;
+    std::fs::remove_dir_all(&config.state_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_run_until_signal_stops_on_token() {
+    let config = config("token");
+    let (service, _events) = Service::start(BitcoinRPC::new(), BlockScanner::new(), config.clone()).unwrap();
+    let token = service.shutdown_token();
+    let run = tokio::spawn(service.run_until_signal());
+    token.cancel();
+    let report = tokio::time::timeout(Duration::from_secs(5), run).await.unwrap().unwrap();
+    assert_eq!(report.stopped.len(), 3);
+    std::fs::remove_dir_all(&config.state_dir).unwrap();
+}
@@ -0,0 +1,89 @@
+use bitcoin_scripts::sighash::{preimage, SighashPath, SighashPreimage, TaprootInputData};
+use bitcoin::absolute::LockTime;
+use bitcoin::blockdata::script::Builder;
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::OP_CHECKSIG;
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+
+fn transaction() -> (Transaction, Vec<TxOut>) {
+    let input = |n: u8| TxIn {
+        previous_output: OutPoint::new(Txid::from_byte_array([n; 32]), n as u32),
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::from_consensus(0xfffffff0 + n as u32),
+        witness: Witness::new(),
+    };
+    let output = |value: u64, byte: u8| TxOut { value, script_pubkey: ScriptBuf::from_bytes(vec![0x00, 0x14].into_iter().chain([byte; 20]).collect()) };
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::from_height(800_000).unwrap(),
+        input: vec![input(1), input(2)],
+        output: vec![output(40_000, 7), output(55_000, 8)],
+    };
+    let prevouts = vec![
+        TxOut { value: 50_000, script_pubkey: ScriptBuf::from_bytes([0x51, 0x20].into_iter().chain([3u8; 32]).collect()) },
+        TxOut { value: 50_000, script_pubkey: ScriptBuf::from_bytes([0x00, 0x20].into_iter().chain([4u8; 32]).collect()) },
+    ];
+    (tx, prevouts)
+}
+
+#[test]
+fn test_segwit_v0_preimage_matches_sighash_cache() {
+    let (tx, prevouts) = transaction();
+    let script_code = Builder::new().push_slice([2u8; 33]).push_opcode(OP_CHECKSIG).into_script();
+    let path = SighashPath::SegwitV0 { script_code: script_code.clone() };
+    for ty in [EcdsaSighashType::All, EcdsaSighashType::None, EcdsaSighashType::Single, EcdsaSighashType::AllPlusAnyoneCanPay, EcdsaSighashType::SinglePlusAnyoneCanPay] {
+        let decomposed = preimage(&tx, 1, &prevouts, &path, ty.to_u32()).unwrap();
+        let expected = SighashCache::new(&tx).segwit_signature_hash(1, &script_code, prevouts[1].value, ty).unwrap();
+        assert_eq!(decomposed.digest(), expected.to_byte_array(), "{:?}", ty);
+    }
+
+    match preimage(&tx, 1, &prevouts, &path, EcdsaSighashType::AllPlusAnyoneCanPay.to_u32()).unwrap() {
+        SighashPreimage::SegwitV0(p) => {
+            assert_eq!(p.hash_prevouts.to_byte_array(), [0; 32]);
+            assert_eq!(p.amount.to_sat(), 50_000);
+            assert_eq!(p.lock_time, 800_000);
+        }
+        other => panic!("expected a BIP143 preimage, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_taproot_preimage_matches_sighash_cache() {
+    let (tx, prevouts) = transaction();
+    let leaf_hash = TapLeafHash::from_script(&Builder::new().push_slice([9u8; 32]).push_opcode(OP_CHECKSIG).into_script(), LeafVersion::TapScript);
+    for ty in [TapSighashType::Default, TapSighashType::All, TapSighashType::None, TapSighashType::Single, TapSighashType::AllPlusAnyoneCanPay, TapSighashType::SinglePlusAnyoneCanPay] {
+        let key = preimage(&tx, 0, &prevouts, &SighashPath::TaprootKey, ty as u32).unwrap();
+        let expected = SighashCache::new(&tx).taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), ty).unwrap();
+        assert_eq!(key.digest(), expected.to_byte_array(), "key path {:?}", ty);
+
+        let script = preimage(&tx, 0, &prevouts, &SighashPath::TaprootScript { leaf_hash, code_separator_pos: None }, ty as u32).unwrap();
+        let expected = SighashCache::new(&tx).taproot_script_spend_signature_hash(0, &Prevouts::All(&prevouts), leaf_hash, ty).unwrap();
+        assert_eq!(script.digest(), expected.to_byte_array(), "script path {:?}", ty);
+    }
+
+    match preimage(&tx, 0, &prevouts, &SighashPath::TaprootScript { leaf_hash, code_separator_pos: None }, 0x81).unwrap() {
+        SighashPreimage::Taproot(p) => {
+            assert_eq!((p.epoch, p.spend_type, p.hash_type), (0, 2, TapSighashType::AllPlusAnyoneCanPay));
+            assert!(p.sha_amounts.is_none() && p.sha_outputs.is_some());
+            assert!(matches!(p.input, TaprootInputData::AnyoneCanPay { ref script_pubkey, .. } if *script_pubkey == prevouts[0].script_pubkey));
+            assert_eq!((p.leaf_hash, p.key_version, p.code_separator_pos), (Some(leaf_hash), Some(0), Some(u32::MAX)));
+        }
+        other => panic!("expected a BIP341 preimage, got {:?}", other),
+    }
+    let display = preimage(&tx, 1, &prevouts, &SighashPath::TaprootKey, 0).unwrap().to_string();
+    assert!(display.contains("sha_amounts: ") && display.contains("input_index: 1"));
+}
+
+#[test]
+fn test_invalid_requests_are_rejected() {
+    let (mut tx, prevouts) = transaction();
+    assert!(preimage(&tx, 2, &prevouts, &SighashPath::TaprootKey, 0).is_err());
+    assert!(preimage(&tx, 0, &prevouts[..1], &SighashPath::TaprootKey, 0).is_err());
+    assert!(preimage(&tx, 0, &prevouts, &SighashPath::TaprootKey, 0x04).is_err());
+    tx.output.truncate(1);
+    assert!(preimage(&tx, 1, &prevouts, &SighashPath::TaprootKey, TapSighashType::Single as u32).is_err());
+    // BIP143 has no SIGHASH_SINGLE bug: the outputs hash is simply zero
+    assert!(preimage(&tx, 1, &prevouts, &SighashPath::SegwitV0 { script_code: ScriptBuf::new() }, 0x03).is_ok());
+}
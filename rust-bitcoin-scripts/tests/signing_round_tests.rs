@@ -0,0 +1,58 @@
+use bitcoin_scripts::signing_round::{RoundState, SigningRound, SigningRounds};
+use bitcoin_scripts::webhooks::LifecycleEvent;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, absolute::LockTime};
+use bitcoin::hashes::Hash;
+use std::time::{Duration, Instant};
+
+fn unsigned_psbt() -> Psbt {
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 3),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut { value: 10_000, script_pubkey: ScriptBuf::new() }],
+    };
+    Psbt::from_unsigned_tx(tx).unwrap()
+}
+
+fn signers() -> Vec<String> {
+    vec!["alice".to_string(), "bob".to_string(), "carol".to_string()]
+}
+
+#[test]
+fn test_round_completes_at_quorum() {
+    let mut round = SigningRound::new("r1", unsigned_psbt(), signers(), 2, Duration::from_secs(60));
+    assert!(!round.submit("alice", unsigned_psbt()).unwrap());
+    assert!(!round.submit("alice", unsigned_psbt()).unwrap(), "resubmission does not count twice");
+    assert!(round.submit("carol", unsigned_psbt()).unwrap());
+    assert_eq!(round.state, RoundState::Complete);
+    assert!(round.submit("bob", unsigned_psbt()).is_err());
+    assert!(round.check_timeout(Instant::now() + Duration::from_secs(120)).is_none());
+}
+
+#[test]
+fn test_stalled_round_escalates_and_releases_utxos() {
+    let mut rounds = SigningRounds::new();
+    rounds.open(SigningRound::new("r2", unsigned_psbt(), signers(), 2, Duration::from_secs(60)));
+    rounds.submit("r2", "bob", unsigned_psbt()).unwrap();
+    assert!(rounds.submit("r2", "mallory", unsigned_psbt()).is_err());
+
+    assert!(rounds.tick(Instant::now()).is_empty());
+    let escalations = rounds.tick(Instant::now() + Duration::from_secs(61));
+    assert_eq!(escalations.len(), 1);
+    assert_eq!(escalations[0].unresponsive, vec!["alice".to_string(), "carol".to_string()]);
+    assert_eq!(escalations[0].released_utxos, vec![OutPoint::new(Txid::all_zeros(), 3)]);
+    assert_eq!(
+        escalations[0].to_event(),
+        LifecycleEvent::SigningRoundStalled { round_id: "r2".to_string(), missing_signers: vec!["alice".to_string(), "carol".to_string()] }
+    );
+    assert_eq!(rounds.rounds["r2"].state, RoundState::Stalled);
+    assert!(rounds.tick(Instant::now() + Duration::from_secs(120)).is_empty(), "escalates once");
+    assert_eq!(rounds.missed_rounds.get("alice"), Some(&1));
+    assert_eq!(rounds.missed_rounds.get("bob"), None);
+}
@@ -1,23 +1,31 @@
-use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::amount::{deduct_fee, from_rpc};
+use bitcoin_scripts::receipt::SpendReceipt;
+use bitcoin_scripts::test_setup::{BitcoinRPC, DESTINATION_LABEL, FUNDING_LABEL};
+use bitcoin_scripts::report::AmountReport;
+use bitcoin_scripts::taproot::spend_script_path;
 use bitcoin::blockdata::script::ScriptBuf;
-use bitcoin::taproot::{TaprootBuilder, LeafVersion};
+use bitcoin::taproot::TaprootBuilder;
 use bitcoin::secp256k1::{Secp256k1, SecretKey, KeyPair};
 use bitcoin::key::XOnlyPublicKey;
-use bitcoin::{Address, Network, TxIn, TxOut, Transaction, OutPoint, Witness, Sequence};
+use bitcoin::{Address, Amount, Network, TxIn, TxOut, Transaction, OutPoint, Witness, Sequence};
 use bitcoin::opcodes::OP_TRUE;
 use hex;
 use std::str::FromStr;
+use std::time::Duration;
 use bitcoin::script::PushBytesBuf;
-use bitcoin::sighash::ScriptPath;
+use bitcoin::sighash::TapSighashType;
 use bitcoin::key::TapTweak;
 
+/// Flat fee for the hand-built spends
+const FEE: Amount = Amount::from_sat(100_000);
+
 #[tokio::test]
 async fn test_simple_taproot_script_spend() {
     let rpc = BitcoinRPC::new();
     let _ = rpc.create_wallet("taproot_script_wallet").await;
     let _ = rpc.load_wallet("taproot_script_wallet").await;
     let rpc = rpc.with_wallet("taproot_script_wallet");
-    let _ = rpc.generate_keys(5).await.unwrap();
+    rpc.ensure_keypool(5).await.unwrap();
 
     let secp = Secp256k1::new();
     let sk_bytes = [5; 32];
@@ -51,9 +59,9 @@ async fn test_simple_taproot_script_spend() {
     println!("Simple Taproot address: {}", address);
 
     // Fund the address
-    let funding_address = rpc.get_new_address().await.unwrap();
+    let funding_address = rpc.new_labeled_address(FUNDING_LABEL).await.unwrap();
     let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
-    let send_amount = 0.1;
+    let send_amount = Amount::from_sat(10_000_000);
     let txid = rpc.send_to_address(&address.to_string(), send_amount).await.unwrap();
     let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
     println!("Funded Taproot address with txid: {}", txid);
@@ -67,16 +75,16 @@ async fn test_simple_taproot_script_spend() {
         })
         .expect("Taproot output not found in transaction");
     let output = &raw_tx_details["vout"].as_array().unwrap()[vout];
-    let amount = output["value"].as_f64().unwrap();
-    println!("Found UTXO: {}:{} (amount: {})", txid, vout, amount);
+    let amount = from_rpc(&output["value"]).unwrap();
+    println!("Found UTXO: {}:{} (amount: {})", txid, vout, AmountReport::sats(amount));
 
     // Build spending transaction (script path spend)
     let outpoint = OutPoint::new(bitcoin::Txid::from_str(&txid).unwrap(), vout as u32);
-    let to_address = rpc.get_new_address().await.unwrap();
-    let value = ((amount - 0.001) * 100_000_000.0) as u64;
+    let to_address = rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap();
+    let value = deduct_fee(amount, FEE).unwrap().to_sat();
     // The previous output (the Taproot UTXO being spent)
     let prev_txout = TxOut {
-        value: (amount * 100_000_000.0) as u64,
+        value: amount.to_sat(),
         script_pubkey: address.script_pubkey(),
     };
     let txin = TxIn { previous_output: outpoint, script_sig: ScriptBuf::new(), sequence: Sequence(0xfffffffd), witness: Witness::new() };
@@ -84,18 +92,7 @@ async fn test_simple_taproot_script_spend() {
     let mut tx = Transaction { version: 2, lock_time: bitcoin::absolute::LockTime::ZERO, input: vec![txin], output: vec![txout.clone()] };
 
     // Build witness for script path spend (<pubkey> OP_CHECKSIG)
-    use bitcoin::sighash::{SighashCache, TapSighashType};
-    use bitcoin::secp256k1::Message;
-    let control_block = spend_info.control_block(&(script_buf.clone(), bitcoin::taproot::LeafVersion::TapScript)).unwrap();
-    let mut cache = SighashCache::new(&mut tx);
-    let script_path = ScriptPath::new(&script_buf, LeafVersion::TapScript);
-    let sighash = cache.taproot_script_spend_signature_hash(0, &bitcoin::sighash::Prevouts::All(&[prev_txout]), script_path, TapSighashType::Default).unwrap();
-    let msg = Message::from_slice(sighash.as_ref()).unwrap();
-    let schnorr_sig = secp.sign_schnorr_no_aux_rand(&msg, &script_keypair);
-    tx.input[0].witness.clear();
-    tx.input[0].witness.push(schnorr_sig.as_ref().to_vec()); // Schnorr signature
-    tx.input[0].witness.push(script_buf.to_bytes());
-    tx.input[0].witness.push(control_block.serialize());
+    tx.input[0].witness = spend_script_path(&secp, &spend_info, &script_buf, &script_keypair, &tx, 0, &[prev_txout], TapSighashType::Default).unwrap();
 
     // Debug print: show the witness stack
     println!("=== DEBUG: Taproot script path witness stack ===");
@@ -110,16 +107,10 @@ async fn test_simple_taproot_script_spend() {
     // Print the internal key from the control block (bytes 1..33)
     println!("Control block internal key: {}", hex::encode(&cb[1..33]));
 
-    // Broadcast the spend
-    let tx_hex = bitcoin::consensus::encode::serialize_hex(&tx);
-    println!("Spending tx hex: {}", tx_hex);
-    let spend_txid = rpc.send_raw_transaction(&tx_hex).await.unwrap();
-    println!("Spend broadcasted: {}", spend_txid);
-    // Confirm the spend
+    // Broadcast and confirm the spend
+    let mut receipt = SpendReceipt::broadcast(&rpc, &tx, FEE, "taproot_leaf@0").await.unwrap();
     let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
-    let spent = rpc.call_rpc("gettransaction", serde_json::json!([spend_txid, serde_json::Value::Null])).await.unwrap();
-    assert!(spent["confirmations"].as_i64().unwrap() > 0, "Spend not confirmed");
-    println!("Simple Taproot script path spend confirmed!");
+    assert!(receipt.refresh(&rpc).await.unwrap().is_some(), "Spend not confirmed");
 }
 
 #[tokio::test]
@@ -128,7 +119,7 @@ async fn test_simple_taproot_key_spend() {
     let _ = rpc.create_wallet("taproot_key_wallet").await;
     let _ = rpc.load_wallet("taproot_key_wallet").await;
     let rpc = rpc.with_wallet("taproot_key_wallet");
-    let _ = rpc.generate_keys(5).await.unwrap();
+    rpc.ensure_keypool(5).await.unwrap();
 
     let secp = Secp256k1::new();
     let sk_bytes = [5; 32];
@@ -147,9 +138,9 @@ async fn test_simple_taproot_key_spend() {
     println!("Simple Taproot key spend address: {}", address);
 
     // Fund the address
-    let funding_address = rpc.get_new_address().await.unwrap();
+    let funding_address = rpc.new_labeled_address(FUNDING_LABEL).await.unwrap();
     let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
-    let send_amount = 0.1;
+    let send_amount = Amount::from_sat(10_000_000);
     let txid = rpc.send_to_address(&address.to_string(), send_amount).await.unwrap();
     let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
     println!("Funded Taproot key spend address with txid: {}", txid);
@@ -163,16 +154,16 @@ async fn test_simple_taproot_key_spend() {
         })
         .expect("Taproot output not found in transaction");
     let output = &raw_tx_details["vout"].as_array().unwrap()[vout];
-    let amount = output["value"].as_f64().unwrap();
-    println!("Found UTXO: {}:{} (amount: {})", txid, vout, amount);
+    let amount = from_rpc(&output["value"]).unwrap();
+    println!("Found UTXO: {}:{} (amount: {})", txid, vout, AmountReport::sats(amount));
 
     // Build spending transaction (key spend)
     let outpoint = OutPoint::new(bitcoin::Txid::from_str(&txid).unwrap(), vout as u32);
-    let to_address = rpc.get_new_address().await.unwrap();
-    let value = ((amount - 0.001) * 100_000_000.0) as u64;
+    let to_address = rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap();
+    let value = deduct_fee(amount, FEE).unwrap().to_sat();
     // The previous output (the Taproot UTXO being spent)
     let prev_txout = TxOut {
-        value: (amount * 100_000_000.0) as u64,
+        value: amount.to_sat(),
         script_pubkey: address.script_pubkey(),
     };
     let txin = TxIn { previous_output: outpoint, script_sig: ScriptBuf::new(), sequence: Sequence(0xfffffffd), witness: Witness::new() };
@@ -180,7 +171,7 @@ async fn test_simple_taproot_key_spend() {
     let mut tx = Transaction { version: 2, lock_time: bitcoin::absolute::LockTime::ZERO, input: vec![txin], output: vec![txout.clone()] };
 
     // Build witness for key spend (just a Schnorr signature)
-    use bitcoin::sighash::{SighashCache, TapSighashType};
+    use bitcoin::sighash::SighashCache;
     use bitcoin::secp256k1::Message;
     let mut cache = SighashCache::new(&mut tx);
     let sighash = cache.taproot_key_spend_signature_hash(0, &bitcoin::sighash::Prevouts::All(&[prev_txout]), TapSighashType::Default).unwrap();
@@ -200,16 +191,11 @@ async fn test_simple_taproot_key_spend() {
     }
     println!("=== END DEBUG ===");
 
-    // Broadcast the spend
-    let tx_hex = bitcoin::consensus::encode::serialize_hex(&tx);
-    println!("Spending tx hex: {}", tx_hex);
-    let spend_txid = rpc.send_raw_transaction(&tx_hex).await.unwrap();
-    println!("Key spend broadcasted: {}", spend_txid);
-    // Confirm the spend
+    // Broadcast and confirm the spend
+    let mut receipt = SpendReceipt::broadcast(&rpc, &tx, FEE, "taproot_key").await.unwrap();
+    assert_eq!(receipt.witness_sizes, vec![tx.input[0].witness.serialized_len()]);
     let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
-    let spent = rpc.call_rpc("gettransaction", serde_json::json!([spend_txid, serde_json::Value::Null])).await.unwrap();
-    assert!(spent["confirmations"].as_i64().unwrap() > 0, "Key spend not confirmed");
-    println!("Simple Taproot key spend confirmed!");
+    assert!(receipt.refresh(&rpc).await.unwrap().is_some(), "Key spend not confirmed");
 }
 
 #[tokio::test]
@@ -218,7 +204,7 @@ async fn test_taproot_two_leaf_spend() {
     let _ = rpc.create_wallet("taproot_two_leaf_wallet").await;
     let _ = rpc.load_wallet("taproot_two_leaf_wallet").await;
     let rpc = rpc.with_wallet("taproot_two_leaf_wallet");
-    let _ = rpc.generate_keys(5).await.unwrap();
+    rpc.ensure_keypool(5).await.unwrap();
 
     let secp = Secp256k1::new();
     let sk_bytes = [7; 32];
@@ -255,9 +241,9 @@ async fn test_taproot_two_leaf_spend() {
     println!("Two-leaf Taproot address: {}", address);
 
     // Fund the address
-    let funding_address = rpc.get_new_address().await.unwrap();
+    let funding_address = rpc.new_labeled_address(FUNDING_LABEL).await.unwrap();
     let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
-    let send_amount = 0.1;
+    let send_amount = Amount::from_sat(10_000_000);
     let txid = rpc.send_to_address(&address.to_string(), send_amount).await.unwrap();
     let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
     println!("Funded two-leaf Taproot address with txid: {}", txid);
@@ -271,15 +257,15 @@ async fn test_taproot_two_leaf_spend() {
         })
         .expect("Taproot output not found in transaction");
     let output = &raw_tx_details["vout"].as_array().unwrap()[vout];
-    let amount = output["value"].as_f64().unwrap();
-    println!("Found UTXO: {}:{} (amount: {})", txid, vout, amount);
+    let amount = from_rpc(&output["value"]).unwrap();
+    println!("Found UTXO: {}:{} (amount: {})", txid, vout, AmountReport::sats(amount));
 
     // --- Spend via script path 1 (no timelock) ---
     let outpoint = OutPoint::new(bitcoin::Txid::from_str(&txid).unwrap(), vout as u32);
-    let to_address = rpc.get_new_address().await.unwrap();
-    let value = ((amount - 0.001) * 100_000_000.0) as u64;
+    let to_address = rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap();
+    let value = deduct_fee(amount, FEE).unwrap().to_sat();
     let prev_txout = TxOut {
-        value: (amount * 100_000_000.0) as u64,
+        value: amount.to_sat(),
         script_pubkey: address.script_pubkey(),
     };
     let txin = TxIn { previous_output: outpoint, script_sig: ScriptBuf::new(), sequence: Sequence(0xfffffffd), witness: Witness::new() };
@@ -287,26 +273,10 @@ async fn test_taproot_two_leaf_spend() {
     let mut tx = Transaction { version: 2, lock_time: bitcoin::absolute::LockTime::ZERO, input: vec![txin], output: vec![txout.clone()] };
 
     // Build witness for script path 1
-    use bitcoin::sighash::{SighashCache, TapSighashType};
-    use bitcoin::secp256k1::Message;
-    let control_block1 = spend_info.control_block(&(script1_buf.clone(), bitcoin::taproot::LeafVersion::TapScript)).unwrap();
-    let mut cache = SighashCache::new(&mut tx);
-    let script_path1 = ScriptPath::new(&script1_buf, LeafVersion::TapScript);
-    let sighash1 = cache.taproot_script_spend_signature_hash(0, &bitcoin::sighash::Prevouts::All(&[prev_txout]), script_path1, TapSighashType::Default).unwrap();
-    let msg1 = Message::from_slice(sighash1.as_ref()).unwrap();
-    let schnorr_sig1 = secp.sign_schnorr_no_aux_rand(&msg1, &keypair);
-    tx.input[0].witness.clear();
-    tx.input[0].witness.push(schnorr_sig1.as_ref().to_vec());
-    tx.input[0].witness.push(script1_buf.to_bytes());
-    tx.input[0].witness.push(control_block1.serialize());
-    println!("Spending via script path 1 (no timelock)...");
-    let tx_hex1 = bitcoin::consensus::encode::serialize_hex(&tx);
-    let spend_txid1 = rpc.send_raw_transaction(&tx_hex1).await.unwrap();
-    println!("Spend 1 broadcasted: {}", spend_txid1);
+    tx.input[0].witness = spend_script_path(&secp, &spend_info, &script1_buf, &keypair, &tx, 0, &[prev_txout], TapSighashType::Default).unwrap();
+    let mut receipt1 = SpendReceipt::broadcast(&rpc, &tx, FEE, "taproot_leaf@1").await.unwrap();
     let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
-    let spent1 = rpc.call_rpc("gettransaction", serde_json::json!([spend_txid1, serde_json::Value::Null])).await.unwrap();
-    assert!(spent1["confirmations"].as_i64().unwrap() > 0, "Spend 1 not confirmed");
-    println!("Two-leaf Taproot spend path 1 confirmed!");
+    assert!(receipt1.refresh(&rpc).await.unwrap().is_some(), "Spend 1 not confirmed");
 
     // --- Spend via script path 2 (timelock) ---
     // Fund again for the second spend
@@ -320,12 +290,12 @@ async fn test_taproot_two_leaf_spend() {
         })
         .expect("Taproot output not found in transaction");
     let output2 = &raw_tx_details2["vout"].as_array().unwrap()[vout2];
-    let amount2 = output2["value"].as_f64().unwrap();
+    let amount2 = from_rpc(&output2["value"]).unwrap();
     let outpoint2 = OutPoint::new(bitcoin::Txid::from_str(&txid2).unwrap(), vout2 as u32);
-    let to_address2 = rpc.get_new_address().await.unwrap();
-    let value2 = ((amount2 - 0.001) * 100_000_000.0) as u64;
+    let to_address2 = rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap();
+    let value2 = deduct_fee(amount2, FEE).unwrap().to_sat();
     let prev_txout2 = TxOut {
-        value: (amount2 * 100_000_000.0) as u64,
+        value: amount2.to_sat(),
         script_pubkey: address.script_pubkey(),
     };
     // For CLTV timelocks, sequence must be less than 0xffffffff to enable lock_time
@@ -334,16 +304,7 @@ async fn test_taproot_two_leaf_spend() {
     let mut tx2 = Transaction { version: 2, lock_time: bitcoin::absolute::LockTime::from_height(cltv_height as u32).unwrap(), input: vec![txin2], output: vec![txout2.clone()] };
 
     // Build witness for script path 2
-    let control_block2 = spend_info.control_block(&(script2_buf.clone(), bitcoin::taproot::LeafVersion::TapScript)).unwrap();
-    let mut cache2 = SighashCache::new(&mut tx2);
-    let script_path2 = ScriptPath::new(&script2_buf, LeafVersion::TapScript);
-    let sighash2 = cache2.taproot_script_spend_signature_hash(0, &bitcoin::sighash::Prevouts::All(&[prev_txout2]), script_path2, TapSighashType::Default).unwrap();
-    let msg2 = Message::from_slice(sighash2.as_ref()).unwrap();
-    let schnorr_sig2 = secp.sign_schnorr_no_aux_rand(&msg2, &keypair);
-    tx2.input[0].witness.clear();
-    tx2.input[0].witness.push(schnorr_sig2.as_ref().to_vec());
-    tx2.input[0].witness.push(script2_buf.to_bytes());
-    tx2.input[0].witness.push(control_block2.serialize());
+    tx2.input[0].witness = spend_script_path(&secp, &spend_info, &script2_buf, &keypair, &tx2, 0, &[prev_txout2], TapSighashType::Default).unwrap();
     println!("Spending via script path 2 (timelock)...");
     
     // Check current block height vs CLTV height
@@ -366,21 +327,58 @@ async fn test_taproot_two_leaf_spend() {
         let new_height = rpc.call_rpc("getblockcount", serde_json::json!([])).await.unwrap().as_u64().unwrap();
         tx2.lock_time = bitcoin::absolute::LockTime::from_height(new_height as u32).unwrap();
         println!("Updated tx2.lock_time to current block height: {:?}", tx2.lock_time);
-        let spend_txid2 = rpc.send_raw_transaction(&bitcoin::consensus::encode::serialize_hex(&tx2)).await.unwrap();
-        println!("Spend 2 broadcasted: {}", spend_txid2);
+        rpc.send_raw_transaction(&bitcoin::consensus::encode::serialize_hex(&tx2)).await.unwrap();
         let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
-        let spent2 = rpc.call_rpc("gettransaction", serde_json::json!([spend_txid2, serde_json::Value::Null])).await.unwrap();
-        assert!(spent2["confirmations"].as_i64().unwrap() > 0, "Spend 2 not confirmed");
-        println!("Two-leaf Taproot spend path 2 (timelock) confirmed!");
+        rpc.wait_for_confirmations(&tx2.txid(), 1, Duration::from_secs(30)).await.expect("Spend 2 not confirmed");
     } else {
         assert!(res.is_ok(), "Timelock spend should succeed when block height requirement is met");
         println!("Timelock spend succeeded (block height requirement already met)");
-        let spend_txid2 = res.unwrap();
-        println!("Spend 2 broadcasted: {}", spend_txid2);
         let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
-        let spent2 = rpc.call_rpc("gettransaction", serde_json::json!([spend_txid2, serde_json::Value::Null])).await.unwrap();
-        assert!(spent2["confirmations"].as_i64().unwrap() > 0, "Spend 2 not confirmed");
-        println!("Two-leaf Taproot spend path 2 (timelock) confirmed!");
+        rpc.wait_for_confirmations(&tx2.txid(), 1, Duration::from_secs(30)).await.expect("Spend 2 not confirmed");
         return; // Exit early since the spend already succeeded
     }
-} 
\ No newline at end of file
+} 
+
+#[tokio::test]
+async fn test_taproot_leaf_spend_with_anyonecanpay() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("taproot_acp_wallet").await;
+    let _ = rpc.load_wallet("taproot_acp_wallet").await;
+    let rpc = rpc.with_wallet("taproot_acp_wallet");
+    rpc.ensure_keypool(5).await.unwrap();
+
+    let secp = Secp256k1::new();
+    let keypair = KeyPair::from_secret_key(&secp, &SecretKey::from_slice(&[8; 32]).unwrap());
+    let xonly_pk = XOnlyPublicKey::from_keypair(&keypair).0;
+    let script_buf = bitcoin::blockdata::script::Builder::new()
+        .push_slice(xonly_pk.serialize())
+        .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+        .into_script();
+    let spend_info = TaprootBuilder::new().add_leaf(0, script_buf.clone()).unwrap().finalize(&secp, xonly_pk).unwrap();
+    let address = Address::p2tr_tweaked(spend_info.output_key(), Network::Regtest);
+
+    let funding_address = rpc.new_labeled_address(FUNDING_LABEL).await.unwrap();
+    let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
+    let txid = rpc.send_to_address(&address.to_string(), Amount::from_sat(10_000_000)).await.unwrap();
+    let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
+    let raw_tx_details = rpc.call_rpc("getrawtransaction", serde_json::json!([txid, true])).await.unwrap();
+    let vout = raw_tx_details["vout"].as_array().unwrap()
+        .iter()
+        .position(|output| output["scriptPubKey"]["address"].as_str().unwrap() == address.to_string())
+        .expect("Taproot output not found in transaction");
+    let amount = from_rpc(&raw_tx_details["vout"][vout]["value"]).unwrap();
+
+    let to_address = rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap();
+    let prev_txout = TxOut { value: amount.to_sat(), script_pubkey: address.script_pubkey() };
+    let txin = TxIn { previous_output: OutPoint::new(bitcoin::Txid::from_str(&txid).unwrap(), vout as u32), script_sig: ScriptBuf::new(), sequence: Sequence(0xfffffffd), witness: Witness::new() };
+    let txout = TxOut { value: deduct_fee(amount, FEE).unwrap().to_sat(), script_pubkey: Address::from_str(&to_address).unwrap().require_network(Network::Regtest).unwrap().script_pubkey() };
+    let mut tx = Transaction { version: 2, lock_time: bitcoin::absolute::LockTime::ZERO, input: vec![txin], output: vec![txout] };
+
+    // Commit to this input only; the flag byte follows the 64-byte signature
+    tx.input[0].witness = spend_script_path(&secp, &spend_info, &script_buf, &keypair, &tx, 0, &[prev_txout], TapSighashType::AllPlusAnyoneCanPay).unwrap();
+    let signature = tx.input[0].witness.nth(0).unwrap();
+    assert_eq!((signature.len(), signature[64]), (65, TapSighashType::AllPlusAnyoneCanPay as u8));
+    let mut receipt = SpendReceipt::broadcast(&rpc, &tx, FEE, "taproot_leaf_acp").await.unwrap();
+    let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
+    assert!(receipt.refresh(&rpc).await.unwrap().is_some(), "ANYONECANPAY spend not confirmed");
+}
@@ -0,0 +1,97 @@
+use bitcoin_scripts::spend::{Planner, SpendPath};
+use bitcoin_scripts::timelock_csv::csv_vault_descriptor;
+use miniscript::bitcoin::absolute::LockTime;
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, Sequence, secp256k1};
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+fn keys() -> Vec<PublicKey> {
+    let secp = secp256k1::Secp256k1::new();
+    [31u8, 32, 33, 34, 35].iter()
+        .map(|b| PublicKey::from_private_key(&secp, &PrivateKey::new(secp256k1::SecretKey::from_slice(&[*b; 32]).unwrap(), Network::Regtest)))
+        .collect()
+}
+
+fn csv_vault(k: &[PublicKey]) -> Descriptor<PublicKey> {
+    csv_vault_descriptor(k[3], &k[..3], 2, 10).unwrap()
+}
+
+fn cltv_vault(k: &[PublicKey]) -> Descriptor<PublicKey> {
+    Descriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),after(500))))", k[3], k[0], k[1], k[2])).unwrap()
+}
+
+#[test]
+fn test_backup_key_needs_no_timelock() {
+    let k = keys();
+    let plan = Planner::new([k[3]], 100, 0, 0).plan(&csv_vault(&k)).unwrap();
+    assert_eq!(plan.path, SpendPath::Script);
+    assert_eq!(plan.signers, vec![k[3]]);
+    assert_eq!(plan.sequence, Sequence::ENABLE_RBF_NO_LOCKTIME);
+    assert_eq!(plan.lock_time, LockTime::ZERO);
+
+    // Holding every key, the single signature is still the cheaper branch
+    let all = Planner::new(k.clone(), 100, 0, 20).plan(&csv_vault(&k)).unwrap();
+    assert_eq!(all.signers, vec![k[3]]);
+    assert_eq!(all.satisfaction_weight, plan.satisfaction_weight);
+}
+
+#[test]
+fn test_multisig_branch_waits_for_relative_delay() {
+    let k = keys();
+    let held = [k[0], k[1]];
+    assert!(Planner::new(held, 100, 0, 9).plan(&csv_vault(&k)).is_err());
+
+    let plan = Planner::new(held, 100, 0, 10).plan(&csv_vault(&k)).unwrap();
+    assert_eq!(plan.sequence, Sequence::from_height(10));
+    assert_eq!(plan.lock_time, LockTime::ZERO);
+    let mut signers = held.to_vec();
+    signers.sort();
+    assert_eq!(plan.signers, signers);
+    let backup = Planner::new([k[3]], 100, 0, 10).plan(&csv_vault(&k)).unwrap();
+    assert!(plan.satisfaction_weight > backup.satisfaction_weight);
+
+    // A single cosigner is not enough however long we wait
+    assert!(Planner::new([k[0]], 100, 0, 1000).plan(&csv_vault(&k)).is_err());
+}
+
+#[test]
+fn test_lock_time_only_when_the_path_needs_it() {
+    let k = keys();
+    assert!(Planner::new([k[1], k[2]], 499, 0, 1).plan(&cltv_vault(&k)).is_err());
+    let plan = Planner::new([k[1], k[2]], 500, 0, 1).plan(&cltv_vault(&k)).unwrap();
+    assert_eq!(plan.lock_time, LockTime::from_height(500).unwrap());
+    assert_eq!(plan.sequence, Sequence::ENABLE_RBF_NO_LOCKTIME);
+
+    let backup = Planner::new([k[3]], 500, 0, 1).plan(&cltv_vault(&k)).unwrap();
+    assert_eq!(backup.lock_time, LockTime::ZERO);
+}
+
+#[test]
+fn test_taproot_prefers_key_path_then_cheapest_mature_leaf() {
+    let k = keys();
+    let tr: Descriptor<PublicKey> = Descriptor::from_str(&format!(
+        "tr({},{{and_v(v:pk({}),older(10)),and_v(v:multi_a(2,{},{},{}),older(5))}})",
+        k[4], k[3], k[0], k[1], k[2]
+    )).unwrap();
+
+    let everything = Planner::new(k.clone(), 100, 0, 20);
+    let key_path = everything.plan(&tr).unwrap();
+    assert_eq!(key_path.path, SpendPath::TaprootKey);
+    assert_eq!(key_path.signers, vec![k[4]]);
+    assert_eq!(everything.candidates(&tr).unwrap().len(), 3);
+
+    // Without the internal key the single-key leaf beats the 2-of-3 leaf once both are mature
+    let leaves = Planner::new(k[..4].to_vec(), 100, 0, 20);
+    let plan = leaves.plan(&tr).unwrap();
+    assert!(matches!(plan.path, SpendPath::TaprootLeaf { depth: 1, .. }));
+    assert_eq!(plan.signers, vec![k[3]]);
+    assert_eq!(plan.sequence, Sequence::from_height(10));
+
+    // Before the single-key delay, only the multisig leaf is available
+    let early = Planner::new(k[..4].to_vec(), 100, 0, 7).plan(&tr).unwrap();
+    assert_eq!(early.sequence, Sequence::from_height(5));
+    assert_eq!(early.signers.len(), 2);
+    assert!(early.satisfaction_weight > plan.satisfaction_weight);
+
+    assert!(Planner::new(k[..4].to_vec(), 100, 0, 4).plan(&tr).is_err());
+}
@@ -0,0 +1,73 @@
+use bitcoin_scripts::flows::{fund_descriptor, mine};
+use bitcoin_scripts::htlc::{HtlcKind, HtlcPath};
+use bitcoin_scripts::psbt::SpendableUtxo;
+use bitcoin_scripts::swap::Coordinator;
+use bitcoin_scripts::test_setup::{BitcoinRPC, DESTINATION_LABEL};
+use bitcoin::consensus::encode::serialize;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, Amount, OutPoint, ScriptBuf, TxOut, Txid};
+use std::str::FromStr;
+
+mod common;
+use common::key;
+
+const FEE: Amount = Amount::from_sat(2_000);
+
+#[test]
+fn test_participant_learns_the_secret_from_the_claim() {
+    let secp = Secp256k1::new();
+    for kind in [HtlcKind::Wsh, HtlcKind::Tr] {
+        let initiator = Coordinator::initiate(kind, key(141).public_key(&secp), key(142).public_key(&secp), 300).unwrap();
+        let secret = *initiator.secret().unwrap();
+        assert!(initiator.hash_lock().matches(&secret));
+
+        let utxo = SpendableUtxo::new(OutPoint::new(Txid::all_zeros(), 1), TxOut { value: 50_000, script_pubkey: initiator.funding_descriptor().script_pubkey() });
+        let destination = key(143).public_key(&secp);
+        let destination = bitcoin::ScriptBuf::new_v0_p2wpkh(&destination.wpubkey_hash().unwrap());
+        let claim = initiator.claim_tx(&secp, &utxo, destination.clone(), FEE, &key(141)).unwrap();
+        assert_eq!(claim.output[0].value, 48_000);
+
+        let mut participant = Coordinator::participate(initiator.htlc.clone());
+        assert!(participant.secret().is_none());
+        assert!(participant.claim_tx(&secp, &utxo, destination.clone(), FEE, &key(141)).is_err(), "no secret yet");
+        // A refund reveals nothing
+        let refund = participant.refund_tx(&secp, &utxo, destination.clone(), FEE, &key(142)).unwrap();
+        assert_eq!(refund.lock_time, initiator.htlc.refund_lock_time());
+        assert!(participant.learn_secret(&refund).is_err());
+        assert_eq!(participant.learn_secret(&claim).unwrap(), &secret);
+        assert!(participant.claim_tx(&secp, &utxo, destination, FEE, &key(141)).is_ok());
+    }
+
+    // Wrong keys and foreign outputs are rejected
+    let initiator = Coordinator::initiate(HtlcKind::Wsh, key(141).public_key(&secp), key(142).public_key(&secp), 300).unwrap();
+    let utxo = SpendableUtxo::new(OutPoint::new(Txid::all_zeros(), 1), TxOut { value: 50_000, script_pubkey: initiator.funding_descriptor().script_pubkey() });
+    let destination = initiator.funding_descriptor().script_pubkey();
+    assert!(initiator.claim_tx(&secp, &utxo, destination.clone(), FEE, &key(142)).is_err());
+    let foreign = SpendableUtxo::new(utxo.outpoint, TxOut { value: 50_000, script_pubkey: ScriptBuf::new_v0_p2wpkh(&key(141).public_key(&secp).wpubkey_hash().unwrap()) });
+    assert!(initiator.claim_tx(&secp, &foreign, destination, FEE, &key(141)).is_err());
+    assert_eq!(initiator.htlc.signing_key(HtlcPath::Claim), key(141).public_key(&secp));
+}
+
+#[tokio::test]
+async fn test_swap_claim_reveals_the_secret_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("swap_wallet").await;
+    let _ = rpc.load_wallet("swap_wallet").await;
+    let rpc = rpc.with_wallet("swap_wallet");
+    let tip = mine(&rpc, 101).await.unwrap();
+    let secp = Secp256k1::new();
+    let destination = Address::from_str(&rpc.new_labeled_address(DESTINATION_LABEL).await.unwrap()).unwrap().assume_checked().script_pubkey();
+
+    let initiator = Coordinator::initiate(HtlcKind::Tr, key(141).public_key(&secp), key(142).public_key(&secp), tip as u32 + 20).unwrap();
+    let mut participant = Coordinator::participate(initiator.htlc.clone());
+    let funded = fund_descriptor(&rpc, participant.funding_descriptor(), Amount::from_sat(1_000_000)).await.unwrap();
+
+    let claim = initiator.claim_tx(&secp, &funded.utxo, destination, FEE, &key(141)).unwrap();
+    let txid = Txid::from_str(&rpc.send_raw_transaction(&hex::encode(serialize(&claim))).await.unwrap()).unwrap();
+
+    // The participant only sees the transaction on chain
+    let seen = rpc.get_raw_transaction_verbose(&txid).await.unwrap().transaction().unwrap();
+    assert_eq!(participant.learn_secret(&seen).unwrap(), initiator.secret().unwrap());
+    mine(&rpc, 1).await.unwrap();
+}
@@ -0,0 +1,41 @@
+use bitcoin_scripts::flows::{fund_descriptor, mine};
+use bitcoin_scripts::sweep::sweep_backup_path;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::{Amount, PublicKey, Sequence};
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+mod common;
+use common::{key, pk};
+
+#[tokio::test]
+async fn test_sweep_spends_every_vault_utxo_through_the_backup_key() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("sweep_wallet").await;
+    let _ = rpc.load_wallet("sweep_wallet").await;
+    let rpc = rpc.with_wallet("sweep_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let vault: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(or_d(multi(2,{},{}),and_v(v:pk({}),older(3))))", pk(95), pk(96), pk(97))).unwrap();
+    let first = fund_descriptor(&rpc, &vault, Amount::from_sat(200_000)).await.unwrap();
+    let second = fund_descriptor(&rpc, &vault, Amount::from_sat(300_000)).await.unwrap();
+    let destination = rpc.get_new_address().await.unwrap();
+
+    // The backup path of the newest deposit is not mature yet
+    assert!(sweep_backup_path(&rpc, &vault, &key(97), &destination).await.is_err());
+    mine(&rpc, 2).await.unwrap();
+    let result = sweep_backup_path(&rpc, &vault, &key(97), &destination).await.unwrap();
+    let spent: Vec<_> = result.transaction.input.iter().map(|i| i.previous_output).collect();
+    assert!(spent.contains(&first.outpoint()) && spent.contains(&second.outpoint()));
+    assert_eq!(spent.len(), result.swept.len());
+    assert!(result.transaction.input.iter().all(|i| i.sequence == Sequence::from_height(3)));
+    assert_eq!(result.transaction.output.len(), 1);
+    let total: Amount = result.swept.iter().map(|u| u.amount).sum();
+    assert_eq!(Amount::from_sat(result.transaction.output[0].value) + result.fee, total);
+
+    let mut receipt = result.receipt.clone();
+    assert_eq!(receipt.witness_sizes.len(), spent.len());
+    let height = mine(&rpc, 1).await.unwrap();
+    assert_eq!(receipt.refresh(&rpc).await.unwrap().map(|b| b.height), Some(height));
+    assert!(sweep_backup_path(&rpc, &vault, &key(97), &destination).await.is_err(), "nothing left to sweep");
+}
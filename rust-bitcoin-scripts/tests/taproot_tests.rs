@@ -0,0 +1,340 @@
+use bitcoin_scripts::amount::from_rpc;
+use bitcoin_scripts::taproot::{self, TaprootVault, TaprootVaultParams, VaultLeaf};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::verify::{verify_spend, VerifyError};
+use bitcoin_scripts::weak_keys::KeyPolicy;
+use bitcoin::absolute::LockTime;
+use bitcoin::blockdata::opcodes::all::{OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CLTV};
+use bitcoin::blockdata::script::Builder;
+use bitcoin::key::{KeyPair, XOnlyPublicKey};
+use bitcoin::secp256k1::{schnorr, Message, Secp256k1, SecretKey};
+use bitcoin::sighash::TapSighashType;
+use bitcoin::taproot::TaprootBuilder;
+use bitcoin::{Address, Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use std::str::FromStr;
+use std::time::Duration;
+
+fn keypair(byte: u8) -> KeyPair {
+    KeyPair::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[byte; 32]).unwrap())
+}
+
+fn x_only(kp: &KeyPair) -> XOnlyPublicKey {
+    kp.x_only_public_key().0
+}
+
+fn policy(kps: &[&KeyPair]) -> KeyPolicy {
+    let keys: Vec<bitcoin::PublicKey> = kps.iter().map(|kp| bitcoin::PublicKey::new(kp.public_key())).collect();
+    KeyPolicy::allow(&keys)
+}
+
+fn params(height: u32) -> TaprootVaultParams {
+    let (internal, immediate, recovery) = (keypair(7), keypair(8), keypair(9));
+    TaprootVaultParams {
+        internal_key: x_only(&internal),
+        immediate_key: x_only(&immediate),
+        recovery_key: x_only(&recovery),
+        recovery_height: height,
+        network: Network::Regtest,
+        key_policy: policy(&[&internal, &immediate, &recovery]),
+    }
+}
+
+fn spend(prev: OutPoint, prevout: &TxOut, lock_time: LockTime, sequence: Sequence, to: ScriptBuf) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time,
+        input: vec![TxIn { previous_output: prev, script_sig: ScriptBuf::new(), sequence, witness: Witness::new() }],
+        output: vec![TxOut { value: prevout.value - 10_000, script_pubkey: to }],
+    }
+}
+
+#[test]
+fn test_matches_hand_built_two_leaf_tree() {
+    let secp = Secp256k1::new();
+    let kp = keypair(7);
+    let key = x_only(&kp);
+    let mut p = TaprootVaultParams::single_key(key, 200, Network::Regtest);
+    p.key_policy = policy(&[&kp]);
+    let vault = TaprootVault::new(&secp, p).unwrap();
+
+    let leaf1 = Builder::new().push_x_only_key(&key).push_opcode(OP_CHECKSIG).into_script();
+    let leaf2 = Builder::new().push_x_only_key(&key).push_opcode(OP_CHECKSIGVERIFY).push_int(200).push_opcode(OP_CLTV).into_script();
+    let spend_info = TaprootBuilder::new().add_leaf(1, leaf1.clone()).unwrap().add_leaf(1, leaf2.clone()).unwrap().finalize(&secp, key).unwrap();
+
+    assert_eq!(vault.address(), Address::p2tr_tweaked(spend_info.output_key(), Network::Regtest));
+    assert_eq!(vault.script(VaultLeaf::Immediate), leaf1);
+    assert_eq!(vault.script(VaultLeaf::Recovery), leaf2);
+    assert_eq!(vault.recovery_lock_time, LockTime::from_height(200).unwrap());
+}
+
+#[test]
+fn test_control_blocks_commit_to_output_key() {
+    let secp = Secp256k1::new();
+    let vault = TaprootVault::new(&secp, params(300)).unwrap();
+    for leaf in [VaultLeaf::Immediate, VaultLeaf::Recovery] {
+        let cb = vault.control_block_for(leaf);
+        assert_eq!(cb.internal_key, vault.internal_key);
+        assert!(cb.verify_taproot_commitment(&secp, vault.output_key().to_inner(), &vault.script(leaf)));
+    }
+}
+
+#[test]
+fn test_rejects_weak_keys_and_time_based_recovery() {
+    let secp = Secp256k1::new();
+    let mut p = params(300);
+    p.key_policy = KeyPolicy::default();
+    assert!(TaprootVault::new(&secp, p).is_err());
+    assert!(TaprootVault::new(&secp, params(600_000_000)).is_err(), "recovery lock must be a block height");
+}
+
+#[test]
+fn test_signed_witnesses_verify() {
+    let secp = Secp256k1::new();
+    let vault = TaprootVault::new(&secp, params(300)).unwrap();
+    let prevout = TxOut { value: 100_000, script_pubkey: vault.script_pubkey() };
+    let tx = spend(OutPoint::new(Txid::from_str(&"11".repeat(32)).unwrap(), 0), &prevout, LockTime::from_height(300).unwrap(), Sequence::ENABLE_LOCKTIME_NO_RBF, prevout.script_pubkey.clone());
+    let prevouts = [prevout];
+
+    let witness = vault.sign_leaf(&secp, &tx, 0, &prevouts, VaultLeaf::Recovery, &keypair(9)).unwrap();
+    assert_eq!(witness.len(), 3);
+    assert_eq!(witness.nth(1).unwrap(), vault.script(VaultLeaf::Recovery).as_bytes());
+    assert_eq!(witness.nth(2).unwrap(), vault.control_block_for(VaultLeaf::Recovery).serialize().as_slice());
+    let sighash = vault.sighash_for_leaf(&tx, 0, &prevouts, VaultLeaf::Recovery).unwrap();
+    let sig = schnorr::Signature::from_slice(witness.nth(0).unwrap()).unwrap();
+    secp.verify_schnorr(&sig, &Message::from_slice(sighash.as_ref()).unwrap(), &vault.recovery_key).unwrap();
+    assert!(vault.sign_leaf(&secp, &tx, 0, &prevouts, VaultLeaf::Recovery, &keypair(8)).is_err());
+
+    let witness = vault.sign_key_spend(&secp, &tx, 0, &prevouts, &keypair(7)).unwrap();
+    assert_eq!(witness.len(), 1);
+    let sighash = vault.key_spend_sighash(&tx, 0, &prevouts).unwrap();
+    let sig = schnorr::Signature::from_slice(witness.nth(0).unwrap()).unwrap();
+    secp.verify_schnorr(&sig, &Message::from_slice(sighash.as_ref()).unwrap(), &vault.output_key().to_inner()).unwrap();
+}
+
+/// One input per vault: key path on the first, the immediate leaf on the second and the recovery
+/// leaf on the third, with the lock time set for the recovery leaf
+fn mixed_path_spend(vaults: &[TaprootVault; 3], outpoints: &[OutPoint], prevouts: &[TxOut], to: ScriptBuf) -> Transaction {
+    let secp = Secp256k1::new();
+    let mut tx = Transaction {
+        version: 2,
+        lock_time: vaults[2].recovery_lock_time,
+        input: outpoints.iter().map(|o| TxIn { previous_output: *o, script_sig: ScriptBuf::new(), sequence: Sequence::ENABLE_RBF_NO_LOCKTIME, witness: Witness::new() }).collect(),
+        output: vec![TxOut { value: prevouts.iter().map(|p| p.value).sum::<u64>() - 30_000, script_pubkey: to }],
+    };
+    let spends = [
+        (0, vaults[0].input_spend(None, keypair(7))),
+        (1, vaults[1].input_spend(Some(VaultLeaf::Immediate), keypair(8))),
+        (2, vaults[2].input_spend(Some(VaultLeaf::Recovery), keypair(9))),
+    ];
+    taproot::sign_inputs(&secp, &mut tx, prevouts, &spends, TapSighashType::Default).unwrap();
+    tx
+}
+
+#[test]
+fn test_sign_inputs_along_mixed_paths() {
+    let secp = Secp256k1::new();
+    let vaults = [300, 310, 320].map(|h| TaprootVault::new(&secp, params(h)).unwrap());
+    let outpoints: Vec<OutPoint> = (0..3).map(|i| OutPoint::new(Txid::from_str(&"11".repeat(32)).unwrap(), i)).collect();
+    let prevouts: Vec<TxOut> = vaults.iter().map(|v| TxOut { value: 100_000, script_pubkey: v.script_pubkey() }).collect();
+    let tx = mixed_path_spend(&vaults, &outpoints, &prevouts, vaults[0].script_pubkey());
+
+    assert_eq!(tx.input.iter().map(|i| i.witness.len()).collect::<Vec<_>>(), vec![1, 3, 3]);
+    assert_eq!(tx.input[1].witness.nth(2).unwrap(), vaults[1].control_block_for(VaultLeaf::Immediate).serialize().as_slice());
+    assert_eq!(tx.input[2].witness.nth(2).unwrap(), vaults[2].control_block_for(VaultLeaf::Recovery).serialize().as_slice());
+    verify_spend(&tx, &prevouts).unwrap();
+
+    // Each input is checked against its own prevout, and every input commits to all of them
+    let mut unsigned = tx.clone();
+    let spends = [(0, vaults[1].input_spend(Some(VaultLeaf::Immediate), keypair(8)))];
+    assert!(taproot::sign_inputs(&secp, &mut unsigned, &prevouts, &spends, TapSighashType::Default).is_err());
+    let spends = [(1, vaults[1].input_spend(Some(VaultLeaf::Immediate), keypair(8))), (1, vaults[1].input_spend(None, keypair(7)))];
+    assert!(taproot::sign_inputs(&secp, &mut unsigned, &prevouts, &spends, TapSighashType::Default).is_err());
+    assert!(taproot::sign_inputs(&secp, &mut unsigned, &prevouts[..2], &[], TapSighashType::Default).is_err());
+    let mut other_amounts = prevouts.clone();
+    other_amounts[2].value += 1;
+    match verify_spend(&tx, &other_amounts) {
+        Err(VerifyError::Inputs(failures)) => assert_eq!(failures.len(), 3),
+        other => panic!("expected every input to fail, got {:?}", other),
+    }
+}
+
+async fn fund(rpc: &BitcoinRPC, vault: &TaprootVault, miner: &str) -> (OutPoint, TxOut) {
+    let txid = rpc.send_to_address(&vault.address().to_string(), Amount::from_sat(10_000_000)).await.unwrap();
+    rpc.generate_to_address(1, miner).await.unwrap();
+    let raw = rpc.call_rpc("getrawtransaction", serde_json::json!([txid, true])).await.unwrap();
+    let vout = raw["vout"].as_array().unwrap().iter()
+        .position(|o| o["scriptPubKey"]["address"].as_str() == Some(&vault.address().to_string()))
+        .unwrap();
+    let value = from_rpc(&raw["vout"][vout]["value"]).unwrap().to_sat();
+    (OutPoint::new(Txid::from_str(&txid).unwrap(), vout as u32), TxOut { value, script_pubkey: vault.script_pubkey() })
+}
+
+#[tokio::test]
+async fn test_taproot_vault_spends_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("taproot_vault_wallet").await;
+    let _ = rpc.load_wallet("taproot_vault_wallet").await;
+    let rpc = rpc.with_wallet("taproot_vault_wallet");
+    let miner = rpc.get_new_address().await.unwrap();
+    rpc.generate_to_address(101, &miner).await.unwrap();
+    let height = rpc.call_rpc("getblockcount", serde_json::json!([])).await.unwrap().as_u64().unwrap() as u32;
+
+    let secp = Secp256k1::new();
+    let vault = TaprootVault::new(&secp, params(height + 10)).unwrap();
+    let to = Address::from_str(&miner).unwrap().require_network(Network::Regtest).unwrap().script_pubkey();
+
+    // Immediate leaf
+    let (outpoint, prevout) = fund(&rpc, &vault, &miner).await;
+    let mut tx = spend(outpoint, &prevout, LockTime::ZERO, Sequence::ENABLE_RBF_NO_LOCKTIME, to.clone());
+    tx.input[0].witness = vault.sign_leaf(&secp, &tx, 0, &[prevout], VaultLeaf::Immediate, &keypair(8)).unwrap();
+    rpc.send_raw_transaction(&bitcoin::consensus::encode::serialize_hex(&tx)).await.unwrap();
+
+    // Key path
+    let (outpoint, prevout) = fund(&rpc, &vault, &miner).await;
+    let mut tx = spend(outpoint, &prevout, LockTime::ZERO, Sequence::ENABLE_RBF_NO_LOCKTIME, to.clone());
+    tx.input[0].witness = vault.sign_key_spend(&secp, &tx, 0, &[prevout], &keypair(7)).unwrap();
+    rpc.send_raw_transaction(&bitcoin::consensus::encode::serialize_hex(&tx)).await.unwrap();
+
+    // Recovery leaf: non-final until the lock height is reached
+    let (outpoint, prevout) = fund(&rpc, &vault, &miner).await;
+    let mut tx = spend(outpoint, &prevout, vault.recovery_lock_time, Sequence::ENABLE_LOCKTIME_NO_RBF, to);
+    tx.input[0].witness = vault.sign_leaf(&secp, &tx, 0, &[prevout], VaultLeaf::Recovery, &keypair(9)).unwrap();
+    let hex = bitcoin::consensus::encode::serialize_hex(&tx);
+    assert!(rpc.send_raw_transaction(&hex).await.is_err());
+    rpc.generate_to_address(10, &miner).await.unwrap();
+    rpc.send_raw_transaction(&hex).await.unwrap();
+    rpc.generate_to_address(1, &miner).await.unwrap();
+    rpc.wait_for_confirmations(&tx.txid(), 1, Duration::from_secs(30)).await.unwrap();
+}
+
+#[test]
+fn test_spend_script_path_on_arbitrary_tree_with_sighash_flags() {
+    use bitcoin::sighash::TapSighashType;
+    use bitcoin_scripts::taproot::spend_script_path;
+    use bitcoin_scripts::verify::verify_spend;
+
+    let secp = Secp256k1::new();
+    let leaf = |kp: &KeyPair| Builder::new().push_x_only_key(&x_only(kp)).push_opcode(OP_CHECKSIG).into_script();
+    // `and_v(v:pk(13),after(10))`: `verify_spend` only evaluates miniscript leaves
+    let timelocked = Builder::new().push_x_only_key(&x_only(&keypair(13))).push_opcode(OP_CHECKSIGVERIFY).push_int(10).push_opcode(OP_CLTV).into_script();
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(1, leaf(&keypair(11))).unwrap()
+        .add_leaf(2, leaf(&keypair(12))).unwrap()
+        .add_leaf(2, timelocked.clone()).unwrap()
+        .finalize(&secp, x_only(&keypair(10))).unwrap();
+    let prevout = TxOut { value: 100_000, script_pubkey: ScriptBuf::new_v1_p2tr_tweaked(spend_info.output_key()) };
+    let tx = spend(OutPoint::new(Txid::from_str(&"22".repeat(32)).unwrap(), 0), &prevout, LockTime::from_height(10).unwrap(), Sequence::ENABLE_LOCKTIME_NO_RBF, prevout.script_pubkey.clone());
+    let prevouts = [prevout];
+
+    for (script, kp, hash_ty) in [
+        (leaf(&keypair(12)), keypair(12), TapSighashType::SinglePlusAnyoneCanPay),
+        (timelocked.clone(), keypair(13), TapSighashType::All),
+        (leaf(&keypair(11)), keypair(11), TapSighashType::Default),
+    ] {
+        let mut signed = tx.clone();
+        signed.input[0].witness = spend_script_path(&secp, &spend_info, &script, &kp, &tx, 0, &prevouts, hash_ty).unwrap();
+        let sig_len = if hash_ty == TapSighashType::Default { 64 } else { 65 };
+        assert_eq!(signed.input[0].witness.nth(0).unwrap().len(), sig_len);
+        assert_eq!(verify_spend(&signed, &prevouts), Ok(()), "{:?}", hash_ty);
+    }
+
+    // Wrong key for the leaf, and a leaf outside the tree
+    assert!(spend_script_path(&secp, &spend_info, &leaf(&keypair(11)), &keypair(12), &tx, 0, &prevouts, TapSighashType::Default).is_err());
+    assert!(spend_script_path(&secp, &spend_info, &leaf(&keypair(14)), &keypair(14), &tx, 0, &prevouts, TapSighashType::Default).is_err());
+}
+
+#[test]
+fn test_spend_key_path_tweaks_and_appends_sighash_flag() {
+    use bitcoin::key::TapTweak;
+    use bitcoin::sighash::TapSighashType;
+    use bitcoin_scripts::taproot::spend_key_path;
+    use bitcoin_scripts::verify::verify_spend;
+
+    let secp = Secp256k1::new();
+    let internal = keypair(20);
+    let tree = TaprootBuilder::new()
+        .add_leaf(0, Builder::new().push_x_only_key(&x_only(&keypair(21))).push_opcode(OP_CHECKSIG).into_script()).unwrap()
+        .finalize(&secp, x_only(&internal)).unwrap();
+    let key_only = ScriptBuf::new_v1_p2tr(&secp, x_only(&internal), None);
+
+    for (script_pubkey, merkle_root) in [(ScriptBuf::new_v1_p2tr_tweaked(tree.output_key()), tree.merkle_root()), (key_only, None)] {
+        let prevout = TxOut { value: 100_000, script_pubkey };
+        let tx = spend(OutPoint::new(Txid::from_str(&"33".repeat(32)).unwrap(), 0), &prevout, LockTime::ZERO, Sequence::MAX, prevout.script_pubkey.clone());
+        let prevouts = [prevout];
+        for hash_ty in [TapSighashType::Default, TapSighashType::All, TapSighashType::None, TapSighashType::Single, TapSighashType::AllPlusAnyoneCanPay, TapSighashType::NonePlusAnyoneCanPay, TapSighashType::SinglePlusAnyoneCanPay] {
+            let mut signed = tx.clone();
+            signed.input[0].witness = spend_key_path(&secp, &internal, merkle_root, &tx, 0, &prevouts, hash_ty).unwrap();
+            let sig = signed.input[0].witness.nth(0).unwrap();
+            assert_eq!(sig.len(), if hash_ty == TapSighashType::Default { 64 } else { 65 });
+            if hash_ty != TapSighashType::Default {
+                assert_eq!(sig[64], hash_ty as u8);
+            }
+            assert_eq!(verify_spend(&signed, &prevouts), Ok(()), "{:?}", hash_ty);
+        }
+        // Already tweaked, or the wrong tree: rejected before signing
+        let tweaked = internal.tap_tweak(&secp, merkle_root).to_inner();
+        assert!(spend_key_path(&secp, &tweaked, merkle_root, &tx, 0, &prevouts, TapSighashType::Default).is_err());
+        assert!(spend_key_path(&secp, &internal, merkle_root, &tx, 1, &prevouts, TapSighashType::Default).is_err());
+    }
+}
+
+#[test]
+fn test_key_path_tweak_only_signs_with_the_internal_key() {
+    use bitcoin::key::TapTweak;
+    use bitcoin::sighash::TapSighashType;
+    use bitcoin_scripts::taproot::KeyPathTweak;
+    use bitcoin_scripts::verify::verify_spend;
+
+    let secp = Secp256k1::new();
+    let vault = TaprootVault::new(&secp, params(200)).unwrap();
+    let tweak = vault.key_path_tweak();
+    assert_eq!(tweak.internal_key, x_only(&keypair(7)));
+    assert_eq!(tweak.output_key, vault.output_key().to_inner());
+    assert_eq!(tweak.script_pubkey(), vault.script_pubkey());
+    assert_eq!(KeyPathTweak::new(&secp, tweak.internal_key, tweak.merkle_root), tweak);
+
+    // Serialized form round-trips
+    let json = serde_json::to_string(&tweak).unwrap();
+    assert_eq!(serde_json::from_str::<KeyPathTweak>(&json).unwrap(), tweak);
+
+    let prevout = TxOut { value: 100_000, script_pubkey: vault.script_pubkey() };
+    let tx = spend(OutPoint::new(Txid::from_str(&"44".repeat(32)).unwrap(), 0), &prevout, LockTime::ZERO, Sequence::MAX, prevout.script_pubkey.clone());
+    let prevouts = [prevout];
+    let signer = tweak.signer(&secp, &keypair(7)).unwrap();
+    let mut signed = tx.clone();
+    signed.input[0].witness = signer.sign(&secp, &tx, 0, &prevouts, TapSighashType::Default).unwrap();
+    assert_eq!(verify_spend(&signed, &prevouts), Ok(()));
+
+    // An already-tweaked keypair or an unrelated key never gets a signer
+    let tweaked = keypair(7).tap_tweak(&secp, tweak.merkle_root).to_inner();
+    assert!(tweak.signer(&secp, &tweaked).unwrap_err().to_string().contains("already tweaked"));
+    assert!(tweak.signer(&secp, &keypair(8)).is_err());
+    assert!(vault.sign_key_spend(&secp, &tx, 0, &prevouts, &tweaked).is_err());
+}
+
+#[tokio::test]
+async fn test_mixed_path_taproot_spend_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("taproot_vault_wallet").await;
+    let _ = rpc.load_wallet("taproot_vault_wallet").await;
+    let rpc = rpc.with_wallet("taproot_vault_wallet");
+    let miner = rpc.get_new_address().await.unwrap();
+    rpc.generate_to_address(101, &miner).await.unwrap();
+    let height = rpc.call_rpc("getblockcount", serde_json::json!([])).await.unwrap().as_u64().unwrap() as u32;
+
+    // Three trees, so every input has its own output key and control block
+    let secp = Secp256k1::new();
+    let vaults = [height + 100, height + 200, height].map(|h| TaprootVault::new(&secp, params(h)).unwrap());
+    let mut outpoints = Vec::new();
+    let mut prevouts = Vec::new();
+    for vault in &vaults {
+        let (outpoint, prevout) = fund(&rpc, vault, &miner).await;
+        outpoints.push(outpoint);
+        prevouts.push(prevout);
+    }
+    let to = Address::from_str(&miner).unwrap().require_network(Network::Regtest).unwrap().script_pubkey();
+    let tx = mixed_path_spend(&vaults, &outpoints, &prevouts, to);
+    rpc.send_raw_transaction(&bitcoin::consensus::encode::serialize_hex(&tx)).await.unwrap();
+    rpc.generate_to_address(1, &miner).await.unwrap();
+    rpc.wait_for_confirmations(&tx.txid(), 1, Duration::from_secs(30)).await.unwrap();
+}
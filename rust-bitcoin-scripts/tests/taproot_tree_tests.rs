@@ -0,0 +1,105 @@
+use bitcoin_scripts::taproot_tree::{encode_merkle_path, merkle_root, spend_info_merkle_path, tapbranch, tapleaf_hash, verify_merkle_path, ScriptTree, TreeError, TreeLimits, TreeOutput, MAX_TREE_DEPTH};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::opcodes::all::OP_PUSHNUM_1;
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{KeyPair, Secp256k1, SecretKey};
+use bitcoin::taproot::{LeafVersion, TapNodeHash, TaprootBuilder};
+use bitcoin::ScriptBuf;
+
+fn script(n: i64) -> ScriptBuf {
+    Builder::new().push_int(n).push_opcode(OP_PUSHNUM_1).into_script()
+}
+
+#[test]
+fn test_from_depths_builds_expected_shape() {
+    let tree = ScriptTree::from_depths(&[(1, script(1)), (2, script(2)), (2, script(3))], &TreeLimits::default()).unwrap();
+    let depths: Vec<usize> = tree.leaves().iter().map(|l| l.depth).collect();
+    assert_eq!(depths, vec![1, 2, 2]);
+    assert_eq!(
+        ScriptTree::from_depths(&[(1, script(1)), (1, script(2)), (1, script(3))], &TreeLimits::default()),
+        Err(TreeError::IncompleteTree)
+    );
+    assert_eq!(ScriptTree::from_depths(&[(1, script(1))], &TreeLimits::default()), Err(TreeError::IncompleteTree));
+    assert_eq!(ScriptTree::from_depths(&[], &TreeLimits::default()), Err(TreeError::Empty));
+}
+
+#[test]
+fn test_limits_are_enforced_and_overridable() {
+    // A left-leaning chain: one leaf at each depth 1..=n and two at depth n
+    let chain = |n: usize| -> Vec<(usize, ScriptBuf)> {
+        let mut leaves: Vec<(usize, ScriptBuf)> = (1..n).map(|d| (d, script(d as i64))).collect();
+        leaves.push((n, script(1000)));
+        leaves.push((n, script(1001)));
+        leaves
+    };
+    let defaults = TreeLimits::default();
+    assert!(ScriptTree::from_depths(&chain(MAX_TREE_DEPTH), &defaults).is_ok());
+    assert_eq!(
+        ScriptTree::from_depths(&chain(MAX_TREE_DEPTH + 1), &defaults),
+        Err(TreeError::DepthExceeded { depth: MAX_TREE_DEPTH + 1, max: MAX_TREE_DEPTH })
+    );
+    let loose = TreeLimits { max_depth: 1000, ..TreeLimits::default() };
+    assert!(ScriptTree::from_depths(&chain(MAX_TREE_DEPTH + 1), &loose).is_err(), "consensus depth cannot be overridden");
+
+    let strict = TreeLimits { max_depth: 4, max_leaves: 3, ..TreeLimits::default() };
+    assert_eq!(ScriptTree::from_depths(&chain(5), &strict), Err(TreeError::TooManyLeaves { count: 6, max: 3 }));
+    assert_eq!(
+        ScriptTree::from_depths(&chain(2), &strict),
+        Ok(ScriptTree::branch(ScriptTree::leaf(script(1)), ScriptTree::branch(ScriptTree::leaf(script(1000)), ScriptTree::leaf(script(1001)))))
+    );
+}
+
+#[test]
+fn test_duplicate_leaves_get_distinct_control_blocks() {
+    let secp = Secp256k1::new();
+    let internal_key = XOnlyPublicKey::from_keypair(&KeyPair::from_secret_key(&secp, &SecretKey::from_slice(&[5; 32]).unwrap())).0;
+    let leaves = [(1, script(7)), (2, script(7)), (2, script(8))];
+
+    match ScriptTree::from_depths(&leaves, &TreeLimits::default()) {
+        Err(TreeError::DuplicateLeaf { first: 0, second: 1, .. }) => {}
+        other => panic!("expected duplicate leaf error, got {:?}", other),
+    }
+
+    let allow = TreeLimits { allow_duplicate_leaves: true, ..TreeLimits::default() };
+    let tree = ScriptTree::from_depths(&leaves, &allow).unwrap();
+    assert!(TreeOutput::checked(&secp, internal_key, &tree, &TreeLimits::default()).is_err());
+    let output = TreeOutput::checked(&secp, internal_key, &tree, &allow).unwrap();
+    let first = output.control_block(0).unwrap();
+    let second = output.control_block(1).unwrap();
+    assert_ne!(first, second);
+    for control_block in [first, second] {
+        assert!(control_block.verify_taproot_commitment(&secp, output.output_key.to_inner(), &script(7)));
+    }
+}
+
+#[test]
+fn test_standalone_hashes_match_builder_spend_info() {
+    let secp = Secp256k1::new();
+    let internal_key = XOnlyPublicKey::from_keypair(&KeyPair::from_secret_key(&secp, &SecretKey::from_slice(&[6; 32]).unwrap())).0;
+    let leaves = [(1, script(1)), (2, script(2)), (2, script(3))];
+    let tree = ScriptTree::from_depths(&leaves, &TreeLimits::default()).unwrap();
+    let spend_info = leaves.iter()
+        .fold(TaprootBuilder::new(), |b, (depth, s)| b.add_leaf(*depth as u8, s.clone()).unwrap())
+        .finalize(&secp, internal_key).unwrap();
+
+    let (a, b, c) = (tapleaf_hash(&script(1), LeafVersion::TapScript), tapleaf_hash(&script(2), LeafVersion::TapScript), tapleaf_hash(&script(3), LeafVersion::TapScript));
+    let bc = tapbranch(b.into(), c.into());
+    assert_eq!(bc, tapbranch(c.into(), b.into()));
+    let root = tapbranch(a.into(), bc);
+    assert_eq!(spend_info.merkle_root(), Some(root));
+    assert_eq!(tree.node_hash(), root);
+
+    for (_, s) in &leaves {
+        let leaf_hash = tapleaf_hash(s, LeafVersion::TapScript);
+        let from_spend_info = spend_info_merkle_path(&spend_info, s, LeafVersion::TapScript).unwrap();
+        assert_eq!(tree.merkle_path(s, LeafVersion::TapScript), Some(from_spend_info.clone()));
+        assert_eq!(merkle_root(leaf_hash, &from_spend_info), root);
+        assert!(verify_merkle_path(root, leaf_hash, &from_spend_info));
+        assert_eq!(encode_merkle_path(&from_spend_info).len(), 32 * from_spend_info.len());
+    }
+    let path_b = tree.merkle_path(&script(2), LeafVersion::TapScript).unwrap();
+    assert_eq!(path_b, vec![TapNodeHash::from(c), TapNodeHash::from(a)]);
+    assert!(!verify_merkle_path(root, c, &path_b));
+    assert!(tree.merkle_path(&script(4), LeafVersion::TapScript).is_none());
+    assert!(spend_info_merkle_path(&spend_info, &script(4), LeafVersion::TapScript).is_none());
+}
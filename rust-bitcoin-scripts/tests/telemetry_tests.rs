@@ -0,0 +1,149 @@
+use bitcoin_scripts::signing_round::SigningRound;
+use bitcoin_scripts::telemetry::{self, OtlpConfig, OtlpExporter, LATENCY_BOUNDS};
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{absolute::LockTime, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+// Recording is process-wide; tests that switch it run one at a time
+static MODE: Mutex<()> = Mutex::new(());
+
+fn unsigned_psbt() -> Psbt {
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 3), script_sig: ScriptBuf::new(), sequence: Sequence::ENABLE_RBF_NO_LOCKTIME, witness: Witness::default() }],
+        output: vec![TxOut { value: 10_000, script_pubkey: ScriptBuf::new() }],
+    };
+    Psbt::from_unsigned_tx(tx).unwrap()
+}
+
+fn signers() -> Vec<String> {
+    vec!["alice".to_string(), "bob".to_string(), "carol".to_string()]
+}
+
+/// `key -> stringValue` of an OTLP attribute list
+fn attributes(value: &Value) -> HashMap<String, String> {
+    value.as_array().unwrap().iter()
+        .map(|a| (a["key"].as_str().unwrap().to_string(), a["value"]["stringValue"].as_str().unwrap().to_string()))
+        .collect()
+}
+
+fn metric<'a>(metrics: &'a Value, name: &str) -> &'a Value {
+    metrics["resourceMetrics"][0]["scopeMetrics"][0]["metrics"].as_array().unwrap().iter().find(|m| m["name"] == name).unwrap()
+}
+
+#[test]
+fn test_signing_rounds_as_otlp_traces_and_metrics() {
+    let _mode = MODE.lock().unwrap_or_else(|e| e.into_inner());
+    telemetry::disable();
+    telemetry::reset();
+    let mut ignored = SigningRound::new("r0", unsigned_psbt(), signers(), 1, Duration::from_secs(60));
+    assert!(ignored.submit("alice", unsigned_psbt()).unwrap());
+    assert!(telemetry::take_spans().is_empty(), "nothing is recorded while disabled");
+
+    telemetry::enable();
+    let mut complete = SigningRound::new("r1", unsigned_psbt(), signers(), 2, Duration::from_secs(60));
+    complete.submit("alice", unsigned_psbt()).unwrap();
+    complete.submit("carol", unsigned_psbt()).unwrap();
+    let mut stalled = SigningRound::new("r2", unsigned_psbt(), signers(), 2, Duration::from_secs(60));
+    stalled.submit("alice", unsigned_psbt()).unwrap();
+    stalled.check_timeout(Instant::now() + Duration::from_secs(120)).unwrap();
+    telemetry::disable();
+
+    let spans = telemetry::take_spans();
+    assert_eq!(spans.len(), 2);
+    let traces = telemetry::traces_json("vault-eu", &spans);
+    let resource = &traces["resourceSpans"][0];
+    assert_eq!(attributes(&resource["resource"]["attributes"])["service.name"], "vault-eu");
+    let exported = resource["scopeSpans"][0]["spans"].as_array().unwrap();
+    assert_eq!(exported[0]["traceId"].as_str().unwrap().len(), 32);
+    assert_eq!(exported[0]["spanId"].as_str().unwrap().len(), 16);
+    assert_eq!(exported[0]["name"], "signing_round");
+    assert_eq!(attributes(&exported[0]["attributes"])["state"], "complete");
+    assert_eq!(exported[0]["status"]["code"], 1);
+    assert_eq!(attributes(&exported[1]["attributes"])["round.id"], "r2");
+    assert_eq!(exported[1]["status"]["code"], 2);
+    assert!(exported[1]["status"]["message"].as_str().unwrap().contains("bob, carol"));
+    // A stalled round's span covers its whole window
+    let start: u64 = exported[1]["startTimeUnixNano"].as_str().unwrap().parse().unwrap();
+    let end: u64 = exported[1]["endTimeUnixNano"].as_str().unwrap().parse().unwrap();
+    assert!(end - start >= 120_000_000_000);
+
+    let metrics = telemetry::metrics_json("vault-eu");
+    let rounds = metric(&metrics, "wrapyield.signing_rounds");
+    assert_eq!(rounds["sum"]["isMonotonic"], true);
+    assert_eq!(rounds["sum"]["dataPoints"].as_array().unwrap().len(), 2);
+    assert!(rounds["sum"]["dataPoints"].as_array().unwrap().iter().all(|p| p["asInt"] == "1"));
+    let durations = metric(&metrics, "wrapyield.signing_round.duration");
+    assert_eq!(durations["unit"], "s");
+    let stalled_point = durations["histogram"]["dataPoints"].as_array().unwrap().iter()
+        .find(|p| attributes(&p["attributes"])["state"] == "stalled").unwrap();
+    assert_eq!(stalled_point["count"], "1");
+    assert_eq!(stalled_point["bucketCounts"][LATENCY_BOUNDS.len()], "1", "120s is above every bound");
+    telemetry::reset();
+}
+
+#[test]
+fn test_otlp_config_from_standard_variables() {
+    let vars = |pairs: &'static [(&'static str, &'static str)]| move |name: &str| pairs.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string());
+    assert_eq!(OtlpConfig::from_vars(vars(&[])).unwrap(), None);
+    assert_eq!(OtlpConfig::from_vars(vars(&[("OTEL_EXPORTER_OTLP_ENDPOINT", " ")])).unwrap(), None);
+
+    let config = OtlpConfig::from_vars(vars(&[
+        ("OTEL_EXPORTER_OTLP_ENDPOINT", "https://otel.example:4318/"),
+        ("OTEL_EXPORTER_OTLP_HEADERS", "x-api-key=abc, x-tenant = eu"),
+        ("OTEL_SERVICE_NAME", "vault-eu"),
+    ])).unwrap().unwrap();
+    assert_eq!(config.endpoint, "https://otel.example:4318");
+    assert_eq!(config.service_name, "vault-eu");
+    assert_eq!(config.headers, vec![("x-api-key".to_string(), "abc".to_string()), ("x-tenant".to_string(), "eu".to_string())]);
+    assert_eq!(OtlpConfig::from_vars(vars(&[("OTEL_EXPORTER_OTLP_ENDPOINT", "http://localhost:4318")])).unwrap().unwrap().service_name, "wrapyield");
+    assert!(OtlpConfig::from_vars(vars(&[("OTEL_EXPORTER_OTLP_ENDPOINT", "http://localhost:4318"), ("OTEL_EXPORTER_OTLP_HEADERS", "novalue")])).is_err());
+}
+
+#[tokio::test]
+#[allow(clippy::await_holding_lock)] // only serializes against the synchronous tests above
+async fn test_export_keeps_spans_the_collector_refused() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = OtlpConfig::new(&format!("http://{}", listener.local_addr().unwrap()));
+    config.headers.push(("x-api-key".to_string(), "abc".to_string()));
+    let server = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for status in ["503 Service Unavailable", "200 OK", "200 OK"] {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 65536];
+            let n = socket.read(&mut buf).await.unwrap();
+            requests.push(String::from_utf8_lossy(&buf[..n]).to_string());
+            let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+        requests
+    });
+
+    let _mode = MODE.lock().unwrap_or_else(|e| e.into_inner());
+    telemetry::reset();
+    telemetry::enable();
+    telemetry::record_timed("rpc getblockcount", "wrapyield.rpc.duration", Duration::from_millis(3), &[("rpc.method", "getblockcount".to_string())], None);
+    telemetry::set_gauge("wrapyield.scanner.height", 812.0, &[]);
+    telemetry::disable();
+
+    let exporter = OtlpExporter::new(config);
+    assert!(exporter.export().await.unwrap_err().to_string().contains("503"));
+    let summary = exporter.export().await.unwrap();
+    assert_eq!((summary.spans, summary.metrics), (1, 2));
+    assert!(telemetry::take_spans().is_empty());
+    telemetry::reset();
+
+    let requests = server.await.unwrap();
+    assert!(requests[0].starts_with("POST /v1/traces") && requests[1].starts_with("POST /v1/traces"));
+    assert!(requests[2].starts_with("POST /v1/metrics"));
+    assert!(requests.iter().all(|r| r.to_lowercase().contains("x-api-key: abc")));
+    assert!(requests[1].contains("\"kind\":3") && requests[1].contains("getblockcount"));
+    assert!(requests[2].contains("\"asDouble\":812.0") && requests[2].contains("\"explicitBounds\""));
+}
@@ -0,0 +1,69 @@
+use bitcoin_scripts::templates::{build, negotiate, TemplateParams, VersionedVault};
+use bitcoin_scripts::weak_keys::KeyPolicy;
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+
+fn params() -> TemplateParams {
+    let secp = secp256k1::Secp256k1::new();
+    let keys: Vec<PublicKey> = [5u8, 6, 7, 8, 9].iter()
+        .map(|b| PublicKey::from_private_key(&secp, &PrivateKey::new(secp256k1::SecretKey::from_slice(&[*b; 32]).unwrap(), Network::Regtest)))
+        .collect();
+    TemplateParams {
+        internal_key: keys[3],
+        federation: keys[0..3].to_vec(),
+        threshold: 2,
+        federation_csv: 10,
+        recovery_key: Some(keys[4]),
+        recovery_csv: 1000,
+        key_policy: KeyPolicy::allow(&keys),
+    }
+}
+
+fn leaf_count(descriptor: &Descriptor<PublicKey>) -> usize {
+    match descriptor {
+        Descriptor::Tr(tr) => tr.iter_scripts().count(),
+        _ => 0,
+    }
+}
+
+#[test]
+fn test_negotiation_picks_highest_common_version() {
+    assert_eq!(negotiate(&[1, 2], &[1, 2, 3]), Some(2));
+    assert_eq!(negotiate(&[1, 2], &[1]), Some(1));
+    assert_eq!(negotiate(&[2], &[1]), None);
+}
+
+#[test]
+fn test_upgrade_keeps_old_descriptor_spendable() {
+    let params = params();
+    let mut vault = VersionedVault::new(1, &params).unwrap();
+    assert_eq!(leaf_count(&vault.descriptor), 1);
+    let v1_script = vault.descriptor.script_pubkey();
+
+    assert_eq!(vault.upgrade(&params, &[1]).unwrap(), None, "counterparty only speaks v1");
+    assert_eq!(vault.upgrade(&params, &[1, 2]).unwrap(), Some(2));
+    assert_eq!(leaf_count(&vault.descriptor), 2);
+    assert_ne!(vault.descriptor.script_pubkey(), v1_script);
+
+    let (version, old) = vault.descriptor_for_script(&v1_script).unwrap();
+    assert_eq!(version, 1);
+    assert_eq!(old, &build(1, &params).unwrap());
+    assert_eq!(vault.upgrade(&params, &[1, 2]).unwrap(), None, "already current");
+}
+
+#[test]
+fn test_v2_requires_longer_recovery_delay() {
+    let mut params = params();
+    params.recovery_csv = 5;
+    assert!(build(2, &params).is_err());
+    params.recovery_key = None;
+    assert!(build(2, &params).is_err());
+    assert!(build(3, &params).is_err());
+}
+
+#[test]
+fn test_fixture_keys_need_the_allowlist() {
+    let mut params = params();
+    params.key_policy = KeyPolicy::default();
+    assert!(build(1, &params).is_err(), "[5; 32]-style keys are rejected outside the allowlist");
+}
@@ -0,0 +1,76 @@
+use bitcoin_scripts::scanner::ScanEvent;
+use bitcoin_scripts::tenancy::{FeePolicy, VaultConfig, VaultRegistry};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::descriptor::DescriptorPublicKey;
+use miniscript::Descriptor;
+use serde_json::json;
+use std::str::FromStr;
+
+fn vault(id: &str, key_byte: u8) -> VaultConfig {
+    let secp = secp256k1::Secp256k1::new();
+    let pk = PublicKey::from_private_key(&secp, &PrivateKey::new(secp256k1::SecretKey::from_slice(&[key_byte; 32]).unwrap(), Network::Regtest));
+    VaultConfig {
+        id: id.to_string(),
+        descriptor: Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({})", pk)).unwrap(),
+        min_confirmations: 6,
+        fee_policy: FeePolicy::default(),
+        signers: vec![format!("{}-signer", id)],
+        quorum: 1,
+        lookahead: 1,
+    }
+}
+
+#[tokio::test]
+async fn test_events_are_routed_per_vault() {
+    let mut registry = VaultRegistry::new();
+    registry.add(vault("client-a", 5)).unwrap();
+    registry.add(vault("client-b", 6)).unwrap();
+    assert!(registry.add(vault("client-c", 5)).is_err(), "vaults may not share scripts");
+    assert!(registry.add(vault("client-a", 7)).is_err());
+
+    let mut a_events = registry.subscribe("client-a").unwrap();
+    let mut b_events = registry.subscribe("client-b").unwrap();
+
+    let script = |id: &str| registry.config(id).unwrap().descriptor.at_derivation_index(0).unwrap().script_pubkey();
+    let (script_a, script_b) = (script("client-a"), script("client-b"));
+    let funding_txid = "11".repeat(32);
+    let block = json!({
+        "height": 200,
+        "tx": [
+            {
+                "txid": funding_txid,
+                "vin": [{"coinbase": "00"}],
+                "vout": [
+                    {"value": 0.5, "scriptPubKey": {"hex": hex::encode(script_a.as_bytes())}},
+                    {"value": 0.25, "scriptPubKey": {"hex": hex::encode(script_b.as_bytes())}},
+                ],
+            },
+            {
+                "txid": "22".repeat(32),
+                "vin": [{"txid": funding_txid, "vout": 0}],
+                "vout": [],
+            },
+        ],
+    });
+    let rpc = BitcoinRPC::new();
+    let routed = registry.scan_block_json(&rpc, &block).await.unwrap();
+    assert_eq!(routed.len(), 3);
+
+    assert!(matches!(a_events.try_recv().unwrap(), ScanEvent::Deposit { value_sats: 50_000_000, .. }));
+    assert!(matches!(a_events.try_recv().unwrap(), ScanEvent::Spend { value_sats: 50_000_000, .. }));
+    assert!(a_events.try_recv().is_err());
+    assert!(matches!(b_events.try_recv().unwrap(), ScanEvent::Deposit { value_sats: 25_000_000, .. }));
+    assert!(b_events.try_recv().is_err(), "client-b never sees client-a's spend");
+
+    let a = registry.metrics("client-a").unwrap();
+    assert_eq!((a.deposits, a.spends, a.received_sats, a.spent_sats), (1, 1, 50_000_000, 50_000_000));
+    let b = registry.metrics("client-b").unwrap();
+    assert_eq!((b.deposits, b.spends, b.received_sats), (1, 0, 25_000_000));
+
+    assert!(!registry.is_confirmed("client-a", 200, 204));
+    assert!(registry.is_confirmed("client-a", 200, 205));
+
+    registry.remove("client-b").unwrap();
+    assert_eq!(registry.vault_ids(), vec!["client-a".to_string()]);
+}
@@ -0,0 +1,111 @@
+use bitcoin_scripts::flows::{fund_descriptor, mine};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::timeout_tree::{NodeKind, TimeoutTree, TimeoutTreeParams};
+use bitcoin_scripts::verify::verify_spend;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::hashes::Hash;
+use bitcoin::{Amount, OutPoint, PublicKey, ScriptBuf, TxOut, Txid};
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+mod common;
+use common::{key, pk};
+
+/// 2-of-3 federation (keys 1-3) and depositors 11, 12, ... with 100k, 200k, ... sats
+fn tree(users: u8, timeout: u32) -> TimeoutTree {
+    let params = TimeoutTreeParams { federation: vec![pk(1), pk(2), pk(3)], threshold: 2, timeout, node_fee: Amount::from_sat(500) };
+    let deposits: Vec<(PublicKey, Amount)> = (0..users).map(|i| (pk(11 + i), Amount::from_sat(100_000 * (i as u64 + 1)))).collect();
+    TimeoutTree::new(params, &deposits).unwrap()
+}
+
+fn destination() -> ScriptBuf {
+    ScriptBuf::new_v0_p2wpkh(&pk(50).wpubkey_hash().unwrap())
+}
+
+#[test]
+fn test_leaves_are_assigned_and_exits_verify_offline() {
+    let tree = tree(5, 1_000);
+    // 5 leaves, 4 fan-outs
+    assert_eq!(tree.nodes.len(), 9);
+    assert_eq!(tree.root_value(), Amount::from_sat(1_500_000 + 4 * 500));
+    let first = tree.assignment(&pk(11)).unwrap();
+    assert_eq!(first.path.first(), Some(&0));
+    assert!(matches!(tree.nodes[first.node].kind, NodeKind::Leaf(0)));
+    assert!(tree.assignments.iter().all(|a| (2..=3).contains(&a.path.len())));
+    assert!(tree.assignment(&pk(50)).is_none());
+
+    let root = OutPoint::new(Txid::from_byte_array([9; 32]), 0);
+    assert!(tree.presign(root, &[key(1)]).is_err(), "one signer is below the threshold");
+    let presigned = tree.presign(root, &[key(1), key(3)]).unwrap();
+    assert_eq!(presigned.fan_outs.len(), 4);
+    assert!(presigned.fan_outs.iter().all(|(_, tx)| tx.lock_time.to_consensus_u32() == 1_000));
+
+    // The exit path chains from the pool down to the leaf, and the leaf spend verifies
+    let path = presigned.exit_path(&pk(15)).unwrap();
+    assert_eq!(path[0].input[0].previous_output, root);
+    for pair in path.windows(2) {
+        assert_eq!(pair[1].input[0].previous_output.txid, pair[0].txid());
+    }
+    let assignment = tree.assignment(&pk(15)).unwrap();
+    let leaf = &tree.nodes[assignment.node];
+    let exit = presigned.exit(&key(15), &destination(), Amount::from_sat(1_000)).unwrap();
+    assert_eq!(exit.input[0].previous_output, presigned.leaf_outpoint(&pk(15)).unwrap());
+    assert_eq!(exit.output[0].value, 499_000);
+    verify_spend(&exit, &[TxOut { value: leaf.value.to_sat(), script_pubkey: leaf.descriptor.script_pubkey() }]).unwrap();
+    assert!(presigned.exit(&key(50), &destination(), Amount::from_sat(1_000)).is_err());
+
+    // Bookkeeping survives a restart
+    let path = std::env::temp_dir().join(format!("wrapyield-timeout-tree-{}.json", std::process::id()));
+    tree.save(&path).unwrap();
+    assert_eq!(TimeoutTree::load(&path).unwrap().unwrap(), tree);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_invalid_trees_are_rejected() {
+    let params = TimeoutTreeParams { federation: vec![pk(1), pk(2)], threshold: 2, timeout: 1_000, node_fee: Amount::from_sat(500) };
+    assert!(TimeoutTree::new(params.clone(), &[]).is_err());
+    assert!(TimeoutTree::new(params.clone(), &[(pk(11), Amount::from_sat(1_000)), (pk(11), Amount::from_sat(2_000))]).is_err());
+    assert!(TimeoutTree::new(TimeoutTreeParams { threshold: 3, ..params.clone() }, &[(pk(11), Amount::from_sat(1_000))]).is_err());
+    assert!(TimeoutTree::new(TimeoutTreeParams { timeout: 600_000_000, ..params.clone() }, &[(pk(11), Amount::from_sat(1_000))]).is_err());
+    // A single depositor's leaf is the pool itself
+    let single = TimeoutTree::new(params, &[(pk(11), Amount::from_sat(1_000))]).unwrap();
+    assert_eq!(single.nodes.len(), 1);
+    assert!(single.assignments[0].path.is_empty());
+}
+
+#[tokio::test]
+async fn test_depositor_exits_alone_after_timeout_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("timeout_tree_wallet").await;
+    let _ = rpc.load_wallet("timeout_tree_wallet").await;
+    let rpc = rpc.with_wallet("timeout_tree_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let timeout = rpc.get_block_count().await.unwrap() as u32 + 10;
+    let tree = tree(3, timeout);
+    let funded = fund_descriptor(&rpc, tree.root_descriptor(), tree.root_value()).await.unwrap();
+    let presigned = tree.presign(funded.outpoint(), &[key(2), key(3)]).unwrap();
+
+    // The federation is gone; depositor 13 leaves on their own
+    let path = presigned.exit_path(&pk(13)).unwrap();
+    assert!(rpc.send_raw_transaction(&serialize_hex(path[0])).await.is_err(), "fan-outs wait for the timeout");
+    let tip = rpc.get_block_count().await.unwrap() as u32;
+    mine(&rpc, timeout - tip).await.unwrap();
+    for fan_out in &path {
+        rpc.send_raw_transaction(&serialize_hex(*fan_out)).await.unwrap();
+    }
+    let exit = presigned.exit(&key(13), &destination(), Amount::from_sat(1_000)).unwrap();
+    rpc.send_raw_transaction(&serialize_hex(&exit)).await.unwrap();
+    mine(&rpc, 1).await.unwrap();
+
+    let paid: Descriptor<PublicKey> = Descriptor::from_str(&format!("wpkh({})", pk(50))).unwrap();
+    let paid = rpc.find_utxos_for_descriptor(&paid).await.unwrap();
+    assert!(paid.iter().any(|u| u.outpoint == OutPoint::new(exit.txid(), 0)));
+    // Depositors 11 and 12 share the other half of the root, untouched until one of them leaves
+    let NodeKind::Branch { left, .. } = tree.nodes[0].kind else { panic!("root is a branch") };
+    let rest = rpc.find_utxos_for_descriptor(&tree.nodes[left].descriptor).await.unwrap();
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].outpoint, OutPoint::new(path[0].txid(), 0));
+    assert_eq!(rest[0].amount, Amount::from_sat(300_000 + 500));
+}
@@ -0,0 +1,151 @@
+use bitcoin_scripts::recovery::RecoveryTemplate;
+use bitcoin_scripts::secret::SigningKey;
+use bitcoin_scripts::tower_client::{decrypt_blob, receipt_message, recover_signer, sign_message, Appointment, Locator, TowerClient};
+use bitcoin_scripts::watchtower::{Trigger, Watchtower, WatchtowerExport};
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::{Amount, Network, OutPoint, PublicKey, Txid};
+use miniscript::Descriptor;
+use serde_json::{json, Value};
+use std::str::FromStr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+mod common;
+use common::{key, pk};
+
+fn signing_key(byte: u8) -> SigningKey {
+    SigningKey::new(SecretKey::from_slice(&[byte; 32]).unwrap(), Network::Regtest)
+}
+
+/// A vault output created by breach txid [7; 32], with its recovery pre-signed
+fn watchtower(trigger: Trigger) -> Watchtower {
+    let vault: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(or_d(multi(2,{},{}),and_v(v:pk({}),older(10))))", pk(91), pk(92), pk(93))).unwrap();
+    let cold: Descriptor<PublicKey> = Descriptor::from_str(&format!("wpkh({})", pk(94))).unwrap();
+    let template = RecoveryTemplate::new(&vault, &cold, Amount::from_sat(200_000), 10, Amount::from_sat(1_000)).unwrap();
+    let deposit = OutPoint::new(Txid::from_byte_array([7; 32]), 1);
+    let recovery = template.presign(deposit, &[key(93)]).unwrap();
+    let mut export = WatchtowerExport::new(Network::Regtest);
+    export.add_clawback(&recovery.tx, deposit, &[template.prevout().unwrap()], trigger, Some(24)).unwrap();
+    Watchtower::import(&export.to_json().unwrap()).unwrap()
+}
+
+/// Read one HTTP request and return its path and JSON body
+async fn read_request(socket: &mut tokio::net::TcpStream) -> (String, Value) {
+    let mut buf = Vec::new();
+    let mut chunk = vec![0u8; 65536];
+    loop {
+        let n = socket.read(&mut chunk).await.unwrap();
+        buf.extend_from_slice(&chunk[..n]);
+        let text = String::from_utf8_lossy(&buf).to_string();
+        if let Some(end) = text.find("\r\n\r\n") {
+            let length: usize = text[..end].lines()
+                .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                .unwrap_or(0);
+            if buf.len() >= end + 4 + length {
+                let path = text.split_whitespace().nth(1).unwrap().to_string();
+                return (path, serde_json::from_slice(&buf[end + 4..end + 4 + length]).unwrap());
+            }
+        }
+    }
+}
+
+/// A tower holding key `tower_key` that answers `requests` requests and returns what it stored
+async fn tower(listener: TcpListener, tower_key: SigningKey, requests: usize) -> Vec<(String, Value)> {
+    let mut seen = Vec::new();
+    for _ in 0..requests {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let (path, body) = read_request(&mut socket).await;
+        let reply = match path.as_str() {
+            "/register" => json!({ "user_id": body["user_id"], "available_slots": 100, "subscription_expiry": 4_320, "subscription_signature": "" }),
+            "/add_appointment" => {
+                let appointment = Appointment {
+                    locator: Locator(hex::decode(body["appointment"]["locator"].as_str().unwrap()).unwrap().try_into().unwrap()),
+                    encrypted_blob: hex::decode(body["appointment"]["encrypted_blob"].as_str().unwrap()).unwrap(),
+                    to_self_delay: body["appointment"]["to_self_delay"].as_u64().unwrap() as u32,
+                };
+                let user_signature = body["signature"].as_str().unwrap();
+                let user = recover_signer(&appointment.to_vec(), user_signature).unwrap();
+                // TEOS receipts sign the user's signature string followed by the start block
+                let receipt = receipt_message(user_signature, 812);
+                json!({ "locator": body["appointment"]["locator"], "start_block": 812, "signature": sign_message(&receipt, &tower_key), "available_slots": 99, "user": user.to_string() })
+            }
+            _ => json!({ "error": "unknown endpoint" }),
+        };
+        let body_text = reply.to_string();
+        let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body_text.len(), body_text);
+        socket.write_all(response.as_bytes()).await.unwrap();
+        seen.push((path, body));
+    }
+    seen
+}
+
+#[test]
+fn test_blobs_open_only_with_the_breach_and_signatures_recover_the_signer() {
+    let breach = Txid::from_byte_array([7; 32]);
+    let response = watchtower(Trigger::OnConfirmation).clawbacks[0].tx.clone();
+    let appointment = Appointment::new(&breach, &response, 24).unwrap();
+    assert_eq!(appointment.locator.to_string(), "07".repeat(16));
+    assert_eq!(decrypt_blob(&appointment.encrypted_blob, &breach).unwrap(), response);
+    assert!(decrypt_blob(&appointment.encrypted_blob, &Txid::from_byte_array([8; 32])).is_err());
+    assert_eq!(&appointment.to_vec()[appointment.to_vec().len() - 4..], &[0, 0, 0, 24]);
+
+    let signature = sign_message(b"get appointment", &signing_key(5));
+    assert_eq!(recover_signer(b"get appointment", &signature).unwrap(), pk(5).inner);
+    assert_ne!(recover_signer(b"get appointments", &signature).unwrap(), pk(5).inner);
+    assert!(recover_signer(b"get appointment", "not zbase32!").is_err());
+}
+
+#[tokio::test]
+async fn test_register_and_upload_clawbacks_to_a_tower() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(tower(listener, signing_key(60), 2));
+
+    let client = TowerClient::new(&url, pk(60).inner, signing_key(61));
+    let session = client.register().await.unwrap();
+    assert_eq!((session.available_slots, session.subscription_expiry), (100, 4_320));
+
+    let armed = watchtower(Trigger::OnConfirmation);
+    let report = client.upload_clawbacks(&armed).await.unwrap();
+    assert!(report.skipped.is_empty());
+    assert_eq!(report.receipts.len(), 1);
+    assert_eq!(report.receipts[0].locator, Locator::new(&Txid::from_byte_array([7; 32])));
+    assert_eq!(report.receipts[0].start_block, 812);
+    assert_eq!(&report.receipts[0].to_vec()[report.receipts[0].to_vec().len() - 4..], &812u32.to_be_bytes());
+    assert_eq!(recover_signer(&report.receipts[0].to_vec(), &report.receipts[0].tower_signature).unwrap(), pk(60).inner);
+
+    let seen = server.await.unwrap();
+    assert_eq!(seen[0].1["user_id"], pk(61).inner.to_string());
+    // The tower saw only the locator and an opaque blob
+    let uploaded = &seen[1].1["appointment"];
+    assert_eq!(uploaded["to_self_delay"], 24);
+    let blob = hex::decode(uploaded["encrypted_blob"].as_str().unwrap()).unwrap();
+    assert_eq!(decrypt_blob(&blob, &Txid::from_byte_array([7; 32])).unwrap(), armed.clawbacks[0].tx);
+
+    // Clawbacks waiting on confirmations cannot be outsourced
+    let waiting = watchtower(Trigger::AfterConfirmations { blocks: 6 });
+    let report = client.upload_clawbacks(&waiting).await.unwrap();
+    assert!(report.receipts.is_empty());
+    assert_eq!(report.skipped[0].0, waiting.clawbacks[0].tx.txid());
+
+    // Without a deadline there is no to_self_delay to give the tower
+    let mut undated = watchtower(Trigger::OnConfirmation);
+    undated.clawbacks[0].deadline_blocks = None;
+    let report = client.upload_clawbacks(&undated).await.unwrap();
+    assert!(report.receipts.is_empty());
+    assert!(report.skipped[0].1.contains("no deadline"));
+}
+
+#[tokio::test]
+async fn test_receipt_from_another_tower_is_rejected() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    // The tower signs with key 62, the client expects 60
+    let server = tokio::spawn(tower(listener, signing_key(62), 1));
+    let client = TowerClient::new(&url, pk(60).inner, signing_key(61));
+    let clawback = &watchtower(Trigger::OnConfirmation).clawbacks[0];
+    let err = client.add_appointment(&clawback.spends.txid, &clawback.tx, 24).await.unwrap_err();
+    assert!(err.to_string().contains("not signed by tower"));
+    server.await.unwrap();
+}
@@ -0,0 +1,177 @@
+use bitcoin_scripts::fees;
+use bitcoin_scripts::psbt::SpendableUtxo;
+use bitcoin_scripts::rpc_types::MempoolEntry;
+use bitcoin_scripts::secret::SigningKey;
+use bitcoin_scripts::txbuilder::{cpfp_builder, signals_rbf, TxBuilder};
+use bitcoin_scripts::verify::verify_spend;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::{Amount, FeeRate, Network, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::Descriptor;
+use serde_json::json;
+use std::str::FromStr;
+
+mod common;
+use common::{key, pk};
+
+/// P2SH 2-of-2, a wsh relative timelock and a taproot leaf (the internal key is not held)
+fn descriptors() -> Vec<Descriptor<PublicKey>> {
+    [
+        format!("sh(multi(2,{},{}))", pk(101), pk(102)),
+        format!("wsh(and_v(v:pk({}),older(5)))", pk(103)),
+        format!("tr({},pk({}))", pk(104), pk(105)),
+    ]
+    .iter()
+    .map(|s| Descriptor::from_str(s).unwrap())
+    .collect()
+}
+
+fn funding(descriptors: &[Descriptor<PublicKey>]) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+        output: descriptors.iter().enumerate().map(|(i, d)| TxOut { value: 100_000 * (i as u64 + 1), script_pubkey: d.script_pubkey() }).collect(),
+    }
+}
+
+fn builder(descriptors: &[Descriptor<PublicKey>], funding: &Transaction) -> TxBuilder {
+    let mut builder = TxBuilder::new();
+    for (vout, descriptor) in descriptors.iter().enumerate() {
+        let mut utxo = SpendableUtxo::new(OutPoint::new(funding.txid(), vout as u32), funding.output[vout].clone());
+        if vout == 0 {
+            utxo.prev_tx = Some(funding.clone());
+        }
+        if vout == 1 {
+            utxo.sequence = Sequence::from_height(5);
+        }
+        builder.add_input(descriptor, utxo).unwrap();
+    }
+    builder.add_output(TxOut { value: 590_000, script_pubkey: descriptors[1].script_pubkey() });
+    builder
+}
+
+#[test]
+fn test_heterogeneous_inputs_are_signed_in_one_transaction() {
+    let descriptors = descriptors();
+    let funding = funding(&descriptors);
+    let builder = builder(&descriptors, &funding);
+    assert_eq!(builder.fee().unwrap(), Amount::from_sat(10_000));
+
+    let tx = builder.sign(&[key(101), key(102), key(103), key(105)]).unwrap();
+    let prevouts = funding.output.clone();
+    assert_eq!(verify_spend(&tx, &prevouts), Ok(()));
+    // Legacy: everything in the scriptSig
+    assert!(!tx.input[0].script_sig.is_empty() && tx.input[0].witness.is_empty());
+    // Segwit v0: signature and witness script, timelock in the sequence
+    assert_eq!(tx.input[1].witness.len(), 2);
+    assert_eq!(tx.input[1].witness.last().unwrap(), descriptors[1].explicit_script().unwrap().as_bytes());
+    assert_eq!(tx.input[1].sequence, Sequence::from_height(5));
+    // Taproot leaf: signature, leaf script, control block
+    assert_eq!(tx.input[2].witness.len(), 3);
+    assert!(tx.input[2].script_sig.is_empty());
+
+    // A taproot signature commits to every spent output, so changing any amount breaks it
+    let mut wrong = prevouts.clone();
+    wrong[0].value -= 1;
+    assert!(verify_spend(&tx, &wrong).is_err());
+}
+
+#[test]
+fn test_builder_rejects_inconsistent_inputs() {
+    let descriptors = descriptors();
+    let funding = funding(&descriptors);
+    let mut builder = builder(&descriptors, &funding);
+
+    // Missing a signer
+    assert!(builder.sign(&[key(101), key(103), key(105)]).is_err());
+
+    let utxo = |vout: u32| SpendableUtxo::new(OutPoint::new(funding.txid(), vout), funding.output[vout as usize].clone());
+    assert!(builder.add_input(&descriptors[1], utxo(1)).is_err(), "duplicate input");
+    assert!(builder.add_input(&descriptors[1], utxo(2)).is_err(), "wrong descriptor");
+    assert!(TxBuilder::new().add_input(&descriptors[0], utxo(0)).is_err(), "legacy without prev_tx");
+
+    builder.add_output(TxOut { value: 20_000, script_pubkey: descriptors[0].script_pubkey() });
+    assert!(builder.fee().is_err());
+    assert!(builder.psbt().is_err());
+    assert!(TxBuilder::new().psbt().is_err());
+}
+
+#[test]
+fn test_bump_fee_takes_the_increase_from_change() {
+    let descriptors = descriptors();
+    let funding = funding(&descriptors);
+    let mut builder = builder(&descriptors, &funding);
+    builder.outputs[0].value = 500_000;
+    builder.add_change(TxOut { value: 80_000, script_pubkey: descriptors[2].script_pubkey() });
+    for byte in [101, 102, 103, 105] {
+        builder.add_signer(SigningKey::new(key(byte).inner, Network::Regtest));
+    }
+    let original = builder.sign_stored().unwrap();
+    assert!(signals_rbf(&original));
+    assert!(original.input.iter().all(|i| i.sequence.is_rbf()));
+
+    let feerate = FeeRate::from_sat_per_vb(50).unwrap();
+    let bumped = builder.bumped(&original, feerate).unwrap();
+    let replacement = bumped.sign_stored().unwrap();
+    assert_eq!(verify_spend(&replacement, &funding.output), Ok(()));
+    let fee = bumped.fee().unwrap();
+    assert!(fee >= fees::fee_at(feerate, original.weight()).unwrap());
+    assert_eq!(replacement.output[1].value, 80_000 - (fee - builder.fee().unwrap()).to_sat());
+    assert_eq!(replacement.output[0], original.output[0]);
+
+    // Too large a bump would leave the change below dust
+    assert!(builder.bumped(&original, FeeRate::from_sat_per_vb(1_000).unwrap()).is_err());
+    // Without change there is nothing to take the fee from
+    let mut no_change = builder.clone();
+    no_change.change = None;
+    assert!(no_change.bumped(&original, feerate).is_err());
+}
+
+#[test]
+fn test_rbf_opt_out_keeps_relative_timelocks() {
+    let descriptors = descriptors();
+    let funding = funding(&descriptors);
+    let mut builder = builder(&descriptors, &funding);
+    builder.rbf(false);
+    let tx = builder.sign(&[key(101), key(102), key(103), key(105)]).unwrap();
+    assert_eq!(tx.input[0].sequence, Sequence::ENABLE_LOCKTIME_NO_RBF);
+    assert_eq!(tx.input[1].sequence, Sequence::from_height(5));
+    assert_eq!(tx.input[2].sequence, Sequence::ENABLE_LOCKTIME_NO_RBF);
+    assert_eq!(verify_spend(&tx, &funding.output), Ok(()));
+}
+
+fn mempool_entry(vsize: u64, ancestor_size: u64, ancestor_fee: f64) -> MempoolEntry {
+    serde_json::from_value(json!({
+        "vsize": vsize, "weight": vsize * 4, "ancestorcount": 2, "ancestorsize": ancestor_size,
+        "fees": { "base": 0.00000200, "modified": 0.00000200, "ancestor": ancestor_fee, "descendant": 0.00000200 }
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_cpfp_child_pays_for_the_ancestor_package() {
+    let descriptors = descriptors();
+    let parent = funding(&descriptors);
+    let feerate = FeeRate::from_sat_per_vb(20).unwrap();
+    let destination = descriptors[1].script_pubkey();
+
+    // 400 vB of ancestors paying 600 sat: the child makes up the rest of 20 sat/vB
+    let mut child = cpfp_builder(&parent, 2, &mempool_entry(200, 400, 0.00000600), feerate, &descriptors[2], destination.clone()).unwrap();
+    child.add_signer(SigningKey::new(key(105).inner, Network::Regtest));
+    let tx = child.sign_stored().unwrap();
+    assert_eq!(verify_spend(&tx, &parent.output[2..]), Ok(()));
+    let fee = child.fee().unwrap();
+    let package_vsize = 400 + tx.vsize() as u64;
+    assert!(fee.to_sat() + 600 >= 20 * package_vsize, "{} sat for {} vB", fee, package_vsize);
+    assert_eq!(tx.output[0].script_pubkey, destination);
+
+    // A parent already above the target still gets a child paying its own way
+    let rich = cpfp_builder(&parent, 2, &mempool_entry(200, 200, 0.001), feerate, &descriptors[2], destination.clone()).unwrap();
+    assert!(rich.fee().unwrap() >= fees::fee_at_vsize(feerate, tx.vsize() as u64).unwrap());
+    assert!(rich.fee().unwrap() < fee);
+
+    assert!(cpfp_builder(&parent, 3, &mempool_entry(200, 400, 0.00000600), feerate, &descriptors[2], destination.clone()).is_err(), "no such output");
+    assert!(cpfp_builder(&parent, 1, &mempool_entry(200, 400, 0.00000600), feerate, &descriptors[2], destination.clone()).is_err(), "wrong descriptor");
+    assert!(cpfp_builder(&parent, 2, &mempool_entry(200, 400, 0.00000600), FeeRate::from_sat_per_vb(10_000).unwrap(), &descriptors[2], destination).is_err(), "fee above the output");
+}
@@ -0,0 +1,68 @@
+use bitcoin_scripts::rpc_types::ScanTxOutSetResult;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin::{Amount, Txid};
+use miniscript::bitcoin::{Network, PrivateKey, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use serde_json::json;
+use std::str::FromStr;
+
+#[test]
+fn test_scan_result_confirmations() {
+    let result: ScanTxOutSetResult = serde_json::from_value(json!({
+        "success": true, "txouts": 200, "height": 120, "bestblock": "00",
+        "unspents": [
+            { "txid": "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b", "vout": 0,
+              "scriptPubKey": "0014751e76e8199196d454941c45d1b3a323f1433bd6", "desc": "addr(bcrt1q...)#x",
+              "amount": 1.5, "coinbase": false, "height": 120 },
+            { "txid": "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b", "vout": 1,
+              "scriptPubKey": "0014751e76e8199196d454941c45d1b3a323f1433bd6", "desc": "addr(bcrt1q...)#x",
+              "amount": 0.00001, "coinbase": false, "height": 101 }
+        ],
+        "total_amount": 1.50001
+    })).unwrap();
+    let utxos = result.utxos();
+    assert_eq!(utxos.iter().map(|u| u.confirmations).collect::<Vec<_>>(), vec![1, 20]);
+    assert_eq!(utxos[0].amount, Amount::from_sat(150_000_000));
+    assert_eq!(utxos[1].outpoint.vout, 1);
+}
+
+#[tokio::test]
+async fn test_find_utxos_for_address_and_descriptor() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("utxo_discovery_wallet").await;
+    let _ = rpc.load_wallet("utxo_discovery_wallet").await;
+    let rpc = rpc.with_wallet("utxo_discovery_wallet");
+    let funding_address = rpc.get_new_address().await.unwrap();
+    rpc.generate_to_address(101, &funding_address).await.unwrap();
+
+    // A wallet address: unconfirmed outputs are visible through listunspent
+    let wallet_address = rpc.get_new_address().await.unwrap();
+    let txid = Txid::from_str(&rpc.send_to_address(&wallet_address, Amount::from_sat(30_000_000)).await.unwrap()).unwrap();
+    let pending = rpc.find_utxos_for_address(&wallet_address).await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!((pending[0].outpoint.txid, pending[0].confirmations), (txid, 0));
+    rpc.generate_to_address(2, &funding_address).await.unwrap();
+    let confirmed = rpc.find_utxos_for_address(&wallet_address).await.unwrap();
+    assert_eq!(confirmed.len(), 1);
+    assert_eq!(confirmed[0].confirmations, 2);
+    assert_eq!(confirmed[0].amount, Amount::from_sat(30_000_000));
+
+    // A descriptor the wallet knows nothing about
+    let secp = secp256k1::Secp256k1::new();
+    let mut seed = [0u8; 32];
+    seed[..8].copy_from_slice(&(std::process::id() as u64).to_le_bytes());
+    seed[31] = 1;
+    let key = PublicKey::from_private_key(&secp, &PrivateKey::new(secp256k1::SecretKey::from_slice(&seed).unwrap(), Network::Regtest));
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(and_v(v:pk({}),older(10)))", key)).unwrap();
+    let address = descriptor.address(Network::Regtest).unwrap().to_string();
+    let first = Txid::from_str(&rpc.send_to_address(&address, Amount::from_sat(10_000_000)).await.unwrap()).unwrap();
+    let second = Txid::from_str(&rpc.send_to_address(&address, Amount::from_sat(20_000_000)).await.unwrap()).unwrap();
+    assert!(rpc.find_utxos_for_descriptor(&descriptor).await.unwrap().is_empty(), "mempool outputs are not in the UTXO set");
+    rpc.generate_to_address(1, &funding_address).await.unwrap();
+
+    let mut found = rpc.find_utxos_for_descriptor(&descriptor).await.unwrap();
+    found.sort_by_key(|u| u.amount);
+    assert_eq!(found.iter().map(|u| u.outpoint.txid).collect::<Vec<_>>(), vec![first, second]);
+    assert!(found.iter().all(|u| u.script_pubkey == descriptor.script_pubkey() && u.confirmations == 1));
+    assert_eq!(rpc.find_utxos_for_address(&address).await.unwrap().len(), 2);
+}
@@ -0,0 +1,77 @@
+use bitcoin_scripts::validate::{failure_point, verify_spend, Blame, VerifyError};
+use bitcoin_scripts::witness::build_multisig_timelock_witness;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::Descriptor;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+mod common;
+use common::{key, pk};
+
+const VALUE: u64 = 100_000;
+
+/// Keys 5 and 7 sign the 2-of-3 path of `or_d(pk(8), and_v(v:multi(2,5,6,7), older(10)))`
+fn multisig_spend(sequence: Sequence) -> (Transaction, TxOut) {
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))", pk(8), pk(5), pk(6), pk(7))).unwrap();
+    let prevout = TxOut { value: VALUE, script_pubkey: descriptor.script_pubkey() };
+    let mut tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence, witness: Witness::default() }],
+        output: vec![TxOut { value: VALUE - 1_000, script_pubkey: prevout.script_pubkey.clone() }],
+    };
+    let script = descriptor.explicit_script().unwrap();
+    let sighash = SighashCache::new(&tx).segwit_signature_hash(0, &script, VALUE, EcdsaSighashType::All).unwrap();
+    let msg = Message::from_slice(&sighash[..]).unwrap();
+    let sigs: HashMap<PublicKey, bitcoin::ecdsa::Signature> = [5u8, 7].iter()
+        .map(|b| (pk(*b), bitcoin::ecdsa::Signature::sighash_all(Secp256k1::new().sign_ecdsa(&msg, &key(*b).inner))))
+        .collect();
+    tx.input[0].witness = build_multisig_timelock_witness(&descriptor, &sigs).unwrap();
+    (tx, prevout)
+}
+
+/// Flip a bit inside witness element `index`, keeping the DER encoding intact
+fn tamper(tx: &Transaction, index: usize) -> Transaction {
+    let mut tampered = tx.clone();
+    let mut items = tampered.input[0].witness.to_vec();
+    items[index][10] ^= 0x01;
+    tampered.input[0].witness = Witness::from_slice(&items);
+    tampered
+}
+
+#[test]
+fn test_failure_point_names_the_rejected_signature() {
+    let (tx, prevout) = multisig_spend(Sequence(10));
+    assert_eq!(failure_point(&tx, 0, &[prevout.clone()]), None);
+
+    // [<>, sig 5, sig 7, <>, script]: the multisig op is handed both signatures and blames
+    // the one no remaining key accepts
+    for index in [1, 2] {
+        let tampered = tamper(&tx, index);
+        let point = failure_point(&tampered, 0, &[prevout.clone()]).unwrap();
+        let witness = tampered.input[0].witness.to_vec();
+        assert_eq!(point.step.op, "OP_CHECKMULTISIGVERIFY");
+        assert_eq!(point.step.stack_before[..3], witness[..3]);
+        assert_eq!(point.blame, Some(Blame::Element { index, value: witness[index].clone() }));
+
+        // The same blame reaches verify_spend's report
+        let Err(VerifyError::Inputs(failures)) = verify_spend(&tampered, &[prevout.clone()]) else { panic!("should fail") };
+        assert_eq!(failures[0].blame, point.blame);
+    }
+}
+
+#[test]
+fn test_failure_point_blames_the_sequence_for_an_unmet_csv() {
+    let (tx, prevout) = multisig_spend(Sequence(9));
+    let point = failure_point(&tx, 0, &[prevout.clone()]).unwrap();
+    assert_eq!(point.step.op, "OP_CSV");
+    assert_eq!(point.step.stack_before, vec![vec![10]]);
+    assert_eq!(point.blame, Some(Blame::Sequence));
+    assert_eq!(point.error, "nSequence 9 below required 10");
+
+    assert_eq!(failure_point(&tx, 1, &[prevout]), None);
+}
@@ -0,0 +1,113 @@
+use bitcoin_scripts::flows::{fund_descriptor, mine};
+use bitcoin_scripts::psbt;
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::vault::{Protocol, VaultParams, VaultState};
+use bitcoin::absolute::LockTime;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Amount, Network, OutPoint, PrivateKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use std::time::Duration;
+
+mod common;
+use common::key;
+
+fn params(keys: &[PrivateKey]) -> VaultParams {
+    let secp = Secp256k1::new();
+    VaultParams {
+        user: keys[0].public_key(&secp),
+        federation: keys[1..4].iter().map(|k| k.public_key(&secp)).collect(),
+        threshold: 2,
+        unbonding_csv: 5,
+        min_confirmations: 2,
+        network: Network::Regtest,
+    }
+}
+
+fn sign(unsigned: bitcoin::psbt::PartiallySignedTransaction, keys: &[PrivateKey]) -> Transaction {
+    let mut unsigned = unsigned;
+    psbt::sign(&mut unsigned, keys).unwrap();
+    psbt::finalize(unsigned).unwrap()
+}
+
+#[test]
+fn test_full_lifecycle_offline_with_resumption() {
+    let keys: Vec<PrivateKey> = [81u8, 82, 83, 84].iter().map(|b| key(*b)).collect();
+    let params = params(&keys);
+    let funding = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+        output: vec![TxOut { value: 1_000_000, script_pubkey: params.locked_descriptor().unwrap().script_pubkey() }],
+    };
+    let path = std::env::temp_dir().join(format!("wrapyield-vault-{}.json", std::process::id()));
+    let resume = |protocol: &Protocol| {
+        protocol.save(&path).unwrap();
+        Protocol::load(&path).unwrap().unwrap()
+    };
+
+    let mut protocol = Protocol::deposit(params.clone(), &funding, None).unwrap();
+    assert!(protocol.start_accrual(100).is_err());
+    assert!(protocol.lock(100, 100).is_err());
+    protocol = resume(&protocol);
+    assert_eq!(protocol.state, VaultState::Deposit { utxo: OutPoint::new(funding.txid(), 0), amount: Amount::from_sat(1_000_000), height: Some(100) });
+    protocol.lock(100, 101).unwrap();
+    protocol.start_accrual(101).unwrap();
+    protocol = resume(&protocol);
+
+    // The federation moves the coins to unbonding; a transaction paying elsewhere is refused
+    let unsigned = protocol.build_unbonding(Amount::from_sat(1_000)).unwrap();
+    let mut diverted = sign(unsigned.clone(), &keys[1..3]);
+    diverted.output[0].script_pubkey = params.locked_descriptor().unwrap().script_pubkey();
+    assert!(protocol.unbond(&diverted).is_err());
+    let unbonding = sign(unsigned, &keys[1..3]);
+    protocol.unbond(&unbonding).unwrap();
+    assert_eq!(protocol.utxo(), Some((OutPoint::new(unbonding.txid(), 0), Amount::from_sat(999_000))));
+    protocol = resume(&protocol);
+
+    let destination = ScriptBuf::new_v0_p2wpkh(&keys[0].public_key(&Secp256k1::new()).wpubkey_hash().unwrap());
+    assert!(protocol.build_withdrawal(destination.clone(), Amount::from_sat(1_000), 200).is_err(), "not confirmed yet");
+    protocol.unbonding_confirmed(110).unwrap();
+    assert_eq!(protocol.withdrawable_at(), Some(115));
+    assert!(protocol.build_withdrawal(destination.clone(), Amount::from_sat(1_000), 112).is_err(), "still unbonding");
+    let withdrawal = sign(protocol.build_withdrawal(destination.clone(), Amount::from_sat(1_000), 114).unwrap(), &keys[..1]);
+    assert_eq!(withdrawal.input[0].sequence, Sequence::from_height(5));
+    protocol.withdraw(&withdrawal).unwrap();
+    protocol = resume(&protocol);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(protocol.state, VaultState::Withdrawn { txid: withdrawal.txid(), amount: Amount::from_sat(998_000) });
+    assert_eq!(protocol.utxo(), None);
+}
+
+#[tokio::test]
+async fn test_lifecycle_on_regtest() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("vault_protocol_wallet").await;
+    let _ = rpc.load_wallet("vault_protocol_wallet").await;
+    let rpc = rpc.with_wallet("vault_protocol_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let keys: Vec<PrivateKey> = [85u8, 86, 87, 88].iter().map(|b| key(*b)).collect();
+    let params = params(&keys);
+    let funded = fund_descriptor(&rpc, &params.locked_descriptor().unwrap(), Amount::from_sat(500_000)).await.unwrap();
+    let mut protocol = Protocol::deposit(params, funded.utxo.prev_tx.as_ref().unwrap(), Some(funded.height)).unwrap();
+    let tip = mine(&rpc, 1).await.unwrap();
+    protocol.lock(funded.height, tip).unwrap();
+    protocol.start_accrual(tip).unwrap();
+
+    let unbonding = sign(protocol.build_unbonding(Amount::from_sat(1_000)).unwrap(), &keys[2..4]);
+    protocol.unbond(&unbonding).unwrap();
+    rpc.send_raw_transaction(&serialize_hex(&unbonding)).await.unwrap();
+    let height = mine(&rpc, 1).await.unwrap();
+    protocol.unbonding_confirmed(height).unwrap();
+
+    let destination = rpc.parse_address(&rpc.get_new_address().await.unwrap()).unwrap().script_pubkey();
+    let tip = mine(&rpc, 3).await.unwrap();
+    assert!(protocol.build_withdrawal(destination.clone(), Amount::from_sat(1_000), tip).is_err());
+    let tip = mine(&rpc, 1).await.unwrap();
+    let withdrawal = sign(protocol.build_withdrawal(destination, Amount::from_sat(1_000), tip).unwrap(), &keys[..1]);
+    protocol.withdraw(&withdrawal).unwrap();
+    rpc.send_raw_transaction(&serialize_hex(&withdrawal)).await.unwrap();
+    mine(&rpc, 1).await.unwrap();
+    rpc.wait_for_confirmations(&withdrawal.txid(), 1, Duration::from_secs(30)).await.unwrap();
+}
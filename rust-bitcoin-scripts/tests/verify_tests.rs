@@ -0,0 +1,213 @@
+use bitcoin_scripts::differential::local_verdict;
+use bitcoin_scripts::flows::{fund_descriptor, mine};
+use bitcoin_scripts::psbt::{self, SpendableUtxo};
+use bitcoin_scripts::test_setup::BitcoinRPC;
+use bitcoin_scripts::verify::{verify_and_send, verify_input, verify_spend, Blame, VerifyError};
+use bitcoin_scripts::witness::build_multisig_timelock_witness;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{Amount, Network, OutPoint, PrivateKey, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use miniscript::descriptor::DefiniteDescriptorKey;
+use miniscript::Descriptor;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+mod common;
+use common::{key, pk};
+
+fn descriptor(template: &str) -> Descriptor<DefiniteDescriptorKey> {
+    let secp = Secp256k1::new();
+    let s = template.replace('A', &key(11).public_key(&secp).to_string()).replace('B', &key(12).public_key(&secp).to_string());
+    Descriptor::from_str(&s).unwrap()
+}
+
+/// A finalized spend of a fresh output locked by `template`, and the output it spends
+fn signed_spend(template: &str) -> (Transaction, TxOut) {
+    let descriptor = descriptor(template);
+    let prev_tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+        output: vec![TxOut { value: 100_000, script_pubkey: descriptor.script_pubkey() }],
+    };
+    let utxo = SpendableUtxo { outpoint: OutPoint::new(prev_tx.txid(), 0), txout: prev_tx.output[0].clone(), prev_tx: Some(prev_tx.clone()), sequence: Sequence::ENABLE_RBF_NO_LOCKTIME };
+    let outputs = vec![TxOut { value: 90_000, script_pubkey: descriptor.script_pubkey() }];
+    let mut unsigned = psbt::create(&descriptor, &[utxo], outputs, LockTime::ZERO).unwrap();
+    psbt::sign(&mut unsigned, &[key(11), key(12)]).unwrap();
+    (psbt::finalize(unsigned).unwrap(), prev_tx.output[0].clone())
+}
+
+/// Flip a bit inside the first signature of the witness
+fn tamper(tx: &Transaction) -> Transaction {
+    let mut tampered = tx.clone();
+    let mut items: Vec<Vec<u8>> = tampered.input[0].witness.iter().map(|w| w.to_vec()).collect();
+    let sig = items.iter_mut().find(|item| item.len() > 64).unwrap();
+    sig[10] ^= 0x01;
+    tampered.input[0].witness = Witness::from_slice(&items);
+    tampered
+}
+
+#[test]
+fn test_finalized_spends_verify() {
+    for template in ["wpkh(A)", "wsh(multi(2,A,B))", "tr(A)"] {
+        let (tx, prevout) = signed_spend(template);
+        assert_eq!(verify_spend(&tx, &[prevout.clone()]), Ok(()), "{}", template);
+        if !template.starts_with("tr") {
+            assert_eq!(verify_input(&tx, 0, &prevout), Ok(()), "{}", template);
+        }
+    }
+}
+
+#[test]
+fn test_tampered_signature_names_input_and_traces_p2wsh() {
+    let (tx, prevout) = signed_spend("wsh(multi(2,A,B))");
+    match verify_spend(&tamper(&tx), &[prevout.clone()]) {
+        Err(VerifyError::Inputs(failures)) => {
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].input_index, 0);
+            let trace = failures[0].trace.as_ref().unwrap();
+            assert!(!trace.succeeded());
+            assert!(trace.to_string().contains("FAILED: "), "{}", trace);
+            // The multisig op saw [<>, sig, sig, 2, key, key, 2] with the tampered signature in it
+            let failing = trace.failing_step().unwrap();
+            assert_eq!(failing.op, "OP_CHECKMULTISIG");
+            assert_eq!(failing.stack_before.len(), 7);
+            assert_eq!(failing.stack_before[..3], tamper(&tx).input[0].witness.to_vec()[..3]);
+        }
+        other => panic!("expected an input failure, got {:?}", other),
+    }
+    assert!(verify_input(&tamper(&tx), 0, &prevout).is_err());
+
+    let (tx, prevout) = signed_spend("wpkh(A)");
+    let tampered = tamper(&tx);
+    match verify_spend(&tampered, &[prevout]) {
+        Err(VerifyError::Inputs(failures)) => {
+            // The P2PKH script code: DUP HASH160 <hash> EQUALVERIFY CHECKSIG, failing at the last op
+            let failing = failures[0].trace.as_ref().unwrap().failing_step().unwrap().clone();
+            assert_eq!(failing.op, "OP_CHECKSIG");
+            assert_eq!(failing.stack_before, tampered.input[0].witness.to_vec());
+            assert!(failing.stack_after.is_empty());
+        }
+        other => panic!("expected an input failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_prevout_count_must_match_inputs() {
+    let (tx, prevout) = signed_spend("wpkh(A)");
+    assert_eq!(verify_spend(&tx, &[]), Err(VerifyError::PrevoutCount { inputs: 1, prevouts: 0 }));
+    assert_eq!(verify_spend(&tx, &[prevout.clone(), prevout.clone()]), Err(VerifyError::PrevoutCount { inputs: 1, prevouts: 2 }));
+    assert!(verify_input(&tx, 1, &prevout).is_err());
+}
+
+/// Keys 5 and 7 sign the 2-of-3 path of `or_d(pk(8), and_v(v:multi(2,5,6,7), older(10)))`
+fn multisig_spend(sequence: Sequence) -> (Transaction, TxOut) {
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))", pk(8), pk(5), pk(6), pk(7))).unwrap();
+    let prevout = TxOut { value: 100_000, script_pubkey: descriptor.script_pubkey() };
+    let mut tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), script_sig: ScriptBuf::new(), sequence, witness: Witness::default() }],
+        output: vec![TxOut { value: 99_000, script_pubkey: prevout.script_pubkey.clone() }],
+    };
+    let script = descriptor.explicit_script().unwrap();
+    let sighash = SighashCache::new(&tx).segwit_signature_hash(0, &script, prevout.value, EcdsaSighashType::All).unwrap();
+    let msg = Message::from_slice(&sighash[..]).unwrap();
+    let sigs: HashMap<PublicKey, bitcoin::ecdsa::Signature> = [5u8, 7].iter()
+        .map(|b| (pk(*b), bitcoin::ecdsa::Signature::sighash_all(Secp256k1::new().sign_ecdsa(&msg, &key(*b).inner))))
+        .collect();
+    tx.input[0].witness = build_multisig_timelock_witness(&descriptor, &sigs).unwrap();
+    (tx, prevout)
+}
+
+/// Flip a bit inside witness element `index`, keeping the DER encoding intact
+fn tamper_element(tx: &Transaction, index: usize) -> Transaction {
+    let mut tampered = tx.clone();
+    let mut items = tampered.input[0].witness.to_vec();
+    items[index][10] ^= 0x01;
+    tampered.input[0].witness = Witness::from_slice(&items);
+    tampered
+}
+
+#[test]
+fn test_bad_multisig_signature_is_blamed() {
+    let (tx, prevout) = multisig_spend(Sequence(10));
+    assert_eq!(verify_spend(&tx, &[prevout.clone()]), Ok(()));
+
+    // [<>, sig 5, sig 7, <>, script]: the second signature is the broken one
+    for index in [1, 2] {
+        let tampered = tamper_element(&tx, index);
+        let Err(VerifyError::Inputs(failures)) = verify_spend(&tampered, &[prevout.clone()]) else { panic!("should fail") };
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].blame, Some(Blame::Element { index, value: tampered.input[0].witness.to_vec()[index].clone() }));
+        assert!(failures[0].to_string().contains(&format!("blaming witness element {}", index)), "{}", failures[0]);
+    }
+}
+
+#[test]
+fn test_unmet_timelock_blames_the_sequence() {
+    let (tx, prevout) = multisig_spend(Sequence(9));
+    let Err(VerifyError::Inputs(failures)) = verify_spend(&tx, &[prevout]) else { panic!("should fail") };
+    assert_eq!(failures[0].blame, Some(Blame::Sequence));
+    assert!(failures[0].to_string().ends_with("blaming nSequence"), "{}", failures[0]);
+}
+
+#[test]
+fn test_trace_stops_at_the_unmet_timelock() {
+    let (tx, prevout) = multisig_spend(Sequence(9));
+    let Err(VerifyError::Inputs(failures)) = verify_spend(&tx, &[prevout.clone()]) else { panic!("should fail") };
+    let trace = failures[0].trace.as_ref().unwrap();
+    // <8> CHECKSIG IFDUP NOTIF 2 <5> <6> <7> 3 CHECKMULTISIGVERIFY <10> CSV: both signatures of
+    // the multisig verified, then `older(10)` did not hold
+    let ops: Vec<&str> = trace.steps.iter().map(|s| s.op.as_str()).collect();
+    assert_eq!(ops[1..4], ["OP_CHECKSIG", "OP_IFDUP", "OP_NOTIF"]);
+    assert_eq!(ops[9..], ["OP_CHECKMULTISIGVERIFY", "OP_PUSHNUM_10", "OP_CSV"]);
+    let witness = tx.input[0].witness.to_vec();
+    // The failed backup signature leaves false, which NOTIF consumes
+    assert_eq!(trace.steps[1].stack_before, witness[..4].iter().cloned().chain([pk(8).to_bytes()]).collect::<Vec<_>>());
+    assert_eq!(trace.steps[1].stack_after, witness[..3].iter().cloned().chain([vec![]]).collect::<Vec<_>>());
+    assert_eq!(trace.steps[3].stack_after, witness[..3].to_vec());
+    assert!(trace.steps[9].stack_after.is_empty());
+    assert_eq!(trace.steps[11].stack_before, vec![vec![10]]);
+    assert_eq!(trace.failing_step(), trace.steps.get(11));
+    assert!(trace.to_string().ends_with("FAILED: nSequence 9 below required 10"), "{}", trace);
+
+    let verdict = local_verdict(&tx, &[prevout]);
+    assert!(!verdict.accepted());
+    assert_eq!(verdict.trace.unwrap().failing_step().unwrap().op, "OP_CSV");
+
+    let (tx, prevout) = multisig_spend(Sequence(10));
+    let verdict = local_verdict(&tx, &[prevout]);
+    assert!(verdict.accepted(), "{:?}", verdict.rejection);
+    assert!(verdict.trace.is_none());
+}
+
+#[tokio::test]
+async fn test_invalid_witness_never_reaches_the_node() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("verify_wallet").await;
+    let _ = rpc.load_wallet("verify_wallet").await;
+    let rpc = rpc.with_wallet("verify_wallet");
+    mine(&rpc, 101).await.unwrap();
+
+    let keys: Vec<PrivateKey> = (0..3).map(|_| PrivateKey::new(SecretKey::from_slice(&rand::random::<[u8; 32]>()).unwrap(), Network::Regtest)).collect();
+    let pks: Vec<String> = keys.iter().map(|k| k.public_key(&Secp256k1::new()).to_string()).collect();
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(multi(2,{}))", pks.join(","))).unwrap();
+    let funded = fund_descriptor(&rpc, &descriptor, Amount::from_sat(100_000)).await.unwrap();
+
+    let definite = Descriptor::<DefiniteDescriptorKey>::from_str(&descriptor.to_string()).unwrap();
+    let outputs = vec![TxOut { value: 99_000, script_pubkey: descriptor.script_pubkey() }];
+    let mut unsigned = psbt::create(&definite, &[funded.utxo.clone()], outputs, LockTime::ZERO).unwrap();
+    psbt::sign(&mut unsigned, &[keys[0], keys[1]]).unwrap();
+    let tx = psbt::finalize(unsigned).unwrap();
+
+    // [<>, sig, sig, script]
+    let e = verify_and_send(&rpc, &tamper_element(&tx, 1)).await.unwrap_err();
+    let Some(VerifyError::Inputs(failures)) = e.downcast_ref::<VerifyError>() else { panic!("expected a local rejection, got {}", e) };
+    assert!(matches!(failures[0].blame, Some(Blame::Element { index: 1, .. })));
+    assert!(rpc.get_raw_transaction_verbose(&tx.txid()).await.is_err(), "nothing was sent");
+
+    assert_eq!(verify_and_send(&rpc, &tx).await.unwrap(), tx.txid());
+}
@@ -0,0 +1,49 @@
+use bitcoin_scripts::amount::from_rpc;
+use bitcoin_scripts::test_setup::{BitcoinRPC, SendOptions};
+use bitcoin::Amount;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_send_with_fee_rate_and_subtract_fee() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("send_wallet").await;
+    let _ = rpc.load_wallet("send_wallet").await;
+    let rpc = rpc.with_wallet("send_wallet");
+    let funding_address = rpc.get_new_address().await.unwrap();
+    let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
+
+    let destination = rpc.get_new_address().await.unwrap();
+    let options = SendOptions {
+        fee_rate_sat_vb: Some(5.0),
+        change_type: Some("bech32m".to_string()),
+        subtract_fee_from_outputs: vec![0],
+    };
+    let result = rpc.send(&[(destination.clone(), Amount::from_sat(50_000_000))], &options).await.unwrap();
+    assert!(result.complete, "send did not complete");
+    let txid = result.txid.expect("send returned no txid");
+    let _ = rpc.generate_to_address(1, &funding_address).await.unwrap();
+
+    let tx = rpc.call_rpc("getrawtransaction", json!([txid, true])).await.unwrap();
+    let paid = tx["vout"].as_array().unwrap().iter()
+        .find(|o| o["scriptPubKey"]["address"].as_str() == Some(destination.as_str()))
+        .expect("destination output missing");
+    assert!(from_rpc(&paid["value"]).unwrap() < Amount::from_sat(50_000_000), "fee should be subtracted from the destination output");
+}
+
+#[tokio::test]
+async fn test_wallet_create_funded_psbt() {
+    let rpc = BitcoinRPC::new();
+    let _ = rpc.create_wallet("send_wallet").await;
+    let _ = rpc.load_wallet("send_wallet").await;
+    let rpc = rpc.with_wallet("send_wallet");
+    let funding_address = rpc.get_new_address().await.unwrap();
+    let _ = rpc.generate_to_address(101, &funding_address).await.unwrap();
+
+    let destination = rpc.get_new_address().await.unwrap();
+    let options = SendOptions { fee_rate_sat_vb: Some(2.0), ..SendOptions::default() };
+    let funded = rpc.wallet_create_funded_psbt(vec![], &[(destination, Amount::from_sat(25_000_000))], &options).await.unwrap();
+    assert!(funded.fee > Amount::ZERO);
+    assert!(funded.change_position >= 0, "expected a change output");
+    let decoded = rpc.call_rpc("decodepsbt", json!([funded.psbt])).await.unwrap();
+    assert_eq!(decoded["tx"]["vout"].as_array().unwrap().len(), 2);
+}
@@ -0,0 +1,28 @@
+use bitcoin_scripts::watch_only::{assert_no_secrets, WatchOnly};
+use miniscript::bitcoin::{PublicKey, secp256k1};
+
+mod common;
+use common::key;
+
+#[test]
+fn test_public_descriptors_pass() {
+    let secp = secp256k1::Secp256k1::new();
+    let pubkeys: Vec<PublicKey> = [5u8, 6, 7, 8].iter().map(|b| PublicKey::from_private_key(&secp, &key(*b))).collect();
+    let descriptors = vec![format!(
+        "wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),older(10))))",
+        pubkeys[3], pubkeys[0], pubkeys[1], pubkeys[2]
+    )];
+    assert!(assert_no_secrets(&descriptors).is_ok());
+    let watch_only = WatchOnly::load(&descriptors).unwrap();
+    assert_eq!(watch_only.scanner(10).unwrap().watched_scripts.len(), 1);
+}
+
+#[test]
+fn test_secrets_are_rejected_without_echoing_them() {
+    let wif = key(9).to_wif();
+    let inputs = vec![format!("wpkh({})", wif), format!("note: backup key is {}", wif)];
+    let err = assert_no_secrets(&inputs).unwrap_err();
+    assert_eq!(err.findings.len(), 2);
+    assert!(!err.to_string().contains(&wif), "error output must not contain the secret");
+    assert!(WatchOnly::load(&inputs).is_err());
+}
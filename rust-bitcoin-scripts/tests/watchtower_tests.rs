@@ -0,0 +1,79 @@
+use bitcoin_scripts::recovery::RecoveryTemplate;
+use bitcoin_scripts::watchtower::{Trigger, Watchtower, WatchtowerExport};
+use bitcoin::hashes::Hash;
+use bitcoin::{Amount, Network, OutPoint, PublicKey, Txid};
+use miniscript::Descriptor;
+use std::str::FromStr;
+
+mod common;
+use common::{key, pk};
+
+/// Federation 2-of-2 any time, recovery key 93 after 10 blocks; cold storage is key 94
+fn template() -> RecoveryTemplate {
+    let vault: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(or_d(multi(2,{},{}),and_v(v:pk({}),older(10))))", pk(91), pk(92), pk(93))).unwrap();
+    let cold: Descriptor<PublicKey> = Descriptor::from_str(&format!("wpkh({})", pk(94))).unwrap();
+    RecoveryTemplate::new(&vault, &cold, Amount::from_sat(200_000), 10, Amount::from_sat(1_000)).unwrap()
+}
+
+fn export() -> (WatchtowerExport, OutPoint) {
+    let template = template();
+    let deposit = OutPoint::new(Txid::from_byte_array([7; 32]), 1);
+    let recovery = template.presign(deposit, &[key(93)]).unwrap();
+    let mut export = WatchtowerExport::new(Network::Regtest);
+    export.watch_descriptor(&template.cold().unwrap(), "cold storage");
+    export.add_recovery(&recovery).unwrap();
+    (export, deposit)
+}
+
+#[test]
+fn test_export_round_trips_to_an_armed_watchtower() {
+    let (exported, deposit) = export();
+    let json = exported.to_json().unwrap();
+    assert!(json.contains("\"kind\": \"watchtower_export\""));
+    // Deterministic: watching again and re-adding the same clawback changes nothing
+    let (mut again, _) = export();
+    again.watch_descriptor(&template().cold().unwrap(), "cold storage");
+    let recovery = template().presign(deposit, &[key(93)]).unwrap();
+    again.add_recovery(&recovery).unwrap();
+    assert_eq!(again.to_json().unwrap(), json);
+
+    let tower = Watchtower::import(&json).unwrap();
+    assert_eq!(tower.network, Network::Regtest);
+    assert_eq!(tower.scripts.len(), 2);
+    assert!(tower.scripts.iter().any(|(script, _)| *script == template().vault().unwrap().script_pubkey()));
+    assert_eq!(tower.clawbacks.len(), 1);
+    assert_eq!(tower.clawbacks[0].tx, recovery.tx);
+    assert_eq!(tower.clawbacks[0].trigger, Trigger::AfterConfirmations { blocks: 10 });
+    assert!(tower.due(deposit, 9).is_empty());
+    assert_eq!(tower.due(deposit, 10).len(), 1);
+    assert!(tower.due(OutPoint::new(deposit.txid, 0), 10).is_empty());
+}
+
+#[test]
+fn test_export_rejects_unsigned_and_import_rejects_tampering_and_secrets() {
+    let template = template();
+    let deposit = OutPoint::new(Txid::from_byte_array([7; 32]), 1);
+    let mut export = WatchtowerExport::new(Network::Regtest);
+    let unsigned = template.unsigned(deposit).unwrap().unsigned_tx;
+    assert!(export.add_clawback(&unsigned, deposit, &[template.prevout().unwrap()], Trigger::OnConfirmation, None).is_err());
+    let recovery = template.presign(deposit, &[key(93)]).unwrap();
+    assert!(export.add_clawback(&recovery.tx, OutPoint::new(deposit.txid, 0), &[template.prevout().unwrap()], Trigger::OnConfirmation, None).is_err());
+    // A trigger at or past the deadline could never win the race
+    assert!(export.add_clawback(&recovery.tx, deposit, &[template.prevout().unwrap()], Trigger::AfterConfirmations { blocks: 6 }, Some(6)).is_err());
+    export.add_clawback(&recovery.tx, deposit, &[template.prevout().unwrap()], Trigger::OnConfirmation, Some(6)).unwrap();
+    let json = export.to_json().unwrap();
+    Watchtower::import(&json).unwrap();
+
+    // A changed prevout amount breaks the signature
+    let tampered = json.replace("\"value_sats\": 200000", "\"value_sats\": 190000");
+    assert_ne!(tampered, json);
+    assert!(Watchtower::import(&tampered).unwrap_err().to_string().contains("does not verify"));
+    // Without the spent script the trigger would never be seen
+    let mut unwatched = export.clone();
+    unwatched.scripts.clear();
+    assert!(Watchtower::import(&unwatched.to_json().unwrap()).unwrap_err().to_string().contains("does not watch"));
+    // Private key material anywhere refuses the import
+    let mut leaky = export.clone();
+    leaky.scripts[0].label = key(93).to_wif();
+    assert!(Watchtower::import(&leaky.to_json().unwrap()).unwrap_err().to_string().contains("private key"));
+}
@@ -0,0 +1,34 @@
+use bitcoin_scripts::weak_keys::{is_weak_secret, KeyPolicy};
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use std::str::FromStr;
+use bitcoin::hashes::Hash;
+
+fn pubkey(bytes: [u8; 32]) -> PublicKey {
+    let secp = secp256k1::Secp256k1::new();
+    PublicKey::from_private_key(&secp, &PrivateKey::new(secp256k1::SecretKey::from_slice(&bytes).unwrap(), Network::Regtest))
+}
+
+#[test]
+fn test_known_keys_are_rejected() {
+    let policy = KeyPolicy::default();
+    assert!(policy.check_key(&pubkey([5; 32])).is_err());
+    let mut seven = [0u8; 32];
+    seven[31] = 7;
+    assert!(policy.check_key(&pubkey(seven)).is_err(), "7*G has a known discrete log");
+    assert!(is_weak_secret(&secp256k1::SecretKey::from_slice(&seven).unwrap()));
+
+    let mut random_looking = [0u8; 32];
+    random_looking.copy_from_slice(&bitcoin::hashes::sha256::Hash::hash(b"wrapyield weak key test").to_byte_array());
+    assert!(policy.check_key(&pubkey(random_looking)).is_ok());
+    assert!(!is_weak_secret(&secp256k1::SecretKey::from_slice(&random_looking).unwrap()));
+}
+
+#[test]
+fn test_allowlist_admits_fixture_keys() {
+    let (a, b) = (pubkey([5; 32]), pubkey([6; 32]));
+    let descriptor: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(multi(1,{},{}))", a, b)).unwrap();
+    assert!(KeyPolicy::default().check_descriptor(&descriptor).is_err());
+    assert!(KeyPolicy::allow(&[a]).check_descriptor(&descriptor).is_err());
+    assert!(KeyPolicy::allow(&[a, b]).check_descriptor(&descriptor).is_ok());
+}
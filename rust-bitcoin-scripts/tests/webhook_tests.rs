@@ -0,0 +1,58 @@
+use bitcoin_scripts::webhooks::{sign_payload, verify_payload, LifecycleEvent, WebhookEndpoint, Webhooks, SIGNATURE_HEADER};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use std::time::Duration;
+
+#[test]
+fn test_payload_signature() {
+    // RFC 4231 test case 2
+    assert_eq!(
+        sign_payload(b"Jefe", b"what do ya want for nothing?"),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    let body = serde_json::to_vec(&LifecycleEvent::ClawbackTriggered { txid: "00".repeat(32), path_used: "backup".to_string() }).unwrap();
+    let signature = sign_payload(b"secret", &body);
+    assert!(verify_payload(b"secret", &body, &signature));
+    assert!(!verify_payload(b"other", &body, &signature));
+    assert!(String::from_utf8(body).unwrap().contains("\"event\":\"clawback_triggered\""));
+}
+
+#[tokio::test]
+async fn test_webhook_retries_until_accepted() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let mut signatures = Vec::new();
+        for status in ["503 Service Unavailable", "200 OK"] {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 8192];
+            let n = socket.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            signatures.push(request.lines()
+                .find(|l| l.to_lowercase().starts_with(&SIGNATURE_HEADER.to_lowercase()))
+                .map(|l| l.split(':').nth(1).unwrap().trim().to_string()));
+            let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+        signatures
+    });
+
+    let mut endpoint = WebhookEndpoint::new(&url, b"secret");
+    endpoint.initial_backoff = Duration::from_millis(10);
+    endpoint.events = vec!["signing_round_stalled".to_string()];
+    let webhooks = Webhooks::new(vec![endpoint]);
+
+    let ignored = LifecycleEvent::DepositConfirmed { txid: "00".repeat(32), vout: 0, value_sats: 1000, confirmations: 6 };
+    assert!(webhooks.fire(&ignored).await.unwrap().is_empty());
+
+    let event = LifecycleEvent::SigningRoundStalled { round_id: "round-1".to_string(), missing_signers: vec!["signer-2".to_string()] };
+    let deliveries = webhooks.fire(&event).await.unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].attempts, 2);
+    assert!(deliveries[0].error.is_none());
+
+    let expected = sign_payload(b"secret", &serde_json::to_vec(&event).unwrap());
+    for signature in server.await.unwrap() {
+        assert_eq!(signature.as_deref(), Some(expected.as_str()));
+    }
+}
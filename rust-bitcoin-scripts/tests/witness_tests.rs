@@ -0,0 +1,91 @@
+use bitcoin_scripts::witness::{build_backup_path_witness, build_multisig_timelock_witness, build_vault_script_sig, vault_descriptor, VaultShape, VaultTimelock};
+use miniscript::bitcoin::{PrivateKey, Network, PublicKey, secp256k1};
+use miniscript::Descriptor;
+use bitcoin::ecdsa::Signature;
+use bitcoin::secp256k1::Message;
+use bitcoin::Sequence;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+fn keys() -> Vec<(secp256k1::SecretKey, PublicKey)> {
+    let secp = secp256k1::Secp256k1::new();
+    [5u8, 6, 7, 8].iter().map(|b| {
+        let sk = secp256k1::SecretKey::from_slice(&[*b; 32]).unwrap();
+        (sk, PublicKey::from_private_key(&secp, &PrivateKey::new(sk, Network::Regtest)))
+    }).collect()
+}
+
+fn sign(sk: &secp256k1::SecretKey) -> Signature {
+    let secp = secp256k1::Secp256k1::new();
+    Signature::sighash_all(secp.sign_ecdsa(&Message::from_slice(&[1; 32]).unwrap(), sk))
+}
+
+fn vault(lock: &str) -> Descriptor<PublicKey> {
+    let k = keys();
+    Descriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:multi(2,{},{},{}),{})))", k[3].1, k[0].1, k[1].1, k[2].1, lock)).unwrap()
+}
+
+#[test]
+fn test_vault_shape_is_recognized() {
+    let k = keys();
+    let shape = VaultShape::from_descriptor(&vault("older(10)")).unwrap();
+    assert_eq!(shape.backup_key, k[3].1);
+    assert_eq!(shape.threshold, 2);
+    assert_eq!(shape.multisig_keys, vec![k[0].1, k[1].1, k[2].1]);
+    assert_eq!(shape.timelock, VaultTimelock::Relative(10));
+    assert_eq!(VaultShape::from_descriptor(&vault("after(200)")).unwrap().timelock, VaultTimelock::Absolute(200));
+
+    let other: Descriptor<PublicKey> = Descriptor::from_str(&format!("wsh(multi(1,{},{}))", k[0].1, k[1].1)).unwrap();
+    assert!(VaultShape::from_descriptor(&other).is_err());
+
+    // The builder produces the shape it parses, for either timelock
+    let signers = [k[0].1, k[1].1, k[2].1];
+    for timelock in [VaultTimelock::Relative(10), VaultTimelock::Absolute(200)] {
+        let built = vault_descriptor(k[3].1, &signers, 2, timelock).unwrap();
+        assert_eq!(VaultShape::from_descriptor(&built).unwrap().timelock, timelock);
+    }
+    assert!(vault_descriptor(k[3].1, &signers, 2, VaultTimelock::Relative(0)).is_err());
+}
+
+#[test]
+fn test_witnesses_match_miniscript_satisfier() {
+    let k = keys();
+    let descriptor = vault("older(10)");
+
+    let backup_sig = sign(&k[3].0);
+    let witness = build_backup_path_witness(&descriptor, &backup_sig).unwrap();
+    let (expected, _) = descriptor.get_satisfaction(HashMap::from([(k[3].1, backup_sig)])).unwrap();
+    assert_eq!(witness.to_vec(), expected);
+
+    // Signatures given in reverse key order still come out in multi order
+    let sigs = HashMap::from([(k[2].1, sign(&k[2].0)), (k[1].1, sign(&k[1].0))]);
+    let witness = build_multisig_timelock_witness(&descriptor, &sigs).unwrap();
+    let (expected, _) = descriptor.get_satisfaction((sigs.clone(), Sequence(10))).unwrap();
+    assert_eq!(witness.to_vec(), expected);
+    assert_eq!(witness.len(), 5);
+
+    let too_few = HashMap::from([(k[0].1, sign(&k[0].0))]);
+    assert!(build_multisig_timelock_witness(&descriptor, &too_few).is_err());
+}
+
+#[test]
+fn test_nested_vault_gets_script_sig_and_same_witness() {
+    let k = keys();
+    let native = vault("older(10)");
+    let nested: Descriptor<PublicKey> = Descriptor::from_str(&format!("sh({})", native.to_string().split('#').next().unwrap())).unwrap();
+    let shape = VaultShape::from_descriptor(&nested).unwrap();
+    assert_eq!(shape.witness_script, native.explicit_script().unwrap());
+    assert_eq!(shape.timelock, VaultTimelock::Relative(10));
+    assert!(build_vault_script_sig(&native).unwrap().is_empty());
+
+    let backup_sig = sign(&k[3].0);
+    let witness = build_backup_path_witness(&nested, &backup_sig).unwrap();
+    let (expected_witness, expected_script_sig) = nested.get_satisfaction(HashMap::from([(k[3].1, backup_sig)])).unwrap();
+    assert_eq!(witness.to_vec(), expected_witness);
+    assert_eq!(build_vault_script_sig(&nested).unwrap(), expected_script_sig);
+    // One push of the P2WSH program
+    assert_eq!(expected_script_sig.len(), 35);
+
+    let sh_multi: Descriptor<PublicKey> = Descriptor::from_str(&format!("sh(multi(1,{},{}))", k[0].1, k[1].1)).unwrap();
+    assert!(VaultShape::from_descriptor(&sh_multi).is_err());
+}